@@ -0,0 +1,22 @@
+#![feature(thread_local)]
+use std::hint::black_box;
+use tls_dep::{COUNTER, NAME};
+
+fn main() {
+    // Reads and writes a thread local declared in another crate.
+    COUNTER.set(black_box(COUNTER.get() + 41));
+    COUNTER.set(COUNTER.get() + 1);
+    assert_eq!(COUNTER.get(), 42);
+    // Each thread gets its own copy.
+    let other = std::thread::spawn(|| {
+        COUNTER.set(COUNTER.get() + 1);
+        NAME.set("other");
+        (COUNTER.get(), NAME.get())
+    })
+    .join()
+    .unwrap();
+    assert_eq!(other, (1, "other"));
+    assert_eq!(COUNTER.get(), 42);
+    assert_eq!(NAME.get(), "main");
+    mycorrhiza::system::console::Console::writeln_u64(black_box(COUNTER.get().into()));
+}
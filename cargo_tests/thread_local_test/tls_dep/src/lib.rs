@@ -0,0 +1,9 @@
+#![feature(thread_local)]
+use std::cell::Cell;
+/// A raw thread local. On targets which can't export thread locals(like `windows-msvc`), other crates access it through a
+/// thread local shim.
+#[thread_local]
+pub static COUNTER: Cell<u32> = Cell::new(0);
+std::thread_local! {
+    pub static NAME: Cell<&'static str> = const { Cell::new("main") };
+}
@@ -0,0 +1,535 @@
+//! Generates `mycorrhiza` bindings for a .NET assembly, by reading its ECMA-335 metadata.
+//!
+//! Usage: `bindgen path/to/Assembly.dll [--crate PATH] [-o OUT.rs]`
+//!
+//! Every public, non-generic class gets a type alias, placed in a module matching its namespace, and an extension trait
+//! (`ClassNameApi`) with its public constructors(`new`), methods, property accessors(`get_X`/`set_X`) and fields. Overloads
+//! get a numeric suffix. Members using types which can't be expressed trough `mycorrhiza`(like generics, or value types
+//! other than enums and primitives) are skipped.
+//!
+//! `--crate` sets the path to `mycorrhiza`. When generating bindings inside `mycorrhiza` itself(`--crate crate`), classes
+//! get `From` impls converting them to their base class. Since the orphan rule forbids that in any other crate, the
+//! bindings then define an `Upcast` trait instead.
+use std::{collections::BTreeMap, fmt::Write};
+
+use cilly::v2::{
+    pe_exporter::{
+        reader::{MetadataReader, ReadError},
+        tables::{MetadataToken, TableId},
+    },
+    Assembly, Float, Int, Type,
+};
+use fxhash::{FxHashMap, FxHashSet};
+
+// TypeDef flags(II.23.1.15)
+const TYPE_VISIBILITY_MASK: u32 = 0x07;
+const TYPE_PUBLIC: u32 = 0x01;
+// MethodDef and Field flags(II.23.1.10, II.23.1.5)
+const MEMBER_ACCESS_MASK: u16 = 0x07;
+const MEMBER_PUBLIC: u16 = 0x06;
+const MEMBER_STATIC: u16 = 0x10;
+const METHOD_VIRTUAL: u16 = 0x40;
+const METHOD_ABSTRACT: u16 = 0x400;
+const FIELD_LITERAL: u16 = 0x40;
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+    "pub", "ref", "return", "static", "struct", "trait", "true", "try", "type", "unsafe", "use",
+    "where", "while", "yield",
+];
+/// Turns a .NET name into a valid Rust identifier.
+fn ident(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match name.as_str() {
+        "self" | "Self" | "crate" | "super" | "_" => format!("{name}_"),
+        _ if RUST_KEYWORDS.contains(&name.as_str()) => format!("r#{name}"),
+        _ if name.starts_with(|c: char| c.is_numeric()) => format!("_{name}"),
+        _ => name,
+    }
+}
+/// A namespace, and the bindings of the types in it.
+#[derive(Default)]
+struct Module {
+    items: Vec<String>,
+    children: BTreeMap<String, Module>,
+}
+impl Module {
+    fn write(&self, depth: usize, out: &mut String) {
+        let indent = "    ".repeat(depth);
+        for item in &self.items {
+            for line in item.lines() {
+                if line.is_empty() {
+                    out.push('\n');
+                } else {
+                    writeln!(out, "{indent}{line}").unwrap();
+                }
+            }
+        }
+        for (name, child) in &self.children {
+            writeln!(out, "{indent}pub mod {name} {{").unwrap();
+            child.write(depth + 1, out);
+            writeln!(out, "{indent}}}").unwrap();
+        }
+    }
+}
+struct Generator<'a> {
+    reader: MetadataReader<'a>,
+    /// Stores the types decoded from signatures.
+    asm: Assembly,
+    /// The path to `mycorrhiza`.
+    krate: String,
+    /// The underlying types of the enums defined in this assembly.
+    enums: FxHashMap<String, Type>,
+    skipped: usize,
+}
+impl<'a> Generator<'a> {
+    fn new(reader: MetadataReader<'a>, krate: String) -> Result<Self, ReadError> {
+        let mut generator = Self {
+            reader,
+            asm: Assembly::default(),
+            krate,
+            enums: FxHashMap::default(),
+            skipped: 0,
+        };
+        for tdef in generator.reader.tokens(TableId::TypeDef) {
+            if generator.base_name(tdef)?.as_deref() != Some("System.Enum") {
+                continue;
+            }
+            // The underlying type of an enum is the type of its only instance field, `value__`.
+            for field in generator.reader.list(tdef, 4) {
+                let field = MetadataToken::new(TableId::Field, field);
+                if generator.reader.string(field, 1)? == "value__" {
+                    let blob = generator.reader.blob(field, 2)?;
+                    let tpe = generator
+                        .reader
+                        .decode_field_sig(blob, &mut generator.asm)?;
+                    generator
+                        .enums
+                        .insert(generator.reader.type_name(tdef)?, tpe);
+                }
+            }
+        }
+        Ok(generator)
+    }
+    /// Returns the name of the base class of a `TypeDef`, if it has one, and it is not generic.
+    fn base_name(&self, tdef: MetadataToken) -> Result<Option<String>, ReadError> {
+        match self.reader.index(tdef, 3) {
+            Some(base) if base.table() != TableId::TypeSpec => {
+                Ok(Some(self.reader.type_name(base)?))
+            }
+            _ => Ok(None),
+        }
+    }
+    fn class(&self, asm: &str, name: &str) -> String {
+        format!(
+            "{}::intrinsics::RustcCLRInteropManagedClass<\"{asm}\", \"{name}\">",
+            self.krate
+        )
+    }
+    /// Returns the Rust equivalent of a .NET type, if it has one.
+    fn rust_type(&self, tpe: Type) -> Option<String> {
+        Some(match tpe {
+            Type::Void => "()".into(),
+            Type::Bool => "bool".into(),
+            Type::Int(int) => match int {
+                Int::U8 => "u8",
+                Int::U16 => "u16",
+                Int::U32 => "u32",
+                Int::U64 => "u64",
+                Int::USize => "usize",
+                Int::I8 => "i8",
+                Int::I16 => "i16",
+                Int::I32 => "i32",
+                Int::I64 => "i64",
+                Int::ISize => "isize",
+                Int::U128 | Int::I128 => return None,
+            }
+            .into(),
+            Type::Float(Float::F32) => "f32".into(),
+            Type::Float(Float::F64) => "f64".into(),
+            Type::PlatformChar => format!("{}::intrinsics::RustcCLRInteropManagedChar", self.krate),
+            Type::PlatformString => self.class("System.Runtime", "System.String"),
+            Type::PlatformObject => self.class("System.Runtime", "System.Object"),
+            Type::ClassRef(cref) => {
+                let cref = &self.asm[cref];
+                if !cref.generics().is_empty() {
+                    return None;
+                }
+                let name = &self.asm[cref.name()];
+                if cref.is_valuetype() {
+                    return self.rust_type(*self.enums.get(name)?);
+                }
+                self.class(&self.asm[cref.asm()?], name)
+            }
+            Type::Ptr(inner) => match self.asm[inner] {
+                Type::Void => "*mut core::ffi::c_void".into(),
+                inner => format!("*mut {}", self.rust_type(inner)?),
+            },
+            Type::Ref(inner) => format!("&mut {}", self.rust_type(self.asm[inner])?),
+            Type::PlatformArray { elem, dims } => format!(
+                "{}::intrinsics::RustcCLRInteropManagedArray<{}, {dims}>",
+                self.krate,
+                self.rust_type(self.asm[elem])?
+            ),
+            _ => return None,
+        })
+    }
+    /// Returns a unique name for a member called `name`, adding a numeric suffix to overloads.
+    fn member_name(name: &str, used: &mut FxHashSet<String>) -> String {
+        let base = ident(name);
+        let mut candidate = base.clone();
+        let mut suffix = 1;
+        while !used.insert(candidate.clone()) {
+            candidate = format!("{}_{suffix}", base.trim_start_matches("r#"));
+            suffix += 1;
+        }
+        candidate
+    }
+    /// Generates the bindings of a single class, returning its namespace, and its bindings.
+    fn bind_class(&mut self, tdef: MetadataToken) -> Result<(String, String), ReadError> {
+        let full_name = self.reader.type_name(tdef)?;
+        let namespace = self.reader.string(tdef, 2)?.to_owned();
+        let alias = ident(self.reader.string(tdef, 1)?);
+        let asm_name = self.reader.assembly_name()?.to_owned();
+        let this = self.class(&asm_name, &full_name);
+        let mut out = format!("pub type {alias} = {this};\n");
+        let mut decls = String::new();
+        let mut impls = String::new();
+        let mut used = FxHashSet::default();
+        for method in self.reader.list(tdef, 5) {
+            let method = MetadataToken::new(TableId::MethodDef, method);
+            let flags = self.reader.raw(method, 2) as u16;
+            if flags & MEMBER_ACCESS_MASK != MEMBER_PUBLIC {
+                continue;
+            }
+            let name = self.reader.string(method, 3)?;
+            let is_static = flags & MEMBER_STATIC != 0;
+            // Static abstract interface methods can only be called trough a generic.
+            if name == ".cctor" || (is_static && flags & METHOD_ABSTRACT != 0) {
+                continue;
+            }
+            let sig = match self
+                .reader
+                .decode_method_sig(self.reader.blob(method, 4)?, &mut self.asm)
+            {
+                Ok(sig) if sig.generic_params == 0 => sig,
+                _ => {
+                    self.skipped += 1;
+                    continue;
+                }
+            };
+            let ret = if name == ".ctor" {
+                Some(this.clone())
+            } else {
+                self.rust_type(*sig.sig.output())
+            };
+            let inputs: Option<Vec<String>> = sig
+                .sig
+                .inputs()
+                .iter()
+                .map(|input| self.rust_type(*input))
+                .collect();
+            let (Some(ret), Some(inputs)) = (ret, inputs) else {
+                self.skipped += 1;
+                continue;
+            };
+            let params: String = inputs
+                .iter()
+                .enumerate()
+                .map(|(idx, input)| format!(", arg{idx}: {input}"))
+                .collect();
+            let args: String = (0..inputs.len()).map(|idx| format!("arg{idx}, ")).collect();
+            let arg_types: String = inputs.iter().map(|input| format!("{input}, ")).collect();
+            let (rust_name, receiver, call) = if name == ".ctor" {
+                (
+                    Self::member_name("new", &mut used),
+                    "",
+                    format!("Self::ctor::<({arg_types})>(({args}))"),
+                )
+            } else {
+                let kind = match (is_static, flags & METHOD_VIRTUAL != 0) {
+                    (true, _) => "static_call",
+                    (false, false) => "instance_call",
+                    (false, true) => "virt_call",
+                };
+                let (receiver, this_arg, this_type) = if is_static {
+                    ("", "", "")
+                } else {
+                    ("self", "self, ", "Self, ")
+                };
+                (
+                    Self::member_name(name, &mut used),
+                    receiver,
+                    format!(
+                        "Self::{kind}::<\"{name}\", ({this_type}{arg_types}), {ret}>(({this_arg}{args}))"
+                    ),
+                )
+            };
+            let params = format!("{receiver}{params}");
+            let params = params.trim_start_matches(", ");
+            writeln!(decls, "    fn {rust_name}({params}) -> {ret};").unwrap();
+            writeln!(
+                impls,
+                "    fn {rust_name}({params}) -> {ret} {{\n        {call}\n    }}"
+            )
+            .unwrap();
+        }
+        for field in self.reader.list(tdef, 4) {
+            let field = MetadataToken::new(TableId::Field, field);
+            let flags = self.reader.raw(field, 0) as u16;
+            // Literal fields have no storage: their value is stored in the metadata.
+            if flags & MEMBER_ACCESS_MASK != MEMBER_PUBLIC || flags & FIELD_LITERAL != 0 {
+                continue;
+            }
+            let name = self.reader.string(field, 1)?;
+            let tpe = self
+                .reader
+                .decode_field_sig(self.reader.blob(field, 2)?, &mut self.asm)
+                .ok()
+                .and_then(|tpe| self.rust_type(tpe));
+            let Some(tpe) = tpe else {
+                self.skipped += 1;
+                continue;
+            };
+            let getter = Self::member_name(name, &mut used);
+            let setter = Self::member_name(&format!("set_{name}"), &mut used);
+            let (receiver, this, load, store) = if flags & MEMBER_STATIC != 0 {
+                ("", "", "Self::static_field", "Self::set_static_field")
+            } else {
+                ("self", "self, ", "self.field", "self.set_field")
+            };
+            writeln!(decls, "    fn {getter}({receiver}) -> {tpe};").unwrap();
+            writeln!(decls, "    fn {setter}({this}value: {tpe});").unwrap();
+            writeln!(
+                impls,
+                "    fn {getter}({receiver}) -> {tpe} {{\n        {load}::<\"{name}\", {tpe}>()\n    }}"
+            )
+            .unwrap();
+            writeln!(
+                impls,
+                "    fn {setter}({this}value: {tpe}) {{\n        {store}::<\"{name}\", {tpe}>(value)\n    }}"
+            )
+            .unwrap();
+        }
+        if !decls.is_empty() {
+            write!(
+                out,
+                "pub trait {alias}Api {{\n{decls}}}\nimpl {alias}Api for {alias} {{\n{impls}}}\n"
+            )
+            .unwrap();
+        }
+        if let Some(base) = self.reader.index(tdef, 3) {
+            if base.table() != TableId::TypeSpec {
+                let base = self.class(
+                    self.reader.type_assembly(base)?,
+                    &self.reader.type_name(base)?,
+                );
+                if self.krate == "crate" {
+                    write!(
+                        out,
+                        "impl From<{alias}> for {base} {{\n    fn from(v: {alias}) -> {base} {{\n        crate::intrinsics::rustc_clr_interop_managed_checked_cast::<{base}, {alias}>(v)\n    }}\n}}\n"
+                    )
+                    .unwrap();
+                } else {
+                    write!(
+                        out,
+                        "impl {}Upcast<{base}> for {alias} {{\n    fn upcast(self) -> {base} {{\n        {}::intrinsics::rustc_clr_interop_managed_checked_cast::<{base}, {alias}>(self)\n    }}\n}}\n",
+                        // `Upcast` is defined in the root module of the bindings.
+                        "super::".repeat(namespace.split('.').filter(|part| !part.is_empty()).count()),
+                        self.krate
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        Ok((namespace, out))
+    }
+    fn generate(&mut self) -> Result<String, ReadError> {
+        let mut root = Module::default();
+        for tdef in self.reader.tokens(TableId::TypeDef) {
+            let flags = self.reader.raw(tdef, 0);
+            let name = self.reader.string(tdef, 1)?;
+            // Nested types are not public, and generic types can't be bound yet.
+            if flags & TYPE_VISIBILITY_MASK != TYPE_PUBLIC || name.contains('`') {
+                continue;
+            }
+            if matches!(
+                self.base_name(tdef)?.as_deref(),
+                Some("System.ValueType" | "System.Enum")
+            ) {
+                continue;
+            }
+            let (namespace, bindings) = self.bind_class(tdef)?;
+            let module = namespace
+                .split('.')
+                .filter(|part| !part.is_empty())
+                .fold(&mut root, |module, part| {
+                    module.children.entry(ident(part)).or_default()
+                });
+            module.items.push(bindings);
+        }
+        let mut out = String::from(
+            "// Generated by the cilly binding generator. Do not edit.\n#![allow(non_snake_case, non_camel_case_types, dead_code)]\n",
+        );
+        root.write(0, &mut out);
+        Ok(out)
+    }
+}
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let mut input = None;
+    let mut output = None;
+    let mut krate = "mycorrhiza".to_owned();
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--crate" => krate = args.next().expect("ERROR: --crate requires a path").clone(),
+            "-o" => output = Some(args.next().expect("ERROR: -o requires a path").clone()),
+            _ => input = Some(arg.clone()),
+        }
+    }
+    let Some(input) = input else {
+        eprintln!("Usage: bindgen path/to/Assembly.dll [--crate PATH] [-o OUT.rs]");
+        std::process::exit(1);
+    };
+    let pe = std::fs::read(&input).expect("ERROR: Could not read the assembly file!");
+    let reader = MetadataReader::new(&pe)
+        .unwrap_or_else(|err| panic!("ERROR: Could not read {input}: {err}"));
+    let is_crate = krate == "crate";
+    let mut generator = Generator::new(reader, krate)
+        .unwrap_or_else(|err| panic!("ERROR: Could not read {input}: {err}"));
+    let mut bindings = generator
+        .generate()
+        .unwrap_or_else(|err| panic!("ERROR: Could not read {input}: {err}"));
+    if !is_crate {
+        bindings.push_str("/// Converts a class to one of its base classes.\npub trait Upcast<T> {\n    fn upcast(self) -> T;\n}\n");
+    }
+    eprintln!(
+        "Skipped {} members with signatures not supported by mycorrhiza.",
+        generator.skipped
+    );
+    match output {
+        Some(output) => std::fs::write(output, bindings).expect("ERROR: Could not write bindings"),
+        None => print!("{bindings}"),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cilly::v2::{
+        cilnode::MethodKind, pe_exporter::PEExporter, Access, ClassDef, MethodDef, MethodImpl,
+    };
+    /// Exports an assembly with a single class, `Demo.Point`, and generates its bindings.
+    fn bindings(krate: &str) -> String {
+        let mut asm = Assembly::default();
+        let name = asm.alloc_string("Demo.Point");
+        let x = asm.alloc_string("x");
+        let origin = asm.alloc_string("origin");
+        let class = asm.class_def(ClassDef::new(
+            name,
+            false,
+            0,
+            None,
+            vec![(Type::Int(Int::I32), x, None)],
+            vec![(Type::Int(Int::I64), origin, false)],
+            Access::Public,
+            None,
+            None,
+        ));
+        let int_sig = asm.sig([Type::Int(Int::I32)], Type::Bool);
+        let string_sig = asm.sig([Type::PlatformString], Type::Bool);
+        // Unsupported, so it must be skipped.
+        let wide_sig = asm.sig([Type::Int(Int::I128)], Type::Void);
+        let empty_sig = asm.sig([], Type::Void);
+        for (name, sig) in [
+            ("Add", int_sig),
+            ("Add", string_sig),
+            ("Wide", wide_sig),
+            ("type", empty_sig),
+        ] {
+            let name = asm.alloc_string(name);
+            asm.new_method(MethodDef::new(
+                Access::Public,
+                class,
+                name,
+                sig,
+                MethodKind::Static,
+                MethodImpl::Missing,
+                vec![None; asm[sig].inputs().len()],
+            ));
+        }
+        let pe = PEExporter::new(true)
+            .export_to_bytes(&asm, "demo.dll")
+            .unwrap();
+        let reader = MetadataReader::new(&pe).unwrap();
+        let mut generator = Generator::new(reader, krate.to_owned()).unwrap();
+        let bindings = generator.generate().unwrap();
+        assert_eq!(generator.skipped, 1);
+        bindings
+    }
+    #[test]
+    fn mangle_idents() {
+        assert_eq!(ident("Add"), "Add");
+        assert_eq!(ident("type"), "r#type");
+        assert_eq!(ident("self"), "self_");
+        assert_eq!(ident("Self"), "Self_");
+        assert_eq!(ident("2D"), "_2D");
+        assert_eq!(ident("op_Implicit<T>"), "op_Implicit_T_");
+        assert_eq!(ident("<Module>"), "_Module_");
+    }
+    #[test]
+    fn overload_suffixes() {
+        let mut used = FxHashSet::default();
+        assert_eq!(Generator::member_name("Add", &mut used), "Add");
+        assert_eq!(Generator::member_name("Add", &mut used), "Add_1");
+        assert_eq!(Generator::member_name("Add", &mut used), "Add_2");
+        assert_eq!(Generator::member_name("type", &mut used), "r#type");
+        // Suffixed keywords are no longer keywords, so they don't need to be raw.
+        assert_eq!(Generator::member_name("type", &mut used), "type_1");
+    }
+    #[test]
+    fn class_bindings() {
+        let bindings = bindings("mycorrhiza");
+        assert!(bindings.contains("pub mod Demo {"));
+        assert!(bindings.contains(
+            "pub type Point = mycorrhiza::intrinsics::RustcCLRInteropManagedClass<\"_\", \"Demo.Point\">;"
+        ));
+        assert!(bindings.contains("pub trait PointApi {"));
+        assert!(bindings.contains("fn Add(arg0: i32) -> bool;"));
+        assert!(bindings.contains(
+            "fn Add_1(arg0: mycorrhiza::intrinsics::RustcCLRInteropManagedClass<\"System.Runtime\", \"System.String\">) -> bool;"
+        ));
+        assert!(bindings.contains("fn r#type() -> ();"));
+        assert!(!bindings.contains("Wide"));
+        assert!(bindings.contains("fn x(self) -> i32;"));
+        assert!(bindings.contains("fn set_x(self, value: i32);"));
+        assert!(bindings.contains("fn origin() -> i64;"));
+        assert!(bindings.contains("Self::set_static_field::<\"origin\", i64>(value)"));
+    }
+    #[test]
+    fn upcast_outside_of_mycorrhiza() {
+        let bindings = bindings("mycorrhiza");
+        // `Upcast` is defined in the root module, one level above `Demo`.
+        assert!(bindings.contains(
+            "impl super::Upcast<mycorrhiza::intrinsics::RustcCLRInteropManagedClass<\"System.Runtime\", \"System.Object\">> for Point {"
+        ));
+        assert!(!bindings.contains("impl From<"));
+    }
+    #[test]
+    fn from_inside_of_mycorrhiza() {
+        let bindings = bindings("crate");
+        assert!(bindings.contains(
+            "impl From<Point> for crate::intrinsics::RustcCLRInteropManagedClass<\"System.Runtime\", \"System.Object\"> {"
+        ));
+        assert!(!bindings.contains("Upcast"));
+    }
+}
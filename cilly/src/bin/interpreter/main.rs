@@ -1,3 +1,49 @@
-#![feature(iterator_try_collect)]
-#![allow(dead_code)]
-fn main() {}
+//! Interprets `cilly` assemblies, without needing a .NET runtime.
+//!
+//! Usage: `interpreter path/to/assembly.cilly2 [args...]`
+//!
+//! The interpreter models unmanaged memory precisely, and reports out-of-bounds accesses, use-after-free and other
+//! kinds of UB as errors, together with the method and source location they happened in.
+use std::io::Read;
+
+use cilly::v2::{
+    interpreter::{insert_builtins, Interpreter, Object, Trap, Value},
+    Assembly,
+};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let asm_path = args
+        .next()
+        .expect("Usage: interpreter path/to/assembly.cilly2 [args...]");
+    let mut asm_bytes = Vec::with_capacity(0x10000);
+    std::fs::File::open(&asm_path)
+        .expect("ERROR: Could not open the assembly file!")
+        .read_to_end(&mut asm_bytes)
+        .expect("ERROR: Could not load the assembly file!");
+    let mut asm: Assembly = postcard::from_bytes(&asm_bytes).unwrap();
+    insert_builtins(&mut asm);
+    let argv = std::iter::once(asm_path).chain(args).collect();
+    let mut interpreter = Interpreter::new(asm, argv);
+    let code = match interpreter.run_entrypoint() {
+        Ok(code) => code,
+        Err(Trap::Exception(exception)) => {
+            let message = match exception {
+                Value::Obj(handle) => match interpreter.object(handle) {
+                    Ok(Object::Exception { class, message }) => format!("{class}: {message}"),
+                    Ok(other) => format!("{other:?}"),
+                    Err(_) => format!("{exception:?}"),
+                },
+                other => format!("{other:?}"),
+            };
+            eprintln!("Unhandled exception. {message}");
+            134
+        }
+        Err(Trap::Error(message)) => {
+            eprintln!("ERROR: {message}");
+            101
+        }
+        Err(Trap::Exit(code)) => code,
+    };
+    std::process::exit(code);
+}
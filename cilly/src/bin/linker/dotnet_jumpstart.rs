@@ -26,7 +26,7 @@ fn main(){{
     let curr_path = std::env::current_exe().unwrap();
     let dll_path = curr_path.with_extension("dll");
     let config = curr_path.with_extension("runtimeconfig.json");
-    let pdb_file = curr_path.with_file_name("{pdb_file}");
+    let pdb_file = curr_path.with_file_name("{pdb_out}");
     let mut requires_refresh = false;
     if dll_path.exists() {{
     	let ondisk_len = std::fs::File::open(dll_path.clone()).expect("Could not create a file to unpack the .NET assembly").metadata().unwrap().len();
@@ -62,6 +62,6 @@ fn main(){{
     }}
     let args:Vec<String> = std::env::args().collect();
     let args = &args[1..];
-    std::process::Command::new("{jumpstart_cmd}").arg(dll_path).args(args).status().expect("Could not start the .NET runtime.");
+    std::process::Command::new("{jumpstart_cmd}").args(&{jumpstart_args:?} as &[&str]).arg(dll_path).args(args).status().expect("Could not start the .NET runtime.");
 }}
 
@@ -6,8 +6,7 @@ use cilly::{
     v2::{
         asm::{MissingMethodPatcher, ILASM_FLAVOUR},
         cilnode::MethodKind,
-        Assembly, BasicBlock, CILNode, CILRoot, ClassDef, ClassRef, Const, IlasmFlavour, Int,
-        MethodImpl, Type,
+        Assembly, BasicBlock, CILNode, CILRoot, ClassDef, ClassRef, Const, Int, MethodImpl, Type,
     },
     MethodRef, DEAD_CODE_ELIMINATION,
 };
@@ -228,14 +227,6 @@ fn main() {
         .collect();
 
     let modifies_errno = LIBC_MODIFIES_ERRNO.iter().copied().collect();
-    if let Some(f128_support) = libc_fns::f128_support_lib() {
-        let f128_support = f128_support.to_str().to_owned().unwrap();
-        externs.extend(
-            libc_fns::F128_SYMBOLS
-                .iter()
-                .map(|fn_name| (*fn_name, f128_support.to_owned())),
-        );
-    }
     let mathf = LIBM.to_owned();
     externs.extend(
         libc_fns::LIBM_FNS
@@ -399,20 +390,6 @@ fn main() {
             }
         }),
     );
-    overrides.insert(
-        final_assembly.alloc_string("__cxa_thread_atexit_impl"),
-        Box::new(|_, asm| {
-            let blocks = vec![BasicBlock::new(
-                vec![asm.alloc_root(CILRoot::VoidRet)],
-                0,
-                None,
-            )];
-            MethodImpl::MethodBody {
-                blocks,
-                locals: vec![],
-            }
-        }),
-    );
     cilly::v2::builtins::select::generate_int_selects(&mut final_assembly, &mut overrides);
     cilly::v2::builtins::insert_swap_at_generic(&mut final_assembly, &mut overrides);
     cilly::v2::builtins::insert_bounds_check(&mut final_assembly, &mut overrides);
@@ -421,10 +398,12 @@ fn main() {
     cilly::v2::builtins::int128::generate_int128_ops(&mut final_assembly, &mut overrides, *C_MODE);
     cilly::v2::builtins::int128::i128_mul_ovf_check(&mut final_assembly, &mut overrides);
     cilly::v2::builtins::f16::generate_f16_ops(&mut final_assembly, &mut overrides, *C_MODE);
+    cilly::v2::builtins::f128::generate_f128_ops(&mut final_assembly, &mut overrides, *C_MODE);
     cilly::v2::builtins::atomics::generate_all_atomics(&mut final_assembly, &mut overrides);
     cilly::v2::builtins::stack_addr(&mut final_assembly, &mut overrides);
     cilly::v2::builtins::transmute(&mut final_assembly, &mut overrides);
     cilly::v2::builtins::create_slice(&mut final_assembly, &mut overrides);
+    cilly::v2::builtins::simd::simd(&mut final_assembly, &mut overrides, *C_MODE || *JAVA_MODE);
 
     if *C_MODE {
         cilly::v2::builtins::insert_exeception_stub(&mut final_assembly, &mut overrides);
@@ -447,9 +426,25 @@ fn main() {
             "pthread_key_delete",
             "pthread_join",
             "pthread_setspecific",
+            "__cxa_thread_atexit_impl",
         ] {
             externs.insert(fnc, LIBC.clone());
         }
+        // libc runs the thread local destructors of the main thread on its own.
+        overrides.insert(
+            final_assembly.alloc_string(cilly::v2::builtins::RUN_THREAD_DTORS),
+            Box::new(|_, asm| {
+                let blocks = vec![BasicBlock::new(
+                    vec![asm.alloc_root(CILRoot::VoidRet)],
+                    0,
+                    None,
+                )];
+                MethodImpl::MethodBody {
+                    blocks,
+                    locals: vec![],
+                }
+            }),
+        );
         overrides.insert(
             final_assembly.alloc_string("argc_argv_init"),
             Box::new(|_, asm| {
@@ -467,9 +462,9 @@ fn main() {
     } else {
         cilly::v2::builtins::instert_threading(&mut final_assembly, &mut overrides);
         cilly::v2::builtins::math::math(&mut final_assembly, &mut overrides);
-        cilly::v2::builtins::simd::simd(&mut final_assembly, &mut overrides);
         cilly::v2::builtins::insert_exception(&mut final_assembly, &mut overrides);
         cilly::v2::builtins::argc_argv_init(&mut final_assembly, &mut overrides);
+        cilly::v2::builtins::dotnet_sys::insert_dotnet_sys(&mut final_assembly, &mut overrides);
     }
 
     // Ensure the cctor and tcctor exist!
@@ -509,8 +504,12 @@ fn main() {
     } else if *JAVA_MODE {
         final_assembly.export(&path, cilly::v2::java_exporter::JavaExporter::new(is_lib));
         if cargo_support {
-            let bootstrap =
-                bootstrap_source(&path.with_extension("jar"), path.to_str().unwrap(), "java");
+            let bootstrap = bootstrap_source(
+                &path.with_extension("jar"),
+                path.to_str().unwrap(),
+                "java",
+                &["-jar"],
+            );
             let bootstrap_path = path.with_extension("rs");
             let mut bootstrap_file = std::fs::File::create(&bootstrap_path).unwrap();
             bootstrap_file.write_all(bootstrap.as_bytes()).unwrap();
@@ -531,15 +530,20 @@ fn main() {
             );
         }
     } else {
-        final_assembly.export(
-            &path,
-            cilly::v2::il_exporter::ILExporter::new(*ILASM_FLAVOUR, is_lib),
-        );
+        if *PE_MODE {
+            final_assembly.export(&path, cilly::v2::pe_exporter::PEExporter::new(is_lib));
+        } else {
+            final_assembly.export(
+                &path,
+                cilly::v2::il_exporter::ILExporter::new(*ILASM_FLAVOUR, is_lib),
+            );
+        }
         if cargo_support {
             let bootstrap = bootstrap_source(
                 &path.with_extension("exe"),
                 path.to_str().unwrap(),
                 "dotnet",
+                &[],
             );
             let bootstrap_path = path.with_extension("rs");
             let mut bootstrap_file = std::fs::File::create(&bootstrap_path).unwrap();
@@ -564,7 +568,12 @@ fn main() {
 
     //todo!();
 }
-fn bootstrap_source(fpath: &Path, output_file_path: &str, jumpstart_cmd: &str) -> String {
+fn bootstrap_source(
+    fpath: &Path,
+    output_file_path: &str,
+    jumpstart_cmd: &str,
+    jumpstart_args: &[&str],
+) -> String {
     if let Err(err) = std::fs::remove_file(output_file_path) {
         match err.kind() {
             std::io::ErrorKind::NotFound => (),
@@ -573,22 +582,27 @@ fn bootstrap_source(fpath: &Path, output_file_path: &str, jumpstart_cmd: &str) -
             }
         }
     };
+    // The PE exporter and the modern `ilasm` emit a Portable PDB, while the classic `ilasm` emits an `.mdb` file. Bundle
+    // whichever was written, renamed to match the unpacked `.dll`.
+    let stem = fpath.file_stem().unwrap().to_string_lossy();
+    let exec_file = fpath.file_name().unwrap().to_string_lossy();
+    let debug_info = [
+        (format!("{stem}.pdb"), format!("{stem}.pdb")),
+        (format!("{exec_file}.mdb"), format!("{stem}.dll.mdb")),
+    ]
+    .into_iter()
+    .find(|(file, _)| fpath.with_file_name(file).exists());
     format!(
         include_str!("dotnet_jumpstart.rs"),
         jumpstart_cmd = jumpstart_cmd,
-        exec_file = fpath.file_name().unwrap().to_string_lossy(),
+        jumpstart_args = jumpstart_args,
+        exec_file = exec_file,
         has_native_companion = *NATIVE_PASSTROUGH,
-        has_pdb = match *ILASM_FLAVOUR {
-            IlasmFlavour::Clasic => false,
-            IlasmFlavour::Modern => true,
-        },
-        pdb_file = match *ILASM_FLAVOUR {
-            IlasmFlavour::Clasic => String::new(),
-            IlasmFlavour::Modern => format!(
-                "{output_file_path}.pdb",
-                output_file_path = fpath.file_stem().unwrap().to_string_lossy()
-            ),
-        },
+        has_pdb = debug_info.is_some(),
+        pdb_file = debug_info
+            .as_ref()
+            .map_or(String::new(), |(file, _)| file.clone()),
+        pdb_out = debug_info.map_or(String::new(), |(_, out)| out),
         native_companion_file = if *NATIVE_PASSTROUGH {
             format!(
                 "rust_native_{output_file_path}.so",
@@ -603,6 +617,7 @@ config!(NATIVE_PASSTROUGH, bool, false);
 config!(ABORT_ON_ERROR, bool, false);
 config!(C_MODE, bool, false);
 config!(JAVA_MODE, bool, false);
+config!(PE_MODE, bool, false);
 config!(PANIC_MANAGED_BT, bool, false);
 /*
 lazy_static! {
@@ -164,6 +164,8 @@ impl<'a> Iterator for CILIter<'a> {
                     | CILRoot::SetTMPLocal { value: tree }
                     | CILRoot::STArg { tree, arg: _ }
                     | CILRoot::Ret { tree }
+                    | CILRoot::TailCall { call: tree }
+                    | CILRoot::Switch { discr: tree, .. }
                     | CILRoot::BTrue { cond: tree, .. }
                     | CILRoot::BFalse { cond: tree, .. }
                     | CILRoot::Throw(tree)
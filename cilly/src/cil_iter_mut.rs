@@ -277,6 +277,8 @@ impl<'a> Iterator for CILIterMut<'a> {
                     | CILRoot::STLoc { tree, local: _ }
                     | CILRoot::STArg { tree, arg: _ }
                     | CILRoot::Ret { tree }
+                    | CILRoot::TailCall { call: tree }
+                    | CILRoot::Switch { discr: tree, .. }
                     | CILRoot::Pop { tree }
                     | CILRoot::BTrue { cond: tree, .. }
                     | CILRoot::BFalse { cond: tree, .. }
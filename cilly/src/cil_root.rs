@@ -1,7 +1,8 @@
 use crate::v2::cilnode::MethodKind;
 use crate::v2::method::LocalDef;
 use crate::v2::{
-    Assembly, ClassRef, FieldIdx, FnSig, MethodRef, MethodRefIdx, StaticFieldDesc, Type,
+    Assembly, ClassRef, FieldIdx, FnSig, MethodRef, MethodRefIdx, StaticFieldDesc, SwitchTable,
+    Type,
 };
 use crate::{
     call,
@@ -127,6 +128,15 @@ pub enum CILRoot {
     Ret {
         tree: CILNode,
     },
+    /// Calls the function in `call`(a `Call` or `CallI` node) as a tail call, and returns its result.
+    TailCall {
+        call: CILNode,
+    },
+    /// Jumps to the target of the case of `table` matching `discr`, or to its default target.
+    Switch {
+        discr: CILNode,
+        table: SwitchTable,
+    },
     Pop {
         tree: CILNode,
     },
@@ -205,6 +215,7 @@ impl CILRoot {
             | Self::GoTo { target, sub_target } => {
                 targets.push((*target, *sub_target));
             }
+            Self::Switch { table, .. } => targets.extend(table.targets()),
             _ => (),
         }
     }
@@ -248,6 +259,16 @@ impl CILRoot {
                 *sub_target = *target;
                 *target = id;
             }
+            Self::Switch { table, .. } => {
+                for (target, sub_target) in table.targets_mut() {
+                    assert_eq!(
+                        *sub_target, 0,
+                        "An exception handler can't contain inner exception handler!"
+                    );
+                    *sub_target = *target;
+                    *target = id;
+                }
+            }
             _ => (),
         }
     }
@@ -339,7 +360,11 @@ impl CILRoot {
                 count.allocate_tmps(curr_loc, locals);
             }
 
-            Self::Ret { tree } | Self::Pop { tree } | Self::Throw(tree) => {
+            Self::Ret { tree }
+            | Self::TailCall { call: tree }
+            | Self::Switch { discr: tree, .. }
+            | Self::Pop { tree }
+            | Self::Throw(tree) => {
                 tree.allocate_tmps(curr_loc, locals);
             }
             Self::VoidRet => (),
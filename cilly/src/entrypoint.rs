@@ -18,6 +18,8 @@ pub fn wrapper(entrypoint: MethodRef, asm: &mut Assembly) -> MethodDefIdx {
     // TODO: check if user_init is used, and only call that method in wrapper if so.
     // This is just a hack that forces user_init to be always present, even when unneded.
     asm.user_init();
+    // The destructors of the thread locals of the main thread run after main returns.
+    let run_thread_dtors = crate::v2::builtins::run_thread_dtors_mref(asm);
     if entry_sig.inputs() == [Type::Int(Int::ISize), uint8_ptr_ptr]
         && entry_sig.output() == &Type::Int(Int::ISize)
     {
@@ -60,6 +62,7 @@ pub fn wrapper(entrypoint: MethodRef, asm: &mut Assembly) -> MethodDefIdx {
                 asm.alloc_root(CILRoot::Call(Box::new((tcctor, [].into())))),
                 asm.alloc_root(CILRoot::Call(Box::new((static_init, [].into())))),
                 asm.alloc_root(CILRoot::Pop(call_main)),
+                asm.alloc_root(CILRoot::Call(Box::new((run_thread_dtors, [].into())))),
                 asm.alloc_root(CILRoot::VoidRet),
             ],
             2,
@@ -103,6 +106,7 @@ pub fn wrapper(entrypoint: MethodRef, asm: &mut Assembly) -> MethodDefIdx {
                 asm.alloc_root(CILRoot::Call(Box::new((tcctor, [].into())))),
                 asm.alloc_root(CILRoot::Call(Box::new(((static_init), [].into())))),
                 asm.alloc_root(CILRoot::Call(Box::new((entrypoint, [].into())))),
+                asm.alloc_root(CILRoot::Call(Box::new((run_thread_dtors, [].into())))),
                 //CILRoot::debug(&format!("Preparing to execute the main program.")).into(),
                 asm.alloc_root(CILRoot::VoidRet),
             ],
@@ -1,5 +1,5 @@
 #![allow(clippy::module_name_repetitions)]
-#![feature(iter_intersperse, pattern)]
+#![feature(iter_intersperse, pattern, iterator_try_collect)]
 
 pub use crate::v2::Type;
 use fxhash::FxHasher;
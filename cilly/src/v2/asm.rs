@@ -132,9 +132,30 @@ impl Assembly {
         if self.method_defs.contains_key(&MethodDefIdx(method)) {
             Some(MethodDefIdx(method))
         } else {
-            None
+            self.variadic_def_ref(method)
+                .map(MethodDefIdx)
+                .filter(|def| self.method_defs.contains_key(def))
         }
     }
+    /// If `method` is a call site of a C-variadic function, passing some variable arguments, returns the reference to the definition of that function,
+    /// whose signature has no variable arguments.
+    #[must_use]
+    pub fn variadic_def_ref(&self, method: MethodRefIdx) -> Option<MethodRefIdx> {
+        let mref = &self[method];
+        let sig = &self[mref.sig()];
+        if sig.variable_inputs().is_empty() {
+            return None;
+        }
+        let fixed_sig = *self.sigs.1.get(&sig.fixed_sig())?;
+        let def_ref = MethodRef::new(
+            mref.class(),
+            mref.name(),
+            fixed_sig,
+            mref.kind(),
+            mref.generics().into(),
+        );
+        self.method_refs.1.get(&def_ref).copied()
+    }
     #[must_use]
     pub fn fuel_from_env(&self) -> OptFuel {
         match std::env::var("OPT_FUEL") {
@@ -755,6 +776,13 @@ impl Assembly {
             .filter(|(_, def)| def.access().is_extern())
             .map(|(idx, _)| *idx)
             .collect();
+        // Methods implementing interfaces can be called trough those interfaces, so they are alive too.
+        previosly_ressurected.extend(
+            self.class_defs
+                .values()
+                .flat_map(|tdef| tdef.overrides().iter().map(|(_, body)| *body))
+                .filter(|body| self.method_defs.contains_key(body)),
+        );
         let mut to_resurrect: FxHashSet<MethodDefIdx> = FxHashSet::default();
         let mut alive: FxHashSet<MethodDefIdx> = FxHashSet::default();
         // If only cleaning up imports, assume all non-import fns are alive.
@@ -771,6 +799,14 @@ impl Assembly {
                 .iter()
                 .map(|def: &MethodDefIdx| self.method_defs.get(def).unwrap())
             {
+                // An alias needs the method it is an alias for.
+                if let MethodImpl::AliasFor(target) = def.implementation() {
+                    if self.method_defs.contains_key(&MethodDefIdx(*target))
+                        && !alive.contains(&MethodDefIdx(*target))
+                    {
+                        to_resurrect.insert(MethodDefIdx(*target));
+                    }
+                }
                 // Iterate torugh the cil of this method, if present
                 let Some(cil) = def.iter_cil(self) else {
                     continue;
@@ -785,16 +821,13 @@ impl Assembly {
                 });
                 // Check if this method reference is also a def. If so, map it to a def
                 let defids = refids.filter_map(|refid| {
-                    self.method_defs
-                        .get(&MethodDefIdx(refid))
-                        .map(|_| MethodDefIdx(refid))
-                        .and_then(|refid| {
-                            if alive.contains(&refid) {
-                                None
-                            } else {
-                                Some(refid)
-                            }
-                        })
+                    self.method_ref_to_def(refid).and_then(|refid| {
+                        if alive.contains(&refid) {
+                            None
+                        } else {
+                            Some(refid)
+                        }
+                    })
                 });
                 to_resurrect.extend(defids);
             }
@@ -815,6 +848,8 @@ impl Assembly {
         self.class_defs.values_mut().for_each(|tdef| {
             tdef.methods_mut()
                 .retain(|def| self.method_defs.contains_key(def));
+            tdef.overrides_mut()
+                .retain(|(_, body)| self.method_defs.contains_key(body));
         });
     }
     pub fn eliminate_dead_code(&mut self) {
@@ -921,7 +956,7 @@ impl Assembly {
             .collect();
         for index in 0..mref_count {
             // Get the full method refernce
-            let mref = &self.method_refs.0[index];
+            let mref = self.method_refs.0[index].clone();
             // Check if this method reference's class has an assembly. If it has, then the method is extern. If it has not, then it is defined in this assembly
             // and must have some kind of implementation
             let class = self.class_ref(mref.class());
@@ -933,6 +968,20 @@ impl Assembly {
             }
             let mref_idx =
                 MethodRefIdx::from_index(std::num::NonZeroU32::new(index as u32 + 1).unwrap());
+            // Call sites of C-variadic functions pass variable arguments, but the function itself is defined without them.
+            let (mref_idx, mref) = if self[mref.sig()].variable_inputs().is_empty() {
+                (mref_idx, mref)
+            } else {
+                let sig = self.alloc_sig(self[mref.sig()].fixed_sig());
+                let def_ref = MethodRef::new(
+                    mref.class(),
+                    mref.name(),
+                    sig,
+                    mref.kind(),
+                    mref.generics().into(),
+                );
+                (self.alloc_methodref(def_ref.clone()), def_ref)
+            };
             // Check if this method already has an implementation.
             if self.method_defs.contains_key(&MethodDefIdx(mref_idx)) {
                 // A method defintion already present, so we don't need to do anyting, so skip.
@@ -1121,6 +1170,8 @@ impl Assembly {
                 CILRoot::StLoc(_, _)
                 | CILRoot::StArg(_, _)
                 | CILRoot::Ret(_)
+                | CILRoot::TailCall(_)
+                | CILRoot::Switch(_)
                 | CILRoot::Pop(_)
                 | CILRoot::Throw(_)
                 | CILRoot::VoidRet
@@ -1578,3 +1629,36 @@ fn link() {
     asm.export("/tmp/link_test.exe", ILExporter::new(*ILASM_FLAVOUR, false));
 }
 config! {LINKER_RECOVER,bool,false}
+#[test]
+fn variadic_externs() {
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let printf = asm.alloc_string("printf");
+    let int = Type::Int(Int::I32);
+    let fmt = asm.nptr(Type::Int(Int::I8));
+    let call_site = |asm: &mut Assembly, args: &[Type]| {
+        let inputs: Vec<_> = std::iter::once(fmt).chain(args.iter().copied()).collect();
+        let sig = asm.alloc_sig(FnSig::new(inputs.into(), int).with_variadic(1));
+        asm.alloc_methodref(MethodRef::new(
+            *main_module,
+            printf,
+            sig,
+            MethodKind::Static,
+            [].into(),
+        ))
+    };
+    let ints = call_site(&mut asm, &[int, int]);
+    let float = call_site(&mut asm, &[Type::Float(super::Float::F64)]);
+    let mut externs = FxHashMap::default();
+    externs.insert("printf", "libc".to_owned());
+    asm.patch_missing_methods(
+        &externs,
+        &FxHashSet::default(),
+        &MissingMethodPatcher::default(),
+    );
+    // Both call sites use the same definition, which has no variable arguments.
+    let def = asm.method_ref_to_def(ints).unwrap();
+    assert_eq!(asm.method_ref_to_def(float), Some(def));
+    assert_eq!(asm[asm[def].sig()].inputs(), &[fmt]);
+    assert!(asm[def].implementation().is_extern());
+}
@@ -57,13 +57,19 @@ impl Assembly {
         self.alloc_class_ref(ClassRef::new(name, asm, cref.is_valuetype(), generics))
     }
     pub(crate) fn translate_sig(&mut self, source: &Assembly, sig: &FnSig) -> FnSig {
-        FnSig::new(
+        let translated = FnSig::new(
             sig.inputs()
                 .iter()
                 .map(|tpe| self.translate_type(source, *tpe))
                 .collect(),
             self.translate_type(source, *sig.output()),
         )
+        .with_conv(sig.conv());
+        if sig.is_variadic() {
+            translated.with_variadic(sig.fixed_inputs().len())
+        } else {
+            translated
+        }
     }
     pub(crate) fn translate_field(&mut self, source: &Assembly, field: FieldDesc) -> FieldDesc {
         let name = self.alloc_string(source[field.name()].as_ref());
@@ -320,6 +326,17 @@ impl Assembly {
                 let node = self.alloc_node(node);
                 CILRoot::Ret(node)
             }
+            CILRoot::TailCall(node) => {
+                let node = self.translate_node(source, source.get_node(node).clone());
+                let node = self.alloc_node(node);
+                CILRoot::TailCall(node)
+            }
+            CILRoot::Switch(switch) => {
+                let (node, table) = *switch;
+                let node = self.translate_node(source, source.get_node(node).clone());
+                let node = self.alloc_node(node);
+                CILRoot::Switch(Box::new((node, table)))
+            }
             CILRoot::Pop(node) => {
                 let node = self.translate_node(source, source.get_node(node).clone());
                 let node = self.alloc_node(node);
@@ -594,7 +611,7 @@ impl Assembly {
                 (tpe, name, *thread_local)
             })
             .collect();
-        let translated = ClassDef::new(
+        let mut translated = ClassDef::new(
             name,
             def.is_valuetype(),
             def.generics(),
@@ -605,6 +622,17 @@ impl Assembly {
             def.explict_size(),
             def.align(),
         );
+        for iface in def.implements() {
+            let iface = self.translate_class_ref(source, *iface);
+            translated.add_interface(iface);
+        }
+        for (decl, body) in def.overrides() {
+            let decl = self.translate_method_ref(source, &source[*decl]);
+            let decl = self.alloc_methodref(decl);
+            let body = self.translate_method_ref(source, &source[body.0]);
+            let body = MethodDefIdx(self.alloc_methodref(body));
+            translated.add_override(decl, body);
+        }
         let class_ref = self.alloc_class_ref(translated.ref_to());
         let (defs_mut, _) = self.class_defs_mut_strings();
         match defs_mut.entry(ClassDefIdx(class_ref)) {
@@ -15,20 +15,19 @@ impl BasicBlock {
         &'block self,
         asm: &'asm Assembly,
     ) -> impl Iterator<Item = BlockId> + 'block {
-        self.roots().iter().filter_map(|root| {
-            match asm.get_root(*root) {
-                CILRoot::Branch(info) => {
-                    let (target, sub_target, _) = info.as_ref();
-                    //Some(*sub_target)
-                    //(eprintln!("{target} {sub_target}");
-                    if *sub_target == 0 {
-                        Some(*target)
-                    } else {
-                        Some(*sub_target)
-                    }
+        self.roots().iter().flat_map(|root| {
+            let target = |(target, sub_target): (u32, u32)| {
+                if sub_target == 0 {
+                    target
+                } else {
+                    sub_target
                 }
-                CILRoot::ExitSpecialRegion { target, .. } => Some(*target),
-                _ => None,
+            };
+            match asm.get_root(*root) {
+                CILRoot::Branch(info) => vec![target((info.0, info.1))],
+                CILRoot::Switch(switch) => switch.1.targets().iter().copied().map(target).collect(),
+                CILRoot::ExitSpecialRegion { target, .. } => vec![*target],
+                _ => vec![],
             }
         })
     }
@@ -1,55 +1,217 @@
-use crate::v2::{
-    asm::MissingMethodPatcher, cilnode::MethodKind, cilroot::BranchCond, BasicBlock, BinOp,
-    CILNode, CILRoot, ClassRef, Const, Int, MethodImpl, MethodRef, Type,
+use crate::{
+    config,
+    v2::{
+        asm::MissingMethodPatcher,
+        cilnode::{ExtendKind, MethodKind, PtrCastRes},
+        cilroot::BranchCond,
+        BasicBlock, BinOp, CILNode, CILRoot, ClassRef, Const, Int, MethodImpl, MethodRef,
+        MethodRefIdx, Type,
+    },
 };
 
 use super::{
     super::{Assembly, NodeIdx},
     math::{int_max, int_min},
 };
-/// Emulates operations on bytes using operations on int32s. Enidianess dependent, can cause segfuaults when used on a page boundary.
-/// TODO: remove when .NET 9 is out.
-pub fn emulate_uint8_cmp_xchng(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
-    generate_atomic(
-        asm,
-        patcher,
-        "cmpxchng",
-        |asm, prev, arg, _| {
-            // 1st, mask the previous value
-            let prev_mask = asm.alloc_node(Const::I32(0x00FF_FFFF));
-            let prev = asm.alloc_node(CILNode::BinOp(prev, prev_mask, BinOp::And));
-            // 2nd. Shift the byte into the least siginificant position(by 24 bytes)
-            let shift_ammount = asm.alloc_node(Const::I32(24));
-            let arg = asm.alloc_node(CILNode::BinOp(arg, shift_ammount, BinOp::Shl));
-            // Assemble those into a new value for the target memory.
-            asm.alloc_node(CILNode::BinOp(prev, arg, BinOp::Or))
-        },
-        Int::I32,
-    );
-    let name = asm.alloc_string("atomic_xchng_u8");
+config!(
+    NATIVE_SUBWORD_ATOMICS,
+    bool,
+    false,
+    "Use the 8 and 16 bit overloads of `Interlocked.CompareExchange` and `Interlocked.Exchange`, added in .NET 9. Off by default, since the emitted assemblies target .NET 8: 8 and 16 bit atomics are then emulated using 32 bit compare exchanges."
+);
+/// Ints with no atomic operations in older .NET runtimes.
+const SUBWORD_INTS: [Int; 4] = [Int::U8, Int::I8, Int::U16, Int::I16];
+/// All ints atomic operations are implemented for.
+const ATOMIC_INTS: [Int; 10] = [
+    Int::U8,
+    Int::U16,
+    Int::U32,
+    Int::U64,
+    Int::USize,
+    Int::I8,
+    Int::I16,
+    Int::I32,
+    Int::I64,
+    Int::ISize,
+];
+/// Returns a reference to `Interlocked.CompareExchange` for `int`.
+fn interlocked_cmpxchng_ref(asm: &mut Assembly, int: Int) -> MethodRefIdx {
+    let tpe = Type::Int(int);
+    let tref = asm.nref(tpe);
+    let cmpxchng_sig = asm.sig([tref, tpe, tpe], tpe);
+    let interlocked = ClassRef::interlocked(asm);
+    let compare_exchange = asm.alloc_string("CompareExchange");
+    asm.alloc_methodref(MethodRef::new(
+        interlocked,
+        compare_exchange,
+        cmpxchng_sig,
+        MethodKind::Static,
+        vec![].into(),
+    ))
+}
+/// Returns a reference to the compare exchange method used for atomics on `int`. This is `Interlocked.CompareExchange`, or the
+/// `atomic_cmpxchng_{int}` builtin for 8 and 16 bit ints, which may be emulated.
+fn cmpxchng_ref(asm: &mut Assembly, int: Int) -> MethodRefIdx {
+    if !SUBWORD_INTS.contains(&int) {
+        return interlocked_cmpxchng_ref(asm, int);
+    }
+    let tpe = Type::Int(int);
+    let tref = asm.nref(tpe);
+    let cmpxchng_sig = asm.sig([tref, tpe, tpe], tpe);
+    let main_module = *asm.main_module();
+    let name = asm.alloc_string(format!("atomic_cmpxchng_{int}", int = int.name()));
+    asm.alloc_methodref(MethodRef::new(
+        main_module,
+        name,
+        cmpxchng_sig,
+        MethodKind::Static,
+        vec![].into(),
+    ))
+}
+/// Adds the `atomic_cmpxchng_{int}` builtin for a 8 or 16 bit int.
+/// If `native` is set, it calls the overload of `Interlocked.CompareExchange` added in .NET 9. Otherwise, it emulates it
+/// using 32 bit compare exchanges on the aligned word containing the value. That word can't cross a page boundary, so the
+/// emulation never touches memory outside of it. This assumes a little-endian target.
+pub fn generate_subword_cmpxchng(
+    asm: &mut Assembly,
+    patcher: &mut MissingMethodPatcher,
+    int: Int,
+    native: bool,
+) {
+    if native {
+        generate_native_subword_cmpxchng(asm, patcher, int);
+    } else {
+        emulate_subword_cmpxchng(asm, patcher, int);
+    }
+}
+fn generate_native_subword_cmpxchng(
+    asm: &mut Assembly,
+    patcher: &mut MissingMethodPatcher,
+    int: Int,
+) {
+    let name = asm.alloc_string(format!("atomic_cmpxchng_{int}", int = int.name()));
     let generator = move |_, asm: &mut Assembly| {
-        let ldarg_0 = asm.alloc_node(CILNode::LdArg(0));
-        let ldarg_1 = asm.alloc_node(CILNode::LdArg(1));
-        let ldloc_0 = asm.alloc_node(CILNode::LdLoc(0));
-        let uint8_idx = asm.alloc_type(Type::Int(Int::U8));
-        // Load value at addr 0 and write it to tmp
-        let arg0_val = asm.alloc_node(CILNode::LdInd {
-            addr: ldarg_0,
-            tpe: uint8_idx,
+        let args = [0, 1, 2].map(|arg| asm.alloc_node(CILNode::LdArg(arg)));
+        let mref = interlocked_cmpxchng_ref(asm, int);
+        let call = asm.alloc_node(CILNode::Call(Box::new((mref, Box::new(args)))));
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(
+                vec![asm.alloc_root(CILRoot::Ret(call))],
+                0,
+                None,
+            )],
+            locals: vec![],
+        }
+    };
+    patcher.insert(name, Box::new(generator));
+}
+/// Emulates a compare exchange of a 8 or 16 bit int using 32 bit compare exchanges of the aligned word containing it.
+fn emulate_subword_cmpxchng(asm: &mut Assembly, patcher: &mut MissingMethodPatcher, int: Int) {
+    let name = asm.alloc_string(format!("atomic_cmpxchng_{int}", int = int.name()));
+    let generator = move |_, asm: &mut Assembly| {
+        let value_mask = match int.size() {
+            Some(1) => 0xFF,
+            Some(2) => 0xFFFF,
+            _ => panic!("ERROR: {int:?} is not a 8 or 16 bit int."),
+        };
+        let word = Type::Int(Int::U32);
+        let word_idx = asm.alloc_type(word);
+        let word_ref = asm.nref(word);
+        let word_ref_idx = asm.alloc_type(word_ref);
+        let (addr, value, comparand) = (
+            asm.alloc_node(CILNode::LdArg(0)),
+            asm.alloc_node(CILNode::LdArg(1)),
+            asm.alloc_node(CILNode::LdArg(2)),
+        );
+        let (aligned, shift, old_word, old) = (
+            asm.alloc_node(CILNode::LdLoc(0)),
+            asm.alloc_node(CILNode::LdLoc(1)),
+            asm.alloc_node(CILNode::LdLoc(2)),
+            asm.alloc_node(CILNode::LdLoc(3)),
+        );
+        // The address of the aligned word containing the value
+        let addr = asm.alloc_node(CILNode::PtrCast(addr, Box::new(PtrCastRes::USize)));
+        let align_mask = asm.alloc_node(Const::USize(!3));
+        let aligned_addr = asm.alloc_node(CILNode::BinOp(addr, align_mask, BinOp::And));
+        let aligned_addr = asm.alloc_node(CILNode::PtrCast(
+            aligned_addr,
+            Box::new(PtrCastRes::Ref(word_idx)),
+        ));
+        // The position of the value within that word, in bits.
+        let offset_mask = asm.alloc_node(Const::USize(3));
+        let offset = asm.alloc_node(CILNode::BinOp(addr, offset_mask, BinOp::And));
+        let eight = asm.alloc_node(Const::USize(8));
+        let bit_offset = asm.alloc_node(CILNode::BinOp(offset, eight, BinOp::Mul));
+        let bit_offset = asm.alloc_node(CILNode::IntCast {
+            input: bit_offset,
+            target: Int::I32,
+            extend: ExtendKind::ZeroExtend,
+        });
+        // Read the whole word, and extract the current value from it.
+        let load_word = asm.alloc_node(CILNode::LdInd {
+            addr: aligned,
+            tpe: word_idx,
             volatile: true,
         });
-        let set_tmp = asm.alloc_root(CILRoot::StLoc(0, arg0_val));
-        // Copy arg1 to addr0
-        let copy_arg1 = asm.alloc_root(CILRoot::StInd(Box::new((
-            ldarg_0,
-            ldarg_1,
-            Type::Int(Int::U8),
-            true,
+        let shifted = asm.alloc_node(CILNode::BinOp(old_word, shift, BinOp::ShrUn));
+        let current = asm.alloc_node(CILNode::IntCast {
+            input: shifted,
+            target: int,
+            extend: ExtendKind::ZeroExtend,
+        });
+        // Replace the value within the word, keeping all the other bytes intact.
+        let value_mask = asm.alloc_node(Const::U32(value_mask));
+        let word_mask = asm.alloc_node(CILNode::BinOp(value_mask, shift, BinOp::Shl));
+        let word_mask = asm.alloc_node(CILNode::UnOp(word_mask, crate::v2::cilnode::UnOp::Not));
+        let rest = asm.alloc_node(CILNode::BinOp(old_word, word_mask, BinOp::And));
+        let value = asm.alloc_node(CILNode::IntCast {
+            input: value,
+            target: Int::U32,
+            extend: ExtendKind::ZeroExtend,
+        });
+        let value = asm.alloc_node(CILNode::BinOp(value, value_mask, BinOp::And));
+        let value = asm.alloc_node(CILNode::BinOp(value, shift, BinOp::Shl));
+        let new_word = asm.alloc_node(CILNode::BinOp(rest, value, BinOp::Or));
+        let word_cmpxchng = interlocked_cmpxchng_ref(asm, Int::U32);
+        let word_cmpxchng = asm.alloc_node(CILNode::Call(Box::new((
+            word_cmpxchng,
+            Box::new([aligned, new_word, old_word]),
         ))));
-        let ret = asm.alloc_root(CILRoot::Ret(ldloc_0));
+        let entry_block = vec![
+            asm.alloc_root(CILRoot::StLoc(0, aligned_addr)),
+            asm.alloc_root(CILRoot::StLoc(1, bit_offset)),
+            asm.alloc_root(CILRoot::Branch(Box::new((1, 0, None)))),
+        ];
+        let loop_block = vec![
+            asm.alloc_root(CILRoot::StLoc(2, load_word)),
+            asm.alloc_root(CILRoot::StLoc(3, current)),
+            // The value is not equal to the comparand, so the exchange fails.
+            asm.alloc_root(CILRoot::Branch(Box::new((
+                2,
+                0,
+                Some(BranchCond::Ne(old, comparand)),
+            )))),
+            // Some other part of the word changed in the meantime, so try again.
+            asm.alloc_root(CILRoot::Branch(Box::new((
+                1,
+                0,
+                Some(BranchCond::Ne(word_cmpxchng, old_word)),
+            )))),
+            asm.alloc_root(CILRoot::Branch(Box::new((2, 0, None)))),
+        ];
+        let exit_block = vec![asm.alloc_root(CILRoot::Ret(old))];
         MethodImpl::MethodBody {
-            blocks: vec![BasicBlock::new(vec![set_tmp, copy_arg1, ret], 0, None)],
-            locals: vec![(None, uint8_idx)],
+            blocks: vec![
+                BasicBlock::new(entry_block, 0, None),
+                BasicBlock::new(loop_block, 1, None),
+                BasicBlock::new(exit_block, 2, None),
+            ],
+            locals: vec![
+                (None, word_ref_idx),
+                (None, asm.alloc_type(Type::Int(Int::I32))),
+                (None, word_idx),
+                (None, asm.alloc_type(Type::Int(int))),
+            ],
         }
     };
     patcher.insert(name, Box::new(generator));
@@ -74,19 +236,7 @@ pub fn generate_atomic(
         let op = op(asm, ldloc_0, ldarg_1, int);
 
         let tpe = Type::Int(int);
-        let tref = asm.nref(tpe);
-
-        let cmpxchng_sig = asm.sig([tref, tpe, tpe], tpe);
-        let interlocked = ClassRef::interlocked(asm);
-
-        let compare_exchange = asm.alloc_string("CompareExchange");
-        let mref = asm.alloc_methodref(MethodRef::new(
-            interlocked,
-            compare_exchange,
-            cmpxchng_sig,
-            MethodKind::Static,
-            vec![].into(),
-        ));
+        let mref = cmpxchng_ref(asm, int);
         let call = asm.alloc_node(CILNode::Call(Box::new((
             mref,
             Box::new([ldarg_0, op, ldloc_0]),
@@ -124,14 +274,6 @@ pub fn generate_atomic_for_ints(
     op_name: &str,
     op: impl Fn(&mut Assembly, NodeIdx, NodeIdx, Int) -> NodeIdx + 'static + Clone,
 ) {
-    const ATOMIC_INTS: [Int; 6] = [
-        Int::U32,
-        Int::U64,
-        Int::USize,
-        Int::I32,
-        Int::I64,
-        Int::ISize,
-    ];
     for int in ATOMIC_INTS {
         generate_atomic(asm, patcher, op_name, op.clone(), int);
     }
@@ -155,11 +297,15 @@ pub fn generate_all_atomics(asm: &mut Assembly, patcher: &mut MissingMethodPatch
     });
     // Max
     generate_atomic_for_ints(asm, patcher, "max", int_max);
-    // Max
+    // Min
     generate_atomic_for_ints(asm, patcher, "min", int_min);
-    // Emulates 1 byte compare exchange
-    emulate_uint8_cmp_xchng(asm, patcher);
-    for int in [Int::ISize, Int::USize] {
+    // Exchange
+    generate_atomic_for_ints(asm, patcher, "xchng", |_, _, rhs, _| rhs);
+    for int in SUBWORD_INTS {
+        generate_subword_cmpxchng(asm, patcher, int, *NATIVE_SUBWORD_ATOMICS);
+    }
+    // `Interlocked.Or` and `Interlocked.And` only support 32 and 64 bit ints.
+    for int in SUBWORD_INTS.into_iter().chain([Int::ISize, Int::USize]) {
         generate_atomic(
             asm,
             patcher,
@@ -174,13 +320,147 @@ pub fn generate_all_atomics(asm: &mut Assembly, patcher: &mut MissingMethodPatch
             |asm, lhs, rhs, _| asm.alloc_node(CILNode::BinOp(lhs, rhs, BinOp::And)),
             int,
         );
-        generate_atomic(
-            asm,
-            patcher,
-            "add",
-            |asm, lhs, rhs, _| asm.alloc_node(CILNode::BinOp(lhs, rhs, BinOp::Add)),
-            int,
+    }
+}
+#[cfg(test)]
+mod tests {
+    use fxhash::FxHashMap;
+
+    use super::*;
+    use crate::v2::interpreter::{add_method, patch, static_mref, Interpreter, Value};
+    /// Runs 8 and 16 bit compare exchanges on a word, using either the native or the emulated `atomic_cmpxchng_{int}`.
+    fn subword_cmpxchng(native: bool) {
+        let mut asm = Assembly::default();
+        let marshal = crate::v2::ClassRef::marshal(&mut asm);
+        let marshal = asm[marshal].clone();
+        let isize = Type::Int(Int::ISize);
+        let u64_tpe = Type::Int(Int::U64);
+        let alloc = asm.alloc_string("AllocHGlobal");
+        let alloc = marshal.static_mref(&[isize], isize, alloc, &mut asm);
+        let cmpxchng = |int: Int, asm: &mut Assembly| {
+            let tpe = Type::Int(int);
+            let tref = asm.nref(tpe);
+            static_mref(
+                asm,
+                &format!("atomic_cmpxchng_{}", int.name()),
+                &[tref, tpe, tpe],
+                tpe,
+            )
+        };
+        let (cmpxchng_u8, cmpxchng_u16) =
+            (cmpxchng(Int::U8, &mut asm), cmpxchng(Int::U16, &mut asm));
+        let size = asm.alloc_node(Const::ISize(8));
+        let ptr = asm.alloc_node(CILNode::Call(Box::new((alloc, [size].into()))));
+        let local = asm.alloc_node(CILNode::LdLoc(0));
+        let u64_idx = asm.alloc_type(u64_tpe);
+        let word = asm.alloc_node(Const::U64(0x1122_3344_5566_7788));
+        let at = |offset: i64, int: Int, asm: &mut Assembly| {
+            let offset = asm.alloc_node(Const::ISize(offset));
+            let addr = asm.alloc_node(CILNode::BinOp(local, offset, BinOp::Add));
+            let tref = asm.nref(Type::Int(int));
+            let tref = asm.alloc_type(tref);
+            asm.alloc_node(CILNode::PtrCast(
+                addr,
+                Box::new(crate::v2::cilnode::PtrCastRes::Ref(tref)),
+            ))
+        };
+        let call = |mref, args: [NodeIdx; 3], asm: &mut Assembly| {
+            asm.alloc_node(CILNode::Call(Box::new((mref, args.into()))))
+        };
+        // Succeeds: the byte at offset 5 is 0x33.
+        let (addr, value, comparand) = (
+            at(5, Int::U8, &mut asm),
+            asm.alloc_node(Const::U8(0xAA)),
+            asm.alloc_node(Const::U8(0x33)),
+        );
+        let ok = call(cmpxchng_u8, [addr, value, comparand], &mut asm);
+        // Fails: the byte is now 0xAA.
+        let value = asm.alloc_node(Const::U8(0x00));
+        let fail = call(cmpxchng_u8, [addr, value, comparand], &mut asm);
+        // Succeeds: the short at offset 2 is 0x5566.
+        let (addr, value, comparand) = (
+            at(2, Int::U16, &mut asm),
+            asm.alloc_node(Const::U16(0xBEEF)),
+            asm.alloc_node(Const::U16(0x5566)),
         );
+        let ok_u16 = call(cmpxchng_u16, [addr, value, comparand], &mut asm);
+        let results: Vec<_> = [ok, fail, ok_u16]
+            .into_iter()
+            .enumerate()
+            .map(|(idx, res)| {
+                let res = asm.alloc_node(CILNode::IntCast {
+                    input: res,
+                    target: Int::U64,
+                    extend: ExtendKind::ZeroExtend,
+                });
+                asm.alloc_root(CILRoot::StLoc(u32::try_from(idx).unwrap() + 1, res))
+            })
+            .collect();
+        let load = asm.alloc_node(CILNode::LdInd {
+            addr: local,
+            tpe: u64_idx,
+            volatile: false,
+        });
+        let mut roots = vec![
+            asm.alloc_root(CILRoot::StLoc(0, ptr)),
+            asm.alloc_root(CILRoot::StInd(Box::new((local, word, u64_tpe, false)))),
+        ];
+        roots.extend(results);
+        let method = |ret: NodeIdx, name: &str, asm: &mut Assembly| {
+            let roots = roots
+                .iter()
+                .copied()
+                .chain([asm.alloc_root(CILRoot::Ret(ret))])
+                .collect();
+            let blocks = vec![BasicBlock::new(roots, 0, None)];
+            add_method(
+                asm,
+                name,
+                &[],
+                u64_tpe,
+                blocks,
+                vec![isize, u64_tpe, u64_tpe, u64_tpe],
+            )
+        };
+        let word = method(load, "word", &mut asm);
+        let [ok, fail, ok_u16] = [1, 2, 3].map(|loc| {
+            let res = asm.alloc_node(CILNode::LdLoc(loc));
+            method(res, &format!("res{loc}"), &mut asm)
+        });
+        let mut overrides: MissingMethodPatcher = FxHashMap::default();
+        for int in [Int::U8, Int::U16] {
+            generate_subword_cmpxchng(&mut asm, &mut overrides, int, native);
+        }
+        patch(&mut asm, &overrides);
+        // Only the native version uses the 8 bit overload of `Interlocked.CompareExchange`.
+        let u8_tpe = Type::Int(Int::U8);
+        let u8_ref = asm.nref(u8_tpe);
+        let sig = asm.sig([u8_ref, u8_tpe, u8_tpe], u8_tpe);
+        let interlocked = ClassRef::interlocked(&mut asm);
+        let compare_exchange = asm.alloc_string("CompareExchange");
+        let native_u8 = MethodRef::new(
+            interlocked,
+            compare_exchange,
+            sig,
+            MethodKind::Static,
+            vec![].into(),
+        );
+        assert_eq!(asm.get_prealllocated_methodref(native_u8).is_some(), native);
+        let mut interp = Interpreter::new(asm, vec![]);
+        let mut run = |mref| interp.call(mref, vec![]).unwrap().unwrap();
+        assert_eq!(run(ok), Value::I64(0x33));
+        assert_eq!(run(fail), Value::I64(0xAA));
+        assert_eq!(run(ok_u16), Value::I64(0x5566));
+        // Only the targeted bytes changed.
+        assert_eq!(run(word), Value::I64(0x1122_AA44_BEEF_7788));
+    }
+    #[test]
+    fn emulated_subword_cmpxchng() {
+        subword_cmpxchng(false);
+    }
+    #[test]
+    fn native_subword_cmpxchng() {
+        subword_cmpxchng(true);
     }
 }
 /*
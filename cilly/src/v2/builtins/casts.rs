@@ -47,7 +47,12 @@ fn clampy_float_to_int(
     };
     patcher.insert(name, Box::new(generator));
 }
-fn float_to_int(asm: &mut Assembly, int: Int, float: Float, patcher: &mut MissingMethodPatcher) {
+pub(super) fn float_to_int(
+    asm: &mut Assembly,
+    int: Int,
+    float: Float,
+    patcher: &mut MissingMethodPatcher,
+) {
     let name = format!("cast_{}_{}", float.name(), int.name());
     let name = asm.alloc_string(name);
     let generator = move |_, asm: &mut Assembly| {
@@ -0,0 +1,909 @@
+//! A platform layer built on the BCL: C ABI functions implemented using the .NET class library, instead of libc and pthreads.
+//! `System.Environment` is used for args and env, `System.IO` for files, `System.Threading` for threads and locks, and
+//! `System.Diagnostics.Stopwatch` / `System.DateTime` for time.
+//!
+//! Those functions are meant to back a `std::sys` implementation for .NET, which does not exist yet. For now, they can only be
+//! called through `extern "C"` declarations, like any other extern function. The linker inserts them into every .NET assembly,
+//! next to the libc imports.
+//!
+//! Strings passed to them are null-terminated UTF-8, and strings returned by them must be freed using `__dotnet_free_str`.
+//! Managed objects(files, threads, locks) are passed around as `GCHandle`s, converted to `isize`. Fallible functions catch
+//! .NET exceptions, return -1 (or a null handle) on failure, and save the `HResult` of the exception, which can be retrived
+//! using `__dotnet_last_error`.
+use std::num::NonZeroU8;
+
+use super::{
+    super::{
+        asm::MissingMethodPatcher,
+        cilnode::{ExtendKind, PtrCastRes},
+        cilroot::BranchCond,
+        tpe::GenericKind,
+        Assembly, BasicBlock, CILNode, CILRoot, ClassRef, Const, Int, MethodImpl, NodeIdx, RootIdx,
+        StaticFieldDesc, Type,
+    },
+    run_thread_dtors_mref, UNMANAGED_THREAD_START,
+};
+use crate::ClassRefIdx;
+/// Name of the thread local static holding the `HResult` of the last exception caught by the platform layer.
+const LAST_ERROR: &str = "dotnet_last_error";
+/// Inserts the BCL-backed platform layer. Requires [`super::instert_threading`] to be called first, since the threads
+/// spawned by `__dotnet_thread_spawn` are started trough an `UnmanagedThreadStart`.
+pub fn insert_dotnet_sys(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let main_mod = asm.main_module();
+    asm.add_static(Type::Int(Int::I32), LAST_ERROR, true, main_mod);
+    insert_env(asm, patcher);
+    insert_time(asm, patcher);
+    insert_threads(asm, patcher);
+    insert_locks(asm, patcher);
+    insert_files(asm, patcher);
+}
+/// Registers a builtin with a body which does not depend on the exact signature it is called with.
+fn builtin(
+    asm: &mut Assembly,
+    patcher: &mut MissingMethodPatcher,
+    name: &str,
+    generator: impl Fn(&mut Assembly) -> MethodImpl + 'static,
+) {
+    let name = asm.alloc_string(name);
+    patcher.insert(name, Box::new(move |_, asm| generator(asm)));
+}
+/// A method body consisting of a single block.
+fn body(roots: Vec<RootIdx>, locals: &[Type], asm: &mut Assembly) -> MethodImpl {
+    MethodImpl::MethodBody {
+        blocks: vec![BasicBlock::new(roots, 0, None)],
+        locals: locals
+            .iter()
+            .map(|tpe| (None, asm.alloc_type(*tpe)))
+            .collect(),
+    }
+}
+/// A method body consisting of a single block, which executes `root`, and then returns.
+fn void_body(root: RootIdx, asm: &mut Assembly) -> MethodImpl {
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    body(vec![root, ret], &[], asm)
+}
+/// A method body returning `val`.
+fn ret_body(val: NodeIdx, asm: &mut Assembly) -> MethodImpl {
+    let ret = asm.alloc_root(CILRoot::Ret(val));
+    body(vec![ret], &[], asm)
+}
+/// A method body executing `roots`, and then returning `val`. If an exception is thrown, its `HResult` is saved, and `err` is returned
+/// instead. `val` may only refer to the local 0, of type `tpe`.
+fn fallible_body(
+    roots: Vec<RootIdx>,
+    val: NodeIdx,
+    tpe: Type,
+    err: Const,
+    asm: &mut Assembly,
+) -> MethodImpl {
+    let mut roots = roots;
+    roots.push(asm.alloc_root(CILRoot::StLoc(0, val)));
+    roots.push(asm.alloc_root(CILRoot::ExitSpecialRegion {
+        target: 2,
+        source: 0,
+    }));
+    // Save the HResult of the exception.
+    let exception = ClassRef::exception(asm);
+    let hresult = asm.alloc_string("get_HResult");
+    let hresult =
+        asm.class_ref(exception)
+            .clone()
+            .virtual_mref(&[], Type::Int(Int::I32), hresult, asm);
+    let get_exception = asm.alloc_node(CILNode::GetException);
+    let hresult = asm.alloc_node(CILNode::Call(Box::new((hresult, [get_exception].into()))));
+    let last_error = last_error(asm);
+    let save_error = asm.alloc_root(CILRoot::SetStaticField {
+        field: last_error,
+        val: hresult,
+    });
+    let exit_handler = asm.alloc_root(CILRoot::ExitSpecialRegion {
+        target: 3,
+        source: 0,
+    });
+    let loc_0 = asm.alloc_node(CILNode::LdLoc(0));
+    let ret_val = asm.alloc_root(CILRoot::Ret(loc_0));
+    let err = asm.alloc_node(err);
+    let ret_err = asm.alloc_root(CILRoot::Ret(err));
+    MethodImpl::MethodBody {
+        blocks: vec![
+            BasicBlock::new(
+                roots,
+                0,
+                Some(vec![BasicBlock::new(
+                    vec![save_error, exit_handler],
+                    1,
+                    None,
+                )]),
+            ),
+            BasicBlock::new(vec![ret_val], 2, None),
+            BasicBlock::new(vec![ret_err], 3, None),
+        ],
+        locals: vec![(None, asm.alloc_type(tpe))],
+    }
+}
+fn last_error(asm: &mut Assembly) -> crate::v2::StaticFieldIdx {
+    let main_mod = *asm.main_module();
+    let name = asm.alloc_string(LAST_ERROR);
+    asm.alloc_sfld(StaticFieldDesc::new(main_mod, name, Type::Int(Int::I32)))
+}
+fn class(name: &str, asm_name: &str, is_valuetype: bool, asm: &mut Assembly) -> ClassRefIdx {
+    let name = asm.alloc_string(name);
+    let asm_name = Some(asm.alloc_string(asm_name));
+    asm.alloc_class_ref(ClassRef::new(name, asm_name, is_valuetype, [].into()))
+}
+fn static_call(
+    class: ClassRefIdx,
+    name: &str,
+    inputs: &[Type],
+    output: Type,
+    args: &[NodeIdx],
+    asm: &mut Assembly,
+) -> NodeIdx {
+    let name = asm.alloc_string(name);
+    let mref = asm
+        .class_ref(class)
+        .clone()
+        .static_mref(inputs, output, name, asm);
+    asm.alloc_node(CILNode::Call(Box::new((mref, args.into()))))
+}
+fn virtual_call(
+    class: ClassRefIdx,
+    name: &str,
+    inputs: &[Type],
+    output: Type,
+    args: &[NodeIdx],
+    asm: &mut Assembly,
+) -> NodeIdx {
+    let name = asm.alloc_string(name);
+    let mref = asm
+        .class_ref(class)
+        .clone()
+        .virtual_mref(inputs, output, name, asm);
+    asm.alloc_node(CILNode::Call(Box::new((mref, args.into()))))
+}
+/// Turns the call `node` into a root, discarding its result if it has any.
+fn pop(node: NodeIdx, asm: &mut Assembly) -> RootIdx {
+    let CILNode::Call(info) = asm[node].clone() else {
+        return asm.alloc_root(CILRoot::Pop(node));
+    };
+    if *asm[asm[info.0].sig()].output() == Type::Void {
+        asm.alloc_root(CILRoot::Call(info))
+    } else {
+        asm.alloc_root(CILRoot::Pop(node))
+    }
+}
+fn arg(idx: u32, asm: &mut Assembly) -> NodeIdx {
+    asm.alloc_node(CILNode::LdArg(idx))
+}
+/// Converts a null-terminated UTF-8 string to a managed string.
+fn mstring(utf8: NodeIdx, asm: &mut Assembly) -> NodeIdx {
+    let utf8 = asm.alloc_node(CILNode::PtrCast(utf8, Box::new(PtrCastRes::ISize)));
+    let marshal = ClassRef::marshal(asm);
+    static_call(
+        marshal,
+        "PtrToStringUTF8",
+        &[Type::Int(Int::ISize)],
+        Type::PlatformString,
+        &[utf8],
+        asm,
+    )
+}
+/// Converts a managed string to a null-terminated UTF-8 string, which has to be freed using `__dotnet_free_str`.
+fn utf8(mstring: NodeIdx, asm: &mut Assembly) -> NodeIdx {
+    let marshal = ClassRef::marshal(asm);
+    let ptr = static_call(
+        marshal,
+        "StringToCoTaskMemUTF8",
+        &[Type::PlatformString],
+        Type::Int(Int::ISize),
+        &[mstring],
+        asm,
+    );
+    let u8_tpe = asm.alloc_type(Type::Int(Int::U8));
+    asm.alloc_node(CILNode::PtrCast(ptr, Box::new(PtrCastRes::Ptr(u8_tpe))))
+}
+/// Gets the object behind a `GCHandle`.
+fn handle_to_obj(handle: NodeIdx, class: ClassRefIdx, asm: &mut Assembly) -> NodeIdx {
+    let main_module = *asm.main_module();
+    let obj = static_call(
+        main_module,
+        "handle_to_obj",
+        &[Type::Int(Int::ISize)],
+        Type::PlatformObject,
+        &[handle],
+        asm,
+    );
+    let class = asm.alloc_type(Type::ClassRef(class));
+    asm.alloc_node(CILNode::CheckedCast(obj, class))
+}
+/// Creates a new `GCHandle` to `obj`.
+fn obj_to_handle(obj: NodeIdx, asm: &mut Assembly) -> NodeIdx {
+    let handle = asm[obj].clone().ref_to_handle(asm);
+    asm.alloc_node(handle)
+}
+/// Frees the `GCHandle` `handle`, using the local `local` of type `GCHandle`.
+fn free_handle(handle: NodeIdx, local: u32, asm: &mut Assembly) -> [RootIdx; 2] {
+    let gc_handle = ClassRef::gc_handle(asm);
+    let handle = static_call(
+        gc_handle,
+        "FromIntPtr",
+        &[Type::Int(Int::ISize)],
+        Type::ClassRef(gc_handle),
+        &[handle],
+        asm,
+    );
+    let set_local = asm.alloc_root(CILRoot::StLoc(local, handle));
+    let free = asm.alloc_string("Free");
+    let free = asm
+        .class_ref(gc_handle)
+        .clone()
+        .instance(&[], Type::Void, free, asm);
+    let local = asm.alloc_node(CILNode::LdLocA(local));
+    let free = asm.alloc_root(CILRoot::Call(Box::new((free, [local].into()))));
+    [set_local, free]
+}
+fn insert_env(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let string = asm.alloc_type(Type::PlatformString);
+    let string_array = Type::PlatformArray {
+        elem: string,
+        dims: NonZeroU8::new(1).unwrap(),
+    };
+    let command_line_args = move |asm: &mut Assembly| {
+        let env = ClassRef::enviroment(asm);
+        static_call(env, "GetCommandLineArgs", &[], string_array, &[], asm)
+    };
+    builtin(asm, patcher, "__dotnet_args_count", move |asm| {
+        let args = command_line_args(asm);
+        let len = asm.alloc_node(CILNode::LdLen(args));
+        let len = asm.alloc_node(CILNode::IntCast {
+            input: len,
+            target: Int::USize,
+            extend: ExtendKind::ZeroExtend,
+        });
+        ret_body(len, asm)
+    });
+    builtin(asm, patcher, "__dotnet_arg", move |asm| {
+        let args = command_line_args(asm);
+        let idx = arg(0, asm);
+        let arg = asm.alloc_node(CILNode::LdElelemRef {
+            array: args,
+            index: idx,
+        });
+        let arg = utf8(arg, asm);
+        ret_body(arg, asm)
+    });
+    builtin(asm, patcher, "__dotnet_getenv", move |asm| {
+        let env = ClassRef::enviroment(asm);
+        let name = arg(0, asm);
+        let name = mstring(name, asm);
+        let val = static_call(
+            env,
+            "GetEnvironmentVariable",
+            &[Type::PlatformString],
+            Type::PlatformString,
+            &[name],
+            asm,
+        );
+        let set_val = asm.alloc_root(CILRoot::StLoc(0, val));
+        let val = asm.alloc_node(CILNode::LdLoc(0));
+        // Unset variables are null, and null is returned for them.
+        let is_unset = asm.alloc_root(CILRoot::Branch(Box::new((
+            1,
+            0,
+            Some(BranchCond::False(val)),
+        ))));
+        let val = utf8(val, asm);
+        let ret_val = asm.alloc_root(CILRoot::Ret(val));
+        let null = asm.alloc_node(Const::USize(0));
+        let u8_tpe = asm.alloc_type(Type::Int(Int::U8));
+        let null = asm.alloc_node(CILNode::PtrCast(null, Box::new(PtrCastRes::Ptr(u8_tpe))));
+        let ret_null = asm.alloc_root(CILRoot::Ret(null));
+        MethodImpl::MethodBody {
+            blocks: vec![
+                BasicBlock::new(vec![set_val, is_unset, ret_val], 0, None),
+                BasicBlock::new(vec![ret_null], 1, None),
+            ],
+            locals: vec![(None, string)],
+        }
+    });
+    let set_env = move |val: Option<NodeIdx>, asm: &mut Assembly| {
+        let env = ClassRef::enviroment(asm);
+        let name = arg(0, asm);
+        let name = mstring(name, asm);
+        let val = val.unwrap_or_else(|| {
+            let string = ClassRef::string(asm);
+            asm.alloc_node(Const::Null(string))
+        });
+        let set = static_call(
+            env,
+            "SetEnvironmentVariable",
+            &[Type::PlatformString, Type::PlatformString],
+            Type::Void,
+            &[name, val],
+            asm,
+        );
+        let set = pop(set, asm);
+        let zero = asm.alloc_node(Const::I32(0));
+        fallible_body(vec![set], zero, Type::Int(Int::I32), Const::I32(-1), asm)
+    };
+    builtin(asm, patcher, "__dotnet_setenv", move |asm| {
+        let val = arg(1, asm);
+        let val = mstring(val, asm);
+        set_env(Some(val), asm)
+    });
+    builtin(asm, patcher, "__dotnet_unsetenv", move |asm| {
+        set_env(None, asm)
+    });
+    builtin(asm, patcher, "__dotnet_free_str", move |asm| {
+        let marshal = ClassRef::marshal(asm);
+        let ptr = arg(0, asm);
+        let ptr = asm.alloc_node(CILNode::PtrCast(ptr, Box::new(PtrCastRes::ISize)));
+        let free = static_call(
+            marshal,
+            "FreeCoTaskMem",
+            &[Type::Int(Int::ISize)],
+            Type::Void,
+            &[ptr],
+            asm,
+        );
+        let free = pop(free, asm);
+        void_body(free, asm)
+    });
+    builtin(asm, patcher, "__dotnet_exit", move |asm| {
+        // `exit` runs the destructors of the thread locals of the calling thread.
+        let run_dtors = run_thread_dtors_mref(asm);
+        let run_dtors = asm.alloc_root(CILRoot::Call(Box::new((run_dtors, [].into()))));
+        let env = ClassRef::enviroment(asm);
+        let code = arg(0, asm);
+        let exit = static_call(
+            env,
+            "Exit",
+            &[Type::Int(Int::I32)],
+            Type::Void,
+            &[code],
+            asm,
+        );
+        let exit = pop(exit, asm);
+        let ret = asm.alloc_root(CILRoot::VoidRet);
+        body(vec![run_dtors, exit, ret], &[], asm)
+    });
+    builtin(asm, patcher, "__dotnet_last_error", move |asm| {
+        let last_error = last_error(asm);
+        let last_error = asm.alloc_node(CILNode::LdStaticField(last_error));
+        ret_body(last_error, asm)
+    });
+}
+/// Number of 100 ns ticks between 0001-01-01 and the unix epoch.
+const UNIX_EPOCH_TICKS: i64 = 621_355_968_000_000_000;
+fn insert_time(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let stopwatch = move |asm: &mut Assembly| {
+        class("System.Diagnostics.Stopwatch", "System.Runtime", false, asm)
+    };
+    builtin(asm, patcher, "__dotnet_monotonic_ticks", move |asm| {
+        let stopwatch = stopwatch(asm);
+        let ticks = static_call(
+            stopwatch,
+            "GetTimestamp",
+            &[],
+            Type::Int(Int::I64),
+            &[],
+            asm,
+        );
+        ret_body(ticks, asm)
+    });
+    builtin(asm, patcher, "__dotnet_monotonic_frequency", move |asm| {
+        let stopwatch = stopwatch(asm);
+        let frequency = asm.alloc_string("Frequency");
+        let frequency = asm.alloc_sfld(StaticFieldDesc::new(
+            stopwatch,
+            frequency,
+            Type::Int(Int::I64),
+        ));
+        let frequency = asm.alloc_node(CILNode::LdStaticField(frequency));
+        ret_body(frequency, asm)
+    });
+    // Returns the number of 100 ns ticks since the unix epoch.
+    builtin(asm, patcher, "__dotnet_system_time_ticks", move |asm| {
+        let date_time = class("System.DateTime", "System.Runtime", true, asm);
+        let now = static_call(
+            date_time,
+            "get_UtcNow",
+            &[],
+            Type::ClassRef(date_time),
+            &[],
+            asm,
+        );
+        let set_now = asm.alloc_root(CILRoot::StLoc(0, now));
+        let now = asm.alloc_node(CILNode::LdLocA(0));
+        let ticks = asm.alloc_string("get_Ticks");
+        let ticks = asm
+            .class_ref(date_time)
+            .clone()
+            .instance(&[], Type::Int(Int::I64), ticks, asm);
+        let ticks = asm.alloc_node(CILNode::Call(Box::new((ticks, [now].into()))));
+        let epoch = asm.alloc_node(Const::I64(UNIX_EPOCH_TICKS));
+        let ticks = asm.alloc_node(CILNode::BinOp(ticks, epoch, super::super::BinOp::Sub));
+        let ret = asm.alloc_root(CILRoot::Ret(ticks));
+        body(vec![set_now, ret], &[Type::ClassRef(date_time)], asm)
+    });
+}
+fn insert_threads(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    builtin(asm, patcher, "__dotnet_thread_spawn", move |asm| {
+        let void_ptr = asm.nptr(Type::Void);
+        let start_sig = asm.sig([void_ptr], void_ptr);
+        // Create an UnmanagedThreadStart, which calls `start` with `data` on the new thread.
+        let uts = asm.alloc_string(UNMANAGED_THREAD_START);
+        let uts = ClassRef::new(uts, None, false, [].into());
+        let uts_ctor = uts.ctor(&[Type::FnPtr(start_sig), void_ptr], asm);
+        let start = arg(0, asm);
+        let data = arg(1, asm);
+        let uts_obj = asm.alloc_node(CILNode::Call(Box::new((uts_ctor, [start, data].into()))));
+        let start_name = asm.alloc_string("Start");
+        let uts_start = uts.virtual_mref(&[], Type::Void, start_name, asm);
+        let uts_start = asm.alloc_node(CILNode::LdFtn(uts_start));
+        let uts_start = asm.alloc_node(CILNode::PtrCast(uts_start, Box::new(PtrCastRes::ISize)));
+        let thread_start = ClassRef::thread_start(asm);
+        let thread_start_ctor = asm
+            .class_ref(thread_start)
+            .clone()
+            .ctor(&[Type::PlatformObject, Type::Int(Int::ISize)], asm);
+        let thread_start = asm.alloc_node(CILNode::Call(Box::new((
+            thread_start_ctor,
+            [uts_obj, uts_start].into(),
+        ))));
+        // Create and start the thread
+        let thread = ClassRef::thread(asm);
+        let thread_ctor = asm
+            .class_ref(thread)
+            .clone()
+            .ctor(&[Type::ClassRef(ClassRef::thread_start(asm))], asm);
+        let thread_obj = asm.alloc_node(CILNode::Call(Box::new((
+            thread_ctor,
+            [thread_start].into(),
+        ))));
+        let create_thread = asm.alloc_root(CILRoot::StLoc(0, thread_obj));
+        let thread_obj = asm.alloc_node(CILNode::LdLoc(0));
+        let start = virtual_call(thread, "Start", &[], Type::Void, &[thread_obj], asm);
+        let start = pop(start, asm);
+        let handle = obj_to_handle(thread_obj, asm);
+        let ret = asm.alloc_root(CILRoot::Ret(handle));
+        body(
+            vec![create_thread, start, ret],
+            &[Type::ClassRef(thread)],
+            asm,
+        )
+    });
+    builtin(asm, patcher, "__dotnet_thread_join", move |asm| {
+        let thread = ClassRef::thread(asm);
+        let handle = arg(0, asm);
+        let thread_obj = handle_to_obj(handle, thread, asm);
+        let join = virtual_call(thread, "Join", &[], Type::Void, &[thread_obj], asm);
+        let join = pop(join, asm);
+        let [get_handle, free] = free_handle(handle, 0, asm);
+        let ret = asm.alloc_root(CILRoot::VoidRet);
+        let gc_handle = ClassRef::gc_handle(asm);
+        body(
+            vec![join, get_handle, free, ret],
+            &[Type::ClassRef(gc_handle)],
+            asm,
+        )
+    });
+    builtin(asm, patcher, "__dotnet_thread_detach", move |asm| {
+        let handle = arg(0, asm);
+        let [get_handle, free] = free_handle(handle, 0, asm);
+        let ret = asm.alloc_root(CILRoot::VoidRet);
+        let gc_handle = ClassRef::gc_handle(asm);
+        body(
+            vec![get_handle, free, ret],
+            &[Type::ClassRef(gc_handle)],
+            asm,
+        )
+    });
+    builtin(asm, patcher, "__dotnet_thread_yield", move |asm| {
+        let thread = ClassRef::thread(asm);
+        let yield_now = static_call(thread, "Yield", &[], Type::Bool, &[], asm);
+        let yield_now = pop(yield_now, asm);
+        void_body(yield_now, asm)
+    });
+    builtin(asm, patcher, "__dotnet_thread_sleep_ms", move |asm| {
+        let thread = ClassRef::thread(asm);
+        let ms = arg(0, asm);
+        let sleep = static_call(
+            thread,
+            "Sleep",
+            &[Type::Int(Int::I32)],
+            Type::Void,
+            &[ms],
+            asm,
+        );
+        let sleep = pop(sleep, asm);
+        void_body(sleep, asm)
+    });
+    builtin(asm, patcher, "__dotnet_thread_set_name", move |asm| {
+        let thread = ClassRef::thread(asm);
+        let current = static_call(
+            thread,
+            "get_CurrentThread",
+            &[],
+            Type::ClassRef(thread),
+            &[],
+            asm,
+        );
+        let name = arg(0, asm);
+        let name = mstring(name, asm);
+        let set_name = virtual_call(
+            thread,
+            "set_Name",
+            &[Type::PlatformString],
+            Type::Void,
+            &[current, name],
+            asm,
+        );
+        let set_name = pop(set_name, asm);
+        void_body(set_name, asm)
+    });
+    builtin(asm, patcher, "__dotnet_available_parallelism", move |asm| {
+        let env = ClassRef::enviroment(asm);
+        let count = static_call(
+            env,
+            "get_ProcessorCount",
+            &[],
+            Type::Int(Int::I32),
+            &[],
+            asm,
+        );
+        ret_body(count, asm)
+    });
+}
+/// Locks and condition variables, backed by `System.Threading.Monitor`.
+fn insert_locks(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let monitor =
+        move |asm: &mut Assembly| class("System.Threading.Monitor", "System.Threading", false, asm);
+    builtin(asm, patcher, "__dotnet_lock_new", move |asm| {
+        let object = ClassRef::object(asm);
+        let ctor = asm.class_ref(object).clone().ctor(&[], asm);
+        let obj = asm.alloc_node(CILNode::Call(Box::new((ctor, [].into()))));
+        let handle = obj_to_handle(obj, asm);
+        ret_body(handle, asm)
+    });
+    builtin(asm, patcher, "__dotnet_lock_free", move |asm| {
+        let handle = arg(0, asm);
+        let [get_handle, free] = free_handle(handle, 0, asm);
+        let ret = asm.alloc_root(CILRoot::VoidRet);
+        let gc_handle = ClassRef::gc_handle(asm);
+        body(
+            vec![get_handle, free, ret],
+            &[Type::ClassRef(gc_handle)],
+            asm,
+        )
+    });
+    for (name, monitor_fn) in [
+        ("__dotnet_lock_enter", "Enter"),
+        ("__dotnet_lock_exit", "Exit"),
+        ("__dotnet_lock_notify_one", "Pulse"),
+        ("__dotnet_lock_notify_all", "PulseAll"),
+    ] {
+        builtin(asm, patcher, name, move |asm| {
+            let monitor = monitor(asm);
+            let object = ClassRef::object(asm);
+            let handle = arg(0, asm);
+            let obj = handle_to_obj(handle, object, asm);
+            let call = static_call(
+                monitor,
+                monitor_fn,
+                &[Type::PlatformObject],
+                Type::Void,
+                &[obj],
+                asm,
+            );
+            let call = pop(call, asm);
+            void_body(call, asm)
+        });
+    }
+    builtin(asm, patcher, "__dotnet_lock_try_enter", move |asm| {
+        let monitor = monitor(asm);
+        let object = ClassRef::object(asm);
+        let handle = arg(0, asm);
+        let obj = handle_to_obj(handle, object, asm);
+        let entered = static_call(
+            monitor,
+            "TryEnter",
+            &[Type::PlatformObject],
+            Type::Bool,
+            &[obj],
+            asm,
+        );
+        ret_body(entered, asm)
+    });
+    // Waits for a notification, for at most `timeout_ms`(or forever, if it is -1). Returns false on timeout.
+    builtin(asm, patcher, "__dotnet_lock_wait", move |asm| {
+        let monitor = monitor(asm);
+        let object = ClassRef::object(asm);
+        let handle = arg(0, asm);
+        let obj = handle_to_obj(handle, object, asm);
+        let timeout = arg(1, asm);
+        let notified = static_call(
+            monitor,
+            "Wait",
+            &[Type::PlatformObject, Type::Int(Int::I32)],
+            Type::Bool,
+            &[obj, timeout],
+            asm,
+        );
+        ret_body(notified, asm)
+    });
+}
+fn insert_files(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let stream = move |asm: &mut Assembly| class("System.IO.Stream", "System.Runtime", false, asm);
+    // Opens the file at `path`. `mode`, `access` and `share` are the values of `FileMode`, `FileAccess` and `FileShare`.
+    builtin(asm, patcher, "__dotnet_file_open", move |asm| {
+        let file_stream = class("System.IO.FileStream", "System.Runtime", false, asm);
+        let file_mode = class("System.IO.FileMode", "System.Runtime", true, asm);
+        let file_access = class("System.IO.FileAccess", "System.Runtime", true, asm);
+        let file_share = class("System.IO.FileShare", "System.Runtime", true, asm);
+        let ctor = asm.class_ref(file_stream).clone().ctor(
+            &[
+                Type::PlatformString,
+                Type::ClassRef(file_mode),
+                Type::ClassRef(file_access),
+                Type::ClassRef(file_share),
+            ],
+            asm,
+        );
+        let path = arg(0, asm);
+        let path = mstring(path, asm);
+        let (mode, access, share) = (arg(1, asm), arg(2, asm), arg(3, asm));
+        let file = asm.alloc_node(CILNode::Call(Box::new((
+            ctor,
+            [path, mode, access, share].into(),
+        ))));
+        let handle = obj_to_handle(file, asm);
+        fallible_body(vec![], handle, Type::Int(Int::ISize), Const::ISize(0), asm)
+    });
+    for (name, open) in [
+        ("__dotnet_stdin", "OpenStandardInput"),
+        ("__dotnet_stdout", "OpenStandardOutput"),
+        ("__dotnet_stderr", "OpenStandardError"),
+    ] {
+        builtin(asm, patcher, name, move |asm| {
+            let console = ClassRef::console(asm);
+            let stream = stream(asm);
+            let stream = static_call(console, open, &[], Type::ClassRef(stream), &[], asm);
+            let handle = obj_to_handle(stream, asm);
+            ret_body(handle, asm)
+        });
+    }
+    // Reads and writes go trough a managed buffer, since streams can't operate on pointers directly.
+    let byte_array = move |asm: &mut Assembly| {
+        let u8_tpe = asm.alloc_type(Type::Int(Int::U8));
+        Type::PlatformArray {
+            elem: u8_tpe,
+            dims: NonZeroU8::new(1).unwrap(),
+        }
+    };
+    let alloc_buffer = move |len: NodeIdx, asm: &mut Assembly| {
+        let gc = ClassRef::gc(asm);
+        let generic = asm.alloc_type(Type::PlatformGeneric(0, GenericKind::CallGeneric));
+        let name = asm.alloc_string("AllocateUninitializedArray");
+        let alloc = asm.class_ref(gc).clone().static_mref_generic(
+            &[Type::Int(Int::I32), Type::Bool],
+            Type::PlatformArray {
+                elem: generic,
+                dims: NonZeroU8::new(1).unwrap(),
+            },
+            name,
+            asm,
+            [Type::Int(Int::U8)].into(),
+        );
+        let pinned = asm.alloc_node(Const::Bool(false));
+        asm.alloc_node(CILNode::Call(Box::new((alloc, [len, pinned].into()))))
+    };
+    let copy = move |inputs: &[Type], args: &[NodeIdx], asm: &mut Assembly| {
+        let marshal = ClassRef::marshal(asm);
+        let copy = static_call(marshal, "Copy", inputs, Type::Void, args, asm);
+        pop(copy, asm)
+    };
+    builtin(asm, patcher, "__dotnet_file_read", move |asm| {
+        let stream = stream(asm);
+        let byte_array = byte_array(asm);
+        let len = arg(2, asm);
+        let buffer = alloc_buffer(len, asm);
+        let set_buffer = asm.alloc_root(CILRoot::StLoc(1, buffer));
+        let buffer = asm.alloc_node(CILNode::LdLoc(1));
+        let handle = arg(0, asm);
+        let stream_obj = handle_to_obj(handle, stream, asm);
+        let zero = asm.alloc_node(Const::I32(0));
+        let read = virtual_call(
+            stream,
+            "Read",
+            &[byte_array, Type::Int(Int::I32), Type::Int(Int::I32)],
+            Type::Int(Int::I32),
+            &[stream_obj, buffer, zero, len],
+            asm,
+        );
+        let set_read = asm.alloc_root(CILRoot::StLoc(2, read));
+        let read = asm.alloc_node(CILNode::LdLoc(2));
+        let dst = arg(1, asm);
+        let dst = asm.alloc_node(CILNode::PtrCast(dst, Box::new(PtrCastRes::ISize)));
+        let copy = copy(
+            &[
+                byte_array,
+                Type::Int(Int::I32),
+                Type::Int(Int::ISize),
+                Type::Int(Int::I32),
+            ],
+            &[buffer, zero, dst, read],
+            asm,
+        );
+        let mut mimpl = fallible_body(
+            vec![set_buffer, set_read, copy],
+            read,
+            Type::Int(Int::I32),
+            Const::I32(-1),
+            asm,
+        );
+        add_locals(&mut mimpl, &[byte_array, Type::Int(Int::I32)], asm);
+        mimpl
+    });
+    builtin(asm, patcher, "__dotnet_file_write", move |asm| {
+        let stream = stream(asm);
+        let byte_array = byte_array(asm);
+        let len = arg(2, asm);
+        let buffer = alloc_buffer(len, asm);
+        let set_buffer = asm.alloc_root(CILRoot::StLoc(1, buffer));
+        let buffer = asm.alloc_node(CILNode::LdLoc(1));
+        let src = arg(1, asm);
+        let src = asm.alloc_node(CILNode::PtrCast(src, Box::new(PtrCastRes::ISize)));
+        let zero = asm.alloc_node(Const::I32(0));
+        let copy = copy(
+            &[
+                Type::Int(Int::ISize),
+                byte_array,
+                Type::Int(Int::I32),
+                Type::Int(Int::I32),
+            ],
+            &[src, buffer, zero, len],
+            asm,
+        );
+        let handle = arg(0, asm);
+        let stream_obj = handle_to_obj(handle, stream, asm);
+        let write = virtual_call(
+            stream,
+            "Write",
+            &[byte_array, Type::Int(Int::I32), Type::Int(Int::I32)],
+            Type::Void,
+            &[stream_obj, buffer, zero, len],
+            asm,
+        );
+        let write = pop(write, asm);
+        let mut mimpl = fallible_body(
+            vec![set_buffer, copy, write],
+            len,
+            Type::Int(Int::I32),
+            Const::I32(-1),
+            asm,
+        );
+        add_locals(&mut mimpl, &[byte_array], asm);
+        mimpl
+    });
+    builtin(asm, patcher, "__dotnet_file_flush", move |asm| {
+        let stream = stream(asm);
+        let handle = arg(0, asm);
+        let stream_obj = handle_to_obj(handle, stream, asm);
+        let flush = virtual_call(stream, "Flush", &[], Type::Void, &[stream_obj], asm);
+        let flush = pop(flush, asm);
+        let zero = asm.alloc_node(Const::I32(0));
+        fallible_body(vec![flush], zero, Type::Int(Int::I32), Const::I32(-1), asm)
+    });
+    // Seeks to `offset`, relative to `origin`(a `SeekOrigin`). Returns the new position.
+    builtin(asm, patcher, "__dotnet_file_seek", move |asm| {
+        let stream = stream(asm);
+        let seek_origin = class("System.IO.SeekOrigin", "System.Runtime", true, asm);
+        let handle = arg(0, asm);
+        let stream_obj = handle_to_obj(handle, stream, asm);
+        let (offset, origin) = (arg(1, asm), arg(2, asm));
+        let pos = virtual_call(
+            stream,
+            "Seek",
+            &[Type::Int(Int::I64), Type::ClassRef(seek_origin)],
+            Type::Int(Int::I64),
+            &[stream_obj, offset, origin],
+            asm,
+        );
+        fallible_body(vec![], pos, Type::Int(Int::I64), Const::I64(-1), asm)
+    });
+    builtin(asm, patcher, "__dotnet_file_close", move |asm| {
+        let stream = stream(asm);
+        let handle = arg(0, asm);
+        let stream_obj = handle_to_obj(handle, stream, asm);
+        let dispose = virtual_call(stream, "Dispose", &[], Type::Void, &[stream_obj], asm);
+        let dispose = pop(dispose, asm);
+        let [get_handle, free] = free_handle(handle, 0, asm);
+        let ret = asm.alloc_root(CILRoot::VoidRet);
+        let gc_handle = ClassRef::gc_handle(asm);
+        body(
+            vec![dispose, get_handle, free, ret],
+            &[Type::ClassRef(gc_handle)],
+            asm,
+        )
+    });
+    for (name, class_name, method, returns_dir_info) in [
+        ("__dotnet_remove_file", "System.IO.File", "Delete", false),
+        (
+            "__dotnet_remove_dir",
+            "System.IO.Directory",
+            "Delete",
+            false,
+        ),
+        (
+            "__dotnet_create_dir",
+            "System.IO.Directory",
+            "CreateDirectory",
+            true,
+        ),
+    ] {
+        builtin(asm, patcher, name, move |asm| {
+            let output = if returns_dir_info {
+                let dir_info = class("System.IO.DirectoryInfo", "System.Runtime", false, asm);
+                Type::ClassRef(dir_info)
+            } else {
+                Type::Void
+            };
+            let class = class(class_name, "System.Runtime", false, asm);
+            let path = arg(0, asm);
+            let path = mstring(path, asm);
+            let call = static_call(class, method, &[Type::PlatformString], output, &[path], asm);
+            let call = pop(call, asm);
+            let zero = asm.alloc_node(Const::I32(0));
+            fallible_body(vec![call], zero, Type::Int(Int::I32), Const::I32(-1), asm)
+        });
+    }
+}
+/// Appends `locals` to the locals of `mimpl`.
+fn add_locals(mimpl: &mut MethodImpl, locals: &[Type], asm: &mut Assembly) {
+    let MethodImpl::MethodBody {
+        locals: mlocals, ..
+    } = mimpl
+    else {
+        unreachable!()
+    };
+    mlocals.extend(locals.iter().map(|tpe| (None, asm.alloc_type(*tpe))));
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::interpreter::{insert_builtins, static_mref, Interpreter, Value};
+    #[test]
+    fn dotnet_sys_env() {
+        let mut asm = Assembly::default();
+        let usize_tpe = Type::Int(Int::USize);
+        let u8_ptr = asm.nptr(Type::Int(Int::U8));
+        let mut builtin =
+            |name: &str, inputs: &[Type], output: Type| static_mref(&mut asm, name, inputs, output);
+        let args_count = builtin("__dotnet_args_count", &[], usize_tpe);
+        let arg = builtin("__dotnet_arg", &[usize_tpe], u8_ptr);
+        let getenv = builtin("__dotnet_getenv", &[u8_ptr], u8_ptr);
+        let free_str = builtin("__dotnet_free_str", &[u8_ptr], Type::Void);
+        insert_builtins(&mut asm);
+        let mut interp = Interpreter::new(asm, vec!["test".into(), "arg1".into()]);
+        assert_eq!(
+            interp.call(args_count, vec![]).unwrap(),
+            Some(Value::NInt(2))
+        );
+        let Some(Value::NInt(arg1)) = interp.call(arg, vec![Value::NInt(1)]).unwrap() else {
+            panic!("Expected a pointer");
+        };
+        assert_eq!(interp.mem.read_cstr(arg1 as u64).unwrap(), b"arg1");
+        let live_allocs = interp.mem.live_allocs();
+        interp.call(free_str, vec![Value::NInt(arg1)]).unwrap();
+        assert_eq!(interp.mem.live_allocs(), live_allocs - 1);
+        // Unset variables are null.
+        let Some(name) = interp.call(arg, vec![Value::NInt(0)]).unwrap() else {
+            panic!("Expected a pointer");
+        };
+        assert_eq!(
+            interp.call(getenv, vec![name]).unwrap(),
+            Some(Value::NInt(0))
+        );
+    }
+}
@@ -0,0 +1,1553 @@
+//! Software implementation of IEEE 754 binary128 (`f128`).
+//!
+//! .NET has no quadruple precision float, so in managed mode every `f128` operation is emulated using the bits of the
+//! float, stored in a `System.UInt128`. In C mode, `_Float128` is used directly.
+use crate::{
+    v2::{
+        asm::MissingMethodPatcher,
+        cilnode::{ExtendKind, PtrCastRes, UnOp},
+        cilroot::CmpKind,
+        Assembly, BasicBlock, BinOp, BranchCond, CILNode, CILRoot, ClassRef, Const, Float, Int,
+        MethodImpl, NodeIdx, RootIdx, StringIdx, TypeIdx,
+    },
+    Type,
+};
+
+/// Layout of an IEEE 754 binary floating-point format.
+#[derive(Clone, Copy)]
+struct Format {
+    /// Number of explicitly stored significand bits.
+    sig_bits: u32,
+    exp_bits: u32,
+}
+impl Format {
+    const F32: Self = Self {
+        sig_bits: 23,
+        exp_bits: 8,
+    };
+    const F64: Self = Self {
+        sig_bits: 52,
+        exp_bits: 11,
+    };
+    const F128: Self = Self {
+        sig_bits: 112,
+        exp_bits: 15,
+    };
+    fn bias(self) -> i32 {
+        (1 << (self.exp_bits - 1)) - 1
+    }
+    fn max_exp(self) -> i32 {
+        (1 << self.exp_bits) - 1
+    }
+    fn sign(self) -> u128 {
+        1 << (self.sig_bits + self.exp_bits)
+    }
+    fn abs_mask(self) -> u128 {
+        self.sign() - 1
+    }
+    fn implicit_bit(self) -> u128 {
+        1 << self.sig_bits
+    }
+    fn sig_mask(self) -> u128 {
+        self.implicit_bit() - 1
+    }
+    fn inf(self) -> u128 {
+        (self.max_exp() as u128) << self.sig_bits
+    }
+    fn quiet_bit(self) -> u128 {
+        1 << (self.sig_bits - 1)
+    }
+    fn nan(self) -> u128 {
+        self.inf() | self.quiet_bit()
+    }
+}
+const F128: Format = Format::F128;
+/// Number of extra bits below the significand used for rounding: guard, round and sticky.
+const GUARD_BITS: u32 = 3;
+
+/// Builds the body of a software float routine, one block at a time.
+struct Body<'asm> {
+    asm: &'asm mut Assembly,
+    locals: Vec<(Option<StringIdx>, TypeIdx)>,
+    blocks: Vec<BasicBlock>,
+    roots: Vec<RootIdx>,
+    block: u32,
+    next_block: u32,
+    /// Set if the current block already ends with a return or an unconditional jump.
+    terminated: bool,
+}
+impl<'asm> Body<'asm> {
+    fn new(asm: &'asm mut Assembly) -> Self {
+        Self {
+            asm,
+            locals: vec![],
+            blocks: vec![],
+            roots: vec![],
+            block: 0,
+            next_block: 1,
+            terminated: false,
+        }
+    }
+    fn finish(mut self) -> MethodImpl {
+        self.blocks
+            .push(BasicBlock::new(self.roots, self.block, None));
+        MethodImpl::MethodBody {
+            blocks: self.blocks,
+            locals: self.locals,
+        }
+    }
+    fn local(&mut self, tpe: Type) -> u32 {
+        let tpe = self.asm.alloc_type(tpe);
+        self.locals.push((None, tpe));
+        (self.locals.len() - 1).try_into().unwrap()
+    }
+    /// Allocates a new `u128` local, and sets it to `val`.
+    fn wide_local(&mut self, val: NodeIdx) -> u32 {
+        let loc = self.local(Type::Int(Int::U128));
+        self.set(loc, val);
+        loc
+    }
+    /// Allocates a new `i32` local, and sets it to `val`.
+    fn int_local(&mut self, val: NodeIdx) -> u32 {
+        let loc = self.local(Type::Int(Int::I32));
+        self.set(loc, val);
+        loc
+    }
+    fn root(&mut self, root: CILRoot) {
+        let root = self.asm.alloc_root(root);
+        self.roots.push(root);
+    }
+    fn node(&mut self, node: CILNode) -> NodeIdx {
+        self.asm.alloc_node(node)
+    }
+    fn set(&mut self, loc: u32, val: NodeIdx) {
+        self.root(CILRoot::StLoc(loc, val));
+    }
+    fn get(&mut self, loc: u32) -> NodeIdx {
+        self.node(CILNode::LdLoc(loc))
+    }
+    fn ret(&mut self, val: NodeIdx) {
+        self.root(CILRoot::Ret(val));
+        self.terminated = true;
+    }
+    /// Reserves a new block.
+    fn label(&mut self) -> u32 {
+        self.next_block += 1;
+        self.next_block - 1
+    }
+    fn jump(&mut self, target: u32) {
+        self.root(CILRoot::Branch(Box::new((target, 0, None))));
+        self.terminated = true;
+    }
+    fn branch(&mut self, target: u32, cond: BranchCond) {
+        self.root(CILRoot::Branch(Box::new((target, 0, Some(cond)))));
+    }
+    /// Ends the current block, and continues in the block `label`.
+    fn start(&mut self, label: u32) {
+        if !self.terminated {
+            self.jump(label);
+        }
+        let roots = std::mem::take(&mut self.roots);
+        self.blocks.push(BasicBlock::new(roots, self.block, None));
+        self.block = label;
+        self.terminated = false;
+    }
+    /// Emits the code generated by `then` only if `cond` is true.
+    fn when(&mut self, cond: BranchCond, then: impl FnOnce(&mut Self)) {
+        let then_block = self.label();
+        let end = self.label();
+        self.branch(then_block, cond);
+        self.jump(end);
+        self.start(then_block);
+        then(self);
+        self.start(end);
+    }
+    /// Emits the code generated by `then` if `cond` is true, and the code generated by `otherwise` if it is not.
+    fn when_else(
+        &mut self,
+        cond: BranchCond,
+        then: impl FnOnce(&mut Self),
+        otherwise: impl FnOnce(&mut Self),
+    ) {
+        let then_block = self.label();
+        let end = self.label();
+        self.branch(then_block, cond);
+        otherwise(self);
+        if !self.terminated {
+            self.jump(end);
+        }
+        self.start(then_block);
+        then(self);
+        self.start(end);
+    }
+    fn int(&mut self, val: i32) -> NodeIdx {
+        self.node(CILNode::Const(Box::new(Const::I32(val))))
+    }
+    fn wide(&mut self, val: u128) -> NodeIdx {
+        self.node(CILNode::Const(Box::new(Const::U128(val))))
+    }
+    fn int_op(&mut self, lhs: NodeIdx, rhs: NodeIdx, op: BinOp) -> NodeIdx {
+        self.node(CILNode::BinOp(lhs, rhs, op))
+    }
+    /// Calls the static method `name` of `System.UInt128`.
+    fn call_wide(
+        &mut self,
+        name: &str,
+        args: &[NodeIdx],
+        inputs: &[Type],
+        output: Type,
+    ) -> NodeIdx {
+        let class = ClassRef::uint_128(self.asm);
+        let class = self.asm[class].clone();
+        let name = self.asm.alloc_string(name);
+        let mref = class.static_mref(inputs, output, name, self.asm);
+        self.node(CILNode::Call(Box::new((mref, args.into()))))
+    }
+    /// Performs a binary operation on two `u128`s. Shifts take an `i32` rhs, and comparisons return a `bool`.
+    fn wide_op(&mut self, lhs: NodeIdx, rhs: NodeIdx, op: BinOp) -> NodeIdx {
+        let u128 = Type::Int(Int::U128);
+        let (rhs_tpe, output) = match op {
+            BinOp::Shl | BinOp::Shr | BinOp::ShrUn => (Type::Int(Int::I32), u128),
+            BinOp::Eq | BinOp::Lt | BinOp::LtUn | BinOp::Gt | BinOp::GtUn => (u128, Type::Bool),
+            _ => (u128, u128),
+        };
+        self.call_wide(op.dotnet_name(), &[lhs, rhs], &[u128, rhs_tpe], output)
+    }
+    /// Compares two `u128`s, using the `System.UInt128` comparison operator `name`.
+    fn wide_cmp(&mut self, lhs: NodeIdx, rhs: NodeIdx, name: &str) -> NodeIdx {
+        let u128 = Type::Int(Int::U128);
+        self.call_wide(name, &[lhs, rhs], &[u128, u128], Type::Bool)
+    }
+    fn wide_ne(&mut self, lhs: NodeIdx, rhs: NodeIdx) -> NodeIdx {
+        self.wide_cmp(lhs, rhs, "op_Inequality")
+    }
+    /// Calculates `lhs op rhs` and stores the result in `lhs`.
+    fn update(&mut self, lhs: u32, rhs: NodeIdx, op: BinOp) {
+        let val = self.get(lhs);
+        let val = self.wide_op(val, rhs, op);
+        self.set(lhs, val);
+    }
+    /// Calculates `lhs op rhs` and stores the result in the `i32` local `lhs`.
+    fn update_int(&mut self, lhs: u32, rhs: NodeIdx, op: BinOp) {
+        let val = self.get(lhs);
+        let val = self.int_op(val, rhs, op);
+        self.set(lhs, val);
+    }
+    /// Truncates a `u128` to `target`.
+    fn narrow(&mut self, val: NodeIdx, target: Int) -> NodeIdx {
+        self.call_wide(
+            "op_Explicit",
+            &[val],
+            &[Type::Int(Int::U128)],
+            Type::Int(target),
+        )
+    }
+    /// Zero extends a `u64` to a `u128`.
+    fn widen(&mut self, val: NodeIdx) -> NodeIdx {
+        self.call_wide(
+            "op_Implicit",
+            &[val],
+            &[Type::Int(Int::U64)],
+            Type::Int(Int::U128),
+        )
+    }
+    /// Zero extends a non-negative `i32` to a `u128`.
+    fn widen_int(&mut self, val: NodeIdx) -> NodeIdx {
+        let val = self.node(CILNode::IntCast {
+            input: val,
+            target: Int::U64,
+            extend: ExtendKind::ZeroExtend,
+        });
+        self.widen(val)
+    }
+    fn leading_zeros(&mut self, val: NodeIdx) -> NodeIdx {
+        let u128 = Type::Int(Int::U128);
+        let count = self.call_wide("LeadingZeroCount", &[val], &[u128], u128);
+        self.narrow(count, Int::I32)
+    }
+    /// Reinterprets the bits of `val` as a value of type `target`.
+    fn transmute(&mut self, val: NodeIdx, src: Type, target: Type) -> NodeIdx {
+        let loc = self.local(src);
+        self.set(loc, val);
+        let addr = self.node(CILNode::LdLocA(loc));
+        self.load_as(addr, target)
+    }
+    /// Loads the bits of the argument `arg` as a value of type `target`.
+    fn arg_as(&mut self, arg: u32, target: Type) -> NodeIdx {
+        let addr = self.node(CILNode::LdArgA(arg));
+        self.load_as(addr, target)
+    }
+    fn load_as(&mut self, addr: NodeIdx, target: Type) -> NodeIdx {
+        let target = self.asm.alloc_type(target);
+        let ptr = self.node(CILNode::RefToPtr(addr));
+        let ptr = self.node(CILNode::PtrCast(ptr, Box::new(PtrCastRes::Ptr(target))));
+        self.node(CILNode::LdInd {
+            addr: ptr,
+            tpe: target,
+            volatile: false,
+        })
+    }
+    /// Loads the bits of the `f128` argument `arg`.
+    fn arg_bits(&mut self, arg: u32) -> u32 {
+        let bits = self.arg_as(arg, Type::Int(Int::U128));
+        self.wide_local(bits)
+    }
+    /// Returns a float of type `float` with the bits `bits`.
+    fn ret_float(&mut self, bits: NodeIdx, float: Float) {
+        let val = match float {
+            Float::F128 => self.transmute(bits, Type::Int(Int::U128), Type::Float(float)),
+            Float::F64 | Float::F32 => {
+                let int = if float == Float::F64 {
+                    Int::U64
+                } else {
+                    Int::U32
+                };
+                let bits = self.narrow(bits, int);
+                self.transmute(bits, Type::Int(int), Type::Float(float))
+            }
+            Float::F16 => panic!("ERROR: f16 is not supported by the software f128 routines."),
+        };
+        self.ret(val);
+    }
+    /// Returns a `f128` with the bits `bits`.
+    fn ret_f128(&mut self, bits: NodeIdx) {
+        self.ret_float(bits, Float::F128);
+    }
+    /// Returns the `f128` with the bits `bits` if `cond` is true.
+    fn ret_f128_if(&mut self, cond: BranchCond, bits: NodeIdx) {
+        self.when(cond, |body| body.ret_f128(bits));
+    }
+    /// Shifts `val` right by `shift`, keeping track of the shifted out bits in the lowest bit.
+    /// `shift` must be greater than 0.
+    fn shr_sticky(&mut self, val: u32, shift: u32) {
+        let shift_node = self.get(shift);
+        let max = self.int(128);
+        self.when_else(
+            BranchCond::Ge(shift_node, max, CmpKind::Signed),
+            |body| {
+                // All bits got shifted out.
+                let curr = body.get(val);
+                let zero = body.wide(0);
+                let is_nonzero = body.wide_ne(curr, zero);
+                body.set(val, zero);
+                let one = body.wide(1);
+                body.when(BranchCond::True(is_nonzero), |body| body.set(val, one));
+            },
+            |body| {
+                let curr = body.get(val);
+                let lost_shift = body.int_op(max, shift_node, BinOp::Sub);
+                let lost = body.wide_op(curr, lost_shift, BinOp::Shl);
+                let lost = body.wide_local(lost);
+                body.update(val, shift_node, BinOp::Shr);
+                let lost = body.get(lost);
+                let zero = body.wide(0);
+                let is_inexact = body.wide_ne(lost, zero);
+                let one = body.wide(1);
+                body.when(BranchCond::True(is_inexact), |body| {
+                    body.update(val, one, BinOp::Or);
+                });
+            },
+        );
+    }
+    /// Rounds (to nearest, ties to even) and packs a float of the format `fmt` into its bits, returning a local holding
+    /// them. The value packed is `sig * 2^(exp - bias - sig_bits - GUARD_BITS)`, with `sign` already in its place.
+    fn round_pack(&mut self, fmt: Format, sign: NodeIdx, exp: u32, sig: u32) -> u32 {
+        let res = self.local(Type::Int(Int::U128));
+        let done = self.label();
+        let sig_node = self.get(sig);
+        let zero = self.wide(0);
+        let is_zero = self.wide_op(sig_node, zero, BinOp::Eq);
+        self.when(BranchCond::True(is_zero), |body| {
+            body.set(res, sign);
+            body.jump(done);
+        });
+        // Move the leading bit of `sig` to its place.
+        let leading_zeros = self.leading_zeros(sig_node);
+        let lead = (127 - fmt.sig_bits - GUARD_BITS) as i32;
+        let lead = self.int(lead);
+        let shift = self.int_op(lead, leading_zeros, BinOp::Sub);
+        let shift = self.int_local(shift);
+        let shift_node = self.get(shift);
+        let int_zero = self.int(0);
+        self.when_else(
+            BranchCond::Gt(shift_node, int_zero, CmpKind::Signed),
+            |body| body.shr_sticky(sig, shift),
+            |body| {
+                let neg_shift = body.node(CILNode::UnOp(shift_node, UnOp::Neg));
+                body.update(sig, neg_shift, BinOp::Shl);
+            },
+        );
+        self.update_int(exp, shift_node, BinOp::Add);
+        // Overflow to infinity.
+        let exp_node = self.get(exp);
+        let max_exp = self.int(fmt.max_exp());
+        let inf = self.wide(fmt.inf());
+        self.when(BranchCond::Ge(exp_node, max_exp, CmpKind::Signed), |body| {
+            let inf = body.wide_op(sign, inf, BinOp::Or);
+            body.set(res, inf);
+            body.jump(done);
+        });
+        // Subnormal results.
+        self.when(
+            BranchCond::Le(exp_node, int_zero, CmpKind::Signed),
+            |body| {
+                let one = body.int(1);
+                let shift = body.int_op(one, exp_node, BinOp::Sub);
+                let shift = body.int_local(shift);
+                body.shr_sticky(sig, shift);
+                body.set(exp, int_zero);
+            },
+        );
+        let sig_node = self.get(sig);
+        let exp_node = self.get(exp);
+        let guard_mask = self.wide((1 << GUARD_BITS) - 1);
+        let round = self.wide_op(sig_node, guard_mask, BinOp::And);
+        let round = self.narrow(round, Int::I32);
+        let round = self.int_local(round);
+        let guard_bits = self.int(GUARD_BITS as i32);
+        let packed = self.wide_op(sig_node, guard_bits, BinOp::Shr);
+        let sig_mask = self.wide(fmt.sig_mask());
+        let packed = self.wide_op(packed, sig_mask, BinOp::And);
+        let exp_bits = self.widen_int(exp_node);
+        let sig_bits = self.int(fmt.sig_bits as i32);
+        let exp_bits = self.wide_op(exp_bits, sig_bits, BinOp::Shl);
+        let packed = self.wide_op(packed, exp_bits, BinOp::Or);
+        self.set(res, packed);
+        let round = self.get(round);
+        let half = self.int(1 << (GUARD_BITS - 1));
+        let one = self.wide(1);
+        self.when(BranchCond::Gt(round, half, CmpKind::Signed), |body| {
+            body.update(res, one, BinOp::Add);
+        });
+        self.when(BranchCond::Eq(round, half), |body| {
+            let res_node = body.get(res);
+            let odd = body.wide_op(res_node, one, BinOp::And);
+            body.update(res, odd, BinOp::Add);
+        });
+        self.update(res, sign, BinOp::Or);
+        self.start(done);
+        res
+    }
+    /// Splits the finite, non-zero `f128` with the bits `bits` into its exponent and its significand, including the
+    /// implicit bit. If `normalize` is set, the significands of subnormals get shifted, so that their leading bit is
+    /// in the place of the implicit bit.
+    fn unpack(&mut self, bits: u32, normalize: bool) -> (u32, u32) {
+        let bits = self.get(bits);
+        let sig_bits = self.int(F128.sig_bits as i32);
+        let exp = self.wide_op(bits, sig_bits, BinOp::Shr);
+        let exp_mask = self.wide(F128.max_exp() as u128);
+        let exp = self.wide_op(exp, exp_mask, BinOp::And);
+        let exp = self.narrow(exp, Int::I32);
+        let exp = self.int_local(exp);
+        let sig_mask = self.wide(F128.sig_mask());
+        let sig = self.wide_op(bits, sig_mask, BinOp::And);
+        let sig = self.wide_local(sig);
+        let exp_node = self.get(exp);
+        let zero = self.int(0);
+        let one = self.int(1);
+        self.when_else(
+            BranchCond::Eq(exp_node, zero),
+            |body| {
+                if normalize {
+                    let sig_node = body.get(sig);
+                    let leading_zeros = body.leading_zeros(sig_node);
+                    let lead = body.int((127 - F128.sig_bits) as i32);
+                    let shift = body.int_op(leading_zeros, lead, BinOp::Sub);
+                    let shift = body.int_local(shift);
+                    let shift = body.get(shift);
+                    body.update(sig, shift, BinOp::Shl);
+                    let exp_val = body.int_op(one, shift, BinOp::Sub);
+                    body.set(exp, exp_val);
+                } else {
+                    body.set(exp, one);
+                }
+            },
+            |body| {
+                let implicit = body.wide(F128.implicit_bit());
+                body.update(sig, implicit, BinOp::Or);
+            },
+        );
+        (exp, sig)
+    }
+    /// Returns a quiet NaN if either `lhs` or `rhs` is a NaN.
+    fn propagate_nan(&mut self, lhs: u32, lhs_abs: u32, rhs: u32, rhs_abs: u32) {
+        let inf = self.wide(F128.inf());
+        let quiet = self.wide(F128.quiet_bit());
+        for (val, abs) in [(lhs, lhs_abs), (rhs, rhs_abs)] {
+            let abs = self.get(abs);
+            let is_nan = self.wide_op(abs, inf, BinOp::Gt);
+            let val = self.get(val);
+            let quieted = self.wide_op(val, quiet, BinOp::Or);
+            self.ret_f128_if(BranchCond::True(is_nan), quieted);
+        }
+    }
+    fn abs(&mut self, bits: u32) -> u32 {
+        let bits = self.get(bits);
+        let mask = self.wide(F128.abs_mask());
+        let abs = self.wide_op(bits, mask, BinOp::And);
+        self.wide_local(abs)
+    }
+}
+/// `a + b`, or `a - b` if `negate_rhs` is set.
+fn add(asm: &mut Assembly, negate_rhs: bool) -> MethodImpl {
+    let mut body = Body::new(asm);
+    let a = body.arg_bits(0);
+    let b = body.arg_bits(1);
+    let sign = body.wide(F128.sign());
+    if negate_rhs {
+        body.update(b, sign, BinOp::XOr);
+    }
+    let a_abs = body.abs(a);
+    let b_abs = body.abs(b);
+    body.propagate_nan(a, a_abs, b, b_abs);
+    let (a_node, b_node) = (body.get(a), body.get(b));
+    let (a_abs_node, b_abs_node) = (body.get(a_abs), body.get(b_abs));
+    let inf = body.wide(F128.inf());
+    let zero = body.wide(0);
+    // inf - inf is NaN, any other sum with an infinity is that infinity.
+    let a_inf = body.wide_op(a_abs_node, inf, BinOp::Eq);
+    body.when(BranchCond::True(a_inf), |body| {
+        let diff = body.wide_op(a_node, b_node, BinOp::XOr);
+        let nan = body.wide(F128.nan());
+        let is_opposite = body.wide_op(diff, sign, BinOp::Eq);
+        body.ret_f128_if(BranchCond::True(is_opposite), nan);
+        body.ret_f128(a_node);
+    });
+    let b_inf = body.wide_op(b_abs_node, inf, BinOp::Eq);
+    body.ret_f128_if(BranchCond::True(b_inf), b_node);
+    // Zeros. -0 + -0 is -0, all other sums of zeros are +0.
+    let a_zero = body.wide_op(a_abs_node, zero, BinOp::Eq);
+    body.when(BranchCond::True(a_zero), |body| {
+        let b_zero = body.wide_op(b_abs_node, zero, BinOp::Eq);
+        let both = body.wide_op(a_node, b_node, BinOp::And);
+        body.ret_f128_if(BranchCond::True(b_zero), both);
+        body.ret_f128(b_node);
+    });
+    let b_zero = body.wide_op(b_abs_node, zero, BinOp::Eq);
+    body.ret_f128_if(BranchCond::True(b_zero), a_node);
+    // Make sure |a| >= |b|.
+    let b_bigger = body.wide_op(a_abs_node, b_abs_node, BinOp::Lt);
+    body.when(BranchCond::True(b_bigger), |body| {
+        let tmp = body.wide_local(a_node);
+        body.set(a, b_node);
+        let tmp = body.get(tmp);
+        body.set(b, tmp);
+    });
+    let (a_exp, a_sig) = body.unpack(a, false);
+    let (b_exp, b_sig) = body.unpack(b, false);
+    let guard_bits = body.int(GUARD_BITS as i32);
+    body.update(a_sig, guard_bits, BinOp::Shl);
+    body.update(b_sig, guard_bits, BinOp::Shl);
+    // Align the smaller significand
+    let (a_exp_node, b_exp_node) = (body.get(a_exp), body.get(b_exp));
+    let diff = body.int_op(a_exp_node, b_exp_node, BinOp::Sub);
+    let diff = body.int_local(diff);
+    let diff_node = body.get(diff);
+    let int_zero = body.int(0);
+    body.when(
+        BranchCond::Gt(diff_node, int_zero, CmpKind::Signed),
+        |body| {
+            body.shr_sticky(b_sig, diff);
+        },
+    );
+    let b_sig_node = body.get(b_sig);
+    let a_node = body.get(a);
+    let b_node = body.get(b);
+    let signs = body.wide_op(a_node, b_node, BinOp::XOr);
+    let signs = body.wide_op(signs, sign, BinOp::And);
+    let same_sign = body.wide_op(signs, zero, BinOp::Eq);
+    body.when_else(
+        BranchCond::True(same_sign),
+        |body| body.update(a_sig, b_sig_node, BinOp::Add),
+        |body| {
+            body.update(a_sig, b_sig_node, BinOp::Sub);
+            // Exact cancellation results in +0.
+            let a_sig_node = body.get(a_sig);
+            let cancelled = body.wide_op(a_sig_node, zero, BinOp::Eq);
+            body.ret_f128_if(BranchCond::True(cancelled), zero);
+        },
+    );
+    let res_sign = body.wide_op(a_node, sign, BinOp::And);
+    let res = body.round_pack(F128, res_sign, a_exp, a_sig);
+    let res = body.get(res);
+    body.ret_f128(res);
+    body.finish()
+}
+/// `a * b`
+fn mul(asm: &mut Assembly) -> MethodImpl {
+    let mut body = Body::new(asm);
+    let a = body.arg_bits(0);
+    let b = body.arg_bits(1);
+    let a_abs = body.abs(a);
+    let b_abs = body.abs(b);
+    body.propagate_nan(a, a_abs, b, b_abs);
+    let (a_node, b_node) = (body.get(a), body.get(b));
+    let (a_abs_node, b_abs_node) = (body.get(a_abs), body.get(b_abs));
+    let sign = body.wide(F128.sign());
+    let res_sign = body.wide_op(a_node, b_node, BinOp::XOr);
+    let res_sign = body.wide_op(res_sign, sign, BinOp::And);
+    let res_sign = body.wide_local(res_sign);
+    let res_sign = body.get(res_sign);
+    let inf = body.wide(F128.inf());
+    let zero = body.wide(0);
+    let nan = body.wide(F128.nan());
+    let signed_inf = body.wide_op(res_sign, inf, BinOp::Or);
+    // inf * 0 is NaN, inf * x is inf.
+    for (abs, other_abs) in [(a_abs_node, b_abs_node), (b_abs_node, a_abs_node)] {
+        let is_inf = body.wide_op(abs, inf, BinOp::Eq);
+        body.when(BranchCond::True(is_inf), |body| {
+            let other_zero = body.wide_op(other_abs, zero, BinOp::Eq);
+            body.ret_f128_if(BranchCond::True(other_zero), nan);
+            body.ret_f128(signed_inf);
+        });
+    }
+    for abs in [a_abs_node, b_abs_node] {
+        let is_zero = body.wide_op(abs, zero, BinOp::Eq);
+        body.ret_f128_if(BranchCond::True(is_zero), res_sign);
+    }
+    let (a_exp, a_sig) = body.unpack(a, true);
+    let (b_exp, b_sig) = body.unpack(b, true);
+    // 226 bit product of the 113 bit significands, calculated from 64 bit halves.
+    let half = body.int(64);
+    let low_mask = body.wide(u128::from(u64::MAX));
+    let (a_sig, b_sig) = (body.get(a_sig), body.get(b_sig));
+    let a_lo = body.wide_op(a_sig, low_mask, BinOp::And);
+    let a_hi = body.wide_op(a_sig, half, BinOp::Shr);
+    let b_lo = body.wide_op(b_sig, low_mask, BinOp::And);
+    let b_hi = body.wide_op(b_sig, half, BinOp::Shr);
+    let lo = body.wide_op(a_lo, b_lo, BinOp::Mul);
+    let lo = body.wide_local(lo);
+    let mid_a = body.wide_op(a_lo, b_hi, BinOp::Mul);
+    let mid_b = body.wide_op(a_hi, b_lo, BinOp::Mul);
+    let mid = body.wide_op(mid_a, mid_b, BinOp::Add);
+    let mid = body.wide_local(mid);
+    let mid = body.get(mid);
+    let hi = body.wide_op(a_hi, b_hi, BinOp::Mul);
+    let mid_hi = body.wide_op(mid, half, BinOp::Shr);
+    let hi = body.wide_op(hi, mid_hi, BinOp::Add);
+    let hi = body.wide_local(hi);
+    let lo_node = body.get(lo);
+    let mid_lo = body.wide_op(mid, half, BinOp::Shl);
+    let sum = body.wide_op(lo_node, mid_lo, BinOp::Add);
+    let sum = body.wide_local(sum);
+    let sum = body.get(sum);
+    let carry = body.wide_op(sum, lo_node, BinOp::Lt);
+    let one = body.wide(1);
+    body.when(BranchCond::True(carry), |body| {
+        body.update(hi, one, BinOp::Add)
+    });
+    body.set(lo, sum);
+    // The product has `2 * sig_bits` fractional bits, and we need `sig_bits + GUARD_BITS` of them. The rest gets
+    // folded into the sticky bit.
+    let dropped = (F128.sig_bits - GUARD_BITS) as i32;
+    let hi_shift = body.int(128 - dropped);
+    let lo_shift = body.int(dropped);
+    let hi = body.get(hi);
+    let lo_node = body.get(lo);
+    let sig = body.wide_op(hi, hi_shift, BinOp::Shl);
+    let sig_lo = body.wide_op(lo_node, lo_shift, BinOp::Shr);
+    let sig = body.wide_op(sig, sig_lo, BinOp::Or);
+    let sig = body.wide_local(sig);
+    let lost = body.wide_op(lo_node, hi_shift, BinOp::Shl);
+    let inexact = body.wide_ne(lost, zero);
+    body.when(BranchCond::True(inexact), |body| {
+        body.update(sig, one, BinOp::Or)
+    });
+    let (a_exp, b_exp) = (body.get(a_exp), body.get(b_exp));
+    let exp = body.int_op(a_exp, b_exp, BinOp::Add);
+    let bias = body.int(F128.bias());
+    let exp = body.int_op(exp, bias, BinOp::Sub);
+    let exp = body.int_local(exp);
+    let res = body.round_pack(F128, res_sign, exp, sig);
+    let res = body.get(res);
+    body.ret_f128(res);
+    body.finish()
+}
+/// `a / b`
+fn div(asm: &mut Assembly) -> MethodImpl {
+    let mut body = Body::new(asm);
+    let a = body.arg_bits(0);
+    let b = body.arg_bits(1);
+    let a_abs = body.abs(a);
+    let b_abs = body.abs(b);
+    body.propagate_nan(a, a_abs, b, b_abs);
+    let (a_node, b_node) = (body.get(a), body.get(b));
+    let (a_abs_node, b_abs_node) = (body.get(a_abs), body.get(b_abs));
+    let sign = body.wide(F128.sign());
+    let res_sign = body.wide_op(a_node, b_node, BinOp::XOr);
+    let res_sign = body.wide_op(res_sign, sign, BinOp::And);
+    let res_sign = body.wide_local(res_sign);
+    let res_sign = body.get(res_sign);
+    let inf = body.wide(F128.inf());
+    let zero = body.wide(0);
+    let nan = body.wide(F128.nan());
+    let signed_inf = body.wide_op(res_sign, inf, BinOp::Or);
+    // inf / inf and 0 / 0 are NaN.
+    let a_inf = body.wide_op(a_abs_node, inf, BinOp::Eq);
+    body.when(BranchCond::True(a_inf), |body| {
+        let b_inf = body.wide_op(b_abs_node, inf, BinOp::Eq);
+        body.ret_f128_if(BranchCond::True(b_inf), nan);
+        body.ret_f128(signed_inf);
+    });
+    let b_inf = body.wide_op(b_abs_node, inf, BinOp::Eq);
+    body.ret_f128_if(BranchCond::True(b_inf), res_sign);
+    let b_zero = body.wide_op(b_abs_node, zero, BinOp::Eq);
+    body.when(BranchCond::True(b_zero), |body| {
+        let a_zero = body.wide_op(a_abs_node, zero, BinOp::Eq);
+        body.ret_f128_if(BranchCond::True(a_zero), nan);
+        body.ret_f128(signed_inf);
+    });
+    let a_zero = body.wide_op(a_abs_node, zero, BinOp::Eq);
+    body.ret_f128_if(BranchCond::True(a_zero), res_sign);
+    let (a_exp, a_sig) = body.unpack(a, true);
+    let (b_exp, b_sig) = body.unpack(b, true);
+    // Restoring division, producing the significand, the guard bits and one extra bit.
+    let quotient_bits = (F128.sig_bits + GUARD_BITS + 2) as i32;
+    let quotient = body.wide_local(zero);
+    let int_zero = body.int(0);
+    let counter = body.int_local(int_zero);
+    let one = body.wide(1);
+    let int_one = body.int(1);
+    let iteration = body.label();
+    body.start(iteration);
+    body.update(quotient, int_one, BinOp::Shl);
+    let (rem, divisor) = (body.get(a_sig), body.get(b_sig));
+    let fits = body.wide_op(rem, divisor, BinOp::Lt);
+    body.when(BranchCond::False(fits), |body| {
+        body.update(a_sig, divisor, BinOp::Sub);
+        body.update(quotient, one, BinOp::Or);
+    });
+    body.update(a_sig, int_one, BinOp::Shl);
+    body.update_int(counter, int_one, BinOp::Add);
+    let counter = body.get(counter);
+    let quotient_bits = body.int(quotient_bits);
+    body.branch(
+        iteration,
+        BranchCond::Lt(counter, quotient_bits, CmpKind::Signed),
+    );
+    let rem = body.get(a_sig);
+    let inexact = body.wide_ne(rem, zero);
+    body.when(BranchCond::True(inexact), |body| {
+        body.update(quotient, one, BinOp::Or);
+    });
+    let (a_exp, b_exp) = (body.get(a_exp), body.get(b_exp));
+    let exp = body.int_op(a_exp, b_exp, BinOp::Sub);
+    let bias = body.int(F128.bias() - 1);
+    let exp = body.int_op(exp, bias, BinOp::Add);
+    let exp = body.int_local(exp);
+    let res = body.round_pack(F128, res_sign, exp, quotient);
+    let res = body.get(res);
+    body.ret_f128(res);
+    body.finish()
+}
+/// `a % b`, with the sign of `a`, like C's `fmod`.
+///
+/// The remainder is exact, and computed by long division, one bit at a time. So, this runs one iteration per bit of the
+/// difference of the exponents of `a` and `b`: at most 32877 of them, for the largest float modulo the smallest subnormal.
+fn rem(asm: &mut Assembly) -> MethodImpl {
+    let mut body = Body::new(asm);
+    let a = body.arg_bits(0);
+    let b = body.arg_bits(1);
+    let a_abs = body.abs(a);
+    let b_abs = body.abs(b);
+    body.propagate_nan(a, a_abs, b, b_abs);
+    let a_node = body.get(a);
+    let (a_abs_node, b_abs_node) = (body.get(a_abs), body.get(b_abs));
+    let inf = body.wide(F128.inf());
+    let zero = body.wide(0);
+    let nan = body.wide(F128.nan());
+    let a_inf = body.wide_op(a_abs_node, inf, BinOp::Eq);
+    body.ret_f128_if(BranchCond::True(a_inf), nan);
+    let b_zero = body.wide_op(b_abs_node, zero, BinOp::Eq);
+    body.ret_f128_if(BranchCond::True(b_zero), nan);
+    // |a| < |b| (this includes b being infinite, or a being 0).
+    let smaller = body.wide_op(a_abs_node, b_abs_node, BinOp::Lt);
+    body.ret_f128_if(BranchCond::True(smaller), a_node);
+    let sign = body.wide(F128.sign());
+    let res_sign = body.wide_op(a_node, sign, BinOp::And);
+    let res_sign = body.wide_local(res_sign);
+    let res_sign = body.get(res_sign);
+    let (a_exp, a_sig) = body.unpack(a, true);
+    let (b_exp, b_sig) = body.unpack(b, true);
+    // Subtract `b` shifted by every exponent difference, from the largest to the smallest.
+    let (a_exp_node, b_exp_node) = (body.get(a_exp), body.get(b_exp));
+    let diff = body.int_op(a_exp_node, b_exp_node, BinOp::Sub);
+    let diff = body.int_local(diff);
+    let int_zero = body.int(0);
+    let int_one = body.int(1);
+    let divisor = body.get(b_sig);
+    let iteration = body.label();
+    let reduced = body.label();
+    body.start(iteration);
+    let rem = body.get(a_sig);
+    let fits = body.wide_op(rem, divisor, BinOp::Lt);
+    body.when(BranchCond::False(fits), |body| {
+        body.update(a_sig, divisor, BinOp::Sub);
+    });
+    let diff_node = body.get(diff);
+    body.branch(
+        reduced,
+        BranchCond::Le(diff_node, int_zero, CmpKind::Signed),
+    );
+    body.update(a_sig, int_one, BinOp::Shl);
+    body.update_int(diff, int_one, BinOp::Sub);
+    body.jump(iteration);
+    body.start(reduced);
+    let rem = body.get(a_sig);
+    let is_zero = body.wide_op(rem, zero, BinOp::Eq);
+    body.ret_f128_if(BranchCond::True(is_zero), res_sign);
+    // The remainder is exact, so `round_pack` will only normalize it.
+    let b_exp_node = body.get(b_exp);
+    let guard_bits = body.int(GUARD_BITS as i32);
+    let exp = body.int_op(b_exp_node, guard_bits, BinOp::Add);
+    let exp = body.int_local(exp);
+    let res = body.round_pack(F128, res_sign, exp, a_sig);
+    let res = body.get(res);
+    body.ret_f128(res);
+    body.finish()
+}
+/// Compares `a` and `b` using the `System.UInt128` comparison operator `op`. Any comparison with a NaN is false,
+/// except for `!=`.
+fn cmp(asm: &mut Assembly, op: &str) -> MethodImpl {
+    let mut body = Body::new(asm);
+    let a = body.arg_bits(0);
+    let b = body.arg_bits(1);
+    let inf = body.wide(F128.inf());
+    let zero = body.wide(0);
+    let unordered = body.node(CILNode::Const(Box::new(Const::Bool(op == "op_Inequality"))));
+    let sign = body.wide(F128.sign());
+    for val in [a, b] {
+        let abs = body.abs(val);
+        let abs = body.get(abs);
+        let is_nan = body.wide_op(abs, inf, BinOp::Gt);
+        body.when(BranchCond::True(is_nan), |body| body.ret(unordered));
+        // -0 == +0
+        let is_zero = body.wide_op(abs, zero, BinOp::Eq);
+        body.when(BranchCond::True(is_zero), |body| body.set(val, zero));
+        // Map the bits to integers with the same order as the floats.
+        let val_node = body.get(val);
+        let negative = body.wide_op(val_node, sign, BinOp::And);
+        let negative = body.wide_ne(negative, zero);
+        body.when_else(
+            BranchCond::True(negative),
+            |body| {
+                let all = body.wide(u128::MAX);
+                body.update(val, all, BinOp::XOr);
+            },
+            |body| body.update(val, sign, BinOp::Or),
+        );
+    }
+    let (a, b) = (body.get(a), body.get(b));
+    let res = body.wide_cmp(a, b, op);
+    body.ret(res);
+    body.finish()
+}
+/// `-a`
+fn neg(asm: &mut Assembly) -> MethodImpl {
+    let mut body = Body::new(asm);
+    let a = body.arg_bits(0);
+    let sign = body.wide(F128.sign());
+    body.update(a, sign, BinOp::XOr);
+    let a = body.get(a);
+    body.ret_f128(a);
+    body.finish()
+}
+/// `a as f128`, for an integer `a`.
+fn from_int(asm: &mut Assembly, int: Int) -> MethodImpl {
+    let mut body = Body::new(asm);
+    let zero = body.wide(0);
+    let sign = body.local(Type::Int(Int::U128));
+    body.set(sign, zero);
+    let magnitude = match int {
+        Int::U128 | Int::I128 => {
+            let bits = body.arg_as(0, Type::Int(Int::U128));
+            let bits = body.wide_local(bits);
+            if int.is_signed() {
+                let bits_node = body.get(bits);
+                let sign_bit = body.wide(F128.sign());
+                let sign_node = body.wide_op(bits_node, sign_bit, BinOp::And);
+                body.set(sign, sign_node);
+                let negative = body.wide_ne(sign_node, zero);
+                body.when(BranchCond::True(negative), |body| {
+                    let abs = body.wide_op(zero, bits_node, BinOp::Sub);
+                    body.set(bits, abs);
+                });
+            }
+            bits
+        }
+        _ => {
+            let arg = body.node(CILNode::LdArg(0));
+            let (target, extend) = if int.is_signed() {
+                (Int::I64, ExtendKind::SignExtend)
+            } else {
+                (Int::U64, ExtendKind::ZeroExtend)
+            };
+            let cast = body.node(CILNode::IntCast {
+                input: arg,
+                target,
+                extend,
+            });
+            let val = body.local(Type::Int(target));
+            body.set(val, cast);
+            if int.is_signed() {
+                let val_node = body.get(val);
+                let int_zero = body.node(CILNode::Const(Box::new(Const::I64(0))));
+                body.when(
+                    BranchCond::Lt(val_node, int_zero, CmpKind::Signed),
+                    |body| {
+                        let sign_bit = body.wide(F128.sign());
+                        body.set(sign, sign_bit);
+                        let abs = body.node(CILNode::UnOp(val_node, UnOp::Neg));
+                        body.set(val, abs);
+                    },
+                );
+            }
+            let val_node = body.get(val);
+            let val_node = body.node(CILNode::IntCast {
+                input: val_node,
+                target: Int::U64,
+                extend: ExtendKind::ZeroExtend,
+            });
+            let wide = body.widen(val_node);
+            body.wide_local(wide)
+        }
+    };
+    let exp = body.int(F128.bias() + (F128.sig_bits + GUARD_BITS) as i32);
+    let exp = body.int_local(exp);
+    let sign = body.get(sign);
+    let res = body.round_pack(F128, sign, exp, magnitude);
+    let res = body.get(res);
+    body.ret_f128(res);
+    body.finish()
+}
+/// `a as int`, saturating on overflow, with NaNs becoming 0.
+fn to_int(asm: &mut Assembly, int: Int) -> MethodImpl {
+    let mut body = Body::new(asm);
+    let a = body.arg_bits(0);
+    let a_abs = body.abs(a);
+    let zero = body.wide(0);
+    let magnitude = body.wide_local(zero);
+    let done = body.label();
+    let abs = body.get(a_abs);
+    let inf = body.wide(F128.inf());
+    let is_nan = body.wide_op(abs, inf, BinOp::Gt);
+    body.when(BranchCond::True(is_nan), |body| body.jump(done));
+    let sig_bits = body.int(F128.sig_bits as i32);
+    let exp = body.wide_op(abs, sig_bits, BinOp::Shr);
+    let exp = body.narrow(exp, Int::I32);
+    let bias = body.int(F128.bias());
+    let exp = body.int_op(exp, bias, BinOp::Sub);
+    let exp = body.int_local(exp);
+    let exp = body.get(exp);
+    // |a| < 1
+    let int_zero = body.int(0);
+    body.when(BranchCond::Lt(exp, int_zero, CmpKind::Signed), |body| {
+        body.jump(done);
+    });
+    let int_bits = body.int(128);
+    body.when_else(
+        BranchCond::Ge(exp, int_bits, CmpKind::Signed),
+        |body| {
+            let max = body.wide(u128::MAX);
+            body.set(magnitude, max);
+        },
+        |body| {
+            let sig_mask = body.wide(F128.sig_mask());
+            let implicit = body.wide(F128.implicit_bit());
+            let sig = body.wide_op(abs, sig_mask, BinOp::And);
+            let sig = body.wide_op(sig, implicit, BinOp::Or);
+            body.set(magnitude, sig);
+            body.when_else(
+                BranchCond::Ge(exp, sig_bits, CmpKind::Signed),
+                |body| {
+                    let shift = body.int_op(exp, sig_bits, BinOp::Sub);
+                    body.update(magnitude, shift, BinOp::Shl);
+                },
+                |body| {
+                    let shift = body.int_op(sig_bits, exp, BinOp::Sub);
+                    body.update(magnitude, shift, BinOp::Shr);
+                },
+            );
+        },
+    );
+    body.start(done);
+    // Saturate
+    let bits = int.bits().map_or(64, u32::from);
+    let a_node = body.get(a);
+    let sign = body.wide(F128.sign());
+    let negative = body.wide_op(a_node, sign, BinOp::And);
+    let negative = body.wide_ne(negative, zero);
+    let limit = body.local(Type::Int(Int::U128));
+    if int.is_signed() {
+        let max = body.wide((1 << (bits - 1)) - 1);
+        let min = body.wide(1 << (bits - 1));
+        body.when_else(
+            BranchCond::True(negative),
+            |body| body.set(limit, min),
+            |body| body.set(limit, max),
+        );
+    } else {
+        let max = body.wide(u128::MAX >> (128 - bits));
+        body.set(limit, max);
+        body.when(BranchCond::True(negative), |body| body.set(limit, zero));
+    }
+    let (magnitude_node, limit) = (body.get(magnitude), body.get(limit));
+    let overflow = body.wide_op(magnitude_node, limit, BinOp::Gt);
+    body.when(BranchCond::True(overflow), |body| {
+        body.set(magnitude, limit)
+    });
+    if int.is_signed() {
+        body.when(BranchCond::True(negative), |body| {
+            let negated = body.wide_op(zero, magnitude_node, BinOp::Sub);
+            body.set(magnitude, negated);
+        });
+    }
+    let magnitude = body.get(magnitude);
+    let res = match int {
+        Int::U128 | Int::I128 => body.transmute(magnitude, Type::Int(Int::U128), Type::Int(int)),
+        _ => {
+            let res = body.narrow(magnitude, Int::U64);
+            body.node(CILNode::IntCast {
+                input: res,
+                target: int,
+                extend: ExtendKind::ZeroExtend,
+            })
+        }
+    };
+    body.ret(res);
+    body.finish()
+}
+/// `a as f128`, for a `f32` or `f64` `a`. This conversion is always exact.
+fn from_float(asm: &mut Assembly, float: Float) -> MethodImpl {
+    let mut body = Body::new(asm);
+    // `f32`s are first extended to `f64`s, which is exact too.
+    let bits = match float {
+        Float::F64 => body.arg_as(0, Type::Int(Int::U64)),
+        Float::F32 => {
+            let arg = body.node(CILNode::LdArg(0));
+            let arg = body.node(CILNode::FloatCast {
+                input: arg,
+                target: Float::F64,
+                is_signed: true,
+            });
+            body.transmute(arg, Type::Float(Float::F64), Type::Int(Int::U64))
+        }
+        _ => panic!("ERROR: Can't convert {float:?} to f128 in software."),
+    };
+    let fmt = Format::F64;
+    let bits = body.widen(bits);
+    let bits = body.wide_local(bits);
+    let bits = body.get(bits);
+    let sign_shift = body.int((fmt.sig_bits + fmt.exp_bits) as i32);
+    let sign = body.wide_op(bits, sign_shift, BinOp::Shr);
+    let sign_place = body.int(127);
+    let sign = body.wide_op(sign, sign_place, BinOp::Shl);
+    let sign = body.wide_local(sign);
+    let sign = body.get(sign);
+    let sig_bits = body.int(fmt.sig_bits as i32);
+    let exp = body.wide_op(bits, sig_bits, BinOp::Shr);
+    let exp_mask = body.wide(fmt.max_exp() as u128);
+    let exp = body.wide_op(exp, exp_mask, BinOp::And);
+    let exp = body.narrow(exp, Int::I32);
+    let exp = body.int_local(exp);
+    let sig_mask = body.wide(fmt.sig_mask());
+    let sig = body.wide_op(bits, sig_mask, BinOp::And);
+    let sig = body.wide_local(sig);
+    let (exp_node, sig_node) = (body.get(exp), body.get(sig));
+    let zero = body.wide(0);
+    let is_zero = body.wide_op(sig_node, zero, BinOp::Eq);
+    // Infinities and NaNs, keeping the payload.
+    let max_exp = body.int(fmt.max_exp());
+    body.when(BranchCond::Eq(exp_node, max_exp), |body| {
+        let inf = body.wide(F128.inf());
+        let inf = body.wide_op(sign, inf, BinOp::Or);
+        body.ret_f128_if(BranchCond::True(is_zero), inf);
+        let payload_shift = body.int((F128.sig_bits - fmt.sig_bits) as i32);
+        let payload = body.wide_op(sig_node, payload_shift, BinOp::Shl);
+        let nan = body.wide(F128.quiet_bit());
+        let nan = body.wide_op(inf, nan, BinOp::Or);
+        let nan = body.wide_op(nan, payload, BinOp::Or);
+        body.ret_f128(nan);
+    });
+    let int_zero = body.int(0);
+    body.when_else(
+        BranchCond::Eq(exp_node, int_zero),
+        |body| {
+            body.ret_f128_if(BranchCond::True(is_zero), sign);
+            let one = body.int(1);
+            body.set(exp, one);
+        },
+        |body| {
+            let implicit = body.wide(fmt.implicit_bit());
+            body.update(sig, implicit, BinOp::Or);
+        },
+    );
+    let rebias =
+        F128.bias() + (F128.sig_bits + GUARD_BITS) as i32 - fmt.bias() - fmt.sig_bits as i32;
+    let rebias = body.int(rebias);
+    body.update_int(exp, rebias, BinOp::Add);
+    let res = body.round_pack(F128, sign, exp, sig);
+    let res = body.get(res);
+    body.ret_f128(res);
+    body.finish()
+}
+/// `a as float`, for a `float` of `f32` or `f64`.
+fn to_float(asm: &mut Assembly, float: Float) -> MethodImpl {
+    let fmt = match float {
+        Float::F32 => Format::F32,
+        Float::F64 => Format::F64,
+        _ => panic!("ERROR: Can't convert f128 to {float:?} in software."),
+    };
+    let mut body = Body::new(asm);
+    let a = body.arg_bits(0);
+    let a_abs = body.abs(a);
+    let (a_node, abs) = (body.get(a), body.get(a_abs));
+    let sign_place = body.int(127);
+    let sign = body.wide_op(a_node, sign_place, BinOp::Shr);
+    let sign_shift = body.int((fmt.sig_bits + fmt.exp_bits) as i32);
+    let sign = body.wide_op(sign, sign_shift, BinOp::Shl);
+    let sign = body.wide_local(sign);
+    let sign = body.get(sign);
+    let inf = body.wide(F128.inf());
+    let target_inf = body.wide(fmt.inf());
+    let target_inf = body.wide_op(sign, target_inf, BinOp::Or);
+    // NaNs, keeping as much of the payload as fits.
+    let is_nan = body.wide_op(abs, inf, BinOp::Gt);
+    body.when(BranchCond::True(is_nan), |body| {
+        let sig_mask = body.wide(F128.sig_mask());
+        let payload = body.wide_op(abs, sig_mask, BinOp::And);
+        let payload_shift = body.int((F128.sig_bits - fmt.sig_bits) as i32);
+        let payload = body.wide_op(payload, payload_shift, BinOp::Shr);
+        let nan = body.wide(fmt.quiet_bit());
+        let nan = body.wide_op(target_inf, nan, BinOp::Or);
+        let nan = body.wide_op(nan, payload, BinOp::Or);
+        body.ret_float(nan, float);
+    });
+    let is_inf = body.wide_op(abs, inf, BinOp::Eq);
+    body.when(BranchCond::True(is_inf), |body| {
+        body.ret_float(target_inf, float)
+    });
+    let zero = body.wide(0);
+    let is_zero = body.wide_op(abs, zero, BinOp::Eq);
+    body.when(BranchCond::True(is_zero), |body| {
+        body.ret_float(sign, float)
+    });
+    let (exp, sig) = body.unpack(a, false);
+    let rebias =
+        fmt.bias() + (fmt.sig_bits + GUARD_BITS) as i32 - F128.bias() - F128.sig_bits as i32;
+    let rebias = body.int(rebias);
+    body.update_int(exp, rebias, BinOp::Add);
+    let res = body.round_pack(fmt, sign, exp, sig);
+    let res = body.get(res);
+    body.ret_float(res, float);
+    body.finish()
+}
+fn insert(
+    asm: &mut Assembly,
+    patcher: &mut MissingMethodPatcher,
+    name: &str,
+    generator: impl Fn(&mut Assembly) -> MethodImpl + 'static,
+) {
+    let name = asm.alloc_string(name);
+    patcher.insert(name, Box::new(move |_, asm: &mut Assembly| generator(asm)));
+}
+/// Returns a method body returning `op(ldarg.0, ldarg.1)`, for C mode.
+fn direct(
+    asm: &mut Assembly,
+    op: impl Fn(&mut Assembly, NodeIdx, NodeIdx) -> CILNode,
+) -> MethodImpl {
+    let lhs = asm.alloc_node(CILNode::LdArg(0));
+    let rhs = asm.alloc_node(CILNode::LdArg(1));
+    let res = op(asm, lhs, rhs);
+    let res = asm.alloc_node(res);
+    let ret = asm.alloc_root(CILRoot::Ret(res));
+    MethodImpl::MethodBody {
+        blocks: vec![BasicBlock::new(vec![ret], 0, None)],
+        locals: vec![],
+    }
+}
+/// Inserts the `f128` arithmetic, comparisons and conversions. If `direct` is set, they are implemented using
+/// native `f128` operations (C mode). Otherwise, they are implemented in software.
+pub fn generate_f128_ops(asm: &mut Assembly, patcher: &mut MissingMethodPatcher, direct: bool) {
+    const OPS: [BinOp; 5] = [BinOp::Add, BinOp::Sub, BinOp::Mul, BinOp::Div, BinOp::Rem];
+    // Name, .NET operator used on the ordered bits, and the C mode implementation.
+    const CMPS: [(&str, &str, &[BinOp]); 6] = [
+        ("eq", "op_Equality", &[BinOp::Eq]),
+        ("lt", "op_LessThan", &[BinOp::Lt]),
+        ("gt", "op_GreaterThan", &[BinOp::Gt]),
+        ("le", "op_LessThanOrEqual", &[BinOp::Lt, BinOp::Eq]),
+        ("ge", "op_GreaterThanOrEqual", &[BinOp::Gt, BinOp::Eq]),
+        ("ne", "op_Inequality", &[]),
+    ];
+    const INTS: [Int; 12] = [
+        Int::U8,
+        Int::U16,
+        Int::U32,
+        Int::U64,
+        Int::U128,
+        Int::USize,
+        Int::I8,
+        Int::I16,
+        Int::I32,
+        Int::I64,
+        Int::I128,
+        Int::ISize,
+    ];
+    for op in OPS {
+        let name = format!("{op}_f128", op = op.name());
+        if direct {
+            insert(asm, patcher, &name, move |asm| {
+                self::direct(asm, |_, lhs, rhs| CILNode::BinOp(lhs, rhs, op))
+            });
+        } else {
+            insert(asm, patcher, &name, move |asm| match op {
+                BinOp::Add => add(asm, false),
+                BinOp::Sub => add(asm, true),
+                BinOp::Mul => mul(asm),
+                BinOp::Div => div(asm),
+                BinOp::Rem => rem(asm),
+                _ => unreachable!(),
+            });
+        }
+    }
+    for (name, dotnet_op, ops) in CMPS {
+        let name = format!("{name}_f128");
+        if direct {
+            insert(asm, patcher, &name, move |asm| {
+                self::direct(asm, |asm, lhs, rhs| match ops {
+                    // `a != b` is `(a == b) == false`.
+                    [] => {
+                        let eq = asm.alloc_node(CILNode::BinOp(lhs, rhs, BinOp::Eq));
+                        let false_node = asm.alloc_node(Const::Bool(false));
+                        CILNode::BinOp(eq, false_node, BinOp::Eq)
+                    }
+                    [op] => CILNode::BinOp(lhs, rhs, *op),
+                    [lt_or_gt, eq] => {
+                        let lt_or_gt = asm.alloc_node(CILNode::BinOp(lhs, rhs, *lt_or_gt));
+                        let eq = asm.alloc_node(CILNode::BinOp(lhs, rhs, *eq));
+                        CILNode::BinOp(lt_or_gt, eq, BinOp::Or)
+                    }
+                    _ => unreachable!(),
+                })
+            });
+        } else {
+            insert(asm, patcher, &name, move |asm| cmp(asm, dotnet_op));
+        }
+    }
+    if direct {
+        insert(asm, patcher, "neg_f128", |asm| {
+            self::direct(asm, |_, lhs, _| CILNode::UnOp(lhs, UnOp::Neg))
+        });
+    } else {
+        insert(asm, patcher, "neg_f128", neg);
+    }
+    for int in INTS {
+        let name = format!("cast_{int}_f128", int = int.name());
+        if direct {
+            insert(asm, patcher, &name, move |asm| {
+                self::direct(asm, |_, input, _| CILNode::FloatCast {
+                    input,
+                    target: Float::F128,
+                    is_signed: int.is_signed(),
+                })
+            });
+        } else {
+            insert(asm, patcher, &name, move |asm| from_int(asm, int));
+        }
+        if direct {
+            super::casts::float_to_int(asm, int, Float::F128, patcher);
+        } else {
+            let name = format!("cast_f128_{int}", int = int.name());
+            insert(asm, patcher, &name, move |asm| to_int(asm, int));
+        }
+    }
+    for float in [Float::F32, Float::F64] {
+        let name = format!("cast_{float}_f128", float = float.name());
+        if direct {
+            insert(asm, patcher, &name, |asm| {
+                self::direct(asm, |_, input, _| CILNode::FloatCast {
+                    input,
+                    target: Float::F128,
+                    is_signed: true,
+                })
+            });
+        } else {
+            insert(asm, patcher, &name, move |asm| from_float(asm, float));
+        }
+        let name = format!("cast_f128_{float}", float = float.name());
+        if direct {
+            insert(asm, patcher, &name, move |asm| {
+                self::direct(asm, |_, input, _| CILNode::FloatCast {
+                    input,
+                    target: float,
+                    is_signed: true,
+                })
+            });
+        } else {
+            insert(asm, patcher, &name, move |asm| to_float(asm, float));
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::v2::{
+        interpreter::{call_builtin, Value},
+        Float, Int, Type,
+    };
+
+    const ONE: u128 = 0x3FFF_0000_0000_0000_0000_0000_0000_0000;
+    const TWO: u128 = 0x4000_0000_0000_0000_0000_0000_0000_0000;
+    const THREE: u128 = 0x4000_8000_0000_0000_0000_0000_0000_0000;
+    const HALF: u128 = 0x3FFE_0000_0000_0000_0000_0000_0000_0000;
+    const NEG: u128 = 1 << 127;
+    const INF: u128 = 0x7FFF_0000_0000_0000_0000_0000_0000_0000;
+    const NAN: u128 = 0x7FFF_8000_0000_0000_0000_0000_0000_0000;
+    const MAX: u128 = 0x7FFE_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF;
+    const MIN_NORMAL: u128 = 0x0001_0000_0000_0000_0000_0000_0000_0000;
+    const MIN_SUBNORMAL: u128 = 1;
+    /// The unit in the last place of 1.
+    const ULP: u128 = 1;
+
+    fn f128_value(bits: u128) -> Value {
+        Value::Struct(bits.to_le_bytes().into())
+    }
+    /// Calls the binary operation `name`, like `add_f128`, on the floats with the bits `lhs` and `rhs`.
+    fn binop(name: &str, lhs: u128, rhs: u128) -> Value {
+        let f128 = Type::Float(Float::F128);
+        call_builtin(
+            name,
+            &[f128, f128],
+            f128,
+            vec![f128_value(lhs), f128_value(rhs)],
+        )
+    }
+    fn cmp(name: &str, lhs: u128, rhs: u128) -> Value {
+        let f128 = Type::Float(Float::F128);
+        call_builtin(
+            name,
+            &[f128, f128],
+            Type::Bool,
+            vec![f128_value(lhs), f128_value(rhs)],
+        )
+    }
+    #[test]
+    fn arithmetic() {
+        // 1.5 + 2.25 == 3.75
+        assert_eq!(
+            binop(
+                "add_f128",
+                0x3FFF_8000_0000_0000_0000_0000_0000_0000,
+                0x4000_2000_0000_0000_0000_0000_0000_0000
+            ),
+            f128_value(0x4000_E000_0000_0000_0000_0000_0000_0000)
+        );
+        // 1 - 1 == +0
+        assert_eq!(binop("sub_f128", ONE, ONE), f128_value(0));
+        // 1 / 3, rounded to nearest
+        assert_eq!(
+            binop("div_f128", ONE, THREE),
+            f128_value(0x3FFD_5555_5555_5555_5555_5555_5555_5555)
+        );
+        // 3 * 3 == 9
+        assert_eq!(
+            binop("mul_f128", THREE, THREE),
+            f128_value(0x4002_2000_0000_0000_0000_0000_0000_0000)
+        );
+        assert_eq!(cmp("lt_f128", ONE, THREE), Value::bool(true));
+        assert_eq!(cmp("ge_f128", ONE, THREE), Value::bool(false));
+        // -0 == +0
+        assert_eq!(cmp("eq_f128", 0, NEG), Value::bool(true));
+    }
+    #[test]
+    fn rounding() {
+        // 2^-113 is half an ULP of 1, so ties round to the even neighbour.
+        let half_ulp = 0x3F8E_0000_0000_0000_0000_0000_0000_0000;
+        assert_eq!(binop("add_f128", ONE, half_ulp), f128_value(ONE));
+        assert_eq!(
+            binop("add_f128", ONE + ULP, half_ulp),
+            f128_value(ONE + 2 * ULP)
+        );
+        // 3/4 of an ULP rounds up.
+        let three_quarters_ulp = 0x3F8E_8000_0000_0000_0000_0000_0000_0000;
+        assert_eq!(
+            binop("add_f128", ONE, three_quarters_ulp),
+            f128_value(ONE + ULP)
+        );
+        // Below 1, the ULP is halved, so 2^-114 is a tie between 1 and the float just below it.
+        let quarter_ulp = 0x3F8D_0000_0000_0000_0000_0000_0000_0000;
+        assert_eq!(binop("sub_f128", ONE, quarter_ulp), f128_value(ONE));
+        // Results too large to represent round to infinity.
+        assert_eq!(binop("add_f128", MAX, MAX), f128_value(INF));
+        assert_eq!(binop("mul_f128", MAX, TWO), f128_value(INF));
+        assert_eq!(binop("mul_f128", MAX, TWO | NEG), f128_value(INF | NEG));
+    }
+    #[test]
+    fn subnormals() {
+        assert_eq!(
+            binop("add_f128", MIN_SUBNORMAL, MIN_SUBNORMAL),
+            f128_value(2 * MIN_SUBNORMAL)
+        );
+        // The largest subnormal is just below the smallest normal.
+        assert_eq!(
+            binop("sub_f128", MIN_NORMAL, MIN_SUBNORMAL),
+            f128_value(MIN_NORMAL - 1)
+        );
+        assert_eq!(
+            binop("mul_f128", MIN_NORMAL, HALF),
+            f128_value(MIN_NORMAL >> 1)
+        );
+        assert_eq!(
+            binop("div_f128", MIN_NORMAL, TWO),
+            f128_value(MIN_NORMAL >> 1)
+        );
+        // Halving subnormals rounds ties to even: 0.5 to 0, and 1.5 to 2.
+        assert_eq!(binop("mul_f128", MIN_SUBNORMAL, HALF), f128_value(0));
+        assert_eq!(
+            binop("mul_f128", 3 * MIN_SUBNORMAL, HALF),
+            f128_value(2 * MIN_SUBNORMAL)
+        );
+        // Subnormal `f64`s are normal `f128`s.
+        let f128 = Type::Float(Float::F128);
+        let f64_tpe = Type::Float(Float::F64);
+        let extended = call_builtin(
+            "cast_f64_f128",
+            &[f64_tpe],
+            f128,
+            vec![Value::F64(f64::from_bits(1))],
+        );
+        assert_eq!(
+            extended,
+            f128_value(0x3BCD_0000_0000_0000_0000_0000_0000_0000)
+        );
+        assert_eq!(
+            call_builtin("cast_f128_f64", &[f128], f64_tpe, vec![extended]),
+            Value::F64(f64::from_bits(1))
+        );
+        // `f128` subnormals are too small for a `f64`.
+        assert_eq!(
+            call_builtin(
+                "cast_f128_f64",
+                &[f128],
+                f64_tpe,
+                vec![f128_value(MIN_SUBNORMAL)]
+            ),
+            Value::F64(0.0)
+        );
+    }
+    #[test]
+    fn nan_and_inf() {
+        assert_eq!(binop("add_f128", INF, ONE), f128_value(INF));
+        assert_eq!(binop("add_f128", INF, INF | NEG), f128_value(NAN));
+        assert_eq!(binop("sub_f128", INF, INF), f128_value(NAN));
+        assert_eq!(binop("mul_f128", 0, INF), f128_value(NAN));
+        assert_eq!(binop("div_f128", 0, 0), f128_value(NAN));
+        assert_eq!(binop("div_f128", INF, INF), f128_value(NAN));
+        assert_eq!(binop("div_f128", ONE, 0), f128_value(INF));
+        assert_eq!(binop("div_f128", ONE | NEG, 0), f128_value(INF | NEG));
+        assert_eq!(binop("div_f128", ONE, INF), f128_value(0));
+        // NaNs are propagated, and get quieted.
+        let signaling = INF | 1;
+        assert_eq!(binop("add_f128", signaling, ONE), f128_value(NAN | 1));
+        assert_eq!(binop("mul_f128", ONE, NAN | 7), f128_value(NAN | 7));
+        assert_eq!(cmp("eq_f128", NAN, NAN), Value::bool(false));
+        assert_eq!(cmp("ne_f128", NAN, NAN), Value::bool(true));
+        assert_eq!(cmp("lt_f128", NAN, INF), Value::bool(false));
+        assert_eq!(cmp("lt_f128", INF | NEG, MAX | NEG), Value::bool(true));
+    }
+    #[test]
+    fn rem() {
+        const FIVE_AND_HALF: u128 = 0x4001_6000_0000_0000_0000_0000_0000_0000;
+        const ONE_AND_HALF: u128 = 0x3FFF_8000_0000_0000_0000_0000_0000_0000;
+        assert_eq!(binop("mod_f128", THREE, ONE), f128_value(0));
+        assert_eq!(
+            binop("mod_f128", FIVE_AND_HALF, TWO),
+            f128_value(ONE_AND_HALF)
+        );
+        // The result has the sign of the dividend.
+        assert_eq!(
+            binop("mod_f128", FIVE_AND_HALF | NEG, TWO),
+            f128_value(ONE_AND_HALF | NEG)
+        );
+        assert_eq!(
+            binop("mod_f128", FIVE_AND_HALF, TWO | NEG),
+            f128_value(ONE_AND_HALF)
+        );
+        assert_eq!(binop("mod_f128", THREE | NEG, ONE), f128_value(NEG));
+        // Dividends smaller than the divisor are returned as is.
+        assert_eq!(binop("mod_f128", ONE, THREE), f128_value(ONE));
+        assert_eq!(binop("mod_f128", ONE, INF), f128_value(ONE));
+        assert_eq!(binop("mod_f128", NEG, ONE), f128_value(NEG));
+        assert_eq!(
+            binop("mod_f128", MIN_SUBNORMAL, MIN_NORMAL),
+            f128_value(MIN_SUBNORMAL)
+        );
+        // Subnormal remainders: 3 % 2 == 1, in units of the smallest subnormal.
+        assert_eq!(
+            binop("mod_f128", 3 * MIN_SUBNORMAL, 2 * MIN_SUBNORMAL),
+            f128_value(MIN_SUBNORMAL)
+        );
+        assert_eq!(binop("mod_f128", INF, ONE), f128_value(NAN));
+        assert_eq!(binop("mod_f128", ONE, 0), f128_value(NAN));
+        assert_eq!(binop("mod_f128", NAN | 3, ONE), f128_value(NAN | 3));
+        // The largest exponent difference: every finite float is a multiple of the smallest subnormal.
+        assert_eq!(binop("mod_f128", MAX, MIN_SUBNORMAL), f128_value(0));
+        // 2^16383 % 3 == 2, since 2^odd % 3 == 2.
+        let pow2_16383 = 0x7FFE_0000_0000_0000_0000_0000_0000_0000;
+        assert_eq!(binop("mod_f128", pow2_16383, THREE), f128_value(TWO));
+    }
+    #[test]
+    fn casts() {
+        let f128 = Type::Float(Float::F128);
+        let f64_tpe = Type::Float(Float::F64);
+        // 0.1 gets extended exactly, and rounds back to itself.
+        let extended = call_builtin("cast_f64_f128", &[f64_tpe], f128, vec![Value::F64(0.1)]);
+        assert_eq!(
+            extended,
+            f128_value(0x3FFB_9999_9999_9999_A000_0000_0000_0000)
+        );
+        assert_eq!(
+            call_builtin("cast_f128_f64", &[f128], f64_tpe, vec![extended]),
+            Value::F64(0.1)
+        );
+        let i64_tpe = Type::Int(Int::I64);
+        let minus_five = call_builtin("cast_i64_f128", &[i64_tpe], f128, vec![Value::I64(-5)]);
+        assert_eq!(
+            minus_five,
+            f128_value(0xC001_4000_0000_0000_0000_0000_0000_0000)
+        );
+        assert_eq!(
+            call_builtin("cast_f128_i64", &[f128], i64_tpe, vec![minus_five.clone()]),
+            Value::I64(-5)
+        );
+        // Float to int casts saturate, and NaNs become 0.
+        let u8_tpe = Type::Int(Int::U8);
+        assert_eq!(
+            call_builtin("cast_f128_u8", &[f128], u8_tpe, vec![minus_five]),
+            Value::I32(0)
+        );
+        let big = f128_value(0x4007_2C00_0000_0000_0000_0000_0000_0000);
+        assert_eq!(
+            call_builtin("cast_f128_u8", &[f128], u8_tpe, vec![big]),
+            Value::I32(255)
+        );
+        let to_i64 = |bits| call_builtin("cast_f128_i64", &[f128], i64_tpe, vec![f128_value(bits)]);
+        assert_eq!(to_i64(INF), Value::I64(i64::MAX));
+        assert_eq!(to_i64(INF | NEG), Value::I64(i64::MIN));
+        assert_eq!(to_i64(NAN), Value::I64(0));
+        assert_eq!(
+            call_builtin(
+                "cast_f64_f128",
+                &[f64_tpe],
+                f128,
+                vec![Value::F64(f64::NEG_INFINITY)]
+            ),
+            f128_value(INF | NEG)
+        );
+        let Value::F64(nan) =
+            call_builtin("cast_f128_f64", &[f128], f64_tpe, vec![f128_value(NAN)])
+        else {
+            panic!("Expected a f64");
+        };
+        assert!(nan.is_nan());
+    }
+}
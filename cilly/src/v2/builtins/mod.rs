@@ -12,6 +12,7 @@ use super::{
 
 pub mod atomics;
 pub mod casts;
+pub mod dotnet_sys;
 pub mod math;
 pub mod select;
 pub mod thread;
@@ -20,6 +21,8 @@ pub mod int128;
 pub use int128::*;
 pub mod f16;
 pub use f16::*;
+pub mod f128;
+pub use f128::*;
 pub mod simd;
 
 pub fn insert_swap_at_generic(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
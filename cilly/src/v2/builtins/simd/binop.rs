@@ -52,4 +52,5 @@ binop!(simd_add, "Add");
 binop!(simd_and, "BitwiseAnd");
 binop!(simd_sub, "Subtract");
 binop!(simd_mul, "Multiply");
-binop!(simd_div, "Divides");
+binop!(simd_div, "Divide");
+binop!(simd_xor, "Xor");
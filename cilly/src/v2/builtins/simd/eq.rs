@@ -3,22 +3,23 @@ use crate::{
     MethodRefIdx, Type,
 };
 
-use super::dotnet_vec_cast;
+use super::{
+    dotnet_vec_cast,
+    scalar::{cmp_lanes_impl, compare_impl, Cmp},
+};
 pub(super) fn simd_eq(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
     let name = asm.alloc_string("simd_eq");
     let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
         let sig = asm[asm[mref].sig()].clone();
-        let result = sig.output();
-        let Some(comparands) = sig.inputs()[0].as_simdvector() else {
-            todo!(
-                "Can't simd compare {comparands:?} and get {result:?}",
-                comparands = sig.inputs()[0]
-            )
+        // The mask has as many lanes as the comparands, and single lane vectors are lowered to their element type. So,
+        // either both are vectors, or both are scalars, which get compared like single lane vectors.
+        let (Some(comparands), Some(result)) = (
+            sig.inputs()[0].as_simdvector(),
+            sig.output().as_simdvector(),
+        ) else {
+            return compare_impl(mref, asm, Cmp::Eq);
         };
         let elem: Type = comparands.elem().into();
-        let Some(result) = result.as_simdvector() else {
-            todo!("Can't simd compare {comparands:?} and get {result:?}",)
-        };
         let extension_class = comparands.extension_class(asm);
         let extension_class = asm[extension_class].clone();
         let equals = asm.alloc_string("Equals");
@@ -53,12 +54,9 @@ pub(super) fn simd_eq_all(asm: &mut Assembly, patcher: &mut MissingMethodPatcher
     let name = asm.alloc_string("simd_eq_all");
     let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
         let sig = asm[asm[mref].sig()].clone();
-        let result = sig.output();
+        // Single lane vectors are lowered to their element type.
         let Some(comparands) = sig.inputs()[0].as_simdvector() else {
-            todo!(
-                "Can't simd compare {comparands:?} and get {result:?}",
-                comparands = sig.inputs()[0]
-            )
+            return cmp_lanes_impl(mref, asm, Cmp::Eq, false);
         };
         let elem: Type = comparands.elem().into();
 
@@ -96,12 +94,9 @@ pub(super) fn simd_eq_any(asm: &mut Assembly, patcher: &mut MissingMethodPatcher
     let name = asm.alloc_string("simd_eq_any");
     let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
         let sig = asm[asm[mref].sig()].clone();
-        let result = sig.output();
+        // Single lane vectors are lowered to their element type.
         let Some(comparands) = sig.inputs()[0].as_simdvector() else {
-            todo!(
-                "Can't simd compare {comparands:?} and get {result:?}",
-                comparands = sig.inputs()[0]
-            )
+            return cmp_lanes_impl(mref, asm, Cmp::Eq, true);
         };
         let elem: Type = comparands.elem().into();
 
@@ -135,3 +130,80 @@ pub(super) fn simd_eq_any(asm: &mut Assembly, patcher: &mut MissingMethodPatcher
     };
     patcher.insert(name, Box::new(generator));
 }
+macro_rules! cmp {
+    ($op_name:ident,$op_dotnet:literal,$cmp:ident) => {
+        pub(super) fn $op_name(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+            let name = asm.alloc_string(stringify!($op_name));
+            let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
+                let sig = asm[asm[mref].sig()].clone();
+                // Like in `simd_eq`, either both the comparands and the mask are vectors, or both are scalars.
+                let (Some(comparands), Some(result)) = (
+                    sig.inputs()[0].as_simdvector(),
+                    sig.output().as_simdvector(),
+                ) else {
+                    return compare_impl(mref, asm, Cmp::$cmp);
+                };
+                let elem: Type = comparands.elem().into();
+                let extension_class = comparands.extension_class(asm);
+                let extension_class = asm[extension_class].clone();
+                let cmp = asm.alloc_string($op_dotnet);
+                let generic_vec = super::generic_vec(*comparands, 0, asm);
+                let cmp = extension_class.static_mref_generic(
+                    &[generic_vec, generic_vec],
+                    generic_vec,
+                    cmp,
+                    asm,
+                    [elem].into(),
+                );
+                let lhs = asm.alloc_node(CILNode::LdArg(0));
+                let rhs = asm.alloc_node(CILNode::LdArg(1));
+                let cmp = asm.alloc_node(CILNode::Call(Box::new((cmp, [lhs, rhs].into()))));
+                let cast = dotnet_vec_cast(cmp, *comparands, *result, asm);
+                let ret = asm.alloc_root(CILRoot::Ret(cast));
+                MethodImpl::MethodBody {
+                    blocks: vec![BasicBlock::new(vec![ret], 0, None)],
+                    locals: vec![],
+                }
+            };
+            patcher.insert(name, Box::new(generator));
+        }
+    };
+}
+cmp!(simd_lt, "LessThan", Lt);
+cmp!(simd_le, "LessThanOrEqual", Le);
+cmp!(simd_gt, "GreaterThan", Gt);
+cmp!(simd_ge, "GreaterThanOrEqual", Ge);
+#[cfg(test)]
+mod tests {
+    use fxhash::FxHashMap;
+
+    use crate::v2::{
+        il_exporter::export_il,
+        interpreter::{patch, static_mref},
+        tpe::simd::SIMDVector,
+        Float, Int,
+    };
+
+    use super::*;
+    // The interpreter can't run `System.Runtime.Intrinsics`, so this checks the emitted IL.
+    #[test]
+    fn simd_cmp() {
+        let mut asm = Assembly::default();
+        let f32x4 = Type::SIMDVector(SIMDVector::new(Float::F32.into(), 4));
+        let i32x4 = Type::SIMDVector(SIMDVector::new(Int::I32.into(), 4));
+        static_mref(&mut asm, "simd_lt", &[f32x4, f32x4], i32x4);
+        let mut patcher = FxHashMap::default();
+        super::super::simd(&mut asm, &mut patcher, false);
+        patch(&mut asm, &patcher);
+        let il = export_il(&asm);
+        let body = il.split_once("'simd_lt'(").expect("No simd_lt method").1;
+        // The comparison is done natively, and the float mask gets reinterpreted as an int one.
+        let less_than = body
+            .find("'System.Runtime.Intrinsics.Vector128'::'LessThan'<float32>(")
+            .expect("No Vector128.LessThan call");
+        let cast = body
+            .find("'System.Runtime.Intrinsics.Vector128'::'As'<float32,int32>(")
+            .expect("No Vector128.As call");
+        assert!(less_than < cast);
+    }
+}
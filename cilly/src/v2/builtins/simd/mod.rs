@@ -6,6 +6,18 @@ mod eq;
 use eq::*;
 mod binop;
 use binop::*;
+mod scalar;
+/// `Vector{bits}<T>`, where `T` is the call generic `idx`.
+fn generic_vec(vec: SIMDVector, idx: u32, asm: &mut Assembly) -> Type {
+    let generic_class = vec.class(asm);
+    let mut generic_class = asm[generic_class].clone();
+    generic_class.set_generics(vec![Type::PlatformGeneric(
+        idx,
+        crate::tpe::GenericKind::CallGeneric,
+    )]);
+    Type::ClassRef(asm.alloc_class_ref(generic_class))
+}
+/// Reinterprets the bits of a vector as a vector of another type, of the same size.
 fn dotnet_vec_cast(
     src: NodeIdx,
     src_type: SIMDVector,
@@ -15,9 +27,26 @@ fn dotnet_vec_cast(
     if src_type == target_type {
         return src;
     }
-    eprintln!("Can't cast {src_type:?} -> {target_type:?}");
-    let _ = asm;
-    src
+    assert_eq!(
+        src_type.bits(),
+        target_type.bits(),
+        "ERROR: Can't cast {src_type:?} -> {target_type:?}, since their sizes differ."
+    );
+    let extension_class = src_type.extension_class(asm);
+    let extension_class = asm[extension_class].clone();
+    let as_vec = asm.alloc_string("As");
+    let (from, to) = (
+        generic_vec(src_type, 0, asm),
+        generic_vec(target_type, 1, asm),
+    );
+    let as_vec = extension_class.static_mref_generic(
+        &[from],
+        to,
+        as_vec,
+        asm,
+        [src_type.elem().into(), target_type.elem().into()].into(),
+    );
+    asm.alloc_node(CILNode::Call(Box::new((as_vec, [src].into()))))
 }
 
 fn simd_ones_compliment(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
@@ -141,18 +170,6 @@ fn simd_abs(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
     };
     patcher.insert(name, Box::new(generator));
 }
-#[allow(dead_code)]
-fn simd_shuffle(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
-    let name: crate::StringIdx = asm.alloc_string("simd_shuffle");
-    let generator = move |_mref: MethodRefIdx, _asm: &mut Assembly| {
-        todo!("simd_shuffle not supported yet!");
-        /*MethodImpl::MethodBody {
-            blocks: vec![BasicBlock::new(vec![], 0, None)],
-            locals: vec![],
-        }*/
-    };
-    patcher.insert(name, Box::new(generator));
-}
 fn simd_vec_from_val(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
     let name: crate::StringIdx = asm.alloc_string("simd_vec_from_val");
     let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
@@ -205,7 +222,13 @@ fn simd_allset(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
     patcher.insert(name, Box::new(generator));
 }
 
-pub fn simd(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+/// Inserts the SIMD builtins. If `scalar` is set, none of them use `System.Runtime.Intrinsics`.
+pub fn simd(asm: &mut Assembly, patcher: &mut MissingMethodPatcher, scalar: bool) {
+    scalar::lane_ops(asm, patcher);
+    if scalar {
+        scalar::scalar_fallbacks(asm, patcher);
+        return;
+    }
     simd_eq(asm, patcher);
     simd_ones_compliment(asm, patcher);
     simd_neg(asm, patcher);
@@ -220,4 +243,9 @@ pub fn simd(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
     simd_eq_any(asm, patcher);
     simd_mul(asm, patcher);
     simd_div(asm, patcher);
+    simd_xor(asm, patcher);
+    simd_lt(asm, patcher);
+    simd_le(asm, patcher);
+    simd_gt(asm, patcher);
+    simd_ge(asm, patcher);
 }
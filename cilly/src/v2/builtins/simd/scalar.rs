@@ -0,0 +1,1025 @@
+//! Lane by lane implementations of the SIMD builtins. They are used for operations `System.Runtime.Intrinsics` has no equivalent of,
+//! and as the fallback for backends without native vector support(like the C exporter).
+use crate::v2::{
+    asm::MissingMethodPatcher,
+    cilnode::{ExtendKind, PtrCastRes, UnOp},
+    cilroot::BranchCond,
+    Assembly, BasicBlock, BinOp, CILNode, CILRoot, Const, Int, MethodImpl, MethodRefIdx, NodeIdx,
+    RootIdx, Type,
+};
+/// The lanes an operation is applied to.
+struct Lanes {
+    /// Addresses of the arguments.
+    addrs: Vec<NodeIdx>,
+    /// Index of the current lane of each argument.
+    idxs: Vec<NodeIdx>,
+    /// Element types of the arguments.
+    elems: Vec<Type>,
+    /// Lane counts of the arguments.
+    counts: Vec<u32>,
+    /// Element type of the result.
+    out: Type,
+    /// Index of the lane of the result.
+    lane: u32,
+}
+impl Lanes {
+    /// Loads the current lane of the argument `arg`.
+    fn val(&self, asm: &mut Assembly, arg: usize) -> NodeIdx {
+        let addr = lane_addr(asm, self.addrs[arg], self.elems[arg], self.idxs[arg]);
+        load(asm, addr, self.elems[arg])
+    }
+    /// Loads the current lane of the argument `arg`, reinterpreted as an unsigned int.
+    fn bits(&self, asm: &mut Assembly, arg: usize) -> NodeIdx {
+        let bits = Type::Int(bits_int(self.elems[arg]));
+        let addr = lane_addr(asm, self.addrs[arg], bits, self.idxs[arg]);
+        load(asm, addr, bits)
+    }
+}
+/// The element type and lane count of `tpe`. Scalars are treated as vectors with a single lane.
+fn lanes_of(tpe: Type) -> (Type, u32) {
+    match tpe.as_simdvector() {
+        Some(vec) => (vec.elem().into(), u32::from(vec.count())),
+        None => (tpe, 1),
+    }
+}
+/// The unsigned int with the same size as `tpe`.
+fn bits_int(tpe: Type) -> Int {
+    let size = match tpe {
+        Type::Int(int) => int.size().unwrap_or(8),
+        Type::Float(float) => float.size(),
+        Type::Bool => 1,
+        _ => panic!("ERROR: {tpe:?} is not a valid SIMD lane type."),
+    };
+    match size {
+        1 => Int::U8,
+        2 => Int::U16,
+        4 => Int::U32,
+        8 => Int::U64,
+        _ => Int::U128,
+    }
+}
+fn is_signed(tpe: Type) -> bool {
+    match tpe {
+        Type::Int(int) => int.is_signed(),
+        Type::Float(_) => true,
+        _ => false,
+    }
+}
+/// Address of the lane `idx` of the vector at `addr`.
+fn lane_addr(asm: &mut Assembly, addr: NodeIdx, elem: Type, idx: NodeIdx) -> NodeIdx {
+    let elem = asm.alloc_type(elem);
+    let addr = asm.alloc_node(CILNode::PtrCast(addr, Box::new(PtrCastRes::Ref(elem))));
+    let size = asm.alloc_node(CILNode::SizeOf(elem));
+    let size = asm.alloc_node(CILNode::IntCast {
+        input: size,
+        target: Int::USize,
+        extend: ExtendKind::ZeroExtend,
+    });
+    let offset = asm.alloc_node(CILNode::BinOp(idx, size, BinOp::Mul));
+    asm.alloc_node(CILNode::BinOp(addr, offset, BinOp::Add))
+}
+fn load(asm: &mut Assembly, addr: NodeIdx, tpe: Type) -> NodeIdx {
+    let tpe = asm.alloc_type(tpe);
+    asm.alloc_node(CILNode::LdInd {
+        addr,
+        tpe,
+        volatile: false,
+    })
+}
+fn store(asm: &mut Assembly, addr: NodeIdx, val: NodeIdx, tpe: Type) -> RootIdx {
+    asm.alloc_root(CILRoot::StInd(Box::new((addr, val, tpe, false))))
+}
+fn bin(asm: &mut Assembly, lhs: NodeIdx, rhs: NodeIdx, op: BinOp) -> NodeIdx {
+    asm.alloc_node(CILNode::BinOp(lhs, rhs, op))
+}
+fn int_const(asm: &mut Assembly, int: Int, val: i128) -> NodeIdx {
+    let cst = match int {
+        Int::U8 => Const::U8(val as u8),
+        Int::U16 => Const::U16(val as u16),
+        Int::U32 => Const::U32(val as u32),
+        Int::U64 => Const::U64(val as u64),
+        Int::U128 => Const::U128(val as u128),
+        Int::USize => Const::USize(val as u64),
+        Int::I8 => Const::I8(val as i8),
+        Int::I16 => Const::I16(val as i16),
+        Int::I32 => Const::I32(val as i32),
+        Int::I64 => Const::I64(val as i64),
+        Int::I128 => Const::I128(val),
+        Int::ISize => Const::ISize(val as i64),
+    };
+    asm.alloc_node(cst)
+}
+fn lane_idx(asm: &mut Assembly, lane: u32) -> NodeIdx {
+    asm.alloc_node(Const::USize(u64::from(lane)))
+}
+/// Casts `input` to `target`. Narrow ints are always extended according to their own sign, since that is how they are kept on the stack.
+fn int_cast(asm: &mut Assembly, input: NodeIdx, target: Int, src_signed: bool) -> NodeIdx {
+    let signed = match target.size() {
+        Some(1 | 2) => target.is_signed(),
+        _ => src_signed,
+    };
+    asm.alloc_node(CILNode::IntCast {
+        input,
+        target,
+        extend: if signed {
+            ExtendKind::SignExtend
+        } else {
+            ExtendKind::ZeroExtend
+        },
+    })
+}
+/// Truncates the result of an arithmetic operation on 8 and 16 bit ints, which is performed on 32 bit values.
+fn wrap(asm: &mut Assembly, val: NodeIdx, tpe: Type) -> NodeIdx {
+    match tpe {
+        Type::Int(int) if matches!(int.size(), Some(1 | 2)) => int_cast(asm, val, int, false),
+        _ => val,
+    }
+}
+/// A lane with all bits set if `cond` is true, and all bits cleared otherwise.
+fn mask(asm: &mut Assembly, cond: NodeIdx, int: Int) -> NodeIdx {
+    let cond = int_cast(asm, cond, int, false);
+    let neg = asm.alloc_node(CILNode::UnOp(cond, UnOp::Neg));
+    wrap(asm, neg, Type::Int(int))
+}
+/// Bitwise select of `if_true` or `if_false`, without branching.
+fn select_bits(
+    asm: &mut Assembly,
+    cond: NodeIdx,
+    if_true: NodeIdx,
+    if_false: NodeIdx,
+    int: Int,
+) -> NodeIdx {
+    let mask = mask(asm, cond, int);
+    let diff = bin(asm, if_true, if_false, BinOp::XOr);
+    let diff = bin(asm, diff, mask, BinOp::And);
+    bin(asm, if_false, diff, BinOp::XOr)
+}
+fn is_nonzero(asm: &mut Assembly, val: NodeIdx, tpe: Type) -> NodeIdx {
+    let Type::Int(int) = tpe else {
+        panic!("ERROR: SIMD masks must be made of ints, not {tpe:?}.")
+    };
+    let zero = int_const(asm, int, 0);
+    let is_zero = bin(asm, val, zero, BinOp::Eq);
+    let false_cst = asm.alloc_node(Const::Bool(false));
+    bin(asm, is_zero, false_cst, BinOp::Eq)
+}
+fn shift_amount(asm: &mut Assembly, amount: u32) -> NodeIdx {
+    asm.alloc_node(Const::I32(amount as i32))
+}
+fn bit_count(int: Int) -> u32 {
+    u32::from(int.bits().unwrap_or(64))
+}
+/// Inserts a builtin `name`, which computes each lane of its result using `op`.
+fn lanewise(
+    asm: &mut Assembly,
+    patcher: &mut MissingMethodPatcher,
+    name: &str,
+    op: impl Fn(&mut Assembly, &Lanes) -> (NodeIdx, Type) + 'static,
+) {
+    let name = asm.alloc_string(name);
+    let generator = move |mref: MethodRefIdx, asm: &mut Assembly| lanewise_impl(mref, asm, &op);
+    patcher.insert(name, Box::new(generator));
+}
+/// Implements the builtin `mref`, computing each lane of its result using `op`.
+fn lanewise_impl(
+    mref: MethodRefIdx,
+    asm: &mut Assembly,
+    op: &impl Fn(&mut Assembly, &Lanes) -> (NodeIdx, Type),
+) -> MethodImpl {
+    let sig = asm[asm[mref].sig()].clone();
+    let output = *sig.output();
+    let (out, count) = lanes_of(output);
+    let addrs: Vec<_> = (0..sig.inputs().len())
+        .map(|arg| asm.alloc_node(CILNode::LdArgA(arg as u32)))
+        .collect();
+    let res = asm.alloc_node(CILNode::LdLocA(0));
+    let mut roots: Vec<_> = (0..count)
+        .map(|lane| {
+            let idx = lane_idx(asm, lane);
+            let first = lane_idx(asm, 0);
+            let (elems, counts): (Vec<_>, Vec<_>) =
+                sig.inputs().iter().map(|tpe| lanes_of(*tpe)).unzip();
+            let idxs = counts
+                .iter()
+                .map(|count| if *count == 1 { first } else { idx })
+                .collect();
+            let lanes = Lanes {
+                addrs: addrs.clone(),
+                idxs,
+                elems,
+                counts,
+                out,
+                lane,
+            };
+            let (val, tpe) = op(asm, &lanes);
+            let addr = lane_addr(asm, res, tpe, idx);
+            store(asm, addr, val, tpe)
+        })
+        .collect();
+    let res = asm.alloc_node(CILNode::LdLoc(0));
+    roots.push(asm.alloc_root(CILRoot::Ret(res)));
+    MethodImpl::MethodBody {
+        blocks: vec![BasicBlock::new(roots, 0, None)],
+        locals: vec![(None, asm.alloc_type(output))],
+    }
+}
+/// Inserts a builtin `name`, which folds the lanes of its first argument using `op`.
+/// If the builtin takes a second argument, it is used as the initial value of the accumulator.
+fn fold(
+    asm: &mut Assembly,
+    patcher: &mut MissingMethodPatcher,
+    name: &str,
+    op: impl Fn(&mut Assembly, &Lanes) -> (NodeIdx, Type) + 'static,
+) {
+    let name = asm.alloc_string(name);
+    let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
+        let sig = asm[asm[mref].sig()].clone();
+        let (elem, count) = lanes_of(sig.inputs()[0]);
+        let vec = asm.alloc_node(CILNode::LdArgA(0));
+        let acc = asm.alloc_node(CILNode::LdLocA(0));
+        let (init, start) = if sig.inputs().len() > 1 {
+            (asm.alloc_node(CILNode::LdArg(1)), 0)
+        } else {
+            let first = lane_idx(asm, 0);
+            let addr = lane_addr(asm, vec, elem, first);
+            (load(asm, addr, elem), 1)
+        };
+        let mut roots = vec![asm.alloc_root(CILRoot::StLoc(0, init))];
+        for lane in start..count {
+            let first = lane_idx(asm, 0);
+            let idx = lane_idx(asm, lane);
+            let lanes = Lanes {
+                addrs: vec![acc, vec],
+                idxs: vec![first, idx],
+                elems: vec![elem, elem],
+                counts: vec![1, count],
+                out: elem,
+                lane,
+            };
+            let (val, tpe) = op(asm, &lanes);
+            let addr = lane_addr(asm, acc, tpe, first);
+            roots.push(store(asm, addr, val, tpe));
+        }
+        let res = asm.alloc_node(CILNode::LdLoc(0));
+        roots.push(asm.alloc_root(CILRoot::Ret(res)));
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(roots, 0, None)],
+            locals: vec![(None, asm.alloc_type(elem))],
+        }
+    };
+    patcher.insert(name, Box::new(generator));
+}
+/// Lane-wise arithmetic, using `signed` for signed ints and floats, and `unsigned` for everything else.
+fn arith(signed: BinOp, unsigned: BinOp) -> impl Fn(&mut Assembly, &Lanes) -> (NodeIdx, Type) {
+    move |asm, lanes| {
+        let op = if is_signed(lanes.out) {
+            signed
+        } else {
+            unsigned
+        };
+        let (lhs, rhs) = (lanes.val(asm, 0), lanes.val(asm, 1));
+        let res = bin(asm, lhs, rhs, op);
+        (wrap(asm, res, lanes.out), lanes.out)
+    }
+}
+/// Lane-wise shifts. Unlike the .NET ones, those shift each lane by a diffrent amount.
+fn shift(signed: BinOp, unsigned: BinOp) -> impl Fn(&mut Assembly, &Lanes) -> (NodeIdx, Type) {
+    move |asm, lanes| {
+        let op = if is_signed(lanes.out) {
+            signed
+        } else {
+            unsigned
+        };
+        let val = lanes.val(asm, 0);
+        let amount = lanes.val(asm, 1);
+        let amount = int_cast(asm, amount, Int::I32, false);
+        let res = bin(asm, val, amount, op);
+        (wrap(asm, res, lanes.out), lanes.out)
+    }
+}
+#[derive(Clone, Copy)]
+pub(super) enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+fn cmp_cond(asm: &mut Assembly, lhs: NodeIdx, rhs: NodeIdx, tpe: Type, cmp: Cmp) -> NodeIdx {
+    let (lt, gt) = if is_signed(tpe) {
+        (BinOp::Lt, BinOp::Gt)
+    } else {
+        (BinOp::LtUn, BinOp::GtUn)
+    };
+    let eq = bin(asm, lhs, rhs, BinOp::Eq);
+    match cmp {
+        Cmp::Eq => eq,
+        Cmp::Ne => {
+            let false_cst = asm.alloc_node(Const::Bool(false));
+            bin(asm, eq, false_cst, BinOp::Eq)
+        }
+        Cmp::Lt => bin(asm, lhs, rhs, lt),
+        Cmp::Gt => bin(asm, lhs, rhs, gt),
+        // `!(lhs > rhs)` would be true for NaNs.
+        Cmp::Le => {
+            let lt = bin(asm, lhs, rhs, lt);
+            bin(asm, lt, eq, BinOp::Or)
+        }
+        Cmp::Ge => {
+            let gt = bin(asm, lhs, rhs, gt);
+            bin(asm, gt, eq, BinOp::Or)
+        }
+    }
+}
+/// Lane-wise comparison, producing a mask vector.
+fn compare(cmp: Cmp) -> impl Fn(&mut Assembly, &Lanes) -> (NodeIdx, Type) {
+    move |asm, lanes| {
+        let (lhs, rhs) = (lanes.val(asm, 0), lanes.val(asm, 1));
+        let cond = cmp_cond(asm, lhs, rhs, lanes.elems[0], cmp);
+        let Type::Int(int) = lanes.out else {
+            panic!(
+                "ERROR: SIMD comparisons must produce int masks, not {:?}.",
+                lanes.out
+            )
+        };
+        (mask(asm, cond, int), lanes.out)
+    }
+}
+/// Implements the comparison builtin `mref` lane by lane.
+pub(super) fn compare_impl(mref: MethodRefIdx, asm: &mut Assembly, cmp: Cmp) -> MethodImpl {
+    lanewise_impl(mref, asm, &compare(cmp))
+}
+/// Lane-wise minimum or maximum. Floats follow IEEE minNum/maxNum: NaNs are ignored, unless both lanes are NaN.
+fn min_max(max: bool) -> impl Fn(&mut Assembly, &Lanes) -> (NodeIdx, Type) {
+    move |asm, lanes| {
+        let (lhs, rhs, tpe) = (lanes.val(asm, 0), lanes.val(asm, 1), lanes.elems[0]);
+        let mut cond = cmp_cond(asm, lhs, rhs, tpe, if max { Cmp::Gt } else { Cmp::Lt });
+        if matches!(tpe, Type::Float(_)) {
+            let rhs_nan = cmp_cond(asm, rhs, rhs, tpe, Cmp::Ne);
+            cond = bin(asm, cond, rhs_nan, BinOp::Or);
+        }
+        let int = bits_int(tpe);
+        let (lhs, rhs) = (lanes.bits(asm, 0), lanes.bits(asm, 1));
+        (select_bits(asm, cond, lhs, rhs, int), Type::Int(int))
+    }
+}
+fn saturating(sub: bool) -> impl Fn(&mut Assembly, &Lanes) -> (NodeIdx, Type) {
+    move |asm, lanes| {
+        let Type::Int(int) = lanes.out else {
+            panic!(
+                "ERROR: saturating SIMD ops require ints, not {:?}.",
+                lanes.out
+            )
+        };
+        let (lhs, rhs) = (lanes.val(asm, 0), lanes.val(asm, 1));
+        let res = bin(asm, lhs, rhs, if sub { BinOp::Sub } else { BinOp::Add });
+        let res = wrap(asm, res, lanes.out);
+        if !int.is_signed() {
+            // Unsigned ops overflowed if the result wrapped around.
+            let overflow = if sub {
+                bin(asm, lhs, rhs, BinOp::LtUn)
+            } else {
+                bin(asm, res, lhs, BinOp::LtUn)
+            };
+            let saturated = int_const(asm, int, if sub { 0 } else { -1 });
+            return (select_bits(asm, overflow, saturated, res, int), lanes.out);
+        }
+        // Signed ops overflowed if the sign of the result is wrong.
+        let (a, b) = if sub {
+            (
+                bin(asm, lhs, rhs, BinOp::XOr),
+                bin(asm, lhs, res, BinOp::XOr),
+            )
+        } else {
+            (
+                bin(asm, lhs, res, BinOp::XOr),
+                bin(asm, rhs, res, BinOp::XOr),
+            )
+        };
+        let flipped = bin(asm, a, b, BinOp::And);
+        let zero = int_const(asm, int, 0);
+        let overflow = bin(asm, flipped, zero, BinOp::Lt);
+        // MIN if `lhs` is negative, MAX otherwise.
+        let amount = shift_amount(asm, bit_count(int) - 1);
+        let sign = bin(asm, lhs, amount, BinOp::Shr);
+        let max = int.max(asm);
+        let max = asm.alloc_node(max);
+        let saturated = bin(asm, sign, max, BinOp::XOr);
+        let saturated = wrap(asm, saturated, lanes.out);
+        (select_bits(asm, overflow, saturated, res, int), lanes.out)
+    }
+}
+fn neg(asm: &mut Assembly, lanes: &Lanes) -> (NodeIdx, Type) {
+    let val = lanes.val(asm, 0);
+    let neg = asm.alloc_node(CILNode::UnOp(val, UnOp::Neg));
+    (wrap(asm, neg, lanes.out), lanes.out)
+}
+fn not(asm: &mut Assembly, lanes: &Lanes) -> (NodeIdx, Type) {
+    let val = lanes.val(asm, 0);
+    let not = asm.alloc_node(CILNode::UnOp(val, UnOp::Not));
+    (wrap(asm, not, lanes.out), lanes.out)
+}
+fn abs(asm: &mut Assembly, lanes: &Lanes) -> (NodeIdx, Type) {
+    match lanes.out {
+        // Clears the sign bit.
+        Type::Float(_) => {
+            let int = bits_int(lanes.out);
+            let bits = lanes.bits(asm, 0);
+            let mask = int_const(asm, int, (1_i128 << (bit_count(int) - 1)) - 1);
+            (bin(asm, bits, mask, BinOp::And), Type::Int(int))
+        }
+        Type::Int(int) => {
+            let val = lanes.val(asm, 0);
+            let amount = shift_amount(asm, bit_count(int) - 1);
+            let sign = bin(asm, val, amount, BinOp::Shr);
+            let flipped = bin(asm, val, sign, BinOp::XOr);
+            let abs = bin(asm, flipped, sign, BinOp::Sub);
+            (wrap(asm, abs, lanes.out), lanes.out)
+        }
+        _ => panic!(
+            "ERROR: can't calculate the absolute value of {:?}",
+            lanes.out
+        ),
+    }
+}
+fn splat(asm: &mut Assembly, lanes: &Lanes) -> (NodeIdx, Type) {
+    (lanes.val(asm, 0), lanes.out)
+}
+fn all_set(asm: &mut Assembly, lanes: &Lanes) -> (NodeIdx, Type) {
+    let int = bits_int(lanes.out);
+    (int_const(asm, int, -1), Type::Int(int))
+}
+/// Picks the lane of the second argument if the lane of the mask is set, and the lane of the third one otherwise.
+fn select(asm: &mut Assembly, lanes: &Lanes) -> (NodeIdx, Type) {
+    let mask = lanes.val(asm, 0);
+    let cond = is_nonzero(asm, mask, lanes.elems[0]);
+    let int = bits_int(lanes.out);
+    let (if_true, if_false) = (lanes.bits(asm, 1), lanes.bits(asm, 2));
+    (
+        select_bits(asm, cond, if_true, if_false, int),
+        Type::Int(int),
+    )
+}
+/// Like [`select`], but the mask is an int with a bit per lane.
+fn select_bitmask(asm: &mut Assembly, lanes: &Lanes) -> (NodeIdx, Type) {
+    let Type::Int(mask_int) = lanes.elems[0] else {
+        panic!("ERROR: bitmasks must be ints, not {:?}.", lanes.elems[0])
+    };
+    let mask = lanes.val(asm, 0);
+    let amount = shift_amount(asm, lanes.lane);
+    let shifted = bin(asm, mask, amount, BinOp::ShrUn);
+    let one = int_const(asm, mask_int, 1);
+    let cond = bin(asm, shifted, one, BinOp::And);
+    let int = bits_int(lanes.out);
+    let (if_true, if_false) = (lanes.bits(asm, 1), lanes.bits(asm, 2));
+    (
+        select_bits(asm, cond, if_true, if_false, int),
+        Type::Int(int),
+    )
+}
+/// Converts each lane, following the semantics of `as`.
+fn cast(asm: &mut Assembly, lanes: &Lanes) -> (NodeIdx, Type) {
+    let val = lanes.val(asm, 0);
+    let res = match (lanes.elems[0], lanes.out) {
+        (Type::Int(src), Type::Int(target)) => int_cast(asm, val, target, src.is_signed()),
+        (Type::Int(src), Type::Float(target)) => asm.alloc_node(CILNode::FloatCast {
+            input: val,
+            target,
+            is_signed: src.is_signed(),
+        }),
+        (Type::Float(_), Type::Float(target)) => asm.alloc_node(CILNode::FloatCast {
+            input: val,
+            target,
+            is_signed: true,
+        }),
+        // Saturates, just like `as`.
+        (Type::Float(src), Type::Int(target)) => {
+            let main_module = *asm.main_module();
+            let main_module = asm[main_module].clone();
+            let name = asm.alloc_string(format!("cast_{}_{}", src.name(), target.name()));
+            let cast = main_module.static_mref(&[Type::Float(src)], Type::Int(target), name, asm);
+            asm.alloc_node(CILNode::Call(Box::new((cast, [val].into()))))
+        }
+        (src, target) => panic!("ERROR: can't cast SIMD lanes of type {src:?} to {target:?}"),
+    };
+    (res, lanes.out)
+}
+/// Shuffles the lanes of two vectors. Indices smaller than the lane count select from the first one, bigger ones from the second one.
+fn shuffle(asm: &mut Assembly, lanes: &Lanes) -> (NodeIdx, Type) {
+    let count = lanes.counts[0];
+    assert!(
+        count.is_power_of_two(),
+        "ERROR: can't shuffle vectors with {count} lanes."
+    );
+    let u32_tpe = Type::Int(Int::U32);
+    let lane = lane_idx(asm, lanes.lane);
+    let idx = lane_addr(asm, lanes.addrs[2], u32_tpe, lane);
+    let idx = load(asm, idx, u32_tpe);
+    let lane_mask = int_const(asm, Int::U32, i128::from(count - 1));
+    let src_lane = bin(asm, idx, lane_mask, BinOp::And);
+    let src_lane = int_cast(asm, src_lane, Int::USize, false);
+    // Both reads stay in bounds, no matter which vector gets selected.
+    let amount = shift_amount(asm, count.trailing_zeros());
+    let from_rhs = bin(asm, idx, amount, BinOp::ShrUn);
+    let int = bits_int(lanes.elems[0]);
+    let (lhs, rhs) = (
+        lane_addr(asm, lanes.addrs[0], Type::Int(int), src_lane),
+        lane_addr(asm, lanes.addrs[1], Type::Int(int), src_lane),
+    );
+    let (lhs, rhs) = (
+        load(asm, lhs, Type::Int(int)),
+        load(asm, rhs, Type::Int(int)),
+    );
+    (select_bits(asm, from_rhs, rhs, lhs, int), Type::Int(int))
+}
+/// Inserts a builtin `name`, checking if `cmp` holds for all(or any) of the lanes.
+fn cmp_lanes(
+    asm: &mut Assembly,
+    patcher: &mut MissingMethodPatcher,
+    name: &str,
+    cmp: Cmp,
+    any: bool,
+) {
+    let name = asm.alloc_string(name);
+    let generator =
+        move |mref: MethodRefIdx, asm: &mut Assembly| cmp_lanes_impl(mref, asm, cmp, any);
+    patcher.insert(name, Box::new(generator));
+}
+/// Implements the builtin `mref`, checking if `cmp` holds for all(or any) of the lanes.
+pub(super) fn cmp_lanes_impl(
+    mref: MethodRefIdx,
+    asm: &mut Assembly,
+    cmp: Cmp,
+    any: bool,
+) -> MethodImpl {
+    let sig = asm[asm[mref].sig()].clone();
+    let (elem, count) = lanes_of(sig.inputs()[0]);
+    let (lhs, rhs) = (
+        asm.alloc_node(CILNode::LdArgA(0)),
+        asm.alloc_node(CILNode::LdArgA(1)),
+    );
+    let conds: Vec<_> = (0..count)
+        .map(|lane| {
+            let idx = lane_idx(asm, lane);
+            let (lhs, rhs) = (
+                lane_addr(asm, lhs, elem, idx),
+                lane_addr(asm, rhs, elem, idx),
+            );
+            let (lhs, rhs) = (load(asm, lhs, elem), load(asm, rhs, elem));
+            cmp_cond(asm, lhs, rhs, elem, cmp)
+        })
+        .collect();
+    let res = conds
+        .into_iter()
+        .reduce(|acc, cond| bin(asm, acc, cond, if any { BinOp::Or } else { BinOp::And }))
+        .expect("ERROR: SIMD vectors must have at least one lane.");
+    let ret = asm.alloc_root(CILRoot::Ret(res));
+    MethodImpl::MethodBody {
+        blocks: vec![BasicBlock::new(vec![ret], 0, None)],
+        locals: vec![],
+    }
+}
+/// Packs the most significant bit of each lane into an int.
+fn most_significant_bits(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let name = asm.alloc_string("simd_get_most_significant_bits");
+    let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
+        let sig = asm[asm[mref].sig()].clone();
+        let (elem, count) = lanes_of(sig.inputs()[0]);
+        let Type::Int(out) = *sig.output() else {
+            panic!(
+                "ERROR: SIMD bitmasks must be stored in ints, not {:?}.",
+                sig.output()
+            )
+        };
+        let int = bits_int(elem);
+        let vec = asm.alloc_node(CILNode::LdArgA(0));
+        let msb_shift = shift_amount(asm, bit_count(int) - 1);
+        let one = int_const(asm, int, 1);
+        let init = int_const(asm, out, 0);
+        let res = (0..count).fold(init, |acc, lane| {
+            let idx = lane_idx(asm, lane);
+            let addr = lane_addr(asm, vec, Type::Int(int), idx);
+            let bits = load(asm, addr, Type::Int(int));
+            let msb = bin(asm, bits, msb_shift, BinOp::ShrUn);
+            let msb = bin(asm, msb, one, BinOp::And);
+            let msb = int_cast(asm, msb, out, false);
+            let amount = shift_amount(asm, lane);
+            let msb = bin(asm, msb, amount, BinOp::Shl);
+            bin(asm, acc, msb, BinOp::Or)
+        });
+        let ret = asm.alloc_root(CILRoot::Ret(res));
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![ret], 0, None)],
+            locals: vec![],
+        }
+    };
+    patcher.insert(name, Box::new(generator));
+}
+/// Builds blocks running `lane_root` for each lane with a non-zero mask. Masked-off lanes are never touched,
+/// since their pointers may be invalid.
+fn masked_lanes(
+    asm: &mut Assembly,
+    mask_arg: u32,
+    mask_tpe: Type,
+    init: Option<RootIdx>,
+    ret: RootIdx,
+    mut lane_root: impl FnMut(&mut Assembly, NodeIdx) -> RootIdx,
+) -> Vec<BasicBlock> {
+    let (mask_elem, count) = lanes_of(mask_tpe);
+    let mask = asm.alloc_node(CILNode::LdArgA(mask_arg));
+    let mut blocks = vec![];
+    let next = asm.alloc_root(CILRoot::Branch(Box::new((1, 0, None))));
+    blocks.push(BasicBlock::new(
+        init.into_iter().chain([next]).collect(),
+        0,
+        None,
+    ));
+    for lane in 0..count {
+        let idx = lane_idx(asm, lane);
+        let addr = lane_addr(asm, mask, mask_elem, idx);
+        let mask = load(asm, addr, mask_elem);
+        let skip = asm.alloc_root(CILRoot::Branch(Box::new((
+            lane + 2,
+            0,
+            Some(BranchCond::False(mask)),
+        ))));
+        let root = lane_root(asm, idx);
+        let next = asm.alloc_root(CILRoot::Branch(Box::new((lane + 2, 0, None))));
+        blocks.push(BasicBlock::new(vec![skip, root, next], lane + 1, None));
+    }
+    blocks.push(BasicBlock::new(vec![ret], count + 1, None));
+    blocks
+}
+/// Reads the pointer in the lane `idx` of a pointer vector.
+fn lane_ptr(asm: &mut Assembly, ptrs: NodeIdx, idx: NodeIdx, elem: Type) -> NodeIdx {
+    let addr = lane_addr(asm, ptrs, Type::Int(Int::USize), idx);
+    let ptr = load(asm, addr, Type::Int(Int::USize));
+    let elem = asm.alloc_type(elem);
+    asm.alloc_node(CILNode::PtrCast(ptr, Box::new(PtrCastRes::Ptr(elem))))
+}
+/// `simd_gather(values, pointers, mask)`: reads the enabled lanes trough their pointers, and takes the rest from `values`.
+fn gather(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let name = asm.alloc_string("simd_gather");
+    let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
+        let sig = asm[asm[mref].sig()].clone();
+        let (elem, _) = lanes_of(sig.inputs()[0]);
+        let values = asm.alloc_node(CILNode::LdArg(0));
+        let init = asm.alloc_root(CILRoot::StLoc(0, values));
+        let res = asm.alloc_node(CILNode::LdLoc(0));
+        let ret = asm.alloc_root(CILRoot::Ret(res));
+        let (ptrs, res) = (
+            asm.alloc_node(CILNode::LdArgA(1)),
+            asm.alloc_node(CILNode::LdLocA(0)),
+        );
+        let blocks = masked_lanes(asm, 2, sig.inputs()[2], Some(init), ret, |asm, idx| {
+            let ptr = lane_ptr(asm, ptrs, idx, elem);
+            let val = load(asm, ptr, elem);
+            let addr = lane_addr(asm, res, elem, idx);
+            store(asm, addr, val, elem)
+        });
+        MethodImpl::MethodBody {
+            blocks,
+            locals: vec![(None, asm.alloc_type(sig.inputs()[0]))],
+        }
+    };
+    patcher.insert(name, Box::new(generator));
+}
+/// `simd_scatter(values, pointers, mask)`: writes the enabled lanes of `values` trough their pointers.
+fn scatter(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let name = asm.alloc_string("simd_scatter");
+    let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
+        let sig = asm[asm[mref].sig()].clone();
+        let (elem, _) = lanes_of(sig.inputs()[0]);
+        let ret = asm.alloc_root(CILRoot::VoidRet);
+        let (values, ptrs) = (
+            asm.alloc_node(CILNode::LdArgA(0)),
+            asm.alloc_node(CILNode::LdArgA(1)),
+        );
+        let blocks = masked_lanes(asm, 2, sig.inputs()[2], None, ret, |asm, idx| {
+            let ptr = lane_ptr(asm, ptrs, idx, elem);
+            let addr = lane_addr(asm, values, elem, idx);
+            let val = load(asm, addr, elem);
+            store(asm, ptr, val, elem)
+        });
+        MethodImpl::MethodBody {
+            blocks,
+            locals: vec![],
+        }
+    };
+    patcher.insert(name, Box::new(generator));
+}
+/// `simd_masked_load(mask, ptr, values)`: reads the enabled lanes from consecutive elements at `ptr`, and takes the rest from `values`.
+fn masked_load(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let name = asm.alloc_string("simd_masked_load");
+    let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
+        let sig = asm[asm[mref].sig()].clone();
+        let (elem, _) = lanes_of(*sig.output());
+        let values = asm.alloc_node(CILNode::LdArg(2));
+        let init = asm.alloc_root(CILRoot::StLoc(0, values));
+        let res = asm.alloc_node(CILNode::LdLoc(0));
+        let ret = asm.alloc_root(CILRoot::Ret(res));
+        let (ptr, res) = (
+            asm.alloc_node(CILNode::LdArg(1)),
+            asm.alloc_node(CILNode::LdLocA(0)),
+        );
+        let blocks = masked_lanes(asm, 0, sig.inputs()[0], Some(init), ret, |asm, idx| {
+            let src = lane_addr(asm, ptr, elem, idx);
+            let val = load(asm, src, elem);
+            let addr = lane_addr(asm, res, elem, idx);
+            store(asm, addr, val, elem)
+        });
+        MethodImpl::MethodBody {
+            blocks,
+            locals: vec![(None, asm.alloc_type(*sig.output()))],
+        }
+    };
+    patcher.insert(name, Box::new(generator));
+}
+/// `simd_masked_store(mask, ptr, values)`: writes the enabled lanes of `values` to consecutive elements at `ptr`.
+fn masked_store(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let name = asm.alloc_string("simd_masked_store");
+    let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
+        let sig = asm[asm[mref].sig()].clone();
+        let (elem, _) = lanes_of(sig.inputs()[2]);
+        let ret = asm.alloc_root(CILRoot::VoidRet);
+        let (ptr, values) = (
+            asm.alloc_node(CILNode::LdArg(1)),
+            asm.alloc_node(CILNode::LdArgA(2)),
+        );
+        let blocks = masked_lanes(asm, 0, sig.inputs()[0], None, ret, |asm, idx| {
+            let dst = lane_addr(asm, ptr, elem, idx);
+            let addr = lane_addr(asm, values, elem, idx);
+            let val = load(asm, addr, elem);
+            store(asm, dst, val, elem)
+        });
+        MethodImpl::MethodBody {
+            blocks,
+            locals: vec![],
+        }
+    };
+    patcher.insert(name, Box::new(generator));
+}
+/// `simd_extract(vec, idx)`: reads a single lane, selected at runtime.
+fn extract(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let name = asm.alloc_string("simd_extract");
+    let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
+        let sig = asm[asm[mref].sig()].clone();
+        let (elem, _) = lanes_of(sig.inputs()[0]);
+        let vec = asm.alloc_node(CILNode::LdArgA(0));
+        let idx = asm.alloc_node(CILNode::LdArg(1));
+        let idx = int_cast(asm, idx, Int::USize, false);
+        let addr = lane_addr(asm, vec, elem, idx);
+        let val = load(asm, addr, elem);
+        let ret = asm.alloc_root(CILRoot::Ret(val));
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![ret], 0, None)],
+            locals: vec![],
+        }
+    };
+    patcher.insert(name, Box::new(generator));
+}
+/// `simd_insert(vec, idx, val)`: replaces a single lane, selected at runtime.
+fn insert(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let name = asm.alloc_string("simd_insert");
+    let generator = move |mref: MethodRefIdx, asm: &mut Assembly| {
+        let sig = asm[asm[mref].sig()].clone();
+        let (elem, _) = lanes_of(sig.inputs()[0]);
+        let vec = asm.alloc_node(CILNode::LdArg(0));
+        let init = asm.alloc_root(CILRoot::StLoc(0, vec));
+        let res = asm.alloc_node(CILNode::LdLocA(0));
+        let idx = asm.alloc_node(CILNode::LdArg(1));
+        let idx = int_cast(asm, idx, Int::USize, false);
+        let addr = lane_addr(asm, res, elem, idx);
+        let val = asm.alloc_node(CILNode::LdArg(2));
+        let set = store(asm, addr, val, elem);
+        let res = asm.alloc_node(CILNode::LdLoc(0));
+        let ret = asm.alloc_root(CILRoot::Ret(res));
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![init, set, ret], 0, None)],
+            locals: vec![(None, asm.alloc_type(sig.inputs()[0]))],
+        }
+    };
+    patcher.insert(name, Box::new(generator));
+}
+/// Inserts the lane by lane versions of the builtins, which have a native .NET implementation too.
+pub(super) fn scalar_fallbacks(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    lanewise(asm, patcher, "simd_add", arith(BinOp::Add, BinOp::Add));
+    lanewise(asm, patcher, "simd_sub", arith(BinOp::Sub, BinOp::Sub));
+    lanewise(asm, patcher, "simd_mul", arith(BinOp::Mul, BinOp::Mul));
+    lanewise(asm, patcher, "simd_div", arith(BinOp::Div, BinOp::DivUn));
+    lanewise(asm, patcher, "simd_and", arith(BinOp::And, BinOp::And));
+    lanewise(asm, patcher, "simd_or", arith(BinOp::Or, BinOp::Or));
+    lanewise(asm, patcher, "simd_xor", arith(BinOp::XOr, BinOp::XOr));
+    lanewise(asm, patcher, "simd_eq", compare(Cmp::Eq));
+    lanewise(asm, patcher, "simd_lt", compare(Cmp::Lt));
+    lanewise(asm, patcher, "simd_le", compare(Cmp::Le));
+    lanewise(asm, patcher, "simd_gt", compare(Cmp::Gt));
+    lanewise(asm, patcher, "simd_ge", compare(Cmp::Ge));
+    lanewise(asm, patcher, "simd_neg", neg);
+    lanewise(asm, patcher, "simd_abs", abs);
+    lanewise(asm, patcher, "simd_ones_compliment", not);
+    lanewise(asm, patcher, "simd_vec_from_val", splat);
+    lanewise(asm, patcher, "simd_allset", all_set);
+    cmp_lanes(asm, patcher, "simd_eq_all", Cmp::Eq, false);
+    cmp_lanes(asm, patcher, "simd_eq_any", Cmp::Eq, true);
+}
+/// Inserts the builtins `System.Runtime.Intrinsics` has no equivalent of.
+pub(super) fn lane_ops(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    lanewise(asm, patcher, "simd_rem", arith(BinOp::Rem, BinOp::RemUn));
+    lanewise(asm, patcher, "simd_shl", shift(BinOp::Shl, BinOp::Shl));
+    lanewise(asm, patcher, "simd_shr", shift(BinOp::Shr, BinOp::ShrUn));
+    lanewise(asm, patcher, "simd_ne", compare(Cmp::Ne));
+    lanewise(asm, patcher, "simd_fmin", min_max(false));
+    lanewise(asm, patcher, "simd_fmax", min_max(true));
+    lanewise(asm, patcher, "simd_saturating_add", saturating(false));
+    lanewise(asm, patcher, "simd_saturating_sub", saturating(true));
+    lanewise(asm, patcher, "simd_select", select);
+    lanewise(asm, patcher, "simd_select_bitmask", select_bitmask);
+    lanewise(asm, patcher, "simd_cast", cast);
+    lanewise(asm, patcher, "simd_shuffle", shuffle);
+    fold(
+        asm,
+        patcher,
+        "simd_reduce_add",
+        arith(BinOp::Add, BinOp::Add),
+    );
+    fold(
+        asm,
+        patcher,
+        "simd_reduce_mul",
+        arith(BinOp::Mul, BinOp::Mul),
+    );
+    fold(
+        asm,
+        patcher,
+        "simd_reduce_and",
+        arith(BinOp::And, BinOp::And),
+    );
+    fold(asm, patcher, "simd_reduce_or", arith(BinOp::Or, BinOp::Or));
+    fold(
+        asm,
+        patcher,
+        "simd_reduce_xor",
+        arith(BinOp::XOr, BinOp::XOr),
+    );
+    fold(asm, patcher, "simd_reduce_min", min_max(false));
+    fold(asm, patcher, "simd_reduce_max", min_max(true));
+    most_significant_bits(asm, patcher);
+    gather(asm, patcher);
+    scatter(asm, patcher);
+    masked_load(asm, patcher);
+    masked_store(asm, patcher);
+    extract(asm, patcher);
+    insert(asm, patcher);
+}
+#[cfg(test)]
+mod tests {
+    use fxhash::FxHashMap;
+
+    use super::*;
+    use crate::v2::{
+        interpreter::{patch, static_mref, Interpreter, Value},
+        tpe::simd::{SIMDElem, SIMDVector},
+        Float,
+    };
+    #[test]
+    fn scalar_simd() {
+        let mut asm = Assembly::default();
+        let vec = |elem: SIMDElem, count| Type::SIMDVector(SIMDVector::new(elem, count));
+        let (i8x8, u8x8) = (vec(Int::I8.into(), 8), vec(Int::U8.into(), 8));
+        let (i32x4, u32x4) = (vec(Int::I32.into(), 4), vec(Int::U32.into(), 4));
+        let f32x4 = vec(Float::F32.into(), 4);
+        let mut builtin =
+            |name: &str, inputs: &[Type], output: Type| static_mref(&mut asm, name, inputs, output);
+        let sat_add = builtin("simd_saturating_add", &[i8x8, i8x8], i8x8);
+        let sat_sub = builtin("simd_saturating_sub", &[u8x8, u8x8], u8x8);
+        let shuffle = builtin("simd_shuffle", &[i32x4, i32x4, u32x4], i32x4);
+        let fmin = builtin("simd_fmin", &[f32x4, f32x4], f32x4);
+        let lt = builtin("simd_lt", &[f32x4, f32x4], i32x4);
+        let bitmask = builtin(
+            "simd_get_most_significant_bits",
+            &[i8x8],
+            Type::Int(Int::U8),
+        );
+        let select = builtin("simd_select", &[i32x4, f32x4, f32x4], f32x4);
+        let reduce_add = builtin("simd_reduce_add", &[u8x8], Type::Int(Int::U8));
+        let mut overrides: MissingMethodPatcher = FxHashMap::default();
+        super::super::simd(&mut asm, &mut overrides, true);
+        patch(&mut asm, &overrides);
+        let bytes = |bytes: Vec<u8>| Value::Struct(bytes.into());
+        let i8s = |vals: [i8; 8]| bytes(vals.iter().map(|val| *val as u8).collect());
+        let i32s = |vals: [i32; 4]| bytes(vals.iter().flat_map(|val| val.to_le_bytes()).collect());
+        let u32s = |vals: [u32; 4]| bytes(vals.iter().flat_map(|val| val.to_le_bytes()).collect());
+        let f32s = |vals: [f32; 4]| bytes(vals.iter().flat_map(|val| val.to_le_bytes()).collect());
+        let mut interp = Interpreter::new(asm, vec![]);
+        let mut run = |mref, args| interp.call(mref, args).unwrap().unwrap();
+        assert_eq!(
+            run(
+                sat_add,
+                vec![
+                    i8s([100, -100, 1, 127, -128, 0, 50, -50]),
+                    i8s([100, -100, 1, 1, -1, 0, 50, -100]),
+                ]
+            ),
+            i8s([127, -128, 2, 127, -128, 0, 100, -128])
+        );
+        assert_eq!(
+            run(
+                sat_sub,
+                vec![
+                    bytes(vec![5, 10, 255, 0, 128, 1, 2, 3]),
+                    bytes(vec![6, 10, 1, 1, 127, 0, 3, 3]),
+                ]
+            ),
+            bytes(vec![0, 0, 254, 0, 1, 1, 0, 0])
+        );
+        assert_eq!(
+            run(
+                shuffle,
+                vec![
+                    i32s([10, 11, 12, 13]),
+                    i32s([20, 21, 22, 23]),
+                    u32s([0, 5, 2, 7])
+                ]
+            ),
+            i32s([10, 21, 12, 23])
+        );
+        let Value::Struct(min) = run(
+            fmin,
+            vec![
+                f32s([1.0, f32::NAN, 3.0, f32::NAN]),
+                f32s([2.0, 5.0, f32::NAN, f32::NAN]),
+            ],
+        ) else {
+            panic!("simd_fmin did not return a vector")
+        };
+        let min: Vec<_> = min
+            .chunks(4)
+            .map(|lane| f32::from_le_bytes(lane.try_into().unwrap()))
+            .collect();
+        assert_eq!(&min[..3], &[1.0, 5.0, 3.0]);
+        assert!(min[3].is_nan());
+        assert_eq!(
+            run(
+                lt,
+                vec![
+                    f32s([1.0, 2.0, f32::NAN, -1.0]),
+                    f32s([2.0, 2.0, 0.0, -2.0])
+                ]
+            ),
+            i32s([-1, 0, 0, 0])
+        );
+        assert_eq!(
+            run(bitmask, vec![i8s([-1, 0, -1, 0, 0, 0, 0, -128])]),
+            Value::I32(0b1000_0101)
+        );
+        assert_eq!(
+            run(
+                select,
+                vec![
+                    i32s([-1, 0, -1, 0]),
+                    f32s([1.0, 2.0, 3.0, 4.0]),
+                    f32s([5.0, 6.0, 7.0, 8.0])
+                ]
+            ),
+            f32s([1.0, 6.0, 3.0, 8.0])
+        );
+        assert_eq!(
+            run(reduce_add, vec![bytes(vec![200, 100, 1, 2, 3, 4, 5, 6])]),
+            Value::I32(65)
+        );
+    }
+    #[test]
+    fn single_lane_simd_cmp() {
+        // Single lane vectors are lowered to scalars, which the native SIMD builtins compare lane by lane.
+        let mut asm = Assembly::default();
+        let (int, float) = (Type::Int(Int::I32), Type::Float(Float::F32));
+        let lt = static_mref(&mut asm, "simd_lt", &[float, float], int);
+        let ge = static_mref(&mut asm, "simd_ge", &[int, int], int);
+        let eq = static_mref(&mut asm, "simd_eq", &[int, int], int);
+        let eq_all = static_mref(&mut asm, "simd_eq_all", &[int, int], Type::Bool);
+        let mut overrides: MissingMethodPatcher = FxHashMap::default();
+        super::super::simd(&mut asm, &mut overrides, false);
+        patch(&mut asm, &overrides);
+        let mut interp = Interpreter::new(asm, vec![]);
+        let mut run = |mref, args| interp.call(mref, args).unwrap().unwrap();
+        assert_eq!(
+            run(lt, vec![Value::F32(1.0), Value::F32(2.0)]),
+            Value::I32(-1)
+        );
+        assert_eq!(
+            run(lt, vec![Value::F32(f32::NAN), Value::F32(2.0)]),
+            Value::I32(0)
+        );
+        assert_eq!(
+            run(ge, vec![Value::I32(-3), Value::I32(-3)]),
+            Value::I32(-1)
+        );
+        assert_eq!(run(eq, vec![Value::I32(4), Value::I32(5)]), Value::I32(0));
+        assert_eq!(
+            run(eq_all, vec![Value::I32(4), Value::I32(4)]),
+            Value::I32(1)
+        );
+    }
+}
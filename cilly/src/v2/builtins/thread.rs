@@ -1,7 +1,7 @@
 use super::{
     super::{
         asm::MissingMethodPatcher, cilnode::MethodKind, Access, Assembly, BasicBlock, CILNode,
-        CILRoot, ClassDef, ClassRef, Const, FieldDesc, Int, MethodDef, MethodImpl, Type,
+        CILRoot, ClassDef, ClassRef, Const, FieldDesc, Int, MethodDef, MethodImpl, MethodRef, Type,
     },
     UNMANAGED_THREAD_START,
 };
@@ -10,7 +10,7 @@ use crate::{
         cilnode::{ExtendKind, PtrCastRes},
         cilroot::BranchCond,
         tpe::GenericKind,
-        BinOp, StaticFieldDesc,
+        BinOp, NodeIdx, StaticFieldDesc,
     },
     ClassRefIdx,
 };
@@ -415,6 +415,7 @@ pub fn instert_threading(asm: &mut Assembly, patcher: &mut MissingMethodPatcher)
     insert_pthread_key_create(asm, patcher);
     insert_pthread_setspecific(asm, patcher);
     handle_to_obj(asm, patcher);
+    insert_thread_dtors(asm, patcher);
 
     let uts = asm.alloc_string(UNMANAGED_THREAD_START);
     let object = ClassRef::object(asm);
@@ -487,6 +488,9 @@ pub fn instert_threading(asm: &mut Assembly, patcher: &mut MissingMethodPatcher)
         [data_node].into(),
     ))));
     let call = asm.alloc_root(CILRoot::StLoc(0, call));
+    // Run the destructors of the thread locals of this thread.
+    let run_dtors = run_thread_dtors_mref(asm);
+    let run_dtors = asm.alloc_root(CILRoot::Call(Box::new((run_dtors, [].into()))));
     // Get the ID of this thread
     let thread = ClassRef::thread(asm);
     let current_thread = asm.alloc_string("get_CurrentThread");
@@ -551,7 +555,7 @@ pub fn instert_threading(asm: &mut Assembly, patcher: &mut MissingMethodPatcher)
         MethodKind::Virtual,
         MethodImpl::MethodBody {
             blocks: vec![BasicBlock::new(
-                vec![call_tcctor, call, set_result, ret],
+                vec![call_tcctor, call, run_dtors, set_result, ret],
                 0,
                 None,
             )],
@@ -707,3 +711,315 @@ fn insert_pthread_key_delete(asm: &mut Assembly, patcher: &mut MissingMethodPatc
     };
     patcher.insert(name, Box::new(generator));
 }
+/// Name of the thread local static holding the list of thread local destructors of the current thread.
+const THREAD_DTORS: &str = "thread_dtors";
+/// Name of the method running all the destructors registered by the current thread.
+pub const RUN_THREAD_DTORS: &str = "run_thread_dtors";
+/// Inserts `__cxa_thread_atexit_impl`, which registers a destructor of a thread local, and the method running those destructors
+/// for the current thread.
+pub fn insert_thread_dtors(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let main_mod = asm.main_module();
+    let void_ptr = asm.nptr(Type::Void);
+    asm.add_static(void_ptr, THREAD_DTORS, true, main_mod);
+    insert_cxa_thread_atexit_impl(asm, patcher);
+    insert_run_thread_dtors(asm, patcher);
+}
+fn thread_dtors_static(asm: &mut Assembly) -> crate::v2::StaticFieldIdx {
+    let main_mod = *asm.main_module();
+    let thread_dtors = asm.alloc_string(THREAD_DTORS);
+    let void_ptr = asm.nptr(Type::Void);
+    asm.alloc_sfld(StaticFieldDesc::new(main_mod, thread_dtors, void_ptr))
+}
+/// A reference to the method running the destructors of the current thread.
+pub fn run_thread_dtors_mref(asm: &mut Assembly) -> crate::v2::MethodRefIdx {
+    let main_mod = *asm.main_module();
+    let name = asm.alloc_string(RUN_THREAD_DTORS);
+    let sig = asm.sig([], Type::Void);
+    asm.alloc_methodref(MethodRef::new(
+        main_mod,
+        name,
+        sig,
+        MethodKind::Static,
+        [].into(),
+    ))
+}
+/// The signature of a thread local destructor.
+fn dtor_sig(asm: &mut Assembly) -> crate::v2::SigIdx {
+    let void_ptr = asm.nptr(Type::Void);
+    asm.sig([void_ptr], Type::Void)
+}
+/// Returns the address of the `slot`-th pointer of a destructor list entry. Each entry consists of the
+/// destructor, its argument, and the next entry.
+fn dtor_entry_slot(asm: &mut Assembly, entry: NodeIdx, slot: i32, tpe: Type) -> NodeIdx {
+    let slot = asm.alloc_node(Const::I32(slot));
+    let void_ptr = asm.nptr(Type::Void);
+    let void_ptr = asm.alloc_type(void_ptr);
+    let ptr_size = asm.alloc_node(CILNode::SizeOf(void_ptr));
+    let offset = asm.alloc_node(CILNode::BinOp(ptr_size, slot, BinOp::Mul));
+    let offset = asm.alloc_node(CILNode::IntCast {
+        input: offset,
+        target: Int::USize,
+        extend: ExtendKind::ZeroExtend,
+    });
+    let addr = asm.alloc_node(CILNode::BinOp(entry, offset, BinOp::Add));
+    let tpe = asm.alloc_type(tpe);
+    asm.alloc_node(CILNode::PtrCast(addr, Box::new(PtrCastRes::Ptr(tpe))))
+}
+fn insert_cxa_thread_atexit_impl(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let name = asm.alloc_string("__cxa_thread_atexit_impl");
+    let generator = move |_, asm: &mut Assembly| {
+        let void_ptr = asm.nptr(Type::Void);
+        let dtor_tpe = Type::FnPtr(dtor_sig(asm));
+        // Allocate a new entry
+        let entry_size = asm.alloc_node(Const::I32(3));
+        let void_ptr_idx = asm.alloc_type(void_ptr);
+        let ptr_size = asm.alloc_node(CILNode::SizeOf(void_ptr_idx));
+        let entry_size = asm.alloc_node(CILNode::BinOp(ptr_size, entry_size, BinOp::Mul));
+        let entry_size = asm.alloc_node(CILNode::IntCast {
+            input: entry_size,
+            target: Int::USize,
+            extend: ExtendKind::ZeroExtend,
+        });
+        let ptr_size = asm.alloc_node(CILNode::IntCast {
+            input: ptr_size,
+            target: Int::USize,
+            extend: ExtendKind::ZeroExtend,
+        });
+        let aligned_alloc = asm.alloc_string("AlignedAlloc");
+        let native_mem = ClassRef::native_mem(asm);
+        let alloc_sig = asm.sig([Type::Int(Int::USize), Type::Int(Int::USize)], void_ptr);
+        let aligned_alloc = asm.alloc_methodref(MethodRef::new(
+            native_mem,
+            aligned_alloc,
+            alloc_sig,
+            MethodKind::Static,
+            [].into(),
+        ));
+        let entry = asm.alloc_node(CILNode::Call(Box::new((
+            aligned_alloc,
+            [entry_size, ptr_size].into(),
+        ))));
+        let alloc_entry = asm.alloc_root(CILRoot::StLoc(0, entry));
+        let entry = asm.alloc_node(CILNode::LdLoc(0));
+        // Fill the entry
+        let dtor_addr = dtor_entry_slot(asm, entry, 0, dtor_tpe);
+        let dtor = asm.alloc_node(CILNode::LdArg(0));
+        let set_dtor = asm.alloc_root(CILRoot::StInd(Box::new((dtor_addr, dtor, dtor_tpe, false))));
+        let obj_addr = dtor_entry_slot(asm, entry, 1, void_ptr);
+        let obj = asm.alloc_node(CILNode::LdArg(1));
+        let set_obj = asm.alloc_root(CILRoot::StInd(Box::new((obj_addr, obj, void_ptr, false))));
+        let next_addr = dtor_entry_slot(asm, entry, 2, void_ptr);
+        let thread_dtors = thread_dtors_static(asm);
+        let next = asm.alloc_node(CILNode::LdStaticField(thread_dtors));
+        let set_next = asm.alloc_root(CILRoot::StInd(Box::new((next_addr, next, void_ptr, false))));
+        // Push the entry onto the list of destructors of this thread
+        let push_entry = asm.alloc_root(CILRoot::SetStaticField {
+            field: thread_dtors,
+            val: entry,
+        });
+        let const_0 = asm.alloc_node(Const::I32(0));
+        let ret = asm.alloc_root(CILRoot::Ret(const_0));
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(
+                vec![alloc_entry, set_dtor, set_obj, set_next, push_entry, ret],
+                0,
+                None,
+            )],
+            locals: vec![(None, void_ptr_idx)],
+        }
+    };
+    patcher.insert(name, Box::new(generator));
+}
+fn insert_run_thread_dtors(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
+    let name = asm.alloc_string(RUN_THREAD_DTORS);
+    let generator = move |_, asm: &mut Assembly| {
+        let void_ptr = asm.nptr(Type::Void);
+        let void_ptr_idx = asm.alloc_type(void_ptr);
+        let dtor_sig = dtor_sig(asm);
+        let dtor_tpe = asm.alloc_type(Type::FnPtr(dtor_sig));
+        // Pop the most recently registered entry, if there is any.
+        let thread_dtors = thread_dtors_static(asm);
+        let head = asm.alloc_node(CILNode::LdStaticField(thread_dtors));
+        let get_head = asm.alloc_root(CILRoot::StLoc(0, head));
+        let entry = asm.alloc_node(CILNode::LdLoc(0));
+        let check_empty = asm.alloc_root(CILRoot::Branch(Box::new((
+            1,
+            0,
+            Some(BranchCond::False(entry)),
+        ))));
+        let next_addr = dtor_entry_slot(asm, entry, 2, void_ptr);
+        let next = asm.alloc_node(CILNode::LdInd {
+            addr: next_addr,
+            tpe: void_ptr_idx,
+            volatile: false,
+        });
+        let pop_entry = asm.alloc_root(CILRoot::SetStaticField {
+            field: thread_dtors,
+            val: next,
+        });
+        // Run the destructor. It may register new destructors, which will be run by the next iterations.
+        let dtor_addr = dtor_entry_slot(asm, entry, 0, Type::FnPtr(dtor_sig));
+        let dtor = asm.alloc_node(CILNode::LdInd {
+            addr: dtor_addr,
+            tpe: dtor_tpe,
+            volatile: false,
+        });
+        let obj_addr = dtor_entry_slot(asm, entry, 1, void_ptr);
+        let obj = asm.alloc_node(CILNode::LdInd {
+            addr: obj_addr,
+            tpe: void_ptr_idx,
+            volatile: false,
+        });
+        let run_dtor = asm.alloc_root(CILRoot::CallI(Box::new((dtor, dtor_sig, [obj].into()))));
+        // Free the entry
+        let aligned_free = asm.alloc_string("AlignedFree");
+        let native_mem = ClassRef::native_mem(asm);
+        let free_sig = asm.sig([void_ptr], Type::Void);
+        let aligned_free = asm.alloc_methodref(MethodRef::new(
+            native_mem,
+            aligned_free,
+            free_sig,
+            MethodKind::Static,
+            [].into(),
+        ));
+        let free_entry = asm.alloc_root(CILRoot::Call(Box::new((aligned_free, [entry].into()))));
+        let next_entry = asm.alloc_root(CILRoot::Branch(Box::new((0, 0, None))));
+        let ret = asm.alloc_root(CILRoot::VoidRet);
+        MethodImpl::MethodBody {
+            blocks: vec![
+                BasicBlock::new(
+                    vec![
+                        get_head,
+                        check_empty,
+                        pop_entry,
+                        run_dtor,
+                        free_entry,
+                        next_entry,
+                    ],
+                    0,
+                    None,
+                ),
+                BasicBlock::new(vec![ret], 1, None),
+            ],
+            locals: vec![(None, void_ptr_idx)],
+        }
+    };
+    patcher.insert(name, Box::new(generator));
+}
+#[cfg(test)]
+mod tests {
+    use fxhash::FxHashMap;
+
+    use super::*;
+    use crate::v2::{
+        il_exporter::export_il,
+        interpreter::{add_method, insert_builtins, patch, static_mref, Interpreter, Value},
+    };
+    #[test]
+    fn thread_dtors() {
+        let mut asm = Assembly::default();
+        let main_module = asm.main_module();
+        let int = Type::Int(Int::I32);
+        let void_ptr = asm.nptr(Type::Void);
+        let counter = asm.add_static(int, "counter", false, main_module);
+        // record(arg) sets counter = counter * 10 + arg.
+        let counter_val = asm.alloc_node(CILNode::LdStaticField(counter));
+        let ten = asm.alloc_node(Const::I32(10));
+        let scaled = asm.alloc_node(CILNode::BinOp(counter_val, ten, BinOp::Mul));
+        let arg = asm.alloc_node(CILNode::LdArg(0));
+        let arg = asm.alloc_node(CILNode::IntCast {
+            input: arg,
+            target: Int::I32,
+            extend: ExtendKind::ZeroExtend,
+        });
+        let val = asm.alloc_node(CILNode::BinOp(scaled, arg, BinOp::Add));
+        let roots = vec![
+            asm.alloc_root(CILRoot::SetStaticField {
+                field: counter,
+                val,
+            }),
+            asm.alloc_root(CILRoot::VoidRet),
+        ];
+        let record = add_method(
+            &mut asm,
+            "record",
+            &[void_ptr],
+            Type::Void,
+            vec![BasicBlock::new(roots, 0, None)],
+            vec![],
+        );
+        // Register 2 destructors, and run them.
+        let record_sig = asm[record].sig();
+        let atexit = static_mref(
+            &mut asm,
+            "__cxa_thread_atexit_impl",
+            &[Type::FnPtr(record_sig), void_ptr, void_ptr],
+            int,
+        );
+        let run_dtors = run_thread_dtors_mref(&mut asm);
+        let dtor = asm.alloc_node(CILNode::LdFtn(record));
+        let register = |val: i64, asm: &mut Assembly| {
+            let val = asm.alloc_node(Const::ISize(val));
+            let dso = asm.alloc_node(Const::ISize(0));
+            let call = asm.alloc_node(CILNode::Call(Box::new((atexit, [dtor, val, dso].into()))));
+            asm.alloc_root(CILRoot::Pop(call))
+        };
+        let roots = vec![
+            register(1, &mut asm),
+            register(2, &mut asm),
+            asm.alloc_root(CILRoot::Call(Box::new((run_dtors, [].into())))),
+            asm.alloc_root(CILRoot::Call(Box::new((run_dtors, [].into())))),
+            asm.alloc_root(CILRoot::Ret(counter_val)),
+        ];
+        let mref = add_method(
+            &mut asm,
+            "run",
+            &[],
+            int,
+            vec![BasicBlock::new(roots, 0, None)],
+            vec![],
+        );
+        insert_builtins(&mut asm);
+        let mut interp = Interpreter::new(asm, vec![]);
+        // Destructors run once, in the reverse order of their registration.
+        assert_eq!(interp.call(mref, vec![]).unwrap(), Some(Value::I32(21)));
+        let live_allocs = interp.mem.live_allocs();
+        assert_eq!(interp.call(mref, vec![]).unwrap(), Some(Value::I32(2121)));
+        // The entries of the destructor list got freed.
+        assert_eq!(interp.mem.live_allocs(), live_allocs);
+    }
+    #[test]
+    fn thread_dtors_il() {
+        let mut asm = Assembly::default();
+        let mut patcher = FxHashMap::default();
+        insert_thread_dtors(&mut asm, &mut patcher);
+        instert_threading(&mut asm, &mut patcher);
+        // Reference `__cxa_thread_atexit_impl`, so that the destructor list gets exported.
+        let void_ptr = asm.nptr(Type::Void);
+        static_mref(
+            &mut asm,
+            "__cxa_thread_atexit_impl",
+            &[void_ptr, void_ptr, void_ptr],
+            Type::Int(Int::I32),
+        );
+        patch(&mut asm, &patcher);
+        let il = export_il(&asm);
+        // Each thread has its own list of destructors, which the interpreter can't check.
+        assert!(il.contains(
+            ".field static void* 'thread_dtors'\n.custom instance void [System.Runtime]System.ThreadStaticAttribute::.ctor()"
+        ));
+        // Threads started with `pthread_create` run their destructors once their start routine returns.
+        let start = il
+            .match_indices(".method ")
+            .map(|(start, _)| &il[start..])
+            .find(|def| def.lines().next().unwrap().contains("'Start'("))
+            .expect("No Start method");
+        let start_fn = start
+            .find("calli void* (void*)")
+            .expect("No call to the start routine");
+        let dtors = start
+            .find("call void class 'MainModule'::'run_thread_dtors'()")
+            .expect("No call to run_thread_dtors");
+        assert!(start_fn < dtors);
+    }
+}
@@ -29,6 +29,21 @@ static inline void *System_Runtime_InteropServices_NativeMemory_AlignedReallocpv
 /*Utility macros*/
 #define eprintf(...) fprintf(stderr, __VA_ARGS__)
 #define BUILTIN_UNSUPORTED(NAME,OUTPUT, ARGLIST) static inline OUTPUT NAME ARGLIST { eprintf("Function " #NAME "is not yet supported!"); abort();}
+/* Guaranteed tail calls. Without `musttail`, the compiler will still turn most of them into jumps when optimizing. */
+#if defined(__has_attribute)
+#if __has_attribute(musttail)
+#define MUSTTAIL __attribute__((musttail))
+#endif
+#endif
+#ifndef MUSTTAIL
+#define MUSTTAIL
+#endif
+/* The calling convention of `extern "system"` functions: stdcall on 32 bit Windows, C everywhere else. */
+#if defined(_WIN32) && defined(__i386__)
+#define SYSTEM_ABI __attribute__((stdcall))
+#else
+#define SYSTEM_ABI
+#endif
 /*Wrappers for certain 128 bit ops: TODO: remove this once all ops are ported to new cilly builtins*/
 #define System_UInt128_op_Additionu16u16u16(lhs, rhs) (lhs + rhs)
 #define System_Int128_op_Additioni16i16i16(lhs, rhs) (__int128)((unsigned __int128)lhs + (unsigned __int128)rhs)
@@ -283,6 +298,14 @@ double fabsf64(double val);
 #define System_Math_Maxisisis(x, y) (((x) > (y)) ? (x) : (y))
 #define System_Math_Minususus(x, y) (((x) < (y)) ? (x) : (y))
 #define System_Math_Maxususus(x, y) (((x) > (y)) ? (x) : (y))
+#define System_Math_Minu1u1u1(x, y) (((x) < (y)) ? (x) : (y))
+#define System_Math_Maxu1u1u1(x, y) (((x) > (y)) ? (x) : (y))
+#define System_Math_Mini1i1i1(x, y) (((x) < (y)) ? (x) : (y))
+#define System_Math_Maxi1i1i1(x, y) (((x) > (y)) ? (x) : (y))
+#define System_Math_Minu2u2u2(x, y) (((x) < (y)) ? (x) : (y))
+#define System_Math_Maxu2u2u2(x, y) (((x) > (y)) ? (x) : (y))
+#define System_Math_Mini2i2i2(x, y) (((x) < (y)) ? (x) : (y))
+#define System_Math_Maxi2i2i2(x, y) (((x) > (y)) ? (x) : (y))
 
 typedef struct TSWData
 {
@@ -401,6 +424,20 @@ static inline uintptr_t System_Threading_Interlocked_Exchangerususus(uintptr_t *
     __atomic_exchange(addr, &val, &ret, 5);
     return ret;
 }
+#define SUBWORD_INTERLOCKED(T, NAME)                                                                   \
+    static inline T System_Threading_Interlocked_CompareExchanger##NAME##NAME##NAME##NAME(T *addr, T value, T comparand) \
+    {                                                                                                  \
+        __atomic_compare_exchange_n(addr, &comparand, value, false, 5, 5);                             \
+        return comparand;                                                                              \
+    }                                                                                                  \
+    static inline T System_Threading_Interlocked_Exchanger##NAME##NAME##NAME(T *addr, T val)           \
+    {                                                                                                  \
+        return __atomic_exchange_n(addr, val, 5);                                                      \
+    }
+SUBWORD_INTERLOCKED(uint8_t, u1)
+SUBWORD_INTERLOCKED(int8_t, i1)
+SUBWORD_INTERLOCKED(uint16_t, u2)
+SUBWORD_INTERLOCKED(int16_t, i2)
 static inline uint32_t System_Threading_Interlocked_Addru4u4u4(uint32_t *addr, uint32_t addend)
 {
     fprintf(stderr, "Can't System_Threading_Interlocked_Addru4u4u4 yet.\n");
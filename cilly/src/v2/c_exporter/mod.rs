@@ -24,8 +24,8 @@ use super::{
     method::LocalDef,
     tpe::simd::SIMDVector,
     typecheck::TypeCheckError,
-    Assembly, BinOp, CILIter, CILIterElem, CILNode, CILRoot, ClassDefIdx, ClassRef, ClassRefIdx,
-    Const, Exporter, Int, MethodDef, MethodRef, NodeIdx, RootIdx, SigIdx, Type,
+    Assembly, BinOp, CILIter, CILIterElem, CILNode, CILRoot, CallConv, ClassDefIdx, ClassRef,
+    ClassRefIdx, Const, Exporter, Int, MethodDef, MethodRef, NodeIdx, RootIdx, SigIdx, Type,
 };
 fn local_name(locals: &[LocalDef], asm: &Assembly, loc: u32) -> String {
     // If the name of this local repeats, use the L form.
@@ -35,7 +35,6 @@ fn local_name(locals: &[LocalDef], asm: &Assembly, loc: u32) -> String {
         .count()
         > 1
     {
-    
         return format!("L{loc}");
     }
     match locals[loc as usize].0 {
@@ -130,6 +129,27 @@ fn c_tpe(field_tpe: Type, asm: &Assembly) -> String {
         }
     }
 }
+/// Returns the parameter list of a function with the inputs `inputs`. C-variadic functions also accept variable arguments.
+fn c_inputs(inputs: &[Type], is_variadic: bool, asm: &Assembly) -> String {
+    inputs
+        .iter()
+        .map(|i| nonvoid_c_type(*i, asm))
+        .chain(is_variadic.then(|| "...".into()))
+        .intersperse(",".into())
+        .collect::<String>()
+}
+/// Returns the attribute selecting the calling convention `conv`, if it is not the default one.
+fn c_conv(conv: CallConv) -> &'static str {
+    match conv {
+        CallConv::Rust | CallConv::C => "",
+        CallConv::System => "SYSTEM_ABI ",
+        CallConv::StdCall => "__attribute__((stdcall)) ",
+        CallConv::FastCall => "__attribute__((fastcall)) ",
+        CallConv::ThisCall => "__attribute__((thiscall)) ",
+        CallConv::Win64 => "__attribute__((ms_abi)) ",
+        CallConv::SysV64 => "__attribute__((sysv_abi)) ",
+    }
+}
 fn mref_to_name(mref: &MethodRef, asm: &Assembly) -> String {
     let class = &asm[mref.class()];
     let class_name = escape_ident(&asm[class.name()]);
@@ -182,14 +202,10 @@ impl CExporter {
             return Ok(());
         }
         let output = c_tpe(mref.output(asm), asm);
-        let inputs = mref
-            .stack_inputs(asm)
-            .iter()
-            .map(|i| nonvoid_c_type(*i, asm))
-            .intersperse(",".into())
-            .collect::<String>();
-
-        writeln!(method_decls, "{output} {method_name}({inputs});")
+        let sig = &asm[mref.sig()];
+        let conv = c_conv(sig.conv());
+        let inputs = c_inputs(mref.stack_inputs(asm), sig.is_variadic(), asm);
+        writeln!(method_decls, "{output} {conv}{method_name}({inputs});")
     }
     #[allow(clippy::too_many_arguments)]
     fn binop_to_string(
@@ -355,7 +371,7 @@ impl CExporter {
                     super::Float::F16 => todo!(),
                     super::Float::F32 => format!("(float)fmod((double)({lhs}),((double)({rhs}))"),
                     super::Float::F64 => format!("fmod(({lhs}),({rhs}))"),
-                    super::Float::F128 => todo!(),
+                    super::Float::F128 => format!("fmodf128(({lhs}),({rhs}))"),
                 },
                 // TODO: reminder of a bool can only be false or a segfault. Is this a valid operation?
                 Type::Bool => "false".into(),
@@ -583,7 +599,7 @@ impl CExporter {
                     super::Float::F16 => todo!(),
                     super::Float::F32 => format!("(float)({input})"),
                     super::Float::F64 => format!("(double)({input})"),
-                    super::Float::F128 => todo!(),
+                    super::Float::F128 => format!("(_Float128)({input})"),
                 }
             }
             CILNode::RefToPtr(node_idx) => {
@@ -656,14 +672,10 @@ impl CExporter {
                     .intersperse(",".into())
                     .collect::<String>();
                 let ret = c_tpe(*fn_ptr_sig.output(), asm);
-                let args = fn_ptr_sig
-                    .inputs()
-                    .iter()
-                    .map(|i| nonvoid_c_type(*i, asm))
-                    .intersperse(",".into())
-                    .collect::<String>();
+                let conv = c_conv(fn_ptr_sig.conv());
+                let args = c_inputs(fn_ptr_sig.fixed_inputs(), fn_ptr_sig.is_variadic(), asm);
                 let fn_ptr = Self::node_to_string(asm[*fn_ptr].clone(), asm, locals, inputs, sig)?;
-                format!("((*({ret}(*)({args}))({fn_ptr})))({call_args})")
+                format!("((*({ret}({conv}*)({args}))({fn_ptr})))({call_args})")
             }
             CILNode::LocAlloc { size } => format!(
                 "((uint8_t*)alloca({}))",
@@ -745,6 +757,43 @@ impl CExporter {
                 node = Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig)?
             ),
             CILRoot::VoidRet => "return;".into(),
+            CILRoot::TailCall(node_idx) => {
+                let node = Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig)?;
+                // C does not allow returning a void expression, so calls to void functions can't be forced to be tail calls.
+                if *asm[sig].output() == Type::Void {
+                    format!("{node};return;")
+                } else {
+                    format!("MUSTTAIL return {node};")
+                }
+            }
+            CILRoot::Switch(switch) => {
+                let (node_idx, table) = switch.as_ref();
+                let label = |(target, sub_target): (u32, u32)| {
+                    if sub_target != 0 {
+                        sub_target
+                    } else {
+                        target
+                    }
+                };
+                // Signed values are sign extended, so that case labels can always be written as 64 bit unsigned ints.
+                let mut res = format!(
+                    "switch((uint64_t)(({tpe})({node}))){{",
+                    tpe = c_tpe(Type::Int(table.tpe()), asm),
+                    node = Self::node_to_string(asm[*node_idx].clone(), asm, locals, inputs, sig)?
+                );
+                for (value, target) in table.cases() {
+                    res.push_str(&format!(
+                        "case {value}ull: goto bb{target};",
+                        value = table.extend(value) as u64,
+                        target = label(target)
+                    ));
+                }
+                res.push_str(&format!(
+                    "default: goto bb{otherwise};}}",
+                    otherwise = label(table.otherwise())
+                ));
+                res
+            }
             CILRoot::Break => "".into(),
             CILRoot::Nop => "".into(),
             CILRoot::Branch(binfo) => {
@@ -870,14 +919,10 @@ impl CExporter {
                     .intersperse(",".into())
                     .collect::<String>();
                 let ret = c_tpe(*fn_ptr_sig.output(), asm);
-                let args = fn_ptr_sig
-                    .inputs()
-                    .iter()
-                    .map(|i| nonvoid_c_type(*i, asm))
-                    .intersperse(",".into())
-                    .collect::<String>();
+                let conv = c_conv(fn_ptr_sig.conv());
+                let args = c_inputs(fn_ptr_sig.fixed_inputs(), fn_ptr_sig.is_variadic(), asm);
                 let fn_ptr = Self::node_to_string(asm[*fn_ptr].clone(), asm, locals, inputs, sig)?;
-                format!("((*({ret}(*)({args}))({fn_ptr})))({call_args});")
+                format!("((*({ret}({conv}*)({args}))({fn_ptr})))({call_args});")
             }
             CILRoot::ExitSpecialRegion { target, source } => format!("goto bb{target};"),
             CILRoot::ReThrow => todo!(),
@@ -907,6 +952,8 @@ impl CExporter {
         }
         let method_name = mref_to_name(&def.ref_to(), asm);
         let output = c_tpe(def.ref_to().output(asm), asm);
+        let conv = c_conv(asm[def.sig()].conv());
+        let is_variadic = asm[def.sig()].is_variadic();
         match def.resolved_implementation(asm) {
             MethodImpl::MethodBody { blocks, locals } => (),
             MethodImpl::Extern {
@@ -935,28 +982,16 @@ impl CExporter {
                 | "sched_getaffinity"
                 | "poll" => return Ok(()),
                 _ => {
-                    let inputs = def
-                        .ref_to()
-                        .stack_inputs(asm)
-                        .iter()
-                        .map(|i| nonvoid_c_type(*i, asm))
-                        .intersperse(",".into())
-                        .collect::<String>();
-                    writeln!(method_decls, "{output} {method_name}({inputs});")?;
+                    let inputs = c_inputs(def.ref_to().stack_inputs(asm), is_variadic, asm);
+                    writeln!(method_decls, "{output} {conv}{method_name}({inputs});")?;
                     return Ok(());
                 }
             },
             MethodImpl::Missing => {
-                let inputs = def
-                    .ref_to()
-                    .stack_inputs(asm)
-                    .iter()
-                    .map(|i| nonvoid_c_type(*i, asm))
-                    .intersperse(",".into())
-                    .collect::<String>();
+                let inputs = c_inputs(def.ref_to().stack_inputs(asm), is_variadic, asm);
                 writeln!(
                     method_defs,
-                    "{output} {method_name}({inputs}){{eprintf(\"Missing method {method_name}\\n\");abort();}}"
+                    "{output} {conv}{method_name}({inputs}){{eprintf(\"Missing method {method_name}\\n\");abort();}}"
                 )?;
                 return Ok(());
             }
@@ -977,18 +1012,17 @@ impl CExporter {
             })
             .intersperse(",".into())
             .collect::<String>();
-        writeln!(method_defs, "{output} {method_name}({inputs}){{")?;
+        writeln!(method_defs, "{output} {conv}{method_name}({inputs}){{")?;
         let locals: Vec<_> = def.iter_locals(asm).copied().collect();
         for (idx, (lname, local_type)) in locals.iter().enumerate() {
             // If the name of this local is found multiple times, use the L form.
-           
+
             writeln!(
                 method_defs,
                 "{local_type} {lname};",
                 lname = local_name(&locals, asm, idx as u32),
                 local_type = nonvoid_c_type(asm[*local_type], asm),
             )?;
-         
         }
         let blocks = def.blocks(asm).unwrap().to_vec();
         for block in blocks {
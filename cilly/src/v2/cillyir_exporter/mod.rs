@@ -1,5 +1,5 @@
 #[allow(dead_code)]
-use super::{Assembly, ClassRef, Exporter, FnSig, Type};
+use super::{Assembly, CallConv, ClassRef, Exporter, FnSig, Type};
 use std::io::Write;
 #[derive(Default)]
 pub struct CillyIRExpoter {}
@@ -104,9 +104,18 @@ fn sig_to(sig: FnSig, asm: &Assembly) -> String {
         .map(|input| tpe_to(input, asm))
         .intersperse(",".into())
         .collect();
+    let output = tpe_to(sig.output(), asm);
+    if sig.conv() == CallConv::Rust && !sig.is_variadic() {
+        return format!("{{asm.sig([{inputs}],{output})}}");
+    }
+    let variadic = if sig.is_variadic() {
+        format!(".with_variadic({})", sig.fixed_inputs().len())
+    } else {
+        String::new()
+    };
     format!(
-        "{{asm.sig([{inputs}],{output})}}",
-        output = tpe_to(sig.output(), asm)
+        "{{asm.alloc_sig(FnSig::new([{inputs}].into(),{output}).with_conv(CallConv::{conv:?}){variadic})}}",
+        conv = sig.conv()
     )
 }
 fn escape_class_name(name: &str) -> String {
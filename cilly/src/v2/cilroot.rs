@@ -3,8 +3,8 @@ use serde::{Deserialize, Serialize};
 use super::{
     bimap::{BiMapIndex, IntoBiMapIndex},
     field::FieldIdx,
-    Assembly, CILNode, Float, Int, MethodRefIdx, NodeIdx, SigIdx, StaticFieldIdx, StringIdx, Type,
-    TypeIdx,
+    Assembly, CILNode, Float, Int, MethodRefIdx, NodeIdx, SigIdx, StaticFieldIdx, StringIdx,
+    SwitchTable, Type, TypeIdx,
 };
 use crate::cil_root::CILRoot as V1Root;
 //use crate::cil_node::CILNode as V1Node;
@@ -57,6 +57,10 @@ pub enum CILRoot {
     },
     /// Executing this root is instant UB.
     Unreachable(StringIdx),
+    /// Calls the function in `call`(a [`CILNode::Call`] or [`CILNode::CallI`]) as a tail call, and returns its result.
+    TailCall(NodeIdx),
+    /// value, cases. Jumps to the target of the case matching the value, or to the default target.
+    Switch(Box<(NodeIdx, SwitchTable)>),
 }
 
 #[derive(Hash, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
@@ -124,7 +128,9 @@ impl CILRoot {
             | CILRoot::Ret(tree)
             | CILRoot::Pop(tree)
             | CILRoot::Throw(tree)
+            | CILRoot::TailCall(tree)
             | CILRoot::SetStaticField { val: tree, .. } => [tree].into(),
+            CILRoot::Switch(info) => [&mut info.0].into(),
             CILRoot::SourceFileInfo { .. }
             | CILRoot::ExitSpecialRegion { .. }
             | CILRoot::VoidRet
@@ -174,7 +180,9 @@ impl CILRoot {
             | CILRoot::Ret(tree)
             | CILRoot::Pop(tree)
             | CILRoot::Throw(tree)
+            | CILRoot::TailCall(tree)
             | CILRoot::SetStaticField { val: tree, .. } => [tree].into(),
+            CILRoot::Switch(info) => [&info.0].into(),
             CILRoot::SourceFileInfo { .. }
             | CILRoot::ExitSpecialRegion { .. }
             | CILRoot::VoidRet
@@ -242,6 +250,14 @@ impl CILRoot {
                 let tree = CILNode::from_v1(tree, asm);
                 Self::Ret(asm.alloc_node(tree))
             }
+            V1Root::TailCall { call } => {
+                let call = CILNode::from_v1(call, asm);
+                Self::TailCall(asm.alloc_node(call))
+            }
+            V1Root::Switch { discr, table } => {
+                let discr = CILNode::from_v1(discr, asm);
+                Self::Switch(Box::new((asm.alloc_node(discr), table.clone())))
+            }
             V1Root::Pop { tree } => {
                 let tree = CILNode::from_v1(tree, asm);
                 Self::Pop(asm.alloc_node(tree))
@@ -496,6 +512,17 @@ impl CILRoot {
                 let root = CILRoot::Ret(asm.alloc_node(ret));
                 root_map(root, asm)
             }
+            CILRoot::TailCall(call) => {
+                let call = asm.get_node(call).clone().map(asm, node_map);
+                let root = CILRoot::TailCall(asm.alloc_node(call));
+                root_map(root, asm)
+            }
+            CILRoot::Switch(switch) => {
+                let (discr, table) = *switch;
+                let discr = asm.get_node(discr).clone().map(asm, node_map);
+                let root = CILRoot::Switch(Box::new((asm.alloc_node(discr), table)));
+                root_map(root, asm)
+            }
             CILRoot::Pop(pop) => {
                 let pop = asm.get_node(pop).clone().map(asm, node_map);
                 let root = CILRoot::Pop(asm.alloc_node(pop));
@@ -191,6 +191,12 @@ impl ClassRef {
         let asm_name = Some(asm.alloc_string("System.Runtime"));
         asm.alloc_class_ref(ClassRef::new(name, asm_name, true, [].into()))
     }
+    /// Returns a reference to the `System.IDisposable` interface.
+    pub fn disposable(asm: &mut Assembly) -> ClassRefIdx {
+        let name = asm.alloc_string("System.IDisposable");
+        let asm_name = Some(asm.alloc_string("System.Runtime"));
+        asm.alloc_class_ref(ClassRef::new(name, asm_name, false, [].into()))
+    }
     /// Returns a reference to the `System.String`
     pub fn string(asm: &mut Assembly) -> ClassRefIdx {
         let name = asm.alloc_string("System.String");
@@ -203,6 +209,12 @@ impl ClassRef {
         let asm_name = Some(asm.alloc_string("System.Runtime"));
         asm.alloc_class_ref(ClassRef::new(name, asm_name, false, [].into()))
     }
+    /// Returns a reference to the `System.GC`
+    pub fn gc(asm: &mut Assembly) -> ClassRefIdx {
+        let name = asm.alloc_string("System.GC");
+        let asm_name = Some(asm.alloc_string("System.Runtime"));
+        asm.alloc_class_ref(ClassRef::new(name, asm_name, false, [].into()))
+    }
     /// Returns a reference to the `System.Threading.Thread`
     pub fn thread(asm: &mut Assembly) -> ClassRefIdx {
         let name = asm.alloc_string("System.Threading.Thread");
@@ -281,6 +293,27 @@ impl ClassRef {
         let asm_name = Some(asm.alloc_string("System.Runtime.InteropServices"));
         asm.alloc_class_ref(ClassRef::new(name, asm_name, false, [].into()))
     }
+    /// Returns a reference to the class `System.Runtime.InteropServices.MemoryMarshal`
+    #[must_use]
+    pub fn memory_marshal(asm: &mut Assembly) -> ClassRefIdx {
+        let name = asm.alloc_string("System.Runtime.InteropServices.MemoryMarshal");
+        let asm_name = Some(asm.alloc_string("System.Memory"));
+        asm.alloc_class_ref(ClassRef::new(name, asm_name, false, [].into()))
+    }
+    /// Returns a reference to the class `System.Text.Encoding`
+    #[must_use]
+    pub fn encoding(asm: &mut Assembly) -> ClassRefIdx {
+        let name = asm.alloc_string("System.Text.Encoding");
+        let asm_name = Some(asm.alloc_string("System.Runtime"));
+        asm.alloc_class_ref(ClassRef::new(name, asm_name, false, [].into()))
+    }
+    /// Returns a reference to the class `System.Array`
+    #[must_use]
+    pub fn array(asm: &mut Assembly) -> ClassRefIdx {
+        let name = asm.alloc_string("System.Array");
+        let asm_name = Some(asm.alloc_string("System.Runtime"));
+        asm.alloc_class_ref(ClassRef::new(name, asm_name, false, [].into()))
+    }
     /// Returns a reference to the class `System.Collections.DictionaryEntry`
     #[must_use]
     pub fn dictionary_entry(asm: &mut Assembly) -> ClassRefIdx {
@@ -450,6 +483,8 @@ pub struct ClassDef {
     access: Access,
     explict_size: Option<NonZeroU32>,
     align: Option<NonZeroU32>,
+    implements: Vec<ClassRefIdx>,
+    overrides: Vec<(MethodRefIdx, MethodDefIdx)>,
 }
 impl ClassDef {
     /// Checks if this class defition has a with the name and type.
@@ -466,6 +501,7 @@ impl ClassDef {
             .chain(self.static_fields().iter().map(|(tpe, _, _)| tpe))
             .copied()
             .chain(self.extends.iter().map(|cref| Type::ClassRef(*cref)))
+            .chain(self.implements.iter().map(|cref| Type::ClassRef(*cref)))
     }
     #[allow(clippy::too_many_arguments)]
     #[must_use]
@@ -492,6 +528,8 @@ impl ClassDef {
             access,
             explict_size,
             align,
+            implements: vec![],
+            overrides: vec![],
         }
     }
 
@@ -527,6 +565,32 @@ impl ClassDef {
     pub fn extends(&self) -> Option<ClassRefIdx> {
         self.extends
     }
+    /// Marks this class as implementing the interface `iface`.
+    pub fn add_interface(&mut self, iface: ClassRefIdx) {
+        if !self.implements.contains(&iface) {
+            self.implements.push(iface);
+        }
+    }
+    /// The interfaces this class implements.
+    #[must_use]
+    pub fn implements(&self) -> &[ClassRefIdx] {
+        &self.implements
+    }
+    /// Makes the method `body`, defined in this class, the implementation of the method `decl`, declared by a base class
+    /// or an interface.
+    pub fn add_override(&mut self, decl: MethodRefIdx, body: MethodDefIdx) {
+        if !self.overrides.contains(&(decl, body)) {
+            self.overrides.push((decl, body));
+        }
+    }
+    /// The explicit overrides of this class: pairs of an overriden method declaration, and the method implementing it.
+    #[must_use]
+    pub fn overrides(&self) -> &[(MethodRefIdx, MethodDefIdx)] {
+        &self.overrides
+    }
+    pub fn overrides_mut(&mut self) -> &mut Vec<(MethodRefIdx, MethodDefIdx)> {
+        &mut self.overrides
+    }
 
     pub(crate) fn has_explicit_layout(&self) -> bool {
         self.explict_size.is_some() || self.fields.iter().any(|(_, _, offset)| offset.is_some())
@@ -579,6 +643,11 @@ impl ClassDef {
         // Merge the methods, removing duplicates
         self.methods_mut().extend(translated.methods());
         make_unique(self.methods_mut());
+        // Merge the interfaces and overrides, removing duplicates
+        self.implements.extend(translated.implements());
+        make_unique(&mut self.implements);
+        self.overrides.extend(translated.overrides());
+        make_unique(&mut self.overrides);
         // Check accessibility matches
         assert_eq!(self.access(), translated.access());
     }
@@ -933,3 +1002,49 @@ fn class_ref() {
         }
     }
 }
+#[test]
+fn implements() {
+    use super::{BasicBlock, CILRoot, MethodDef, MethodImpl};
+    let mut asm = Assembly::default();
+    let name: StringIdx = asm.alloc_string("Handle");
+    let disposable = ClassRef::disposable(&mut asm);
+    let mut def = ClassDef::new(
+        name,
+        false,
+        0,
+        None,
+        vec![],
+        vec![],
+        Access::Public,
+        None,
+        None,
+    );
+    def.add_interface(disposable);
+    def.add_interface(disposable);
+    assert_eq!(def.implements(), &[disposable]);
+    assert_eq!(def.iter_types().count(), 1);
+    let class = asm.class_def(def);
+    // A private method, only reachable trough the interface.
+    let dispose = asm.alloc_string("Dispose");
+    let sig = asm.sig([Type::ClassRef(*class)], Type::Void);
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    let body = asm.new_method(MethodDef::new(
+        Access::Private,
+        class,
+        dispose,
+        sig,
+        super::cilnode::MethodKind::Virtual,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![ret], 0, None)],
+            locals: vec![],
+        },
+        vec![None],
+    ));
+    let decl = asm[disposable]
+        .clone()
+        .virtual_mref(&[], Type::Void, dispose, &mut asm);
+    asm.class_mut(class).add_override(decl, body);
+    asm.eliminate_dead_fns(false);
+    assert_eq!(asm[class].overrides(), &[(decl, body)]);
+    assert!(asm[class].methods().contains(&body));
+}
@@ -14,10 +14,33 @@ impl IntoBiMapIndex for SigIdx {
         self.0
     }
 }
+/// The calling convention of a function.
+/// Functions compiled to CIL are always managed, and function pointers always point to managed methods(or p/invoke stubs).
+/// So, the calling convention only changes how p/invoke stubs and exporters emitting native code call a function.
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum CallConv {
+    /// The default calling convention, used by Rust functions.
+    #[default]
+    Rust,
+    /// The C calling convention(cdecl).
+    C,
+    /// The default calling convention of the platform's system APIs: stdcall on 32 bit Windows, C everywhere else.
+    System,
+    StdCall,
+    FastCall,
+    ThisCall,
+    /// The calling convention used by 64 bit Windows.
+    Win64,
+    /// The System V AMD64 calling convention, used by 64 bit Unix-like systems.
+    SysV64,
+}
 #[derive(Hash, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub struct FnSig {
     inputs: Box<[Type]>,
     output: Type,
+    conv: CallConv,
+    /// If this function is C-variadic, the number of its fixed inputs. All other inputs are variable arguments of a call site.
+    fixed_inputs: Option<u32>,
 }
 
 impl FnSig {
@@ -26,6 +49,66 @@ impl FnSig {
         Self {
             inputs: input,
             output,
+            conv: CallConv::Rust,
+            fixed_inputs: None,
+        }
+    }
+    /// Sets the calling convention of this signature.
+    #[must_use]
+    pub fn with_conv(mut self, conv: CallConv) -> Self {
+        self.conv = conv;
+        self
+    }
+    /// Marks this signature as C-variadic: only the first `fixed` inputs are fixed, and the rest of them are variable arguments.
+    /// ```
+    /// # use cilly::v2::{FnSig, Int};
+    /// # use cilly::Type;
+    /// let ptr = Type::Int(Int::USize);
+    /// let printf = FnSig::new([ptr, Type::Int(Int::I32)].into(), Type::Int(Int::I32)).with_variadic(1);
+    /// assert_eq!(printf.fixed_inputs(), &[ptr]);
+    /// assert_eq!(printf.variable_inputs(), &[Type::Int(Int::I32)]);
+    /// assert_eq!(printf.fixed_sig().inputs(), &[ptr]);
+    /// ```
+    #[must_use]
+    pub fn with_variadic(mut self, fixed: usize) -> Self {
+        assert!(
+            fixed <= self.inputs.len(),
+            "ERROR: a variadic function can't have more fixed inputs than inputs"
+        );
+        self.fixed_inputs = Some(u32::try_from(fixed).expect("ERROR: too many fixed inputs"));
+        self
+    }
+    /// The calling convention of this signature.
+    #[must_use]
+    pub fn conv(&self) -> CallConv {
+        self.conv
+    }
+    /// Checks if this signature is C-variadic.
+    #[must_use]
+    pub fn is_variadic(&self) -> bool {
+        self.fixed_inputs.is_some()
+    }
+    /// The inputs which are not variable arguments.
+    #[must_use]
+    pub fn fixed_inputs(&self) -> &[Type] {
+        match self.fixed_inputs {
+            Some(fixed) => &self.inputs[..fixed as usize],
+            None => &self.inputs,
+        }
+    }
+    /// The variable arguments passed by a call site of a C-variadic function.
+    #[must_use]
+    pub fn variable_inputs(&self) -> &[Type] {
+        &self.inputs[self.fixed_inputs().len()..]
+    }
+    /// The signature of the definition of a C-variadic function, without any variable arguments.
+    #[must_use]
+    pub fn fixed_sig(&self) -> Self {
+        Self {
+            inputs: self.fixed_inputs().into(),
+            output: self.output,
+            conv: self.conv,
+            fixed_inputs: self.fixed_inputs,
         }
     }
 
@@ -50,7 +133,13 @@ impl FnSig {
         &mut self.inputs
     }
 
+    /// Sets the inputs of this signature. For C-variadic functions, the inputs past the fixed ones are the variable arguments.
     pub fn set_inputs(&mut self, inputs: Box<[Type]>) {
+        assert!(
+            self.fixed_inputs
+                .is_none_or(|fixed| fixed as usize <= inputs.len()),
+            "ERROR: a variadic function can't have more fixed inputs than inputs"
+        );
         self.inputs = inputs;
     }
 }
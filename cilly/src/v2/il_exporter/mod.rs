@@ -3,7 +3,13 @@ use crate::v2::MethodImpl;
 use std::{io::Write, path::Path};
 
 use super::{
-    asm::{IlasmFlavour, ILASM_FLAVOUR, ILASM_PATH}, cilnode::{ExtendKind, UnOp}, cilroot::BranchCond, method::LocalDef, tpe::simd::SIMDElem, Assembly, BinOp, CILIter, CILIterElem, CILNode, ClassRefIdx, Exporter, Int, MethodDefIdx, NodeIdx, RootIdx, SigIdx, Type
+    asm::{IlasmFlavour, ILASM_FLAVOUR, ILASM_PATH},
+    cilnode::{ExtendKind, UnOp},
+    cilroot::BranchCond,
+    method::LocalDef,
+    tpe::simd::SIMDElem,
+    Assembly, BinOp, CILIter, CILIterElem, CILNode, CallConv, ClassRefIdx, Exporter, FnSig, Int,
+    MethodDefIdx, MethodRefIdx, NodeIdx, RootIdx, SigIdx, SwitchPlan, SwitchTable, Type,
 };
 
 pub struct ILExporter {
@@ -43,9 +49,20 @@ impl ILExporter {
                 "auto"
             };
             let name = &asm[class_def.name()];
+            let implements = if class_def.implements().is_empty() {
+                String::new()
+            } else {
+                let interfaces: String = class_def
+                    .implements()
+                    .iter()
+                    .map(|iface| class_ref(*iface, asm))
+                    .intersperse(",".to_owned())
+                    .collect();
+                format!(" implements {interfaces}")
+            };
             writeln!(
                 out,
-                ".class {vis} ansi {sealed} {explicit} '{name}' extends {extends}{{"
+                ".class {vis} ansi {sealed} {explicit} '{name}' extends {extends}{implements}{{"
             )?;
             // Export size
             if let Some(size) = class_def.explict_size() {
@@ -82,7 +99,8 @@ impl ILExporter {
                 };
             }
             // Debug check
-            let mut ensure_unqiue:std::collections::HashSet<MethodDefIdx> = std::collections::HashSet::new();
+            let mut ensure_unqiue: std::collections::HashSet<MethodDefIdx> =
+                std::collections::HashSet::new();
             // Export all methods
 
             for method_id in class_def.methods() {
@@ -97,24 +115,27 @@ impl ILExporter {
                     crate::v2::cilnode::MethodKind::Virtual => "virtual instance",
                     crate::v2::cilnode::MethodKind::Constructor => "rtspecialname specialname",
                 };
+                let name = &asm[method.name()];
+                let sig = &asm[method.sig()];
                 let pinvoke = if let MethodImpl::Extern {
                     lib,
                     preserve_errno,
                 } = method.implementation()
                 {
                     let lib = &asm[*lib];
+                    let conv = pinvoke_conv_il(sig.conv());
                     if *preserve_errno {
-                        format!("pinvokeimpl(\"{lib}\" cdecl lasterr)")
+                        format!("pinvokeimpl(\"{lib}\" {conv} lasterr)")
                     } else {
-                        format!("pinvokeimpl(\"{lib}\" cdecl)")
+                        format!("pinvokeimpl(\"{lib}\" {conv})")
                     }
                 } else {
                     String::new()
                 };
-                let name = &asm[method.name()];
-                let sig = &asm[method.sig()];
+                let vararg = vararg_il(sig);
                 let ret = type_il(sig.output(), asm);
                 assert_eq!(method.arg_names().len(), sig.inputs().len(), "{name:?}");
+                // Definitons of C-variadic functions have no variable arguments, so all of their inputs are fixed.
                 let inputs = match method.kind() {
                     crate::v2::cilnode::MethodKind::Static => sig.inputs(),
                     crate::v2::cilnode::MethodKind::Instance
@@ -140,7 +161,7 @@ impl ILExporter {
                 };
                 writeln!(
                     out,
-                    ".method {vis} hidebysig {kind} {pinvoke} {ret} '{name}'({inputs}) cil managed {preservesig}{{// Method ID {method_id:?}"
+                    ".method {vis} hidebysig {kind} {pinvoke} {vararg}{ret} '{name}'({inputs}) cil managed {preservesig}{{// Method ID {method_id:?}"
                 )?;
                 debug_assert!(ensure_unqiue.insert(*method_id));
                 let stack_size = match method.resolved_implementation(asm_mut) {
@@ -160,6 +181,26 @@ impl ILExporter {
                 };
 
                 writeln!(out, ".maxstack {stack_size}")?;
+                for (decl, _) in class_def
+                    .overrides()
+                    .iter()
+                    .filter(|(_, body)| body == method_id)
+                {
+                    let decl = &asm[*decl];
+                    let sig = &asm[decl.sig()];
+                    let ret = type_il(sig.output(), asm);
+                    let inputs: String = sig.inputs()[1..]
+                        .iter()
+                        .map(|tpe| non_void_type_il(tpe, asm))
+                        .intersperse(",".to_owned())
+                        .collect();
+                    let class = class_ref(decl.class(), asm);
+                    let decl_name = &asm[decl.name()];
+                    writeln!(
+                        out,
+                        ".override method instance {ret} {class}::'{decl_name}'({inputs})"
+                    )?;
+                }
 
                 if *name == *"entrypoint" {
                     writeln!(out, ".entrypoint")?;
@@ -200,9 +241,10 @@ impl ILExporter {
                     }
                     //DEBUG REMOVE THIS
                     writeln!(out,"// targets:{}",block.targets(asm).count())?;
-                    writeln!(out," bb{}:",block.block_id())?;
+                    let label = format!("bb{}",block.block_id());
+                    writeln!(out," {label}:")?;
                     for root in block.roots(){
-                        self.export_root(asm,out,*root,false, block.handler().is_some(),sig,locals)?;
+                        self.export_root(asm,out,*root,&label,false, block.handler().is_some(),sig,locals)?;
                     }
                     if let Some(handler) = block.handler(){
                         if Some(handler) == blocks_iter.peek().and_then(|block|block.handler()){
@@ -214,9 +256,10 @@ impl ILExporter {
                             writeln!(out,"pop")?;
                         }
                         for hblock in handler{
-                            writeln!(out," h{}_{}:",block.block_id(),hblock.block_id())?;
+                            let label = format!("h{}_{}",block.block_id(),hblock.block_id());
+                            writeln!(out," {label}:")?;
                             for root in hblock.roots(){
-                                self.export_root(asm,out,*root,true,false,sig,locals)?;
+                                self.export_root(asm,out,*root,&label,true,false,sig,locals)?;
                             }
                         }
                         writeln!(out,"}}")?;
@@ -421,51 +464,7 @@ impl ILExporter {
                 for arg in &call.1 {
                     self.export_node(asm, out, *arg, sig, locals)?;
                 }
-                let mref = &asm[call.0];
-                let call_op = match mref.kind() {
-                    crate::v2::cilnode::MethodKind::Static => "call",
-                    crate::v2::cilnode::MethodKind::Instance => "call instance",
-                    crate::v2::cilnode::MethodKind::Virtual => " callvirt instance",
-                    crate::v2::cilnode::MethodKind::Constructor => "newobj instance",
-                };
-                let sig = &asm[mref.sig()];
-                let output = type_il(sig.output(), asm);
-                let inputs = match mref.kind() {
-                    crate::v2::cilnode::MethodKind::Static => sig.inputs(),
-                    crate::v2::cilnode::MethodKind::Instance
-                    | crate::v2::cilnode::MethodKind::Virtual
-                    | crate::v2::cilnode::MethodKind::Constructor => {
-                        assert!(
-                            !sig.inputs().is_empty(),
-                            "invalid argc when calling {} of {}",
-                            &asm[mref.name()],
-                            class_ref(mref.class(), asm)
-                        );
-                        &sig.inputs()[1..]
-                    }
-                };
-                let inputs: String = inputs
-                    .iter()
-                    .map(|tpe| non_void_type_il(tpe, asm))
-                    .intersperse(",".to_owned())
-                    .collect();
-                let generic = if mref.generics().is_empty() {
-                    "".to_string()
-                } else {
-                    let generic_list: String = mref
-                        .generics()
-                        .iter()
-                        .map(|tpe| type_il(tpe, asm))
-                        .intersperse(",".to_owned())
-                        .collect();
-                    format!("<{generic_list}>")
-                };
-                let name = &asm[mref.name()];
-                let class = class_ref(mref.class(), asm);
-                writeln!(
-                    out,
-                    "{call_op} {output} {class}::'{name}'{generic}({inputs})"
-                )
+                writeln!(out, "{}", call_il(call.0, asm))
             }
             CILNode::IntCast {
                 input,
@@ -676,16 +675,8 @@ impl ILExporter {
                 for arg in args {
                     self.export_node(asm, out, *arg, sig, locals)?;
                 }
-                let fn_sig = asm[*fn_sig].clone();
-                let output = type_il(fn_sig.output(), asm);
                 self.export_node(asm, out, *fn_ptr, sig, locals)?;
-                let inputs: String = fn_sig
-                    .inputs()
-                    .iter()
-                    .map(|tpe| non_void_type_il(tpe, asm))
-                    .intersperse(",".to_owned())
-                    .collect();
-                writeln!(out, "calli {output} ({inputs})")
+                writeln!(out, "{}", calli_il(&asm[*fn_sig], asm))
             }
             CILNode::LocAlloc { size } => {
                 self.export_node(asm, out, size, sig, locals)?;
@@ -760,6 +751,7 @@ impl ILExporter {
         asm: &mut super::Assembly,
         out: &mut impl Write,
         root: RootIdx,
+        block: &str,
         is_handler: bool,
         has_handler: bool,
         sig: SigIdx,
@@ -797,6 +789,67 @@ impl ILExporter {
             super::CILRoot::VoidRet => {
                 writeln!(out, "ret")
             }
+            super::CILRoot::TailCall(call) => {
+                let call_op = match asm[call].clone() {
+                    CILNode::Call(call) => {
+                        for arg in call.1 {
+                            self.export_node(asm, out, arg, sig, locals)?;
+                        }
+                        call_il(call.0, asm)
+                    }
+                    CILNode::CallI(calli) => {
+                        let (fn_ptr, fn_sig, args) = *calli;
+                        for arg in args {
+                            self.export_node(asm, out, arg, sig, locals)?;
+                        }
+                        self.export_node(asm, out, fn_ptr, sig, locals)?;
+                        calli_il(&asm[fn_sig], asm)
+                    }
+                    other => panic!("ERROR: can't tail call {other:?}, which is not a call."),
+                };
+                writeln!(out, "tail. {call_op}\nret")
+            }
+            super::CILRoot::Switch(switch) => {
+                let (discr, table) = *switch;
+                // The switched-on value is evaluated once, and kept on the stack until the target is known. Each target
+                // gets a trampoline popping that value, labeled `{block}_sw{target index}`.
+                self.export_node(asm, out, discr, sig, locals)?;
+                let mut trampolines: Vec<(u32, u32)> = vec![];
+                let mut trampoline = |target: (u32, u32)| {
+                    let idx = trampolines
+                        .iter()
+                        .position(|other| *other == target)
+                        .unwrap_or_else(|| {
+                            trampolines.push(target);
+                            trampolines.len() - 1
+                        });
+                    format!("{block}_sw{idx}")
+                };
+                let otherwise = trampoline(table.otherwise());
+                let mut splits = 0;
+                export_switch_plan(
+                    out,
+                    &table,
+                    &table.plan(),
+                    block,
+                    &mut splits,
+                    &mut trampoline,
+                )?;
+                writeln!(out, "br {otherwise}")?;
+                for (idx, (target, sub_target)) in trampolines.into_iter().enumerate() {
+                    let label = if sub_target == 0 {
+                        format!("bb{target}")
+                    } else if is_handler {
+                        format!("h{target}_{sub_target}")
+                    } else if has_handler {
+                        format!("jp{target}_{sub_target}")
+                    } else {
+                        format!("bb{sub_target}")
+                    };
+                    writeln!(out, "{block}_sw{idx}: pop br {label}")?;
+                }
+                Ok(())
+            }
             super::CILRoot::Break => {
                 writeln!(out, "break")
             }
@@ -1050,23 +1103,19 @@ impl ILExporter {
                 };
                 let sig = &asm[mref.sig()];
                 let output = type_il(sig.output(), asm);
+                let vararg = vararg_il(sig);
                 let inputs = match mref.kind() {
-                    crate::v2::cilnode::MethodKind::Static => sig.inputs(),
+                    crate::v2::cilnode::MethodKind::Static => inputs_il(sig, 0, asm),
                     crate::v2::cilnode::MethodKind::Instance
                     | crate::v2::cilnode::MethodKind::Virtual
-                    | crate::v2::cilnode::MethodKind::Constructor => &sig.inputs()[1..],
+                    | crate::v2::cilnode::MethodKind::Constructor => inputs_il(sig, 1, asm),
                 };
-                let inputs: String = inputs
-                    .iter()
-                    .map(|tpe| non_void_type_il(tpe, asm))
-                    .intersperse(",".to_owned())
-                    .collect();
                 let name = &asm[mref.name()];
                 let class = class_ref(mref.class(), asm);
 
                 writeln!(
                     out,
-                    "{call_op} {output} {class}::'{name}'({inputs}) //mref:{:?}",
+                    "{call_op} {vararg}{output} {class}::'{name}'({inputs}) //mref:{:?}",
                     call.0
                 )
             }
@@ -1150,16 +1199,8 @@ impl ILExporter {
                 for arg in args {
                     self.export_node(asm, out, *arg, sig, locals)?;
                 }
-                let fn_sig = asm[*fn_sig].clone();
-                let output = type_il(fn_sig.output(), asm);
                 self.export_node(asm, out, *fn_ptr, sig, locals)?;
-                let inputs: String = fn_sig
-                    .inputs()
-                    .iter()
-                    .map(|tpe| non_void_type_il(tpe, asm))
-                    .intersperse(",".to_owned())
-                    .collect();
-                writeln!(out, "calli {output} ({inputs})")
+                writeln!(out, "{}", calli_il(&asm[*fn_sig], asm))
             }
             super::CILRoot::ExitSpecialRegion { target, source } => {
                 if is_handler {
@@ -1218,27 +1259,6 @@ fn assemble_file(exe_out: &Path, il_path: &Path, is_lib: bool) {
 }
 #[cfg(target_os = "windows")]
 fn assemble_file(exe_out: &Path, il_path: &Path, is_lib: bool) {
-    let asm_type = if is_lib { "-dll" } else { "-exe" };
-    let mut cmd = std::process::Command::new(ILASM_PATH.clone());
-    cmd.arg(il_path)
-    .arg(format!("-output:{exe_out}", exe_out = exe_out.to_string_lossy()))
-    .arg("-OPTIMIZE")
-    .arg(asm_type)
-    // .arg("-FOLD") saves up on space, consider enabling.
-    ;
-    if *ILASM_FLAVOUR == IlasmFlavour::Clasic {
-        // Limit the memory usage of mono
-        cmd.env("MONO_GC_PARAMS", "soft-heap-limit=500m");
-    }
-    let out = cmd.output().unwrap();
-    let stdout = String::from_utf8_lossy(&out.stdout);
-    let stderr = String::from_utf8_lossy(&out.stderr);
-    assert!(
-        !(stderr.contains("\nError\n") || stderr.contains("FAILURE") || stdout.contains("FAILURE")),
-        "stdout:{} stderr:{} cmd:{cmd:?}",
-        stdout,
-        String::from_utf8_lossy(&out.stderr)
-    );
     let asm_type = if is_lib { "-dll" } else { "-exe" };
     let mut cmd = std::process::Command::new(ILASM_PATH.clone());
     cmd.arg(il_path)
@@ -1340,6 +1360,92 @@ pub(crate) fn class_ref(cref: ClassRefIdx, asm: &Assembly) -> String {
         format!("{prefix} '{name}{generic_postfix}'{generic_list}")
     }
 }
+/// Returns the instruction calling `mref`, without the arguments of the call.
+fn call_il(mref: MethodRefIdx, asm: &Assembly) -> String {
+    let mref = &asm[mref];
+    let call_op = match mref.kind() {
+        crate::v2::cilnode::MethodKind::Static => "call",
+        crate::v2::cilnode::MethodKind::Instance => "call instance",
+        crate::v2::cilnode::MethodKind::Virtual => " callvirt instance",
+        crate::v2::cilnode::MethodKind::Constructor => "newobj instance",
+    };
+    let sig = &asm[mref.sig()];
+    let output = type_il(sig.output(), asm);
+    let vararg = vararg_il(sig);
+    let inputs = match mref.kind() {
+        crate::v2::cilnode::MethodKind::Static => inputs_il(sig, 0, asm),
+        crate::v2::cilnode::MethodKind::Instance
+        | crate::v2::cilnode::MethodKind::Virtual
+        | crate::v2::cilnode::MethodKind::Constructor => {
+            assert!(
+                !sig.inputs().is_empty(),
+                "invalid argc when calling {} of {}",
+                &asm[mref.name()],
+                class_ref(mref.class(), asm)
+            );
+            inputs_il(sig, 1, asm)
+        }
+    };
+    let generic = if mref.generics().is_empty() {
+        "".to_string()
+    } else {
+        let generic_list: String = mref
+            .generics()
+            .iter()
+            .map(|tpe| type_il(tpe, asm))
+            .intersperse(",".to_owned())
+            .collect();
+        format!("<{generic_list}>")
+    };
+    let name = &asm[mref.name()];
+    let class = class_ref(mref.class(), asm);
+    format!("{call_op} {vararg}{output} {class}::'{name}'{generic}({inputs})")
+}
+/// Returns the `calli` instruction calling a function pointer with the signature `fn_sig`.
+fn calli_il(fn_sig: &FnSig, asm: &Assembly) -> String {
+    let output = type_il(fn_sig.output(), asm);
+    let inputs = inputs_il(fn_sig, 0, asm);
+    format!(
+        "calli {vararg}{output} ({inputs})",
+        vararg = vararg_il(fn_sig)
+    )
+}
+/// Returns the IL of the inputs of `sig`, without the first `skip` of them(e.g. `this`).
+/// The variable arguments passed to a C-variadic function are preceded by a sentinel(`...`).
+fn inputs_il(sig: &FnSig, skip: usize, asm: &Assembly) -> String {
+    let fixed = sig.fixed_inputs()[skip..]
+        .iter()
+        .map(|tpe| non_void_type_il(tpe, asm));
+    let variable = sig
+        .variable_inputs()
+        .iter()
+        .map(|tpe| non_void_type_il(tpe, asm));
+    let sentinel = (!sig.variable_inputs().is_empty()).then(|| "...".to_owned());
+    fixed
+        .chain(sentinel)
+        .chain(variable)
+        .intersperse(",".to_owned())
+        .collect()
+}
+/// Returns the calling convention of a call site, function pointer or method with signature `sig`: `vararg` for C-variadic functions, and the default one otherwise.
+fn vararg_il(sig: &FnSig) -> &'static str {
+    if sig.is_variadic() {
+        "vararg "
+    } else {
+        ""
+    }
+}
+/// Returns the calling convention used by a p/invoke stub.
+fn pinvoke_conv_il(conv: CallConv) -> &'static str {
+    match conv {
+        // .NET only supports the native calling convention of the platform on 64 bit targets, and cdecl maps to it.
+        CallConv::Rust | CallConv::C | CallConv::Win64 | CallConv::SysV64 => "cdecl",
+        CallConv::System => "winapi",
+        CallConv::StdCall => "stdcall",
+        CallConv::FastCall => "fastcall",
+        CallConv::ThisCall => "thiscall",
+    }
+}
 fn non_void_type_il(tpe: &Type, asm: &Assembly) -> String {
     match tpe {
         Type::Void => "valuetype RustVoid".into(),
@@ -1400,14 +1506,10 @@ fn type_il(tpe: &Type, asm: &Assembly) -> String {
         Type::FnPtr(sig) => {
             let sig = asm[*sig].clone();
             format!(
-                "method {output}*({inputs})",
+                "method {vararg}{output}*({inputs})",
+                vararg = vararg_il(&sig),
                 output = type_il(sig.output(), asm),
-                inputs = sig
-                    .inputs()
-                    .iter()
-                    .map(|tpe| non_void_type_il(tpe, asm))
-                    .intersperse(",".to_string())
-                    .collect::<String>(),
+                inputs = inputs_il(&sig, 0, asm),
             )
         }
         Type::PlatformString => "string".into(),
@@ -1453,3 +1555,104 @@ static RUNTIME_CONFIG: std::sync::LazyLock<String> = std::sync::LazyLock::new(||
       }}"
     )
 });
+/// Exports the comparisons of `plan`, with the switched-on value on top of the stack. Falls trough if no case matches.
+fn export_switch_plan(
+    out: &mut impl Write,
+    table: &SwitchTable,
+    plan: &SwitchPlan,
+    block: &str,
+    splits: &mut u32,
+    trampoline: &mut impl FnMut((u32, u32)) -> String,
+) -> std::io::Result<()> {
+    let tpe = table.tpe();
+    match plan {
+        SwitchPlan::Cases(cases) => {
+            for (value, target) in cases {
+                writeln!(out, "dup")?;
+                writeln!(out, "{}", ldc_switch_value(table, *value))?;
+                writeln!(out, "beq {}", trampoline(*target))?;
+            }
+            Ok(())
+        }
+        SwitchPlan::Table { min, targets } => {
+            let labels: Vec<_> = targets.iter().map(|target| trampoline(*target)).collect();
+            writeln!(out, "dup")?;
+            writeln!(out, "{}", ldc_switch_value(table, *min))?;
+            writeln!(out, "sub")?;
+            // `switch` only accepts 32 bit ints, so the range of bigger values must be checked first.
+            if tpe.size().is_none_or(|size| size > 4) {
+                let otherwise = trampoline(table.otherwise());
+                writeln!(out, "dup")?;
+                writeln!(out, "{}", ldc_switch_value(table, targets.len() as u64))?;
+                writeln!(out, "blt.un {block}_in{splits}")?;
+                writeln!(out, "pop br {otherwise}")?;
+                writeln!(out, "{block}_in{splits}: conv.u4")?;
+                *splits += 1;
+            }
+            writeln!(out, "switch({})", labels.join(","))
+        }
+        SwitchPlan::Split { pivot, lt, ge } => {
+            let lt_label = format!("{block}_lt{splits}");
+            *splits += 1;
+            writeln!(out, "dup")?;
+            writeln!(out, "{}", ldc_switch_value(table, *pivot))?;
+            if tpe.is_signed() {
+                writeln!(out, "blt {lt_label}")?;
+            } else {
+                writeln!(out, "blt.un {lt_label}")?;
+            }
+            export_switch_plan(out, table, ge, block, splits, trampoline)?;
+            writeln!(out, "br {}", trampoline(table.otherwise()))?;
+            writeln!(out, "{lt_label}:")?;
+            export_switch_plan(out, table, lt, block, splits, trampoline)
+        }
+    }
+}
+/// Returns the IL loading `value`, a value of the int `table` switches on, in the form it has on the evaluation stack.
+fn ldc_switch_value(table: &SwitchTable, value: u64) -> String {
+    match table.tpe() {
+        Int::I64 | Int::U64 => format!("ldc.i8 {}", value as i64),
+        Int::ISize | Int::USize => format!("ldc.i8 {} conv.i", value as i64),
+        // Smaller ints are extended to 32 bits on the evaluation stack.
+        _ => format!("ldc.i4 {}", table.extend(value) as i32),
+    }
+}
+#[cfg(test)]
+/// Exports `asm` as IL, and returns it as a string.
+pub(crate) fn export_il(asm: &Assembly) -> String {
+    let mut out = Vec::new();
+    ILExporter::new(IlasmFlavour::Modern, true)
+        .export_to_write(asm, &mut out)
+        .unwrap();
+    String::from_utf8(out).unwrap()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::{cilnode::MethodKind, interpreter::add_method, BasicBlock, CILRoot, Const};
+    #[test]
+    fn tail_call() {
+        let mut asm = Assembly::default();
+        let main_module = *asm.main_module();
+        let sig = asm.sig([Type::Int(Int::I32)], Type::Int(Int::I32));
+        let callee = asm.new_methodref(main_module, "callee", sig, MethodKind::Static, vec![]);
+        let arg = asm.alloc_node(Const::I32(7));
+        let call = asm.alloc_node(CILNode::Call(Box::new((callee, [arg].into()))));
+        let root = asm.alloc_root(CILRoot::TailCall(call));
+        add_method(
+            &mut asm,
+            "test",
+            &[],
+            Type::Int(Int::I32),
+            vec![BasicBlock::new(vec![root], 0, None)],
+            vec![],
+        );
+        let il = export_il(&asm);
+        // The arguments are evaluated before the call, and the `tail.` prefix comes right before the call, followed by `ret`.
+        let body = il.split_once("'test'").expect("No test method").1;
+        let tail = body.find("tail. call").expect("No tail call");
+        assert!(body[..tail].contains("ldc.i4"));
+        assert!(body[tail..].starts_with("tail. call int32"));
+        assert!(body[tail..].contains("::'callee'(int32)\nret\n"));
+    }
+}
@@ -0,0 +1,1268 @@
+use std::{io::Write, rc::Rc};
+
+use crate::v2::{
+    basic_block::BlockId,
+    cilnode::{ExtendKind, MethodKind, UnOp},
+    cilroot::CmpKind,
+    Assembly, BasicBlock, BinOp, BranchCond, CILNode, CILRoot, ClassDefIdx, ClassRefIdx, Const,
+    Float, Int, MethodDefIdx, MethodImpl, MethodRefIdx, NodeIdx, RootIdx, StaticFieldIdx,
+    StringIdx, Type,
+};
+use fxhash::FxHashMap;
+
+#[cfg(test)]
+use super::{add_method, static_mref};
+use super::{memory::Memory, native, value::int_size, value::Value, Trap};
+/// Maximal depth of nested calls, before the interpreter reports a stack overflow.
+const MAX_CALL_DEPTH: usize = 4096;
+/// Function pointers are represented as addresses in this range, which is never handed out by the allocator.
+const FN_PTR_BASE: u64 = 0x7F00_0000_0000;
+/// A managed object.
+#[derive(Debug)]
+pub enum Object {
+    String(String),
+    Array(Vec<Value>),
+    /// An instance of a class defined in the interpreted assembly. Its fields are stored in unmanaged memory at `addr`.
+    Instance {
+        class: ClassRefIdx,
+        addr: u64,
+    },
+    Exception {
+        class: String,
+        message: String,
+    },
+    /// An object of an external .NET class, which is opaque to the interpreted code.
+    Opaque(String),
+}
+/// Memory layout of a value type, or of the fields of a class.
+struct Layout {
+    size: u64,
+    align: u64,
+    fields: FxHashMap<StringIdx, u64>,
+}
+/// A method body, prepared for execution.
+struct Body {
+    blocks: Vec<BasicBlock>,
+    locals: Vec<Type>,
+    inputs: Box<[Type]>,
+    index: FxHashMap<BlockId, usize>,
+    name: String,
+}
+#[derive(Clone)]
+enum Callee {
+    Body(Rc<Body>),
+    Alias(MethodRefIdx),
+    /// Implemented by the interpreter itself. This includes externs, .NET methods and missing methods.
+    Native,
+}
+#[derive(Default)]
+struct Frame {
+    args: Vec<(u64, Type)>,
+    locals: Vec<(u64, Type)>,
+    /// Memory allocated using `LocAlloc`, freed on return.
+    allocs: Vec<u64>,
+    /// The exception currently being handled.
+    exception: Option<Value>,
+    /// Last source location, used for error reporting.
+    location: Option<(StringIdx, u32, u16)>,
+}
+/// The kind of region a block is in. Decides where branches go.
+#[derive(Clone, Copy)]
+enum Region<'a> {
+    Plain,
+    Try,
+    Handler(&'a [BasicBlock]),
+}
+/// Returns where a jump to `target` and `sub_target`, from a block in `region`, goes.
+fn jump(region: Region, target: BlockId, sub_target: BlockId) -> Next {
+    match region {
+        _ if sub_target == 0 => Next::Goto(target),
+        Region::Handler(handler) if handler.iter().any(|block| block.block_id() == sub_target) => {
+            Next::GotoHandler(sub_target)
+        }
+        // Any other jump leaves the protected region.
+        Region::Handler(_) | Region::Try | Region::Plain => Next::Goto(sub_target),
+    }
+}
+/// What to do after executing a root.
+enum Next {
+    Continue,
+    Goto(BlockId),
+    GotoHandler(BlockId),
+    Ret(Option<Value>),
+    /// Replaces the current frame with a call to the method with these arguments.
+    TailCall(MethodRefIdx, Vec<Value>),
+}
+/// Executes the methods of an [`Assembly`].
+pub struct Interpreter {
+    pub asm: Assembly,
+    pub mem: Memory,
+    objects: Vec<Object>,
+    strings: FxHashMap<StringIdx, u64>,
+    statics: FxHashMap<StaticFieldIdx, u64>,
+    layouts: FxHashMap<ClassRefIdx, Rc<Layout>>,
+    callees: FxHashMap<MethodRefIdx, Callee>,
+    fn_ptrs: Vec<MethodRefIdx>,
+    fn_ptr_ids: FxHashMap<MethodRefIdx, u64>,
+    /// Command line arguments, including the name of the program.
+    pub argv: Vec<String>,
+    /// If set, everything written to stdout is captured here instead.
+    pub captured_stdout: Option<Vec<u8>>,
+    /// Address of `errno`.
+    pub errno: u64,
+    depth: usize,
+}
+impl Interpreter {
+    pub fn new(asm: Assembly, argv: Vec<String>) -> Self {
+        let mut mem = Memory::default();
+        let errno = mem.alloc(4, 4);
+        Self {
+            asm,
+            mem,
+            objects: vec![],
+            strings: FxHashMap::default(),
+            statics: FxHashMap::default(),
+            layouts: FxHashMap::default(),
+            callees: FxHashMap::default(),
+            fn_ptrs: vec![],
+            fn_ptr_ids: FxHashMap::default(),
+            argv,
+            captured_stdout: None,
+            errno,
+            depth: 0,
+        }
+    }
+    /// Runs the static constructor of the main module, and then the entrypoint. Returns the exit code.
+    pub fn run_entrypoint(&mut self) -> Result<i32, Trap> {
+        let main_module = *self.asm.main_module();
+        let main_module = ClassDefIdx(main_module);
+        if let Some(cctor) = self.find_method(main_module, ".cctor") {
+            self.call(cctor.0, vec![])?;
+        }
+        let entrypoint = self
+            .find_method(main_module, "entrypoint")
+            .ok_or_else(|| Trap::Error("The assembly has no entrypoint".to_owned()))?;
+        let args = if self.asm[self.asm[entrypoint].sig()].inputs().is_empty() {
+            vec![]
+        } else {
+            let args = self
+                .argv
+                .clone()
+                .into_iter()
+                .map(|arg| Value::Obj(self.new_object(Object::String(arg))))
+                .collect();
+            vec![Value::Obj(self.new_object(Object::Array(args)))]
+        };
+        match self.call(entrypoint.0, args) {
+            Ok(_) => Ok(0),
+            Err(Trap::Exit(code)) => Ok(code),
+            Err(err) => Err(err),
+        }
+    }
+    /// Finds a method called `name` in `class`.
+    pub fn find_method(&self, class: ClassDefIdx, name: &str) -> Option<MethodDefIdx> {
+        let name = self.asm.get_prealllocated_string(name)?;
+        self.asm
+            .class_defs()
+            .get(&class)?
+            .methods()
+            .iter()
+            .find(|method| self.asm[**method].name() == name)
+            .copied()
+    }
+    pub fn new_object(&mut self, obj: Object) -> u64 {
+        self.objects.push(obj);
+        self.objects.len() as u64
+    }
+    pub fn object(&self, handle: u64) -> Result<&Object, Trap> {
+        if handle == 0 {
+            return Err(Trap::Error("Null reference".to_owned()));
+        }
+        self.objects
+            .get(handle as usize - 1)
+            .ok_or_else(|| Trap::Error(format!("Invalid object handle {handle}")))
+    }
+    /// Returns the text of a managed string.
+    pub fn string(&self, handle: u64) -> Result<&str, Trap> {
+        match self.object(handle)? {
+            Object::String(string) => Ok(string),
+            other => Err(Trap::Error(format!("Expected a string, got {other:?}"))),
+        }
+    }
+    /// Writes to the standard output or error stream.
+    pub fn write_output(&mut self, fd: i64, bytes: &[u8]) -> Result<(), Trap> {
+        let res = match (fd, &mut self.captured_stdout) {
+            (1, Some(captured)) => {
+                captured.extend_from_slice(bytes);
+                Ok(())
+            }
+            (1, None) => std::io::stdout()
+                .write_all(bytes)
+                .and_then(|()| std::io::stdout().flush()),
+            (2, _) => std::io::stderr().write_all(bytes),
+            _ => return Err(Trap::Error(format!("Write to unsupported fd {fd}"))),
+        };
+        res.map_err(|err| Trap::Error(format!("Could not write output: {err}")))
+    }
+    fn layout(&mut self, class: ClassRefIdx) -> Result<Rc<Layout>, Trap> {
+        if let Some(layout) = self.layouts.get(&class) {
+            return Ok(layout.clone());
+        }
+        let layout = match self.asm.class_ref_to_def(class) {
+            Some(def) => {
+                let def = self.asm[def].clone();
+                let mut fields = FxHashMap::default();
+                let mut size: u64 = 0;
+                let mut align: u64 = 1;
+                for (tpe, name, offset) in def.fields() {
+                    let field_size = self.size_of(*tpe)?;
+                    let field_align = self.align_of(*tpe)?;
+                    align = align.max(field_align);
+                    let offset = match offset {
+                        Some(offset) => u64::from(*offset),
+                        None => size.next_multiple_of(field_align.max(1)),
+                    };
+                    size = size.max(offset + field_size);
+                    fields.insert(*name, offset);
+                }
+                let align = def.align().map_or(align, |align| u64::from(align.get()));
+                let size = def.explict_size().map_or_else(
+                    || size.next_multiple_of(align),
+                    |size| u64::from(size.get()),
+                );
+                Layout {
+                    size,
+                    align,
+                    fields,
+                }
+            }
+            None => {
+                let (size, align) = match &self.asm[self.asm[class].name()] {
+                    "System.Int128" | "System.UInt128" => (16, 16),
+                    "System.Half" => (2, 2),
+                    name => {
+                        return Err(Trap::Error(format!(
+                            "Layout of the external type {name} is not known"
+                        )))
+                    }
+                };
+                Layout {
+                    size,
+                    align,
+                    fields: FxHashMap::default(),
+                }
+            }
+        };
+        let layout = Rc::new(layout);
+        self.layouts.insert(class, layout.clone());
+        Ok(layout)
+    }
+    pub fn size_of(&mut self, tpe: Type) -> Result<u64, Trap> {
+        Ok(match tpe {
+            Type::Int(int) => int_size(int),
+            Type::Float(float) => u64::from(float.size()),
+            Type::Bool => 1,
+            Type::PlatformChar => 2,
+            Type::Void => 0,
+            Type::Ptr(_)
+            | Type::Ref(_)
+            | Type::FnPtr(_)
+            | Type::PlatformString
+            | Type::PlatformObject
+            | Type::PlatformArray { .. } => 8,
+            Type::ClassRef(class) if !self.asm[class].is_valuetype() => 8,
+            Type::ClassRef(class) => self.layout(class)?.size,
+            Type::SIMDVector(vec) => u64::from(vec.bits()) / 8,
+            Type::PlatformGeneric(..) => {
+                return Err(Trap::Error(
+                    "Size of a generic type is not known".to_owned(),
+                ))
+            }
+        })
+    }
+    fn align_of(&mut self, tpe: Type) -> Result<u64, Trap> {
+        Ok(match tpe {
+            Type::ClassRef(class) if self.asm[class].is_valuetype() => self.layout(class)?.align,
+            Type::SIMDVector(vec) => match vec.elem() {
+                crate::v2::tpe::simd::SIMDElem::Int(int) => int_size(int),
+                crate::v2::tpe::simd::SIMDElem::Float(float) => u64::from(float.size()),
+            },
+            _ => self.size_of(tpe)?.max(1),
+        })
+    }
+    /// Loads a value of type `tpe` from `addr`.
+    pub fn load(&mut self, addr: u64, tpe: Type) -> Result<Value, Trap> {
+        let size = self.size_of(tpe)?;
+        let bytes = self.mem.read(addr, size)?;
+        Value::decode(bytes, tpe, &self.asm)
+    }
+    /// Stores `val` as a value of type `tpe` at `addr`.
+    pub fn store(&mut self, addr: u64, tpe: Type, val: &Value) -> Result<(), Trap> {
+        let size = self.size_of(tpe)?;
+        let bytes = val.encode(tpe, size)?;
+        self.mem.write(addr, &bytes)
+    }
+    /// Allocates zeroed memory for a value of type `tpe`.
+    fn alloc_value(&mut self, tpe: Type) -> Result<u64, Trap> {
+        let size = self.size_of(tpe)?;
+        let align = self.align_of(tpe)?;
+        Ok(self.mem.alloc(size, align))
+    }
+    fn static_addr(&mut self, field: StaticFieldIdx) -> Result<u64, Trap> {
+        if let Some(addr) = self.statics.get(&field) {
+            return Ok(*addr);
+        }
+        let addr = self.alloc_value(self.asm[field].tpe())?;
+        self.statics.insert(field, addr);
+        Ok(addr)
+    }
+    /// Computes the address of a field of the object or struct pointed to by `base`.
+    fn field_addr(&mut self, base: &Value, field: crate::v2::FieldIdx) -> Result<u64, Trap> {
+        let desc = self.asm[field];
+        let base = match base {
+            Value::Obj(handle) => match self.object(*handle)? {
+                Object::Instance { addr, .. } => *addr,
+                other => {
+                    return Err(Trap::Error(format!(
+                        "Object {other:?} has no field {}",
+                        &self.asm[desc.name()]
+                    )))
+                }
+            },
+            _ => base.as_addr()?,
+        };
+        Ok(base + self.field_offset(desc.owner(), desc.name())?)
+    }
+    fn field_offset(&mut self, owner: ClassRefIdx, name: StringIdx) -> Result<u64, Trap> {
+        self.layout(owner)?
+            .fields
+            .get(&name)
+            .copied()
+            .ok_or_else(|| {
+                Trap::Error(format!(
+                    "Class {} has no field {}",
+                    &self.asm[self.asm[owner].name()],
+                    &self.asm[name]
+                ))
+            })
+    }
+    /// Returns a function pointer to `mref`.
+    fn fn_ptr(&mut self, mref: MethodRefIdx) -> u64 {
+        if let Some(id) = self.fn_ptr_ids.get(&mref) {
+            return FN_PTR_BASE + id * 16;
+        }
+        let id = self.fn_ptrs.len() as u64;
+        self.fn_ptrs.push(mref);
+        self.fn_ptr_ids.insert(mref, id);
+        FN_PTR_BASE + id * 16
+    }
+    fn fn_ptr_target(&self, ptr: u64) -> Result<MethodRefIdx, Trap> {
+        ptr.checked_sub(FN_PTR_BASE)
+            .filter(|offset| offset.is_multiple_of(16))
+            .and_then(|offset| self.fn_ptrs.get((offset / 16) as usize))
+            .copied()
+            .ok_or_else(|| Trap::Error(format!("Call to invalid function pointer {ptr:#x}")))
+    }
+    fn callee(&mut self, mref: MethodRefIdx) -> Callee {
+        if let Some(callee) = self.callees.get(&mref) {
+            return callee.clone();
+        }
+        let callee = match self.asm.method_ref_to_def(mref).map(|def| &self.asm[def]) {
+            Some(def) => match def.implementation() {
+                MethodImpl::MethodBody { blocks, locals } => {
+                    let index = blocks
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, block)| (block.block_id(), idx))
+                        .collect();
+                    let class = self.asm[def.class()].name();
+                    Callee::Body(Rc::new(Body {
+                        blocks: blocks.clone(),
+                        locals: locals.iter().map(|(_, tpe)| self.asm[*tpe]).collect(),
+                        inputs: self.asm[def.sig()].inputs().into(),
+                        index,
+                        name: format!("{}::{}", &self.asm[class], &self.asm[def.name()]),
+                    }))
+                }
+                MethodImpl::AliasFor(target) => Callee::Alias(*target),
+                MethodImpl::Extern { .. } | MethodImpl::Missing => Callee::Native,
+            },
+            None => Callee::Native,
+        };
+        self.callees.insert(mref, callee.clone());
+        callee
+    }
+    /// Calls the method `mref` with `args`. Calling a constructor creates a new object.
+    pub fn call(&mut self, mref: MethodRefIdx, args: Vec<Value>) -> Result<Option<Value>, Trap> {
+        match self.asm[mref].kind() {
+            MethodKind::Constructor => self.new_obj(mref, args).map(Some),
+            MethodKind::Virtual => {
+                let target = self.dispatch(mref, &args)?;
+                self.invoke(target, args)
+            }
+            MethodKind::Static | MethodKind::Instance => self.invoke(mref, args),
+        }
+    }
+    fn invoke(
+        &mut self,
+        mut mref: MethodRefIdx,
+        mut args: Vec<Value>,
+    ) -> Result<Option<Value>, Trap> {
+        loop {
+            match self.callee(mref) {
+                Callee::Body(body) => match self.run_body(&body, args)? {
+                    // The frame of the caller is already gone, so tail calls don't grow the stack.
+                    Next::TailCall(target, target_args) => {
+                        mref = match self.asm[target].kind() {
+                            MethodKind::Virtual => self.dispatch(target, &target_args)?,
+                            _ => target,
+                        };
+                        args = target_args;
+                    }
+                    Next::Ret(val) => return Ok(val),
+                    Next::Continue | Next::Goto(_) | Next::GotoHandler(_) => {
+                        unreachable!("A method body can only be exited by returning")
+                    }
+                },
+                Callee::Alias(target) => return self.call(target, args),
+                Callee::Native => return native::call(self, mref, &args),
+            }
+        }
+    }
+    /// Finds the override of a virtual method, based on the class of `this`.
+    fn dispatch(&mut self, mref: MethodRefIdx, args: &[Value]) -> Result<MethodRefIdx, Trap> {
+        let Some(Value::Obj(this)) = args.first() else {
+            return Ok(mref);
+        };
+        let Object::Instance { class, .. } = self.object(*this)? else {
+            return Ok(mref);
+        };
+        let (name, sig) = (self.asm[mref].name(), self.asm[mref].sig());
+        let mut class = Some(*class);
+        while let Some(def) = class.and_then(|class| self.asm.class_ref_to_def(class)) {
+            // Explicit overrides(e.g. interface implementations) take precedence.
+            if let Some((_, body)) = self.asm[def]
+                .overrides()
+                .iter()
+                .find(|(decl, _)| *decl == mref)
+            {
+                return Ok(body.0);
+            }
+            let found = self.asm[def].methods().iter().find(|method| {
+                let method = &self.asm[**method];
+                method.name() == name && method.sig() == sig
+            });
+            if let Some(found) = found {
+                return Ok(found.0);
+            }
+            class = self.asm[def].extends();
+        }
+        Ok(mref)
+    }
+    fn new_obj(&mut self, ctor: MethodRefIdx, mut args: Vec<Value>) -> Result<Value, Trap> {
+        let class = self.asm[ctor].class();
+        if self.asm.class_ref_to_def(class).is_none() {
+            return native::new_object(self, ctor, &args);
+        }
+        let layout = self.layout(class)?;
+        let addr = self.mem.alloc(layout.size, layout.align);
+        if self.asm[class].is_valuetype() {
+            args.insert(0, Value::NInt(addr as i64));
+            self.invoke(ctor, args)?;
+            let val = self.load(addr, Type::ClassRef(class));
+            self.mem.free(addr)?;
+            val
+        } else {
+            let this = self.new_object(Object::Instance { class, addr });
+            args.insert(0, Value::Obj(this));
+            self.invoke(ctor, args)?;
+            Ok(Value::Obj(this))
+        }
+    }
+    /// Runs `body`, returning either [`Next::Ret`] or [`Next::TailCall`].
+    fn run_body(&mut self, body: &Body, args: Vec<Value>) -> Result<Next, Trap> {
+        if args.len() != body.inputs.len() {
+            return Err(Trap::Error(format!(
+                "{} expects {} arguments, but got {}",
+                body.name,
+                body.inputs.len(),
+                args.len()
+            )));
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(Trap::Error("Stack overflow".to_owned()));
+        }
+        self.depth += 1;
+        let mut frame = Frame::default();
+        let res = self
+            .init_frame(body, &args, &mut frame)
+            .and_then(|()| self.run_blocks(body, &mut frame));
+        for (addr, _) in frame.args.iter().chain(frame.locals.iter()) {
+            self.mem.free(*addr)?;
+        }
+        for addr in &frame.allocs {
+            self.mem.free(*addr)?;
+        }
+        self.depth -= 1;
+        res.map_err(|err| match err {
+            Trap::Error(msg) => {
+                let location = match frame.location {
+                    Some((file, line, col)) => format!(" ({}:{line}:{col})", &self.asm[file]),
+                    None => String::new(),
+                };
+                Trap::Error(format!("{msg}\n  in {}{location}", body.name))
+            }
+            other => other,
+        })
+    }
+    fn init_frame(&mut self, body: &Body, args: &[Value], frame: &mut Frame) -> Result<(), Trap> {
+        for (arg, tpe) in args.iter().zip(body.inputs.iter()) {
+            let addr = self.alloc_value(*tpe)?;
+            frame.args.push((addr, *tpe));
+            self.store(addr, *tpe, arg)?;
+        }
+        for tpe in &body.locals {
+            let addr = self.alloc_value(*tpe)?;
+            frame.locals.push((addr, *tpe));
+        }
+        Ok(())
+    }
+    fn run_blocks(&mut self, body: &Body, frame: &mut Frame) -> Result<Next, Trap> {
+        let mut idx = 0;
+        loop {
+            let block = body.blocks.get(idx).ok_or_else(|| {
+                Trap::Error("Control flow fell off the end of the method".to_owned())
+            })?;
+            let region = if block.handler().is_some() {
+                Region::Try
+            } else {
+                Region::Plain
+            };
+            let next = match (
+                self.run_roots(block.roots(), frame, region),
+                block.handler(),
+            ) {
+                (Err(Trap::Exception(exception)), Some(handler)) => {
+                    self.run_handler(handler, frame, exception)?
+                }
+                (res, _) => res?,
+            };
+            idx = match next {
+                Next::Continue => idx + 1,
+                next @ (Next::Ret(_) | Next::TailCall(..)) => return Ok(next),
+                Next::Goto(target) => *body
+                    .index
+                    .get(&target)
+                    .ok_or_else(|| Trap::Error(format!("Jump to missing block {target}")))?,
+                Next::GotoHandler(target) => {
+                    return Err(Trap::Error(format!(
+                        "Jump to handler block {target} outside of a handler"
+                    )))
+                }
+            };
+        }
+    }
+    fn run_handler(
+        &mut self,
+        handler: &[BasicBlock],
+        frame: &mut Frame,
+        exception: Value,
+    ) -> Result<Next, Trap> {
+        let prev = frame.exception.replace(exception);
+        let mut idx = 0;
+        let res = loop {
+            let Some(block) = handler.get(idx) else {
+                break Err(Trap::Error(
+                    "Control flow fell off the end of a handler".to_owned(),
+                ));
+            };
+            match self.run_roots(block.roots(), frame, Region::Handler(handler)) {
+                Ok(Next::Continue) => idx += 1,
+                Ok(Next::GotoHandler(target)) => {
+                    match handler.iter().position(|block| block.block_id() == target) {
+                        Some(target) => idx = target,
+                        None => {
+                            break Err(Trap::Error(format!(
+                                "Jump to missing handler block {target}"
+                            )))
+                        }
+                    }
+                }
+                other => break other,
+            }
+        };
+        frame.exception = prev;
+        res
+    }
+    fn run_roots(
+        &mut self,
+        roots: &[RootIdx],
+        frame: &mut Frame,
+        region: Region,
+    ) -> Result<Next, Trap> {
+        for root in roots {
+            match self.exec_root(*root, frame, region)? {
+                Next::Continue => (),
+                next => return Ok(next),
+            }
+        }
+        Ok(Next::Continue)
+    }
+    fn exec_root(
+        &mut self,
+        root: RootIdx,
+        frame: &mut Frame,
+        region: Region,
+    ) -> Result<Next, Trap> {
+        match self.asm[root].clone() {
+            CILRoot::StLoc(loc, val) => {
+                let val = self.eval(val, frame)?;
+                let (addr, tpe) = local(&frame.locals, loc)?;
+                self.store(addr, tpe, &val)?;
+            }
+            CILRoot::StArg(arg, val) => {
+                let val = self.eval(val, frame)?;
+                let (addr, tpe) = local(&frame.args, arg)?;
+                self.store(addr, tpe, &val)?;
+            }
+            CILRoot::Ret(val) => return Ok(Next::Ret(Some(self.eval(val, frame)?))),
+            CILRoot::VoidRet => return Ok(Next::Ret(None)),
+            CILRoot::TailCall(call) => {
+                return match self.asm[call].clone() {
+                    CILNode::Call(info) => {
+                        let (mref, args) = *info;
+                        Ok(Next::TailCall(mref, self.eval_args(&args, frame)?))
+                    }
+                    CILNode::CallI(info) => {
+                        let (ptr, _, args) = *info;
+                        let ptr = self.eval(ptr, frame)?.as_addr()?;
+                        let args = self.eval_args(&args, frame)?;
+                        Ok(Next::TailCall(self.fn_ptr_target(ptr)?, args))
+                    }
+                    other => Err(Trap::Error(format!(
+                        "Tail call of a non-call node {other:?}"
+                    ))),
+                };
+            }
+            CILRoot::Pop(val) => {
+                self.eval(val, frame)?;
+            }
+            CILRoot::Throw(exception) => return Err(Trap::Exception(self.eval(exception, frame)?)),
+            CILRoot::ReThrow => {
+                return Err(Trap::Exception(frame.exception.clone().ok_or_else(
+                    || Trap::Error("Rethrow outside of a handler".to_owned()),
+                )?))
+            }
+            CILRoot::Break | CILRoot::Nop => (),
+            CILRoot::SourceFileInfo {
+                line_start,
+                col_start,
+                file,
+                ..
+            } => frame.location = Some((file, line_start, col_start)),
+            CILRoot::Branch(info) => {
+                let (target, sub_target, cond) = *info;
+                let taken = match cond {
+                    Some(cond) => self.branch_cond(&cond, frame)?,
+                    None => true,
+                };
+                if taken {
+                    return Ok(jump(region, target, sub_target));
+                }
+            }
+            CILRoot::Switch(info) => {
+                let (discr, table) = *info;
+                let value = self.eval(discr, frame)?.as_u64()?;
+                let (target, sub_target) = table.target_of(value);
+                return Ok(jump(region, target, sub_target));
+            }
+            CILRoot::ExitSpecialRegion { target, .. } => match region {
+                Region::Plain => (),
+                Region::Try | Region::Handler(_) => return Ok(Next::Goto(target)),
+            },
+            CILRoot::SetField(info) => {
+                let (field, addr, val) = *info;
+                let addr = self.eval(addr, frame)?;
+                let val = self.eval(val, frame)?;
+                let addr = self.field_addr(&addr, field)?;
+                self.store(addr, self.asm[field].tpe(), &val)?;
+            }
+            CILRoot::Call(info) => {
+                let (mref, args) = *info;
+                let args = self.eval_args(&args, frame)?;
+                self.call(mref, args)?;
+            }
+            CILRoot::CallI(info) => {
+                let (ptr, _, args) = *info;
+                let ptr = self.eval(ptr, frame)?.as_addr()?;
+                let args = self.eval_args(&args, frame)?;
+                let target = self.fn_ptr_target(ptr)?;
+                self.call(target, args)?;
+            }
+            CILRoot::StInd(info) => {
+                let (addr, val, tpe, _) = *info;
+                let addr = self.eval(addr, frame)?.as_addr()?;
+                let val = self.eval(val, frame)?;
+                self.store(addr, tpe, &val)?;
+            }
+            CILRoot::InitBlk(info) => {
+                let (dst, val, count) = *info;
+                let dst = self.eval(dst, frame)?.as_addr()?;
+                let val = self.eval(val, frame)?.as_u64()?;
+                let count = self.eval(count, frame)?.as_u64()?;
+                self.mem.fill(dst, val as u8, count)?;
+            }
+            CILRoot::CpBlk(info) => {
+                let (dst, src, len) = *info;
+                let dst = self.eval(dst, frame)?.as_addr()?;
+                let src = self.eval(src, frame)?.as_addr()?;
+                let len = self.eval(len, frame)?.as_u64()?;
+                self.mem.copy(dst, src, len)?;
+            }
+            CILRoot::CpObj { src, dst, tpe } => {
+                let src = self.eval(src, frame)?.as_addr()?;
+                let dst = self.eval(dst, frame)?.as_addr()?;
+                let size = self.size_of(self.asm[tpe])?;
+                self.mem.copy(dst, src, size)?;
+            }
+            CILRoot::SetStaticField { field, val } => {
+                let val = self.eval(val, frame)?;
+                let addr = self.static_addr(field)?;
+                self.store(addr, self.asm[field].tpe(), &val)?;
+            }
+            CILRoot::Unreachable(msg) => {
+                return Err(Trap::Error(format!(
+                    "Reached unreachable code: {}",
+                    &self.asm[msg]
+                )))
+            }
+        }
+        Ok(Next::Continue)
+    }
+    fn branch_cond(&mut self, cond: &BranchCond, frame: &mut Frame) -> Result<bool, Trap> {
+        let (lhs, rhs, op, kind) = match cond {
+            BranchCond::True(cond) => return self.eval(*cond, frame)?.as_bool(),
+            BranchCond::False(cond) => return Ok(!self.eval(*cond, frame)?.as_bool()?),
+            BranchCond::Eq(lhs, rhs) => (lhs, rhs, BranchOp::Eq, &CmpKind::Ordered),
+            BranchCond::Ne(lhs, rhs) => (lhs, rhs, BranchOp::Ne, &CmpKind::Unordered),
+            BranchCond::Lt(lhs, rhs, kind) => (lhs, rhs, BranchOp::Lt, kind),
+            BranchCond::Gt(lhs, rhs, kind) => (lhs, rhs, BranchOp::Gt, kind),
+            BranchCond::Le(lhs, rhs, kind) => (lhs, rhs, BranchOp::Le, kind),
+            BranchCond::Ge(lhs, rhs, kind) => (lhs, rhs, BranchOp::Ge, kind),
+        };
+        let lhs = self.eval(*lhs, frame)?;
+        let rhs = self.eval(*rhs, frame)?;
+        let unsigned = matches!(kind, CmpKind::Unordered | CmpKind::Unsigned);
+        if let (Value::F32(_) | Value::F64(_), Value::F32(_) | Value::F64(_)) = (&lhs, &rhs) {
+            let (lhs, rhs) = (lhs.as_f64()?, rhs.as_f64()?);
+            let res = match op {
+                BranchOp::Eq => lhs == rhs,
+                BranchOp::Ne => lhs != rhs,
+                BranchOp::Lt => lhs < rhs,
+                BranchOp::Gt => lhs > rhs,
+                BranchOp::Le => lhs <= rhs,
+                BranchOp::Ge => lhs >= rhs,
+            };
+            // The unordered variants are also taken if any operand is NaN.
+            return Ok(res || (unsigned && (lhs.is_nan() || rhs.is_nan())));
+        }
+        let (lt, gt) = if unsigned {
+            (BinOp::LtUn, BinOp::GtUn)
+        } else {
+            (BinOp::Lt, BinOp::Gt)
+        };
+        Ok(match op {
+            BranchOp::Eq => lhs.binop(&rhs, BinOp::Eq)?.as_bool()?,
+            BranchOp::Ne => !lhs.binop(&rhs, BinOp::Eq)?.as_bool()?,
+            BranchOp::Lt => lhs.binop(&rhs, lt)?.as_bool()?,
+            BranchOp::Gt => lhs.binop(&rhs, gt)?.as_bool()?,
+            BranchOp::Le => !lhs.binop(&rhs, gt)?.as_bool()?,
+            BranchOp::Ge => !lhs.binop(&rhs, lt)?.as_bool()?,
+        })
+    }
+    fn eval_args(&mut self, args: &[NodeIdx], frame: &mut Frame) -> Result<Vec<Value>, Trap> {
+        args.iter().map(|arg| self.eval(*arg, frame)).try_collect()
+    }
+    fn eval(&mut self, node: NodeIdx, frame: &mut Frame) -> Result<Value, Trap> {
+        Ok(match self.asm[node].clone() {
+            CILNode::Const(cst) => match Value::from_const(&cst) {
+                Some(val) => val,
+                None => {
+                    let Const::PlatformString(string) = *cst else {
+                        unreachable!()
+                    };
+                    Value::Obj(self.ldstr(string))
+                }
+            },
+            CILNode::BinOp(lhs, rhs, op) => {
+                let lhs = self.eval(lhs, frame)?;
+                let rhs = self.eval(rhs, frame)?;
+                lhs.binop(&rhs, op)?
+            }
+            CILNode::UnOp(val, op) => unop(self.eval(val, frame)?, &op)?,
+            CILNode::LdLoc(loc) => {
+                let (addr, tpe) = local(&frame.locals, loc)?;
+                self.load(addr, tpe)?
+            }
+            CILNode::LdArg(arg) => {
+                let (addr, tpe) = local(&frame.args, arg)?;
+                self.load(addr, tpe)?
+            }
+            CILNode::LdLocA(loc) => Value::NInt(local(&frame.locals, loc)?.0 as i64),
+            CILNode::LdArgA(arg) => Value::NInt(local(&frame.args, arg)?.0 as i64),
+            CILNode::Call(info) => {
+                let (mref, args) = *info;
+                let args = self.eval_args(&args, frame)?;
+                self.call(mref, args)?.ok_or_else(|| {
+                    Trap::Error(format!(
+                        "The void method {} was used as a value",
+                        &self.asm[self.asm[mref].name()]
+                    ))
+                })?
+            }
+            CILNode::CallI(info) => {
+                let (ptr, _, args) = *info;
+                let ptr = self.eval(ptr, frame)?.as_addr()?;
+                let args = self.eval_args(&args, frame)?;
+                let target = self.fn_ptr_target(ptr)?;
+                self.call(target, args)?.ok_or_else(|| {
+                    Trap::Error("A void function pointer was used as a value".to_owned())
+                })?
+            }
+            CILNode::IntCast {
+                input,
+                target,
+                extend,
+            } => int_cast(self.eval(input, frame)?, target, extend)?,
+            CILNode::FloatCast {
+                input,
+                target,
+                is_signed,
+            } => float_cast(self.eval(input, frame)?, target, is_signed)?,
+            CILNode::RefToPtr(val) | CILNode::PtrCast(val, _) => self.eval(val, frame)?,
+            CILNode::LdFieldAdress { addr, field } => {
+                let addr = self.eval(addr, frame)?;
+                Value::NInt(self.field_addr(&addr, field)? as i64)
+            }
+            CILNode::LdField { addr, field } => {
+                let addr = self.eval(addr, frame)?;
+                let desc = self.asm[field];
+                if let Value::Struct(bytes) = addr {
+                    let offset = self.field_offset(desc.owner(), desc.name())? as usize;
+                    let size = self.size_of(desc.tpe())? as usize;
+                    let bytes = bytes.get(offset..offset + size).ok_or_else(|| {
+                        Trap::Error("Field out of bounds of its struct".to_owned())
+                    })?;
+                    Value::decode(bytes, desc.tpe(), &self.asm)?
+                } else {
+                    let addr = self.field_addr(&addr, field)?;
+                    self.load(addr, desc.tpe())?
+                }
+            }
+            CILNode::LdInd { addr, tpe, .. } => {
+                let addr = self.eval(addr, frame)?.as_addr()?;
+                self.load(addr, self.asm[tpe])?
+            }
+            CILNode::SizeOf(tpe) => Value::I32(self.size_of(self.asm[tpe])? as i32),
+            CILNode::GetException => frame
+                .exception
+                .clone()
+                .ok_or_else(|| Trap::Error("GetException used outside of a handler".to_owned()))?,
+            CILNode::IsInst(obj, tpe) => {
+                let obj = self.eval(obj, frame)?.as_obj()?;
+                if obj != 0 && self.is_instance(obj, self.asm[tpe])? {
+                    Value::Obj(obj)
+                } else {
+                    Value::Obj(0)
+                }
+            }
+            CILNode::CheckedCast(obj, tpe) => {
+                let obj = self.eval(obj, frame)?.as_obj()?;
+                if obj != 0 && !self.is_instance(obj, self.asm[tpe])? {
+                    return Err(Trap::Error(format!(
+                        "Invalid cast of {:?} to {}",
+                        self.object(obj)?,
+                        self.asm[tpe].mangle(&self.asm)
+                    )));
+                }
+                Value::Obj(obj)
+            }
+            CILNode::LocAlloc { size } => {
+                let size = self.eval(size, frame)?.as_u64()?;
+                let addr = self.mem.alloc(size, 16);
+                frame.allocs.push(addr);
+                Value::NInt(addr as i64)
+            }
+            CILNode::LocAllocAlgined { tpe, align } => {
+                let size = self.size_of(self.asm[tpe])?;
+                let addr = self.mem.alloc(size, align);
+                frame.allocs.push(addr);
+                Value::NInt(addr as i64)
+            }
+            CILNode::LdStaticField(field) => {
+                let addr = self.static_addr(field)?;
+                self.load(addr, self.asm[field].tpe())?
+            }
+            CILNode::LdStaticFieldAdress(field) => Value::NInt(self.static_addr(field)? as i64),
+            CILNode::LdFtn(mref) => Value::NInt(self.fn_ptr(mref) as i64),
+            CILNode::LdLen(array) => {
+                let array = self.eval(array, frame)?.as_obj()?;
+                match self.object(array)? {
+                    Object::Array(elems) => Value::NInt(elems.len() as i64),
+                    other => return Err(Trap::Error(format!("Expected an array, got {other:?}"))),
+                }
+            }
+            CILNode::LdElelemRef { array, index } => {
+                let array = self.eval(array, frame)?.as_obj()?;
+                let index = self.eval(index, frame)?.as_u64()?;
+                match self.object(array)? {
+                    Object::Array(elems) => {
+                        elems.get(index as usize).cloned().ok_or_else(|| {
+                            Trap::Error(format!(
+                                "Index {index} out of bounds of an array of length {}",
+                                elems.len()
+                            ))
+                        })?
+                    }
+                    other => return Err(Trap::Error(format!("Expected an array, got {other:?}"))),
+                }
+            }
+            CILNode::LdTypeToken(_) | CILNode::UnboxAny { .. } => {
+                return Err(Trap::Error(format!(
+                    "{:?} is not supported by the interpreter",
+                    self.asm[node]
+                )))
+            }
+        })
+    }
+    /// Returns the managed string for the string literal `string`.
+    fn ldstr(&mut self, string: StringIdx) -> u64 {
+        if let Some(handle) = self.strings.get(&string) {
+            return *handle;
+        }
+        let handle = self.new_object(Object::String(self.asm[string].to_owned()));
+        self.strings.insert(string, handle);
+        handle
+    }
+    fn is_instance(&self, obj: u64, tpe: Type) -> Result<bool, Trap> {
+        Ok(match (self.object(obj)?, tpe) {
+            (_, Type::PlatformObject) => true,
+            (Object::String(_), Type::PlatformString) => true,
+            (Object::Array(_), Type::PlatformArray { .. }) => true,
+            (Object::Instance { class, .. }, Type::ClassRef(target)) => {
+                let mut class = Some(*class);
+                while let Some(current) = class {
+                    if current == target {
+                        return Ok(true);
+                    }
+                    let def = self.asm.class_ref_to_def(current);
+                    if def.is_some_and(|def| self.asm[def].implements().contains(&target)) {
+                        return Ok(true);
+                    }
+                    class = def.and_then(|def| self.asm[def].extends());
+                }
+                false
+            }
+            (Object::Exception { class, .. }, Type::ClassRef(target)) => {
+                let target = &self.asm[self.asm[target].name()];
+                target == class || target == "System.Exception"
+            }
+            (Object::Opaque(class), Type::ClassRef(target)) => {
+                &self.asm[self.asm[target].name()] == class
+            }
+            _ => false,
+        })
+    }
+}
+#[derive(Clone, Copy)]
+enum BranchOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+fn local(slots: &[(u64, Type)], idx: u32) -> Result<(u64, Type), Trap> {
+    slots
+        .get(idx as usize)
+        .copied()
+        .ok_or_else(|| Trap::Error(format!("Access to missing local or argument {idx}")))
+}
+fn unop(val: Value, op: &UnOp) -> Result<Value, Trap> {
+    Ok(match (val, op) {
+        (Value::I32(val), UnOp::Not) => Value::I32(!val),
+        (Value::I64(val), UnOp::Not) => Value::I64(!val),
+        (Value::NInt(val), UnOp::Not) => Value::NInt(!val),
+        (Value::I128(val), UnOp::Not) => Value::I128(!val),
+        (Value::U128(val), UnOp::Not) => Value::U128(!val),
+        (Value::I32(val), UnOp::Neg) => Value::I32(val.wrapping_neg()),
+        (Value::I64(val), UnOp::Neg) => Value::I64(val.wrapping_neg()),
+        (Value::NInt(val), UnOp::Neg) => Value::NInt(val.wrapping_neg()),
+        (Value::I128(val), UnOp::Neg) => Value::I128(val.wrapping_neg()),
+        (Value::U128(val), UnOp::Neg) => Value::U128(val.wrapping_neg()),
+        (Value::F32(val), UnOp::Neg) => Value::F32(-val),
+        (Value::F64(val), UnOp::Neg) => Value::F64(-val),
+        (val, op) => return Err(Trap::Error(format!("Invalid operand of {op:?}: {val:?}"))),
+    })
+}
+/// Converts `val` to the integer type `target`. Integer inputs are first extended to 128 bits, as specified by `extend`.
+pub fn int_cast(val: Value, target: Int, extend: ExtendKind) -> Result<Value, Trap> {
+    let signed = extend == ExtendKind::SignExtend;
+    let bits = match val {
+        Value::F32(_) | Value::F64(_) => {
+            let val = val.as_f64()?;
+            if target.is_signed() {
+                val as i128 as u128
+            } else {
+                val as u128
+            }
+        }
+        Value::I32(val) if !signed => u128::from(val as u32),
+        Value::I64(val) | Value::NInt(val) if !signed => u128::from(val as u64),
+        Value::I32(_) | Value::I64(_) | Value::NInt(_) | Value::I128(_) | Value::U128(_) => {
+            val.bits()
+        }
+        Value::Obj(_) | Value::Struct(_) => {
+            return Err(Trap::Error(format!("Can't cast {val:?} to an integer")))
+        }
+    };
+    Ok(Value::from_int_bits(bits, target))
+}
+/// Converts `val` to the float type `target`. Integer inputs are treated as signed if `is_signed` is set.
+fn float_cast(val: Value, target: Float, is_signed: bool) -> Result<Value, Trap> {
+    macro_rules! convert {
+        ($float:ty) => {
+            match val {
+                Value::F32(val) => val as $float,
+                Value::F64(val) => val as $float,
+                Value::I32(val) if is_signed => val as $float,
+                Value::I32(val) => val as u32 as $float,
+                Value::I64(val) | Value::NInt(val) if is_signed => val as $float,
+                Value::I64(val) | Value::NInt(val) => val as u64 as $float,
+                Value::I128(val) if is_signed => val as $float,
+                Value::I128(val) => val as u128 as $float,
+                Value::U128(val) if is_signed => val as i128 as $float,
+                Value::U128(val) => val as $float,
+                Value::Obj(_) | Value::Struct(_) => {
+                    return Err(Trap::Error(format!("Can't cast {val:?} to a float")))
+                }
+            }
+        };
+    }
+    Ok(match target {
+        Float::F32 => Value::F32(convert!(f32)),
+        Float::F64 => Value::F64(convert!(f64)),
+        Float::F16 | Float::F128 => {
+            return Err(Trap::Error(format!(
+                "Casts to {target:?} are not supported by the interpreter"
+            )))
+        }
+    })
+}
+#[test]
+fn loop_sum() {
+    let mut asm = Assembly::default();
+    let (i, sum) = (
+        asm.alloc_node(CILNode::LdLoc(0)),
+        asm.alloc_node(CILNode::LdLoc(1)),
+    );
+    let (zero, one, ten) = (
+        asm.alloc_node(Const::I32(0)),
+        asm.alloc_node(Const::I32(1)),
+        asm.alloc_node(Const::I32(10)),
+    );
+    let init = vec![
+        asm.alloc_root(CILRoot::StLoc(0, one)),
+        asm.alloc_root(CILRoot::StLoc(1, zero)),
+        asm.alloc_root(CILRoot::Branch(Box::new((1, 0, None)))),
+    ];
+    let add = asm.alloc_node(CILNode::BinOp(sum, i, BinOp::Add));
+    let inc = asm.alloc_node(CILNode::BinOp(i, one, BinOp::Add));
+    let cond = BranchCond::Le(i, ten, CmpKind::Signed);
+    let body = vec![
+        asm.alloc_root(CILRoot::StLoc(1, add)),
+        asm.alloc_root(CILRoot::StLoc(0, inc)),
+        asm.alloc_root(CILRoot::Branch(Box::new((1, 0, Some(cond))))),
+        asm.alloc_root(CILRoot::Ret(sum)),
+    ];
+    let blocks = vec![
+        BasicBlock::new(init, 0, None),
+        BasicBlock::new(body, 1, None),
+    ];
+    let int = Type::Int(Int::I32);
+    let mref = add_method(&mut asm, "sum", &[], int, blocks, vec![int, int]);
+    let mut interp = Interpreter::new(asm, vec![]);
+    assert_eq!(interp.call(mref, vec![]).unwrap(), Some(Value::I32(55)));
+    // All locals got freed on return.
+    assert_eq!(interp.mem.live_allocs(), 1);
+}
+#[test]
+fn unmanaged_memory() {
+    let mut asm = Assembly::default();
+    let marshal = crate::v2::ClassRef::marshal(&mut asm);
+    let marshal = asm[marshal].clone();
+    let isize = Type::Int(Int::ISize);
+    let alloc = asm.alloc_string("AllocHGlobal");
+    let alloc = marshal.static_mref(&[isize], isize, alloc, &mut asm);
+    let free = asm.alloc_string("FreeHGlobal");
+    let free = marshal.static_mref(&[isize], Type::Void, free, &mut asm);
+    let size = asm.alloc_node(Const::ISize(8));
+    let ptr = asm.alloc_node(CILNode::Call(Box::new((alloc, [size].into()))));
+    let local = asm.alloc_node(CILNode::LdLoc(0));
+    let val = asm.alloc_node(Const::U16(0xBEEF));
+    let offset = asm.alloc_node(Const::ISize(6));
+    let addr = asm.alloc_node(CILNode::BinOp(local, offset, BinOp::Add));
+    let u16_tpe = asm.alloc_type(Type::Int(Int::U16));
+    let load = asm.alloc_node(CILNode::LdInd {
+        addr,
+        tpe: u16_tpe,
+        volatile: false,
+    });
+    let loaded = asm.alloc_node(CILNode::LdLoc(1));
+    let roots = vec![
+        asm.alloc_root(CILRoot::StLoc(0, ptr)),
+        asm.alloc_root(CILRoot::StInd(Box::new((
+            addr,
+            val,
+            Type::Int(Int::U16),
+            false,
+        )))),
+        asm.alloc_root(CILRoot::StLoc(1, load)),
+        asm.alloc_root(CILRoot::Call(Box::new((free, [local].into())))),
+        asm.alloc_root(CILRoot::Ret(loaded)),
+    ];
+    let blocks = vec![BasicBlock::new(roots, 0, None)];
+    let int = Type::Int(Int::I32);
+    let ok = add_method(&mut asm, "ok", &[], int, blocks, vec![isize, int]);
+    // Storing a 32 bit value at offset 6 of an 8 byte allocation is out of bounds.
+    let roots = vec![
+        asm.alloc_root(CILRoot::StLoc(0, ptr)),
+        asm.alloc_root(CILRoot::StInd(Box::new((addr, offset, int, false)))),
+        asm.alloc_root(CILRoot::VoidRet),
+    ];
+    let blocks = vec![BasicBlock::new(roots, 0, None)];
+    let oob = add_method(&mut asm, "oob", &[], Type::Void, blocks, vec![isize]);
+    let mut interp = Interpreter::new(asm, vec![]);
+    assert_eq!(interp.call(ok, vec![]).unwrap(), Some(Value::I32(0xBEEF)));
+    match interp.call(oob, vec![]) {
+        Err(Trap::Error(msg)) => assert!(msg.contains("Out of bounds"), "{msg}"),
+        other => panic!("Expected an out of bounds error, got {other:?}"),
+    }
+}
+#[test]
+fn catch_exception() {
+    let mut asm = Assembly::default();
+    let exception = crate::v2::ClassRef::exception(&mut asm);
+    let ctor = asm[exception]
+        .clone()
+        .ctor(&[Type::PlatformString], &mut asm);
+    let msg = asm.ldstr("boom");
+    let msg = asm.alloc_node(msg);
+    let exception = asm.alloc_node(CILNode::Call(Box::new((ctor, [msg].into()))));
+    let throw = vec![asm.alloc_root(CILRoot::Throw(exception))];
+    let get_exception = asm.alloc_node(CILNode::GetException);
+    let handler = vec![
+        asm.alloc_root(CILRoot::Pop(get_exception)),
+        asm.alloc_root(CILRoot::Branch(Box::new((0, 3, None)))),
+    ];
+    let one = asm.alloc_node(Const::I32(1));
+    let ret = vec![asm.alloc_root(CILRoot::Ret(one))];
+    let blocks = vec![
+        BasicBlock::new(
+            throw.clone(),
+            0,
+            Some(vec![BasicBlock::new(handler, 2, None)]),
+        ),
+        BasicBlock::new(ret, 3, None),
+    ];
+    let caught = add_method(&mut asm, "caught", &[], Type::Int(Int::I32), blocks, vec![]);
+    let blocks = vec![BasicBlock::new(throw, 0, None)];
+    let uncaught = add_method(&mut asm, "uncaught", &[], Type::Void, blocks, vec![]);
+    let mut interp = Interpreter::new(asm, vec![]);
+    assert_eq!(interp.call(caught, vec![]).unwrap(), Some(Value::I32(1)));
+    let Err(Trap::Exception(Value::Obj(handle))) = interp.call(uncaught, vec![]) else {
+        panic!("Expected an exception");
+    };
+    assert!(matches!(
+        interp.object(handle).unwrap(),
+        Object::Exception { message, .. } if message == "boom"
+    ));
+}
+#[test]
+fn entrypoint() {
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let int = Type::Int(Int::I32);
+    let counter = asm.add_static(int, "counter", false, main_module);
+    let console = crate::v2::ClassRef::console(&mut asm);
+    let console = asm[console].clone();
+    let write_line = asm.alloc_string("WriteLine");
+    let write_line = console.static_mref(&[Type::PlatformString], Type::Void, write_line, &mut asm);
+    let env = crate::v2::ClassRef::enviroment(&mut asm);
+    let env = asm[env].clone();
+    let exit = asm.alloc_string("Exit");
+    let exit = env.static_mref(&[int], Type::Void, exit, &mut asm);
+    let msg = asm.ldstr("Hello");
+    let msg = asm.alloc_node(msg);
+    let val = asm.alloc_node(Const::I32(41));
+    let one = asm.alloc_node(Const::I32(1));
+    let counter_val = asm.alloc_node(CILNode::LdStaticField(counter));
+    let code = asm.alloc_node(CILNode::BinOp(counter_val, one, BinOp::Add));
+    let roots = vec![
+        asm.alloc_root(CILRoot::SetStaticField {
+            field: counter,
+            val,
+        }),
+        asm.alloc_root(CILRoot::Call(Box::new((write_line, [msg].into())))),
+        asm.alloc_root(CILRoot::Call(Box::new((exit, [code].into())))),
+        asm.alloc_root(CILRoot::VoidRet),
+    ];
+    add_method(
+        &mut asm,
+        "entrypoint",
+        &[],
+        Type::Void,
+        vec![BasicBlock::new(roots, 0, None)],
+        vec![],
+    );
+    let mut interp = Interpreter::new(asm, vec!["test".into()]);
+    interp.captured_stdout = Some(vec![]);
+    assert_eq!(interp.run_entrypoint().unwrap(), 42);
+    assert_eq!(interp.captured_stdout.unwrap(), b"Hello\n");
+}
+#[test]
+fn tail_calls() {
+    let mut asm = Assembly::default();
+    let int = Type::Int(Int::I32);
+    let countdown = static_mref(&mut asm, "countdown", &[int], int);
+    let (arg, zero, one) = (
+        asm.alloc_node(CILNode::LdArg(0)),
+        asm.alloc_node(Const::I32(0)),
+        asm.alloc_node(Const::I32(1)),
+    );
+    let dec = asm.alloc_node(CILNode::BinOp(arg, one, BinOp::Sub));
+    let call = asm.alloc_node(CILNode::Call(Box::new((countdown, [dec].into()))));
+    let roots = vec![
+        asm.alloc_root(CILRoot::Branch(Box::new((
+            1,
+            0,
+            Some(BranchCond::Eq(arg, zero)),
+        )))),
+        asm.alloc_root(CILRoot::TailCall(call)),
+    ];
+    let blocks = vec![
+        BasicBlock::new(roots, 0, None),
+        BasicBlock::new(vec![asm.alloc_root(CILRoot::Ret(arg))], 1, None),
+    ];
+    assert_eq!(
+        add_method(&mut asm, "countdown", &[int], int, blocks, vec![]),
+        countdown
+    );
+    let mut interp = Interpreter::new(asm, vec![]);
+    // Much deeper than `MAX_CALL_DEPTH`, which is only possible if tail calls reuse the frame.
+    assert_eq!(
+        interp.call(countdown, vec![Value::I32(100_000)]).unwrap(),
+        Some(Value::I32(0))
+    );
+}
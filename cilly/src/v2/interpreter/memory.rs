@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use super::Trap;
+/// The first address handed out by the allocator. Keeps small integers from being valid pointers.
+const BASE_ADDR: u64 = 0x1_0000;
+/// Space left between allocations, so that small out-of-bounds accesses are detected.
+const RED_ZONE: u64 = 0x40;
+/// Flat, byte-addressable model of unmanaged memory.
+///
+/// Every allocation is tracked separately, and every access is checked against the bounds of the allocation it
+/// starts in. This catches out-of-bounds accesses, use-after-free and double frees, which would silently corrupt
+/// memory when running under the real runtime.
+#[derive(Default)]
+pub struct Memory {
+    allocs: BTreeMap<u64, Vec<u8>>,
+    next: u64,
+}
+impl Memory {
+    /// Allocates `size` zeroed bytes, aligned to `align`.
+    pub fn alloc(&mut self, size: u64, align: u64) -> u64 {
+        let align = align.max(16);
+        let next = self.next.max(BASE_ADDR);
+        let addr = next.next_multiple_of(align);
+        self.next = addr + size.max(1) + RED_ZONE;
+        self.allocs.insert(addr, vec![0; size as usize]);
+        addr
+    }
+    /// Frees the allocation starting at `addr`. Freeing a null pointer is a no-op.
+    pub fn free(&mut self, addr: u64) -> Result<(), Trap> {
+        if addr == 0 {
+            return Ok(());
+        }
+        self.allocs
+            .remove(&addr)
+            .map(|_| ())
+            .ok_or_else(|| Trap::Error(format!("Invalid free of {addr:#x}")))
+    }
+    /// Resizes the allocation at `addr`, moving it to a new address.
+    pub fn realloc(&mut self, addr: u64, size: u64, align: u64) -> Result<u64, Trap> {
+        if addr == 0 {
+            return Ok(self.alloc(size, align));
+        }
+        let old = self
+            .allocs
+            .remove(&addr)
+            .ok_or_else(|| Trap::Error(format!("Invalid realloc of {addr:#x}")))?;
+        let new = self.alloc(size, align);
+        let len = old.len().min(size as usize);
+        self.allocs.get_mut(&new).unwrap()[..len].copy_from_slice(&old[..len]);
+        Ok(new)
+    }
+    /// Finds the allocation containing `addr..addr+len`, returning its base and the allocated bytes.
+    fn find(&self, addr: u64, len: u64) -> Result<(u64, &Vec<u8>), Trap> {
+        if addr == 0 {
+            return Err(Trap::Error(format!(
+                "Null pointer dereference ({len} bytes)"
+            )));
+        }
+        let (base, data) = self
+            .allocs
+            .range(..=addr)
+            .next_back()
+            .ok_or_else(|| Trap::Error(format!("Access to unallocated memory at {addr:#x}")))?;
+        // A wild pointer near `u64::MAX` must be reported as an invalid access, not overflow.
+        if addr
+            .checked_add(len)
+            .is_none_or(|end| end > base + data.len() as u64)
+        {
+            return Err(Trap::Error(format!(
+                "Out of bounds access: {len} bytes at {addr:#x}, but the allocation at {base:#x} is only {} bytes long",
+                data.len()
+            )));
+        }
+        Ok((*base, data))
+    }
+    /// Reads `len` bytes starting at `addr`.
+    pub fn read(&self, addr: u64, len: u64) -> Result<&[u8], Trap> {
+        let (base, data) = self.find(addr, len)?;
+        let start = (addr - base) as usize;
+        Ok(&data[start..start + len as usize])
+    }
+    /// Returns a mutable view of `len` bytes starting at `addr`.
+    pub fn slice_mut(&mut self, addr: u64, len: u64) -> Result<&mut [u8], Trap> {
+        let (base, _) = self.find(addr, len)?;
+        let data = self.allocs.get_mut(&base).unwrap();
+        let start = (addr - base) as usize;
+        Ok(&mut data[start..start + len as usize])
+    }
+    /// Writes `bytes` at `addr`.
+    pub fn write(&mut self, addr: u64, bytes: &[u8]) -> Result<(), Trap> {
+        self.slice_mut(addr, bytes.len() as u64)?
+            .copy_from_slice(bytes);
+        Ok(())
+    }
+    /// Sets `len` bytes at `addr` to `val`.
+    pub fn fill(&mut self, addr: u64, val: u8, len: u64) -> Result<(), Trap> {
+        if len == 0 {
+            return Ok(());
+        }
+        self.slice_mut(addr, len)?.fill(val);
+        Ok(())
+    }
+    /// Copies `len` bytes from `src` to `dst`. The regions may overlap.
+    pub fn copy(&mut self, dst: u64, src: u64, len: u64) -> Result<(), Trap> {
+        if len == 0 {
+            return Ok(());
+        }
+        let tmp = self.read(src, len)?.to_vec();
+        self.write(dst, &tmp)
+    }
+    /// Reads a nul-terminated string starting at `addr`, without the terminator.
+    pub fn read_cstr(&self, addr: u64) -> Result<Vec<u8>, Trap> {
+        let (base, data) = self.find(addr, 1)?;
+        let start = (addr - base) as usize;
+        let len = data[start..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| Trap::Error(format!("Unterminated C string at {addr:#x}")))?;
+        Ok(data[start..start + len].to_vec())
+    }
+    /// Number of live allocations.
+    #[cfg(test)]
+    pub fn live_allocs(&self) -> usize {
+        self.allocs.len()
+    }
+}
+#[test]
+fn wild_pointer() {
+    let mut memory = Memory::default();
+    memory.alloc(16, 8);
+    assert!(matches!(memory.read(u64::MAX - 4, 8), Err(Trap::Error(_))));
+    assert!(matches!(memory.slice_mut(u64::MAX, 1), Err(Trap::Error(_))));
+}
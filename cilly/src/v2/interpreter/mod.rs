@@ -0,0 +1,107 @@
+//! Interprets `cilly` assemblies, without needing a .NET runtime.
+//!
+//! The interpreter models unmanaged memory precisely, and reports out-of-bounds accesses, use-after-free and other
+//! kinds of UB as errors, together with the method and source location they happened in.
+use fxhash::{FxHashMap, FxHashSet};
+
+use super::{asm::MissingMethodPatcher, builtins, Assembly};
+#[cfg(test)]
+use super::{cilnode::MethodKind, Access, BasicBlock, MethodDef, MethodImpl, MethodRefIdx, Type};
+
+mod exec;
+mod memory;
+mod native;
+mod value;
+
+pub use exec::{Interpreter, Object};
+pub use value::Value;
+/// Reasons for the execution of a method to stop early.
+#[derive(Debug)]
+pub enum Trap {
+    /// A managed exception was thrown, and may still be caught.
+    Exception(Value),
+    /// The program asked to exit with this exit code.
+    Exit(i32),
+    /// The interpreted program did something invalid (hit UB), or used an unsupported feature.
+    Error(String),
+}
+/// Provides implementations of missing methods, using the same builtins as the linker.
+///
+/// 128 bit integer and `f16` operations are implemented directly, since the interpreter supports them natively.
+pub fn insert_builtins(asm: &mut Assembly) {
+    let mut overrides: MissingMethodPatcher = FxHashMap::default();
+    builtins::select::generate_int_selects(asm, &mut overrides);
+    builtins::insert_swap_at_generic(asm, &mut overrides);
+    builtins::insert_bounds_check(asm, &mut overrides);
+    builtins::casts::insert_casts(asm, &mut overrides);
+    builtins::insert_heap(asm, &mut overrides, false);
+    builtins::int128::generate_int128_ops(asm, &mut overrides, true);
+    builtins::int128::i128_mul_ovf_check(asm, &mut overrides);
+    builtins::f16::generate_f16_ops(asm, &mut overrides, true);
+    builtins::f128::generate_f128_ops(asm, &mut overrides, false);
+    builtins::atomics::generate_all_atomics(asm, &mut overrides);
+    builtins::stack_addr(asm, &mut overrides);
+    builtins::transmute(asm, &mut overrides);
+    builtins::create_slice(asm, &mut overrides);
+    builtins::math::math(asm, &mut overrides);
+    builtins::simd::simd(asm, &mut overrides, true);
+    builtins::insert_thread_dtors(asm, &mut overrides);
+    builtins::dotnet_sys::insert_dotnet_sys(asm, &mut overrides);
+    // Externs are handled by the interpreter itself, so none are declared here.
+    asm.patch_missing_methods(&FxHashMap::default(), &FxHashSet::default(), &overrides);
+}
+/// Defines the static method `name` in the main module, with the body `blocks`.
+#[cfg(test)]
+pub(crate) fn add_method(
+    asm: &mut Assembly,
+    name: &str,
+    inputs: &[Type],
+    output: Type,
+    blocks: Vec<BasicBlock>,
+    locals: Vec<Type>,
+) -> MethodRefIdx {
+    let main_module = asm.main_module();
+    let name = asm.alloc_string(name);
+    let sig = asm.sig(inputs.to_vec(), output);
+    let locals = locals
+        .into_iter()
+        .map(|tpe| (None, asm.alloc_type(tpe)))
+        .collect();
+    asm.new_method(MethodDef::new(
+        Access::Public,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody { blocks, locals },
+        vec![None; inputs.len()],
+    ))
+    .0
+}
+/// References the static method `name` of the main module, like a builtin which gets patched in later.
+#[cfg(test)]
+pub(crate) fn static_mref(
+    asm: &mut Assembly,
+    name: &str,
+    inputs: &[Type],
+    output: Type,
+) -> MethodRefIdx {
+    let main_module = *asm.main_module();
+    let main_module = asm[main_module].clone();
+    let name = asm.alloc_string(name);
+    main_module.static_mref(inputs, output, name, asm)
+}
+/// Replaces the missing methods of `asm` with `overrides`.
+#[cfg(test)]
+pub(crate) fn patch(asm: &mut Assembly, overrides: &MissingMethodPatcher) {
+    asm.patch_missing_methods(&FxHashMap::default(), &FxHashSet::default(), overrides);
+}
+/// Calls the builtin `name`, patched in by `insert_builtins`.
+#[cfg(test)]
+pub(crate) fn call_builtin(name: &str, inputs: &[Type], output: Type, args: Vec<Value>) -> Value {
+    let mut asm = Assembly::default();
+    let mref = static_mref(&mut asm, name, inputs, output);
+    insert_builtins(&mut asm);
+    let mut interp = Interpreter::new(asm, vec![]);
+    interp.call(mref, args).unwrap().unwrap()
+}
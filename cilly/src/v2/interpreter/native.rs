@@ -0,0 +1,710 @@
+//! Implementations of methods which have no CIL body: externs, missing methods and methods of .NET classes.
+use crate::v2::{cilnode::ExtendKind, BinOp, Float, Int, MethodRefIdx, Type};
+
+use super::{
+    exec::{int_cast, Interpreter, Object},
+    value::{int_size, Value},
+    Trap,
+};
+/// Calls `mref`, which has no CIL implementation.
+pub fn call(
+    interp: &mut Interpreter,
+    mref: MethodRefIdx,
+    args: &[Value],
+) -> Result<Option<Value>, Trap> {
+    let method = interp.asm[mref].clone();
+    let class = interp.asm[method.class()].clone();
+    let name = interp.asm[method.name()].to_owned();
+    let sig = interp.asm[method.sig()].clone();
+    let output = *sig.output();
+    if class.asm().is_none() {
+        // Defined in this assembly, so this is either an extern or a missing method.
+        return call_extern(interp, &name, output, args);
+    }
+    let class_name = interp.asm[class.name()].to_owned();
+    call_dotnet(interp, &class_name, &name, sig.inputs(), output, args)
+}
+/// Creates a new object of an external .NET class, using the constructor `ctor`.
+pub fn new_object(
+    interp: &mut Interpreter,
+    ctor: MethodRefIdx,
+    args: &[Value],
+) -> Result<Value, Trap> {
+    let class = interp.asm[ctor].class();
+    let class_name = interp.asm[interp.asm[class].name()].to_owned();
+    match class_name.as_str() {
+        "System.Int128" | "System.UInt128" => {
+            let upper = u128::from(arg(args, 0)?.as_u64()?);
+            let lower = u128::from(arg(args, 1)?.as_u64()?);
+            let val = (upper << 64) | lower;
+            Ok(if class_name == "System.Int128" {
+                Value::I128(val as i128)
+            } else {
+                Value::U128(val)
+            })
+        }
+        "System.Object" => Ok(Value::Obj(interp.new_object(Object::Opaque(class_name)))),
+        _ if class_name.ends_with("Exception") => {
+            let message = match args.first() {
+                Some(Value::Obj(msg)) if *msg != 0 => interp.string(*msg)?.to_owned(),
+                _ => String::new(),
+            };
+            Ok(Value::Obj(interp.new_object(Object::Exception {
+                class: class_name,
+                message,
+            })))
+        }
+        _ => Err(Trap::Error(format!(
+            "Construction of {class_name} is not supported by the interpreter"
+        ))),
+    }
+}
+fn arg(args: &[Value], idx: usize) -> Result<&Value, Trap> {
+    args.get(idx)
+        .ok_or_else(|| Trap::Error(format!("Missing argument {idx}")))
+}
+fn u64_arg(args: &[Value], idx: usize) -> Result<u64, Trap> {
+    arg(args, idx)?.as_u64()
+}
+fn f64_arg(args: &[Value], idx: usize) -> Result<f64, Trap> {
+    arg(args, idx)?.as_f64()
+}
+/// Converts the integer `bits` into a return value of type `output`.
+fn ret_int(output: Type, bits: i128) -> Option<Value> {
+    match output {
+        Type::Void => None,
+        Type::Int(int) => Some(Value::from_int_bits(bits as u128, int)),
+        Type::Float(Float::F32) => Some(Value::F32(bits as f32)),
+        Type::Float(_) => Some(Value::F64(bits as f64)),
+        _ => Some(Value::NInt(bits as i64)),
+    }
+}
+/// Converts the float `val` into a return value of type `output`.
+fn ret_float(output: Type, val: f64) -> Option<Value> {
+    match output {
+        Type::Float(Float::F32) => Some(Value::F32(val as f32)),
+        _ => Some(Value::F64(val)),
+    }
+}
+fn call_extern(
+    interp: &mut Interpreter,
+    name: &str,
+    output: Type,
+    args: &[Value],
+) -> Result<Option<Value>, Trap> {
+    let int = |bits: i128| Ok(ret_int(output, bits));
+    match name {
+        "malloc" => int(interp.mem.alloc(u64_arg(args, 0)?, 16).into()),
+        "calloc" => {
+            let size = u64_arg(args, 0)?.saturating_mul(u64_arg(args, 1)?);
+            int(interp.mem.alloc(size, 16).into())
+        }
+        "aligned_alloc" | "_mm_malloc" => {
+            let (align, size) = if name == "_mm_malloc" {
+                (u64_arg(args, 1)?, u64_arg(args, 0)?)
+            } else {
+                (u64_arg(args, 0)?, u64_arg(args, 1)?)
+            };
+            int(interp.mem.alloc(size, align).into())
+        }
+        "posix_memalign" => {
+            let ptr = interp.mem.alloc(u64_arg(args, 2)?, u64_arg(args, 1)?);
+            interp.mem.write(u64_arg(args, 0)?, &ptr.to_le_bytes())?;
+            int(0)
+        }
+        "realloc" => {
+            let ptr = interp
+                .mem
+                .realloc(u64_arg(args, 0)?, u64_arg(args, 1)?, 16)?;
+            int(ptr.into())
+        }
+        "free" | "_mm_free" => {
+            interp.mem.free(u64_arg(args, 0)?)?;
+            int(0)
+        }
+        "memcpy" | "memmove" => {
+            let dst = u64_arg(args, 0)?;
+            interp.mem.copy(dst, u64_arg(args, 1)?, u64_arg(args, 2)?)?;
+            int(dst.into())
+        }
+        "memset" => {
+            let dst = u64_arg(args, 0)?;
+            interp
+                .mem
+                .fill(dst, u64_arg(args, 1)? as u8, u64_arg(args, 2)?)?;
+            int(dst.into())
+        }
+        "memcmp" | "bcmp" => {
+            let len = u64_arg(args, 2)?;
+            if len == 0 {
+                return int(0);
+            }
+            let lhs = interp.mem.read(u64_arg(args, 0)?, len)?;
+            let rhs = interp.mem.read(u64_arg(args, 1)?, len)?;
+            int(match lhs.cmp(rhs) {
+                std::cmp::Ordering::Less => -1,
+                std::cmp::Ordering::Equal => 0,
+                std::cmp::Ordering::Greater => 1,
+            })
+        }
+        "strlen" => int(interp.mem.read_cstr(u64_arg(args, 0)?)?.len() as i128),
+        "write" => {
+            let fd = arg(args, 0)?.as_i64()?;
+            let len = u64_arg(args, 2)?;
+            let bytes = interp.mem.read(u64_arg(args, 1)?, len)?.to_vec();
+            interp.write_output(fd, &bytes)?;
+            int(len.into())
+        }
+        "puts" => {
+            let mut bytes = interp.mem.read_cstr(u64_arg(args, 0)?)?;
+            bytes.push(b'\n');
+            interp.write_output(1, &bytes)?;
+            int(0)
+        }
+        "putchar" => {
+            let char = u64_arg(args, 0)? as u8;
+            interp.write_output(1, &[char])?;
+            int(char.into())
+        }
+        "exit" | "_exit" => Err(Trap::Exit(arg(args, 0)?.as_i64()? as i32)),
+        "abort" => {
+            interp.write_output(2, b"Aborted\n")?;
+            Err(Trap::Exit(134))
+        }
+        "__errno_location" => int(interp.errno.into()),
+        "getenv" | "pthread_self" | "getpid" | "getuid" => int(i128::from(name != "getenv")),
+        "sched_yield"
+        | "isatty"
+        | "signal"
+        | "sigaction"
+        | "sigaltstack"
+        | "sigemptyset"
+        | "sigaddset"
+        | "pthread_atfork"
+        | "pthread_mutex_init"
+        | "pthread_mutex_lock"
+        | "pthread_mutex_trylock"
+        | "pthread_mutex_unlock"
+        | "pthread_mutex_destroy"
+        | "pthread_mutexattr_init"
+        | "pthread_mutexattr_settype"
+        | "pthread_mutexattr_destroy" => int(0),
+        "ldexp" | "ldexpf" | "scalbn" | "scalbnf" => {
+            let exp = arg(args, 1)?.as_i64()? as i32;
+            Ok(ret_float(output, f64_arg(args, 0)? * 2.0_f64.powi(exp)))
+        }
+        _ => {
+            // Math functions: `sqrtf` is `sqrt` computed in single precision.
+            let base = name
+                .strip_suffix('f')
+                .filter(|base| float_fn(base, &[0.0; 3]).is_some());
+            let val = float_args(args)
+                .ok()
+                .and_then(|args| float_fn(base.unwrap_or(name), &args));
+            if let Some(val) = val {
+                return Ok(ret_float(output, val));
+            }
+            Err(Trap::Error(format!(
+                "Call to the missing or unsupported function {name}"
+            )))
+        }
+    }
+}
+fn float_args(args: &[Value]) -> Result<Vec<f64>, Trap> {
+    args.iter().map(Value::as_f64).collect()
+}
+/// Computes a libm function `name`, if it is supported.
+fn float_fn(name: &str, args: &[f64]) -> Option<f64> {
+    let unary: fn(f64) -> f64 = match name {
+        "sqrt" => f64::sqrt,
+        "cbrt" => f64::cbrt,
+        "sin" => f64::sin,
+        "cos" => f64::cos,
+        "tan" => f64::tan,
+        "asin" => f64::asin,
+        "acos" => f64::acos,
+        "atan" => f64::atan,
+        "sinh" => f64::sinh,
+        "cosh" => f64::cosh,
+        "tanh" => f64::tanh,
+        "asinh" => f64::asinh,
+        "acosh" => f64::acosh,
+        "atanh" => f64::atanh,
+        "exp" => f64::exp,
+        "exp2" => f64::exp2,
+        "expm1" => f64::exp_m1,
+        "log" => f64::ln,
+        "log2" => f64::log2,
+        "log10" => f64::log10,
+        "log1p" => f64::ln_1p,
+        "floor" => f64::floor,
+        "ceil" => f64::ceil,
+        "round" => f64::round,
+        "rint" | "nearbyint" | "roundeven" => f64::round_ties_even,
+        "trunc" => f64::trunc,
+        "fabs" => f64::abs,
+        _ => {
+            let binary: fn(f64, f64) -> f64 = match name {
+                "pow" => f64::powf,
+                "atan2" => f64::atan2,
+                "fmod" => |lhs, rhs| lhs % rhs,
+                "fmin" => f64::min,
+                "fmax" => f64::max,
+                "fdim" => |lhs, rhs| (lhs - rhs).max(0.0),
+                "hypot" => f64::hypot,
+                "copysign" => f64::copysign,
+                "fma" => return Some(args.first()?.mul_add(*args.get(1)?, *args.get(2)?)),
+                _ => return None,
+            };
+            return Some(binary(*args.first()?, *args.get(1)?));
+        }
+    };
+    Some(unary(*args.first()?))
+}
+fn call_dotnet(
+    interp: &mut Interpreter,
+    class: &str,
+    name: &str,
+    inputs: &[Type],
+    output: Type,
+    args: &[Value],
+) -> Result<Option<Value>, Trap> {
+    let int = |bits: i128| Ok(ret_int(output, bits));
+    match (class, name) {
+        ("System.Runtime.InteropServices.NativeMemory", _) => native_memory(interp, name, args),
+        ("System.Runtime.InteropServices.Marshal", _) => marshal(interp, name, output, args),
+        ("System.Int128" | "System.UInt128", _) => {
+            int128(class == "System.UInt128", name, inputs, output, args)
+        }
+        ("System.Math" | "System.MathF" | "System.Double" | "System.Single", _) => {
+            math(name, output, args)
+        }
+        ("System.Threading.Interlocked", _) => interlocked(interp, name, inputs, args),
+        ("System.Numerics.BitOperations", _) => {
+            let val = u64_arg(args, 0)?;
+            let bits = inputs.first().and_then(Type::as_int).map_or(64, int_size) * 8;
+            let mask = if bits == 64 {
+                u64::MAX
+            } else {
+                (1 << bits) - 1
+            };
+            let val = val & mask;
+            int(match name {
+                "LeadingZeroCount" => i128::from(val.leading_zeros()) - (64 - bits as i128),
+                "TrailingZeroCount" if val == 0 => bits.into(),
+                "TrailingZeroCount" => val.trailing_zeros().into(),
+                "PopCount" => val.count_ones().into(),
+                "Log2" => i128::from(63 - (val | 1).leading_zeros()),
+                "RotateLeft" | "RotateRight" => {
+                    let amount = u64_arg(args, 1)? % bits;
+                    let amount = if name == "RotateLeft" {
+                        amount
+                    } else {
+                        (bits - amount) % bits
+                    };
+                    (((val << amount) | (val >> ((bits - amount) % bits))) & mask).into()
+                }
+                _ => return unsupported(class, name),
+            })
+        }
+        ("System.Buffers.Binary.BinaryPrimitives", "ReverseEndianness") => {
+            let size = inputs.first().and_then(Type::as_int).map_or(8, int_size);
+            let bits = arg(args, 0)?.bits();
+            let bytes = bits.to_le_bytes();
+            let mut reversed = [0; 16];
+            for (idx, byte) in bytes[..size as usize].iter().rev().enumerate() {
+                reversed[idx] = *byte;
+            }
+            int(u128::from_le_bytes(reversed) as i128)
+        }
+        ("System.Console", "Write" | "WriteLine") => {
+            let mut text = match (args.first(), inputs.first()) {
+                (Some(val), Some(tpe)) => format_value(interp, val, *tpe)?,
+                _ => String::new(),
+            };
+            if name == "WriteLine" {
+                text.push('\n');
+            }
+            interp.write_output(1, text.as_bytes())?;
+            Ok(None)
+        }
+        ("System.Environment", "Exit") => Err(Trap::Exit(arg(args, 0)?.as_i64()? as i32)),
+        ("System.Environment", "FailFast") => {
+            let msg = interp.string(arg(args, 0)?.as_obj()?)?;
+            Err(Trap::Error(format!("Environment.FailFast: {msg}")))
+        }
+        ("System.Environment", "GetCommandLineArgs") => {
+            let args = interp
+                .argv
+                .clone()
+                .into_iter()
+                .map(|arg| Value::Obj(interp.new_object(Object::String(arg))))
+                .collect();
+            Ok(Some(Value::Obj(interp.new_object(Object::Array(args)))))
+        }
+        ("System.Environment", "get_ProcessorCount") => int(1),
+        ("System.Environment", "GetEnvironmentVariable") => Ok(Some(Value::Obj(0))),
+        ("System.Threading.Thread", "get_CurrentThread") => Ok(Some(Value::Obj(
+            interp.new_object(Object::Opaque(class.to_owned())),
+        ))),
+        ("System.Threading.Thread", "get_ManagedThreadId") => int(1),
+        ("System.Threading.Thread", "Sleep" | "Yield" | "SpinWait" | "MemoryBarrier")
+        | ("System.GC", "KeepAlive" | "Collect" | "SuppressFinalize")
+        | ("System.Object", ".ctor") => int(0),
+        ("System.String", "Concat") => {
+            let mut res = String::new();
+            for val in args {
+                res.push_str(interp.string(val.as_obj()?)?);
+            }
+            Ok(Some(Value::Obj(interp.new_object(Object::String(res)))))
+        }
+        ("System.String", "op_Equality" | "op_Inequality" | "Equals") => {
+            let eq = interp.string(arg(args, 0)?.as_obj()?)?
+                == interp.string(arg(args, 1)?.as_obj()?)?;
+            int(i128::from(eq == (name != "op_Inequality")))
+        }
+        ("System.String", "get_Length") => int(interp
+            .string(arg(args, 0)?.as_obj()?)?
+            .encode_utf16()
+            .count() as i128),
+        ("System.String", "get_Chars") => {
+            let idx = u64_arg(args, 1)? as usize;
+            let char = interp
+                .string(arg(args, 0)?.as_obj()?)?
+                .encode_utf16()
+                .nth(idx);
+            int(char
+                .ok_or_else(|| Trap::Error(format!("String index {idx} out of bounds")))?
+                .into())
+        }
+        (_, "get_Message" | "ToString") => {
+            let text = match interp.object(arg(args, 0)?.as_obj()?)? {
+                Object::String(string) => string.clone(),
+                Object::Exception { class, message } if name == "ToString" => {
+                    format!("{class}: {message}")
+                }
+                Object::Exception { message, .. } => message.clone(),
+                other => format!("{other:?}"),
+            };
+            Ok(Some(Value::Obj(interp.new_object(Object::String(text)))))
+        }
+        _ => unsupported(class, name),
+    }
+}
+fn unsupported(class: &str, name: &str) -> Result<Option<Value>, Trap> {
+    Err(Trap::Error(format!(
+        "The .NET method {class}::{name} is not supported by the interpreter"
+    )))
+}
+/// Formats a value, like `Console.Write` would.
+fn format_value(interp: &Interpreter, val: &Value, tpe: Type) -> Result<String, Trap> {
+    Ok(match (tpe, val) {
+        (Type::Bool, _) => if val.as_bool()? { "True" } else { "False" }.to_owned(),
+        (Type::PlatformChar, _) => char::from_u32(val.as_u64()? as u32)
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
+            .to_string(),
+        (Type::Int(int), _) if int.is_signed() => match val {
+            Value::I128(val) => val.to_string(),
+            _ => val.as_i64()?.to_string(),
+        },
+        (Type::Int(int), _) => match val {
+            Value::U128(val) => val.to_string(),
+            _ => (val.bits() & ((1 << (int_size(int) * 8).min(127)) - 1)).to_string(),
+        },
+        (_, Value::F32(val)) => val.to_string(),
+        (_, Value::F64(val)) => val.to_string(),
+        (_, Value::Obj(0)) => String::new(),
+        (_, Value::Obj(handle)) => match interp.object(*handle)? {
+            Object::String(string) => string.clone(),
+            other => format!("{other:?}"),
+        },
+        _ => format!("{val:?}"),
+    })
+}
+fn native_memory(
+    interp: &mut Interpreter,
+    name: &str,
+    args: &[Value],
+) -> Result<Option<Value>, Trap> {
+    let ptr = |ptr: u64| Ok(Some(Value::NInt(ptr as i64)));
+    match name {
+        "Alloc" | "AllocZeroed" => {
+            let size = match args {
+                [count, size] => count.as_u64()?.saturating_mul(size.as_u64()?),
+                _ => u64_arg(args, 0)?,
+            };
+            ptr(interp.mem.alloc(size, 16))
+        }
+        "AlignedAlloc" => ptr(interp.mem.alloc(u64_arg(args, 0)?, u64_arg(args, 1)?)),
+        "Realloc" => ptr(interp
+            .mem
+            .realloc(u64_arg(args, 0)?, u64_arg(args, 1)?, 16)?),
+        "AlignedRealloc" => {
+            ptr(interp
+                .mem
+                .realloc(u64_arg(args, 0)?, u64_arg(args, 1)?, u64_arg(args, 2)?)?)
+        }
+        "Free" | "AlignedFree" => {
+            interp.mem.free(u64_arg(args, 0)?)?;
+            Ok(None)
+        }
+        "Clear" => {
+            interp.mem.fill(u64_arg(args, 0)?, 0, u64_arg(args, 1)?)?;
+            Ok(None)
+        }
+        "Fill" => {
+            let val = u64_arg(args, 2)? as u8;
+            interp.mem.fill(u64_arg(args, 0)?, val, u64_arg(args, 1)?)?;
+            Ok(None)
+        }
+        "Copy" => {
+            interp
+                .mem
+                .copy(u64_arg(args, 1)?, u64_arg(args, 0)?, u64_arg(args, 2)?)?;
+            Ok(None)
+        }
+        _ => unsupported("System.Runtime.InteropServices.NativeMemory", name),
+    }
+}
+fn marshal(
+    interp: &mut Interpreter,
+    name: &str,
+    output: Type,
+    args: &[Value],
+) -> Result<Option<Value>, Trap> {
+    match name {
+        "AllocHGlobal" | "AllocCoTaskMem" => Ok(ret_int(
+            output,
+            interp.mem.alloc(u64_arg(args, 0)?, 16).into(),
+        )),
+        "ReAllocHGlobal" | "ReAllocCoTaskMem" => {
+            let ptr = interp
+                .mem
+                .realloc(u64_arg(args, 0)?, u64_arg(args, 1)?, 16)?;
+            Ok(ret_int(output, ptr.into()))
+        }
+        "FreeHGlobal" | "FreeCoTaskMem" => {
+            interp.mem.free(u64_arg(args, 0)?)?;
+            Ok(None)
+        }
+        "GetLastPInvokeError" | "GetLastSystemError" | "GetLastWin32Error" => {
+            let errno = interp.load(interp.errno, Type::Int(Int::I32))?;
+            Ok(Some(errno))
+        }
+        "SetLastPInvokeError" | "SetLastSystemError" => {
+            interp.store(interp.errno, Type::Int(Int::I32), arg(args, 0)?)?;
+            Ok(None)
+        }
+        "StringToCoTaskMemUTF8" | "StringToHGlobalAnsi" => {
+            let string = interp.string(arg(args, 0)?.as_obj()?)?.as_bytes().to_vec();
+            let ptr = interp.mem.alloc(string.len() as u64 + 1, 16);
+            interp.mem.write(ptr, &string)?;
+            Ok(Some(Value::NInt(ptr as i64)))
+        }
+        "PtrToStringUTF8" | "PtrToStringAnsi" => {
+            let ptr = u64_arg(args, 0)?;
+            let bytes = match args.get(1) {
+                Some(len) => interp.mem.read(ptr, len.as_u64()?)?.to_vec(),
+                None => interp.mem.read_cstr(ptr)?,
+            };
+            let string = String::from_utf8_lossy(&bytes).into_owned();
+            Ok(Some(Value::Obj(interp.new_object(Object::String(string)))))
+        }
+        _ => unsupported("System.Runtime.InteropServices.Marshal", name),
+    }
+}
+fn math(name: &str, output: Type, args: &[Value]) -> Result<Option<Value>, Trap> {
+    // Integer overloads
+    if let Some(Value::I32(_) | Value::I64(_) | Value::NInt(_)) = args.first() {
+        let lhs = arg(args, 0)?.as_i64()?;
+        return Ok(ret_int(
+            output,
+            match name {
+                "Abs" => lhs.wrapping_abs(),
+                "Max" => lhs.max(arg(args, 1)?.as_i64()?),
+                "Min" => lhs.min(arg(args, 1)?.as_i64()?),
+                _ => return unsupported("System.Math", name),
+            }
+            .into(),
+        ));
+    }
+    let val = f64_arg(args, 0)?;
+    let res = match name {
+        "Round" if args.len() == 2 => match arg(args, 1)?.as_i64()? {
+            0 => val.round_ties_even(),
+            1 => val.round(),
+            2 => val.trunc(),
+            3 => val.floor(),
+            4 => val.ceil(),
+            mode => return Err(Trap::Error(format!("Invalid rounding mode {mode}"))),
+        },
+        "Round" => val.round_ties_even(),
+        "Log" if args.len() == 2 => val.log(f64_arg(args, 1)?),
+        "Max" | "Min" => {
+            let rhs = f64_arg(args, 1)?;
+            // Unlike `f64::max`, .NET propagates NaNs.
+            if val.is_nan() || rhs.is_nan() {
+                f64::NAN
+            } else if name == "Max" {
+                val.max(rhs)
+            } else {
+                val.min(rhs)
+            }
+        }
+        "ScaleB" => val * 2.0_f64.powi(arg(args, 1)?.as_i64()? as i32),
+        "BitIncrement" | "BitDecrement" => {
+            return Ok(match (arg(args, 0)?, name == "BitIncrement") {
+                (Value::F32(val), up) => {
+                    Some(Value::F32(if up { val.next_up() } else { val.next_down() }))
+                }
+                (_, up) => Some(Value::F64(if up { val.next_up() } else { val.next_down() })),
+            })
+        }
+        "IsNaN" => return Ok(Some(Value::bool(val.is_nan()))),
+        "IsInfinity" => return Ok(Some(Value::bool(val.is_infinite()))),
+        "IsFinite" => return Ok(Some(Value::bool(val.is_finite()))),
+        _ => {
+            let libm_name = match name {
+                "Ceiling" => "ceil",
+                "Truncate" => "trunc",
+                "Abs" => "fabs",
+                "FusedMultiplyAdd" => "fma",
+                "CopySign" => "copysign",
+                "Log" => "log",
+                "Atan2" => "atan2",
+                _ => &name.to_lowercase(),
+            };
+            float_fn(libm_name, &float_args(args)?)
+                .ok_or_else(|| Trap::Error(format!("The math function {name} is not supported")))?
+        }
+    };
+    Ok(ret_float(output, res))
+}
+fn int128(
+    unsigned: bool,
+    name: &str,
+    inputs: &[Type],
+    output: Type,
+    args: &[Value],
+) -> Result<Option<Value>, Trap> {
+    let wide = |val: &Value| {
+        if unsigned {
+            Value::U128(val.bits())
+        } else {
+            Value::I128(val.bits() as i128)
+        }
+    };
+    let binop = BinOp::ALL_OPS
+        .into_iter()
+        .filter(|op| !matches!(op, BinOp::LtUn | BinOp::GtUn | BinOp::RemUn | BinOp::DivUn))
+        .find(|op| op.dotnet_name() == name);
+    if let Some(binop) = binop {
+        let lhs = wide(arg(args, 0)?);
+        let rhs = arg(args, 1)?;
+        let rhs = if matches!(binop, BinOp::Shl | BinOp::Shr) {
+            rhs.clone()
+        } else {
+            wide(rhs)
+        };
+        return lhs.binop(&rhs, binop).map(Some);
+    }
+    let val = || arg(args, 0).map(wide);
+    let not = |res: Value| Ok(Some(Value::bool(!res.as_bool()?)));
+    match name {
+        "op_Inequality" => not(val()?.binop(&wide(arg(args, 1)?), BinOp::Eq)?),
+        "op_LessThanOrEqual" => not(val()?.binop(&wide(arg(args, 1)?), BinOp::Gt)?),
+        "op_GreaterThanOrEqual" => not(val()?.binop(&wide(arg(args, 1)?), BinOp::Lt)?),
+        "op_UnaryNegation" => Ok(Some(wide(&Value::I128(
+            (val()?.bits() as i128).wrapping_neg(),
+        )))),
+        "op_OnesComplement" => Ok(Some(wide(&Value::U128(!val()?.bits())))),
+        "get_Zero" => Ok(Some(wide(&Value::U128(0)))),
+        "get_One" => Ok(Some(wide(&Value::U128(1)))),
+        "get_MaxValue" if unsigned => Ok(Some(Value::U128(u128::MAX))),
+        "get_MaxValue" => Ok(Some(Value::I128(i128::MAX))),
+        "get_MinValue" if unsigned => Ok(Some(Value::U128(0))),
+        "get_MinValue" => Ok(Some(Value::I128(i128::MIN))),
+        "LeadingZeroCount" => Ok(Some(wide(&Value::U128(
+            val()?.bits().leading_zeros().into(),
+        )))),
+        "TrailingZeroCount" => Ok(Some(wide(&Value::U128(
+            val()?.bits().trailing_zeros().into(),
+        )))),
+        "PopCount" => Ok(Some(wide(&Value::U128(val()?.bits().count_ones().into())))),
+        "op_Implicit" | "op_Explicit" | "op_CheckedExplicit" => {
+            let input = *inputs
+                .first()
+                .ok_or_else(|| Trap::Error(format!("{name} takes one argument")))?;
+            let extend = match input {
+                Type::Int(int) if !int.is_signed() => ExtendKind::ZeroExtend,
+                _ => ExtendKind::SignExtend,
+            };
+            let val = arg(args, 0)?.clone();
+            match output {
+                Type::Int(int) => int_cast(val, int, extend).map(Some),
+                Type::Float(_) => {
+                    let float = if extend == ExtendKind::SignExtend {
+                        val.bits() as i128 as f64
+                    } else {
+                        val.bits() as f64
+                    };
+                    Ok(ret_float(output, float))
+                }
+                _ => Err(Trap::Error(format!(
+                    "Unsupported 128 bit integer conversion to {output:?}"
+                ))),
+            }
+        }
+        _ => unsupported(
+            if unsigned {
+                "System.UInt128"
+            } else {
+                "System.Int128"
+            },
+            name,
+        ),
+    }
+}
+fn interlocked(
+    interp: &mut Interpreter,
+    name: &str,
+    inputs: &[Type],
+    args: &[Value],
+) -> Result<Option<Value>, Trap> {
+    let tpe = match inputs.first() {
+        Some(Type::Ref(inner) | Type::Ptr(inner)) => interp.asm[*inner],
+        other => {
+            return Err(Trap::Error(format!(
+                "Interlocked::{name} called with a location of type {other:?}"
+            )))
+        }
+    };
+    let addr = u64_arg(args, 0)?;
+    let old = interp.load(addr, tpe)?;
+    let one = match old {
+        Value::I64(_) => Value::I64(1),
+        Value::NInt(_) => Value::NInt(1),
+        _ => Value::I32(1),
+    };
+    let (new, ret_new) = match name {
+        "CompareExchange" => {
+            let new = arg(args, 1)?;
+            let comparand = arg(args, 2)?;
+            ((old.bits() == comparand.bits()).then(|| new.clone()), false)
+        }
+        "Exchange" => (Some(arg(args, 1)?.clone()), false),
+        "Add" => (Some(old.binop(arg(args, 1)?, BinOp::Add)?), true),
+        "Increment" => (Some(old.binop(&one, BinOp::Add)?), true),
+        "Decrement" => (Some(old.binop(&one, BinOp::Sub)?), true),
+        "And" => (Some(old.binop(arg(args, 1)?, BinOp::And)?), false),
+        "Or" => (Some(old.binop(arg(args, 1)?, BinOp::Or)?), false),
+        _ => return unsupported("System.Threading.Interlocked", name),
+    };
+    match new {
+        Some(new) => {
+            interp.store(addr, tpe, &new)?;
+            Ok(Some(if ret_new { new } else { old }))
+        }
+        None => Ok(Some(old)),
+    }
+}
@@ -0,0 +1,309 @@
+use crate::v2::{Assembly, BinOp, Const, Float, Int, Type};
+
+use super::Trap;
+
+/// A value on the evaluation stack.
+///
+/// Just like in CIL, integers smaller than 32 bits are widened to `I32` when loaded, and are truncated again
+/// when stored. Pointers and native-sized integers are both `NInt`. 128 bit integers keep their signedness, since
+/// `cilly` uses the same operations for signed and unsigned 128 bit integers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    NInt(i64),
+    I128(i128),
+    U128(u128),
+    F32(f32),
+    F64(f64),
+    /// A handle to a managed object. 0 is `null`.
+    Obj(u64),
+    /// Raw bytes of a value type.
+    Struct(Box<[u8]>),
+}
+/// Size of `int`, for types which have a known size.
+pub fn int_size(int: Int) -> u64 {
+    int.size().map_or(8, u64::from)
+}
+impl Value {
+    /// Converts a constant into a stack value. Strings must be handled by the caller, since they require allocating
+    /// a managed object.
+    pub fn from_const(cst: &Const) -> Option<Self> {
+        Some(match cst {
+            Const::I8(val) => Self::I32(i32::from(*val)),
+            Const::I16(val) => Self::I32(i32::from(*val)),
+            Const::I32(val) => Self::I32(*val),
+            Const::U8(val) => Self::I32(i32::from(*val)),
+            Const::U16(val) => Self::I32(i32::from(*val)),
+            Const::U32(val) => Self::I32(*val as i32),
+            Const::I64(val) => Self::I64(*val),
+            Const::U64(val) => Self::I64(*val as i64),
+            Const::ISize(val) => Self::NInt(*val),
+            Const::USize(val) => Self::NInt(*val as i64),
+            Const::I128(val) => Self::I128(*val),
+            Const::U128(val) => Self::U128(*val),
+            Const::Bool(val) => Self::I32(i32::from(*val)),
+            Const::F32(val) => Self::F32(val.0),
+            Const::F64(val) => Self::F64(val.0),
+            Const::Null(_) => Self::Obj(0),
+            Const::PlatformString(_) => return None,
+        })
+    }
+    /// Decodes a value of type `tpe` from its in-memory representation.
+    pub fn decode(bytes: &[u8], tpe: Type, asm: &Assembly) -> Result<Self, Trap> {
+        let mut buf = [0; 16];
+        buf[..bytes.len().min(16)].copy_from_slice(&bytes[..bytes.len().min(16)]);
+        let raw = u128::from_le_bytes(buf);
+        Ok(match tpe {
+            Type::Int(int) => match int {
+                Int::I8 => Self::I32(i32::from(raw as i8)),
+                Int::U8 => Self::I32(i32::from(raw as u8)),
+                Int::I16 => Self::I32(i32::from(raw as i16)),
+                Int::U16 => Self::I32(i32::from(raw as u16)),
+                Int::I32 | Int::U32 => Self::I32(raw as i32),
+                Int::I64 | Int::U64 => Self::I64(raw as i64),
+                Int::ISize | Int::USize => Self::NInt(raw as i64),
+                Int::I128 => Self::I128(raw as i128),
+                Int::U128 => Self::U128(raw),
+            },
+            Type::Bool => Self::I32(i32::from(raw as u8 != 0)),
+            Type::PlatformChar => Self::I32(i32::from(raw as u16)),
+            Type::Float(Float::F32) => Self::F32(f32::from_bits(raw as u32)),
+            Type::Float(Float::F64) => Self::F64(f64::from_bits(raw as u64)),
+            Type::Ptr(_) | Type::Ref(_) | Type::FnPtr(_) => Self::NInt(raw as i64),
+            Type::PlatformString | Type::PlatformObject | Type::PlatformArray { .. } => {
+                Self::Obj(raw as u64)
+            }
+            Type::ClassRef(cref) if !asm[cref].is_valuetype() => Self::Obj(raw as u64),
+            Type::ClassRef(_) | Type::SIMDVector(_) | Type::Float(_) | Type::Void => {
+                Self::Struct(bytes.into())
+            }
+            Type::PlatformGeneric(..) => {
+                return Err(Trap::Error(
+                    "Can't decode a value of a generic type".to_owned(),
+                ))
+            }
+        })
+    }
+    /// Encodes this value as `size` bytes of type `tpe`, truncating integers if needed.
+    pub fn encode(&self, tpe: Type, size: u64) -> Result<Vec<u8>, Trap> {
+        let size = size as usize;
+        let raw: u128 = match (self, tpe) {
+            (Self::Struct(bytes), _) => {
+                if bytes.len() != size {
+                    return Err(Trap::Error(format!(
+                        "Struct of size {} stored as a value of size {size}",
+                        bytes.len()
+                    )));
+                }
+                return Ok(bytes.to_vec());
+            }
+            (Self::F32(val), Type::Float(Float::F64)) => u128::from(f64::from(*val).to_bits()),
+            (Self::F64(val), Type::Float(Float::F32)) => u128::from((*val as f32).to_bits()),
+            _ => self.bits(),
+        };
+        if size > 16 {
+            return Err(Trap::Error(format!(
+                "Scalar {self:?} stored as a value of size {size}"
+            )));
+        }
+        Ok(raw.to_le_bytes()[..size].to_vec())
+    }
+    /// The raw bits of this value, sign extended to 128 bits.
+    pub fn bits(&self) -> u128 {
+        match self {
+            Self::I32(val) => i128::from(*val) as u128,
+            Self::I64(val) | Self::NInt(val) => i128::from(*val) as u128,
+            Self::I128(val) => *val as u128,
+            Self::U128(val) => *val,
+            Self::F32(val) => u128::from(val.to_bits()),
+            Self::F64(val) => u128::from(val.to_bits()),
+            Self::Obj(handle) => u128::from(*handle),
+            Self::Struct(bytes) => {
+                let mut buf = [0; 16];
+                let len = bytes.len().min(16);
+                buf[..len].copy_from_slice(&bytes[..len]);
+                u128::from_le_bytes(buf)
+            }
+        }
+    }
+    /// Interprets this value as a signed integer.
+    pub fn as_i64(&self) -> Result<i64, Trap> {
+        match self {
+            Self::I32(val) => Ok(i64::from(*val)),
+            Self::I64(val) | Self::NInt(val) => Ok(*val),
+            Self::I128(val) => Ok(*val as i64),
+            Self::U128(val) => Ok(*val as i64),
+            _ => Err(Trap::Error(format!("Expected an integer, got {self:?}"))),
+        }
+    }
+    /// Interprets this value as an unsigned integer. `I32`s are zero extended.
+    pub fn as_u64(&self) -> Result<u64, Trap> {
+        match self {
+            Self::I32(val) => Ok(u64::from(*val as u32)),
+            _ => self.as_i64().map(|val| val as u64),
+        }
+    }
+    /// Interprets this value as an address.
+    pub fn as_addr(&self) -> Result<u64, Trap> {
+        self.as_u64()
+    }
+    /// Interprets this value as a condition: any non-zero value is true.
+    pub fn as_bool(&self) -> Result<bool, Trap> {
+        match self {
+            Self::Obj(handle) => Ok(*handle != 0),
+            _ => Ok(self.as_i64()? != 0),
+        }
+    }
+    pub fn as_f64(&self) -> Result<f64, Trap> {
+        match self {
+            Self::F32(val) => Ok(f64::from(*val)),
+            Self::F64(val) => Ok(*val),
+            _ => Err(Trap::Error(format!("Expected a float, got {self:?}"))),
+        }
+    }
+    pub fn as_obj(&self) -> Result<u64, Trap> {
+        match self {
+            Self::Obj(handle) => Ok(*handle),
+            _ => Err(Trap::Error(format!("Expected an object, got {self:?}"))),
+        }
+    }
+    /// Creates an integer value of type `int` from its bits.
+    pub fn from_int_bits(bits: u128, int: Int) -> Self {
+        match int {
+            Int::I8 => Self::I32(i32::from(bits as i8)),
+            Int::U8 => Self::I32(i32::from(bits as u8)),
+            Int::I16 => Self::I32(i32::from(bits as i16)),
+            Int::U16 => Self::I32(i32::from(bits as u16)),
+            Int::I32 | Int::U32 => Self::I32(bits as i32),
+            Int::I64 | Int::U64 => Self::I64(bits as i64),
+            Int::ISize | Int::USize => Self::NInt(bits as i64),
+            Int::I128 => Self::I128(bits as i128),
+            Int::U128 => Self::U128(bits),
+        }
+    }
+    pub fn bool(val: bool) -> Self {
+        Self::I32(i32::from(val))
+    }
+    /// Applies a binary operation to 2 values, following the CIL rules for operand types.
+    pub fn binop(&self, rhs: &Self, op: BinOp) -> Result<Self, Trap> {
+        macro_rules! int_op {
+            ($lhs:expr, $rhs:expr, $signed:ty, $unsigned:ty, $wrap:expr) => {{
+                let lhs: $signed = $lhs;
+                let rhs: $signed = $rhs;
+                let amount = rhs as u32;
+                match op {
+                    BinOp::Add => $wrap(lhs.wrapping_add(rhs)),
+                    BinOp::Sub => $wrap(lhs.wrapping_sub(rhs)),
+                    BinOp::Mul => $wrap(lhs.wrapping_mul(rhs)),
+                    BinOp::And => $wrap(lhs & rhs),
+                    BinOp::Or => $wrap(lhs | rhs),
+                    BinOp::XOr => $wrap(lhs ^ rhs),
+                    BinOp::Shl => $wrap(lhs.wrapping_shl(amount)),
+                    BinOp::Shr => $wrap(lhs.wrapping_shr(amount)),
+                    BinOp::ShrUn => $wrap((lhs as $unsigned).wrapping_shr(amount) as $signed),
+                    BinOp::Div | BinOp::Rem | BinOp::DivUn | BinOp::RemUn if rhs == 0 => {
+                        return Err(Trap::Error("Integer division by zero".to_owned()))
+                    }
+                    BinOp::Div => $wrap(lhs.checked_div(rhs).ok_or_else(overflow)?),
+                    BinOp::Rem => $wrap(lhs.checked_rem(rhs).ok_or_else(overflow)?),
+                    BinOp::DivUn => $wrap(((lhs as $unsigned) / (rhs as $unsigned)) as $signed),
+                    BinOp::RemUn => $wrap(((lhs as $unsigned) % (rhs as $unsigned)) as $signed),
+                    BinOp::Eq => Self::bool(lhs == rhs),
+                    BinOp::Lt => Self::bool(lhs < rhs),
+                    BinOp::Gt => Self::bool(lhs > rhs),
+                    BinOp::LtUn => Self::bool((lhs as $unsigned) < (rhs as $unsigned)),
+                    BinOp::GtUn => Self::bool((lhs as $unsigned) > (rhs as $unsigned)),
+                }
+            }};
+        }
+        fn overflow() -> Trap {
+            Trap::Error("Arithmetic overflow in division".to_owned())
+        }
+        let is_shift = matches!(op, BinOp::Shl | BinOp::Shr | BinOp::ShrUn);
+        Ok(match (self, rhs) {
+            (Self::I32(lhs), _) if is_shift => {
+                int_op!(*lhs, rhs.as_i64()? as i32, i32, u32, Self::I32)
+            }
+            (Self::I64(lhs), _) if is_shift => {
+                int_op!(*lhs, rhs.as_i64()?, i64, u64, Self::I64)
+            }
+            (Self::NInt(lhs), _) if is_shift => {
+                int_op!(*lhs, rhs.as_i64()?, i64, u64, Self::NInt)
+            }
+            (Self::I128(lhs), _) if is_shift => {
+                int_op!(*lhs, i128::from(rhs.as_i64()?), i128, u128, Self::I128)
+            }
+            // Unsigned 128 bit ints use the same ops as the signed ones, so their signedness must be flipped.
+            (Self::U128(lhs), _) if is_shift => {
+                let op = if op == BinOp::Shr { BinOp::ShrUn } else { op };
+                return Self::I128(*lhs as i128)
+                    .binop(rhs, op)
+                    .map(|val| Self::U128(val.bits()));
+            }
+            (Self::I32(lhs), Self::I32(rhs)) => int_op!(*lhs, *rhs, i32, u32, Self::I32),
+            (Self::I64(lhs), Self::I64(rhs)) => int_op!(*lhs, *rhs, i64, u64, Self::I64),
+            (Self::NInt(lhs), Self::NInt(rhs) | Self::I64(rhs))
+            | (Self::I64(lhs), Self::NInt(rhs)) => int_op!(*lhs, *rhs, i64, u64, Self::NInt),
+            (Self::NInt(lhs), Self::I32(rhs)) => {
+                int_op!(*lhs, i64::from(*rhs), i64, u64, Self::NInt)
+            }
+            (Self::I32(lhs), Self::NInt(rhs)) => {
+                int_op!(i64::from(*lhs), *rhs, i64, u64, Self::NInt)
+            }
+            (Self::I128(lhs), Self::I128(_) | Self::U128(_)) => {
+                int_op!(*lhs, rhs.bits() as i128, i128, u128, Self::I128)
+            }
+            (Self::U128(lhs), Self::U128(_) | Self::I128(_)) => {
+                let op = match op {
+                    BinOp::Div => BinOp::DivUn,
+                    BinOp::Rem => BinOp::RemUn,
+                    BinOp::Lt => BinOp::LtUn,
+                    BinOp::Gt => BinOp::GtUn,
+                    _ => op,
+                };
+                match Self::I128(*lhs as i128).binop(&Self::I128(rhs.bits() as i128), op)? {
+                    Self::I128(val) => Self::U128(val as u128),
+                    res => res,
+                }
+            }
+            (Self::F32(lhs), Self::F32(rhs)) => {
+                match float_op(f64::from(*lhs), f64::from(*rhs), op)? {
+                    Self::F64(val) => Self::F32(val as f32),
+                    res => res,
+                }
+            }
+            (Self::F32(_) | Self::F64(_), Self::F32(_) | Self::F64(_)) => {
+                float_op(self.as_f64()?, rhs.as_f64()?, op)?
+            }
+            (Self::Obj(lhs), Self::Obj(rhs)) if op == BinOp::Eq => Self::bool(lhs == rhs),
+            (Self::Obj(lhs), Self::Obj(rhs)) if op == BinOp::GtUn => Self::bool(lhs > rhs),
+            _ => {
+                return Err(Trap::Error(format!(
+                    "Invalid operands of {op:?}: {self:?} and {rhs:?}"
+                )))
+            }
+        })
+    }
+}
+/// Applies `op` to 2 floats. Arithmetic is done in double precision, and rounded back by the caller when needed.
+fn float_op(lhs: f64, rhs: f64, op: BinOp) -> Result<Value, Trap> {
+    Ok(match op {
+        BinOp::Add => Value::F64(lhs + rhs),
+        BinOp::Sub => Value::F64(lhs - rhs),
+        BinOp::Mul => Value::F64(lhs * rhs),
+        BinOp::Div => Value::F64(lhs / rhs),
+        BinOp::Rem => Value::F64(lhs % rhs),
+        BinOp::Eq => Value::bool(lhs == rhs),
+        BinOp::Lt => Value::bool(lhs < rhs),
+        BinOp::Gt => Value::bool(lhs > rhs),
+        // Unordered comparisons are true if any of the operands is NaN.
+        BinOp::LtUn => Value::bool(lhs < rhs || lhs.is_nan() || rhs.is_nan()),
+        BinOp::GtUn => Value::bool(lhs > rhs || lhs.is_nan() || rhs.is_nan()),
+        _ => {
+            return Err(Trap::Error(format!(
+                "{op:?} is not supported for floating-point values"
+            )))
+        }
+    })
+}
@@ -181,6 +181,7 @@ impl Iterator for CILIter<'_> {
                     CILRoot::StLoc(_, val)
                     | CILRoot::StArg(_, val)
                     | CILRoot::Ret(val)
+                    | CILRoot::TailCall(val)
                     | CILRoot::Pop(val)
                     | CILRoot::Throw(val)
                     | CILRoot::SetStaticField { val, .. },
@@ -195,6 +196,17 @@ impl Iterator for CILIter<'_> {
                         continue;
                     }
                 }
+                CILIterElem::Root(CILRoot::Switch(switch)) => {
+                    if idx == &1 {
+                        *idx += 1;
+                        let val = self.asm.get_node(switch.0);
+                        self.elems.push((CILIterElem::Node(val.clone()), 0));
+                        continue;
+                    } else {
+                        self.elems.pop();
+                        continue;
+                    }
+                }
                 CILIterElem::Node(
                     CILNode::Const(_)
                     | CILNode::LdArg(_)
@@ -512,6 +524,8 @@ impl<'this, T: Iterator<Item = CILIterElem> + 'this> TpeIter<'this> for T {
                     CILRoot::StLoc(_, _)
                     | CILRoot::StArg(_, _)
                     | CILRoot::Ret(_)
+                    | CILRoot::TailCall(_)
+                    | CILRoot::Switch(_)
                     | CILRoot::Pop(_)
                     | CILRoot::Throw(_)
                     | CILRoot::VoidRet
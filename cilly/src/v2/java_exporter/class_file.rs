@@ -0,0 +1,902 @@
+//! Serialization of JVM class files, and a small bytecode assembler used to build method bodies.
+use fxhash::FxHashMap;
+
+/// The class file version emitted. Version 49 (Java 5) does not require `StackMapTable`s, which
+/// saves us from computing verification frames.
+const MAJOR_VERSION: u16 = 49;
+
+pub(super) const ACC_PUBLIC: u16 = 0x0001;
+pub(super) const ACC_STATIC: u16 = 0x0008;
+pub(super) const ACC_FINAL: u16 = 0x0010;
+pub(super) const ACC_SUPER: u16 = 0x0020;
+/// JVM opcodes used by the exporter.
+#[allow(dead_code)]
+pub(super) mod op {
+    pub const NOP: u8 = 0x00;
+    pub const ACONST_NULL: u8 = 0x01;
+    pub const ICONST_M1: u8 = 0x02;
+    pub const ICONST_0: u8 = 0x03;
+    pub const ICONST_1: u8 = 0x04;
+    pub const LCONST_0: u8 = 0x09;
+    pub const LCONST_1: u8 = 0x0a;
+    pub const FCONST_0: u8 = 0x0b;
+    pub const DCONST_0: u8 = 0x0e;
+    pub const BIPUSH: u8 = 0x10;
+    pub const SIPUSH: u8 = 0x11;
+    pub const LDC: u8 = 0x12;
+    pub const LDC_W: u8 = 0x13;
+    pub const LDC2_W: u8 = 0x14;
+    pub const ILOAD: u8 = 0x15;
+    pub const LLOAD: u8 = 0x16;
+    pub const FLOAD: u8 = 0x17;
+    pub const DLOAD: u8 = 0x18;
+    pub const ALOAD: u8 = 0x19;
+    pub const IALOAD: u8 = 0x2e;
+    pub const LALOAD: u8 = 0x2f;
+    pub const FALOAD: u8 = 0x30;
+    pub const DALOAD: u8 = 0x31;
+    pub const AALOAD: u8 = 0x32;
+    pub const BALOAD: u8 = 0x33;
+    pub const CALOAD: u8 = 0x34;
+    pub const SALOAD: u8 = 0x35;
+    pub const ISTORE: u8 = 0x36;
+    pub const LSTORE: u8 = 0x37;
+    pub const FSTORE: u8 = 0x38;
+    pub const DSTORE: u8 = 0x39;
+    pub const ASTORE: u8 = 0x3a;
+    pub const IASTORE: u8 = 0x4f;
+    pub const LASTORE: u8 = 0x50;
+    pub const FASTORE: u8 = 0x51;
+    pub const DASTORE: u8 = 0x52;
+    pub const AASTORE: u8 = 0x53;
+    pub const BASTORE: u8 = 0x54;
+    pub const CASTORE: u8 = 0x55;
+    pub const SASTORE: u8 = 0x56;
+    pub const POP: u8 = 0x57;
+    pub const POP2: u8 = 0x58;
+    pub const DUP: u8 = 0x59;
+    pub const DUP_X1: u8 = 0x5a;
+    pub const DUP_X2: u8 = 0x5b;
+    pub const DUP2: u8 = 0x5c;
+    pub const DUP2_X1: u8 = 0x5d;
+    pub const SWAP: u8 = 0x5f;
+    pub const IADD: u8 = 0x60;
+    pub const LADD: u8 = 0x61;
+    pub const FADD: u8 = 0x62;
+    pub const DADD: u8 = 0x63;
+    pub const ISUB: u8 = 0x64;
+    pub const LSUB: u8 = 0x65;
+    pub const FSUB: u8 = 0x66;
+    pub const DSUB: u8 = 0x67;
+    pub const IMUL: u8 = 0x68;
+    pub const LMUL: u8 = 0x69;
+    pub const FMUL: u8 = 0x6a;
+    pub const DMUL: u8 = 0x6b;
+    pub const IDIV: u8 = 0x6c;
+    pub const LDIV: u8 = 0x6d;
+    pub const FDIV: u8 = 0x6e;
+    pub const DDIV: u8 = 0x6f;
+    pub const IREM: u8 = 0x70;
+    pub const LREM: u8 = 0x71;
+    pub const FREM: u8 = 0x72;
+    pub const DREM: u8 = 0x73;
+    pub const INEG: u8 = 0x74;
+    pub const LNEG: u8 = 0x75;
+    pub const FNEG: u8 = 0x76;
+    pub const DNEG: u8 = 0x77;
+    pub const ISHL: u8 = 0x78;
+    pub const LSHL: u8 = 0x79;
+    pub const ISHR: u8 = 0x7a;
+    pub const LSHR: u8 = 0x7b;
+    pub const IUSHR: u8 = 0x7c;
+    pub const LUSHR: u8 = 0x7d;
+    pub const IAND: u8 = 0x7e;
+    pub const LAND: u8 = 0x7f;
+    pub const IOR: u8 = 0x80;
+    pub const LOR: u8 = 0x81;
+    pub const IXOR: u8 = 0x82;
+    pub const LXOR: u8 = 0x83;
+    pub const I2L: u8 = 0x85;
+    pub const I2F: u8 = 0x86;
+    pub const I2D: u8 = 0x87;
+    pub const L2I: u8 = 0x88;
+    pub const L2F: u8 = 0x89;
+    pub const L2D: u8 = 0x8a;
+    pub const F2I: u8 = 0x8b;
+    pub const F2L: u8 = 0x8c;
+    pub const F2D: u8 = 0x8d;
+    pub const D2I: u8 = 0x8e;
+    pub const D2L: u8 = 0x8f;
+    pub const D2F: u8 = 0x90;
+    pub const I2B: u8 = 0x91;
+    pub const I2C: u8 = 0x92;
+    pub const I2S: u8 = 0x93;
+    pub const LCMP: u8 = 0x94;
+    pub const FCMPL: u8 = 0x95;
+    pub const FCMPG: u8 = 0x96;
+    pub const DCMPL: u8 = 0x97;
+    pub const DCMPG: u8 = 0x98;
+    pub const IFEQ: u8 = 0x99;
+    pub const IFNE: u8 = 0x9a;
+    pub const IFLT: u8 = 0x9b;
+    pub const IFGE: u8 = 0x9c;
+    pub const IFGT: u8 = 0x9d;
+    pub const IFLE: u8 = 0x9e;
+    pub const IF_ICMPEQ: u8 = 0x9f;
+    pub const IF_ICMPNE: u8 = 0xa0;
+    pub const IF_ICMPLT: u8 = 0xa1;
+    pub const IF_ICMPGE: u8 = 0xa2;
+    pub const IF_ICMPGT: u8 = 0xa3;
+    pub const IF_ICMPLE: u8 = 0xa4;
+    pub const IF_ACMPEQ: u8 = 0xa5;
+    pub const IF_ACMPNE: u8 = 0xa6;
+    pub const GOTO: u8 = 0xa7;
+    pub const LOOKUPSWITCH: u8 = 0xab;
+    pub const IRETURN: u8 = 0xac;
+    pub const LRETURN: u8 = 0xad;
+    pub const FRETURN: u8 = 0xae;
+    pub const DRETURN: u8 = 0xaf;
+    pub const ARETURN: u8 = 0xb0;
+    pub const RETURN: u8 = 0xb1;
+    pub const GETSTATIC: u8 = 0xb2;
+    pub const PUTSTATIC: u8 = 0xb3;
+    pub const GETFIELD: u8 = 0xb4;
+    pub const PUTFIELD: u8 = 0xb5;
+    pub const INVOKEVIRTUAL: u8 = 0xb6;
+    pub const INVOKESPECIAL: u8 = 0xb7;
+    pub const INVOKESTATIC: u8 = 0xb8;
+    pub const NEW: u8 = 0xbb;
+    pub const NEWARRAY: u8 = 0xbc;
+    pub const ANEWARRAY: u8 = 0xbd;
+    pub const ARRAYLENGTH: u8 = 0xbe;
+    pub const ATHROW: u8 = 0xbf;
+    pub const CHECKCAST: u8 = 0xc0;
+    pub const INSTANCEOF: u8 = 0xc1;
+    pub const WIDE: u8 = 0xc4;
+    pub const IFNULL: u8 = 0xc6;
+    pub const IFNONNULL: u8 = 0xc7;
+    /// The `atype` of `newarray` creating a `byte[]`.
+    pub const T_BYTE: u8 = 8;
+}
+/// The computational kind of a JVM value: decides which load, store and return instructions are used.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub(super) enum Kind {
+    Int,
+    Long,
+    Float,
+    Double,
+    Ref,
+}
+impl Kind {
+    /// The amount of local variable / operand stack slots a value of this kind takes up.
+    pub(super) fn slots(self) -> u16 {
+        match self {
+            Self::Long | Self::Double => 2,
+            _ => 1,
+        }
+    }
+    fn load_op(self) -> u8 {
+        match self {
+            Self::Int => op::ILOAD,
+            Self::Long => op::LLOAD,
+            Self::Float => op::FLOAD,
+            Self::Double => op::DLOAD,
+            Self::Ref => op::ALOAD,
+        }
+    }
+    fn store_op(self) -> u8 {
+        match self {
+            Self::Int => op::ISTORE,
+            Self::Long => op::LSTORE,
+            Self::Float => op::FSTORE,
+            Self::Double => op::DSTORE,
+            Self::Ref => op::ASTORE,
+        }
+    }
+    pub(super) fn return_op(self) -> u8 {
+        match self {
+            Self::Int => op::IRETURN,
+            Self::Long => op::LRETURN,
+            Self::Float => op::FRETURN,
+            Self::Double => op::DRETURN,
+            Self::Ref => op::ARETURN,
+        }
+    }
+    /// The kind of a value with the field descriptor `desc`.
+    pub(super) fn of_desc(desc: &str) -> Option<Self> {
+        match desc.as_bytes().first()? {
+            b'V' => None,
+            b'J' => Some(Self::Long),
+            b'F' => Some(Self::Float),
+            b'D' => Some(Self::Double),
+            b'L' | b'[' => Some(Self::Ref),
+            _ => Some(Self::Int),
+        }
+    }
+}
+/// Splits a method descriptor into its argument descriptors and its return descriptor.
+pub(super) fn split_method_desc(desc: &str) -> (Vec<&str>, &str) {
+    let (args, ret) = desc[1..]
+        .split_once(')')
+        .expect("ERROR: malformed method descriptor");
+    let mut res = Vec::new();
+    let mut rest = args;
+    while !rest.is_empty() {
+        let dims = rest.bytes().take_while(|byte| *byte == b'[').count();
+        let len = match rest.as_bytes()[dims] {
+            b'L' => rest.find(';').expect("ERROR: malformed class descriptor") + 1,
+            _ => dims + 1,
+        };
+        res.push(&rest[..len]);
+        rest = &rest[len..];
+    }
+    (res, ret)
+}
+/// The amount of stack slots taken by the arguments of a method, and by its return value.
+fn desc_slots(desc: &str) -> (i32, i32) {
+    let (args, ret) = split_method_desc(desc);
+    let args = args
+        .iter()
+        .filter_map(|arg| Kind::of_desc(arg))
+        .map(|kind| i32::from(kind.slots()))
+        .sum();
+    let ret = Kind::of_desc(ret).map_or(0, |kind| i32::from(kind.slots()));
+    (args, ret)
+}
+/// Encodes `string` in the "modified UTF-8" used by class files.
+pub(super) fn modified_utf8(string: &str) -> Vec<u8> {
+    let mut res = Vec::with_capacity(string.len());
+    for unit in string.encode_utf16() {
+        match unit {
+            1..=0x7f => res.push(unit as u8),
+            0 | 0x80..=0x7ff => {
+                res.push(0xc0 | (unit >> 6) as u8);
+                res.push(0x80 | (unit & 0x3f) as u8);
+            }
+            _ => {
+                res.push(0xe0 | (unit >> 12) as u8);
+                res.push(0x80 | ((unit >> 6) & 0x3f) as u8);
+                res.push(0x80 | (unit & 0x3f) as u8);
+            }
+        }
+    }
+    res
+}
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Constant {
+    Utf8(String),
+    Int(i32),
+    Float(u32),
+    Long(i64),
+    Double(u64),
+    Class(u16),
+    String(u16),
+    NameAndType(u16, u16),
+    Field(u16, u16),
+    Method(u16, u16),
+}
+/// A deduplicated constant pool of a single class.
+pub(super) struct ConstPool {
+    bytes: Vec<u8>,
+    next: u16,
+    map: FxHashMap<Constant, u16>,
+}
+impl Default for ConstPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl ConstPool {
+    /// The maximum amount of entries a constant pool may have.
+    pub(super) const LIMIT: u16 = u16::MAX - 1;
+    pub(super) fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            next: 1,
+            map: FxHashMap::default(),
+        }
+    }
+    fn alloc(&mut self, constant: Constant) -> u16 {
+        if let Some(idx) = self.map.get(&constant) {
+            return *idx;
+        }
+        let idx = self.next;
+        let (tag, size) = match &constant {
+            Constant::Utf8(_) => (1, 1),
+            Constant::Int(_) => (3, 1),
+            Constant::Float(_) => (4, 1),
+            Constant::Long(_) => (5, 2),
+            Constant::Double(_) => (6, 2),
+            Constant::Class(_) => (7, 1),
+            Constant::String(_) => (8, 1),
+            Constant::Field(_, _) => (9, 1),
+            Constant::Method(_, _) => (10, 1),
+            Constant::NameAndType(_, _) => (12, 1),
+        };
+        self.next = self
+            .next
+            .checked_add(size)
+            .filter(|next| *next <= Self::LIMIT)
+            .expect("ERROR: constant pool overflow");
+        self.bytes.push(tag);
+        match &constant {
+            Constant::Utf8(string) => {
+                let encoded = modified_utf8(string);
+                let len = u16::try_from(encoded.len()).expect("ERROR: constant string too long");
+                self.bytes.extend(len.to_be_bytes());
+                self.bytes.extend(encoded);
+            }
+            Constant::Int(val) => self.bytes.extend(val.to_be_bytes()),
+            Constant::Float(val) => self.bytes.extend(val.to_be_bytes()),
+            Constant::Long(val) => self.bytes.extend(val.to_be_bytes()),
+            Constant::Double(val) => self.bytes.extend(val.to_be_bytes()),
+            Constant::Class(idx) | Constant::String(idx) => self.bytes.extend(idx.to_be_bytes()),
+            Constant::NameAndType(a, b) | Constant::Field(a, b) | Constant::Method(a, b) => {
+                self.bytes.extend(a.to_be_bytes());
+                self.bytes.extend(b.to_be_bytes());
+            }
+        }
+        self.map.insert(constant, idx);
+        idx
+    }
+    pub(super) fn utf8(&mut self, string: &str) -> u16 {
+        self.alloc(Constant::Utf8(string.into()))
+    }
+    pub(super) fn int(&mut self, val: i32) -> u16 {
+        self.alloc(Constant::Int(val))
+    }
+    pub(super) fn float(&mut self, val: f32) -> u16 {
+        self.alloc(Constant::Float(val.to_bits()))
+    }
+    pub(super) fn long(&mut self, val: i64) -> u16 {
+        self.alloc(Constant::Long(val))
+    }
+    pub(super) fn double(&mut self, val: f64) -> u16 {
+        self.alloc(Constant::Double(val.to_bits()))
+    }
+    pub(super) fn class(&mut self, name: &str) -> u16 {
+        let name = self.utf8(name);
+        self.alloc(Constant::Class(name))
+    }
+    pub(super) fn string(&mut self, string: &str) -> u16 {
+        let string = self.utf8(string);
+        self.alloc(Constant::String(string))
+    }
+    fn name_and_type(&mut self, name: &str, desc: &str) -> u16 {
+        let name = self.utf8(name);
+        let desc = self.utf8(desc);
+        self.alloc(Constant::NameAndType(name, desc))
+    }
+    pub(super) fn field(&mut self, class: &str, name: &str, desc: &str) -> u16 {
+        let class = self.class(class);
+        let nat = self.name_and_type(name, desc);
+        self.alloc(Constant::Field(class, nat))
+    }
+    pub(super) fn method(&mut self, class: &str, name: &str, desc: &str) -> u16 {
+        let class = self.class(class);
+        let nat = self.name_and_type(name, desc);
+        self.alloc(Constant::Method(class, nat))
+    }
+}
+/// A position in the bytecode, which may be jumped to before it is placed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) struct Label(u32);
+struct Fixup {
+    /// The offset of the jumping instruction: branch offsets are relative to it.
+    insn: usize,
+    /// The offset at which the branch offset is written.
+    at: usize,
+    wide: bool,
+    target: Label,
+}
+/// An exception table entry.
+struct Handler {
+    start: Label,
+    end: Label,
+    handler: Label,
+    catch_type: u16,
+}
+/// The reasons why a method body could not be assembled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum CodeError {
+    /// The body is larger than the 64 KiB the JVM supports.
+    TooLong,
+    /// A jump is too far for a 16 bit branch offset.
+    JumpTooFar,
+}
+/// A bytecode assembler: tracks labels, the depth of the operand stack and the amount of locals used.
+pub(super) struct Code {
+    bytes: Vec<u8>,
+    depth: i32,
+    max_stack: i32,
+    max_locals: u16,
+    labels: Vec<Option<usize>>,
+    fixups: Vec<Fixup>,
+    handlers: Vec<Handler>,
+}
+impl Code {
+    /// Creates a new, empty body, whose arguments take up `arg_slots` local slots.
+    pub(super) fn new(arg_slots: u16) -> Self {
+        Self {
+            bytes: Vec::new(),
+            depth: 0,
+            max_stack: 0,
+            max_locals: arg_slots,
+            labels: Vec::new(),
+            fixups: Vec::new(),
+            handlers: Vec::new(),
+        }
+    }
+    /// The offset at which the next instruction will be emitted.
+    pub(super) fn pos(&self) -> usize {
+        self.bytes.len()
+    }
+    /// The current depth of the operand stack, in slots.
+    pub(super) fn depth(&self) -> i32 {
+        self.depth
+    }
+    /// Sets the depth of the operand stack: used after unconditional jumps and at handler entry.
+    pub(super) fn set_depth(&mut self, depth: i32) {
+        self.depth = depth;
+        self.max_stack = self.max_stack.max(depth);
+    }
+    fn adjust(&mut self, delta: i32) {
+        self.set_depth(self.depth + delta);
+        debug_assert!(self.depth >= 0, "ERROR: operand stack underflow");
+    }
+    /// Allocates a new local variable, taking up `slots` slots.
+    pub(super) fn alloc_local(&mut self, slots: u16) -> u16 {
+        let idx = self.max_locals;
+        self.max_locals = self
+            .max_locals
+            .checked_add(slots)
+            .expect("ERROR: too many JVM locals");
+        idx
+    }
+    /// Emits a single byte instruction, changing the stack depth by `delta`.
+    pub(super) fn op(&mut self, opcode: u8, delta: i32) {
+        self.bytes.push(opcode);
+        self.adjust(delta);
+    }
+    pub(super) fn op_u8(&mut self, opcode: u8, arg: u8, delta: i32) {
+        self.bytes.extend([opcode, arg]);
+        self.adjust(delta);
+    }
+    pub(super) fn op_u16(&mut self, opcode: u8, arg: u16, delta: i32) {
+        self.bytes.push(opcode);
+        self.bytes.extend(arg.to_be_bytes());
+        self.adjust(delta);
+    }
+    /// Overwrites the 16 bit operand at `at`. Used for values only known once the body is complete.
+    pub(super) fn patch_u16(&mut self, at: usize, val: u16) {
+        self.bytes[at..at + 2].copy_from_slice(&val.to_be_bytes());
+    }
+    fn local_op(&mut self, opcode: u8, idx: u16, delta: i32) {
+        match u8::try_from(idx) {
+            Ok(idx) => self.op_u8(opcode, idx, delta),
+            Err(_) => {
+                self.bytes.push(op::WIDE);
+                self.op_u16(opcode, idx, delta);
+            }
+        }
+    }
+    pub(super) fn load(&mut self, kind: Kind, idx: u16) {
+        self.local_op(kind.load_op(), idx, i32::from(kind.slots()));
+    }
+    pub(super) fn store(&mut self, kind: Kind, idx: u16) {
+        self.local_op(kind.store_op(), idx, -i32::from(kind.slots()));
+    }
+    pub(super) fn push_int(&mut self, cp: &mut ConstPool, val: i32) {
+        match val {
+            -1..=5 => self.op((i32::from(op::ICONST_0) + val) as u8, 1),
+            -128..=127 => self.op_u8(op::BIPUSH, val as u8, 1),
+            -32768..=32767 => self.op_u16(op::SIPUSH, val as u16, 1),
+            _ => self.ldc(cp.int(val), 1),
+        }
+    }
+    pub(super) fn push_long(&mut self, cp: &mut ConstPool, val: i64) {
+        match val {
+            0 => self.op(op::LCONST_0, 2),
+            1 => self.op(op::LCONST_1, 2),
+            _ => {
+                if let Ok(val) = i32::try_from(val) {
+                    self.push_int(cp, val);
+                    self.op(op::I2L, 1);
+                } else {
+                    self.op_u16(op::LDC2_W, cp.long(val), 2);
+                }
+            }
+        }
+    }
+    pub(super) fn push_float(&mut self, cp: &mut ConstPool, val: f32) {
+        if val.to_bits() == 0 {
+            self.op(op::FCONST_0, 1);
+        } else {
+            self.ldc(cp.float(val), 1);
+        }
+    }
+    pub(super) fn push_double(&mut self, cp: &mut ConstPool, val: f64) {
+        if val.to_bits() == 0 {
+            self.op(op::DCONST_0, 2);
+        } else {
+            self.op_u16(op::LDC2_W, cp.double(val), 2);
+        }
+    }
+    /// Loads the single slot constant `idx`.
+    pub(super) fn ldc(&mut self, idx: u16, delta: i32) {
+        match u8::try_from(idx) {
+            Ok(idx) => self.op_u8(op::LDC, idx, delta),
+            Err(_) => self.op_u16(op::LDC_W, idx, delta),
+        }
+    }
+    /// Emits an `invoke*` instruction calling the method `idx` with the descriptor `desc`.
+    pub(super) fn invoke(&mut self, opcode: u8, idx: u16, desc: &str) {
+        let (args, ret) = desc_slots(desc);
+        let this = i32::from(opcode != op::INVOKESTATIC);
+        self.op_u16(opcode, idx, ret - args - this);
+    }
+    /// Emits a field access instruction, for the field `idx` with the descriptor `desc`.
+    pub(super) fn field(&mut self, opcode: u8, idx: u16, desc: &str) {
+        let size = Kind::of_desc(desc).map_or(0, |kind| i32::from(kind.slots()));
+        let delta = match opcode {
+            op::GETSTATIC => size,
+            op::PUTSTATIC => -size,
+            op::GETFIELD => size - 1,
+            op::PUTFIELD => -size - 1,
+            _ => panic!("ERROR: {opcode:x} is not a field access instruction"),
+        };
+        self.op_u16(opcode, idx, delta);
+    }
+    pub(super) fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(u32::try_from(self.labels.len() - 1).expect("ERROR: too many labels"))
+    }
+    /// Places `label` at the current position.
+    pub(super) fn place(&mut self, label: Label) {
+        debug_assert!(
+            self.labels[label.0 as usize].is_none(),
+            "ERROR: label placed twice"
+        );
+        self.labels[label.0 as usize] = Some(self.bytes.len());
+    }
+    /// Emits a branch instruction jumping to `target`, which pops `pops` slots.
+    pub(super) fn jump(&mut self, opcode: u8, target: Label, pops: i32) {
+        let insn = self.bytes.len();
+        self.bytes.extend([opcode, 0, 0]);
+        self.fixups.push(Fixup {
+            insn,
+            at: insn + 1,
+            wide: false,
+            target,
+        });
+        self.adjust(-pops);
+    }
+    /// Emits a `lookupswitch` over the int on top of the stack.
+    pub(super) fn lookupswitch(&mut self, default: Label, cases: &mut [(i32, Label)]) {
+        cases.sort_by_key(|(key, _)| *key);
+        let insn = self.bytes.len();
+        self.bytes.push(op::LOOKUPSWITCH);
+        while self.bytes.len() % 4 != 0 {
+            self.bytes.push(0);
+        }
+        let mut wide_fixup = |bytes: &mut Vec<u8>, target| {
+            self.fixups.push(Fixup {
+                insn,
+                at: bytes.len(),
+                wide: true,
+                target,
+            });
+            bytes.extend([0; 4]);
+        };
+        wide_fixup(&mut self.bytes, default);
+        self.bytes.extend(
+            i32::try_from(cases.len())
+                .expect("ERROR: too many switch cases")
+                .to_be_bytes(),
+        );
+        for (key, target) in cases.iter() {
+            self.bytes.extend(key.to_be_bytes());
+            wide_fixup(&mut self.bytes, *target);
+        }
+        self.adjust(-1);
+    }
+    /// Registers an exception handler at `handler`, covering the code between `start` and `end`.
+    pub(super) fn handler(&mut self, start: Label, end: Label, handler: Label, catch_type: u16) {
+        self.handlers.push(Handler {
+            start,
+            end,
+            handler,
+            catch_type,
+        });
+    }
+    /// Records the current state of this body, so that code emitted after this point can be discarded.
+    pub(super) fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            bytes: self.bytes.len(),
+            depth: self.depth,
+            labels: self.labels.len(),
+            fixups: self.fixups.len(),
+            handlers: self.handlers.len(),
+        }
+    }
+    /// Discards all code emitted since `checkpoint`. Labels created since then must not be used anymore.
+    pub(super) fn rollback(&mut self, checkpoint: &Checkpoint) {
+        self.bytes.truncate(checkpoint.bytes);
+        self.depth = checkpoint.depth;
+        self.labels.truncate(checkpoint.labels);
+        self.fixups.truncate(checkpoint.fixups);
+        self.handlers.truncate(checkpoint.handlers);
+    }
+    fn label_pos(&self, label: Label) -> usize {
+        self.labels[label.0 as usize].expect("ERROR: jump to a label which was never placed")
+    }
+    /// Resolves all jumps, and serializes this body as a `Code` attribute.
+    pub(super) fn finish(mut self, cp: &mut ConstPool) -> Result<Vec<u8>, CodeError> {
+        if self.bytes.len() >= usize::from(u16::MAX) {
+            return Err(CodeError::TooLong);
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let offset = self.label_pos(fixup.target) as i64 - fixup.insn as i64;
+            if fixup.wide {
+                self.bytes[fixup.at..fixup.at + 4].copy_from_slice(&(offset as i32).to_be_bytes());
+            } else {
+                let offset = i16::try_from(offset).map_err(|_| CodeError::JumpTooFar)?;
+                self.bytes[fixup.at..fixup.at + 2].copy_from_slice(&offset.to_be_bytes());
+            }
+        }
+        let mut handlers = Vec::new();
+        for handler in &self.handlers {
+            let start = self.label_pos(handler.start);
+            let end = self.label_pos(handler.end);
+            // Empty ranges are not allowed, and can't throw anyway.
+            if start == end {
+                continue;
+            }
+            for pos in [start, end, self.label_pos(handler.handler)] {
+                handlers.extend((pos as u16).to_be_bytes());
+            }
+            handlers.extend(handler.catch_type.to_be_bytes());
+        }
+        let mut attr = Vec::with_capacity(self.bytes.len() + handlers.len() + 18);
+        attr.extend(cp.utf8("Code").to_be_bytes());
+        let len = 2 + 2 + 4 + self.bytes.len() + 2 + handlers.len() + 2;
+        attr.extend((len as u32).to_be_bytes());
+        attr.extend((self.max_stack as u16).to_be_bytes());
+        attr.extend(self.max_locals.to_be_bytes());
+        attr.extend((self.bytes.len() as u32).to_be_bytes());
+        attr.extend(&self.bytes);
+        attr.extend(((handlers.len() / 8) as u16).to_be_bytes());
+        attr.extend(handlers);
+        attr.extend(0_u16.to_be_bytes());
+        Ok(attr)
+    }
+}
+/// The state of a [`Code`] at some point, see [`Code::checkpoint`].
+pub(super) struct Checkpoint {
+    bytes: usize,
+    depth: i32,
+    labels: usize,
+    fixups: usize,
+    handlers: usize,
+}
+/// A method body being written, together with the constant pool of its class.
+pub(super) struct Builder<'cp> {
+    pub(super) code: Code,
+    pub(super) cp: &'cp mut ConstPool,
+}
+impl<'cp> Builder<'cp> {
+    pub(super) fn new(cp: &'cp mut ConstPool, arg_slots: u16) -> Self {
+        Self {
+            code: Code::new(arg_slots),
+            cp,
+        }
+    }
+    pub(super) fn op(&mut self, opcode: u8, delta: i32) {
+        self.code.op(opcode, delta);
+    }
+    pub(super) fn int(&mut self, val: i32) {
+        self.code.push_int(self.cp, val);
+    }
+    pub(super) fn long(&mut self, val: i64) {
+        self.code.push_long(self.cp, val);
+    }
+    pub(super) fn float(&mut self, val: f32) {
+        self.code.push_float(self.cp, val);
+    }
+    pub(super) fn double(&mut self, val: f64) {
+        self.code.push_double(self.cp, val);
+    }
+    /// Pushes a `java.lang.String` constant.
+    pub(super) fn string(&mut self, val: &str) {
+        let idx = self.cp.string(val);
+        self.code.ldc(idx, 1);
+    }
+    /// Pushes the `java.lang.Class` object of the class `name`.
+    pub(super) fn class_const(&mut self, name: &str) {
+        let idx = self.cp.class(name);
+        self.code.ldc(idx, 1);
+    }
+    pub(super) fn load(&mut self, kind: Kind, idx: u16) {
+        self.code.load(kind, idx);
+    }
+    pub(super) fn store(&mut self, kind: Kind, idx: u16) {
+        self.code.store(kind, idx);
+    }
+    /// Allocates a new local variable of `kind`.
+    pub(super) fn local(&mut self, kind: Kind) -> u16 {
+        self.code.alloc_local(kind.slots())
+    }
+    pub(super) fn invoke(&mut self, opcode: u8, class: &str, name: &str, desc: &str) {
+        let idx = self.cp.method(class, name, desc);
+        self.code.invoke(opcode, idx, desc);
+    }
+    /// Calls the static method `class::name`.
+    pub(super) fn call(&mut self, class: &str, name: &str, desc: &str) {
+        self.invoke(op::INVOKESTATIC, class, name, desc);
+    }
+    pub(super) fn field(&mut self, opcode: u8, class: &str, name: &str, desc: &str) {
+        let idx = self.cp.field(class, name, desc);
+        self.code.field(opcode, idx, desc);
+    }
+    /// Emits an instruction taking a class operand: `new`, `checkcast`, `instanceof` or `anewarray`.
+    pub(super) fn type_op(&mut self, opcode: u8, class: &str) {
+        let idx = self.cp.class(class);
+        let delta = i32::from(opcode == op::NEW);
+        self.code.op_u16(opcode, idx, delta);
+    }
+    pub(super) fn label(&mut self) -> Label {
+        self.code.new_label()
+    }
+    pub(super) fn place(&mut self, label: Label) {
+        self.code.place(label);
+    }
+    /// Emits a conditional or unconditional jump to `target`.
+    pub(super) fn jump(&mut self, opcode: u8, target: Label) {
+        let pops = match opcode {
+            op::IFEQ..=op::IFLE | op::IFNULL | op::IFNONNULL => 1,
+            op::IF_ICMPEQ..=op::IF_ACMPNE => 2,
+            _ => 0,
+        };
+        self.code.jump(opcode, target, pops);
+    }
+    /// Returns a value of `kind`, or nothing.
+    pub(super) fn ret(&mut self, kind: Option<Kind>) {
+        match kind {
+            Some(kind) => self.op(kind.return_op(), -i32::from(kind.slots())),
+            None => self.op(op::RETURN, 0),
+        }
+    }
+    /// Throws a new exception of `class`, which has a constructor accepting a message.
+    pub(super) fn throw_new(&mut self, class: &str, msg: &str) {
+        // Constant strings are limited to 64 KiB, so long messages get cut short.
+        let msg = match msg.char_indices().nth(2048) {
+            Some((end, _)) => &msg[..end],
+            None => msg,
+        };
+        self.type_op(op::NEW, class);
+        self.op(op::DUP, 1);
+        self.string(msg);
+        self.invoke(op::INVOKESPECIAL, class, "<init>", "(Ljava/lang/String;)V");
+        self.op(op::ATHROW, -1);
+    }
+    pub(super) fn finish(self) -> Result<Vec<u8>, CodeError> {
+        self.code.finish(self.cp)
+    }
+}
+/// A single JVM class, being built.
+pub(super) struct ClassFile {
+    pub(super) cp: ConstPool,
+    name: String,
+    access: u16,
+    this: u16,
+    extends: u16,
+    fields: Vec<u8>,
+    field_count: u16,
+    methods: Vec<u8>,
+    method_count: u16,
+}
+impl ClassFile {
+    pub(super) fn new(name: &str, extends: &str, access: u16) -> Self {
+        let mut cp = ConstPool::new();
+        let this = cp.class(name);
+        let extends = cp.class(extends);
+        Self {
+            cp,
+            name: name.into(),
+            access: access | ACC_SUPER,
+            this,
+            extends,
+            fields: Vec::new(),
+            field_count: 0,
+            methods: Vec::new(),
+            method_count: 0,
+        }
+    }
+    /// The internal (slash separated) name of this class.
+    pub(super) fn name(&self) -> &str {
+        &self.name
+    }
+    pub(super) fn add_field(&mut self, access: u16, name: &str, desc: &str) {
+        let name = self.cp.utf8(name);
+        let desc = self.cp.utf8(desc);
+        for val in [access, name, desc, 0] {
+            self.fields.extend(val.to_be_bytes());
+        }
+        self.field_count += 1;
+    }
+    /// Adds a method, whose body is the `Code` attribute `code` (as returned by [`Code::finish`]).
+    pub(super) fn add_method(&mut self, access: u16, name: &str, desc: &str, code: &[u8]) {
+        let name = self.cp.utf8(name);
+        let desc = self.cp.utf8(desc);
+        for val in [access, name, desc, 1] {
+            self.methods.extend(val.to_be_bytes());
+        }
+        self.methods.extend(code);
+        self.method_count += 1;
+    }
+    pub(super) fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.cp.bytes.len() + self.methods.len() + 64);
+        out.extend(0xCAFE_BABE_u32.to_be_bytes());
+        out.extend(0_u16.to_be_bytes());
+        out.extend(MAJOR_VERSION.to_be_bytes());
+        out.extend(self.cp.next.to_be_bytes());
+        out.extend(&self.cp.bytes);
+        for val in [self.access, self.this, self.extends, 0, self.field_count] {
+            out.extend(val.to_be_bytes());
+        }
+        out.extend(&self.fields);
+        out.extend(self.method_count.to_be_bytes());
+        out.extend(&self.methods);
+        out.extend(0_u16.to_be_bytes());
+        out
+    }
+}
+#[test]
+fn const_pool_dedup() {
+    let mut cp = ConstPool::new();
+    let a = cp.method("java/lang/Math", "sqrt", "(D)D");
+    let b = cp.method("java/lang/Math", "sqrt", "(D)D");
+    assert_eq!(a, b);
+    let long = cp.long(1 << 40);
+    // Longs take up two slots.
+    assert_eq!(cp.int(7), long + 2);
+}
+#[test]
+fn desc_split() {
+    let (args, ret) = split_method_desc("(IJ[Ljava/lang/String;Ljava/lang/Object;[[DZ)V");
+    assert_eq!(
+        args,
+        [
+            "I",
+            "J",
+            "[Ljava/lang/String;",
+            "Ljava/lang/Object;",
+            "[[D",
+            "Z"
+        ]
+    );
+    assert_eq!(ret, "V");
+    assert_eq!(desc_slots("(IJD)J"), (5, 2));
+}
+#[test]
+fn class_layout() {
+    let mut class = ClassFile::new("Hello", "java/lang/Object", ACC_PUBLIC);
+    let mut code = Code::new(1);
+    let skip = code.new_label();
+    code.load(Kind::Long, 0);
+    code.op(op::L2I, -1);
+    code.jump(op::IFEQ, skip, 1);
+    code.push_int(&mut class.cp, 100_000);
+    code.op(op::POP, -1);
+    code.place(skip);
+    code.op(op::RETURN, 0);
+    let code = code.finish(&mut class.cp).unwrap();
+    class.add_method(ACC_PUBLIC | ACC_STATIC, "run", "(J)V", &code);
+    let bytes = class.to_bytes();
+    assert_eq!(&bytes[..4], &[0xCA, 0xFE, 0xBA, 0xBE]);
+    assert_eq!(u16::from_be_bytes([bytes[6], bytes[7]]), MAJOR_VERSION);
+    // lload 0 (2 bytes), l2i, ifeq: the jump skips the ldc (2 bytes) and pop.
+    assert!(bytes.windows(3).any(|w| w == [op::IFEQ, 0, 6]));
+    assert_eq!(modified_utf8("\0"), [0xc0, 0x80]);
+    assert_eq!(modified_utf8("😀").len(), 6);
+}
@@ -0,0 +1,116 @@
+//! A minimal writer of uncompressed (stored) zip archives, used to package the exported classes as a jar.
+use std::io::Write;
+
+/// Computes the CRC-32 (as used by zip) of `bytes`.
+pub(super) fn crc32(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut idx = 0;
+        while idx < 256 {
+            let mut crc = idx as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 == 1 {
+                    0xEDB8_8320 ^ (crc >> 1)
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[idx] = crc;
+            idx += 1;
+        }
+        table
+    };
+    !bytes.iter().fold(!0, |crc, byte| {
+        TABLE[((crc ^ u32::from(*byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+/// A jar file, being built in memory.
+#[derive(Default)]
+pub(super) struct Jar {
+    data: Vec<u8>,
+    central: Vec<u8>,
+    entries: u16,
+}
+impl Jar {
+    /// Creates a new jar. If `main_class` is set, the manifest marks it as the class to run.
+    pub(super) fn new(main_class: Option<&str>) -> Self {
+        let mut jar = Self::default();
+        let mut manifest = "Manifest-Version: 1.0\r\nCreated-By: cilly\r\n".to_owned();
+        if let Some(main_class) = main_class {
+            manifest.push_str(&format!("Main-Class: {}\r\n", main_class.replace('/', ".")));
+        }
+        manifest.push_str("\r\n");
+        jar.add("META-INF/MANIFEST.MF", manifest.as_bytes());
+        jar
+    }
+    /// Adds a file named `name`, with the contents `bytes`.
+    pub(super) fn add(&mut self, name: &str, bytes: &[u8]) {
+        let offset = u32::try_from(self.data.len()).expect("ERROR: jar larger than 4 GiB");
+        let crc = crc32(bytes);
+        let size = u32::try_from(bytes.len()).expect("ERROR: jar entry larger than 4 GiB");
+        let name_len = u16::try_from(name.len()).expect("ERROR: jar entry name too long");
+        // Local file header: version 1.0, no flags, stored, no timestamp.
+        self.data.extend(0x0403_4b50_u32.to_le_bytes());
+        for val in [10_u16, 0, 0, 0, 0x21] {
+            self.data.extend(val.to_le_bytes());
+        }
+        for val in [crc, size, size] {
+            self.data.extend(val.to_le_bytes());
+        }
+        self.data.extend(name_len.to_le_bytes());
+        self.data.extend(0_u16.to_le_bytes());
+        self.data.extend(name.as_bytes());
+        self.data.extend(bytes);
+        // Central directory record
+        self.central.extend(0x0201_4b50_u32.to_le_bytes());
+        for val in [10_u16, 10, 0, 0, 0, 0x21] {
+            self.central.extend(val.to_le_bytes());
+        }
+        for val in [crc, size, size] {
+            self.central.extend(val.to_le_bytes());
+        }
+        for val in [name_len, 0, 0, 0, 0] {
+            self.central.extend(val.to_le_bytes());
+        }
+        self.central.extend(0_u32.to_le_bytes());
+        self.central.extend(offset.to_le_bytes());
+        self.central.extend(name.as_bytes());
+        self.entries = self
+            .entries
+            .checked_add(1)
+            .expect("ERROR: too many jar entries");
+    }
+    /// Writes the complete archive to `out`.
+    pub(super) fn write(&self, out: &mut impl Write) -> std::io::Result<()> {
+        out.write_all(&self.data)?;
+        out.write_all(&self.central)?;
+        out.write_all(&0x0605_4b50_u32.to_le_bytes())?;
+        for val in [0_u16, 0, self.entries, self.entries] {
+            out.write_all(&val.to_le_bytes())?;
+        }
+        out.write_all(&(self.central.len() as u32).to_le_bytes())?;
+        out.write_all(&(self.data.len() as u32).to_le_bytes())?;
+        out.write_all(&0_u16.to_le_bytes())
+    }
+}
+#[test]
+fn crc() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+}
+#[test]
+fn jar_layout() {
+    let mut jar = Jar::new(Some("a/Main"));
+    jar.add("a/Main.class", &[0xCA, 0xFE, 0xBA, 0xBE]);
+    let mut out = Vec::new();
+    jar.write(&mut out).unwrap();
+    assert_eq!(&out[..4], b"PK\x03\x04");
+    // The end of central directory record is 22 bytes long, and records 2 entries.
+    let eocd = &out[out.len() - 22..];
+    assert_eq!(&eocd[..4], b"PK\x05\x06");
+    assert_eq!(u16::from_le_bytes([eocd[10], eocd[11]]), 2);
+    let manifest = String::from_utf8_lossy(&out);
+    assert!(manifest.contains("Main-Class: a.Main\r\n"));
+}
@@ -0,0 +1,1531 @@
+//! Lowers the CIL trees of a method body to JVM bytecode.
+//!
+//! Every root is lowered on its own, leaving the operand stack empty. A root which can't be
+//! lowered (because it uses an unsupported type or method) is replaced by code throwing an
+//! `UnsupportedOperationException`, so that the rest of the method can still run.
+use fxhash::{FxHashMap, FxHashSet};
+
+use super::{
+    class_file::{self, op, Builder, ClassFile, ConstPool, Kind, Label, ACC_PUBLIC, ACC_STATIC},
+    dispatch_desc, runtime, stub, Ctx, JvmMethod, Native, Repr, StaticSlot, Target, DISPATCH,
+    RUNTIME,
+};
+use crate::v2::{
+    cilnode::{ExtendKind, PtrCastRes, UnOp},
+    cilroot::CmpKind,
+    method::LocalDef,
+    BasicBlock, BinOp, BranchCond, CILNode, CILRoot, ClassRef, Const, Float, Int, MethodDefIdx,
+    MethodImpl, MethodRefIdx, NodeIdx, RootIdx, SwitchPlan, SwitchTable, Type,
+};
+
+/// Adds the method `def` to `class`, as the JVM method `jvm`.
+pub(super) fn method(ctx: &mut Ctx, class: &mut ClassFile, def: MethodDefIdx, jvm: &JvmMethod) {
+    let is_init = jvm.name == "<init>";
+    let access = if is_init {
+        ACC_PUBLIC
+    } else {
+        ACC_PUBLIC | ACC_STATIC
+    };
+    let mdef = ctx.asm[def].clone();
+    let name = ctx.asm[mdef.name()].to_owned();
+    let res = match mdef.implementation() {
+        MethodImpl::MethodBody { blocks, locals } => {
+            let sig = ctx.asm[mdef.sig()].clone();
+            let extends = if is_init {
+                ctx.super_class(mdef.class())
+            } else {
+                Ok(String::new())
+            };
+            extends.and_then(|extends| {
+                body(
+                    ctx,
+                    &mut class.cp,
+                    sig.inputs(),
+                    *sig.output(),
+                    locals,
+                    blocks,
+                    is_init.then_some(extends.as_str()),
+                )
+            })
+        }
+        MethodImpl::Extern { .. } => {
+            let sig = ctx.asm[mdef.sig()].clone();
+            let native = Native {
+                class: None,
+                name: name.clone(),
+                inputs: sig.inputs().to_vec(),
+                output: *sig.output(),
+            };
+            runtime::native_code(ctx, &mut class.cp, &native)
+        }
+        MethodImpl::Missing => Err("the method is missing".into()),
+        MethodImpl::AliasFor(_) => return,
+    };
+    match res {
+        Ok(code) => class.add_method(access, &jvm.name, &jvm.desc, &code),
+        Err(msg) => stub(
+            class,
+            access,
+            &jvm.name,
+            &jvm.desc,
+            &format!("Can't call {name}: {msg}"),
+        ),
+    }
+}
+/// Where a local or argument is stored.
+#[derive(Clone, Copy)]
+enum Slot {
+    /// In a JVM local.
+    Jvm(Kind, u16),
+    /// In the shadow stack frame, at an offset.
+    Frame(u64),
+}
+/// A comparison, in the order of the JVM conditional jumps.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Ge,
+    Gt,
+    Le,
+}
+impl Cmp {
+    /// Offsets `ifeq` or `if_icmpeq` to the jump performing this comparison.
+    fn jump(self, base: u8) -> u8 {
+        base + self as u8
+    }
+    fn is_relational(self) -> bool {
+        !matches!(self, Self::Eq | Self::Ne)
+    }
+}
+/// A try block, and the state needed to enter its handler.
+struct Try {
+    /// The JVM local holding the shadow stack pointer at the start of the block.
+    saved_sp: u16,
+    entry: Label,
+}
+struct Lower<'ctx, 'cp> {
+    ctx: &'ctx mut Ctx,
+    b: Builder<'cp>,
+    inputs: Vec<Type>,
+    output: Type,
+    locals: Vec<Type>,
+    args: Vec<Slot>,
+    loc_slots: Vec<Slot>,
+    /// The JVM local holding the frame pointer.
+    fp: u16,
+    /// The size of the part of the frame holding locals and arguments.
+    fixed: u64,
+    /// The end of the temporaries of the current root.
+    temps: u64,
+    frame_size: u64,
+    /// JVM locals used as temporaries, and the amount of them used by the current root.
+    scratch: FxHashMap<Kind, (Vec<u16>, usize)>,
+    labels: FxHashMap<u32, Label>,
+    /// The labels of the blocks of the handler being lowered.
+    handler: Option<FxHashMap<u32, Label>>,
+    in_region: bool,
+    exception: Option<u16>,
+}
+/// Collects the locals and arguments whose address is taken.
+fn taken(ctx: &Ctx, blocks: &[BasicBlock], locals: &mut FxHashSet<u32>, args: &mut FxHashSet<u32>) {
+    let mut visited = FxHashSet::default();
+    let mut stack = Vec::new();
+    for block in blocks {
+        for root in block.roots() {
+            stack.extend(ctx.asm[*root].nodes().iter().map(|node| **node));
+        }
+        if let Some(handler) = block.handler() {
+            taken(ctx, handler, locals, args);
+        }
+    }
+    while let Some(node) = stack.pop() {
+        if !visited.insert(node) {
+            continue;
+        }
+        match ctx.asm[node] {
+            CILNode::LdLocA(loc) => {
+                locals.insert(loc);
+            }
+            CILNode::LdArgA(arg) => {
+                args.insert(arg);
+            }
+            _ => (),
+        }
+        stack.extend(ctx.asm[node].child_nodes());
+    }
+}
+fn body(
+    ctx: &mut Ctx,
+    cp: &mut ConstPool,
+    inputs: &[Type],
+    output: Type,
+    locals: &[LocalDef],
+    blocks: &[BasicBlock],
+    extends: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let is_init = extends.is_some();
+    let mut taken_locals = FxHashSet::default();
+    let mut taken_args = FxHashSet::default();
+    taken(ctx, blocks, &mut taken_locals, &mut taken_args);
+    let mut jvm_args = Vec::with_capacity(inputs.len());
+    let mut arg_slots = 0;
+    for (idx, input) in inputs.iter().enumerate() {
+        let kind = if is_init && idx == 0 {
+            Kind::Ref
+        } else {
+            ctx.repr(*input)?
+                .kind()
+                .ok_or_else(|| "void argument".to_owned())?
+        };
+        jvm_args.push((kind, arg_slots));
+        arg_slots += kind.slots();
+    }
+    let mut b = Builder::new(cp, arg_slots);
+    if let Some(extends) = extends {
+        b.load(Kind::Ref, 0);
+        b.invoke(op::INVOKESPECIAL, extends, "<init>", "()V");
+    }
+    // The size of the frame is only known once the whole body has been lowered.
+    let fp = b.local(Kind::Long);
+    let frame_size_at = b.code.pos() + 1;
+    b.code.op_u16(op::LDC_W, 0, 1);
+    b.op(op::I2L, 1);
+    b.call(RUNTIME, "enter", "(J)J");
+    b.store(Kind::Long, fp);
+    let mut lower = Lower {
+        ctx,
+        b,
+        inputs: inputs.to_vec(),
+        output: if is_init { Type::Void } else { output },
+        locals: Vec::new(),
+        args: Vec::new(),
+        loc_slots: Vec::new(),
+        fp,
+        fixed: 0,
+        temps: 0,
+        frame_size: 0,
+        scratch: FxHashMap::default(),
+        labels: FxHashMap::default(),
+        handler: None,
+        in_region: false,
+        exception: None,
+    };
+    let mut frame: u64 = 0;
+    for (idx, (input, (kind, jvm))) in inputs.iter().zip(jvm_args).enumerate() {
+        let repr = lower.ctx.repr(*input)?;
+        if !matches!(repr, Repr::Memory { .. }) && !taken_args.contains(&(idx as u32)) {
+            lower.args.push(Slot::Jvm(kind, jvm));
+            continue;
+        }
+        if kind == Kind::Ref {
+            return Err("can't take the address of an object reference".into());
+        }
+        let size = lower.ctx.size_of(*input)?;
+        frame = frame.next_multiple_of(lower.ctx.align_of(*input)?.clamp(1, 16));
+        lower.args.push(Slot::Frame(frame));
+        lower.frame_addr(frame);
+        lower.b.load(kind, jvm);
+        lower.store_mem(*input)?;
+        frame += size;
+    }
+    for (idx, (_, tpe)) in locals.iter().enumerate() {
+        let tpe = lower.ctx.asm[*tpe];
+        lower.locals.push(tpe);
+        let repr = lower.ctx.repr(tpe)?;
+        match repr.kind() {
+            Some(kind)
+                if !matches!(repr, Repr::Memory { .. })
+                    && !taken_locals.contains(&(idx as u32)) =>
+            {
+                let local = lower.b.local(kind);
+                lower.zero(kind);
+                lower.b.store(kind, local);
+                lower.loc_slots.push(Slot::Jvm(kind, local));
+            }
+            Some(Kind::Ref) => return Err("can't take the address of an object reference".into()),
+            _ => {
+                frame = frame.next_multiple_of(lower.ctx.align_of(tpe)?.clamp(1, 16));
+                lower.loc_slots.push(Slot::Frame(frame));
+                frame += lower.ctx.size_of(tpe)?;
+            }
+        }
+    }
+    lower.fixed = frame;
+    lower.frame_size = frame;
+    let mut tries = FxHashMap::default();
+    for block in blocks {
+        let label = lower.b.label();
+        lower.labels.insert(block.block_id(), label);
+        if block.handler().is_some() {
+            if lower.exception.is_none() {
+                let exception = lower.b.local(Kind::Ref);
+                lower.b.op(op::ACONST_NULL, 1);
+                lower.b.store(Kind::Ref, exception);
+                lower.exception = Some(exception);
+            }
+            let saved_sp = lower.b.local(Kind::Long);
+            lower.b.long(0);
+            lower.b.store(Kind::Long, saved_sp);
+            let entry = lower.b.label();
+            tries.insert(block.block_id(), Try { saved_sp, entry });
+        }
+    }
+    lower.blocks(blocks, &tries)?;
+    let frame_size = lower.frame_size.next_multiple_of(16);
+    let frame_size = i32::try_from(frame_size).map_err(|_| "the frame is too big".to_owned())?;
+    let idx = lower.b.cp.int(frame_size);
+    lower.b.code.patch_u16(frame_size_at, idx);
+    lower
+        .b
+        .finish()
+        .map_err(|err| format!("could not assemble the method: {err:?}"))
+}
+impl Lower<'_, '_> {
+    fn blocks(&mut self, blocks: &[BasicBlock], tries: &FxHashMap<u32, Try>) -> Result<(), String> {
+        for block in blocks {
+            self.b.place(self.labels[&block.block_id()]);
+            self.b.code.set_depth(0);
+            match tries.get(&block.block_id()) {
+                Some(info) => {
+                    self.b.field(op::GETSTATIC, RUNTIME, "sp", "J");
+                    self.b.store(Kind::Long, info.saved_sp);
+                    let start = self.b.label();
+                    self.b.place(start);
+                    self.in_region = true;
+                    self.roots(block.roots());
+                    self.in_region = false;
+                    let end = self.b.label();
+                    self.b.place(end);
+                    self.b.code.handler(start, end, info.entry, 0);
+                }
+                None => self.roots(block.roots()),
+            }
+        }
+        // Falling through the last block is not allowed on the JVM.
+        self.b.throw_new(
+            "java/lang/IllegalStateException",
+            "Control flow reached the end of a method",
+        );
+        for block in blocks {
+            let (Some(handler), Some(info)) = (block.handler(), tries.get(&block.block_id()))
+            else {
+                continue;
+            };
+            self.b.place(info.entry);
+            self.b.code.set_depth(1);
+            self.b.store(Kind::Ref, self.exception.unwrap());
+            // Frames of callees which were unwound by the exception are freed.
+            self.b.load(Kind::Long, info.saved_sp);
+            self.b.field(op::PUTSTATIC, RUNTIME, "sp", "J");
+            let labels: FxHashMap<_, _> = handler
+                .iter()
+                .map(|block| (block.block_id(), self.b.label()))
+                .collect();
+            self.handler = Some(labels.clone());
+            self.in_region = true;
+            for block in handler {
+                self.b.place(labels[&block.block_id()]);
+                self.b.code.set_depth(0);
+                self.roots(block.roots());
+            }
+            self.in_region = false;
+            self.handler = None;
+            self.b.throw_new(
+                "java/lang/IllegalStateException",
+                "Control flow reached the end of a handler",
+            );
+        }
+        Ok(())
+    }
+    fn roots(&mut self, roots: &[RootIdx]) {
+        for root in roots {
+            self.temps = self.fixed;
+            for (_, used) in self.scratch.values_mut() {
+                *used = 0;
+            }
+            let checkpoint = self.b.code.checkpoint();
+            if let Err(msg) = self.root(*root) {
+                self.b.code.rollback(&checkpoint);
+                self.b
+                    .throw_new("java/lang/UnsupportedOperationException", &msg);
+            }
+            self.b.code.set_depth(0);
+        }
+    }
+    /// Pushes the zero value of `kind`.
+    fn zero(&mut self, kind: Kind) {
+        match kind {
+            Kind::Int => self.b.int(0),
+            Kind::Long => self.b.long(0),
+            Kind::Float => self.b.float(0.0),
+            Kind::Double => self.b.double(0.0),
+            Kind::Ref => self.b.op(op::ACONST_NULL, 1),
+        }
+    }
+    /// Returns a JVM local of `kind`, which is free until the end of the current root.
+    fn scratch(&mut self, kind: Kind) -> u16 {
+        let (locals, used) = self.scratch.entry(kind).or_default();
+        if *used == locals.len() {
+            locals.push(self.b.code.alloc_local(kind.slots()));
+        }
+        *used += 1;
+        locals[*used - 1]
+    }
+    /// Reserves space for a temporary value in the frame, and returns its offset.
+    fn temp(&mut self, size: u64, align: u64) -> u64 {
+        let offset = self.temps.next_multiple_of(align.clamp(1, 16));
+        self.temps = offset + size;
+        self.frame_size = self.frame_size.max(self.temps);
+        offset
+    }
+    /// Pushes the address of the frame slot at `offset`.
+    fn frame_addr(&mut self, offset: u64) {
+        self.b.load(Kind::Long, self.fp);
+        self.add_offset(offset);
+    }
+    fn add_offset(&mut self, offset: u64) {
+        if offset != 0 {
+            self.b.long(offset as i64);
+            self.b.op(op::LADD, -2);
+        }
+    }
+    fn kind(&mut self, tpe: Type) -> Result<Option<Kind>, String> {
+        Ok(self.ctx.repr(tpe)?.kind())
+    }
+    /// Converts the value of `tpe` on top of the stack to a value of `kind`, if it is an int of a different size.
+    fn convert(&mut self, tpe: Type, kind: Kind) -> Result<(), String> {
+        match (self.kind(tpe)?, kind) {
+            (Some(from), to) if from == to => (),
+            (Some(Kind::Int), Kind::Long) => self.b.op(op::I2L, 1),
+            (Some(Kind::Long), Kind::Int) => self.b.op(op::L2I, -1),
+            (from, to) => return Err(format!("can't convert {from:?} to {to:?}")),
+        }
+        Ok(())
+    }
+    /// Makes the verifier accept the reference on top of the stack as a value with the descriptor `desc`.
+    fn coerce(&mut self, desc: &str) {
+        coerce(&mut self.b, desc);
+    }
+    fn normalize(&mut self, tpe: Type) {
+        normalize(&mut self.b, tpe);
+    }
+    fn pop(&mut self, tpe: Type) -> Result<(), String> {
+        match self.kind(tpe)? {
+            None => (),
+            Some(Kind::Long | Kind::Double) => self.b.op(op::POP2, -2),
+            Some(_) => self.b.op(op::POP, -1),
+        }
+        Ok(())
+    }
+    /// The suffix and descriptor of the runtime helpers accessing a value of `tpe` in memory.
+    fn mem_helper(tpe: Type, store: bool) -> Result<(&'static str, &'static str), String> {
+        Ok(match tpe {
+            Type::Bool | Type::Int(Int::U8) if !store => ("u8", "I"),
+            Type::Bool | Type::Int(Int::U8 | Int::I8) => ("i8", "I"),
+            Type::PlatformChar | Type::Int(Int::U16) if !store => ("u16", "I"),
+            Type::PlatformChar | Type::Int(Int::U16 | Int::I16) => ("i16", "I"),
+            Type::Int(Int::U32 | Int::I32) => ("i32", "I"),
+            Type::Int(Int::U64 | Int::I64 | Int::USize | Int::ISize)
+            | Type::Ptr(_)
+            | Type::Ref(_)
+            | Type::FnPtr(_) => ("i64", "J"),
+            Type::Float(Float::F32) => ("f32", "F"),
+            Type::Float(Float::F64) => ("f64", "D"),
+            _ => return Err(format!("{tpe:?} can't be stored in unmanaged memory")),
+        })
+    }
+    /// Replaces the address on top of the stack with the value of `tpe` it points to.
+    fn load_mem(&mut self, tpe: Type) -> Result<(), String> {
+        if let Repr::Memory { .. } = self.ctx.repr(tpe)? {
+            return self.snapshot(tpe);
+        }
+        let (suffix, desc) = Self::mem_helper(tpe, false)?;
+        self.b
+            .call(RUNTIME, &format!("ld_{suffix}"), &format!("(J){desc}"));
+        Ok(())
+    }
+    /// Stores the value of `tpe` on top of the stack at the address below it.
+    fn store_mem(&mut self, tpe: Type) -> Result<(), String> {
+        if let Repr::Memory { size, .. } = self.ctx.repr(tpe)? {
+            self.b.long(size as i64);
+            self.b.call(RUNTIME, "copy", "(JJJ)V");
+            return Ok(());
+        }
+        let (suffix, desc) = Self::mem_helper(tpe, true)?;
+        self.b
+            .call(RUNTIME, &format!("st_{suffix}"), &format!("(J{desc})V"));
+        Ok(())
+    }
+    /// Copies the value of `tpe` at the address on top of the stack to a temporary, if it is stored in memory.
+    fn snapshot(&mut self, tpe: Type) -> Result<(), String> {
+        if let Repr::Memory { size, align } = self.ctx.repr(tpe)? {
+            let offset = self.temp(size, align);
+            self.frame_addr(offset);
+            self.b.long(size as i64);
+            self.b.call(RUNTIME, "move", "(JJJ)J");
+        }
+        Ok(())
+    }
+    fn load_slot(&mut self, slot: Slot, tpe: Type) -> Result<(), String> {
+        match slot {
+            Slot::Jvm(kind, local) => self.b.load(kind, local),
+            Slot::Frame(offset) => {
+                self.frame_addr(offset);
+                // Values in memory are passed around by their address.
+                if !matches!(self.ctx.repr(tpe)?, Repr::Memory { .. }) {
+                    self.load_mem(tpe)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn store_slot(&mut self, slot: Slot, tpe: Type, val: NodeIdx) -> Result<(), String> {
+        match slot {
+            Slot::Jvm(kind, local) => {
+                let val = self.node(val)?;
+                self.convert(val, kind)?;
+                self.b.store(kind, local);
+            }
+            Slot::Frame(offset) => {
+                self.frame_addr(offset);
+                let val = self.node(val)?;
+                if let Some(kind) = self.kind(tpe)? {
+                    self.convert(val, kind)?;
+                }
+                self.store_mem(tpe)?;
+            }
+        }
+        Ok(())
+    }
+    /// Lowers a node producing an address.
+    fn addr(&mut self, node: NodeIdx) -> Result<Type, String> {
+        let tpe = self.node(node)?;
+        match self.ctx.repr(tpe)? {
+            Repr::Scalar(Kind::Long) | Repr::Memory { .. } => Ok(tpe),
+            Repr::Scalar(Kind::Int) => {
+                self.b.op(op::I2L, 1);
+                Ok(tpe)
+            }
+            _ => Err(format!("{tpe:?} is not an address")),
+        }
+    }
+    /// Lowers a node producing a length or a count, as a long.
+    fn length(&mut self, node: NodeIdx) -> Result<(), String> {
+        let tpe = self.node(node)?;
+        self.convert(tpe, Kind::Long)
+    }
+    /// Lowers the arguments of a call, converting them to the parameters of the JVM method.
+    fn args(&mut self, args: &[NodeIdx], params: &[&str]) -> Result<(), String> {
+        if args.len() != params.len() {
+            return Err(format!(
+                "expected {} arguments, got {}",
+                params.len(),
+                args.len()
+            ));
+        }
+        for (arg, param) in args.iter().zip(params) {
+            let tpe = self.node(*arg)?;
+            if let Some(kind) = Kind::of_desc(param) {
+                self.convert(tpe, kind)?;
+                if kind == Kind::Ref {
+                    self.coerce(param);
+                }
+            }
+        }
+        Ok(())
+    }
+    fn call(&mut self, mref: MethodRefIdx, args: &[NodeIdx]) -> Result<Type, String> {
+        let output = self.ctx.asm[mref].output(&self.ctx.asm);
+        match self.ctx.target(mref)? {
+            Target::Static(jvm) => {
+                self.args(args, &class_file::split_method_desc(&jvm.desc).0)?;
+                self.b.call(&jvm.class, &jvm.name, &jvm.desc);
+                self.snapshot(output)?;
+            }
+            Target::Init { class, desc } => {
+                let (params, _) = class_file::split_method_desc(&desc);
+                if args.len() != params.len() {
+                    return Err("constructor argument count mismatch".into());
+                }
+                // The arguments are evaluated before the object is created, so that no uninitialized
+                // object is on the stack when a branch is taken.
+                let mut saved = Vec::new();
+                for (arg, param) in args.iter().zip(&params) {
+                    let tpe = self.node(*arg)?;
+                    let kind = Kind::of_desc(param).unwrap();
+                    self.convert(tpe, kind)?;
+                    let local = self.scratch(kind);
+                    self.b.store(kind, local);
+                    saved.push((kind, local, *param));
+                }
+                self.b.type_op(op::NEW, &class);
+                self.b.op(op::DUP, 1);
+                for (kind, local, param) in saved {
+                    self.b.load(kind, local);
+                    if kind == Kind::Ref {
+                        self.coerce(param);
+                    }
+                }
+                self.b.invoke(op::INVOKESPECIAL, &class, "<init>", &desc);
+            }
+            Target::ValueCtor { ctor, size, align } => {
+                let offset = self.temp(size, align);
+                self.frame_addr(offset);
+                self.args(args, &class_file::split_method_desc(&ctor.desc).0[1..])?;
+                self.b.call(&ctor.class, &ctor.name, &ctor.desc);
+                self.frame_addr(offset);
+            }
+        }
+        Ok(output)
+    }
+    fn call_indirect(
+        &mut self,
+        ptr: NodeIdx,
+        sig: crate::v2::SigIdx,
+        args: &[NodeIdx],
+    ) -> Result<Type, String> {
+        let sig = self.ctx.asm[sig].clone();
+        let desc = self.ctx.method_desc(sig.inputs(), *sig.output())?;
+        let ptr_tpe = self.node(ptr)?;
+        self.convert(ptr_tpe, Kind::Long)?;
+        let ptr = self.scratch(Kind::Long);
+        self.b.store(Kind::Long, ptr);
+        self.args(args, &class_file::split_method_desc(&desc).0)?;
+        self.b.load(Kind::Long, ptr);
+        let dispatcher = self.ctx.dispatcher(&desc);
+        self.b.call(DISPATCH, &dispatcher, &dispatch_desc(&desc));
+        self.snapshot(*sig.output())?;
+        Ok(*sig.output())
+    }
+    /// Pushes a bool, which is true if the jump emitted by `cond` is taken.
+    fn materialize(
+        &mut self,
+        cond: impl FnOnce(&mut Self, Label) -> Result<(), String>,
+    ) -> Result<Type, String> {
+        let yes = self.b.label();
+        let end = self.b.label();
+        cond(self, yes)?;
+        let depth = self.b.code.depth();
+        self.b.int(0);
+        self.b.jump(op::GOTO, end);
+        self.b.code.set_depth(depth);
+        self.b.place(yes);
+        self.b.int(1);
+        self.b.place(end);
+        Ok(Type::Bool)
+    }
+    /// Lowers two operands, converting the second one to the kind of the first one.
+    fn operands(&mut self, lhs: NodeIdx, rhs: NodeIdx) -> Result<Type, String> {
+        let tpe = self.node(lhs)?;
+        let rhs = self.node(rhs)?;
+        if let (Some(kind), Some(_)) = (self.kind(tpe)?, self.kind(rhs)?) {
+            if !matches!(self.ctx.repr(tpe)?, Repr::Memory { .. }) {
+                self.convert(rhs, kind)?;
+            }
+        }
+        Ok(tpe)
+    }
+    fn is_wide(tpe: Type) -> bool {
+        matches!(tpe, Type::Int(Int::I128 | Int::U128))
+    }
+    /// Jumps to `target` if the comparison of the two values of `tpe` on top of the stack holds.
+    fn cmp_jump(
+        &mut self,
+        tpe: Type,
+        cmp: Cmp,
+        unsigned: bool,
+        target: Label,
+    ) -> Result<(), String> {
+        match self.ctx.repr(tpe)? {
+            Repr::Scalar(Kind::Int) => {
+                if unsigned && cmp.is_relational() {
+                    self.b.call("java/lang/Integer", "compareUnsigned", "(II)I");
+                    self.b.jump(cmp.jump(op::IFEQ), target);
+                } else {
+                    self.b.jump(cmp.jump(op::IF_ICMPEQ), target);
+                }
+            }
+            Repr::Scalar(Kind::Long) => {
+                if unsigned && cmp.is_relational() {
+                    self.b.call("java/lang/Long", "compareUnsigned", "(JJ)I");
+                } else {
+                    self.b.op(op::LCMP, -3);
+                }
+                self.b.jump(cmp.jump(op::IFEQ), target);
+            }
+            Repr::Scalar(kind @ (Kind::Float | Kind::Double)) => {
+                // `*cmpg` treats NaN as greater, and `*cmpl` as less: this picks the one making an
+                // ordered comparison false, or an unordered one true, for NaNs.
+                let greater = match cmp {
+                    Cmp::Lt | Cmp::Le => !unsigned,
+                    Cmp::Gt | Cmp::Ge => unsigned,
+                    Cmp::Eq | Cmp::Ne => false,
+                };
+                match (kind, greater) {
+                    (Kind::Float, true) => self.b.op(op::FCMPG, -1),
+                    (Kind::Float, false) => self.b.op(op::FCMPL, -1),
+                    (_, true) => self.b.op(op::DCMPG, -3),
+                    (_, false) => self.b.op(op::DCMPL, -3),
+                }
+                self.b.jump(cmp.jump(op::IFEQ), target);
+            }
+            Repr::Scalar(Kind::Ref) => match cmp {
+                Cmp::Eq => self.b.jump(op::IF_ACMPEQ, target),
+                Cmp::Ne => self.b.jump(op::IF_ACMPNE, target),
+                _ => return Err("object references can only be compared for equality".into()),
+            },
+            Repr::Memory { .. } if Self::is_wide(tpe) => {
+                let signed = tpe == Type::Int(Int::I128) && !unsigned;
+                self.b.int(i32::from(signed));
+                self.b.call(RUNTIME, "wide_cmp", "(JJI)I");
+                self.b.jump(cmp.jump(op::IFEQ), target);
+            }
+            Repr::Memory { size, .. } if !cmp.is_relational() => {
+                self.b.long(size as i64);
+                self.b.call(RUNTIME, "memcmp", "(JJJ)I");
+                self.b.jump(cmp.jump(op::IFEQ), target);
+            }
+            repr => return Err(format!("can't compare values of {tpe:?} ({repr:?})")),
+        }
+        Ok(())
+    }
+    fn binop(&mut self, lhs: NodeIdx, rhs: NodeIdx, op: BinOp) -> Result<Type, String> {
+        let is_shift = matches!(op, BinOp::Shl | BinOp::Shr | BinOp::ShrUn);
+        let tpe = if is_shift {
+            let tpe = self.node(lhs)?;
+            let amount = self.node(rhs)?;
+            if Self::is_wide(amount) {
+                self.b.call(RUNTIME, "wide_to", "(J)J");
+                self.b.op(op::L2I, -1);
+            } else {
+                self.convert(amount, Kind::Int)?;
+            }
+            tpe
+        } else {
+            self.operands(lhs, rhs)?
+        };
+        let (cmp, unsigned) = match op {
+            BinOp::Eq => (Some(Cmp::Eq), false),
+            BinOp::Lt => (Some(Cmp::Lt), false),
+            BinOp::LtUn => (Some(Cmp::Lt), true),
+            BinOp::Gt => (Some(Cmp::Gt), false),
+            BinOp::GtUn => (Some(Cmp::Gt), true),
+            _ => (None, false),
+        };
+        if let Some(cmp) = cmp {
+            return self.materialize(|lower, yes| lower.cmp_jump(tpe, cmp, unsigned, yes));
+        }
+        let repr = self.ctx.repr(tpe)?;
+        if Self::is_wide(tpe) {
+            return self.wide_binop(tpe, op);
+        }
+        let Repr::Scalar(kind) = repr else {
+            return Err(format!("{op:?} is not supported for {tpe:?}"));
+        };
+        let (offset, slots) = match kind {
+            Kind::Int => (0, 1),
+            Kind::Long => (1, 2),
+            Kind::Float => (2, 1),
+            Kind::Double => (3, 2),
+            Kind::Ref => return Err(format!("{op:?} is not supported for objects")),
+        };
+        let is_float = matches!(kind, Kind::Float | Kind::Double);
+        let arith = |base: u8| base + offset;
+        match op {
+            BinOp::Add => self.b.op(arith(op::IADD), -slots),
+            BinOp::Sub => self.b.op(arith(op::ISUB), -slots),
+            BinOp::Mul => self.b.op(arith(op::IMUL), -slots),
+            BinOp::Div => self.b.op(arith(op::IDIV), -slots),
+            BinOp::Rem => self.b.op(arith(op::IREM), -slots),
+            BinOp::DivUn | BinOp::RemUn if !is_float => {
+                let name = if op == BinOp::DivUn {
+                    "divideUnsigned"
+                } else {
+                    "remainderUnsigned"
+                };
+                if kind == Kind::Int {
+                    self.b.call("java/lang/Integer", name, "(II)I");
+                } else {
+                    self.b.call("java/lang/Long", name, "(JJ)J");
+                }
+            }
+            BinOp::And if !is_float => self.b.op(arith(op::IAND), -slots),
+            BinOp::Or if !is_float => self.b.op(arith(op::IOR), -slots),
+            BinOp::XOr if !is_float => self.b.op(arith(op::IXOR), -slots),
+            BinOp::Shl if !is_float => self.b.op(arith(op::ISHL), -1),
+            BinOp::Shr if !is_float => self.b.op(arith(op::ISHR), -1),
+            BinOp::ShrUn if !is_float => self.b.op(arith(op::IUSHR), -1),
+            _ => return Err(format!("{op:?} is not supported for {tpe:?}")),
+        }
+        if kind == Kind::Int {
+            self.normalize(tpe);
+        }
+        // Pointer arithmetic produces a pointer.
+        Ok(tpe)
+    }
+    /// Performs `op` on two 128 bit ints.
+    fn wide_binop(&mut self, tpe: Type, op: BinOp) -> Result<Type, String> {
+        let signed = tpe == Type::Int(Int::I128);
+        let offset = self.temp(16, 16);
+        if let BinOp::Shl | BinOp::Shr | BinOp::ShrUn = op {
+            let mode = match op {
+                BinOp::Shl => 0,
+                BinOp::Shr if signed => 1,
+                _ => 2,
+            };
+            self.frame_addr(offset);
+            self.b.int(mode);
+            self.b.call(RUNTIME, "wide_shift", "(JIJI)J");
+            return Ok(tpe);
+        }
+        let code = match op {
+            BinOp::Add => 0,
+            BinOp::Sub => 1,
+            BinOp::Mul => 2,
+            BinOp::Div if signed => 3 | 16,
+            BinOp::Div | BinOp::DivUn => 3,
+            BinOp::Rem if signed => 4 | 16,
+            BinOp::Rem | BinOp::RemUn => 4,
+            BinOp::And => 5,
+            BinOp::Or => 6,
+            BinOp::XOr => 7,
+            _ => return Err(format!("{op:?} is not supported for {tpe:?}")),
+        };
+        self.frame_addr(offset);
+        self.b.int(code);
+        self.b.call(RUNTIME, "wide", "(JJJI)J");
+        Ok(tpe)
+    }
+    fn unop(&mut self, val: NodeIdx, un_op: UnOp) -> Result<Type, String> {
+        let tpe = self.node(val)?;
+        if Self::is_wide(tpe) {
+            let offset = self.temp(16, 16);
+            self.frame_addr(offset);
+            let name = if un_op == UnOp::Not {
+                "wide_not"
+            } else {
+                "wide_neg"
+            };
+            self.b.call(RUNTIME, name, "(JJ)J");
+            return Ok(tpe);
+        }
+        match (self.ctx.repr(tpe)?, &un_op) {
+            (Repr::Scalar(Kind::Int), UnOp::Not) => {
+                self.b.int(if tpe == Type::Bool { 1 } else { -1 });
+                self.b.op(op::IXOR, -1);
+                self.normalize(tpe);
+            }
+            (Repr::Scalar(Kind::Long), UnOp::Not) => {
+                self.b.long(-1);
+                self.b.op(op::LXOR, -2);
+            }
+            (Repr::Scalar(Kind::Int), UnOp::Neg) => {
+                self.b.op(op::INEG, 0);
+                self.normalize(tpe);
+            }
+            (Repr::Scalar(Kind::Long), UnOp::Neg) => self.b.op(op::LNEG, 0),
+            (Repr::Scalar(Kind::Float), UnOp::Neg) => self.b.op(op::FNEG, 0),
+            (Repr::Scalar(Kind::Double), UnOp::Neg) => self.b.op(op::DNEG, 0),
+            _ => return Err(format!("{un_op:?} is not supported for {tpe:?}")),
+        }
+        Ok(tpe)
+    }
+    fn int_cast(
+        &mut self,
+        input: NodeIdx,
+        target: Int,
+        extend: ExtendKind,
+    ) -> Result<Type, String> {
+        let src = self.node(input)?;
+        let tpe = Type::Int(target);
+        let zero_extend = extend == ExtendKind::ZeroExtend;
+        let src_repr = self.ctx.repr(src)?;
+        let dst_repr = self.ctx.repr(tpe)?;
+        match (src_repr, dst_repr) {
+            (Repr::Scalar(Kind::Float), _) => {
+                self.b.op(op::F2D, 1);
+                self.float_to_int(target)?;
+            }
+            (Repr::Scalar(Kind::Double), _) => self.float_to_int(target)?,
+            (Repr::Scalar(Kind::Int), Repr::Scalar(Kind::Int)) => self.normalize(tpe),
+            (Repr::Scalar(Kind::Long), Repr::Scalar(Kind::Long)) => (),
+            (Repr::Scalar(Kind::Long), Repr::Scalar(Kind::Int)) => {
+                self.b.op(op::L2I, -1);
+                self.normalize(tpe);
+            }
+            (Repr::Scalar(kind @ (Kind::Int | Kind::Long)), dst) => {
+                if kind == Kind::Int {
+                    self.b.op(op::I2L, 1);
+                    if zero_extend {
+                        self.b.long(0xFFFF_FFFF);
+                        self.b.op(op::LAND, -2);
+                    }
+                }
+                if let Repr::Memory { .. } = dst {
+                    self.b.int(i32::from(!zero_extend));
+                    let offset = self.temp(16, 16);
+                    self.frame_addr(offset);
+                    self.b.call(RUNTIME, "wide_from", "(JIJ)J");
+                }
+            }
+            (Repr::Memory { .. }, dst) if Self::is_wide(src) => match dst {
+                Repr::Memory { .. } => (),
+                Repr::Scalar(Kind::Long) => self.b.call(RUNTIME, "wide_to", "(J)J"),
+                _ => {
+                    self.b.call(RUNTIME, "wide_to", "(J)J");
+                    self.b.op(op::L2I, -1);
+                    self.normalize(tpe);
+                }
+            },
+            _ => return Err(format!("can't cast {src:?} to {tpe:?}")),
+        }
+        Ok(tpe)
+    }
+    /// Converts the double on top of the stack to `target`, saturating like Rust does.
+    fn float_to_int(&mut self, target: Int) -> Result<(), String> {
+        match target {
+            Int::I64 | Int::ISize => self.b.op(op::D2L, 0),
+            Int::U64 | Int::USize => self.b.call(RUNTIME, "d2u64", "(D)J"),
+            Int::I32 => self.b.op(op::D2I, -1),
+            Int::U32 => {
+                self.b.op(op::D2L, 0);
+                self.b.long(0);
+                self.b.call("java/lang/Math", "max", "(JJ)J");
+                self.b.long(0xFFFF_FFFF);
+                self.b.call("java/lang/Math", "min", "(JJ)J");
+                self.b.op(op::L2I, -1);
+            }
+            Int::I8 | Int::U8 | Int::I16 | Int::U16 => {
+                let (min, max) = match target {
+                    Int::I8 => (i32::from(i8::MIN), i32::from(i8::MAX)),
+                    Int::U8 => (0, i32::from(u8::MAX)),
+                    Int::I16 => (i32::from(i16::MIN), i32::from(i16::MAX)),
+                    _ => (0, i32::from(u16::MAX)),
+                };
+                self.b.op(op::D2I, -1);
+                self.b.int(min);
+                self.b.call("java/lang/Math", "max", "(II)I");
+                self.b.int(max);
+                self.b.call("java/lang/Math", "min", "(II)I");
+            }
+            Int::I128 | Int::U128 => {
+                self.b.int(i32::from(target == Int::I128));
+                let offset = self.temp(16, 16);
+                self.frame_addr(offset);
+                self.b.call(RUNTIME, "wide_from_double", "(DIJ)J");
+            }
+        }
+        Ok(())
+    }
+    fn float_cast(
+        &mut self,
+        input: NodeIdx,
+        target: Float,
+        is_signed: bool,
+    ) -> Result<Type, String> {
+        let src = self.node(input)?;
+        let double = match target {
+            Float::F32 => false,
+            Float::F64 => true,
+            _ => return Err(format!("{target:?} is not supported")),
+        };
+        match self.ctx.repr(src)? {
+            Repr::Scalar(Kind::Int) if is_signed => {
+                if double {
+                    self.b.op(op::I2D, 1);
+                } else {
+                    self.b.op(op::I2F, 0);
+                }
+            }
+            Repr::Scalar(Kind::Int) => {
+                self.b.op(op::I2L, 1);
+                self.b.long(0xFFFF_FFFF);
+                self.b.op(op::LAND, -2);
+                if double {
+                    self.b.op(op::L2D, 0);
+                } else {
+                    self.b.op(op::L2F, -1);
+                }
+            }
+            Repr::Scalar(Kind::Long) => match (is_signed, double) {
+                (true, true) => self.b.op(op::L2D, 0),
+                (true, false) => self.b.op(op::L2F, -1),
+                (false, true) => self.b.call(RUNTIME, "u64_to_f64", "(J)D"),
+                (false, false) => self.b.call(RUNTIME, "u64_to_f32", "(J)F"),
+            },
+            Repr::Scalar(Kind::Float) => {
+                if double {
+                    self.b.op(op::F2D, 1);
+                }
+            }
+            Repr::Scalar(Kind::Double) => {
+                if !double {
+                    self.b.op(op::D2F, -1);
+                }
+            }
+            Repr::Memory { .. } if Self::is_wide(src) => {
+                self.b.int(i32::from(is_signed));
+                self.b.call(RUNTIME, "wide_to_double", "(JI)D");
+                if !double {
+                    self.b.op(op::D2F, -1);
+                }
+            }
+            _ => return Err(format!("can't cast {src:?} to {target:?}")),
+        }
+        Ok(Type::Float(target))
+    }
+    fn constant(&mut self, cst: &Const) -> Result<Type, String> {
+        match cst {
+            Const::I8(val) => self.b.int(i32::from(*val)),
+            Const::I16(val) => self.b.int(i32::from(*val)),
+            Const::I32(val) => self.b.int(*val),
+            Const::U8(val) => self.b.int(i32::from(*val)),
+            Const::U16(val) => self.b.int(i32::from(*val)),
+            Const::U32(val) => self.b.int(*val as i32),
+            Const::Bool(val) => self.b.int(i32::from(*val)),
+            Const::I64(val) | Const::ISize(val) => self.b.long(*val),
+            Const::U64(val) | Const::USize(val) => self.b.long(*val as i64),
+            Const::I128(val) => self.wide_const(*val as u128),
+            Const::U128(val) => self.wide_const(*val),
+            Const::F32(val) => self.b.float(val.0),
+            Const::F64(val) => self.b.double(val.0),
+            Const::PlatformString(val) => {
+                let val = self.ctx.asm[*val].to_owned();
+                self.b.string(&val);
+            }
+            Const::Null(_) => self.b.op(op::ACONST_NULL, 1),
+        }
+        Ok(cst.get_type())
+    }
+    fn wide_const(&mut self, val: u128) {
+        let offset = self.temp(16, 16);
+        for (half, bits) in [(0, val as u64), (8, (val >> 64) as u64)] {
+            self.frame_addr(offset + half);
+            self.b.long(bits as i64);
+            self.b.call(RUNTIME, "st_i64", "(JJ)V");
+        }
+        self.frame_addr(offset);
+    }
+    fn node(&mut self, idx: NodeIdx) -> Result<Type, String> {
+        let node = self.ctx.asm[idx].clone();
+        match node {
+            CILNode::Const(cst) => self.constant(&cst),
+            CILNode::BinOp(lhs, rhs, op) => self.binop(lhs, rhs, op),
+            CILNode::UnOp(val, op) => self.unop(val, op),
+            CILNode::LdLoc(loc) => {
+                let tpe = self.locals[loc as usize];
+                self.load_slot(self.loc_slots[loc as usize], tpe)?;
+                Ok(tpe)
+            }
+            CILNode::LdArg(arg) => {
+                let tpe = self.inputs[arg as usize];
+                self.load_slot(self.args[arg as usize], tpe)?;
+                Ok(tpe)
+            }
+            CILNode::LdLocA(loc) => {
+                let Slot::Frame(offset) = self.loc_slots[loc as usize] else {
+                    return Err("can't take the address of this local".into());
+                };
+                self.frame_addr(offset);
+                Ok(self.ctx.asm.nptr(self.locals[loc as usize]))
+            }
+            CILNode::LdArgA(arg) => {
+                let Slot::Frame(offset) = self.args[arg as usize] else {
+                    return Err("can't take the address of this argument".into());
+                };
+                self.frame_addr(offset);
+                Ok(self.ctx.asm.nptr(self.inputs[arg as usize]))
+            }
+            CILNode::Call(info) => self.call(info.0, &info.1),
+            CILNode::IntCast {
+                input,
+                target,
+                extend,
+            } => self.int_cast(input, target, extend),
+            CILNode::FloatCast {
+                input,
+                target,
+                is_signed,
+            } => self.float_cast(input, target, is_signed),
+            CILNode::RefToPtr(val) => {
+                let tpe = self.addr(val)?;
+                Ok(match tpe {
+                    Type::Ref(inner) => Type::Ptr(inner),
+                    tpe => tpe,
+                })
+            }
+            CILNode::PtrCast(val, res) => {
+                self.addr(val)?;
+                Ok(match *res {
+                    PtrCastRes::Ptr(inner) => Type::Ptr(inner),
+                    PtrCastRes::Ref(inner) => Type::Ref(inner),
+                    PtrCastRes::FnPtr(sig) => Type::FnPtr(sig),
+                    PtrCastRes::USize => Type::Int(Int::USize),
+                    PtrCastRes::ISize => Type::Int(Int::ISize),
+                })
+            }
+            CILNode::LdFieldAdress { addr, field } => {
+                self.addr(addr)?;
+                let offset = self.ctx.field_offset(field)?;
+                self.add_offset(offset);
+                let tpe = self.ctx.asm[field].tpe();
+                Ok(self.ctx.asm.nptr(tpe))
+            }
+            CILNode::LdField { addr, field } => {
+                let owner = self.node(addr)?;
+                let desc = self.ctx.asm[field];
+                if let Repr::Scalar(Kind::Ref) = self.ctx.repr(owner)? {
+                    let (class, name, fdesc) = self.object_field(field)?;
+                    self.b.type_op(op::CHECKCAST, &class);
+                    self.b.field(op::GETFIELD, &class, &name, &fdesc);
+                    return Ok(desc.tpe());
+                }
+                self.convert(owner, Kind::Long)?;
+                let offset = self.ctx.field_offset(field)?;
+                self.add_offset(offset);
+                self.load_mem(desc.tpe())?;
+                Ok(desc.tpe())
+            }
+            CILNode::LdInd { addr, tpe, .. } => {
+                self.addr(addr)?;
+                let tpe = self.ctx.asm[tpe];
+                self.load_mem(tpe)?;
+                Ok(tpe)
+            }
+            CILNode::SizeOf(tpe) => {
+                let size = self.ctx.size_of(self.ctx.asm[tpe])?;
+                self.b.int(size as i32);
+                Ok(Type::Int(Int::I32))
+            }
+            CILNode::GetException => {
+                let Some(exception) = self.exception else {
+                    return Err("no exception is being handled".into());
+                };
+                self.b.load(Kind::Ref, exception);
+                Ok(Type::ClassRef(ClassRef::exception(&mut self.ctx.asm)))
+            }
+            CILNode::IsInst(obj, tpe) => {
+                self.node(obj)?;
+                let class = self.ctx.object_class(self.ctx.asm[tpe])?;
+                self.b.type_op(op::INSTANCEOF, &class);
+                Ok(Type::Bool)
+            }
+            CILNode::CheckedCast(obj, tpe) => {
+                self.node(obj)?;
+                let tpe = self.ctx.asm[tpe];
+                let class = self.ctx.object_class(tpe)?;
+                self.b.type_op(op::CHECKCAST, &class);
+                Ok(tpe)
+            }
+            CILNode::CallI(info) => self.call_indirect(info.0, info.1, &info.2),
+            CILNode::LocAlloc { size } => {
+                self.length(size)?;
+                self.b.long(16);
+                self.b.call(RUNTIME, "stackalloc", "(JJ)J");
+                Ok(self.ctx.asm.nptr(Type::Int(Int::U8)))
+            }
+            CILNode::LocAllocAlgined { tpe, align } => {
+                let size = self.ctx.size_of(self.ctx.asm[tpe])?;
+                self.b.long(size as i64);
+                self.b.long(align as i64);
+                self.b.call(RUNTIME, "stackalloc", "(JJ)J");
+                Ok(self.ctx.asm.nptr(tpe))
+            }
+            CILNode::LdStaticField(field) => {
+                let tpe = self.ctx.asm[field].tpe();
+                match self.ctx.static_slot(field)? {
+                    StaticSlot::Field(name, desc) => {
+                        self.b.field(op::GETSTATIC, RUNTIME, &name, &desc);
+                    }
+                    StaticSlot::Memory(offset) => {
+                        self.b.field(op::GETSTATIC, RUNTIME, "statics", "J");
+                        self.add_offset(offset);
+                        self.load_mem(tpe)?;
+                    }
+                }
+                Ok(tpe)
+            }
+            CILNode::LdStaticFieldAdress(field) => {
+                let tpe = self.ctx.asm[field].tpe();
+                let StaticSlot::Memory(offset) = self.ctx.static_slot(field)? else {
+                    return Err("can't take the address of a static object reference".into());
+                };
+                self.b.field(op::GETSTATIC, RUNTIME, "statics", "J");
+                self.add_offset(offset);
+                Ok(self.ctx.asm.nptr(tpe))
+            }
+            CILNode::LdFtn(mref) => {
+                let id = self.ctx.fn_id(mref)?;
+                self.b.long(id);
+                Ok(Type::FnPtr(self.ctx.asm[mref].sig()))
+            }
+            CILNode::LdLen(array) => {
+                let tpe = self.node(array)?;
+                let desc = self.ctx.desc(tpe)?;
+                self.coerce(&desc);
+                self.b.op(op::ARRAYLENGTH, 0);
+                Ok(Type::Int(Int::I32))
+            }
+            CILNode::LdElelemRef { array, index } => {
+                let tpe = self.node(array)?;
+                let Type::PlatformArray { elem, dims } = tpe else {
+                    return Err(format!("{tpe:?} is not an array"));
+                };
+                let desc = self.ctx.desc(tpe)?;
+                self.coerce(&desc);
+                let index = self.node(index)?;
+                self.convert(index, Kind::Int)?;
+                let elem = match std::num::NonZeroU8::new(dims.get() - 1) {
+                    Some(dims) => Type::PlatformArray { elem, dims },
+                    None => self.ctx.asm[elem],
+                };
+                let (opcode, delta) = match &desc[1..2] {
+                    "Z" | "B" => (op::BALOAD, -1),
+                    "C" => (op::CALOAD, -1),
+                    "S" => (op::SALOAD, -1),
+                    "I" => (op::IALOAD, -1),
+                    "J" => (op::LALOAD, 0),
+                    "F" => (op::FALOAD, -1),
+                    "D" => (op::DALOAD, 0),
+                    _ => (op::AALOAD, -1),
+                };
+                self.b.op(opcode, delta);
+                self.normalize(elem);
+                Ok(elem)
+            }
+            CILNode::LdTypeToken(_) => Err("type tokens are not supported".into()),
+            CILNode::UnboxAny { .. } => Err("unboxing is not supported".into()),
+        }
+    }
+    /// Returns the JVM class, name and descriptor of the object field `field`.
+    fn object_field(
+        &mut self,
+        field: crate::v2::FieldIdx,
+    ) -> Result<(String, String, String), String> {
+        let field = self.ctx.asm[field];
+        let class = self.ctx.object_class(Type::ClassRef(field.owner()))?;
+        let name = super::escape(&self.ctx.asm[field.name()]);
+        if let Repr::Memory { .. } = self.ctx.repr(field.tpe())? {
+            return Err(format!("the field {name} is not supported"));
+        }
+        let desc = self.ctx.desc(field.tpe())?;
+        Ok((class, name, desc))
+    }
+    /// Returns from the method, with the value of `tpe` on top of the stack.
+    fn ret(&mut self, tpe: Type) -> Result<(), String> {
+        let kind = self.kind(self.output)?;
+        match kind {
+            Some(kind) => {
+                self.convert(tpe, kind)?;
+                if kind == Kind::Ref {
+                    let desc = self.ctx.desc(self.output)?;
+                    self.coerce(&desc);
+                }
+            }
+            None => self.pop(tpe)?,
+        }
+        self.b.load(Kind::Long, self.fp);
+        self.b.call(RUNTIME, "leave", "(J)V");
+        self.b.ret(kind);
+        Ok(())
+    }
+    fn branch_target(&self, target: u32, sub_target: u32) -> Result<Label, String> {
+        let target = if sub_target == 0 {
+            target
+        } else if let Some(label) = self
+            .handler
+            .as_ref()
+            .and_then(|handler| handler.get(&sub_target))
+        {
+            return Ok(*label);
+        } else {
+            sub_target
+        };
+        self.labels
+            .get(&target)
+            .copied()
+            .ok_or_else(|| format!("jump to the nonexistent block {target}"))
+    }
+    fn branch(&mut self, cond: Option<BranchCond>, target: Label) -> Result<(), String> {
+        let unsigned = |kind: CmpKind| matches!(kind, CmpKind::Unordered | CmpKind::Unsigned);
+        let (lhs, rhs, cmp, unsigned) = match cond {
+            None => {
+                self.b.jump(op::GOTO, target);
+                return Ok(());
+            }
+            Some(BranchCond::True(cond)) => return self.truthy_jump(cond, true, target),
+            Some(BranchCond::False(cond)) => return self.truthy_jump(cond, false, target),
+            Some(BranchCond::Eq(lhs, rhs)) => (lhs, rhs, Cmp::Eq, false),
+            Some(BranchCond::Ne(lhs, rhs)) => (lhs, rhs, Cmp::Ne, false),
+            Some(BranchCond::Lt(lhs, rhs, kind)) => (lhs, rhs, Cmp::Lt, unsigned(kind)),
+            Some(BranchCond::Gt(lhs, rhs, kind)) => (lhs, rhs, Cmp::Gt, unsigned(kind)),
+            Some(BranchCond::Le(lhs, rhs, kind)) => (lhs, rhs, Cmp::Le, unsigned(kind)),
+            Some(BranchCond::Ge(lhs, rhs, kind)) => (lhs, rhs, Cmp::Ge, unsigned(kind)),
+        };
+        let tpe = self.operands(lhs, rhs)?;
+        self.cmp_jump(tpe, cmp, unsigned, target)
+    }
+    /// Lowers `plan`, a part of the lowering of `table`, comparing the long in the local `value` with its cases.
+    fn switch_plan(
+        &mut self,
+        table: &SwitchTable,
+        plan: &SwitchPlan,
+        value: u16,
+    ) -> Result<(), String> {
+        let tpe = Type::Int(table.tpe());
+        let (target, sub_target) = table.otherwise();
+        let otherwise = self.branch_target(target, sub_target)?;
+        match plan {
+            SwitchPlan::Cases(cases) => {
+                for (case, (target, sub_target)) in cases {
+                    let target = self.branch_target(*target, *sub_target)?;
+                    self.b.load(Kind::Long, value);
+                    self.b.long(*case as i64);
+                    self.cmp_jump(tpe, Cmp::Eq, false, target)?;
+                }
+                self.b.jump(op::GOTO, otherwise);
+            }
+            SwitchPlan::Table { min, targets } => {
+                // Compared as unsigned, `value - min` is out of range for all values outside of the table.
+                self.b.load(Kind::Long, value);
+                self.b.long(*min as i64);
+                self.b.op(op::LSUB, -2);
+                self.b.long(targets.len() as i64);
+                self.cmp_jump(Type::Int(Int::U64), Cmp::Ge, true, otherwise)?;
+                self.b.load(Kind::Long, value);
+                self.b.long(*min as i64);
+                self.b.op(op::LSUB, -2);
+                self.b.op(op::L2I, -1);
+                let mut cases = targets
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, (target, sub_target))| {
+                        Ok((idx as i32, self.branch_target(*target, *sub_target)?))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                self.b.code.lookupswitch(otherwise, &mut cases);
+            }
+            SwitchPlan::Split { pivot, lt, ge } => {
+                let lt_label = self.b.label();
+                self.b.load(Kind::Long, value);
+                self.b.long(*pivot as i64);
+                self.cmp_jump(tpe, Cmp::Lt, !table.tpe().is_signed(), lt_label)?;
+                self.switch_plan(table, ge, value)?;
+                self.b.place(lt_label);
+                self.switch_plan(table, lt, value)?;
+            }
+        }
+        Ok(())
+    }
+    /// Jumps to `target` if `cond` is (or is not) zero or null.
+    fn truthy_jump(&mut self, cond: NodeIdx, is_true: bool, target: Label) -> Result<(), String> {
+        let tpe = self.node(cond)?;
+        match self.ctx.repr(tpe)? {
+            Repr::Scalar(Kind::Int) => (),
+            Repr::Scalar(Kind::Long) => {
+                self.b.long(0);
+                self.b.op(op::LCMP, -3);
+            }
+            Repr::Scalar(Kind::Ref) => {
+                let opcode = if is_true { op::IFNONNULL } else { op::IFNULL };
+                self.b.jump(opcode, target);
+                return Ok(());
+            }
+            _ => return Err(format!("{tpe:?} can't be used as a condition")),
+        }
+        self.b
+            .jump(if is_true { op::IFNE } else { op::IFEQ }, target);
+        Ok(())
+    }
+    fn root(&mut self, idx: RootIdx) -> Result<(), String> {
+        let root = self.ctx.asm[idx].clone();
+        match root {
+            CILRoot::StLoc(loc, val) => {
+                self.store_slot(self.loc_slots[loc as usize], self.locals[loc as usize], val)
+            }
+            CILRoot::StArg(arg, val) => {
+                self.store_slot(self.args[arg as usize], self.inputs[arg as usize], val)
+            }
+            CILRoot::Ret(val) => {
+                let tpe = self.node(val)?;
+                self.ret(tpe)
+            }
+            CILRoot::TailCall(val) => {
+                // The JVM has no tail calls, so this is just a call followed by a return.
+                let tpe = self.node(val)?;
+                self.ret(tpe)
+            }
+            CILRoot::VoidRet => self.ret(Type::Void),
+            CILRoot::Pop(val) => {
+                let tpe = self.node(val)?;
+                self.pop(tpe)
+            }
+            CILRoot::Throw(val) => {
+                self.node(val)?;
+                self.b.type_op(op::CHECKCAST, "java/lang/Throwable");
+                self.b.op(op::ATHROW, -1);
+                Ok(())
+            }
+            CILRoot::ReThrow => {
+                let Some(exception) = self.exception.filter(|_| self.handler.is_some()) else {
+                    return Err("rethrow outside of a handler".into());
+                };
+                self.b.load(Kind::Ref, exception);
+                self.b.type_op(op::CHECKCAST, "java/lang/Throwable");
+                self.b.op(op::ATHROW, -1);
+                Ok(())
+            }
+            CILRoot::Break | CILRoot::Nop | CILRoot::SourceFileInfo { .. } => Ok(()),
+            CILRoot::Branch(info) => {
+                let (target, sub_target, cond) = *info;
+                let target = self.branch_target(target, sub_target)?;
+                self.branch(cond, target)
+            }
+            CILRoot::Switch(info) => {
+                let (discr, table) = *info;
+                let tpe = self.node(discr)?;
+                match self.ctx.repr(tpe)? {
+                    // `lookupswitch` handles both dense and sparse cases well, so there is no need to use the plan.
+                    Repr::Scalar(Kind::Int) => {
+                        let (target, sub_target) = table.otherwise();
+                        let otherwise = self.branch_target(target, sub_target)?;
+                        let mut cases = table
+                            .cases()
+                            .map(|(value, (target, sub_target))| {
+                                let key = table.extend(value) as i32;
+                                Ok((key, self.branch_target(target, sub_target)?))
+                            })
+                            .collect::<Result<Vec<_>, String>>()?;
+                        self.b.code.lookupswitch(otherwise, &mut cases);
+                        Ok(())
+                    }
+                    Repr::Scalar(Kind::Long) => {
+                        let value = self.scratch(Kind::Long);
+                        self.b.store(Kind::Long, value);
+                        self.switch_plan(&table, &table.plan(), value)
+                    }
+                    _ => Err(format!("can't switch on a value of type {tpe:?}")),
+                }
+            }
+            CILRoot::ExitSpecialRegion { target, .. } => {
+                if self.in_region {
+                    let target = self.branch_target(target, 0)?;
+                    self.b.jump(op::GOTO, target);
+                }
+                Ok(())
+            }
+            CILRoot::SetField(info) => {
+                let (field, addr, val) = *info;
+                let owner = self.node(addr)?;
+                let tpe = self.ctx.asm[field].tpe();
+                if let Repr::Scalar(Kind::Ref) = self.ctx.repr(owner)? {
+                    let (class, name, desc) = self.object_field(field)?;
+                    self.b.type_op(op::CHECKCAST, &class);
+                    let val = self.node(val)?;
+                    let kind = Kind::of_desc(&desc).unwrap();
+                    self.convert(val, kind)?;
+                    if kind == Kind::Ref {
+                        self.coerce(&desc);
+                    }
+                    self.b.field(op::PUTFIELD, &class, &name, &desc);
+                    return Ok(());
+                }
+                self.convert(owner, Kind::Long)?;
+                let offset = self.ctx.field_offset(field)?;
+                self.add_offset(offset);
+                self.store_val(tpe, val)
+            }
+            CILRoot::StInd(info) => {
+                let (addr, val, tpe, _) = *info;
+                self.addr(addr)?;
+                self.store_val(tpe, val)
+            }
+            CILRoot::Call(info) => {
+                let tpe = self.call(info.0, &info.1)?;
+                self.pop(tpe)
+            }
+            CILRoot::CallI(info) => {
+                let tpe = self.call_indirect(info.0, info.1, &info.2)?;
+                self.pop(tpe)
+            }
+            CILRoot::InitBlk(info) => {
+                let (dst, val, count) = *info;
+                self.addr(dst)?;
+                let val = self.node(val)?;
+                self.convert(val, Kind::Int)?;
+                self.length(count)?;
+                self.b.call(RUNTIME, "fill", "(JIJ)V");
+                Ok(())
+            }
+            CILRoot::CpBlk(info) => {
+                let (dst, src, len) = *info;
+                self.addr(dst)?;
+                self.addr(src)?;
+                self.length(len)?;
+                self.b.call(RUNTIME, "copy", "(JJJ)V");
+                Ok(())
+            }
+            CILRoot::CpObj { src, dst, tpe } => {
+                self.addr(dst)?;
+                self.addr(src)?;
+                let size = self.ctx.size_of(self.ctx.asm[tpe])?;
+                self.b.long(size as i64);
+                self.b.call(RUNTIME, "copy", "(JJJ)V");
+                Ok(())
+            }
+            CILRoot::SetStaticField { field, val } => {
+                let tpe = self.ctx.asm[field].tpe();
+                match self.ctx.static_slot(field)? {
+                    StaticSlot::Field(name, desc) => {
+                        let val = self.node(val)?;
+                        self.convert(val, Kind::Ref)?;
+                        self.coerce(&desc);
+                        self.b.field(op::PUTSTATIC, RUNTIME, &name, &desc);
+                        Ok(())
+                    }
+                    StaticSlot::Memory(offset) => {
+                        self.b.field(op::GETSTATIC, RUNTIME, "statics", "J");
+                        self.add_offset(offset);
+                        self.store_val(tpe, val)
+                    }
+                }
+            }
+            CILRoot::Unreachable(msg) => {
+                let msg = self.ctx.asm[msg].to_owned();
+                self.b.throw_new("java/lang/IllegalStateException", &msg);
+                Ok(())
+            }
+        }
+    }
+    /// Stores `val` at the address of `tpe` on top of the stack.
+    fn store_val(&mut self, tpe: Type, val: NodeIdx) -> Result<(), String> {
+        let val = self.node(val)?;
+        if let Repr::Scalar(kind) = self.ctx.repr(tpe)? {
+            self.convert(val, kind)?;
+        }
+        self.store_mem(tpe)
+    }
+}
+/// Casts the reference on top of the stack to the type described by `desc`.
+pub(super) fn coerce(b: &mut Builder, desc: &str) {
+    if desc.starts_with('[') {
+        b.type_op(op::CHECKCAST, desc);
+    } else if desc.starts_with('L') && desc != "Ljava/lang/Object;" {
+        b.type_op(op::CHECKCAST, &desc[1..desc.len() - 1]);
+    }
+}
+/// Truncates the int on top of the stack to the range of `tpe`.
+pub(super) fn normalize(b: &mut Builder, tpe: Type) {
+    match tpe {
+        Type::Int(Int::U8) => {
+            b.int(0xff);
+            b.op(op::IAND, -1);
+        }
+        Type::Int(Int::I8) => b.op(op::I2B, 0),
+        Type::Int(Int::U16) | Type::PlatformChar => b.op(op::I2C, 0),
+        Type::Int(Int::I16) => b.op(op::I2S, 0),
+        _ => (),
+    }
+}
@@ -1,27 +1,51 @@
-// This is a WIP experiment.
-#![allow(dead_code, unused_imports)]
-use std::io::Write;
+//! Exports an [`Assembly`] as a jar of JVM class files.
+//!
+//! Every class of the assembly becomes a JVM class, and all of its methods become static methods
+//! (constructors of reference types excepted). Since a JVM class is limited in size, classes with
+//! many methods are split into chunks: `Foo`, `Foo$1`, `Foo$2`...
+//!
+//! The JVM has no unmanaged memory, so it is emulated using `sun.misc.Unsafe`: pointers are `long`
+//! addresses of off-heap memory. Values which don't fit into a JVM primitive (structs, 128 bit ints)
+//! are passed around as the address of their data. Locals which are such values, or whose address is
+//! taken, live in a shadow stack frame managed by the generated `cilly/Runtime` class.
+use std::{io::Write, rc::Rc};
 
-use crate::v2::MethodImpl;
+use fxhash::{FxHashMap, FxHashSet};
+
+use crate::config;
 
 use super::{
-    cilroot::BranchCond, Assembly, CILIter, CILIterElem, CILNode, ClassRefIdx, Exporter, NodeIdx,
-    RootIdx, Type,
+    cilnode::MethodKind, Assembly, ClassDefIdx, ClassRefIdx, Exporter, FieldIdx, Float, Int,
+    MethodDefIdx, MethodImpl, MethodRefIdx, StaticFieldIdx, StringIdx, Type,
 };
+use class_file::{op, Builder, ClassFile, Kind, ACC_FINAL, ACC_PUBLIC, ACC_STATIC};
 
-#[doc = "Specifies the path to the java bytecode assembler."]
-pub static JAVA_ASM_PATH: std::sync::LazyLock<String> = std::sync::LazyLock::new(|| {
-    std::env::vars()
-        .find_map(|(key, value)| {
-            if key == "JAVA_ASM_PATH" {
-                Some(value)
-            } else {
-                None
-            }
-        })
-        .unwrap_or("krak2".into())
-});
+mod class_file;
+mod jar;
+mod lower;
+mod runtime;
+
+/// The class holding the shadow stack, the statics and the unmanaged memory helpers.
+const RUNTIME: &str = "cilly/Runtime";
+/// The class holding the implementations of externs and .NET methods.
+const NATIVES: &str = "cilly/Natives";
+/// The class holding the trampolines used to call function pointers.
+const DISPATCH: &str = "cilly/Dispatch";
+/// The amount of methods placed in a single JVM class, before a new chunk is started.
+const METHODS_PER_CLASS: usize = 256;
 
+config!(
+    JAVA_EXPORT_WARNINGS,
+    bool,
+    false,
+    "Tells the Java exporter to print the classes, fields and methods it could not export."
+);
+/// Reports something the exporter skipped, if `JAVA_EXPORT_WARNINGS` is set.
+fn warn(msg: std::fmt::Arguments) {
+    if *JAVA_EXPORT_WARNINGS {
+        eprintln!("Warning: {msg}");
+    }
+}
 pub struct JavaExporter {
     is_lib: bool,
 }
@@ -30,176 +54,905 @@ impl JavaExporter {
     pub fn new(is_lib: bool) -> Self {
         Self { is_lib }
     }
-
-    fn export_to_write(&self, asm: &super::Assembly, out: &mut impl Write) -> std::io::Result<()> {
-        // Iterate trough all types
-        for class_def in asm.iter_class_defs() {
-            let vis = match class_def.access() {
-                crate::v2::Access::Extern | crate::v2::Access::Public => "public",
-                crate::v2::Access::Private => "private",
-            };
-            let sealed = if class_def.is_valuetype() {
-                "final"
-            } else {
-                ""
-            };
-            let extends = if let Some(parrent) = class_def.extends() {
-                simple_class_ref(parrent, asm)
-            } else {
-                "java/lang/Object".into()
-            };
-
-            let name = asm[class_def.name()].replace('.', "/");
-            writeln!(out, ".class {vis} {sealed} {name}\n.super {extends}")?;
-            // Export size
-            if let Some(size) = class_def.explict_size() {
-                writeln!(out, ".method public static sizeof : ()J\n.code stack 1 locals 0\nldc {size}\nlreturn\n.end code\n.end method", size = size.get())?;
-            }
-            // Export all fields
-            for (tpe, name, offset) in class_def.fields() {
-                let name = &asm[*name];
-
-                if let Some(offset) = offset {
-                    writeln!(
-                        out,
-                        ".method public static get_{name}_Address : (J)J\n.code stack 3 locals 1\nlload_0\nldc {offset}\nladd\nlreturn\n.end code\n.end method"
-                    )
-                } else {
-                    writeln!(
-                        out,
-                        ".field public {name} {tpe}",
-                        tpe = type_string(*tpe, asm)
-                    )
-                }?;
-            }
-
-            writeln!(out, ".end class")?;
-        }
-
-        Ok(())
-    }
 }
-
 impl Exporter for JavaExporter {
     type Error = std::io::Error;
 
     fn export(&self, asm: &super::Assembly, target: &std::path::Path) -> Result<(), Self::Error> {
-        // The IL file should be next to the target
-        let il_path = target.with_extension("j");
-        let mut il_out = std::io::BufWriter::new(std::fs::File::create(&il_path)?);
-        self.export_to_write(asm, &mut il_out)?;
-        // Needed to ensure the IL file is valid!
-        il_out.flush().unwrap();
-        drop(il_out);
-        let jar_out = target.with_extension("jar");
-
-        let mut cmd = std::process::Command::new(JAVA_ASM_PATH.clone());
-        cmd.arg("asm").arg(il_path)
-        .arg("--out")
-        .arg(jar_out)
-        // .arg("-FOLD") saves up on space, consider enabling.
-        ;
-        let out = cmd.output().unwrap();
-        let stdout = String::from_utf8_lossy(&out.stdout);
-        let stderr = String::from_utf8_lossy(&out.stderr);
-        assert!(
-            !(stderr.contains("Error") || stdout.contains("Error")),
-            "stdout:{} stderr:{} cmd:{cmd:?}",
-            stdout,
-            String::from_utf8_lossy(&out.stderr)
-        );
-
-        Ok(())
+        let jar = export_jar(asm.clone(), self.is_lib);
+        let mut out = std::io::BufWriter::new(std::fs::File::create(target.with_extension("jar"))?);
+        jar.write(&mut out)?;
+        out.flush()
     }
 }
-fn simple_class_ref(cref: ClassRefIdx, asm: &Assembly) -> String {
-    let cref = asm.class_ref(cref);
-    let name = asm[cref.name()].replace('.', "/");
-    if let Some(assembly) = cref.asm() {
-        let assembly = asm[assembly].replace('.', "/");
-        format!("{assembly}/{name}")
+/// The way a value of some type is represented on the JVM.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Repr {
+    Void,
+    /// A JVM primitive or reference.
+    Scalar(Kind),
+    /// Data in unmanaged memory, passed around by its address.
+    Memory {
+        size: u64,
+        align: u64,
+    },
+}
+impl Repr {
+    /// The kind of the JVM value representing this.
+    fn kind(self) -> Option<Kind> {
+        match self {
+            Self::Void => None,
+            Self::Scalar(kind) => Some(kind),
+            Self::Memory { .. } => Some(Kind::Long),
+        }
+    }
+}
+/// The layout of a value type.
+struct Layout {
+    size: u64,
+    align: u64,
+    fields: FxHashMap<StringIdx, u64>,
+}
+/// A JVM method.
+#[derive(Clone, Debug)]
+struct JvmMethod {
+    class: String,
+    name: String,
+    desc: String,
+}
+/// How a method is called.
+enum Target {
+    /// By an `invokestatic`.
+    Static(JvmMethod),
+    /// By creating a new object of `class`, and calling its `<init>` method.
+    Init { class: String, desc: String },
+    /// By calling the static constructor `ctor` of a value type on a fresh instance.
+    ValueCtor {
+        ctor: JvmMethod,
+        size: u64,
+        align: u64,
+    },
+}
+/// Where a static field is stored.
+#[derive(Clone)]
+enum StaticSlot {
+    /// In a static field of the runtime class, with a name and a descriptor.
+    Field(String, String),
+    /// In the statics block, at an offset.
+    Memory(u64),
+}
+/// A method without a CIL body, implemented by the exporter.
+struct Native {
+    /// The .NET class this method belongs to, or `None` for externs.
+    class: Option<String>,
+    name: String,
+    inputs: Vec<Type>,
+    output: Type,
+}
+/// The state shared by the export of all methods.
+struct Ctx {
+    asm: Assembly,
+    layouts: FxHashMap<ClassRefIdx, Rc<Layout>>,
+    class_names: FxHashMap<ClassRefIdx, String>,
+    methods: FxHashMap<MethodRefIdx, Result<JvmMethod, String>>,
+    statics: FxHashMap<StaticFieldIdx, StaticSlot>,
+    statics_size: u64,
+    object_statics: Vec<(String, String)>,
+    fn_ids: FxHashMap<MethodRefIdx, i64>,
+    fn_targets: Vec<(i64, JvmMethod)>,
+    dispatchers: FxHashMap<String, String>,
+    natives: Vec<(JvmMethod, Native)>,
+    native_ids: FxHashMap<MethodRefIdx, JvmMethod>,
+    exceptions: FxHashSet<String>,
+}
+/// Replaces the characters which may not appear in JVM names.
+fn escape(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '.' | ';' | '[' | '/' | '<' | '>' => '$',
+            c => c,
+        })
+        .collect();
+    if name.is_empty() {
+        "$".into()
     } else {
         name
     }
 }
-pub(crate) fn class_ref(cref: ClassRefIdx, asm: &Assembly) -> String {
-    let cref = asm.class_ref(cref);
-    let name = &asm[cref.name()];
-    let prefix = if cref.is_valuetype() {
-        "valuetype"
-    } else {
-        "class"
-    };
-    let generic_list = if cref.generics().is_empty() {
-        String::new()
+/// The descriptor of a JVM value of `kind`.
+fn kind_desc(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Int => "I",
+        Kind::Long => "J",
+        Kind::Float => "F",
+        Kind::Double => "D",
+        Kind::Ref => "Ljava/lang/Object;",
+    }
+}
+/// The JVM class used for objects of the external .NET class `name`, if there is one.
+fn external_class(name: &str) -> Option<String> {
+    match name {
+        "System.Object" => Some("java/lang/Object".into()),
+        "System.String" => Some("java/lang/String".into()),
+        "System.Exception" => Some("java/lang/Throwable".into()),
+        _ if name.ends_with("Exception") => Some(format!("cilly/{}", escape(name))),
+        _ => None,
+    }
+}
+impl Ctx {
+    fn new(asm: Assembly) -> Self {
+        let mut ctx = Self {
+            asm,
+            layouts: FxHashMap::default(),
+            class_names: FxHashMap::default(),
+            methods: FxHashMap::default(),
+            statics: FxHashMap::default(),
+            statics_size: 0,
+            object_statics: Vec::new(),
+            fn_ids: FxHashMap::default(),
+            fn_targets: Vec::new(),
+            dispatchers: FxHashMap::default(),
+            natives: Vec::new(),
+            native_ids: FxHashMap::default(),
+            exceptions: FxHashSet::default(),
+        };
+        ctx.assign_names();
+        ctx
+    }
+    /// The class definitions, sorted by name, so that the output does not depend on hashing.
+    fn sorted_classes(&self) -> Vec<ClassDefIdx> {
+        let mut classes: Vec<_> = self.asm.class_defs().keys().copied().collect();
+        classes.sort_by_key(|class| &self.asm[self.asm.class_defs()[class].name()]);
+        classes
+    }
+    /// Assigns JVM names to all classes and methods.
+    fn assign_names(&mut self) {
+        let classes = self.sorted_classes();
+        let mut used = FxHashSet::default();
+        for class in &classes {
+            let base = format!("rust/{}", escape(&self.asm[self.asm[*class].name()]));
+            let mut name = base.clone();
+            let mut idx = 0;
+            while !used.insert(name.to_lowercase()) {
+                idx += 1;
+                name = format!("{base}${idx}");
+            }
+            self.class_names.insert(**class, name);
+        }
+        for class in classes {
+            let def = self.asm[class].clone();
+            let base = self.class_names[&*class].clone();
+            let mut names = FxHashSet::default();
+            let mut statics = 0;
+            for method in def.methods() {
+                let mdef = self.asm[*method].clone();
+                if let MethodImpl::AliasFor(_) = mdef.implementation() {
+                    continue;
+                }
+                let sig = self.asm[mdef.sig()].clone();
+                let res = if mdef.kind() == MethodKind::Constructor && !def.is_valuetype() {
+                    self.method_desc(&sig.inputs()[1..], Type::Void)
+                        .map(|desc| JvmMethod {
+                            class: base.clone(),
+                            name: "<init>".into(),
+                            desc,
+                        })
+                } else {
+                    self.method_desc(sig.inputs(), *sig.output()).map(|desc| {
+                        let class = match statics / METHODS_PER_CLASS {
+                            0 => base.clone(),
+                            chunk => format!("{base}${chunk}"),
+                        };
+                        statics += 1;
+                        let escaped = escape(&self.asm[mdef.name()]);
+                        let mut name = escaped.clone();
+                        let mut idx = 0;
+                        while !names.insert((class.clone(), name.clone(), desc.clone())) {
+                            idx += 1;
+                            name = format!("{escaped}${idx}");
+                        }
+                        JvmMethod { class, name, desc }
+                    })
+                };
+                self.methods.insert(**method, res);
+            }
+        }
+    }
+    /// Returns the layout of the value type `class`.
+    fn layout(&mut self, class: ClassRefIdx) -> Result<Rc<Layout>, String> {
+        if let Some(layout) = self.layouts.get(&class) {
+            return Ok(layout.clone());
+        }
+        let layout = match self.asm.class_ref_to_def(class) {
+            Some(def) => {
+                let def = self.asm[def].clone();
+                let mut fields = FxHashMap::default();
+                let mut size: u64 = 0;
+                let mut align: u64 = 1;
+                for (tpe, name, offset) in def.fields() {
+                    let field_size = self.size_of(*tpe)?;
+                    let field_align = self.align_of(*tpe)?;
+                    align = align.max(field_align);
+                    let offset = match offset {
+                        Some(offset) => u64::from(*offset),
+                        None => size.next_multiple_of(field_align.max(1)),
+                    };
+                    size = size.max(offset + field_size);
+                    fields.insert(*name, offset);
+                }
+                let align = def.align().map_or(align, |align| u64::from(align.get()));
+                let size = def.explict_size().map_or_else(
+                    || size.next_multiple_of(align),
+                    |size| u64::from(size.get()),
+                );
+                Layout {
+                    size,
+                    align,
+                    fields,
+                }
+            }
+            None => {
+                let (size, align) = match &self.asm[self.asm[class].name()] {
+                    "System.Int128" | "System.UInt128" => (16, 16),
+                    "System.Half" => (2, 2),
+                    name => return Err(format!("The layout of {name} is not known")),
+                };
+                Layout {
+                    size,
+                    align,
+                    fields: FxHashMap::default(),
+                }
+            }
+        };
+        let layout = Rc::new(layout);
+        self.layouts.insert(class, layout.clone());
+        Ok(layout)
+    }
+    fn size_of(&mut self, tpe: Type) -> Result<u64, String> {
+        Ok(match self.repr(tpe)? {
+            Repr::Void => 0,
+            Repr::Memory { size, .. } => size,
+            Repr::Scalar(_) => match tpe {
+                Type::Int(int) => int.size().map_or(8, u64::from),
+                Type::Float(float) => u64::from(float.size()),
+                Type::Bool => 1,
+                Type::PlatformChar => 2,
+                _ => 8,
+            },
+        })
+    }
+    fn align_of(&mut self, tpe: Type) -> Result<u64, String> {
+        Ok(match self.repr(tpe)? {
+            Repr::Memory { align, .. } => align,
+            _ => self.size_of(tpe)?.max(1),
+        })
+    }
+    /// Returns the offset of `field` within its owner.
+    fn field_offset(&mut self, field: FieldIdx) -> Result<u64, String> {
+        let field = self.asm[field];
+        self.layout(field.owner())?
+            .fields
+            .get(&field.name())
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "{} has no field {}",
+                    &self.asm[self.asm[field.owner()].name()],
+                    &self.asm[field.name()]
+                )
+            })
+    }
+    fn repr(&mut self, tpe: Type) -> Result<Repr, String> {
+        Ok(match tpe {
+            Type::Void => Repr::Void,
+            Type::Bool | Type::PlatformChar => Repr::Scalar(Kind::Int),
+            Type::Int(Int::U128 | Int::I128) => Repr::Memory {
+                size: 16,
+                align: 16,
+            },
+            Type::Int(Int::U64 | Int::I64 | Int::USize | Int::ISize) => Repr::Scalar(Kind::Long),
+            Type::Int(_) => Repr::Scalar(Kind::Int),
+            Type::Float(Float::F32) => Repr::Scalar(Kind::Float),
+            Type::Float(Float::F64) => Repr::Scalar(Kind::Double),
+            Type::Float(float) => Repr::Memory {
+                size: u64::from(float.size()),
+                align: u64::from(float.size()),
+            },
+            Type::Ptr(_) | Type::Ref(_) | Type::FnPtr(_) => Repr::Scalar(Kind::Long),
+            Type::ClassRef(class) if self.asm[class].is_valuetype() => {
+                let layout = self.layout(class)?;
+                Repr::Memory {
+                    size: layout.size,
+                    align: layout.align,
+                }
+            }
+            Type::ClassRef(_)
+            | Type::PlatformString
+            | Type::PlatformObject
+            | Type::PlatformArray { .. } => Repr::Scalar(Kind::Ref),
+            Type::SIMDVector(vec) => Repr::Memory {
+                size: u64::from(vec.bits()) / 8,
+                align: match vec.elem() {
+                    super::tpe::simd::SIMDElem::Int(int) => int.size().map_or(8, u64::from),
+                    super::tpe::simd::SIMDElem::Float(float) => u64::from(float.size()),
+                },
+            },
+            Type::PlatformGeneric(..) => {
+                return Err("Generic types are not supported on the JVM".into())
+            }
+        })
+    }
+    /// The field descriptor of a value of `tpe`.
+    fn desc(&mut self, tpe: Type) -> Result<String, String> {
+        Ok(match tpe {
+            Type::PlatformString => "Ljava/lang/String;".into(),
+            Type::ClassRef(class) if &self.asm[self.asm[class].name()] == "System.String" => {
+                "Ljava/lang/String;".into()
+            }
+            Type::PlatformArray { elem, dims } => {
+                let elem = match self.asm[elem] {
+                    Type::Bool => "Z".into(),
+                    Type::PlatformChar => "C".into(),
+                    Type::Int(Int::U8 | Int::I8) => "B".into(),
+                    Type::Int(Int::U16 | Int::I16) => "S".into(),
+                    elem => self.desc(elem)?,
+                };
+                format!("{}{elem}", "[".repeat(usize::from(dims.get())))
+            }
+            _ => match self.repr(tpe)? {
+                Repr::Void => "V".into(),
+                repr => kind_desc(repr.kind().unwrap()).into(),
+            },
+        })
+    }
+    fn method_desc(&mut self, inputs: &[Type], output: Type) -> Result<String, String> {
+        let mut desc = String::from("(");
+        for input in inputs {
+            if *input == Type::Void {
+                return Err("void argument".into());
+            }
+            desc.push_str(&self.desc(*input)?);
+        }
+        desc.push(')');
+        desc.push_str(&self.desc(output)?);
+        Ok(desc)
+    }
+    /// The JVM class (or array descriptor) of objects of `tpe`, used by casts and type checks.
+    fn object_class(&mut self, tpe: Type) -> Result<String, String> {
+        match tpe {
+            Type::PlatformString => Ok("java/lang/String".into()),
+            Type::PlatformObject => Ok("java/lang/Object".into()),
+            Type::PlatformArray { .. } => self.desc(tpe),
+            Type::ClassRef(class) if !self.asm[class].is_valuetype() => {
+                if let Some(name) = self.class_names.get(&class) {
+                    return Ok(name.clone());
+                }
+                let name = &self.asm[self.asm[class].name()];
+                let jvm = external_class(name)
+                    .ok_or_else(|| format!("The class {name} is not supported on the JVM"))?;
+                if jvm.starts_with("cilly/") {
+                    self.exceptions.insert(jvm.clone());
+                }
+                Ok(jvm)
+            }
+            _ => Err(format!("{tpe:?} is not an object type")),
+        }
+    }
+    /// The JVM superclass of the defined reference class `class`.
+    fn super_class(&mut self, class: ClassDefIdx) -> Result<String, String> {
+        let Some(parent) = self.asm[class].extends() else {
+            return Ok("java/lang/Object".into());
+        };
+        match self.object_class(Type::ClassRef(parent))?.as_str() {
+            "java/lang/Throwable" => Ok("java/lang/RuntimeException".into()),
+            other => Ok(other.into()),
+        }
+    }
+    /// Returns the method `mref` is called with.
+    fn target(&mut self, mref: MethodRefIdx) -> Result<Target, String> {
+        let method = self.asm[mref].clone();
+        if let Some(def) = self.asm.method_ref_to_def(mref) {
+            if let MethodImpl::AliasFor(target) = self.asm[def].implementation() {
+                return self.target(*target);
+            }
+            let jvm = self
+                .methods
+                .get(&mref)
+                .cloned()
+                .unwrap_or_else(|| Err("method without a JVM counterpart".into()))?;
+            if method.kind() != MethodKind::Constructor {
+                return Ok(Target::Static(jvm));
+            }
+            return Ok(if jvm.name == "<init>" {
+                Target::Init {
+                    class: jvm.class,
+                    desc: jvm.desc,
+                }
+            } else {
+                let layout = self.layout(method.class())?;
+                Target::ValueCtor {
+                    ctor: jvm,
+                    size: layout.size,
+                    align: layout.align,
+                }
+            });
+        }
+        let class = self.asm[method.class()].clone();
+        let class_name = self.asm[class.name()].to_owned();
+        if method.kind() == MethodKind::Constructor && !class.is_valuetype() {
+            let inputs = method.stack_inputs(&self.asm).to_vec();
+            let desc = self.method_desc(&inputs, Type::Void)?;
+            let class = match self.object_class(Type::ClassRef(method.class()))?.as_str() {
+                "java/lang/Throwable" => {
+                    let shim = format!("cilly/{}", escape(&class_name));
+                    self.exceptions.insert(shim.clone());
+                    shim
+                }
+                other => other.to_owned(),
+            };
+            if desc != "()V" && (desc != "(Ljava/lang/String;)V" || class == "java/lang/Object") {
+                return Err(format!(
+                    "The constructor {class_name}{desc} is not supported"
+                ));
+            }
+            return Ok(Target::Init { class, desc });
+        }
+        // Natives construct value types themselves, and return the new value.
+        Ok(Target::Static(self.native(mref)?))
+    }
+    /// Registers the .NET method `mref`, which will be implemented by the natives class.
+    fn native(&mut self, mref: MethodRefIdx) -> Result<JvmMethod, String> {
+        if let Some(jvm) = self.native_ids.get(&mref) {
+            return Ok(jvm.clone());
+        }
+        let method = self.asm[mref].clone();
+        let class = self.asm[self.asm[method.class()].name()].to_owned();
+        let name = self.asm[method.name()].to_owned();
+        let sig = self.asm[method.sig()].clone();
+        let (inputs, output) = if method.kind() == MethodKind::Constructor {
+            (
+                method.stack_inputs(&self.asm).to_vec(),
+                Type::ClassRef(method.class()),
+            )
+        } else {
+            (sig.inputs().to_vec(), *sig.output())
+        };
+        let desc = self.method_desc(&inputs, output)?;
+        let jvm = JvmMethod {
+            class: NATIVES.into(),
+            name: escape(&format!("{class}::{name}")),
+            desc,
+        };
+        let duplicate = self
+            .natives
+            .iter()
+            .any(|(other, _)| other.name == jvm.name && other.desc == jvm.desc);
+        if !duplicate {
+            self.natives.push((
+                jvm.clone(),
+                Native {
+                    class: Some(class),
+                    name,
+                    inputs,
+                    output,
+                },
+            ));
+        }
+        self.native_ids.insert(mref, jvm.clone());
+        Ok(jvm)
+    }
+    /// Returns where the static field `field` is stored.
+    fn static_slot(&mut self, field: StaticFieldIdx) -> Result<StaticSlot, String> {
+        if let Some(slot) = self.statics.get(&field) {
+            return Ok(slot.clone());
+        }
+        let tpe = self.asm[field].tpe();
+        let slot = match self.repr(tpe)? {
+            Repr::Scalar(Kind::Ref) => {
+                let name = format!("s${}", self.object_statics.len());
+                let desc = self.desc(tpe)?;
+                self.object_statics.push((name.clone(), desc.clone()));
+                StaticSlot::Field(name, desc)
+            }
+            _ => {
+                let offset = self
+                    .statics_size
+                    .next_multiple_of(self.align_of(tpe)?.clamp(1, 64));
+                self.statics_size = offset + self.size_of(tpe)?;
+                StaticSlot::Memory(offset)
+            }
+        };
+        self.statics.insert(field, slot.clone());
+        Ok(slot)
+    }
+    /// Returns the id used as the function pointer to `mref`.
+    fn fn_id(&mut self, mref: MethodRefIdx) -> Result<i64, String> {
+        if let Some(id) = self.fn_ids.get(&mref) {
+            return Ok(*id);
+        }
+        let Target::Static(jvm) = self.target(mref)? else {
+            return Err("Can't take the address of a constructor".into());
+        };
+        // 0 is the null function pointer.
+        let id = self.fn_targets.len() as i64 + 1;
+        self.fn_targets.push((id, jvm));
+        self.fn_ids.insert(mref, id);
+        Ok(id)
+    }
+    /// Returns the name of the trampoline calling function pointers of the JVM signature `desc`.
+    fn dispatcher(&mut self, desc: &str) -> String {
+        let next = self.dispatchers.len();
+        self.dispatchers
+            .entry(desc.to_owned())
+            .or_insert_with(|| format!("call${next}"))
+            .clone()
+    }
+}
+/// Inserts the function pointer argument into the descriptor of a dispatcher.
+fn dispatch_desc(desc: &str) -> String {
+    desc.replacen(')', "J)", 1)
+}
+/// Builds a method, which throws an exception with `msg` when called.
+fn stub(class: &mut ClassFile, access: u16, name: &str, desc: &str, msg: &str) {
+    let (args, _) = class_file::split_method_desc(desc);
+    let slots = args
+        .iter()
+        .filter_map(|arg| Kind::of_desc(arg))
+        .map(Kind::slots)
+        .sum::<u16>()
+        + u16::from(access & ACC_STATIC == 0);
+    let mut builder = Builder::new(&mut class.cp, slots);
+    builder.throw_new("java/lang/UnsupportedOperationException", msg);
+    let code = builder
+        .finish()
+        .expect("ERROR: could not assemble a stub method");
+    class.add_method(access, name, desc, &code);
+}
+/// A set of classes, created on demand.
+#[derive(Default)]
+struct Classes {
+    classes: Vec<ClassFile>,
+    index: FxHashMap<String, usize>,
+}
+impl Classes {
+    fn get(&mut self, name: &str, extends: &str) -> &mut ClassFile {
+        let idx = *self.index.entry(name.to_owned()).or_insert_with(|| {
+            self.classes
+                .push(ClassFile::new(name, extends, ACC_PUBLIC | ACC_FINAL));
+            self.classes.len() - 1
+        });
+        &mut self.classes[idx]
+    }
+}
+fn export_jar(asm: Assembly, is_lib: bool) -> jar::Jar {
+    let mut ctx = Ctx::new(asm);
+    let mut classes = Classes::default();
+    for class in ctx.sorted_classes() {
+        let def = ctx.asm[class].clone();
+        let name = ctx.class_names[&*class].clone();
+        if def.is_valuetype() {
+            classes.get(&name, "java/lang/Object");
+        } else {
+            let extends = ctx.super_class(class).unwrap_or_else(|msg| {
+                warn(format_args!("{msg}"));
+                "java/lang/Object".into()
+            });
+            let jvm_class = classes.get(&name, &extends);
+            for (tpe, field, _) in def.fields() {
+                let field = escape(&ctx.asm[*field]);
+                match ctx.desc(*tpe) {
+                    Ok(desc)
+                        if Kind::of_desc(&desc).is_some()
+                            && !matches!(ctx.repr(*tpe), Ok(Repr::Memory { .. })) =>
+                    {
+                        jvm_class.add_field(ACC_PUBLIC, &field, &desc);
+                    }
+                    _ => warn(format_args!("the field {field} of {name} is not supported")),
+                }
+            }
+            add_default_init(&mut ctx, jvm_class, &def, &extends);
+        }
+        for method in def.methods() {
+            let Some(jvm) = ctx.methods.get(&**method).cloned() else {
+                continue;
+            };
+            let jvm = match jvm {
+                Ok(jvm) => jvm,
+                Err(msg) => {
+                    warn(format_args!(
+                        "can't export {}: {msg}",
+                        &ctx.asm[ctx.asm[*method].name()]
+                    ));
+                    continue;
+                }
+            };
+            // The class itself was created above, so only the extra chunks are created here.
+            let jvm_class = classes.get(&jvm.class, "java/lang/Object");
+            lower::method(&mut ctx, jvm_class, *method, &jvm);
+        }
+    }
+    let main_class = if is_lib {
+        None
     } else {
-        format!(
-            "<{generics}>",
-            generics = cref
-                .generics()
-                .iter()
-                .map(|tpe| type_string(*tpe, asm))
-                .intersperse(",".to_string())
-                .collect::<String>()
-        )
+        let main_module = ctx.asm.main_module();
+        let name = ctx.class_names[&*main_module].clone();
+        add_main(
+            &mut ctx,
+            classes.get(&name, "java/lang/Object"),
+            main_module,
+        );
+        Some(name)
     };
-    let generic_postfix = if cref.generics().is_empty() {
-        String::new()
-    } else {
-        format!("`{}", cref.generics().len())
+    classes.classes.push(runtime::natives(&mut ctx));
+    classes.classes.push(dispatch(&mut ctx));
+    let mut exceptions: Vec<_> = ctx.exceptions.iter().cloned().collect();
+    exceptions.sort();
+    for exception in exceptions {
+        classes.classes.push(exception_class(&exception));
+    }
+    classes.classes.push(runtime::runtime(&mut ctx));
+    let mut jar = jar::Jar::new(main_class.as_deref());
+    for class in &classes.classes {
+        jar.add(&format!("{}.class", class.name()), &class.to_bytes());
+    }
+    jar
+}
+/// Adds a constructor without arguments to a reference class, which has none, so that subclasses can call it.
+fn add_default_init(ctx: &mut Ctx, class: &mut ClassFile, def: &super::ClassDef, extends: &str) {
+    let has_default = def.methods().iter().any(|method| {
+        ctx.methods
+            .get(&**method)
+            .is_some_and(|jvm| matches!(jvm, Ok(jvm) if jvm.name == "<init>" && jvm.desc == "()V"))
+    });
+    if has_default {
+        return;
+    }
+    let mut builder = Builder::new(&mut class.cp, 1);
+    builder.load(Kind::Ref, 0);
+    builder.invoke(op::INVOKESPECIAL, extends, "<init>", "()V");
+    builder.ret(None);
+    let code = builder
+        .finish()
+        .expect("ERROR: could not assemble a default constructor");
+    class.add_method(ACC_PUBLIC, "<init>", "()V", &code);
+}
+/// Adds the `main` method, which sets up the runtime, and calls the static constructor and the entrypoint.
+fn add_main(ctx: &mut Ctx, class: &mut ClassFile, main_module: ClassDefIdx) {
+    let find = |ctx: &Ctx, name: &str| -> Option<MethodDefIdx> {
+        let name = ctx.asm.get_prealllocated_string(name)?;
+        ctx.asm[main_module]
+            .methods()
+            .iter()
+            .find(|method| ctx.asm[**method].name() == name)
+            .copied()
     };
-    if let Some(assembly) = cref.asm() {
-        let assembly = &asm[assembly];
-        format!("{prefix} [{assembly}]'{name}{generic_postfix}'{generic_list}")
-    } else {
-        format!("{prefix} '{name}{generic_postfix}'{generic_list}")
+    let mut builder = Builder::new(&mut class.cp, 1);
+    builder.load(Kind::Ref, 0);
+    builder.call(RUNTIME, "set_args", "([Ljava/lang/String;)V");
+    let entrypoint = find(ctx, "entrypoint");
+    let cctor = find(ctx, ".cctor").filter(|_| ctx.asm.has_cctor());
+    for method in [cctor, entrypoint].into_iter().flatten() {
+        let Some(Ok(jvm)) = ctx.methods.get(&*method).cloned() else {
+            continue;
+        };
+        let inputs = ctx.asm[ctx.asm[method].sig()].inputs().to_vec();
+        for input in inputs {
+            match ctx.repr(input) {
+                Ok(Repr::Scalar(Kind::Ref))
+                    if ctx
+                        .desc(input)
+                        .is_ok_and(|desc| desc == "[Ljava/lang/String;") =>
+                {
+                    builder.call(RUNTIME, "args", "()[Ljava/lang/String;");
+                }
+                Ok(Repr::Scalar(Kind::Int)) => builder.int(0),
+                Ok(Repr::Scalar(Kind::Float)) => builder.float(0.0),
+                Ok(Repr::Scalar(Kind::Double)) => builder.double(0.0),
+                Ok(Repr::Scalar(Kind::Ref)) => builder.op(op::ACONST_NULL, 1),
+                _ => builder.long(0),
+            }
+        }
+        builder.call(&jvm.class, &jvm.name, &jvm.desc);
+        if let Some(kind) = Kind::of_desc(class_file::split_method_desc(&jvm.desc).1) {
+            builder.op(
+                if kind.slots() == 2 { op::POP2 } else { op::POP },
+                -i32::from(kind.slots()),
+            );
+        }
+    }
+    if entrypoint.is_none() {
+        warn(format_args!("the assembly has no entrypoint"));
     }
+    builder.int(0);
+    builder.call(RUNTIME, "exit", "(I)V");
+    builder.ret(None);
+    let code = builder
+        .finish()
+        .expect("ERROR: could not assemble the main method");
+    class.add_method(
+        ACC_PUBLIC | ACC_STATIC,
+        "main",
+        "([Ljava/lang/String;)V",
+        &code,
+    );
+}
+/// Builds the class of trampolines, which call the target of a function pointer.
+fn dispatch(ctx: &mut Ctx) -> ClassFile {
+    let mut class = ClassFile::new(DISPATCH, "java/lang/Object", ACC_PUBLIC | ACC_FINAL);
+    let mut dispatchers: Vec<_> = ctx
+        .dispatchers
+        .iter()
+        .map(|(desc, name)| (name.clone(), desc.clone()))
+        .collect();
+    dispatchers.sort();
+    for (name, desc) in dispatchers {
+        let full_desc = dispatch_desc(&desc);
+        let (args, ret) = class_file::split_method_desc(&desc);
+        let kinds: Vec<Kind> = args.iter().filter_map(|arg| Kind::of_desc(arg)).collect();
+        let slots: u16 = kinds.iter().map(|kind| kind.slots()).sum();
+        let ret = Kind::of_desc(ret);
+        let mut builder = Builder::new(&mut class.cp, slots + 2);
+        builder.load(Kind::Long, slots);
+        builder.op(op::L2I, -1);
+        let default = builder.label();
+        let mut cases = Vec::new();
+        let targets: Vec<_> = ctx
+            .fn_targets
+            .iter()
+            .filter(|(_, target)| target.desc == desc)
+            .cloned()
+            .collect();
+        for (id, _) in &targets {
+            cases.push((*id as i32, builder.label()));
+        }
+        builder.code.lookupswitch(default, &mut cases.clone());
+        for ((_, target), (_, label)) in targets.iter().zip(cases.iter()) {
+            builder.place(*label);
+            builder.code.set_depth(0);
+            let mut slot = 0;
+            for kind in &kinds {
+                builder.load(*kind, slot);
+                slot += kind.slots();
+            }
+            builder.call(&target.class, &target.name, &target.desc);
+            builder.ret(ret);
+        }
+        builder.place(default);
+        builder.code.set_depth(0);
+        builder.throw_new(
+            "java/lang/IllegalStateException",
+            "Call to an invalid function pointer",
+        );
+        match builder.finish() {
+            Ok(code) => class.add_method(ACC_PUBLIC | ACC_STATIC, &name, &full_desc, &code),
+            Err(err) => stub(
+                &mut class,
+                ACC_PUBLIC | ACC_STATIC,
+                &name,
+                &full_desc,
+                &format!("Could not assemble the dispatcher: {err:?}"),
+            ),
+        }
+    }
+    class
+}
+/// Builds a class standing in for the .NET exception class `name`.
+fn exception_class(name: &str) -> ClassFile {
+    let mut class = ClassFile::new(name, "java/lang/RuntimeException", ACC_PUBLIC);
+    for (desc, slots) in [("()V", 1), ("(Ljava/lang/String;)V", 2)] {
+        let mut builder = Builder::new(&mut class.cp, slots);
+        for slot in 0..slots {
+            builder.load(Kind::Ref, slot);
+        }
+        builder.invoke(
+            op::INVOKESPECIAL,
+            "java/lang/RuntimeException",
+            "<init>",
+            desc,
+        );
+        builder.ret(None);
+        let code = builder
+            .finish()
+            .expect("ERROR: could not assemble an exception constructor");
+        class.add_method(ACC_PUBLIC, "<init>", desc, &code);
+    }
+    class
+}
+#[test]
+fn export_entrypoint() {
+    use super::{cilnode::MethodKind, Access, BasicBlock, CILRoot, ClassRef, Const, MethodDef};
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let console = ClassRef::console(&mut asm);
+    let console = asm[console].clone();
+    let beep = asm.alloc_string("Beep");
+    let beep = console.static_mref(&[], Type::Void, beep, &mut asm);
+    let write_line = asm.alloc_string("WriteLine");
+    let write_line = console.static_mref(&[Type::Int(Int::I32)], Type::Void, write_line, &mut asm);
+    let val = asm.alloc_node(Const::I32(42));
+    let roots = vec![
+        asm.alloc_root(CILRoot::Call(Box::new((write_line, [val].into())))),
+        asm.alloc_root(CILRoot::Call(Box::new((beep, [].into())))),
+        asm.alloc_root(CILRoot::VoidRet),
+    ];
+    let name = asm.alloc_string("entrypoint");
+    let sig = asm.sig([], Type::Void);
+    asm.new_method(MethodDef::new(
+        Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(roots, 0, None)],
+            locals: vec![],
+        },
+        vec![],
+    ));
+    let mut out = Vec::new();
+    export_jar(asm, false).write(&mut out).unwrap();
+    // Entries are stored uncompressed, so their names and constants can be found in the archive.
+    let jar = String::from_utf8_lossy(&out);
+    assert!(jar.contains("Main-Class: rust.MainModule\r\n"));
+    for class in [
+        "rust/MainModule.class",
+        "cilly/Runtime.class",
+        "cilly/Natives.class",
+    ] {
+        assert!(jar.contains(class), "{class} is missing");
+    }
+    // Unsupported .NET methods turn into stubs, which throw when called.
+    assert!(jar.contains("System.Console::Beep is not supported on the JVM"));
+}
+#[test]
+fn run_on_jvm() {
+    use super::{
+        cilnode::MethodKind, Access, BasicBlock, CILNode, CILRoot, ClassRef, Const, MethodDef,
+    };
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let console = ClassRef::console(&mut asm);
+    let console = asm[console].clone();
+    let write_line = asm.alloc_string("WriteLine");
+    let write_line = console.static_mref(&[Type::Int(Int::I32)], Type::Void, write_line, &mut asm);
+    let (lhs, rhs) = (
+        asm.alloc_node(Const::I32(40)),
+        asm.alloc_node(Const::I32(2)),
+    );
+    let sum = asm.alloc_node(CILNode::BinOp(lhs, rhs, super::BinOp::Add));
+    let roots = vec![
+        asm.alloc_root(CILRoot::Call(Box::new((write_line, [sum].into())))),
+        asm.alloc_root(CILRoot::VoidRet),
+    ];
+    let name = asm.alloc_string("entrypoint");
+    let sig = asm.sig([], Type::Void);
+    asm.new_method(MethodDef::new(
+        Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(roots, 0, None)],
+            locals: vec![],
+        },
+        vec![],
+    ));
+    let dir = std::env::temp_dir().join(format!("cilly_jvm_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let jar = dir.join("run_on_jvm.jar");
+    JavaExporter::new(false).export(&asm, &jar).unwrap();
+    let out = std::process::Command::new("java")
+        .arg("-jar")
+        .arg(&jar)
+        .output()
+        .expect("Could not run java");
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        out.status.success(),
+        "stderr:{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&out.stdout).trim(), "42");
 }
-
-fn type_string(tpe: Type, asm: &Assembly) -> String {
-    match tpe {
-        Type::Ptr(_) => "J".into(),
-        Type::Ref(_) => "J".into(),
-        Type::Int(_) => "I".into(),
-        Type::ClassRef(cref) => {
-            if asm.class_ref(cref).is_valuetype() {
-                "J".into()
-            } else {
-                todo!();
-            }
-        }
-        Type::Float(_) => "F".into(),
-        Type::PlatformString => todo!(),
-        Type::PlatformChar => "C".into(),
-        Type::PlatformGeneric(_, _) => todo!(),
-        Type::PlatformObject => todo!(),
-        Type::Bool => "B".into(),
-        Type::Void => "V".into(),
-        Type::PlatformArray { elem, dims } => format!(
-            "{arr}{elem}",
-            arr = (0..dims.get()).map(|_| "[").collect::<String>(),
-            elem = type_string(asm[elem], asm)
-        ),
-        Type::FnPtr(_) => "J".into(),
-        Type::SIMDVector(_) => panic!("SMID is not supported in Java"),
-    }
-}
-/*
-// Type your code here, or load an example.
-import java.lang.foreign.*;
-import  java.lang.System;
-class Memory {
-    static MemorySegment globalMem;
-    static int bitWidth;
-    static{
-        bitWidth = 8;
-        globalMem = MemorySegment.ofAddress(0).reinterpret(1l<<(bitWidth * 8));
-    }
-    static int LoadI32(long ptr){
-        return globalMem.get(ValueLayout.JAVA_INT,ptr);
-    }
-    static long LoadI64(long ptr){
-        return globalMem.get(ValueLayout.JAVA_LONG,ptr);
-    }
-}
-*/
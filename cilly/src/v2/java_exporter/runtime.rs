@@ -0,0 +1,2001 @@
+//! The runtime support class, and the JVM implementations of externs and .NET methods.
+//!
+//! Unmanaged memory is allocated and accessed using `sun.misc.Unsafe`. 128 bit ints are stored
+//! in memory, and operated on using `java.math.BigInteger`.
+use super::{
+    class_file::{op, Builder, ClassFile, ConstPool, Kind, ACC_FINAL, ACC_PUBLIC, ACC_STATIC},
+    lower::{coerce, normalize},
+    stub, Ctx, Native, Repr, NATIVES, RUNTIME,
+};
+use crate::v2::{Float, Int, Type};
+
+const UNSAFE: &str = "sun/misc/Unsafe";
+const UNSAFE_DESC: &str = "Lsun/misc/Unsafe;";
+const BIG: &str = "java/math/BigInteger";
+/// The size of the shadow stack.
+const STACK_SIZE: i64 = 64 << 20;
+/// The size of the buffer natives return values stored in memory in.
+const RET_SIZE: i64 = 64;
+
+/// Adds the static method `name` to `class`, with a body built by `build`.
+fn add(class: &mut ClassFile, name: &str, desc: &str, build: impl FnOnce(&mut Builder)) {
+    let (args, _) = super::class_file::split_method_desc(desc);
+    let slots = args
+        .iter()
+        .filter_map(|arg| Kind::of_desc(arg))
+        .map(Kind::slots)
+        .sum();
+    let mut b = Builder::new(&mut class.cp, slots);
+    build(&mut b);
+    let code = b
+        .finish()
+        .expect("ERROR: could not assemble a runtime method");
+    class.add_method(ACC_PUBLIC | ACC_STATIC, name, desc, &code);
+}
+fn get_unsafe(b: &mut Builder) {
+    b.field(op::GETSTATIC, RUNTIME, "U", UNSAFE_DESC);
+}
+fn unsafe_call(b: &mut Builder, name: &str, desc: &str) {
+    b.invoke(op::INVOKEVIRTUAL, UNSAFE, name, desc);
+}
+/// Pushes the offset of the first element of a `byte[]`.
+fn byte_array_base(b: &mut Builder) {
+    get_unsafe(b);
+    b.class_const("[B");
+    unsafe_call(b, "arrayBaseOffset", "(Ljava/lang/Class;)I");
+    b.op(op::I2L, 1);
+}
+/// Allocates `size` bytes aligned to `align`, which are never freed, and pushes their address.
+fn alloc_static(b: &mut Builder, size: i64, align: i64) {
+    get_unsafe(b);
+    b.long(size + align);
+    unsafe_call(b, "allocateMemory", "(J)J");
+    b.long(align - 1);
+    b.op(op::LADD, -2);
+    b.long(-align);
+    b.op(op::LAND, -2);
+}
+/// Throws a `StackOverflowError` if the long on top of the stack is past the end of the shadow stack.
+fn check_limit(b: &mut Builder) {
+    let ok = b.label();
+    b.field(op::GETSTATIC, RUNTIME, "limit", "J");
+    b.op(op::LCMP, -3);
+    b.jump(op::IFLE, ok);
+    b.type_op(op::NEW, "java/lang/StackOverflowError");
+    b.op(op::DUP, 1);
+    b.invoke(
+        op::INVOKESPECIAL,
+        "java/lang/StackOverflowError",
+        "<init>",
+        "()V",
+    );
+    b.op(op::ATHROW, -1);
+    b.place(ok);
+}
+/// Pushes an int, which is 1 if the jump `opcode` is taken, and 0 otherwise.
+pub(super) fn to_bool(b: &mut Builder, opcode: u8) {
+    let yes = b.label();
+    let end = b.label();
+    b.jump(opcode, yes);
+    let depth = b.code.depth();
+    b.int(0);
+    b.jump(op::GOTO, end);
+    b.code.set_depth(depth);
+    b.place(yes);
+    b.int(1);
+    b.place(end);
+}
+/// Builds the runtime class.
+pub(super) fn runtime(ctx: &mut Ctx) -> ClassFile {
+    let mut class = ClassFile::new(RUNTIME, "java/lang/Object", ACC_PUBLIC | ACC_FINAL);
+    let access = ACC_PUBLIC | ACC_STATIC;
+    class.add_field(access, "U", UNSAFE_DESC);
+    for name in ["sp", "limit", "statics", "ret", "errno"] {
+        class.add_field(access, name, "J");
+    }
+    class.add_field(access, "args", "[Ljava/lang/String;");
+    for (name, desc) in &ctx.object_statics {
+        class.add_field(access, name, desc);
+    }
+    let statics_size = ctx.statics_size.max(1) as i64;
+    add(&mut class, "<clinit>", "()V", |b| {
+        b.class_const(UNSAFE);
+        b.string("theUnsafe");
+        b.invoke(
+            op::INVOKEVIRTUAL,
+            "java/lang/Class",
+            "getDeclaredField",
+            "(Ljava/lang/String;)Ljava/lang/reflect/Field;",
+        );
+        b.op(op::DUP, 1);
+        b.int(1);
+        b.invoke(
+            op::INVOKEVIRTUAL,
+            "java/lang/reflect/Field",
+            "setAccessible",
+            "(Z)V",
+        );
+        b.op(op::ACONST_NULL, 1);
+        b.invoke(
+            op::INVOKEVIRTUAL,
+            "java/lang/reflect/Field",
+            "get",
+            "(Ljava/lang/Object;)Ljava/lang/Object;",
+        );
+        b.type_op(op::CHECKCAST, UNSAFE);
+        b.field(op::PUTSTATIC, RUNTIME, "U", UNSAFE_DESC);
+        alloc_static(b, STACK_SIZE, 16);
+        b.op(op::DUP2, 2);
+        b.field(op::PUTSTATIC, RUNTIME, "sp", "J");
+        b.long(STACK_SIZE);
+        b.op(op::LADD, -2);
+        b.field(op::PUTSTATIC, RUNTIME, "limit", "J");
+        for (name, size, align) in [
+            ("statics", statics_size, 64),
+            ("ret", RET_SIZE, 16),
+            ("errno", 16, 16),
+        ] {
+            alloc_static(b, size, align);
+            b.field(op::PUTSTATIC, RUNTIME, name, "J");
+            get_unsafe(b);
+            b.field(op::GETSTATIC, RUNTIME, name, "J");
+            b.long(size);
+            b.int(0);
+            unsafe_call(b, "setMemory", "(JJB)V");
+        }
+        b.ret(None);
+    });
+    add(&mut class, "set_args", "([Ljava/lang/String;)V", |b| {
+        b.load(Kind::Ref, 0);
+        b.field(op::PUTSTATIC, RUNTIME, "args", "[Ljava/lang/String;");
+        b.ret(None);
+    });
+    add(&mut class, "args", "()[Ljava/lang/String;", |b| {
+        b.field(op::GETSTATIC, RUNTIME, "args", "[Ljava/lang/String;");
+        b.ret(Some(Kind::Ref));
+    });
+    shadow_stack(&mut class);
+    memory_access(&mut class);
+    heap(&mut class);
+    conversions(&mut class);
+    strings(&mut class);
+    wide(&mut class);
+    class
+}
+/// Frame management: `enter(size)` allocates a zeroed frame, `leave(fp)` frees it.
+fn shadow_stack(class: &mut ClassFile) {
+    add(class, "enter", "(J)J", |b| {
+        let fp = b.local(Kind::Long);
+        b.field(op::GETSTATIC, RUNTIME, "sp", "J");
+        b.store(Kind::Long, fp);
+        b.load(Kind::Long, fp);
+        b.load(Kind::Long, 0);
+        b.op(op::LADD, -2);
+        b.op(op::DUP2, 2);
+        b.field(op::PUTSTATIC, RUNTIME, "sp", "J");
+        check_limit(b);
+        get_unsafe(b);
+        b.load(Kind::Long, fp);
+        b.load(Kind::Long, 0);
+        b.int(0);
+        unsafe_call(b, "setMemory", "(JJB)V");
+        b.load(Kind::Long, fp);
+        b.ret(Some(Kind::Long));
+    });
+    add(class, "leave", "(J)V", |b| {
+        b.load(Kind::Long, 0);
+        b.field(op::PUTSTATIC, RUNTIME, "sp", "J");
+        b.ret(None);
+    });
+    // stackalloc(size, align): memory freed when the current frame is left.
+    add(class, "stackalloc", "(JJ)J", |b| {
+        let ptr = b.local(Kind::Long);
+        b.field(op::GETSTATIC, RUNTIME, "sp", "J");
+        b.load(Kind::Long, 2);
+        b.op(op::LADD, -2);
+        b.long(1);
+        b.op(op::LSUB, -2);
+        b.load(Kind::Long, 2);
+        b.op(op::LNEG, 0);
+        b.op(op::LAND, -2);
+        b.store(Kind::Long, ptr);
+        b.load(Kind::Long, ptr);
+        b.load(Kind::Long, 0);
+        b.op(op::LADD, -2);
+        b.long(15);
+        b.op(op::LADD, -2);
+        b.long(-16);
+        b.op(op::LAND, -2);
+        b.op(op::DUP2, 2);
+        b.field(op::PUTSTATIC, RUNTIME, "sp", "J");
+        check_limit(b);
+        b.load(Kind::Long, ptr);
+        b.ret(Some(Kind::Long));
+    });
+}
+/// Loads and stores of primitives, and bulk memory operations.
+fn memory_access(class: &mut ClassFile) {
+    for (name, getter, desc, zero_extend) in [
+        ("ld_u8", "getByte", "(J)B", Some(0xff)),
+        ("ld_i8", "getByte", "(J)B", None),
+        ("ld_u16", "getShort", "(J)S", Some(0xffff)),
+        ("ld_i16", "getShort", "(J)S", None),
+        ("ld_i32", "getInt", "(J)I", None),
+        ("ld_i64", "getLong", "(J)J", None),
+        ("ld_f32", "getFloat", "(J)F", None),
+        ("ld_f64", "getDouble", "(J)D", None),
+    ] {
+        let ret = &desc[3..];
+        let kind = Kind::of_desc(ret).unwrap();
+        add(
+            class,
+            name,
+            &format!("(J){}", super::kind_desc(kind)),
+            |b| {
+                get_unsafe(b);
+                b.load(Kind::Long, 0);
+                unsafe_call(b, getter, desc);
+                if let Some(mask) = zero_extend {
+                    b.int(mask);
+                    b.op(op::IAND, -1);
+                }
+                b.ret(Some(kind));
+            },
+        );
+    }
+    for (name, setter, kind, prim, narrow) in [
+        ("st_i8", "putByte", Kind::Int, "B", Some(op::I2B)),
+        ("st_i16", "putShort", Kind::Int, "S", Some(op::I2S)),
+        ("st_i32", "putInt", Kind::Int, "I", None),
+        ("st_i64", "putLong", Kind::Long, "J", None),
+        ("st_f32", "putFloat", Kind::Float, "F", None),
+        ("st_f64", "putDouble", Kind::Double, "D", None),
+    ] {
+        let desc = format!("(J{})V", super::kind_desc(kind));
+        add(class, name, &desc, |b| {
+            get_unsafe(b);
+            b.load(Kind::Long, 0);
+            b.load(kind, 2);
+            if let Some(narrow) = narrow {
+                b.op(narrow, 0);
+            }
+            unsafe_call(b, setter, &format!("(J{prim})V"));
+            b.ret(None);
+        });
+    }
+    // copy(dst, src, len)
+    add(class, "copy", "(JJJ)V", |b| {
+        get_unsafe(b);
+        b.load(Kind::Long, 2);
+        b.load(Kind::Long, 0);
+        b.load(Kind::Long, 4);
+        unsafe_call(b, "copyMemory", "(JJJ)V");
+        b.ret(None);
+    });
+    // move(src, dst, len): copies, and returns dst.
+    add(class, "move", "(JJJ)J", |b| {
+        get_unsafe(b);
+        b.load(Kind::Long, 0);
+        b.load(Kind::Long, 2);
+        b.load(Kind::Long, 4);
+        unsafe_call(b, "copyMemory", "(JJJ)V");
+        b.load(Kind::Long, 2);
+        b.ret(Some(Kind::Long));
+    });
+    // fill(dst, val, len)
+    add(class, "fill", "(JIJ)V", |b| {
+        get_unsafe(b);
+        b.load(Kind::Long, 0);
+        b.load(Kind::Long, 3);
+        b.load(Kind::Int, 2);
+        b.op(op::I2B, 0);
+        unsafe_call(b, "setMemory", "(JJB)V");
+        b.ret(None);
+    });
+    // memcmp(lhs, rhs, len)
+    add(class, "memcmp", "(JJJ)I", |b| {
+        let idx = b.local(Kind::Long);
+        let lhs = b.local(Kind::Int);
+        let rhs = b.local(Kind::Int);
+        let (start, next, equal) = (b.label(), b.label(), b.label());
+        b.long(0);
+        b.store(Kind::Long, idx);
+        b.place(start);
+        b.load(Kind::Long, idx);
+        b.load(Kind::Long, 4);
+        b.op(op::LCMP, -3);
+        b.jump(op::IFGE, equal);
+        for (base, local) in [(0, lhs), (2, rhs)] {
+            b.load(Kind::Long, base);
+            b.load(Kind::Long, idx);
+            b.op(op::LADD, -2);
+            b.call(RUNTIME, "ld_u8", "(J)I");
+            b.store(Kind::Int, local);
+        }
+        b.load(Kind::Int, lhs);
+        b.load(Kind::Int, rhs);
+        b.jump(op::IF_ICMPEQ, next);
+        b.load(Kind::Int, lhs);
+        b.load(Kind::Int, rhs);
+        b.op(op::ISUB, -1);
+        b.call("java/lang/Integer", "signum", "(I)I");
+        b.ret(Some(Kind::Int));
+        b.place(next);
+        b.load(Kind::Long, idx);
+        b.long(1);
+        b.op(op::LADD, -2);
+        b.store(Kind::Long, idx);
+        b.jump(op::GOTO, start);
+        b.place(equal);
+        b.int(0);
+        b.ret(Some(Kind::Int));
+    });
+    add(class, "strlen", "(J)J", |b| {
+        let ptr = b.local(Kind::Long);
+        let (start, end) = (b.label(), b.label());
+        b.load(Kind::Long, 0);
+        b.store(Kind::Long, ptr);
+        b.place(start);
+        b.load(Kind::Long, ptr);
+        b.call(RUNTIME, "ld_u8", "(J)I");
+        b.jump(op::IFEQ, end);
+        b.load(Kind::Long, ptr);
+        b.long(1);
+        b.op(op::LADD, -2);
+        b.store(Kind::Long, ptr);
+        b.jump(op::GOTO, start);
+        b.place(end);
+        b.load(Kind::Long, ptr);
+        b.load(Kind::Long, 0);
+        b.op(op::LSUB, -2);
+        b.ret(Some(Kind::Long));
+    });
+}
+/// The heap: every allocation is preceded by its size, and the address returned by `allocateMemory`.
+fn heap(class: &mut ClassFile) {
+    // malloc(size, align)
+    add(class, "malloc", "(JJ)J", |b| {
+        let raw = b.local(Kind::Long);
+        let ptr = b.local(Kind::Long);
+        b.load(Kind::Long, 2);
+        b.long(16);
+        b.call("java/lang/Math", "max", "(JJ)J");
+        b.store(Kind::Long, 2);
+        get_unsafe(b);
+        b.load(Kind::Long, 0);
+        b.load(Kind::Long, 2);
+        b.op(op::LADD, -2);
+        b.long(16);
+        b.op(op::LADD, -2);
+        unsafe_call(b, "allocateMemory", "(J)J");
+        b.store(Kind::Long, raw);
+        b.load(Kind::Long, raw);
+        b.long(15);
+        b.op(op::LADD, -2);
+        b.load(Kind::Long, 2);
+        b.op(op::LADD, -2);
+        b.load(Kind::Long, 2);
+        b.op(op::LNEG, 0);
+        b.op(op::LAND, -2);
+        b.store(Kind::Long, ptr);
+        for (offset, val) in [(16, 0), (8, raw)] {
+            b.load(Kind::Long, ptr);
+            b.long(offset);
+            b.op(op::LSUB, -2);
+            b.load(Kind::Long, val);
+            b.call(RUNTIME, "st_i64", "(JJ)V");
+        }
+        b.load(Kind::Long, ptr);
+        b.ret(Some(Kind::Long));
+    });
+    add(class, "free", "(J)V", |b| {
+        let end = b.label();
+        b.load(Kind::Long, 0);
+        b.long(0);
+        b.op(op::LCMP, -3);
+        b.jump(op::IFEQ, end);
+        get_unsafe(b);
+        b.load(Kind::Long, 0);
+        b.long(8);
+        b.op(op::LSUB, -2);
+        b.call(RUNTIME, "ld_i64", "(J)J");
+        unsafe_call(b, "freeMemory", "(J)V");
+        b.place(end);
+        b.ret(None);
+    });
+    // realloc(ptr, size, align)
+    add(class, "realloc", "(JJJ)J", |b| {
+        let new = b.local(Kind::Long);
+        let done = b.label();
+        b.load(Kind::Long, 2);
+        b.load(Kind::Long, 4);
+        b.call(RUNTIME, "malloc", "(JJ)J");
+        b.store(Kind::Long, new);
+        b.load(Kind::Long, 0);
+        b.long(0);
+        b.op(op::LCMP, -3);
+        b.jump(op::IFEQ, done);
+        b.load(Kind::Long, new);
+        b.load(Kind::Long, 0);
+        b.load(Kind::Long, 0);
+        b.long(16);
+        b.op(op::LSUB, -2);
+        b.call(RUNTIME, "ld_i64", "(J)J");
+        b.load(Kind::Long, 2);
+        b.call("java/lang/Math", "min", "(JJ)J");
+        b.call(RUNTIME, "copy", "(JJJ)V");
+        b.load(Kind::Long, 0);
+        b.call(RUNTIME, "free", "(J)V");
+        b.place(done);
+        b.load(Kind::Long, new);
+        b.ret(Some(Kind::Long));
+    });
+    // write(fd, buf, len): fd 2 is stderr, everything else goes to stdout.
+    add(class, "write", "(JJJ)J", |b| {
+        let bytes = b.local(Kind::Ref);
+        let stream = b.local(Kind::Ref);
+        let out = b.label();
+        b.load(Kind::Long, 4);
+        b.op(op::L2I, -1);
+        b.code.op_u8(op::NEWARRAY, op::T_BYTE, 0);
+        b.store(Kind::Ref, bytes);
+        get_unsafe(b);
+        b.op(op::ACONST_NULL, 1);
+        b.load(Kind::Long, 2);
+        b.load(Kind::Ref, bytes);
+        byte_array_base(b);
+        b.load(Kind::Long, 4);
+        unsafe_call(
+            b,
+            "copyMemory",
+            "(Ljava/lang/Object;JLjava/lang/Object;JJ)V",
+        );
+        b.field(
+            op::GETSTATIC,
+            "java/lang/System",
+            "out",
+            "Ljava/io/PrintStream;",
+        );
+        b.store(Kind::Ref, stream);
+        b.load(Kind::Long, 0);
+        b.long(2);
+        b.op(op::LCMP, -3);
+        b.jump(op::IFNE, out);
+        b.field(
+            op::GETSTATIC,
+            "java/lang/System",
+            "err",
+            "Ljava/io/PrintStream;",
+        );
+        b.store(Kind::Ref, stream);
+        b.place(out);
+        b.load(Kind::Ref, stream);
+        b.load(Kind::Ref, bytes);
+        b.int(0);
+        b.load(Kind::Long, 4);
+        b.op(op::L2I, -1);
+        b.invoke(op::INVOKEVIRTUAL, "java/io/PrintStream", "write", "([BII)V");
+        b.load(Kind::Ref, stream);
+        b.invoke(op::INVOKEVIRTUAL, "java/io/PrintStream", "flush", "()V");
+        b.load(Kind::Long, 4);
+        b.ret(Some(Kind::Long));
+    });
+    add(class, "exit", "(I)V", |b| {
+        b.field(
+            op::GETSTATIC,
+            "java/lang/System",
+            "out",
+            "Ljava/io/PrintStream;",
+        );
+        b.invoke(op::INVOKEVIRTUAL, "java/io/PrintStream", "flush", "()V");
+        b.load(Kind::Int, 0);
+        b.call("java/lang/System", "exit", "(I)V");
+        b.ret(None);
+    });
+}
+/// Conversions between unsigned 64 bit ints and floats, which the JVM lacks.
+fn conversions(class: &mut ClassFile) {
+    for (name, kind, convert, add_op) in [
+        ("u64_to_f64", Kind::Double, op::L2D, op::DADD),
+        ("u64_to_f32", Kind::Float, op::L2F, op::FADD),
+    ] {
+        let desc = format!("(J){}", super::kind_desc(kind));
+        let delta = i32::from(kind.slots()) - 2;
+        add(class, name, &desc, |b| {
+            let negative = b.label();
+            b.load(Kind::Long, 0);
+            b.long(0);
+            b.op(op::LCMP, -3);
+            b.jump(op::IFLT, negative);
+            b.load(Kind::Long, 0);
+            b.op(convert, delta);
+            b.ret(Some(kind));
+            b.place(negative);
+            // Halve the value (keeping the lowest bit, for correct rounding), convert, and double it.
+            b.load(Kind::Long, 0);
+            b.int(1);
+            b.op(op::LUSHR, -1);
+            b.load(Kind::Long, 0);
+            b.long(1);
+            b.op(op::LAND, -2);
+            b.op(op::LOR, -2);
+            b.op(convert, delta);
+            if kind == Kind::Double {
+                b.op(op::DUP2, 2);
+            } else {
+                b.op(op::DUP, 1);
+            }
+            b.op(add_op, -i32::from(kind.slots()));
+            b.ret(Some(kind));
+        });
+    }
+    add(class, "d2u64", "(D)J", |b| {
+        let (positive, big) = (b.label(), b.label());
+        b.load(Kind::Double, 0);
+        b.double(0.0);
+        b.op(op::DCMPL, -3);
+        b.jump(op::IFGT, positive);
+        b.long(0);
+        b.ret(Some(Kind::Long));
+        b.place(positive);
+        b.load(Kind::Double, 0);
+        b.double(9_223_372_036_854_775_808.0);
+        b.op(op::DCMPL, -3);
+        b.jump(op::IFGE, big);
+        b.load(Kind::Double, 0);
+        b.op(op::D2L, 0);
+        b.ret(Some(Kind::Long));
+        b.place(big);
+        b.load(Kind::Double, 0);
+        b.double(9_223_372_036_854_775_808.0);
+        b.op(op::DSUB, -2);
+        b.op(op::D2L, 0);
+        b.long(i64::MIN);
+        b.op(op::LADD, -2);
+        b.ret(Some(Kind::Long));
+    });
+}
+/// Conversions between UTF-8 C strings and JVM strings.
+fn strings(class: &mut ClassFile) {
+    // string(ptr, len)
+    add(class, "string", "(JJ)Ljava/lang/String;", |b| {
+        let bytes = b.local(Kind::Ref);
+        b.load(Kind::Long, 2);
+        b.op(op::L2I, -1);
+        b.code.op_u8(op::NEWARRAY, op::T_BYTE, 0);
+        b.store(Kind::Ref, bytes);
+        get_unsafe(b);
+        b.op(op::ACONST_NULL, 1);
+        b.load(Kind::Long, 0);
+        b.load(Kind::Ref, bytes);
+        byte_array_base(b);
+        b.load(Kind::Long, 2);
+        unsafe_call(
+            b,
+            "copyMemory",
+            "(Ljava/lang/Object;JLjava/lang/Object;JJ)V",
+        );
+        b.type_op(op::NEW, "java/lang/String");
+        b.op(op::DUP, 1);
+        b.load(Kind::Ref, bytes);
+        b.field(
+            op::GETSTATIC,
+            "java/nio/charset/StandardCharsets",
+            "UTF_8",
+            "Ljava/nio/charset/Charset;",
+        );
+        b.invoke(
+            op::INVOKESPECIAL,
+            "java/lang/String",
+            "<init>",
+            "([BLjava/nio/charset/Charset;)V",
+        );
+        b.ret(Some(Kind::Ref));
+    });
+    add(class, "cstring", "(J)Ljava/lang/String;", |b| {
+        b.load(Kind::Long, 0);
+        b.load(Kind::Long, 0);
+        b.call(RUNTIME, "strlen", "(J)J");
+        b.call(RUNTIME, "string", "(JJ)Ljava/lang/String;");
+        b.ret(Some(Kind::Ref));
+    });
+    // utf8(string): a freshly allocated, nul terminated copy.
+    add(class, "utf8", "(Ljava/lang/String;)J", |b| {
+        let bytes = b.local(Kind::Ref);
+        let len = b.local(Kind::Long);
+        let ptr = b.local(Kind::Long);
+        b.load(Kind::Ref, 0);
+        b.field(
+            op::GETSTATIC,
+            "java/nio/charset/StandardCharsets",
+            "UTF_8",
+            "Ljava/nio/charset/Charset;",
+        );
+        b.invoke(
+            op::INVOKEVIRTUAL,
+            "java/lang/String",
+            "getBytes",
+            "(Ljava/nio/charset/Charset;)[B",
+        );
+        b.store(Kind::Ref, bytes);
+        b.load(Kind::Ref, bytes);
+        b.op(op::ARRAYLENGTH, 0);
+        b.op(op::I2L, 1);
+        b.store(Kind::Long, len);
+        b.load(Kind::Long, len);
+        b.long(1);
+        b.op(op::LADD, -2);
+        b.long(1);
+        b.call(RUNTIME, "malloc", "(JJ)J");
+        b.store(Kind::Long, ptr);
+        get_unsafe(b);
+        b.load(Kind::Ref, bytes);
+        byte_array_base(b);
+        b.op(op::ACONST_NULL, 1);
+        b.load(Kind::Long, ptr);
+        b.load(Kind::Long, len);
+        unsafe_call(
+            b,
+            "copyMemory",
+            "(Ljava/lang/Object;JLjava/lang/Object;JJ)V",
+        );
+        b.load(Kind::Long, ptr);
+        b.load(Kind::Long, len);
+        b.op(op::LADD, -2);
+        b.int(0);
+        b.call(RUNTIME, "st_i8", "(JI)V");
+        b.load(Kind::Long, ptr);
+        b.ret(Some(Kind::Long));
+    });
+}
+/// Stores `lo` and `hi` as the 128 bit int at the address in the local `dst`.
+fn store_halves(b: &mut Builder, dst: u16, lo: i64, hi: i64) {
+    for (offset, val) in [(0, lo), (8, hi)] {
+        b.load(Kind::Long, dst);
+        b.long(offset);
+        b.op(op::LADD, -2);
+        b.long(val);
+        b.call(RUNTIME, "st_i64", "(JJ)V");
+    }
+}
+/// 128 bit int arithmetic. Operands are passed by address, and results are written to a destination address.
+fn wide(class: &mut ClassFile) {
+    let big = |name: &str, desc: &str, b: &mut Builder| {
+        b.invoke(op::INVOKEVIRTUAL, BIG, name, desc);
+    };
+    // ld_big(addr, signed)
+    add(class, "ld_big", "(JI)Ljava/math/BigInteger;", |b| {
+        let done = b.label();
+        b.load(Kind::Long, 0);
+        b.long(8);
+        b.op(op::LADD, -2);
+        b.call(RUNTIME, "ld_i64", "(J)J");
+        b.call(BIG, "valueOf", "(J)Ljava/math/BigInteger;");
+        b.int(64);
+        big("shiftLeft", "(I)Ljava/math/BigInteger;", b);
+        b.load(Kind::Long, 0);
+        b.call(RUNTIME, "ld_i64", "(J)J");
+        b.call(RUNTIME, "unsigned_big", "(J)Ljava/math/BigInteger;");
+        big("or", "(Ljava/math/BigInteger;)Ljava/math/BigInteger;", b);
+        b.load(Kind::Int, 2);
+        b.jump(op::IFNE, done);
+        b.int(128);
+        b.call(RUNTIME, "mask", "(I)Ljava/math/BigInteger;");
+        big("and", "(Ljava/math/BigInteger;)Ljava/math/BigInteger;", b);
+        b.place(done);
+        b.ret(Some(Kind::Ref));
+    });
+    add(class, "unsigned_big", "(J)Ljava/math/BigInteger;", |b| {
+        b.load(Kind::Long, 0);
+        b.call(BIG, "valueOf", "(J)Ljava/math/BigInteger;");
+        b.int(64);
+        b.call(RUNTIME, "mask", "(I)Ljava/math/BigInteger;");
+        big("and", "(Ljava/math/BigInteger;)Ljava/math/BigInteger;", b);
+        b.ret(Some(Kind::Ref));
+    });
+    // mask(bits): 2^bits - 1
+    add(class, "mask", "(I)Ljava/math/BigInteger;", |b| {
+        b.field(op::GETSTATIC, BIG, "ONE", "Ljava/math/BigInteger;");
+        b.load(Kind::Int, 0);
+        big("shiftLeft", "(I)Ljava/math/BigInteger;", b);
+        b.field(op::GETSTATIC, BIG, "ONE", "Ljava/math/BigInteger;");
+        big(
+            "subtract",
+            "(Ljava/math/BigInteger;)Ljava/math/BigInteger;",
+            b,
+        );
+        b.ret(Some(Kind::Ref));
+    });
+    // st_big(val, dst): stores the low 128 bits of val, and returns dst.
+    add(class, "st_big", "(Ljava/math/BigInteger;J)J", |b| {
+        b.load(Kind::Long, 1);
+        b.load(Kind::Ref, 0);
+        big("longValue", "()J", b);
+        b.call(RUNTIME, "st_i64", "(JJ)V");
+        b.load(Kind::Long, 1);
+        b.long(8);
+        b.op(op::LADD, -2);
+        b.load(Kind::Ref, 0);
+        b.int(64);
+        big("shiftRight", "(I)Ljava/math/BigInteger;", b);
+        big("longValue", "()J", b);
+        b.call(RUNTIME, "st_i64", "(JJ)V");
+        b.load(Kind::Long, 1);
+        b.ret(Some(Kind::Long));
+    });
+    // wide(lhs, rhs, dst, op): the low 4 bits of op select the operation, bit 4 makes it signed.
+    add(class, "wide", "(JJJI)J", |b| {
+        let lhs = b.local(Kind::Ref);
+        let rhs = b.local(Kind::Ref);
+        for (addr, local) in [(0, lhs), (2, rhs)] {
+            b.load(Kind::Long, addr);
+            b.load(Kind::Int, 6);
+            b.int(4);
+            b.op(op::ISHR, -1);
+            b.call(RUNTIME, "ld_big", "(JI)Ljava/math/BigInteger;");
+            b.store(Kind::Ref, local);
+        }
+        b.load(Kind::Int, 6);
+        b.int(15);
+        b.op(op::IAND, -1);
+        let ops = [
+            "add",
+            "subtract",
+            "multiply",
+            "divide",
+            "remainder",
+            "and",
+            "or",
+            "xor",
+        ];
+        let mut cases: Vec<_> = (0..ops.len() as i32).map(|idx| (idx, b.label())).collect();
+        let default = b.label();
+        b.code.lookupswitch(default, &mut cases.clone());
+        for (name, (_, label)) in ops.iter().zip(&mut cases) {
+            b.place(*label);
+            b.code.set_depth(0);
+            b.load(Kind::Ref, lhs);
+            b.load(Kind::Ref, rhs);
+            big(name, "(Ljava/math/BigInteger;)Ljava/math/BigInteger;", b);
+            b.load(Kind::Long, 4);
+            b.call(RUNTIME, "st_big", "(Ljava/math/BigInteger;J)J");
+            b.ret(Some(Kind::Long));
+        }
+        b.place(default);
+        b.code.set_depth(0);
+        b.throw_new("java/lang/IllegalArgumentException", "Invalid 128 bit op");
+    });
+    // wide_shift(val, amount, dst, mode): mode 0 is a left shift, 1 an arithmetic and 2 a logical right shift.
+    add(class, "wide_shift", "(JIJI)J", |b| {
+        let (left, done) = (b.label(), b.label());
+        b.load(Kind::Long, 0);
+        b.load(Kind::Int, 5);
+        b.int(1);
+        b.op(op::IAND, -1);
+        b.call(RUNTIME, "ld_big", "(JI)Ljava/math/BigInteger;");
+        b.load(Kind::Int, 2);
+        b.int(127);
+        b.op(op::IAND, -1);
+        b.load(Kind::Int, 5);
+        b.jump(op::IFEQ, left);
+        big("shiftRight", "(I)Ljava/math/BigInteger;", b);
+        b.jump(op::GOTO, done);
+        b.code.set_depth(2);
+        b.place(left);
+        big("shiftLeft", "(I)Ljava/math/BigInteger;", b);
+        b.place(done);
+        b.load(Kind::Long, 3);
+        b.call(RUNTIME, "st_big", "(Ljava/math/BigInteger;J)J");
+        b.ret(Some(Kind::Long));
+    });
+    add(class, "wide_cmp", "(JJI)I", |b| {
+        for addr in [0, 2] {
+            b.load(Kind::Long, addr);
+            b.load(Kind::Int, 4);
+            b.call(RUNTIME, "ld_big", "(JI)Ljava/math/BigInteger;");
+        }
+        big("compareTo", "(Ljava/math/BigInteger;)I", b);
+        b.ret(Some(Kind::Int));
+    });
+    for (name, method) in [("wide_not", "not"), ("wide_neg", "negate")] {
+        add(class, name, "(JJ)J", |b| {
+            b.load(Kind::Long, 0);
+            b.int(1);
+            b.call(RUNTIME, "ld_big", "(JI)Ljava/math/BigInteger;");
+            big(method, "()Ljava/math/BigInteger;", b);
+            b.load(Kind::Long, 2);
+            b.call(RUNTIME, "st_big", "(Ljava/math/BigInteger;J)J");
+            b.ret(Some(Kind::Long));
+        });
+    }
+    // wide_from(val, signed, dst)
+    add(class, "wide_from", "(JIJ)J", |b| {
+        let (zero, store) = (b.label(), b.label());
+        b.load(Kind::Long, 3);
+        b.load(Kind::Long, 0);
+        b.call(RUNTIME, "st_i64", "(JJ)V");
+        b.load(Kind::Long, 3);
+        b.long(8);
+        b.op(op::LADD, -2);
+        b.load(Kind::Int, 2);
+        b.jump(op::IFEQ, zero);
+        b.load(Kind::Long, 0);
+        b.int(63);
+        b.op(op::LSHR, -1);
+        b.jump(op::GOTO, store);
+        b.code.set_depth(2);
+        b.place(zero);
+        b.long(0);
+        b.place(store);
+        b.call(RUNTIME, "st_i64", "(JJ)V");
+        b.load(Kind::Long, 3);
+        b.ret(Some(Kind::Long));
+    });
+    add(class, "wide_to", "(J)J", |b| {
+        b.load(Kind::Long, 0);
+        b.call(RUNTIME, "ld_i64", "(J)J");
+        b.ret(Some(Kind::Long));
+    });
+    add(class, "wide_to_double", "(JI)D", |b| {
+        b.load(Kind::Long, 0);
+        b.load(Kind::Int, 2);
+        b.call(RUNTIME, "ld_big", "(JI)Ljava/math/BigInteger;");
+        big("doubleValue", "()D", b);
+        b.ret(Some(Kind::Double));
+    });
+    // wide_from_double(val, signed, dst): saturates, and maps NaN to 0.
+    add(class, "wide_from_double", "(DIJ)J", |b| {
+        let [zero, unsigned, convert] = [b.label(), b.label(), b.label()];
+        let (min_label, max_label) = (b.label(), b.label());
+        b.load(Kind::Double, 0);
+        b.load(Kind::Double, 0);
+        b.op(op::DCMPL, -3);
+        b.jump(op::IFNE, zero);
+        b.load(Kind::Int, 2);
+        b.jump(op::IFEQ, unsigned);
+        b.load(Kind::Double, 0);
+        b.double(2.0_f64.powi(127));
+        b.op(op::DCMPL, -3);
+        b.jump(op::IFGE, max_label);
+        b.load(Kind::Double, 0);
+        b.double(-(2.0_f64.powi(127)));
+        b.op(op::DCMPG, -3);
+        b.jump(op::IFLE, min_label);
+        b.jump(op::GOTO, convert);
+        b.place(unsigned);
+        b.load(Kind::Double, 0);
+        b.double(0.0);
+        b.op(op::DCMPG, -3);
+        b.jump(op::IFLE, zero);
+        b.load(Kind::Double, 0);
+        b.double(2.0_f64.powi(128));
+        b.op(op::DCMPL, -3);
+        b.jump(op::IFLT, convert);
+        store_halves(b, 3, -1, -1);
+        b.load(Kind::Long, 3);
+        b.ret(Some(Kind::Long));
+        b.place(max_label);
+        store_halves(b, 3, -1, i64::MAX);
+        b.load(Kind::Long, 3);
+        b.ret(Some(Kind::Long));
+        b.place(min_label);
+        store_halves(b, 3, 0, i64::MIN);
+        b.load(Kind::Long, 3);
+        b.ret(Some(Kind::Long));
+        b.place(zero);
+        store_halves(b, 3, 0, 0);
+        b.load(Kind::Long, 3);
+        b.ret(Some(Kind::Long));
+        b.place(convert);
+        b.type_op(op::NEW, "java/math/BigDecimal");
+        b.op(op::DUP, 1);
+        b.load(Kind::Double, 0);
+        b.invoke(op::INVOKESPECIAL, "java/math/BigDecimal", "<init>", "(D)V");
+        b.invoke(
+            op::INVOKEVIRTUAL,
+            "java/math/BigDecimal",
+            "toBigInteger",
+            "()Ljava/math/BigInteger;",
+        );
+        b.load(Kind::Long, 3);
+        b.call(RUNTIME, "st_big", "(Ljava/math/BigInteger;J)J");
+        b.ret(Some(Kind::Long));
+    });
+}
+/// Builds the class implementing the .NET methods called by the assembly.
+pub(super) fn natives(ctx: &mut Ctx) -> ClassFile {
+    let natives = std::mem::take(&mut ctx.natives);
+    let mut class = ClassFile::new(NATIVES, "java/lang/Object", ACC_PUBLIC | ACC_FINAL);
+    for (jvm, native) in &natives {
+        let access = ACC_PUBLIC | ACC_STATIC;
+        match native_code(ctx, &mut class.cp, native) {
+            Ok(code) => class.add_method(access, &jvm.name, &jvm.desc, &code),
+            Err(msg) => stub(&mut class, access, &jvm.name, &jvm.desc, &msg),
+        }
+    }
+    ctx.natives = natives;
+    class
+}
+/// The value a native leaves on the stack, before it is converted to its return type.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Val {
+    Void,
+    Int,
+    Long,
+    Float,
+    Double,
+    Ref,
+    /// The address of a value in memory.
+    Memory,
+}
+struct NativeBuilder<'a, 'cp> {
+    b: Builder<'cp>,
+    ctx: &'a mut Ctx,
+    params: Vec<(Kind, u16)>,
+    inputs: &'a [Type],
+    output: Type,
+}
+/// Builds the body of `native`. Returns an error if it is not supported.
+pub(super) fn native_code(
+    ctx: &mut Ctx,
+    cp: &mut ConstPool,
+    native: &Native,
+) -> Result<Vec<u8>, String> {
+    let mut params = Vec::new();
+    let mut slots = 0;
+    for input in &native.inputs {
+        let kind = ctx
+            .repr(*input)?
+            .kind()
+            .ok_or_else(|| "void argument".to_owned())?;
+        params.push((kind, slots));
+        slots += kind.slots();
+    }
+    let output = native.output;
+    let mut n = NativeBuilder {
+        b: Builder::new(cp, slots),
+        ctx,
+        params,
+        inputs: &native.inputs,
+        output,
+    };
+    let val = match &native.class {
+        None => n.libc(&native.name)?,
+        Some(class) => n.dotnet(class, &native.name)?,
+    };
+    n.ret(val, output)?;
+    n.b.finish()
+        .map_err(|err| format!("could not assemble {}: {err:?}", native.name))
+}
+impl NativeBuilder<'_, '_> {
+    fn arg(&mut self, idx: usize) -> Result<Kind, String> {
+        let (kind, slot) = *self
+            .params
+            .get(idx)
+            .ok_or_else(|| format!("missing argument {idx}"))?;
+        self.b.load(kind, slot);
+        Ok(kind)
+    }
+    fn arg_long(&mut self, idx: usize) -> Result<(), String> {
+        match self.arg(idx)? {
+            Kind::Long => Ok(()),
+            Kind::Int => {
+                self.b.op(op::I2L, 1);
+                Ok(())
+            }
+            kind => Err(format!("argument {idx} is a {kind:?}, not an int")),
+        }
+    }
+    fn arg_int(&mut self, idx: usize) -> Result<(), String> {
+        match self.arg(idx)? {
+            Kind::Int => Ok(()),
+            Kind::Long => {
+                self.b.op(op::L2I, -1);
+                Ok(())
+            }
+            kind => Err(format!("argument {idx} is a {kind:?}, not an int")),
+        }
+    }
+    fn arg_double(&mut self, idx: usize) -> Result<(), String> {
+        match self.arg(idx)? {
+            Kind::Double => (),
+            Kind::Float => self.b.op(op::F2D, 1),
+            Kind::Int => self.b.op(op::I2D, 1),
+            Kind::Long => self.b.op(op::L2D, 0),
+            Kind::Ref => return Err(format!("argument {idx} is an object, not a float")),
+        }
+        Ok(())
+    }
+    fn arg_string(&mut self, idx: usize) -> Result<(), String> {
+        self.arg(idx)?;
+        self.b.type_op(op::CHECKCAST, "java/lang/String");
+        Ok(())
+    }
+    fn stdout(&mut self) {
+        self.b.field(
+            op::GETSTATIC,
+            "java/lang/System",
+            "out",
+            "Ljava/io/PrintStream;",
+        );
+    }
+    fn flush(&mut self) {
+        self.stdout();
+        self.b
+            .invoke(op::INVOKEVIRTUAL, "java/io/PrintStream", "flush", "()V");
+    }
+    fn malloc(&mut self, size: usize, align: Option<usize>) -> Result<Val, String> {
+        self.arg_long(size)?;
+        match align {
+            Some(align) => self.arg_long(align)?,
+            None => self.b.long(16),
+        }
+        self.b.call(RUNTIME, "malloc", "(JJ)J");
+        Ok(Val::Long)
+    }
+    fn realloc(&mut self, align: Option<usize>) -> Result<Val, String> {
+        self.arg_long(0)?;
+        self.arg_long(1)?;
+        match align {
+            Some(align) => self.arg_long(align)?,
+            None => self.b.long(16),
+        }
+        self.b.call(RUNTIME, "realloc", "(JJJ)J");
+        Ok(Val::Long)
+    }
+    fn free(&mut self) -> Result<Val, String> {
+        self.arg_long(0)?;
+        self.b.call(RUNTIME, "free", "(J)V");
+        Ok(Val::Void)
+    }
+    /// Calls `java.lang.Math::name`, with all arguments converted to doubles.
+    fn math(&mut self, name: &str, arity: usize) -> Result<Val, String> {
+        for idx in 0..arity {
+            self.arg_double(idx)?;
+        }
+        let desc = format!("({})D", "D".repeat(arity));
+        self.b.call("java/lang/Math", name, &desc);
+        Ok(Val::Double)
+    }
+    /// Computes the libm function `name`, on doubles.
+    fn libm(&mut self, name: &str) -> Result<Val, String> {
+        match name {
+            "sqrt" | "cbrt" | "sin" | "cos" | "tan" | "asin" | "acos" | "atan" | "sinh"
+            | "cosh" | "tanh" | "exp" | "expm1" | "log" | "log10" | "log1p" | "floor" | "ceil" => {
+                self.math(name, 1)
+            }
+            "rint" | "nearbyint" | "roundeven" => self.math("rint", 1),
+            "fabs" => self.math("abs", 1),
+            "pow" | "atan2" | "hypot" => self.math(name, 2),
+            "fmin" => self.math("min", 2),
+            "fmax" => self.math("max", 2),
+            "copysign" => self.math("copySign", 2),
+            "fma" => self.math("fma", 3),
+            "exp2" => {
+                self.b.double(2.0);
+                self.arg_double(0)?;
+                self.b.call("java/lang/Math", "pow", "(DD)D");
+                Ok(Val::Double)
+            }
+            "log2" => {
+                self.math("log", 1)?;
+                self.b.double(std::f64::consts::LN_2);
+                self.b.op(op::DDIV, -2);
+                Ok(Val::Double)
+            }
+            "fmod" => {
+                self.arg_double(0)?;
+                self.arg_double(1)?;
+                self.b.op(op::DREM, -2);
+                Ok(Val::Double)
+            }
+            "fdim" => {
+                self.arg_double(0)?;
+                self.arg_double(1)?;
+                self.b.op(op::DSUB, -2);
+                self.b.double(0.0);
+                self.b.call("java/lang/Math", "max", "(DD)D");
+                Ok(Val::Double)
+            }
+            "trunc" | "round" => {
+                // Both round towards zero, `round` after moving the value half way away from it.
+                self.arg_double(0)?;
+                self.b.call("java/lang/Math", "abs", "(D)D");
+                if name == "round" {
+                    self.b.double(0.5);
+                    self.b.op(op::DADD, -2);
+                }
+                self.b.call("java/lang/Math", "floor", "(D)D");
+                self.arg_double(0)?;
+                self.b.call("java/lang/Math", "copySign", "(DD)D");
+                Ok(Val::Double)
+            }
+            _ => Err(format!("the function {name} is not supported")),
+        }
+    }
+    fn libc(&mut self, name: &str) -> Result<Val, String> {
+        match name {
+            "malloc" => self.malloc(0, None),
+            "calloc" => {
+                let size = self.b.local(Kind::Long);
+                let ptr = self.b.local(Kind::Long);
+                self.arg_long(0)?;
+                self.arg_long(1)?;
+                self.b.op(op::LMUL, -2);
+                self.b.store(Kind::Long, size);
+                self.b.load(Kind::Long, size);
+                self.b.long(16);
+                self.b.call(RUNTIME, "malloc", "(JJ)J");
+                self.b.store(Kind::Long, ptr);
+                self.b.load(Kind::Long, ptr);
+                self.b.int(0);
+                self.b.load(Kind::Long, size);
+                self.b.call(RUNTIME, "fill", "(JIJ)V");
+                self.b.load(Kind::Long, ptr);
+                Ok(Val::Long)
+            }
+            "aligned_alloc" => self.malloc(1, Some(0)),
+            "_mm_malloc" => self.malloc(0, Some(1)),
+            "posix_memalign" => {
+                self.arg_long(0)?;
+                self.malloc(2, Some(1))?;
+                self.b.call(RUNTIME, "st_i64", "(JJ)V");
+                Ok(Val::Void)
+            }
+            "realloc" => self.realloc(None),
+            "free" | "_mm_free" => self.free(),
+            "memcpy" | "memmove" => {
+                for idx in 0..3 {
+                    self.arg_long(idx)?;
+                }
+                self.b.call(RUNTIME, "copy", "(JJJ)V");
+                self.arg_long(0)?;
+                Ok(Val::Long)
+            }
+            "memset" => {
+                self.arg_long(0)?;
+                self.arg_int(1)?;
+                self.arg_long(2)?;
+                self.b.call(RUNTIME, "fill", "(JIJ)V");
+                self.arg_long(0)?;
+                Ok(Val::Long)
+            }
+            "memcmp" | "bcmp" => {
+                for idx in 0..3 {
+                    self.arg_long(idx)?;
+                }
+                self.b.call(RUNTIME, "memcmp", "(JJJ)I");
+                Ok(Val::Int)
+            }
+            "strlen" => {
+                self.arg_long(0)?;
+                self.b.call(RUNTIME, "strlen", "(J)J");
+                Ok(Val::Long)
+            }
+            "write" => {
+                for idx in 0..3 {
+                    self.arg_long(idx)?;
+                }
+                self.b.call(RUNTIME, "write", "(JJJ)J");
+                Ok(Val::Long)
+            }
+            "puts" => {
+                self.stdout();
+                self.arg_long(0)?;
+                self.b.call(RUNTIME, "cstring", "(J)Ljava/lang/String;");
+                self.b.invoke(
+                    op::INVOKEVIRTUAL,
+                    "java/io/PrintStream",
+                    "println",
+                    "(Ljava/lang/String;)V",
+                );
+                self.flush();
+                Ok(Val::Void)
+            }
+            "putchar" => {
+                self.stdout();
+                self.arg_int(0)?;
+                self.b
+                    .invoke(op::INVOKEVIRTUAL, "java/io/PrintStream", "write", "(I)V");
+                self.flush();
+                self.arg_int(0)?;
+                Ok(Val::Int)
+            }
+            "exit" | "_exit" => {
+                self.arg_int(0)?;
+                self.b.call(RUNTIME, "exit", "(I)V");
+                Ok(Val::Void)
+            }
+            "abort" => {
+                self.b.field(
+                    op::GETSTATIC,
+                    "java/lang/System",
+                    "err",
+                    "Ljava/io/PrintStream;",
+                );
+                self.b.string("Aborted");
+                self.b.invoke(
+                    op::INVOKEVIRTUAL,
+                    "java/io/PrintStream",
+                    "println",
+                    "(Ljava/lang/String;)V",
+                );
+                self.b.int(134);
+                self.b.call(RUNTIME, "exit", "(I)V");
+                Ok(Val::Void)
+            }
+            "__errno_location" => {
+                self.b.field(op::GETSTATIC, RUNTIME, "errno", "J");
+                Ok(Val::Long)
+            }
+            "getenv" => Ok(Val::Void),
+            "pthread_self" | "getpid" | "getuid" => {
+                self.b.long(1);
+                Ok(Val::Long)
+            }
+            "sched_yield"
+            | "isatty"
+            | "signal"
+            | "sigaction"
+            | "sigaltstack"
+            | "sigemptyset"
+            | "sigaddset"
+            | "pthread_atfork"
+            | "pthread_mutex_init"
+            | "pthread_mutex_lock"
+            | "pthread_mutex_trylock"
+            | "pthread_mutex_unlock"
+            | "pthread_mutex_destroy"
+            | "pthread_mutexattr_init"
+            | "pthread_mutexattr_settype"
+            | "pthread_mutexattr_destroy" => Ok(Val::Void),
+            "ldexp" | "ldexpf" | "scalbn" | "scalbnf" => {
+                self.arg_double(0)?;
+                self.b.double(2.0);
+                self.arg_int(1)?;
+                self.b.op(op::I2D, 1);
+                self.b.call("java/lang/Math", "pow", "(DD)D");
+                self.b.op(op::DMUL, -2);
+                Ok(Val::Double)
+            }
+            _ => {
+                // Math functions: `sqrtf` is `sqrt` computed in single precision.
+                let single = name.strip_suffix('f').filter(|_| {
+                    self.inputs
+                        .first()
+                        .is_some_and(|tpe| *tpe == Type::Float(Float::F32))
+                });
+                self.libm(single.unwrap_or(name))
+            }
+        }
+    }
+    fn dotnet(&mut self, class: &str, name: &str) -> Result<Val, String> {
+        match (class, name) {
+            ("System.Runtime.InteropServices.NativeMemory", _) => self.native_memory(name),
+            ("System.Runtime.InteropServices.Marshal", _) => self.marshal(name),
+            ("System.Int128" | "System.UInt128", _) => self.int128(class == "System.Int128", name),
+            ("System.Math" | "System.MathF" | "System.Double" | "System.Single", _) => {
+                self.dotnet_math(name)
+            }
+            ("System.Threading.Interlocked", _) => self.interlocked(name),
+            ("System.Numerics.BitOperations", _) => self.bit_operations(name),
+            ("System.Buffers.Binary.BinaryPrimitives", "ReverseEndianness") => {
+                let tpe = self.inputs.first().copied();
+                self.arg(0)?;
+                match tpe {
+                    Some(Type::Int(Int::I32 | Int::U32)) => {
+                        self.b.call("java/lang/Integer", "reverseBytes", "(I)I");
+                        Ok(Val::Int)
+                    }
+                    Some(Type::Int(Int::I64 | Int::U64 | Int::ISize | Int::USize)) => {
+                        self.b.call("java/lang/Long", "reverseBytes", "(J)J");
+                        Ok(Val::Long)
+                    }
+                    Some(Type::Int(Int::I16)) => {
+                        self.b.call("java/lang/Short", "reverseBytes", "(S)S");
+                        Ok(Val::Int)
+                    }
+                    Some(Type::Int(Int::U16)) => {
+                        self.b.call("java/lang/Character", "reverseBytes", "(C)C");
+                        Ok(Val::Int)
+                    }
+                    Some(Type::Int(Int::I8 | Int::U8)) => Ok(Val::Int),
+                    tpe => Err(format!("ReverseEndianness is not supported for {tpe:?}")),
+                }
+            }
+            ("System.Console", "Write" | "WriteLine") => self.console(name == "WriteLine"),
+            ("System.Environment", "Exit") => {
+                self.arg_int(0)?;
+                self.b.call(RUNTIME, "exit", "(I)V");
+                Ok(Val::Void)
+            }
+            ("System.Environment", "FailFast") => {
+                self.b.type_op(op::NEW, "java/lang/IllegalStateException");
+                self.b.op(op::DUP, 1);
+                self.arg_string(0)?;
+                self.b.invoke(
+                    op::INVOKESPECIAL,
+                    "java/lang/IllegalStateException",
+                    "<init>",
+                    "(Ljava/lang/String;)V",
+                );
+                self.b.op(op::ATHROW, -1);
+                Ok(Val::Void)
+            }
+            ("System.Environment", "GetCommandLineArgs") => {
+                self.b.call(RUNTIME, "args", "()[Ljava/lang/String;");
+                Ok(Val::Ref)
+            }
+            ("System.Environment", "get_ProcessorCount")
+            | ("System.Threading.Thread", "get_ManagedThreadId") => {
+                self.b.int(1);
+                Ok(Val::Int)
+            }
+            ("System.Environment", "GetEnvironmentVariable") => {
+                self.arg_string(0)?;
+                self.b.call(
+                    "java/lang/System",
+                    "getenv",
+                    "(Ljava/lang/String;)Ljava/lang/String;",
+                );
+                Ok(Val::Ref)
+            }
+            ("System.Threading.Thread", "get_CurrentThread") => {
+                self.b
+                    .call("java/lang/Thread", "currentThread", "()Ljava/lang/Thread;");
+                Ok(Val::Ref)
+            }
+            ("System.Threading.Thread", "MemoryBarrier") => {
+                get_unsafe(&mut self.b);
+                unsafe_call(&mut self.b, "fullFence", "()V");
+                Ok(Val::Void)
+            }
+            ("System.Threading.Thread", "Sleep" | "Yield" | "SpinWait")
+            | ("System.GC", "KeepAlive" | "Collect" | "SuppressFinalize")
+            | ("System.Object", ".ctor") => Ok(Val::Void),
+            ("System.String", "Concat") => {
+                for idx in 0..self.params.len() {
+                    self.arg(idx)?;
+                    self.b.call(
+                        "java/lang/String",
+                        "valueOf",
+                        "(Ljava/lang/Object;)Ljava/lang/String;",
+                    );
+                    if idx > 0 {
+                        self.b.invoke(
+                            op::INVOKEVIRTUAL,
+                            "java/lang/String",
+                            "concat",
+                            "(Ljava/lang/String;)Ljava/lang/String;",
+                        );
+                    }
+                }
+                Ok(Val::Ref)
+            }
+            ("System.String", "op_Equality" | "op_Inequality" | "Equals") => {
+                self.arg(0)?;
+                self.arg(1)?;
+                self.b.call(
+                    "java/util/Objects",
+                    "equals",
+                    "(Ljava/lang/Object;Ljava/lang/Object;)Z",
+                );
+                if name == "op_Inequality" {
+                    self.b.int(1);
+                    self.b.op(op::IXOR, -1);
+                }
+                Ok(Val::Int)
+            }
+            ("System.String", "get_Length") => {
+                self.arg_string(0)?;
+                self.b
+                    .invoke(op::INVOKEVIRTUAL, "java/lang/String", "length", "()I");
+                Ok(Val::Int)
+            }
+            ("System.String", "get_Chars") => {
+                self.arg_string(0)?;
+                self.arg_int(1)?;
+                self.b
+                    .invoke(op::INVOKEVIRTUAL, "java/lang/String", "charAt", "(I)C");
+                Ok(Val::Int)
+            }
+            (_, "get_Message") => {
+                self.arg(0)?;
+                self.b.type_op(op::CHECKCAST, "java/lang/Throwable");
+                self.b.invoke(
+                    op::INVOKEVIRTUAL,
+                    "java/lang/Throwable",
+                    "getMessage",
+                    "()Ljava/lang/String;",
+                );
+                Ok(Val::Ref)
+            }
+            (_, "ToString") => {
+                self.arg(0)?;
+                self.b.call(
+                    "java/lang/String",
+                    "valueOf",
+                    "(Ljava/lang/Object;)Ljava/lang/String;",
+                );
+                Ok(Val::Ref)
+            }
+            _ => Err(format!(
+                "the .NET method {class}::{name} is not supported on the JVM"
+            )),
+        }
+    }
+    fn native_memory(&mut self, name: &str) -> Result<Val, String> {
+        match name {
+            "Alloc" | "AllocZeroed" => {
+                let size = self.b.local(Kind::Long);
+                let ptr = self.b.local(Kind::Long);
+                self.arg_long(0)?;
+                if self.params.len() == 2 {
+                    self.arg_long(1)?;
+                    self.b.op(op::LMUL, -2);
+                }
+                self.b.store(Kind::Long, size);
+                self.b.load(Kind::Long, size);
+                self.b.long(16);
+                self.b.call(RUNTIME, "malloc", "(JJ)J");
+                self.b.store(Kind::Long, ptr);
+                if name == "AllocZeroed" {
+                    self.b.load(Kind::Long, ptr);
+                    self.b.int(0);
+                    self.b.load(Kind::Long, size);
+                    self.b.call(RUNTIME, "fill", "(JIJ)V");
+                }
+                self.b.load(Kind::Long, ptr);
+                Ok(Val::Long)
+            }
+            "AlignedAlloc" => self.malloc(0, Some(1)),
+            "Realloc" => self.realloc(None),
+            "AlignedRealloc" => self.realloc(Some(2)),
+            "Free" | "AlignedFree" => self.free(),
+            "Clear" => {
+                self.arg_long(0)?;
+                self.b.int(0);
+                self.arg_long(1)?;
+                self.b.call(RUNTIME, "fill", "(JIJ)V");
+                Ok(Val::Void)
+            }
+            "Fill" => {
+                self.arg_long(0)?;
+                self.arg_int(2)?;
+                self.arg_long(1)?;
+                self.b.call(RUNTIME, "fill", "(JIJ)V");
+                Ok(Val::Void)
+            }
+            "Copy" => {
+                self.arg_long(1)?;
+                self.arg_long(0)?;
+                self.arg_long(2)?;
+                self.b.call(RUNTIME, "copy", "(JJJ)V");
+                Ok(Val::Void)
+            }
+            _ => Err(format!("NativeMemory::{name} is not supported on the JVM")),
+        }
+    }
+    fn marshal(&mut self, name: &str) -> Result<Val, String> {
+        match name {
+            "AllocHGlobal" | "AllocCoTaskMem" => self.malloc(0, None),
+            "ReAllocHGlobal" | "ReAllocCoTaskMem" => self.realloc(None),
+            "FreeHGlobal" | "FreeCoTaskMem" => self.free(),
+            "GetLastPInvokeError" | "GetLastSystemError" | "GetLastWin32Error" => {
+                self.b.field(op::GETSTATIC, RUNTIME, "errno", "J");
+                self.b.call(RUNTIME, "ld_i32", "(J)I");
+                Ok(Val::Int)
+            }
+            "SetLastPInvokeError" | "SetLastSystemError" => {
+                self.b.field(op::GETSTATIC, RUNTIME, "errno", "J");
+                self.arg_int(0)?;
+                self.b.call(RUNTIME, "st_i32", "(JI)V");
+                Ok(Val::Void)
+            }
+            "StringToCoTaskMemUTF8" | "StringToHGlobalAnsi" => {
+                self.arg_string(0)?;
+                self.b.call(RUNTIME, "utf8", "(Ljava/lang/String;)J");
+                Ok(Val::Long)
+            }
+            "PtrToStringUTF8" | "PtrToStringAnsi" => {
+                self.arg_long(0)?;
+                if self.params.len() == 2 {
+                    self.arg_long(1)?;
+                    self.b.call(RUNTIME, "string", "(JJ)Ljava/lang/String;");
+                } else {
+                    self.b.call(RUNTIME, "cstring", "(J)Ljava/lang/String;");
+                }
+                Ok(Val::Ref)
+            }
+            _ => Err(format!("Marshal::{name} is not supported on the JVM")),
+        }
+    }
+    fn dotnet_math(&mut self, name: &str) -> Result<Val, String> {
+        // Integer overloads
+        if let Some(kind @ (Kind::Int | Kind::Long)) = self.params.first().map(|(kind, _)| *kind) {
+            let (desc, val) = if kind == Kind::Int {
+                ("I", Val::Int)
+            } else {
+                ("J", Val::Long)
+            };
+            let arity = match name {
+                "Abs" => 1,
+                "Max" | "Min" => 2,
+                _ => return Err(format!("Math::{name} is not supported for ints")),
+            };
+            for idx in 0..arity {
+                self.arg(idx)?;
+            }
+            let desc = format!("({}){desc}", desc.repeat(arity));
+            self.b.call("java/lang/Math", &name.to_lowercase(), &desc);
+            return Ok(val);
+        }
+        match name {
+            "Round" if self.params.len() == 2 => {
+                Err("Math::Round with a rounding mode is not supported".into())
+            }
+            "Round" => self.math("rint", 1),
+            "Log" if self.params.len() == 2 => {
+                self.math("log", 1)?;
+                self.b.double(1.0);
+                self.arg_double(1)?;
+                self.b.call("java/lang/Math", "log", "(D)D");
+                self.b.op(op::DDIV, -2);
+                self.b.op(op::DMUL, -2);
+                Ok(Val::Double)
+            }
+            // Like .NET, `java.lang.Math` propagates NaNs.
+            "Max" | "Min" => self.math(&name.to_lowercase(), 2),
+            "ScaleB" => self.libc("ldexp"),
+            "BitIncrement" | "BitDecrement" => {
+                let method = if name == "BitIncrement" {
+                    "nextUp"
+                } else {
+                    "nextDown"
+                };
+                if self.arg(0)? == Kind::Float {
+                    self.b.call("java/lang/Math", method, "(F)F");
+                    Ok(Val::Float)
+                } else {
+                    self.b.call("java/lang/Math", method, "(D)D");
+                    Ok(Val::Double)
+                }
+            }
+            "IsNaN" | "IsInfinity" | "IsFinite" => {
+                let method = match name {
+                    "IsNaN" => "isNaN",
+                    "IsInfinity" => "isInfinite",
+                    _ => "isFinite",
+                };
+                self.arg_double(0)?;
+                self.b.call("java/lang/Double", method, "(D)Z");
+                Ok(Val::Int)
+            }
+            _ => {
+                let libm_name = match name {
+                    "Ceiling" => "ceil",
+                    "Truncate" => "trunc",
+                    "Abs" => "fabs",
+                    "FusedMultiplyAdd" => "fma",
+                    "CopySign" => "copysign",
+                    _ => &name.to_lowercase(),
+                };
+                self.libm(libm_name)
+            }
+        }
+    }
+    fn interlocked(&mut self, name: &str) -> Result<Val, String> {
+        let tpe = match self.inputs.first() {
+            Some(Type::Ref(inner) | Type::Ptr(inner)) => self.ctx.asm[*inner],
+            other => {
+                return Err(format!(
+                    "Interlocked::{name} on a location of type {other:?}"
+                ))
+            }
+        };
+        let (suffix, kind, val) = match self.ctx.repr(tpe)? {
+            Repr::Scalar(Kind::Int) if self.ctx.size_of(tpe)? == 4 => ("Int", Kind::Int, Val::Int),
+            Repr::Scalar(Kind::Long) => ("Long", Kind::Long, Val::Long),
+            _ => return Err(format!("Interlocked::{name} is not supported for {tpe:?}")),
+        };
+        let prim = super::kind_desc(kind);
+        let old = self.b.local(kind);
+        let base = |this: &mut Self| -> Result<(), String> {
+            get_unsafe(&mut this.b);
+            this.b.op(op::ACONST_NULL, 1);
+            this.arg_long(0)
+        };
+        let operand = |this: &mut Self, idx: Option<usize>, negate: bool| -> Result<(), String> {
+            match idx {
+                Some(idx) => {
+                    this.arg(idx)?;
+                }
+                None if kind == Kind::Int => this.b.int(if negate { -1 } else { 1 }),
+                None => this.b.long(if negate { -1 } else { 1 }),
+            }
+            Ok(())
+        };
+        let add_op = if kind == Kind::Int {
+            op::IADD
+        } else {
+            op::LADD
+        };
+        let slots = i32::from(kind.slots());
+        match name {
+            "Exchange" => {
+                base(self)?;
+                self.arg(1)?;
+                unsafe_call(
+                    &mut self.b,
+                    &format!("getAndSet{suffix}"),
+                    &format!("(Ljava/lang/Object;J{prim}){prim}"),
+                );
+                Ok(val)
+            }
+            "Add" | "Increment" | "Decrement" => {
+                let idx = (name == "Add").then_some(1);
+                base(self)?;
+                operand(self, idx, name == "Decrement")?;
+                unsafe_call(
+                    &mut self.b,
+                    &format!("getAndAdd{suffix}"),
+                    &format!("(Ljava/lang/Object;J{prim}){prim}"),
+                );
+                operand(self, idx, name == "Decrement")?;
+                self.b.op(add_op, -slots);
+                Ok(val)
+            }
+            "CompareExchange" | "And" | "Or" => {
+                let (start, done) = (self.b.label(), self.b.label());
+                self.b.place(start);
+                base(self)?;
+                unsafe_call(
+                    &mut self.b,
+                    &format!("get{suffix}Volatile"),
+                    &format!("(Ljava/lang/Object;J){prim}"),
+                );
+                self.b.store(kind, old);
+                if name == "CompareExchange" {
+                    // Fails without writing, if the location does not hold the comparand.
+                    self.b.load(kind, old);
+                    self.arg(2)?;
+                    if kind == Kind::Int {
+                        self.b.jump(op::IF_ICMPNE, done);
+                    } else {
+                        self.b.op(op::LCMP, -3);
+                        self.b.jump(op::IFNE, done);
+                    }
+                }
+                base(self)?;
+                self.b.load(kind, old);
+                if name == "CompareExchange" {
+                    self.arg(1)?;
+                } else {
+                    self.b.load(kind, old);
+                    self.arg(1)?;
+                    let logic = if name == "And" { op::IAND } else { op::IOR };
+                    self.b.op(logic + u8::from(kind == Kind::Long), -slots);
+                }
+                unsafe_call(
+                    &mut self.b,
+                    &format!("compareAndSwap{suffix}"),
+                    &format!("(Ljava/lang/Object;J{prim}{prim})Z"),
+                );
+                self.b.jump(op::IFEQ, start);
+                self.b.place(done);
+                self.b.load(kind, old);
+                Ok(val)
+            }
+            _ => Err(format!("Interlocked::{name} is not supported on the JVM")),
+        }
+    }
+    fn bit_operations(&mut self, name: &str) -> Result<Val, String> {
+        let (class, desc, val) = match self.inputs.first() {
+            Some(Type::Int(Int::I32 | Int::U32)) => ("java/lang/Integer", "I", Val::Int),
+            Some(Type::Int(Int::I64 | Int::U64 | Int::ISize | Int::USize)) => {
+                ("java/lang/Long", "J", Val::Long)
+            }
+            tpe => {
+                return Err(format!(
+                    "BitOperations::{name} is not supported for {tpe:?}"
+                ))
+            }
+        };
+        self.arg(0)?;
+        match name {
+            "LeadingZeroCount" | "TrailingZeroCount" | "PopCount" => {
+                let method = match name {
+                    "LeadingZeroCount" => "numberOfLeadingZeros",
+                    "TrailingZeroCount" => "numberOfTrailingZeros",
+                    _ => "bitCount",
+                };
+                self.b.call(class, method, &format!("({desc})I"));
+                Ok(Val::Int)
+            }
+            "Log2" => {
+                if val == Val::Int {
+                    self.b.int(1);
+                    self.b.op(op::IOR, -1);
+                } else {
+                    self.b.long(1);
+                    self.b.op(op::LOR, -2);
+                }
+                self.b
+                    .call(class, "numberOfLeadingZeros", &format!("({desc})I"));
+                self.b.int(if val == Val::Int { 31 } else { 63 });
+                self.b.op(op::SWAP, 0);
+                self.b.op(op::ISUB, -1);
+                Ok(Val::Int)
+            }
+            "RotateLeft" | "RotateRight" => {
+                let method = if name == "RotateLeft" {
+                    "rotateLeft"
+                } else {
+                    "rotateRight"
+                };
+                self.arg_int(1)?;
+                self.b.call(class, method, &format!("({desc}I){desc}"));
+                Ok(val)
+            }
+            _ => Err(format!("BitOperations::{name} is not supported on the JVM")),
+        }
+    }
+    fn console(&mut self, line: bool) -> Result<Val, String> {
+        let method = if line { "println" } else { "print" };
+        self.stdout();
+        let desc = match self.inputs.first().copied() {
+            None => "()V",
+            Some(Type::Bool) => {
+                let (yes, done) = (self.b.label(), self.b.label());
+                self.arg(0)?;
+                self.b.jump(op::IFNE, yes);
+                self.b.string("False");
+                self.b.jump(op::GOTO, done);
+                self.b.code.set_depth(1);
+                self.b.place(yes);
+                self.b.string("True");
+                self.b.place(done);
+                "(Ljava/lang/String;)V"
+            }
+            Some(Type::PlatformChar) => {
+                self.arg(0)?;
+                "(C)V"
+            }
+            Some(Type::Int(Int::U32)) => {
+                self.arg(0)?;
+                self.b.call(
+                    "java/lang/Integer",
+                    "toUnsignedString",
+                    "(I)Ljava/lang/String;",
+                );
+                "(Ljava/lang/String;)V"
+            }
+            Some(Type::Int(Int::U64 | Int::USize)) => {
+                self.arg(0)?;
+                self.b.call(
+                    "java/lang/Long",
+                    "toUnsignedString",
+                    "(J)Ljava/lang/String;",
+                );
+                "(Ljava/lang/String;)V"
+            }
+            Some(tpe) => match self.arg(0)? {
+                Kind::Int => "(I)V",
+                Kind::Long if matches!(self.ctx.repr(tpe)?, Repr::Scalar(_)) => "(J)V",
+                Kind::Float => "(F)V",
+                Kind::Double => "(D)V",
+                Kind::Ref => "(Ljava/lang/Object;)V",
+                Kind::Long => return Err(format!("Console::Write is not supported for {tpe:?}")),
+            },
+        };
+        self.b
+            .invoke(op::INVOKEVIRTUAL, "java/io/PrintStream", method, desc);
+        self.flush();
+        Ok(Val::Void)
+    }
+    /// Implements the operators of `System.Int128` and `System.UInt128`. Results are written to the return buffer.
+    fn int128(&mut self, signed: bool, name: &str) -> Result<Val, String> {
+        let ret = |this: &mut Self| this.b.field(op::GETSTATIC, RUNTIME, "ret", "J");
+        let code = match name {
+            "op_Addition" => Some(0),
+            "op_Subtraction" => Some(1),
+            "op_Multiply" => Some(2),
+            "op_Division" => Some(3),
+            "op_Modulus" => Some(4),
+            "op_BitwiseAnd" => Some(5),
+            "op_BitwiseOr" => Some(6),
+            "op_ExclusiveOr" => Some(7),
+            _ => None,
+        };
+        if let Some(code) = code {
+            self.arg_long(0)?;
+            self.arg_long(1)?;
+            ret(self);
+            self.b.int(code | if signed { 16 } else { 0 });
+            self.b.call(RUNTIME, "wide", "(JJJI)J");
+            return Ok(Val::Memory);
+        }
+        let cmp = match name {
+            "op_Equality" => Some(op::IFEQ),
+            "op_Inequality" => Some(op::IFNE),
+            "op_LessThan" => Some(op::IFLT),
+            "op_GreaterThan" => Some(op::IFGT),
+            "op_LessThanOrEqual" => Some(op::IFLE),
+            "op_GreaterThanOrEqual" => Some(op::IFGE),
+            _ => None,
+        };
+        if let Some(cmp) = cmp {
+            self.arg_long(0)?;
+            self.arg_long(1)?;
+            self.b.int(i32::from(signed));
+            self.b.call(RUNTIME, "wide_cmp", "(JJI)I");
+            to_bool(&mut self.b, cmp);
+            return Ok(Val::Int);
+        }
+        match name {
+            "op_LeftShift" | "op_RightShift" | "op_UnsignedRightShift" => {
+                let mode = match name {
+                    "op_LeftShift" => 0,
+                    "op_RightShift" if signed => 1,
+                    _ => 2,
+                };
+                self.arg_long(0)?;
+                self.arg_int(1)?;
+                ret(self);
+                self.b.int(mode);
+                self.b.call(RUNTIME, "wide_shift", "(JIJI)J");
+            }
+            "op_UnaryNegation" | "op_OnesComplement" => {
+                self.arg_long(0)?;
+                ret(self);
+                let method = if name == "op_UnaryNegation" {
+                    "wide_neg"
+                } else {
+                    "wide_not"
+                };
+                self.b.call(RUNTIME, method, "(JJ)J");
+            }
+            "get_Zero" | "get_One" | "get_MaxValue" | "get_MinValue" => {
+                let (lo, hi) = match (name, signed) {
+                    ("get_Zero", _) | ("get_MinValue", false) => (0, 0),
+                    ("get_One", _) => (1, 0),
+                    ("get_MaxValue", true) => (-1, i64::MAX),
+                    ("get_MaxValue", false) => (-1, -1),
+                    _ => (0, i64::MIN),
+                };
+                let dst = self.b.local(Kind::Long);
+                ret(self);
+                self.b.store(Kind::Long, dst);
+                store_halves(&mut self.b, dst, lo, hi);
+                self.b.load(Kind::Long, dst);
+            }
+            ".ctor" => {
+                // Int128(ulong upper, ulong lower)
+                for (offset, idx) in [(0, 1), (8, 0)] {
+                    ret(self);
+                    self.b.long(offset);
+                    self.b.op(op::LADD, -2);
+                    self.arg_long(idx)?;
+                    self.b.call(RUNTIME, "st_i64", "(JJ)V");
+                }
+                ret(self);
+            }
+            "op_Implicit" | "op_Explicit" | "op_CheckedExplicit" => return self.int128_cast(),
+            _ => {
+                return Err(format!(
+                    "the 128 bit int method {name} is not supported on the JVM"
+                ))
+            }
+        }
+        Ok(Val::Memory)
+    }
+    /// Returns whether `tpe` is a 128 bit int, and whether it is signed.
+    fn int_sign(&self, tpe: Type) -> (bool, bool) {
+        match tpe {
+            Type::Int(int) => (matches!(int, Int::I128 | Int::U128), int.is_signed()),
+            Type::ClassRef(class) => match &self.ctx.asm[self.ctx.asm[class].name()][..] {
+                "System.Int128" => (true, true),
+                "System.UInt128" => (true, false),
+                _ => (false, false),
+            },
+            _ => (false, false),
+        }
+    }
+    /// Converts between 128 bit ints, and other ints or floats.
+    fn int128_cast(&mut self) -> Result<Val, String> {
+        let input = *self
+            .inputs
+            .first()
+            .ok_or_else(|| "a conversion must have an argument".to_owned())?;
+        let (input_wide, input_signed) = self.int_sign(input);
+        let (output_wide, output_signed) = self.int_sign(self.output);
+        match (input_wide, output_wide) {
+            (true, true) => {
+                self.arg_long(0)?;
+                self.b.field(op::GETSTATIC, RUNTIME, "ret", "J");
+                self.b.long(16);
+                self.b.call(RUNTIME, "move", "(JJJ)J");
+                Ok(Val::Memory)
+            }
+            (true, false) => {
+                self.arg_long(0)?;
+                if matches!(
+                    self.ctx.repr(self.output)?,
+                    Repr::Scalar(Kind::Float | Kind::Double)
+                ) {
+                    self.b.int(i32::from(input_signed));
+                    self.b.call(RUNTIME, "wide_to_double", "(JI)D");
+                    Ok(Val::Double)
+                } else {
+                    self.b.call(RUNTIME, "wide_to", "(J)J");
+                    Ok(Val::Long)
+                }
+            }
+            (false, true) => {
+                match self.params[0].0 {
+                    Kind::Float | Kind::Double => {
+                        self.arg_double(0)?;
+                        self.b.int(i32::from(output_signed));
+                        self.b.field(op::GETSTATIC, RUNTIME, "ret", "J");
+                        self.b.call(RUNTIME, "wide_from_double", "(DIJ)J");
+                        return Ok(Val::Memory);
+                    }
+                    Kind::Int => {
+                        self.arg_long(0)?;
+                        if !input_signed {
+                            self.b.long(0xffff_ffff);
+                            self.b.op(op::LAND, -2);
+                        }
+                    }
+                    Kind::Long => self.arg_long(0)?,
+                    Kind::Ref => return Err(format!("can't convert {input:?} to a 128 bit int")),
+                }
+                self.b.int(i32::from(input_signed));
+                self.b.field(op::GETSTATIC, RUNTIME, "ret", "J");
+                self.b.call(RUNTIME, "wide_from", "(JIJ)J");
+                Ok(Val::Memory)
+            }
+            (false, false) => Err(format!(
+                "a conversion from {input:?} to {:?} does not involve a 128 bit int",
+                self.output
+            )),
+        }
+    }
+    /// Converts the value on top of the stack to `output`, and returns it.
+    fn ret(&mut self, val: Val, output: Type) -> Result<(), String> {
+        let repr = self.ctx.repr(output)?;
+        match (val, repr) {
+            (Val::Void, Repr::Void) => (),
+            (Val::Long | Val::Double, Repr::Void) => self.b.op(op::POP2, -2),
+            (_, Repr::Void) => self.b.op(op::POP, -1),
+            (Val::Void, Repr::Scalar(kind)) => match kind {
+                Kind::Int => self.b.int(0),
+                Kind::Long => self.b.long(0),
+                Kind::Float => self.b.float(0.0),
+                Kind::Double => self.b.double(0.0),
+                Kind::Ref => self.b.op(op::ACONST_NULL, 1),
+            },
+            (Val::Int, Repr::Scalar(Kind::Int)) => normalize(&mut self.b, output),
+            (Val::Long, Repr::Scalar(Kind::Int)) => {
+                self.b.op(op::L2I, -1);
+                normalize(&mut self.b, output);
+            }
+            (Val::Int, Repr::Scalar(Kind::Long)) => self.b.op(op::I2L, 1),
+            (Val::Long | Val::Memory, Repr::Scalar(Kind::Long)) => (),
+            (Val::Float, Repr::Scalar(Kind::Float)) | (Val::Double, Repr::Scalar(Kind::Double)) => {
+            }
+            (Val::Double, Repr::Scalar(Kind::Float)) => self.b.op(op::D2F, -1),
+            (Val::Float, Repr::Scalar(Kind::Double)) => self.b.op(op::F2D, 1),
+            (Val::Int, Repr::Scalar(Kind::Double)) => self.b.op(op::I2D, 1),
+            (Val::Int, Repr::Scalar(Kind::Float)) => self.b.op(op::I2F, 0),
+            (Val::Ref, Repr::Scalar(Kind::Ref)) => {
+                let desc = self.ctx.desc(output)?;
+                coerce(&mut self.b, &desc);
+            }
+            (Val::Memory, Repr::Memory { .. }) => (),
+            (val, repr) => return Err(format!("can't return a {val:?} as {repr:?}")),
+        }
+        self.b.ret(repr.kind());
+        Ok(())
+    }
+}
@@ -13,10 +13,11 @@ pub use cilroot::{BranchCond, CILRoot, RootIdx};
 pub use class::{ClassDef, ClassDefIdx, ClassRef, ClassRefIdx};
 pub use cst::Const;
 pub use field::{FieldDesc, FieldIdx, StaticFieldDesc, StaticFieldIdx};
-pub use fnsig::{FnSig, SigIdx};
+pub use fnsig::{CallConv, FnSig, SigIdx};
 pub use iter::{CILIter, CILIterElem};
 pub use method::{MethodDef, MethodDefIdx, MethodImpl, MethodRef, MethodRefIdx};
 pub use strings::StringIdx;
+pub use switch::{SwitchPlan, SwitchTable};
 pub use tpe::float::Float;
 pub use tpe::int::Int;
 pub use tpe::{Type, TypeIdx};
@@ -41,13 +42,16 @@ pub mod fnsig;
 /// Defines hashable and equable floating point types. All NaNs are compared by bits, and -0.0 != 0.0.
 pub mod hashable;
 pub mod il_exporter;
+pub mod interpreter;
 pub mod iter;
 pub mod java_exporter;
 pub mod macros;
 pub mod method;
 pub mod method_builder;
 pub mod opt;
+pub mod pe_exporter;
 pub mod strings;
+pub mod switch;
 pub mod tpe;
 pub mod typecheck;
 #[test]
@@ -1,10 +1,29 @@
 use super::OptFuel;
-use crate::v2::{
-    cilnode::MethodKind, Assembly, CILIter, CILIterElem, CILNode, CILRoot, MethodDef, MethodImpl,
-    MethodRefIdx, NodeIdx, RootIdx,
+use crate::{
+    config,
+    v2::{
+        basic_block::BlockId,
+        cilnode::{ExtendKind, MethodKind},
+        Assembly, BasicBlock, CILIter, CILIterElem, CILNode, CILRoot, ClassDefIdx, Const, Float,
+        Int, MethodDef, MethodImpl, MethodRefIdx, NodeIdx, RootIdx, StringIdx, Type, TypeIdx,
+    },
 };
-#[cfg(test)]
-use crate::v2::{BasicBlock, Type};
+config!(
+    INLINE_CALLS,
+    bool,
+    false,
+    "Allows the optimizer to inline calls to small, static methods defined in the same class."
+);
+/// Callees with more roots than this are never inlined.
+const MAX_INLINE_SIZE: usize = 48;
+/// Callees with this many roots or less are not much bigger than the call itself, so they are always worth inlining.
+const ALWAYS_INLINE_SIZE: usize = 6;
+/// Callees whose size, minus the benefit of inlining them, is below this threshold get inlined.
+const INLINE_THRESHOLD: usize = 24;
+/// Inlining will never grow a method past this many roots.
+const MAX_CALLER_SIZE: usize = 2048;
+/// Inlining will never grow a method past this many locals.
+const MAX_CALLER_LOCALS: usize = 1024;
 fn trivial_inline_block<'def, 'asm: 'def>(
     def: &'def MethodDef,
     asm: &'asm mut Assembly,
@@ -146,6 +165,307 @@ pub fn inline_trivial_call_root(
         None => CILRoot::Call(Box::new((calle, call_args.into()))),
     }
 }
+/// Describes what happens with the value returned by an inlined call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CallSite {
+    /// The result is stored in a local.
+    Store(u32),
+    /// The result is discarded, or the callee returns nothing.
+    Discard,
+    /// The result is immediately returned from the caller.
+    Return,
+}
+impl CallSite {
+    /// Checks if `root` is a call which could be inlined. Returns the calle, its arguments, and what happens with the returned value.
+    fn from_root(root: &CILRoot, asm: &Assembly) -> Option<(MethodRefIdx, Box<[NodeIdx]>, Self)> {
+        let (node, site) = match root {
+            CILRoot::Call(info) => return Some((info.0, info.1.clone(), Self::Discard)),
+            CILRoot::Pop(node) => (*node, Self::Discard),
+            CILRoot::StLoc(loc, node) => (*node, Self::Store(*loc)),
+            CILRoot::Ret(node) => (*node, Self::Return),
+            _ => return None,
+        };
+        match asm.get_node(node) {
+            CILNode::Call(info) => Some((info.0, info.1.clone(), site)),
+            _ => None,
+        }
+    }
+}
+/// Describes how the blocks, locals and arguments of an inlined method map to the blocks and locals of the caller.
+struct InlineMap {
+    arg_base: u32,
+    loc_base: u32,
+    block_offset: BlockId,
+    continuation: BlockId,
+    site: CallSite,
+}
+impl InlineMap {
+    fn block(&self, block: BlockId) -> BlockId {
+        block + self.block_offset
+    }
+    /// Copies a block of the calle into the caller, turning its returns into jumps to the continuation block.
+    fn inline_block(&self, block: &BasicBlock, asm: &mut Assembly) -> BasicBlock {
+        let mut roots = Vec::with_capacity(block.roots().len() + 1);
+        for root in block.roots() {
+            let root = asm.get_root(*root).clone().map(
+                asm,
+                &mut |root, _| match root {
+                    CILRoot::StLoc(loc, val) => CILRoot::StLoc(loc + self.loc_base, val),
+                    CILRoot::StArg(arg, val) => CILRoot::StLoc(arg + self.arg_base, val),
+                    CILRoot::Branch(info) => {
+                        let (target, sub_target, cond) = *info;
+                        let sub_target = if sub_target == 0 {
+                            0
+                        } else {
+                            self.block(sub_target)
+                        };
+                        CILRoot::Branch(Box::new((self.block(target), sub_target, cond)))
+                    }
+                    CILRoot::Switch(mut switch) => {
+                        for (target, sub_target) in switch.1.targets_mut() {
+                            *target = self.block(*target);
+                            if *sub_target != 0 {
+                                *sub_target = self.block(*sub_target);
+                            }
+                        }
+                        CILRoot::Switch(switch)
+                    }
+                    CILRoot::ExitSpecialRegion { target, source } => CILRoot::ExitSpecialRegion {
+                        target: self.block(target),
+                        source: self.block(source),
+                    },
+                    _ => root,
+                },
+                &mut |node, _| match node {
+                    CILNode::LdArg(arg) => CILNode::LdLoc(arg + self.arg_base),
+                    CILNode::LdArgA(arg) => CILNode::LdLocA(arg + self.arg_base),
+                    CILNode::LdLoc(loc) => CILNode::LdLoc(loc + self.loc_base),
+                    CILNode::LdLocA(loc) => CILNode::LdLocA(loc + self.loc_base),
+                    _ => node,
+                },
+            );
+            let jump = CILRoot::Branch(Box::new((self.continuation, 0, None)));
+            match (root, self.site) {
+                (CILRoot::Ret(val), CallSite::Store(loc)) => {
+                    roots.push(asm.alloc_root(CILRoot::StLoc(loc, val)));
+                    roots.push(asm.alloc_root(jump));
+                }
+                (CILRoot::Ret(val), CallSite::Discard) => {
+                    roots.push(asm.alloc_root(CILRoot::Pop(val)));
+                    roots.push(asm.alloc_root(jump));
+                }
+                (CILRoot::VoidRet, CallSite::Store(_) | CallSite::Discard) => {
+                    roots.push(asm.alloc_root(jump));
+                }
+                (root, _) => roots.push(asm.alloc_root(root)),
+            }
+        }
+        let handler = block.handler().map(|handler| {
+            handler
+                .iter()
+                .map(|block| self.inline_block(block, asm))
+                .collect()
+        });
+        BasicBlock::new(roots, self.block(block.block_id()), handler)
+    }
+}
+/// Returns a root setting the local `loc`, of type `tpe`, to zero. The locals of a method are zeroed when it gets called,
+/// but an inlined method may run many times during a single call of its caller, so its locals must be zeroed again each
+/// time it is entered.
+fn zero_local(loc: u32, tpe: TypeIdx, asm: &mut Assembly) -> RootIdx {
+    let zero = match asm[tpe] {
+        Type::Int(int) => Some(int.zero()),
+        Type::Float(float @ (Float::F32 | Float::F64)) => Some(float.zero()),
+        Type::Bool => Some(Const::Bool(false)),
+        _ => None,
+    };
+    let root = if let Some(zero) = zero {
+        CILRoot::StLoc(loc, asm.alloc_node(zero))
+    } else {
+        let addr = asm.alloc_node(CILNode::LdLocA(loc));
+        let zero = asm.alloc_node(Const::U8(0));
+        let size = asm.alloc_node(CILNode::SizeOf(tpe));
+        let size = asm.alloc_node(CILNode::IntCast {
+            input: size,
+            target: Int::USize,
+            extend: ExtendKind::ZeroExtend,
+        });
+        CILRoot::InitBlk(Box::new((addr, zero, size)))
+    };
+    asm.alloc_root(root)
+}
+/// Returns the biggest block id used by `blocks` or their handlers.
+fn max_block_id(blocks: &[BasicBlock]) -> BlockId {
+    blocks
+        .iter()
+        .map(|block| {
+            block
+                .handler()
+                .map_or(0, max_block_id)
+                .max(block.block_id())
+        })
+        .max()
+        .unwrap_or(0)
+}
+/// Checks if `def` can be inlined into a method of class `class`, and if so, returns its size(in roots).
+fn inline_candidate(def: &MethodDef, class: ClassDefIdx, asm: &Assembly) -> Option<usize> {
+    // Only static methods can be inlined.
+    let MethodKind::Static = def.kind() else {
+        return None;
+    };
+    // Inlining a method from another class could break access rules.
+    if def.class() != class {
+        return None;
+    }
+    let MethodImpl::MethodBody { blocks, .. } = def.implementation() else {
+        return None;
+    };
+    if blocks.is_empty() {
+        return None;
+    }
+    let size: usize = blocks.iter().map(|block| block.iter_roots().count()).sum();
+    if size > MAX_INLINE_SIZE {
+        return None;
+    }
+    // Returning from within a protected region or a handler requires more than a jump, so we don't inline such methods.
+    let returns_from_handler = blocks
+        .iter()
+        .filter(|block| block.handler().is_some())
+        .flat_map(BasicBlock::iter_roots)
+        .any(|root| matches!(asm.get_root(root), CILRoot::Ret(_) | CILRoot::VoidRet));
+    if returns_from_handler {
+        return None;
+    }
+    // A tail call is guaranteed not to grow the stack, which would no longer hold after inlining.
+    let tail_calls = blocks
+        .iter()
+        .flat_map(BasicBlock::iter_roots)
+        .any(|root| matches!(asm.get_root(root), CILRoot::TailCall(_)));
+    if tail_calls {
+        return None;
+    }
+    // Memory allocated on the stack is freed when a method returns. If such a method got inlined into a loop, it would leak stack space.
+    let allocates_on_stack = blocks.iter().flat_map(BasicBlock::iter_roots).any(|root| {
+        CILIter::new(asm.get_root(root).clone(), asm).any(|elem| {
+            matches!(
+                elem,
+                CILIterElem::Node(CILNode::LocAlloc { .. } | CILNode::LocAllocAlgined { .. })
+            )
+        })
+    });
+    if allocates_on_stack {
+        return None;
+    }
+    Some(size)
+}
+/// Decides if inlining a calle of `size` roots, called with `call_args`, is worth it.
+fn worth_inlining(size: usize, call_args: &[NodeIdx], asm: &Assembly) -> bool {
+    if size <= ALWAYS_INLINE_SIZE {
+        return true;
+    }
+    // Constants and adresses of locals passed as arguments enable further optimizations after inlining.
+    let benefit: usize = call_args
+        .iter()
+        .map(|arg| match asm.get_node(*arg) {
+            CILNode::Const(_) => 4,
+            CILNode::LdLocA(_) | CILNode::LdArgA(_) => 6,
+            _ => 0,
+        })
+        .sum();
+    size.saturating_sub(benefit) <= INLINE_THRESHOLD
+}
+/// Inlines calls to small static methods, splicing their blocks into the caller.
+pub(super) fn inline_calls(
+    blocks: &mut Vec<BasicBlock>,
+    locals: &mut Vec<(Option<StringIdx>, TypeIdx)>,
+    class: ClassDefIdx,
+    asm: &mut Assembly,
+    fuel: &mut OptFuel,
+) {
+    let mut caller_size: usize = blocks.iter().map(|block| block.iter_roots().count()).sum();
+    let mut block_idx = 0;
+    while block_idx < blocks.len() {
+        // Jumping out of a protected region requires more than a jump, so only calls from blocks without handlers get inlined.
+        if blocks[block_idx].handler().is_some() {
+            block_idx += 1;
+            continue;
+        }
+        let site = blocks[block_idx]
+            .roots()
+            .iter()
+            .enumerate()
+            .find_map(|(root_idx, root)| {
+                let (calle, args, site) = CallSite::from_root(asm.get_root(*root), asm)?;
+                // The method currently being optimized is not present in the assembly, so recursive calls never get inlined.
+                let def = asm.method_def_from_ref(calle)?;
+                let size = inline_candidate(def, class, asm)?;
+                let callee_locals = match def.implementation() {
+                    MethodImpl::MethodBody { locals, .. } => locals.len(),
+                    _ => unreachable!(),
+                };
+                if caller_size + size > MAX_CALLER_SIZE
+                    || locals.len() + args.len() + callee_locals > MAX_CALLER_LOCALS
+                    || !worth_inlining(size, &args, asm)
+                {
+                    return None;
+                }
+                Some((root_idx, calle, args, site, size))
+            });
+        let Some((root_idx, calle, args, site, size)) = site else {
+            block_idx += 1;
+            continue;
+        };
+        if !fuel.consume(8 + 2 * size as u32) {
+            return;
+        }
+        let def = asm.method_def_from_ref(calle).unwrap().clone();
+        let MethodImpl::MethodBody {
+            blocks: callee_blocks,
+            locals: callee_locals,
+        } = def.implementation()
+        else {
+            unreachable!()
+        };
+        // Arguments of the calle are turned into locals, initialized with the values passed to the call.
+        let arg_base = locals.len() as u32;
+        let inputs = asm[def.sig()].inputs().to_vec();
+        locals.extend(inputs.into_iter().map(|tpe| (None, asm.alloc_type(tpe))));
+        let loc_base = locals.len() as u32;
+        locals.extend(callee_locals.iter().copied());
+        let block_offset = max_block_id(blocks) + 1;
+        let map = InlineMap {
+            arg_base,
+            loc_base,
+            block_offset,
+            continuation: block_offset + max_block_id(callee_blocks) + 1,
+            site,
+        };
+        let roots = blocks[block_idx].roots_mut();
+        let rest = roots.split_off(root_idx + 1);
+        roots.pop();
+        for (arg, val) in args.iter().enumerate() {
+            roots.push(asm.alloc_root(CILRoot::StLoc(arg_base + arg as u32, *val)));
+        }
+        for (loc, (_, tpe)) in callee_locals.iter().enumerate() {
+            roots.push(zero_local(loc_base + loc as u32, *tpe, asm));
+        }
+        roots.push(asm.alloc_root(CILRoot::Branch(Box::new((
+            map.block(callee_blocks[0].block_id()),
+            0,
+            None,
+        )))));
+        // Nothing after a return is reachable, so there is no need for a continuation block.
+        if site != CallSite::Return {
+            blocks.push(BasicBlock::new(rest, map.continuation, None));
+        }
+        for block in callee_blocks {
+            let block = map.inline_block(block, asm);
+            blocks.push(block);
+        }
+        caller_size += size + args.len() + callee_locals.len() + 1;
+        block_idx += 1;
+    }
+}
 #[test]
 fn test_inline() {
     let mut asm = Assembly::default();
@@ -190,3 +510,159 @@ fn test_inline() {
     )
     .is_some());
 }
+#[test]
+fn test_inline_calls() {
+    use crate::v2::{cilnode::UnOp, cilroot::CmpKind, Access, BinOp, BranchCond};
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let i32_idx = asm.alloc_type(Type::Int(Int::I32));
+    // fn abs_dec(x: i32) -> i32 { if x < 0 { let tmp = -x; return tmp; } x = x - 1; x }
+    let arg = asm.alloc_node(CILNode::LdArg(0));
+    let zero = asm.alloc_node(0_i32);
+    let one = asm.alloc_node(1_i32);
+    let is_neg = asm.alloc_root(CILRoot::Branch(Box::new((
+        1,
+        0,
+        Some(BranchCond::Lt(arg, zero, CmpKind::Signed)),
+    ))));
+    let dec = asm.alloc_node(CILNode::BinOp(arg, one, BinOp::Sub));
+    let dec = asm.alloc_root(CILRoot::StArg(0, dec));
+    let ret_arg = asm.alloc_root(CILRoot::Ret(arg));
+    let neg = asm.alloc_node(CILNode::UnOp(arg, UnOp::Neg));
+    let neg = asm.alloc_root(CILRoot::StLoc(0, neg));
+    let tmp = asm.alloc_node(CILNode::LdLoc(0));
+    let ret_tmp = asm.alloc_root(CILRoot::Ret(tmp));
+    let name = asm.alloc_string("abs_dec");
+    let sig = asm.sig([Type::Int(Int::I32)], Type::Int(Int::I32));
+    let callee = asm.new_method(MethodDef::new(
+        Access::Public,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![
+                BasicBlock::new(vec![is_neg, dec, ret_arg], 0, None),
+                BasicBlock::new(vec![neg, ret_tmp], 1, None),
+            ],
+            locals: vec![(None, i32_idx)],
+        },
+        vec![None],
+    ));
+    // fn caller(a: i32) -> i32 { let res = abs_dec(a); res + 1 }
+    let call = asm.alloc_node(CILNode::Call(Box::new((*callee, [arg].into()))));
+    let call = asm.alloc_root(CILRoot::StLoc(0, call));
+    let res = asm.alloc_node(CILNode::LdLoc(0));
+    let inc = asm.alloc_node(CILNode::BinOp(res, one, BinOp::Add));
+    let ret = asm.alloc_root(CILRoot::Ret(inc));
+    let mut blocks = vec![BasicBlock::new(vec![call, ret], 0, None)];
+    let mut locals = vec![(None, i32_idx)];
+    // Not enough fuel - nothing should change.
+    inline_calls(
+        &mut blocks,
+        &mut locals,
+        main_module,
+        &mut asm,
+        &mut OptFuel::new(1),
+    );
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(locals.len(), 1);
+    inline_calls(
+        &mut blocks,
+        &mut locals,
+        main_module,
+        &mut asm,
+        &mut OptFuel::new(1000),
+    );
+    // The caller block, its continuation, and the two blocks of the calle.
+    assert_eq!(blocks.len(), 4);
+    // The local of the caller, the argument of the calle, and the local of the calle.
+    assert_eq!(locals.len(), 3);
+    assert!(blocks.iter().flat_map(BasicBlock::iter_roots).all(|root| {
+        !CILIter::new(asm.get_root(root).clone(), &asm)
+            .any(|elem| matches!(elem, CILIterElem::Node(CILNode::Call(_))))
+    }));
+    // The argument is stored in a local, the local of the calle is zeroed, and then the caller jumps to the inlined
+    // entry block.
+    assert_eq!(asm.get_root(blocks[0].roots()[0]), &CILRoot::StLoc(1, arg));
+    assert_eq!(asm.get_root(blocks[0].roots()[1]), &CILRoot::StLoc(2, zero));
+    let CILRoot::Branch(jump) = asm.get_root(blocks[0].roots()[2]) else {
+        panic!("Expected a jump to the inlined method.");
+    };
+    assert_eq!(jump.0, blocks[2].block_id());
+    // The continuation block contains the rest of the caller.
+    assert_eq!(blocks[1].roots(), &[ret]);
+    // Returns of the calle store the result, and jump to the continuation block.
+    let stored: Vec<_> = blocks[2..]
+        .iter()
+        .flat_map(BasicBlock::iter_roots)
+        .filter_map(|root| match asm.get_root(root) {
+            CILRoot::StLoc(0, val) => Some(asm.get_node(*val).clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(stored, vec![CILNode::LdLoc(1), CILNode::LdLoc(2)]);
+}
+#[test]
+fn inline_zeroes_locals() {
+    use crate::v2::{Access, BinOp, ClassRef};
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let i32_idx = asm.alloc_type(Type::Int(Int::I32));
+    let name = asm.alloc_string("Pair");
+    let pair = asm.alloc_class_ref(ClassRef::new(name, None, true, [].into()));
+    let pair_idx = asm.alloc_type(Type::ClassRef(pair));
+    // fn count() -> i32 { loc0 = loc0 + 1; loc0 }, with an unused struct local.
+    let loc = asm.alloc_node(CILNode::LdLoc(0));
+    let one = asm.alloc_node(1_i32);
+    let inc = asm.alloc_node(CILNode::BinOp(loc, one, BinOp::Add));
+    let inc = asm.alloc_root(CILRoot::StLoc(0, inc));
+    let ret = asm.alloc_root(CILRoot::Ret(loc));
+    let name = asm.alloc_string("count");
+    let sig = asm.sig([], Type::Int(Int::I32));
+    let callee = asm.new_method(MethodDef::new(
+        Access::Public,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![inc, ret], 0, None)],
+            locals: vec![(None, i32_idx), (None, pair_idx)],
+        },
+        vec![],
+    ));
+    // fn caller() -> i32 { count(); count() }
+    let call = asm.alloc_node(CILNode::Call(Box::new((*callee, [].into()))));
+    let pop = asm.alloc_root(CILRoot::Pop(call));
+    let ret = asm.alloc_root(CILRoot::Ret(call));
+    let mut blocks = vec![BasicBlock::new(vec![pop, ret], 0, None)];
+    let mut locals = vec![];
+    inline_calls(
+        &mut blocks,
+        &mut locals,
+        main_module,
+        &mut asm,
+        &mut OptFuel::new(1000),
+    );
+    // Both calls got inlined, each with its own copy of the locals.
+    assert_eq!(locals.len(), 4);
+    // Each copy must start from zero, or the second call would return 2.
+    let zeroed: Vec<_> = blocks
+        .iter()
+        .flat_map(BasicBlock::iter_roots)
+        .filter_map(|root| match asm.get_root(root) {
+            CILRoot::StLoc(loc, val)
+                if asm.get_node(*val) == &CILNode::Const(Box::new(Const::I32(0))) =>
+            {
+                Some(*loc)
+            }
+            CILRoot::InitBlk(info) => match asm.get_node(info.0) {
+                CILNode::LdLocA(loc) => Some(*loc),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    assert_eq!(zeroed, vec![0, 1, 2, 3]);
+}
@@ -15,6 +15,8 @@ mod opt_fuel;
 mod opt_node;
 mod side_effect;
 mod simplify_handlers;
+mod sroa;
+mod ssa;
 mod test;
 pub fn opt_if_fuel<T>(new: T, original: T, fuel: &mut OptFuel) -> T {
     if fuel.consume(1) {
@@ -497,7 +499,19 @@ impl MethodDef {
         fuel: &mut OptFuel,
     ) {
         let nop = asm.alloc_root(CILRoot::Nop);
+        if *inline::INLINE_CALLS {
+            let class = self.class();
+            if let MethodImpl::MethodBody { blocks, locals } = self.implementation_mut() {
+                inline::inline_calls(blocks, locals, class, asm, fuel);
+            }
+        }
+        if *sroa::SPLIT_LOCAL_STRUCTS {
+            self.implementation_mut().split_structs(asm, fuel);
+        }
         self.implementation_mut().propagate_locals(asm, cache, fuel);
+        if *ssa::SSA_OPT && fuel.consume(8) {
+            self.implementation_mut().ssa_opt(asm, cache, fuel);
+        }
         self.implementation_mut()
             .remove_dead_writes(asm, cache, fuel);
         if fuel.consume(1) {
@@ -0,0 +1,438 @@
+use super::OptFuel;
+use crate::{
+    config,
+    v2::{
+        Assembly, BasicBlock, CILNode, CILRoot, FieldDesc, FieldIdx, MethodImpl, NodeIdx, RootIdx,
+        Type,
+    },
+};
+config!(
+    SPLIT_LOCAL_STRUCTS,
+    bool,
+    false,
+    "Splits local structs, which are only ever accessed field by field, into one local per field."
+);
+/// Structs with more fields than this are never split.
+const MAX_SPLIT_FIELDS: usize = 8;
+/// The biggest size of a primitive field. Pointer-sized fields are assumed to take up this much space, so the overlap check stays correct on every platform.
+const MAX_PRIMITIVE_SIZE: u32 = 8;
+/// The size of a field, if it is a primitive.
+fn primitive_size(tpe: Type) -> Option<u32> {
+    match tpe {
+        Type::Int(int) => Some(int.size().map_or(MAX_PRIMITIVE_SIZE, u32::from)),
+        Type::Float(float) => Some(u32::from(float.size())),
+        Type::Bool => Some(1),
+        Type::PlatformChar => Some(2),
+        Type::Ptr(_) | Type::Ref(_) | Type::FnPtr(_) | Type::PlatformString => {
+            Some(MAX_PRIMITIVE_SIZE)
+        }
+        Type::ClassRef(_)
+        | Type::PlatformGeneric(..)
+        | Type::PlatformObject
+        | Type::Void
+        | Type::PlatformArray { .. }
+        | Type::SIMDVector(_) => None,
+    }
+}
+/// Returns the fields of a local of type `tpe`, if it is a valuetype which can be split.
+/// Valuetypes with overlapping fields(unions and enums) can't be split, since a write to one field changes the others.
+fn splittable_fields(tpe: Type, asm: &mut Assembly) -> Option<Vec<FieldIdx>> {
+    let Type::ClassRef(cref) = tpe else {
+        return None;
+    };
+    if !asm[cref].is_valuetype() || !asm[cref].generics().is_empty() {
+        return None;
+    }
+    let def = &asm[asm.class_ref_to_def(cref)?];
+    let fields = def.fields();
+    if fields.is_empty() || fields.len() > MAX_SPLIT_FIELDS {
+        return None;
+    }
+    let mut offsets: Vec<_> = fields
+        .iter()
+        .map(|(tpe, _, offset)| offset.map(|offset| (offset, *tpe)))
+        .collect::<Option<_>>()?;
+    offsets.sort_by_key(|(offset, _)| *offset);
+    for pair in offsets.windows(2) {
+        let [(offset, tpe), (next, _)] = pair else {
+            unreachable!()
+        };
+        if offset + primitive_size(*tpe)? > *next {
+            return None;
+        }
+    }
+    let fields = fields.to_vec();
+    Some(
+        fields
+            .into_iter()
+            .map(|(tpe, name, _)| asm.alloc_field(FieldDesc::new(cref, name, tpe)))
+            .collect(),
+    )
+}
+/// The local `addr` refers to, if it is the local itself or its address.
+fn local_of(addr: NodeIdx, asm: &Assembly) -> Option<u32> {
+    match asm.get_node(addr) {
+        CILNode::LdLoc(loc) | CILNode::LdLocA(loc) => Some(*loc),
+        _ => None,
+    }
+}
+/// The index of `field` within the fields of a split local.
+fn field_pos(candidates: &[Option<Vec<FieldIdx>>], loc: u32, field: FieldIdx) -> Option<usize> {
+    candidates[loc as usize]
+        .as_ref()?
+        .iter()
+        .position(|candidate| *candidate == field)
+}
+/// Marks all the locals used by `node` in a way that prevents splitting them.
+fn scan_node(node: NodeIdx, candidates: &mut [Option<Vec<FieldIdx>>], asm: &Assembly) {
+    match asm.get_node(node) {
+        CILNode::LdField { addr, field } => {
+            if let Some(loc) = local_of(*addr, asm) {
+                if field_pos(candidates, loc, *field).is_some() {
+                    return;
+                }
+            }
+            scan_node(*addr, candidates, asm);
+        }
+        // The whole local is read, or its address escapes.
+        CILNode::LdLoc(loc) | CILNode::LdLocA(loc) => candidates[*loc as usize] = None,
+        node => {
+            for child in node.child_nodes() {
+                scan_node(child, candidates, asm);
+            }
+        }
+    }
+}
+/// Marks all the locals used by `root` in a way that prevents splitting them, and records copies between locals.
+fn scan_root(
+    root: RootIdx,
+    candidates: &mut [Option<Vec<FieldIdx>>],
+    copies: &mut Vec<(u32, u32)>,
+    asm: &Assembly,
+) {
+    match asm.get_root(root) {
+        CILRoot::SetField(info) => {
+            let (field, addr, val) = info.as_ref();
+            if let CILNode::LdLocA(loc) = asm.get_node(*addr) {
+                if field_pos(candidates, *loc, *field).is_some() {
+                    scan_node(*val, candidates, asm);
+                    return;
+                }
+            }
+            scan_node(*addr, candidates, asm);
+            scan_node(*val, candidates, asm);
+        }
+        CILRoot::StLoc(dst, tree) => {
+            if let CILNode::LdLoc(src) = asm.get_node(*tree) {
+                if candidates[*dst as usize].is_some()
+                    && candidates[*dst as usize] == candidates[*src as usize]
+                {
+                    copies.push((*dst, *src));
+                    return;
+                }
+            }
+            // The whole local is overwritten.
+            candidates[*dst as usize] = None;
+            scan_node(*tree, candidates, asm);
+        }
+        root => {
+            for node in root.nodes() {
+                scan_node(*node, candidates, asm);
+            }
+        }
+    }
+}
+/// Rewrites the roots of `block` and its handler to use the split locals.
+fn rewrite_block(
+    block: &mut BasicBlock,
+    candidates: &[Option<Vec<FieldIdx>>],
+    split: &[u32],
+    asm: &mut Assembly,
+) {
+    if let Some(handler) = block.handler_mut() {
+        for block in handler {
+            rewrite_block(block, candidates, split, asm);
+        }
+    }
+    let roots = std::mem::take(block.roots_mut());
+    let mut new_roots = Vec::with_capacity(roots.len());
+    for root in roots {
+        match asm.get_root(root).clone() {
+            // A copy of a split local becomes a copy of each of its fields.
+            CILRoot::StLoc(dst, tree) if candidates[dst as usize].is_some() => {
+                let CILNode::LdLoc(src) = *asm.get_node(tree) else {
+                    unreachable!("A split local is only ever written to by copies.")
+                };
+                if dst == src {
+                    continue;
+                }
+                let fields = candidates[dst as usize].clone().unwrap();
+                for (pos, field) in fields.into_iter().enumerate() {
+                    let val = if candidates[src as usize].is_some() {
+                        CILNode::LdLoc(split[src as usize] + pos as u32)
+                    } else {
+                        CILNode::LdField { addr: tree, field }
+                    };
+                    let val = asm.alloc_node(val);
+                    new_roots.push(
+                        asm.alloc_root(CILRoot::StLoc(split[dst as usize] + pos as u32, val)),
+                    );
+                }
+            }
+            root => {
+                let root = root.map(
+                    asm,
+                    &mut |root, asm| match root {
+                        CILRoot::SetField(info) => {
+                            let (field, addr, val) = *info;
+                            match local_of(addr, asm)
+                                .and_then(|loc| Some((loc, field_pos(candidates, loc, field)?)))
+                            {
+                                Some((loc, pos)) => {
+                                    CILRoot::StLoc(split[loc as usize] + pos as u32, val)
+                                }
+                                None => CILRoot::SetField(Box::new((field, addr, val))),
+                            }
+                        }
+                        _ => root,
+                    },
+                    &mut |node, asm| match node {
+                        CILNode::LdField { addr, field } => match local_of(addr, asm)
+                            .and_then(|loc| Some((loc, field_pos(candidates, loc, field)?)))
+                        {
+                            Some((loc, pos)) => CILNode::LdLoc(split[loc as usize] + pos as u32),
+                            None => node,
+                        },
+                        _ => node,
+                    },
+                );
+                new_roots.push(asm.alloc_root(root));
+            }
+        }
+    }
+    *block.roots_mut() = new_roots;
+}
+impl MethodImpl {
+    /// Splits valuetype locals, which never have their address escape and are only ever accessed field by field, into one local per field.
+    pub fn split_structs(&mut self, asm: &mut Assembly, fuel: &mut OptFuel) {
+        let MethodImpl::MethodBody { blocks, locals } = self else {
+            return;
+        };
+        let mut candidates: Vec<Option<Vec<FieldIdx>>> = locals
+            .iter()
+            .map(|(_, tpe)| splittable_fields(asm[*tpe], asm))
+            .collect();
+        if candidates.iter().all(Option::is_none) {
+            return;
+        }
+        let mut copies = Vec::new();
+        for root in blocks.iter().flat_map(BasicBlock::iter_roots) {
+            scan_root(root, &mut candidates, &mut copies, asm);
+        }
+        // A local copied as a whole into a local which is not split can't be split either, since its value would have to be reassembled.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (dst, src) in &copies {
+                if candidates[*dst as usize].is_none() && candidates[*src as usize].is_some() {
+                    candidates[*src as usize] = None;
+                    changed = true;
+                }
+            }
+        }
+        let split_count = candidates.iter().filter(|fields| fields.is_some()).count();
+        if split_count == 0 || !fuel.consume(4 * split_count as u32) {
+            return;
+        }
+        // Allocate one local per field of each split local.
+        let mut split = vec![0; locals.len()];
+        for (loc, fields) in candidates.iter().enumerate() {
+            let Some(fields) = fields else {
+                continue;
+            };
+            split[loc] = locals.len() as u32;
+            let name = locals[loc].0;
+            for field in fields {
+                let field = *asm.get_field(*field);
+                let field_name = name.map(|name| {
+                    let name = format!("{}_{}", &asm[name], &asm[field.name()]);
+                    asm.alloc_string(name)
+                });
+                let tpe = asm.alloc_type(field.tpe());
+                locals.push((field_name, tpe));
+            }
+        }
+        for block in blocks.iter_mut() {
+            rewrite_block(block, &candidates, &split, asm);
+        }
+    }
+}
+/// Defines a fat pointer struct, returning its type and its `ptr` and `len` fields.
+#[cfg(test)]
+fn fat_ptr(asm: &mut Assembly) -> (crate::v2::TypeIdx, FieldIdx, FieldIdx) {
+    use crate::v2::{Access, ClassDef, Int};
+    let name = asm.alloc_string("FatPtr");
+    let ptr_name = asm.alloc_string("ptr");
+    let len_name = asm.alloc_string("len");
+    let void_ptr = asm.nptr(Type::Void);
+    let class = asm.class_def(ClassDef::new(
+        name,
+        true,
+        0,
+        None,
+        vec![
+            (void_ptr, ptr_name, Some(0)),
+            (Type::Int(Int::USize), len_name, Some(8)),
+        ],
+        vec![],
+        Access::Public,
+        None,
+        None,
+    ));
+    let fat_ptr = asm.alloc_type(Type::ClassRef(*class));
+    let ptr = asm.alloc_field(FieldDesc::new(*class, ptr_name, void_ptr));
+    let len = asm.alloc_field(FieldDesc::new(*class, len_name, Type::Int(Int::USize)));
+    (fat_ptr, ptr, len)
+}
+#[test]
+fn split_fat_ptr() {
+    let mut asm = Assembly::default();
+    let (fat_ptr, ptr, len) = fat_ptr(&mut asm);
+    let null = asm.alloc_node(CILNode::LdArg(0));
+    let five = asm.alloc_node(crate::v2::Const::USize(5));
+    let addr = asm.alloc_node(CILNode::LdLocA(0));
+    let copy_src = asm.alloc_node(CILNode::LdLoc(0));
+    let copy_addr = asm.alloc_node(CILNode::LdLocA(1));
+    let ld_len = asm.alloc_node(CILNode::LdField {
+        addr: copy_addr,
+        field: len,
+    });
+    let body: Vec<_> = [
+        CILRoot::SetField(Box::new((ptr, addr, null))),
+        CILRoot::SetField(Box::new((len, addr, five))),
+        CILRoot::StLoc(1, copy_src),
+        CILRoot::Ret(ld_len),
+    ]
+    .into_iter()
+    .map(|root| asm.alloc_root(root))
+    .collect();
+    let mut method = MethodImpl::MethodBody {
+        blocks: vec![BasicBlock::new(body.clone(), 0, None)],
+        locals: vec![(None, fat_ptr), (None, fat_ptr)],
+    };
+    method.split_structs(&mut asm, &mut OptFuel::new(100));
+    let MethodImpl::MethodBody { blocks, locals } = &method else {
+        unreachable!()
+    };
+    assert_eq!(locals.len(), 6);
+    let roots: Vec<_> = blocks[0]
+        .roots()
+        .iter()
+        .map(|root| asm.get_root(*root).clone())
+        .collect();
+    let ld_loc = |loc, asm: &mut Assembly| asm.alloc_node(CILNode::LdLoc(loc));
+    assert_eq!(
+        roots,
+        vec![
+            CILRoot::StLoc(2, null),
+            CILRoot::StLoc(3, five),
+            CILRoot::StLoc(4, ld_loc(2, &mut asm)),
+            CILRoot::StLoc(5, ld_loc(3, &mut asm)),
+            CILRoot::Ret(ld_loc(5, &mut asm)),
+        ]
+    );
+    // If the value of a struct is used as a whole, it can't be split. Its copy can still be split, since it is only ever read field by field.
+    let ret = asm.alloc_root(CILRoot::Ret(copy_src));
+    let mut method = MethodImpl::MethodBody {
+        blocks: vec![BasicBlock::new(
+            vec![body[0], body[1], body[2], ret],
+            0,
+            None,
+        )],
+        locals: vec![(None, fat_ptr), (None, fat_ptr)],
+    };
+    method.split_structs(&mut asm, &mut OptFuel::new(100));
+    let MethodImpl::MethodBody { blocks, locals } = &method else {
+        unreachable!()
+    };
+    assert_eq!(locals.len(), 4);
+    let ld_field = |field, asm: &mut Assembly| {
+        asm.alloc_node(CILNode::LdField {
+            addr: copy_src,
+            field,
+        })
+    };
+    let roots: Vec<_> = blocks[0]
+        .roots()
+        .iter()
+        .map(|root| asm.get_root(*root).clone())
+        .collect();
+    assert_eq!(
+        roots,
+        vec![
+            CILRoot::SetField(Box::new((ptr, addr, null))),
+            CILRoot::SetField(Box::new((len, addr, five))),
+            CILRoot::StLoc(2, ld_field(ptr, &mut asm)),
+            CILRoot::StLoc(3, ld_field(len, &mut asm)),
+            CILRoot::Ret(copy_src),
+        ]
+    );
+}
+#[test]
+fn no_split_escaping() {
+    use crate::v2::{cilnode::MethodKind, MethodRef};
+    let mut asm = Assembly::default();
+    let (fat_ptr, ptr, len) = fat_ptr(&mut asm);
+    let void_ptr = asm.nptr(Type::Void);
+    let main_module = *asm.main_module();
+    let name = asm.alloc_string("escape");
+    let sig = asm.sig([void_ptr], Type::Void);
+    let escape = asm.alloc_methodref(MethodRef::new(
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        [].into(),
+    ));
+    let null = asm.alloc_node(CILNode::LdArg(0));
+    let ld_len = |loc, asm: &mut Assembly| {
+        let addr = asm.alloc_node(CILNode::LdLocA(loc));
+        asm.alloc_node(CILNode::LdField { addr, field: len })
+    };
+    let split = |roots: Vec<CILRoot>, asm: &mut Assembly| {
+        let roots = roots.into_iter().map(|root| asm.alloc_root(root)).collect();
+        let mut method = MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(roots, 0, None)],
+            locals: vec![(None, fat_ptr), (None, fat_ptr)],
+        };
+        method.split_structs(asm, &mut OptFuel::new(100));
+        let MethodImpl::MethodBody { locals, .. } = method else {
+            unreachable!()
+        };
+        locals.len()
+    };
+    // The address of local 0 escapes, so only local 1 gets split.
+    let addr = asm.alloc_node(CILNode::LdLocA(0));
+    let roots = vec![
+        CILRoot::SetField(Box::new((ptr, addr, null))),
+        CILRoot::Call(Box::new((escape, [addr].into()))),
+        CILRoot::Pop(ld_len(0, &mut asm)),
+        CILRoot::Pop(ld_len(1, &mut asm)),
+    ];
+    assert_eq!(split(roots, &mut asm), 4);
+    // Only the address of a single field of local 0 escapes, but the other field could be accessed trough it too.
+    let len_addr = asm.alloc_node(CILNode::LdFieldAdress { addr, field: len });
+    let roots = vec![
+        CILRoot::SetField(Box::new((ptr, addr, null))),
+        CILRoot::Call(Box::new((escape, [len_addr].into()))),
+        CILRoot::Pop(ld_len(1, &mut asm)),
+    ];
+    assert_eq!(split(roots, &mut asm), 4);
+    // Once nothing escapes, both locals get split.
+    let roots = vec![
+        CILRoot::SetField(Box::new((ptr, addr, null))),
+        CILRoot::Pop(ld_len(0, &mut asm)),
+        CILRoot::Pop(ld_len(1, &mut asm)),
+    ];
+    assert_eq!(split(roots, &mut asm), 6);
+}
@@ -0,0 +1,259 @@
+use fxhash::FxHashMap;
+
+use crate::v2::{Assembly, BasicBlock, CILRoot};
+/// The control flow graph of a method body without exception handlers. Blocks are refered to by their index in the method body, and not by their id.
+pub struct Cfg {
+    succs: Vec<Vec<usize>>,
+    preds: Vec<Vec<usize>>,
+}
+impl Cfg {
+    /// Builds the control flow graph of `blocks`. Returns `None` if the blocks have handlers, or use control flow this analysis does not understand.
+    /// All the branches of a block must be at its very end, and the block must end with an unconditional jump, a return or a throw.
+    pub fn new(blocks: &[BasicBlock], asm: &Assembly) -> Option<Self> {
+        let mut ids = FxHashMap::default();
+        for (idx, block) in blocks.iter().enumerate() {
+            if block.handler().is_some() || ids.insert(block.block_id(), idx).is_some() {
+                return None;
+            }
+        }
+        let mut succs = vec![Vec::new(); blocks.len()];
+        for (idx, block) in blocks.iter().enumerate() {
+            let mut in_branches = false;
+            let mut terminated = false;
+            for root in block.roots() {
+                match asm.get_root(*root) {
+                    CILRoot::Branch(info) => {
+                        let (target, sub_target, cond) = info.as_ref();
+                        // Jumps within protected regions require handlers, which are not supported.
+                        if *sub_target != 0 {
+                            return None;
+                        }
+                        let target = *ids.get(target)?;
+                        in_branches = true;
+                        if terminated {
+                            continue;
+                        }
+                        if !succs[idx].contains(&target) {
+                            succs[idx].push(target);
+                        }
+                        terminated = cond.is_none();
+                    }
+                    CILRoot::Switch(switch) => {
+                        in_branches = true;
+                        if terminated {
+                            continue;
+                        }
+                        for (target, sub_target) in switch.1.targets() {
+                            if *sub_target != 0 {
+                                return None;
+                            }
+                            let target = *ids.get(target)?;
+                            if !succs[idx].contains(&target) {
+                                succs[idx].push(target);
+                            }
+                        }
+                        terminated = true;
+                    }
+                    CILRoot::Nop | CILRoot::SourceFileInfo { .. } => (),
+                    CILRoot::ExitSpecialRegion { .. } => return None,
+                    // A root between two branches would only execute on some of the outgoing edges.
+                    _ if in_branches => return None,
+                    CILRoot::Ret(_)
+                    | CILRoot::VoidRet
+                    | CILRoot::TailCall(_)
+                    | CILRoot::Throw(_)
+                    | CILRoot::ReThrow
+                    | CILRoot::Unreachable(_) => terminated = true,
+                    _ => (),
+                }
+            }
+            // Falling trough to the next block is not supported.
+            if !terminated {
+                return None;
+            }
+        }
+        let mut preds = vec![Vec::new(); blocks.len()];
+        for (block, succs) in succs.iter().enumerate() {
+            for succ in succs {
+                preds[*succ].push(block);
+            }
+        }
+        Some(Self { succs, preds })
+    }
+    /// The blocks control may flow to after `block`.
+    pub fn succs(&self, block: usize) -> &[usize] {
+        &self.succs[block]
+    }
+    /// The blocks control may flow to `block` from.
+    pub fn preds(&self, block: usize) -> &[usize] {
+        &self.preds[block]
+    }
+    pub fn len(&self) -> usize {
+        self.succs.len()
+    }
+    /// Returns the blocks reachable from the entry block, in reverse postorder.
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.len()];
+        let mut postorder = Vec::with_capacity(self.len());
+        if self.len() == 0 {
+            return postorder;
+        }
+        // Explicit stack of (block, next successor to visit), to avoid overflowing the native stack on huge methods.
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, succ_idx)) = stack.last_mut() {
+            let block = *block;
+            if let Some(succ) = self.succs[block].get(*succ_idx) {
+                *succ_idx += 1;
+                if !visited[*succ] {
+                    visited[*succ] = true;
+                    stack.push((*succ, 0));
+                }
+            } else {
+                postorder.push(block);
+                stack.pop();
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+}
+/// The dominator tree of a [`Cfg`], computed using the algorithm from "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy.
+pub struct DomTree {
+    idom: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    rpo: Vec<usize>,
+}
+impl DomTree {
+    pub fn new(cfg: &Cfg) -> Self {
+        let rpo = cfg.reverse_postorder();
+        let mut rpo_idx = vec![usize::MAX; cfg.len()];
+        for (idx, block) in rpo.iter().enumerate() {
+            rpo_idx[*block] = idx;
+        }
+        let mut idom: Vec<Option<usize>> = vec![None; cfg.len()];
+        if let Some(entry) = rpo.first() {
+            idom[*entry] = Some(*entry);
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for pred in cfg.preds(*block) {
+                    if idom[*pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => *pred,
+                        Some(curr) => intersect(&idom, &rpo_idx, *pred, curr),
+                    });
+                }
+                if new_idom.is_some() && idom[*block] != new_idom {
+                    idom[*block] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        let mut children = vec![Vec::new(); cfg.len()];
+        for block in rpo.iter().skip(1) {
+            children[idom[*block].unwrap()].push(*block);
+        }
+        Self {
+            idom,
+            children,
+            rpo,
+        }
+    }
+    /// The immediate dominator of `block`, or `None` if the block is the entry block or is unreachable.
+    pub fn idom(&self, block: usize) -> Option<usize> {
+        self.idom[block].filter(|idom| *idom != block)
+    }
+    /// Checks if `block` is reachable from the entry block.
+    pub fn is_reachable(&self, block: usize) -> bool {
+        self.idom[block].is_some()
+    }
+    /// The blocks immediately dominated by `block`.
+    pub fn children(&self, block: usize) -> &[usize] {
+        &self.children[block]
+    }
+    /// Computes the dominance frontier of each block.
+    pub fn frontiers(&self, cfg: &Cfg) -> Vec<Vec<usize>> {
+        let mut frontiers = vec![Vec::new(); cfg.len()];
+        for block in &self.rpo {
+            let preds = cfg.preds(*block);
+            // The entry block is also entered from outside the method, so any jump to it makes it a join point.
+            if preds.len() < 2 && *block != self.rpo[0] {
+                continue;
+            }
+            let idom = self.idom(*block);
+            for pred in preds {
+                if !self.is_reachable(*pred) {
+                    continue;
+                }
+                let mut runner = Some(*pred);
+                while runner != idom {
+                    let curr = runner.unwrap();
+                    if !frontiers[curr].contains(block) {
+                        frontiers[curr].push(*block);
+                    }
+                    runner = self.idom(curr);
+                }
+            }
+        }
+        frontiers
+    }
+}
+fn intersect(idom: &[Option<usize>], rpo_idx: &[usize], mut lhs: usize, mut rhs: usize) -> usize {
+    while lhs != rhs {
+        while rpo_idx[lhs] > rpo_idx[rhs] {
+            lhs = idom[lhs].unwrap();
+        }
+        while rpo_idx[rhs] > rpo_idx[lhs] {
+            rhs = idom[rhs].unwrap();
+        }
+    }
+    lhs
+}
+#[test]
+fn diamond() {
+    use crate::v2::BranchCond;
+    let mut asm = Assembly::default();
+    let cond = asm.alloc_node(true);
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    let jump = |asm: &mut Assembly, target, cond| {
+        asm.alloc_root(CILRoot::Branch(Box::new((target, 0, cond))))
+    };
+    // 0 -> (1 | 2) -> 3
+    let blocks = vec![
+        BasicBlock::new(
+            vec![
+                jump(&mut asm, 1, Some(BranchCond::True(cond))),
+                jump(&mut asm, 2, None),
+            ],
+            0,
+            None,
+        ),
+        BasicBlock::new(vec![jump(&mut asm, 3, None)], 1, None),
+        BasicBlock::new(vec![jump(&mut asm, 3, None)], 2, None),
+        BasicBlock::new(vec![ret], 3, None),
+        // Unreachable
+        BasicBlock::new(vec![jump(&mut asm, 3, None)], 4, None),
+    ];
+    let cfg = Cfg::new(&blocks, &asm).unwrap();
+    assert_eq!(cfg.succs(0), &[1, 2]);
+    assert_eq!(cfg.preds(3), &[1, 2, 4]);
+    let dom = DomTree::new(&cfg);
+    assert_eq!(dom.idom(0), None);
+    assert_eq!(dom.idom(1), Some(0));
+    assert_eq!(dom.idom(2), Some(0));
+    assert_eq!(dom.idom(3), Some(0));
+    assert!(!dom.is_reachable(4));
+    let frontiers = dom.frontiers(&cfg);
+    assert_eq!(frontiers[1], vec![3]);
+    assert_eq!(frontiers[2], vec![3]);
+    assert!(frontiers[0].is_empty());
+    // A block falling trough to the next one is not supported.
+    let fallthrough = vec![BasicBlock::new(vec![], 0, None)];
+    assert!(Cfg::new(&fallthrough, &asm).is_none());
+}
@@ -0,0 +1,159 @@
+use fxhash::FxHashMap;
+
+use super::{
+    sccp::{Lattice, Sccp},
+    Ssa, Version,
+};
+use crate::v2::{
+    opt::SideEffectInfoCache, Assembly, BasicBlock, CILNode, CILRoot, NodeIdx, StringIdx, TypeIdx,
+};
+/// Describes how a root should be rewritten.
+#[derive(Default, Debug)]
+pub struct RootRewrite {
+    /// Locals read by the root, and what those reads should be replaced with.
+    pub reads: Vec<(u32, CILNode)>,
+    /// The new value written by a `StLoc`.
+    pub value: Option<CILNode>,
+}
+impl RootRewrite {
+    pub fn is_empty(&self) -> bool {
+        self.reads.is_empty() && self.value.is_none()
+    }
+}
+/// Computes how each root should be rewritten, using the constants found by [`Sccp`] and global value numbering.
+/// Two versions get the same value number if one is a copy of the other, or if they are computed by the same pure expression from versions with the same value numbers.
+pub fn rewrites(
+    ssa: &Ssa,
+    sccp: &Sccp,
+    blocks: &[BasicBlock],
+    locals: &[(Option<StringIdx>, TypeIdx)],
+    asm: &Assembly,
+    cache: &mut SideEffectInfoCache,
+) -> Vec<Vec<RootRewrite>> {
+    let mut rewrites: Vec<Vec<RootRewrite>> = blocks
+        .iter()
+        .map(|block| {
+            block
+                .roots()
+                .iter()
+                .map(|_| RootRewrite::default())
+                .collect()
+        })
+        .collect();
+    // Each version starts with its own, unique value number.
+    let mut value_numbers: Vec<u32> = (0..ssa.versions.len() as u32).collect();
+    let mut next_number = ssa.versions.len() as u32;
+    let mut expressions: FxHashMap<(NodeIdx, Vec<u32>), u32> = FxHashMap::default();
+    // The versions with each value number, in the order they were defined.
+    let mut holders: FxHashMap<u32, Vec<Version>> = FxHashMap::default();
+    ssa.walk(
+        |block| sccp.is_executable(block),
+        |block, root_idx, current| {
+            let info = ssa.root(block, root_idx);
+            let rewrite = &mut rewrites[block][root_idx];
+            // The first local still holding a version with this value number. Since it was defined first, it dominates all the other ones.
+            let leader = |number: u32, loc: u32| {
+                let (leader, _) = holders
+                    .get(&number)?
+                    .iter()
+                    .map(|version| (ssa.version(*version).0, *version))
+                    .find(|(holder, version)| current[*holder as usize] == *version)?;
+                (leader != loc && locals[leader as usize].1 == locals[loc as usize].1)
+                    .then_some(leader)
+            };
+            for (loc, version) in &info.reads {
+                match sccp.value(*version) {
+                    Lattice::Const(cst) if cst.get_type() == asm[locals[*loc as usize].1] => {
+                        rewrite.reads.push((*loc, cst.into()));
+                    }
+                    _ => {
+                        if let Some(leader) = leader(value_numbers[version.0 as usize], *loc) {
+                            rewrite.reads.push((*loc, CILNode::LdLoc(leader)));
+                        }
+                    }
+                }
+            }
+            let Some(def) = info.def else {
+                return;
+            };
+            let CILRoot::StLoc(loc, tree) = asm.get_root(blocks[block].roots()[root_idx]) else {
+                unreachable!("Only `StLoc` defines a version.")
+            };
+            let pure = is_pure(*tree, ssa, asm);
+            let number = match asm.get_node(*tree) {
+                // A copy has the same value as its source.
+                CILNode::LdLoc(src) => info
+                    .reads
+                    .iter()
+                    .find(|(read, _)| read == src)
+                    .map(|(_, version)| value_numbers[version.0 as usize]),
+                _ if pure => {
+                    let operands = info
+                        .reads
+                        .iter()
+                        .map(|(_, version)| value_numbers[version.0 as usize])
+                        .collect();
+                    Some(*expressions.entry((*tree, operands)).or_insert_with(|| {
+                        next_number += 1;
+                        next_number - 1
+                    }))
+                }
+                _ => None,
+            };
+            if let Some(number) = number {
+                value_numbers[def.0 as usize] = number;
+            }
+            let number = value_numbers[def.0 as usize];
+            match sccp.value(def) {
+                Lattice::Const(cst)
+                    if cst.get_type() == asm[locals[*loc as usize].1]
+                        && !matches!(asm.get_node(*tree), CILNode::Const(_))
+                        && !cache.has_side_effects(*tree, asm) =>
+                {
+                    rewrite.value = Some(cst.into());
+                }
+                // Only replace computations, since replacing a constant or a load with a load gains nothing.
+                _ if pure && is_computation(asm.get_node(*tree)) => {
+                    if let Some(leader) = leader(number, *loc) {
+                        rewrite.value = Some(CILNode::LdLoc(leader));
+                    }
+                }
+                _ => (),
+            }
+            holders.entry(number).or_default().push(def);
+        },
+    );
+    rewrites
+}
+/// Checks if `node` always evaluates to the same value when the versions it reads are the same.
+/// Reads from memory are not pure, since the memory may change in between.
+fn is_pure(node: NodeIdx, ssa: &Ssa, asm: &Assembly) -> bool {
+    match asm.get_node(node) {
+        CILNode::Const(_)
+        | CILNode::SizeOf(_)
+        | CILNode::LdTypeToken(_)
+        | CILNode::LdFtn(_)
+        | CILNode::LdStaticFieldAdress(_)
+        | CILNode::LdLocA(_) => true,
+        CILNode::LdLoc(loc) => ssa.tracked[*loc as usize],
+        CILNode::LdArg(arg) => !ssa.mutable_args.contains(arg),
+        CILNode::BinOp(lhs, rhs, _) => is_pure(*lhs, ssa, asm) && is_pure(*rhs, ssa, asm),
+        CILNode::UnOp(input, _)
+        | CILNode::IntCast { input, .. }
+        | CILNode::FloatCast { input, .. }
+        | CILNode::RefToPtr(input)
+        | CILNode::PtrCast(input, _) => is_pure(*input, ssa, asm),
+        _ => false,
+    }
+}
+fn is_computation(node: &CILNode) -> bool {
+    matches!(
+        node,
+        CILNode::BinOp(..)
+            | CILNode::UnOp(..)
+            | CILNode::IntCast { .. }
+            | CILNode::FloatCast { .. }
+            | CILNode::PtrCast(..)
+            | CILNode::RefToPtr(_)
+    )
+}
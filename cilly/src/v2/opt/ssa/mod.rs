@@ -0,0 +1,562 @@
+//! SSA form of method bodies, and the optimizations built on top of it.
+//!
+//! The SSA form is an overlay over the tree IR: each write to a local whose address is never taken defines a new [`Version`] of that local,
+//! each read of such a local is mapped to the version reaching it, and phi functions are kept on the side.
+//! The passes use it to prove facts about the method, and then rewrite the original roots. Since a read of a local is only ever replaced
+//! with a constant, or with a read of another local holding the very same version at that point, leaving SSA form is just dropping the overlay:
+//! no copies need to be inserted, and the number of locals does not grow.
+use fxhash::{FxHashMap, FxHashSet};
+
+use super::{OptFuel, SideEffectInfoCache};
+use crate::{
+    config,
+    v2::{Assembly, BasicBlock, CILIter, CILIterElem, CILNode, CILRoot, MethodImpl},
+};
+mod dom;
+mod gvn;
+mod sccp;
+pub use dom::{Cfg, DomTree};
+use sccp::{CondValue, Sccp, SwitchTarget};
+config!(
+    SSA_OPT,
+    bool,
+    false,
+    "Turns on the SSA-based optimizations: constant propagation, value numbering and dead block removal."
+);
+/// Methods with more blocks than this are not converted to SSA form, to keep the compile times in check.
+const MAX_SSA_BLOCKS: usize = 4096;
+/// A single, immutable value of a local.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Version(u32);
+/// Describes where a [`Version`] gets its value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VersionDef {
+    /// The value of the local on method entry.
+    Entry,
+    /// A phi function at the start of the block with this index.
+    Phi(usize),
+    /// A root, described by the index of its block and its index within that block.
+    Root(usize, usize),
+}
+/// A phi function, merging the versions of a local coming from each predecessor of a block.
+#[derive(Debug)]
+pub struct Phi {
+    local: u32,
+    version: Version,
+    /// The predecessor block and the version of the local at its end.
+    args: Vec<(usize, Version)>,
+}
+/// The versions a root reads, and the version it defines.
+#[derive(Default, Debug)]
+pub struct RootInfo {
+    reads: Vec<(u32, Version)>,
+    def: Option<Version>,
+}
+/// The SSA form of a method body.
+pub struct Ssa {
+    dom: DomTree,
+    /// Locals which never have their address taken, and are tracked by this SSA form.
+    tracked: Vec<bool>,
+    /// Arguments which are written to, or have their address taken.
+    mutable_args: FxHashSet<u32>,
+    /// The local each version belongs to, and its definition.
+    versions: Vec<(u32, VersionDef)>,
+    phis: Vec<Vec<Phi>>,
+    roots: Vec<Vec<RootInfo>>,
+}
+impl Ssa {
+    /// Converts a method body to SSA form. Returns `None` if the method uses exception handlers or unsupported control flow.
+    pub fn new(blocks: &[BasicBlock], local_count: usize, asm: &Assembly) -> Option<Self> {
+        let cfg = Cfg::new(blocks, asm)?;
+        let dom = DomTree::new(&cfg);
+        let mut tracked = vec![true; local_count];
+        let mut mutable_args = FxHashSet::default();
+        let mut def_blocks: Vec<Vec<usize>> = vec![Vec::new(); local_count];
+        for (block_idx, block) in blocks.iter().enumerate() {
+            for root in block.roots() {
+                for node in CILIter::new(asm.get_root(*root).clone(), asm) {
+                    match node {
+                        // Locals and arguments whose address is taken could be written trough a pointer.
+                        CILIterElem::Node(CILNode::LdLocA(loc)) => tracked[loc as usize] = false,
+                        CILIterElem::Node(CILNode::LdArgA(arg))
+                        | CILIterElem::Root(CILRoot::StArg(arg, _)) => {
+                            mutable_args.insert(arg);
+                        }
+                        CILIterElem::Root(CILRoot::StLoc(loc, _))
+                            if def_blocks[loc as usize].last() != Some(&block_idx) =>
+                        {
+                            def_blocks[loc as usize].push(block_idx);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+        // Each local has a version holding its initial value.
+        let mut versions: Vec<_> = (0..local_count)
+            .map(|loc| (loc as u32, VersionDef::Entry))
+            .collect();
+        // Place phi functions on the iterated dominance frontier of the blocks writing to each local.
+        let frontiers = dom.frontiers(&cfg);
+        let mut phis: Vec<Vec<Phi>> = (0..blocks.len()).map(|_| Vec::new()).collect();
+        let mut has_phi = vec![usize::MAX; blocks.len()];
+        for (loc, defs) in def_blocks.iter().enumerate() {
+            if !tracked[loc] {
+                continue;
+            }
+            let mut worklist: Vec<usize> = defs
+                .iter()
+                .copied()
+                .filter(|block| dom.is_reachable(*block))
+                .collect();
+            while let Some(block) = worklist.pop() {
+                for frontier in &frontiers[block] {
+                    if has_phi[*frontier] == loc {
+                        continue;
+                    }
+                    has_phi[*frontier] = loc;
+                    let version = Version(versions.len() as u32);
+                    versions.push((loc as u32, VersionDef::Phi(*frontier)));
+                    phis[*frontier].push(Phi {
+                        local: loc as u32,
+                        version,
+                        args: Vec::new(),
+                    });
+                    worklist.push(*frontier);
+                }
+            }
+        }
+        // Rename the locals, walking the dominator tree.
+        let mut roots: Vec<Vec<RootInfo>> = blocks
+            .iter()
+            .map(|block| block.roots().iter().map(|_| RootInfo::default()).collect())
+            .collect();
+        let mut current: Vec<Version> = (0..local_count as u32).map(Version).collect();
+        let mut undo: Vec<(u32, Version)> = Vec::new();
+        let mut stack = Vec::new();
+        if !blocks.is_empty() {
+            stack.push(Walk::Enter(0));
+        }
+        while let Some(walk) = stack.pop() {
+            let block = match walk {
+                Walk::Enter(block) => block,
+                Walk::Exit(undo_len) => {
+                    while undo.len() > undo_len {
+                        let (loc, version) = undo.pop().unwrap();
+                        current[loc as usize] = version;
+                    }
+                    continue;
+                }
+            };
+            stack.push(Walk::Exit(undo.len()));
+            for phi in &phis[block] {
+                undo.push((phi.local, current[phi.local as usize]));
+                current[phi.local as usize] = phi.version;
+            }
+            for (root_idx, root) in blocks[block].roots().iter().enumerate() {
+                let root = asm.get_root(*root);
+                let info = &mut roots[block][root_idx];
+                for node in CILIter::new(root.clone(), asm) {
+                    let CILIterElem::Node(CILNode::LdLoc(loc)) = node else {
+                        continue;
+                    };
+                    if tracked[loc as usize] && !info.reads.iter().any(|(read, _)| *read == loc) {
+                        info.reads.push((loc, current[loc as usize]));
+                    }
+                }
+                if let CILRoot::StLoc(loc, _) = root {
+                    if tracked[*loc as usize] {
+                        let version = Version(versions.len() as u32);
+                        versions.push((*loc, VersionDef::Root(block, root_idx)));
+                        info.def = Some(version);
+                        undo.push((*loc, current[*loc as usize]));
+                        current[*loc as usize] = version;
+                    }
+                }
+            }
+            for succ in cfg.succs(block) {
+                for phi in &mut phis[*succ] {
+                    phi.args.push((block, current[phi.local as usize]));
+                }
+            }
+            stack.extend(
+                dom.children(block)
+                    .iter()
+                    .rev()
+                    .map(|child| Walk::Enter(*child)),
+            );
+        }
+        Some(Self {
+            dom,
+            tracked,
+            mutable_args,
+            versions,
+            phis,
+            roots,
+        })
+    }
+    /// Walks the reachable blocks for which `filter` returns true in dominator tree preorder, calling `visit` for each root with the version of each local reaching it.
+    /// If `filter` returns false, the blocks dominated by that block are skipped too.
+    fn walk(
+        &self,
+        mut filter: impl FnMut(usize) -> bool,
+        mut visit: impl FnMut(usize, usize, &[Version]),
+    ) {
+        let mut current: Vec<Version> = (0..self.tracked.len() as u32).map(Version).collect();
+        let mut undo: Vec<(u32, Version)> = Vec::new();
+        let mut stack = Vec::new();
+        if !self.roots.is_empty() {
+            stack.push(Walk::Enter(0));
+        }
+        while let Some(walk) = stack.pop() {
+            let block = match walk {
+                Walk::Enter(block) => block,
+                Walk::Exit(undo_len) => {
+                    while undo.len() > undo_len {
+                        let (loc, version) = undo.pop().unwrap();
+                        current[loc as usize] = version;
+                    }
+                    continue;
+                }
+            };
+            if !filter(block) {
+                continue;
+            }
+            stack.push(Walk::Exit(undo.len()));
+            for phi in &self.phis[block] {
+                undo.push((phi.local, current[phi.local as usize]));
+                current[phi.local as usize] = phi.version;
+            }
+            for (root_idx, info) in self.roots[block].iter().enumerate() {
+                visit(block, root_idx, &current);
+                if let Some(def) = info.def {
+                    let loc = self.versions[def.0 as usize].0;
+                    undo.push((loc, current[loc as usize]));
+                    current[loc as usize] = def;
+                }
+            }
+            stack.extend(
+                self.dom
+                    .children(block)
+                    .iter()
+                    .rev()
+                    .map(|child| Walk::Enter(*child)),
+            );
+        }
+    }
+    /// The local `version` belongs to, and its definition.
+    pub fn version(&self, version: Version) -> (u32, VersionDef) {
+        self.versions[version.0 as usize]
+    }
+    /// Information about the root with index `root` in the block with index `block`.
+    pub fn root(&self, block: usize, root: usize) -> &RootInfo {
+        &self.roots[block][root]
+    }
+}
+enum Walk {
+    Enter(usize),
+    Exit(usize),
+}
+impl MethodImpl {
+    /// Converts this method to SSA form, and optimizes it using sparse conditional constant propagation, global value numbering, and dead block elimination.
+    pub fn ssa_opt(
+        &mut self,
+        asm: &mut Assembly,
+        cache: &mut SideEffectInfoCache,
+        fuel: &mut OptFuel,
+    ) {
+        let MethodImpl::MethodBody { blocks, locals } = self else {
+            return;
+        };
+        if blocks.len() > MAX_SSA_BLOCKS {
+            return;
+        }
+        let Some(ssa) = Ssa::new(blocks, locals.len(), asm) else {
+            return;
+        };
+        let sccp = Sccp::new(&ssa, blocks, asm);
+        // If a branch in an executable block still depends on an undefined value, the analysis can't be trusted.
+        for (block_idx, block) in blocks.iter().enumerate() {
+            if !sccp.is_executable(block_idx) {
+                continue;
+            }
+            for (root_idx, root) in block.roots().iter().enumerate() {
+                match asm.get_root(*root) {
+                    CILRoot::Branch(info) => {
+                        if let Some(cond) = &info.2 {
+                            if sccp.eval_cond(cond, ssa.root(block_idx, root_idx), asm)
+                                == CondValue::Undefined
+                            {
+                                return;
+                            }
+                        }
+                    }
+                    CILRoot::Switch(switch) => {
+                        let (discr, table) = switch.as_ref();
+                        if sccp.eval_switch(*discr, table, ssa.root(block_idx, root_idx), asm)
+                            == SwitchTarget::Undefined
+                        {
+                            return;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let rewrites = gvn::rewrites(&ssa, &sccp, blocks, locals, asm, cache);
+        for (block_idx, block) in blocks.iter_mut().enumerate() {
+            if !sccp.is_executable(block_idx) {
+                continue;
+            }
+            let mut roots = Vec::with_capacity(block.roots().len());
+            for (root_idx, root) in block.roots().iter().enumerate() {
+                let info = ssa.root(block_idx, root_idx);
+                let rewrite = &rewrites[block_idx][root_idx];
+                if let CILRoot::Branch(branch) = asm.get_root(*root) {
+                    let (target, sub_target, cond) = branch.as_ref();
+                    if let Some(cond) = cond {
+                        let has_side_effects = cond
+                            .nodes()
+                            .iter()
+                            .any(|node| cache.has_side_effects(*node, asm));
+                        match sccp.eval_cond(cond, info, asm) {
+                            CondValue::Known(true) if !has_side_effects && fuel.consume(1) => {
+                                // Nothing after an unconditional jump ever executes.
+                                roots.push(asm.alloc_root(CILRoot::Branch(Box::new((
+                                    *target,
+                                    *sub_target,
+                                    None,
+                                )))));
+                                break;
+                            }
+                            CondValue::Known(false) if !has_side_effects && fuel.consume(1) => {
+                                continue;
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                if let CILRoot::Switch(switch) = asm.get_root(*root) {
+                    let (discr, table) = switch.as_ref();
+                    if let SwitchTarget::Known((target, sub_target)) =
+                        sccp.eval_switch(*discr, table, info, asm)
+                    {
+                        if !cache.has_side_effects(*discr, asm) && fuel.consume(1) {
+                            roots.push(
+                                asm.alloc_root(CILRoot::Branch(Box::new((
+                                    target, sub_target, None,
+                                )))),
+                            );
+                            break;
+                        }
+                    }
+                }
+                if rewrite.is_empty() || !fuel.consume(1) {
+                    roots.push(*root);
+                    continue;
+                }
+                let root = match (asm.get_root(*root).clone(), &rewrite.value) {
+                    (CILRoot::StLoc(loc, _), Some(value)) => {
+                        CILRoot::StLoc(loc, asm.alloc_node(value.clone()))
+                    }
+                    (root, _) => root.map(asm, &mut |root, _| root, &mut |node, _| match node {
+                        CILNode::LdLoc(loc) => rewrite
+                            .reads
+                            .iter()
+                            .find(|(read, _)| *read == loc)
+                            .map_or(node, |(_, replacement)| replacement.clone()),
+                        _ => node,
+                    }),
+                };
+                roots.push(asm.alloc_root(root));
+            }
+            *block.roots_mut() = roots;
+        }
+        remove_dead_blocks(blocks, asm, fuel);
+    }
+}
+/// Removes the blocks which are not reachable from the entry block.
+fn remove_dead_blocks(blocks: &mut Vec<BasicBlock>, asm: &Assembly, fuel: &mut OptFuel) {
+    let ids: FxHashMap<_, _> = blocks
+        .iter()
+        .enumerate()
+        .map(|(idx, block)| (block.block_id(), idx))
+        .collect();
+    let mut reachable = vec![false; blocks.len()];
+    let mut worklist = Vec::new();
+    if !blocks.is_empty() {
+        reachable[0] = true;
+        worklist.push(0);
+    }
+    while let Some(block) = worklist.pop() {
+        for target in blocks[block].targets(asm) {
+            let target = ids[&target];
+            if !reachable[target] {
+                reachable[target] = true;
+                worklist.push(target);
+            }
+        }
+    }
+    let mut reachable = reachable.into_iter();
+    blocks.retain(|_| reachable.next().unwrap() || !fuel.consume(1));
+}
+#[cfg(test)]
+fn test_body(asm: &mut Assembly, blocks: Vec<Vec<CILRoot>>, local_count: usize) -> MethodImpl {
+    use crate::v2::{Int, Type};
+    let i32_idx = asm.alloc_type(Type::Int(Int::I32));
+    let blocks = blocks
+        .into_iter()
+        .enumerate()
+        .map(|(id, roots)| {
+            let roots = roots.into_iter().map(|root| asm.alloc_root(root)).collect();
+            BasicBlock::new(roots, id as u32, None)
+        })
+        .collect();
+    MethodImpl::MethodBody {
+        blocks,
+        locals: vec![(None, i32_idx); local_count],
+    }
+}
+#[cfg(test)]
+fn test_roots(method: &MethodImpl, asm: &Assembly) -> Vec<Vec<CILRoot>> {
+    let MethodImpl::MethodBody { blocks, .. } = method else {
+        panic!()
+    };
+    blocks
+        .iter()
+        .map(|block| {
+            block
+                .roots()
+                .iter()
+                .map(|root| asm.get_root(*root).clone())
+                .collect()
+        })
+        .collect()
+}
+#[test]
+fn sccp_folds_branches() {
+    use crate::v2::{BinOp, BranchCond};
+    let mut asm = Assembly::default();
+    let five = asm.alloc_node(5_i32);
+    let one = asm.alloc_node(1_i32);
+    let zero = asm.alloc_node(0_i32);
+    let loc = asm.alloc_node(CILNode::LdLoc(0));
+    let inc = asm.alloc_node(CILNode::BinOp(loc, one, BinOp::Add));
+    let mut method = test_body(
+        &mut asm,
+        vec![
+            vec![
+                CILRoot::StLoc(0, five),
+                CILRoot::Branch(Box::new((1, 0, Some(BranchCond::Eq(loc, five))))),
+                CILRoot::Branch(Box::new((2, 0, None))),
+            ],
+            vec![CILRoot::Ret(inc)],
+            vec![CILRoot::Ret(zero)],
+        ],
+        1,
+    );
+    method.ssa_opt(
+        &mut asm,
+        &mut SideEffectInfoCache::default(),
+        &mut OptFuel::new(100),
+    );
+    let six = asm.alloc_node(CILNode::BinOp(five, one, BinOp::Add));
+    assert_eq!(
+        test_roots(&method, &asm),
+        vec![
+            vec![
+                CILRoot::StLoc(0, five),
+                CILRoot::Branch(Box::new((1, 0, None)))
+            ],
+            vec![CILRoot::Ret(six)],
+        ]
+    );
+}
+#[test]
+fn sccp_loops() {
+    use crate::v2::{cilroot::CmpKind, BinOp, BranchCond};
+    let mut asm = Assembly::default();
+    let zero = asm.alloc_node(0_i32);
+    let one = asm.alloc_node(1_i32);
+    let ten = asm.alloc_node(10_i32);
+    let loc = asm.alloc_node(CILNode::LdLoc(0));
+    let inc = asm.alloc_node(CILNode::BinOp(loc, one, BinOp::Add));
+    let body = vec![
+        vec![
+            CILRoot::StLoc(0, zero),
+            CILRoot::Branch(Box::new((1, 0, None))),
+        ],
+        vec![
+            CILRoot::StLoc(0, inc),
+            CILRoot::Branch(Box::new((
+                1,
+                0,
+                Some(BranchCond::Lt(loc, ten, CmpKind::Signed)),
+            ))),
+            CILRoot::Branch(Box::new((2, 0, None))),
+        ],
+        vec![CILRoot::Ret(loc)],
+    ];
+    // The value of the counter changes on each iteration, so nothing can be folded.
+    let mut method = test_body(&mut asm, body.clone(), 1);
+    method.ssa_opt(
+        &mut asm,
+        &mut SideEffectInfoCache::default(),
+        &mut OptFuel::new(100),
+    );
+    assert_eq!(test_roots(&method, &asm), body);
+    // Same, but the loop jumps back to the entry block.
+    let body = vec![
+        vec![
+            CILRoot::StLoc(0, inc),
+            CILRoot::Branch(Box::new((
+                0,
+                0,
+                Some(BranchCond::Lt(loc, ten, CmpKind::Signed)),
+            ))),
+            CILRoot::Branch(Box::new((1, 0, None))),
+        ],
+        vec![CILRoot::Ret(loc)],
+    ];
+    let mut method = test_body(&mut asm, body.clone(), 1);
+    method.ssa_opt(
+        &mut asm,
+        &mut SideEffectInfoCache::default(),
+        &mut OptFuel::new(100),
+    );
+    assert_eq!(test_roots(&method, &asm), body);
+}
+#[test]
+fn gvn_reuses_values() {
+    use crate::v2::BinOp;
+    let mut asm = Assembly::default();
+    let arg = asm.alloc_node(CILNode::LdArg(0));
+    let three = asm.alloc_node(3_i32);
+    let mul = asm.alloc_node(CILNode::BinOp(arg, three, BinOp::Mul));
+    let loc1 = asm.alloc_node(CILNode::LdLoc(1));
+    let loc2 = asm.alloc_node(CILNode::LdLoc(2));
+    let sum = asm.alloc_node(CILNode::BinOp(loc1, loc2, BinOp::Add));
+    let mut method = test_body(
+        &mut asm,
+        vec![vec![
+            CILRoot::StLoc(1, mul),
+            CILRoot::StLoc(2, mul),
+            CILRoot::Ret(sum),
+        ]],
+        3,
+    );
+    method.ssa_opt(
+        &mut asm,
+        &mut SideEffectInfoCache::default(),
+        &mut OptFuel::new(100),
+    );
+    let double = asm.alloc_node(CILNode::BinOp(loc1, loc1, BinOp::Add));
+    assert_eq!(
+        test_roots(&method, &asm),
+        vec![vec![
+            CILRoot::StLoc(1, mul),
+            CILRoot::StLoc(2, loc1),
+            CILRoot::Ret(double),
+        ]]
+    );
+}
@@ -0,0 +1,494 @@
+use fxhash::{FxHashMap, FxHashSet};
+
+use super::{RootInfo, Ssa, Version};
+use crate::v2::{
+    cilnode::{ExtendKind, UnOp},
+    cilroot::CmpKind,
+    Assembly, BasicBlock, BinOp, BranchCond, CILNode, CILRoot, Const, Int, NodeIdx, SwitchTable,
+};
+/// The value of a SSA version, as known by sparse conditional constant propagation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lattice {
+    /// No value was assigned to this version yet.
+    Top,
+    /// This version always has this value.
+    Const(Const),
+    /// This version may have more than one value.
+    Bottom,
+}
+impl Lattice {
+    fn meet(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Top, other) | (other, Self::Top) => other,
+            (Self::Const(lhs), Self::Const(rhs)) if lhs == rhs => self,
+            _ => Self::Bottom,
+        }
+    }
+}
+/// The result of evaluating a branch condition.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CondValue {
+    Known(bool),
+    Unknown,
+    /// The condition depends on a value, which was not computed yet.
+    Undefined,
+}
+/// The result of evaluating the target of a switch.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SwitchTarget {
+    /// The switch always jumps to this target and sub target.
+    Known((u32, u32)),
+    Unknown,
+    /// The switched-on value was not computed yet.
+    Undefined,
+}
+/// Results of sparse conditional constant propagation: the value of each version, and which blocks may ever execute.
+pub struct Sccp {
+    values: Vec<Lattice>,
+    executable: Vec<bool>,
+}
+impl Sccp {
+    /// Runs sparse conditional constant propagation, as described in "Constant Propagation with Conditional Branches" by Wegman and Zadeck.
+    pub fn new(ssa: &Ssa, blocks: &[BasicBlock], asm: &Assembly) -> Self {
+        let mut values = vec![Lattice::Top; ssa.versions.len()];
+        // Locals are zero-initialized, but we don't rely on that.
+        for (version, (_, def)) in ssa.versions.iter().enumerate() {
+            if *def == super::VersionDef::Entry {
+                values[version] = Lattice::Bottom;
+            }
+        }
+        // Blocks which need to be revisited when a version changes.
+        let mut users = vec![Vec::new(); ssa.versions.len()];
+        for (block, roots) in ssa.roots.iter().enumerate() {
+            for (_, version) in roots.iter().flat_map(|root| root.reads.iter()) {
+                users[version.0 as usize].push(block);
+            }
+            for phi in &ssa.phis[block] {
+                for (_, version) in &phi.args {
+                    users[version.0 as usize].push(block);
+                }
+            }
+        }
+        let mut sccp = Self {
+            values,
+            executable: vec![false; blocks.len()],
+        };
+        if blocks.is_empty() {
+            return sccp;
+        }
+        let block_idxs: FxHashMap<_, _> = blocks
+            .iter()
+            .enumerate()
+            .map(|(idx, block)| (block.block_id(), idx))
+            .collect();
+        let mut executable_edges = FxHashSet::default();
+        let mut worklist = vec![0];
+        sccp.executable[0] = true;
+        while let Some(block) = worklist.pop() {
+            let mut changed = Vec::new();
+            for phi in &ssa.phis[block] {
+                // The entry block is also entered from outside the method, with the initial values of locals.
+                if block == 0 {
+                    if sccp.update(phi.version, Lattice::Bottom) {
+                        changed.push(phi.version);
+                    }
+                    continue;
+                }
+                let value = phi
+                    .args
+                    .iter()
+                    .filter(|(pred, _)| executable_edges.contains(&(*pred, block)))
+                    .fold(Lattice::Top, |acc, (_, version)| {
+                        acc.meet(sccp.values[version.0 as usize])
+                    });
+                if sccp.update(phi.version, value) {
+                    changed.push(phi.version);
+                }
+            }
+            for (root, info) in blocks[block].roots().iter().zip(&ssa.roots[block]) {
+                match asm.get_root(*root) {
+                    CILRoot::StLoc(_, tree) => {
+                        let Some(def) = info.def else {
+                            continue;
+                        };
+                        let value = sccp.eval(*tree, info, asm);
+                        if sccp.update(def, value) {
+                            changed.push(def);
+                        }
+                    }
+                    CILRoot::Branch(branch) => {
+                        let (target, _, cond) = branch.as_ref();
+                        let taken = match cond {
+                            None => CondValue::Known(true),
+                            Some(cond) => sccp.eval_cond(cond, info, asm),
+                        };
+                        match taken {
+                            CondValue::Known(false) => continue,
+                            // Optimistically assume nothing after this branch executes, untill the condition is known.
+                            CondValue::Undefined => break,
+                            CondValue::Known(true) | CondValue::Unknown => {
+                                let target = block_idxs[target];
+                                if executable_edges.insert((block, target)) {
+                                    sccp.executable[target] = true;
+                                    worklist.push(target);
+                                }
+                                if taken == CondValue::Known(true) {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    CILRoot::Switch(switch) => {
+                        let (discr, table) = switch.as_ref();
+                        let targets = match sccp.eval_switch(*discr, table, info, asm) {
+                            SwitchTarget::Known(target) => vec![target],
+                            SwitchTarget::Unknown => table.targets().to_vec(),
+                            SwitchTarget::Undefined => break,
+                        };
+                        for (target, _) in targets {
+                            let target = block_idxs[&target];
+                            if executable_edges.insert((block, target)) {
+                                sccp.executable[target] = true;
+                                worklist.push(target);
+                            }
+                        }
+                        break;
+                    }
+                    CILRoot::Ret(_)
+                    | CILRoot::VoidRet
+                    | CILRoot::TailCall(_)
+                    | CILRoot::Throw(_)
+                    | CILRoot::ReThrow
+                    | CILRoot::Unreachable(_) => break,
+                    _ => (),
+                }
+            }
+            for version in changed {
+                worklist.extend(
+                    users[version.0 as usize]
+                        .iter()
+                        .filter(|block| sccp.executable[**block]),
+                );
+            }
+        }
+        sccp
+    }
+    fn update(&mut self, version: Version, value: Lattice) -> bool {
+        let old = self.values[version.0 as usize];
+        let new = old.meet(value);
+        self.values[version.0 as usize] = new;
+        old != new
+    }
+    /// The value of `version`.
+    pub fn value(&self, version: Version) -> Lattice {
+        self.values[version.0 as usize]
+    }
+    /// Checks if the block with index `block` may ever execute.
+    pub fn is_executable(&self, block: usize) -> bool {
+        self.executable[block]
+    }
+    /// Evaluates `node`, using the versions read by the root `info` describes.
+    pub fn eval(&self, node: NodeIdx, info: &RootInfo, asm: &Assembly) -> Lattice {
+        match asm.get_node(node) {
+            CILNode::Const(cst) => Lattice::Const(*cst.as_ref()),
+            CILNode::LdLoc(loc) => info
+                .reads
+                .iter()
+                .find(|(read, _)| read == loc)
+                .map_or(Lattice::Bottom, |(_, version)| self.value(*version)),
+            CILNode::BinOp(lhs, rhs, op) => {
+                match (self.eval(*lhs, info, asm), self.eval(*rhs, info, asm)) {
+                    (Lattice::Const(lhs), Lattice::Const(rhs)) => {
+                        fold_binop(lhs, rhs, *op).map_or(Lattice::Bottom, Lattice::Const)
+                    }
+                    (Lattice::Bottom, _) | (_, Lattice::Bottom) => Lattice::Bottom,
+                    _ => Lattice::Top,
+                }
+            }
+            CILNode::UnOp(input, op) => match self.eval(*input, info, asm) {
+                Lattice::Const(input) => {
+                    fold_unop(input, op).map_or(Lattice::Bottom, Lattice::Const)
+                }
+                other => other,
+            },
+            CILNode::IntCast {
+                input,
+                target,
+                extend,
+            } => match self.eval(*input, info, asm) {
+                Lattice::Const(input) => {
+                    fold_cast(input, *target, *extend).map_or(Lattice::Bottom, Lattice::Const)
+                }
+                other => other,
+            },
+            _ => Lattice::Bottom,
+        }
+    }
+    /// Evaluates a branch condition, using the versions read by the root `info` describes.
+    pub fn eval_cond(&self, cond: &BranchCond, info: &RootInfo, asm: &Assembly) -> CondValue {
+        let (lhs, rhs, op) = match cond {
+            BranchCond::True(val) | BranchCond::False(val) => {
+                let expected = matches!(cond, BranchCond::True(_));
+                return match self.eval(*val, info, asm) {
+                    Lattice::Const(cst) => match truthiness(cst) {
+                        Some(val) => CondValue::Known(val == expected),
+                        None => CondValue::Unknown,
+                    },
+                    Lattice::Bottom => CondValue::Unknown,
+                    Lattice::Top => CondValue::Undefined,
+                };
+            }
+            BranchCond::Eq(lhs, rhs) => (lhs, rhs, CmpOp::Eq),
+            BranchCond::Ne(lhs, rhs) => (lhs, rhs, CmpOp::Ne),
+            BranchCond::Lt(lhs, rhs, kind) => (lhs, rhs, CmpOp::Lt(is_signed(kind))),
+            BranchCond::Gt(lhs, rhs, kind) => (lhs, rhs, CmpOp::Gt(is_signed(kind))),
+            BranchCond::Le(lhs, rhs, kind) => (lhs, rhs, CmpOp::Le(is_signed(kind))),
+            BranchCond::Ge(lhs, rhs, kind) => (lhs, rhs, CmpOp::Ge(is_signed(kind))),
+        };
+        match (self.eval(*lhs, info, asm), self.eval(*rhs, info, asm)) {
+            (Lattice::Const(lhs), Lattice::Const(rhs)) => match compare(lhs, rhs, op) {
+                Some(val) => CondValue::Known(val),
+                None => CondValue::Unknown,
+            },
+            (Lattice::Bottom, _) | (_, Lattice::Bottom) => CondValue::Unknown,
+            _ => CondValue::Undefined,
+        }
+    }
+    /// Evaluates the target of a switch over `discr`, using the versions read by the root `info` describes.
+    pub fn eval_switch(
+        &self,
+        discr: NodeIdx,
+        table: &SwitchTable,
+        info: &RootInfo,
+        asm: &Assembly,
+    ) -> SwitchTarget {
+        match self.eval(discr, info, asm) {
+            Lattice::Const(cst) => match int_bits(cst) {
+                Some((bits, int)) if int == table.tpe() => {
+                    SwitchTarget::Known(table.target_of(bits))
+                }
+                _ => SwitchTarget::Unknown,
+            },
+            Lattice::Bottom => SwitchTarget::Unknown,
+            Lattice::Top => SwitchTarget::Undefined,
+        }
+    }
+}
+#[derive(Clone, Copy)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt(bool),
+    Gt(bool),
+    Le(bool),
+    Ge(bool),
+}
+fn is_signed(kind: &CmpKind) -> bool {
+    matches!(kind, CmpKind::Ordered | CmpKind::Signed)
+}
+/// Returns the bits of an integer constant this pass knows how to fold, and its type.
+/// Smaller integers get widened on the evaluation stack, and the size of native integers depends on the platform, so they are not folded.
+fn int_bits(cst: Const) -> Option<(u64, Int)> {
+    match cst {
+        Const::I32(val) => Some((u64::from(val as u32), Int::I32)),
+        Const::U32(val) => Some((u64::from(val), Int::U32)),
+        Const::I64(val) => Some((val as u64, Int::I64)),
+        Const::U64(val) => Some((val, Int::U64)),
+        _ => None,
+    }
+}
+fn bits_to_const(bits: u64, int: Int) -> Const {
+    match int {
+        Int::I32 => Const::I32(bits as u32 as i32),
+        Int::U32 => Const::U32(bits as u32),
+        Int::I64 => Const::I64(bits as i64),
+        Int::U64 => Const::U64(bits),
+        _ => unreachable!(),
+    }
+}
+fn width(int: Int) -> u32 {
+    match int {
+        Int::I32 | Int::U32 => 32,
+        _ => 64,
+    }
+}
+fn signed(bits: u64, int: Int) -> i64 {
+    if width(int) == 32 {
+        i64::from(bits as u32 as i32)
+    } else {
+        bits as i64
+    }
+}
+fn truncate(bits: u64, int: Int) -> u64 {
+    if width(int) == 32 {
+        bits & u64::from(u32::MAX)
+    } else {
+        bits
+    }
+}
+fn truthiness(cst: Const) -> Option<bool> {
+    match cst {
+        Const::Bool(val) => Some(val),
+        _ => int_bits(cst).map(|(bits, _)| bits != 0),
+    }
+}
+fn compare(lhs: Const, rhs: Const, op: CmpOp) -> Option<bool> {
+    if let (Const::Bool(lhs), Const::Bool(rhs)) = (lhs, rhs) {
+        return match op {
+            CmpOp::Eq => Some(lhs == rhs),
+            CmpOp::Ne => Some(lhs != rhs),
+            _ => None,
+        };
+    }
+    let (lhs, int) = int_bits(lhs)?;
+    let (rhs, rhs_int) = int_bits(rhs)?;
+    if int != rhs_int {
+        return None;
+    }
+    let ordering = |signed_cmp: bool| {
+        if signed_cmp {
+            signed(lhs, int).cmp(&signed(rhs, int))
+        } else {
+            lhs.cmp(&rhs)
+        }
+    };
+    Some(match op {
+        CmpOp::Eq => lhs == rhs,
+        CmpOp::Ne => lhs != rhs,
+        CmpOp::Lt(signed) => ordering(signed).is_lt(),
+        CmpOp::Gt(signed) => ordering(signed).is_gt(),
+        CmpOp::Le(signed) => ordering(signed).is_le(),
+        CmpOp::Ge(signed) => ordering(signed).is_ge(),
+    })
+}
+/// Folds a binary operation on constants. Returns `None` if the operation can't be folded, or if it would throw.
+fn fold_binop(lhs: Const, rhs: Const, op: BinOp) -> Option<Const> {
+    if let (Const::Bool(lhs), Const::Bool(rhs)) = (lhs, rhs) {
+        return match op {
+            BinOp::And => Some(Const::Bool(lhs & rhs)),
+            BinOp::Or => Some(Const::Bool(lhs | rhs)),
+            BinOp::XOr => Some(Const::Bool(lhs ^ rhs)),
+            BinOp::Eq => Some(Const::Bool(lhs == rhs)),
+            _ => None,
+        };
+    }
+    let (lhs, int) = int_bits(lhs)?;
+    let (rhs, rhs_int) = int_bits(rhs)?;
+    if let BinOp::Shl | BinOp::Shr | BinOp::ShrUn = op {
+        // The result of shifting by more than the width of a value is not specified.
+        if rhs >= u64::from(width(int)) {
+            return None;
+        }
+        let bits = match op {
+            BinOp::Shl => lhs << rhs,
+            BinOp::Shr => (signed(lhs, int) >> rhs) as u64,
+            _ => lhs >> rhs,
+        };
+        return Some(bits_to_const(truncate(bits, int), int));
+    }
+    if int != rhs_int {
+        return None;
+    }
+    let (signed_lhs, signed_rhs) = (signed(lhs, int), signed(rhs, int));
+    let min = if width(int) == 32 {
+        i64::from(i32::MIN)
+    } else {
+        i64::MIN
+    };
+    let bits = match op {
+        BinOp::Add => lhs.wrapping_add(rhs),
+        BinOp::Sub => lhs.wrapping_sub(rhs),
+        BinOp::Mul => lhs.wrapping_mul(rhs),
+        BinOp::And => lhs & rhs,
+        BinOp::Or => lhs | rhs,
+        BinOp::XOr => lhs ^ rhs,
+        BinOp::Eq => return Some(Const::Bool(lhs == rhs)),
+        BinOp::Lt => return Some(Const::Bool(signed_lhs < signed_rhs)),
+        BinOp::Gt => return Some(Const::Bool(signed_lhs > signed_rhs)),
+        BinOp::LtUn => return Some(Const::Bool(lhs < rhs)),
+        BinOp::GtUn => return Some(Const::Bool(lhs > rhs)),
+        // Division by zero and overflowing division throw, so they must be left as-is.
+        BinOp::Div | BinOp::Rem if rhs == 0 || (signed_rhs == -1 && signed_lhs == min) => {
+            return None
+        }
+        BinOp::Div => (signed_lhs / signed_rhs) as u64,
+        BinOp::Rem => (signed_lhs % signed_rhs) as u64,
+        BinOp::DivUn | BinOp::RemUn if rhs == 0 => return None,
+        BinOp::DivUn => lhs / rhs,
+        BinOp::RemUn => lhs % rhs,
+        BinOp::Shl | BinOp::Shr | BinOp::ShrUn => unreachable!(),
+    };
+    Some(bits_to_const(truncate(bits, int), int))
+}
+fn fold_unop(input: Const, op: &UnOp) -> Option<Const> {
+    let (bits, int) = int_bits(input)?;
+    let bits = match op {
+        UnOp::Not => !bits,
+        UnOp::Neg => 0_u64.wrapping_sub(bits),
+    };
+    Some(bits_to_const(truncate(bits, int), int))
+}
+fn fold_cast(input: Const, target: Int, extend: ExtendKind) -> Option<Const> {
+    let (bits, src) = int_bits(input)?;
+    let (Int::I32 | Int::U32 | Int::I64 | Int::U64) = target else {
+        return None;
+    };
+    let bits = if width(target) > width(src) {
+        // Only fold extensions matching the signedness of the source, so that the result does not depend on how an exporter handles mismatched ones.
+        match (src.is_signed(), extend) {
+            (true, ExtendKind::SignExtend) => signed(bits, src) as u64,
+            (false, ExtendKind::ZeroExtend) => bits,
+            _ => return None,
+        }
+    } else {
+        truncate(bits, target)
+    };
+    Some(bits_to_const(bits, target))
+}
+#[test]
+fn fold() {
+    assert_eq!(
+        fold_binop(Const::I32(i32::MAX), Const::I32(1), BinOp::Add),
+        Some(Const::I32(i32::MIN))
+    );
+    assert_eq!(
+        fold_binop(Const::I32(-7), Const::I32(2), BinOp::Div),
+        Some(Const::I32(-3))
+    );
+    assert_eq!(
+        fold_binop(Const::U32(u32::MAX), Const::U32(2), BinOp::DivUn),
+        Some(Const::U32(u32::MAX / 2))
+    );
+    assert_eq!(
+        fold_binop(Const::I64(i64::MIN), Const::I64(-1), BinOp::Div),
+        None
+    );
+    assert_eq!(fold_binop(Const::U64(1), Const::U64(0), BinOp::RemUn), None);
+    assert_eq!(
+        fold_binop(Const::I32(-1), Const::I32(1), BinOp::LtUn),
+        Some(Const::Bool(false))
+    );
+    assert_eq!(
+        fold_binop(Const::I32(-8), Const::I32(1), BinOp::Shr),
+        Some(Const::I32(-4))
+    );
+    assert_eq!(fold_binop(Const::I32(1), Const::I32(32), BinOp::Shl), None);
+    // Smaller integers are widened on the evaluation stack, so folding them is not supported.
+    assert_eq!(fold_binop(Const::U8(200), Const::U8(100), BinOp::Add), None);
+    assert_eq!(
+        fold_cast(Const::I32(-1), Int::I64, ExtendKind::SignExtend),
+        Some(Const::I64(-1))
+    );
+    assert_eq!(
+        fold_cast(Const::U64(u64::MAX), Int::I32, ExtendKind::ZeroExtend),
+        Some(Const::I32(-1))
+    );
+    assert_eq!(
+        compare(Const::I32(-1), Const::I32(0), CmpOp::Lt(true)),
+        Some(true)
+    );
+    assert_eq!(
+        compare(Const::I32(-1), Const::I32(0), CmpOp::Lt(false)),
+        Some(false)
+    );
+}
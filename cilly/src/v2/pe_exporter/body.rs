@@ -0,0 +1,1027 @@
+use fxhash::FxHashMap;
+
+use super::{
+    metadata::MetadataBuilder,
+    pdb::{MethodDebugInfo, SequencePoint},
+    tables::MetadataToken,
+};
+use crate::v2::{
+    basic_block::BlockId,
+    cilnode::{ExtendKind, MethodKind, UnOp},
+    cilroot::{BranchCond, CmpKind},
+    BinOp, CILIter, CILIterElem, CILNode, CILRoot, ClassRef, Const, Float, Int, MethodImpl,
+    NodeIdx, RootIdx, SwitchPlan, SwitchTable, Type,
+};
+
+/// A position in the method body, which can be the target of a branch.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Label {
+    /// The start of a basic block.
+    Block(BlockId),
+    /// The start of a handler block: (protected block, handler block).
+    Handler(BlockId, BlockId),
+    /// A jump out of a protected region: (protected block, target block).
+    Jump(BlockId, BlockId),
+    /// A position inside the code of a single root, such as a part of a switch.
+    Local(u32),
+}
+/// A catch clause, protecting `try_range` and jumping to `handler_range` on exception.
+struct ExceptionClause {
+    try_start: u32,
+    try_len: u32,
+    handler_start: u32,
+    handler_len: u32,
+}
+/// A node the CIL instruction set has no encoding for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BodyError {
+    /// An int cast to a 128 bit int, which is not a primitive in CIL.
+    IntCastTo128,
+    /// A float cast to a float type without a `conv` instruction.
+    FloatCastTo(Float),
+    /// A load or store trough a pointer to a managed reference.
+    IndirectRef,
+    /// `sizeof(void)`, which is UB: not all targets support ZSTs.
+    SizeOfVoid,
+}
+impl std::fmt::Display for BodyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IntCastTo128 => write!(f, "can't encode an int cast to a 128 bit int"),
+            Self::FloatCastTo(float) => write!(f, "can't encode a float cast to {float:?}"),
+            Self::IndirectRef => write!(f, "can't load or store a managed reference indirectly"),
+            Self::SizeOfVoid => write!(
+                f,
+                "attempted to calc size_of(void), use Const::I32(0) instead"
+            ),
+        }
+    }
+}
+impl std::error::Error for BodyError {}
+/// Encodes cilly method implementations as CIL bytecode(II.25.4).
+pub(super) struct BodyEncoder<'b> {
+    builder: &'b mut MetadataBuilder,
+    code: Vec<u8>,
+    labels: FxHashMap<Label, u32>,
+    /// Branch operands to patch: (operand position, position the offset is relative to, label).
+    fixups: Vec<(usize, usize, Label)>,
+    /// The amount of [`Label::Local`]s used so far.
+    local_labels: u32,
+    clauses: Vec<ExceptionClause>,
+    debug: MethodDebugInfo,
+}
+impl<'b> BodyEncoder<'b> {
+    pub(super) fn new(builder: &'b mut MetadataBuilder) -> Self {
+        Self {
+            builder,
+            code: vec![],
+            labels: FxHashMap::default(),
+            fixups: vec![],
+            local_labels: 0,
+            clauses: vec![],
+            debug: MethodDebugInfo::default(),
+        }
+    }
+    /// Encodes a method body, including its header and exception handling sections. Also returns the debug
+    /// information of the method.
+    pub(super) fn encode(
+        mut self,
+        mimpl: &MethodImpl,
+        name: &str,
+    ) -> Result<(Vec<u8>, MethodDebugInfo), BodyError> {
+        let (max_stack, locals) = match mimpl {
+            MethodImpl::MethodBody { blocks, locals } => {
+                let asm = &self.builder.asm;
+                let max_stack = blocks
+                    .iter()
+                    .flat_map(|block| block.roots().iter())
+                    .map(|root| CILIter::new(asm.get_root(*root).clone(), asm).count() + 10)
+                    .max()
+                    .unwrap_or(0);
+                self.debug.locals = locals
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(idx, (name, _))| {
+                        Some((u16::try_from(idx).ok()?, asm[(*name)?].into()))
+                    })
+                    .collect();
+                let locals: Vec<Type> = locals.iter().map(|(_, tpe)| asm[*tpe]).collect();
+                let locals = if locals.is_empty() {
+                    None
+                } else {
+                    Some(self.builder.locals_sig(&locals))
+                };
+                for block in blocks {
+                    let try_start = self.offset();
+                    self.mark(Label::Block(block.block_id()));
+                    for root in block.roots() {
+                        self.root(*root, false, block.handler().is_some())?;
+                    }
+                    let Some(handler) = block.handler() else {
+                        continue;
+                    };
+                    let try_len = self.offset() - try_start;
+                    let handler_start = self.offset();
+                    // Check for the GetException intrinsic. If it is not used, pop the exception.
+                    let asm = &self.builder.asm;
+                    if !handler
+                        .iter()
+                        .flat_map(crate::v2::BasicBlock::roots)
+                        .flat_map(|root| CILIter::new(asm.get_root(*root).clone(), asm))
+                        .any(|elem| matches!(elem, CILIterElem::Node(CILNode::GetException)))
+                    {
+                        self.op(0x26);
+                    }
+                    for hblock in handler {
+                        self.mark(Label::Handler(block.block_id(), hblock.block_id()));
+                        for root in hblock.roots() {
+                            self.root(*root, true, false)?;
+                        }
+                    }
+                    self.clauses.push(ExceptionClause {
+                        try_start,
+                        try_len,
+                        handler_start,
+                        handler_len: self.offset() - handler_start,
+                    });
+                }
+                (max_stack, locals)
+            }
+            MethodImpl::Missing => {
+                self.throw_msg(&format!("missing methiod {name}"));
+                (3, None)
+            }
+            MethodImpl::Extern { .. } => panic!("Extern methods have no body"),
+            MethodImpl::AliasFor(_) => panic!("resolved_implementation returned `AliasFor`"),
+        };
+        self.apply_fixups();
+        self.debug.locals_sig = locals;
+        self.debug.code_size = self.offset();
+        let debug = std::mem::take(&mut self.debug);
+        Ok((
+            self.finish(u16::try_from(max_stack).unwrap_or(u16::MAX), locals),
+            debug,
+        ))
+    }
+    fn finish(mut self, max_stack: u16, locals: Option<MetadataToken>) -> Vec<u8> {
+        let mut out = vec![];
+        // Fat format, header size of 3 dwords.
+        let mut flags: u16 = 0x3003;
+        if !self.clauses.is_empty() {
+            // MoreSects
+            flags |= 0x0008;
+        }
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&max_stack.to_le_bytes());
+        out.extend_from_slice(
+            &u32::try_from(self.code.len())
+                .expect("Method too big")
+                .to_le_bytes(),
+        );
+        out.extend_from_slice(&locals.map_or(0, |locals| locals.raw()).to_le_bytes());
+        out.append(&mut self.code);
+        if self.clauses.is_empty() {
+            return out;
+        }
+        // Extra sections are 4 byte aligned
+        while !out.len().is_multiple_of(4) {
+            out.push(0);
+        }
+        let data_size = u32::try_from(self.clauses.len() * 24 + 4).expect("Too many clauses");
+        // EHTable | FatFormat
+        out.push(0x41);
+        out.extend_from_slice(&data_size.to_le_bytes()[..3]);
+        let catch_type = self
+            .builder
+            .type_ref(Some(super::metadata::CORE_ASSEMBLY), "System.Object");
+        for clause in &self.clauses {
+            // COR_ILEXCEPTION_CLAUSE_EXCEPTION
+            out.extend_from_slice(&0_u32.to_le_bytes());
+            out.extend_from_slice(&clause.try_start.to_le_bytes());
+            out.extend_from_slice(&clause.try_len.to_le_bytes());
+            out.extend_from_slice(&clause.handler_start.to_le_bytes());
+            out.extend_from_slice(&clause.handler_len.to_le_bytes());
+            out.extend_from_slice(&catch_type.raw().to_le_bytes());
+        }
+        out
+    }
+    fn offset(&self) -> u32 {
+        u32::try_from(self.code.len()).expect("Method too big")
+    }
+    fn mark(&mut self, label: Label) {
+        let offset = self.offset();
+        self.labels.insert(label, offset);
+    }
+    fn apply_fixups(&mut self) {
+        for (pos, next, label) in std::mem::take(&mut self.fixups) {
+            let target = *self
+                .labels
+                .get(&label)
+                .unwrap_or_else(|| panic!("Branch to undefined label {label:?}"));
+            // Branch offsets are relative to the start of the next instruction.
+            let next = i64::try_from(next).unwrap();
+            let rel = i32::try_from(i64::from(target) - next).expect("Branch too far");
+            self.code[pos..pos + 4].copy_from_slice(&rel.to_le_bytes());
+        }
+    }
+    fn op(&mut self, op: u8) {
+        self.code.push(op);
+    }
+    fn op_fe(&mut self, op: u8) {
+        self.code.push(0xFE);
+        self.code.push(op);
+    }
+    fn op_token(&mut self, op: u8, token: MetadataToken) {
+        self.op(op);
+        self.code.extend_from_slice(&token.raw().to_le_bytes());
+    }
+    fn op_fe_token(&mut self, op: u8, token: MetadataToken) {
+        self.op_fe(op);
+        self.code.extend_from_slice(&token.raw().to_le_bytes());
+    }
+    fn branch(&mut self, op: u8, label: Label) {
+        self.op(op);
+        self.fixups
+            .push((self.code.len(), self.code.len() + 4, label));
+        self.code.extend_from_slice(&0_i32.to_le_bytes());
+    }
+    fn local_label(&mut self) -> Label {
+        self.local_labels += 1;
+        Label::Local(self.local_labels)
+    }
+    /// Emits a `switch`, jumping to the `n`th target if the int32 on top of the stack is `n`, and falling trough otherwise.
+    fn switch(&mut self, targets: &[Label]) {
+        self.op(0x45);
+        self.code
+            .extend_from_slice(&u32::try_from(targets.len()).unwrap().to_le_bytes());
+        // Switch offsets are relative to the end of the whole instruction.
+        let next = self.code.len() + 4 * targets.len();
+        for target in targets {
+            self.fixups.push((self.code.len(), next, *target));
+            self.code.extend_from_slice(&0_i32.to_le_bytes());
+        }
+    }
+    /// Returns the label of the code jumping to `target` and `sub_target`.
+    fn branch_label(target: u32, sub_target: u32, is_handler: bool, has_handler: bool) -> Label {
+        if sub_target == 0 {
+            Label::Block(target)
+        } else if is_handler {
+            Label::Handler(target, sub_target)
+        } else if has_handler {
+            Label::Jump(target, sub_target)
+        } else {
+            // If the handler was removed, we can just jump to the sub target.
+            Label::Block(sub_target)
+        }
+    }
+    /// Returns the label of a trampoline popping the switched-on value, and jumping to `target`.
+    fn trampoline(
+        &mut self,
+        trampolines: &mut Vec<((u32, u32), Label)>,
+        target: (u32, u32),
+    ) -> Label {
+        if let Some((_, label)) = trampolines.iter().find(|(other, _)| *other == target) {
+            return *label;
+        }
+        let label = self.local_label();
+        trampolines.push((target, label));
+        label
+    }
+    /// Loads `value`, a value of the int `table` switches on, in the form it has on the evaluation stack.
+    fn switch_value(&mut self, table: &SwitchTable, value: u64) {
+        match table.tpe() {
+            Int::I64 | Int::U64 => self.ldc_i8(value as i64),
+            Int::ISize | Int::USize => {
+                self.ldc_i8(value as i64);
+                // conv.i
+                self.op(0xD3);
+            }
+            // Smaller ints are extended to 32 bits on the evaluation stack.
+            _ => self.ldc_i4(table.extend(value) as i32),
+        }
+    }
+    /// Emits the comparisons of `plan`, with the switched-on value on top of the stack. Falls trough if no case matches.
+    fn switch_plan(
+        &mut self,
+        table: &SwitchTable,
+        plan: &SwitchPlan,
+        trampolines: &mut Vec<((u32, u32), Label)>,
+    ) {
+        match plan {
+            SwitchPlan::Cases(cases) => {
+                for (value, target) in cases {
+                    let target = self.trampoline(trampolines, *target);
+                    // dup
+                    self.op(0x25);
+                    self.switch_value(table, *value);
+                    // beq
+                    self.branch(0x3B, target);
+                }
+            }
+            SwitchPlan::Table { min, targets } => {
+                let targets: Vec<_> = targets
+                    .iter()
+                    .map(|target| self.trampoline(trampolines, *target))
+                    .collect();
+                self.op(0x25);
+                self.switch_value(table, *min);
+                // sub
+                self.op(0x59);
+                // `switch` only accepts 32 bit ints, so the range of bigger values must be checked first.
+                if table.tpe().size().is_none_or(|size| size > 4) {
+                    let in_range = self.local_label();
+                    let otherwise = self.trampoline(trampolines, table.otherwise());
+                    self.op(0x25);
+                    self.switch_value(table, targets.len() as u64);
+                    // blt.un
+                    self.branch(0x44, in_range);
+                    self.op(0x26);
+                    self.branch(0x38, otherwise);
+                    self.mark(in_range);
+                    // conv.u4
+                    self.op(0x6D);
+                }
+                self.switch(&targets);
+            }
+            SwitchPlan::Split { pivot, lt, ge } => {
+                let lt_label = self.local_label();
+                self.op(0x25);
+                self.switch_value(table, *pivot);
+                // blt or blt.un
+                self.branch(if table.tpe().is_signed() { 0x3F } else { 0x44 }, lt_label);
+                self.switch_plan(table, ge, trampolines);
+                let otherwise = self.trampoline(trampolines, table.otherwise());
+                self.branch(0x38, otherwise);
+                self.mark(lt_label);
+                self.switch_plan(table, lt, trampolines);
+            }
+        }
+    }
+    fn volatile(&mut self, volatile: bool) {
+        if volatile {
+            self.op_fe(0x13);
+        }
+    }
+    fn ldc_i4(&mut self, val: i32) {
+        match val {
+            -1..=8 => self.op(u8::try_from(0x16 + val).unwrap()),
+            -128..=127 => {
+                self.op(0x1F);
+                self.code.push(val as i8 as u8);
+            }
+            _ => {
+                self.op(0x20);
+                self.code.extend_from_slice(&val.to_le_bytes());
+            }
+        }
+    }
+    fn ldc_i8(&mut self, val: i64) {
+        self.op(0x21);
+        self.code.extend_from_slice(&val.to_le_bytes());
+    }
+    fn ldstr(&mut self, msg: &str) {
+        let token = self.builder.user_string(msg);
+        self.op(0x72);
+        self.code.extend_from_slice(&token.to_le_bytes());
+    }
+    /// Throws a new `System.Exception` with the message `msg`.
+    fn throw_msg(&mut self, msg: &str) {
+        self.ldstr(msg);
+        let asm = &mut self.builder.asm;
+        let exception = ClassRef::exception(asm);
+        let ctor = asm[exception].clone().ctor(&[Type::PlatformString], asm);
+        let ctor = self.builder.method_token(ctor);
+        self.op_token(0x73, ctor);
+        self.op(0x7A);
+    }
+    /// Converts the value on top of the stack into a 128 bit int, using `op_Implicit`.
+    fn conv_128(&mut self, signed: bool, input: Int) {
+        let asm = &mut self.builder.asm;
+        let (cref, output) = if signed {
+            (ClassRef::int_128(asm), Type::Int(Int::I128))
+        } else {
+            (ClassRef::uint_128(asm), Type::Int(Int::U128))
+        };
+        let name = asm.alloc_string("op_Implicit");
+        let mref = asm[cref]
+            .clone()
+            .static_mref(&[Type::Int(input)], output, name, asm);
+        let mref = self.builder.method_token(mref);
+        self.op_token(0x28, mref);
+    }
+    /// Creates a 128 bit int from its `high` and `low` halves.
+    fn new_128(&mut self, signed: bool, val: u128) {
+        self.ldc_i8((val >> 64) as u64 as i64);
+        self.ldc_i8(val as u64 as i64);
+        let asm = &mut self.builder.asm;
+        let cref = if signed {
+            ClassRef::int_128(asm)
+        } else {
+            ClassRef::uint_128(asm)
+        };
+        let ctor = asm[cref]
+            .clone()
+            .ctor(&[Type::Int(Int::U64), Type::Int(Int::U64)], asm);
+        let ctor = self.builder.method_token(ctor);
+        self.op_token(0x73, ctor);
+    }
+    fn constant(&mut self, cst: &Const) {
+        match cst {
+            Const::Null(_) => self.op(0x14),
+            Const::I8(val) => self.ldc_i4(i32::from(*val)),
+            Const::I16(val) => self.ldc_i4(i32::from(*val)),
+            Const::I32(val) => self.ldc_i4(*val),
+            Const::U8(val) => self.ldc_i4(i32::from(*val)),
+            Const::U16(val) => self.ldc_i4(i32::from(*val)),
+            Const::U32(val) => self.ldc_i4(*val as i32),
+            Const::Bool(val) => self.ldc_i4(i32::from(*val)),
+            Const::I64(val) => match i32::try_from(*val) {
+                Ok(val) => {
+                    self.ldc_i4(val);
+                    // conv.i8
+                    self.op(0x6A);
+                }
+                Err(_) => self.ldc_i8(*val),
+            },
+            Const::ISize(val) => {
+                match i32::try_from(*val) {
+                    Ok(val) => self.ldc_i4(val),
+                    Err(_) => self.ldc_i8(*val),
+                }
+                // conv.i
+                self.op(0xD3);
+            }
+            Const::U64(val) => match u32::try_from(*val) {
+                Ok(val) => {
+                    self.ldc_i4(val as i32);
+                    // conv.u8
+                    self.op(0x6E);
+                }
+                Err(_) => self.ldc_i8(*val as i64),
+            },
+            Const::USize(val) => {
+                match i32::try_from(*val) {
+                    Ok(val) => self.ldc_i4(val),
+                    Err(_) => self.ldc_i8(*val as i64),
+                }
+                // conv.u
+                self.op(0xE0);
+            }
+            Const::I128(val) => {
+                if let Ok(val) = i32::try_from(*val) {
+                    self.ldc_i4(val);
+                    self.conv_128(true, Int::I32);
+                } else if let Ok(val) = i64::try_from(*val) {
+                    self.ldc_i8(val);
+                    self.conv_128(true, Int::I64);
+                } else {
+                    self.new_128(true, *val as u128);
+                }
+            }
+            Const::U128(val) => {
+                if let Ok(val) = u32::try_from(*val) {
+                    self.ldc_i4(val as i32);
+                    self.conv_128(false, Int::U32);
+                } else if let Ok(val) = u64::try_from(*val) {
+                    self.ldc_i8(val as i64);
+                    self.conv_128(false, Int::U64);
+                } else {
+                    self.new_128(false, *val);
+                }
+            }
+            Const::PlatformString(msg) => {
+                let msg = self.builder.asm[*msg].to_owned();
+                self.ldstr(&msg);
+            }
+            Const::F32(float) => {
+                self.op(0x22);
+                self.code.extend_from_slice(&float.0.to_le_bytes());
+            }
+            Const::F64(float) => {
+                self.op(0x23);
+                self.code.extend_from_slice(&float.0.to_le_bytes());
+            }
+        }
+    }
+    fn call(
+        &mut self,
+        mref: crate::v2::MethodRefIdx,
+        args: &[NodeIdx],
+        is_root: bool,
+    ) -> Result<(), BodyError> {
+        for arg in args {
+            self.node(*arg)?;
+        }
+        self.call_op(mref, is_root);
+        Ok(())
+    }
+    fn call_op(&mut self, mref: crate::v2::MethodRefIdx, is_root: bool) {
+        let kind = self.builder.asm[mref].kind();
+        let token = self.builder.method_token(mref);
+        match kind {
+            MethodKind::Static | MethodKind::Instance => self.op_token(0x28, token),
+            MethodKind::Virtual => self.op_token(0x6F, token),
+            MethodKind::Constructor => {
+                assert!(!is_root, "A constructor can't be a CIL root");
+                self.op_token(0x73, token);
+            }
+        }
+    }
+    fn calli(
+        &mut self,
+        fn_ptr: NodeIdx,
+        sig: crate::v2::SigIdx,
+        args: &[NodeIdx],
+    ) -> Result<(), BodyError> {
+        for arg in args {
+            self.node(*arg)?;
+        }
+        self.node(fn_ptr)?;
+        self.calli_op(sig);
+        Ok(())
+    }
+    fn calli_op(&mut self, sig: crate::v2::SigIdx) {
+        let sig = self.builder.asm[sig].clone();
+        let sig = self.builder.calli_sig(&sig);
+        self.op_token(0x29, sig);
+    }
+    /// Emits the call `call` prefixed with `tail.`, followed by a `ret`.
+    fn tail_call(&mut self, call: NodeIdx) -> Result<(), BodyError> {
+        match self.builder.asm.get_node(call).clone() {
+            CILNode::Call(call) => {
+                for arg in call.1.iter() {
+                    self.node(*arg)?;
+                }
+                self.op_fe(0x14);
+                self.call_op(call.0, true);
+            }
+            CILNode::CallI(calli) => {
+                let (fn_ptr, sig, args) = calli.as_ref();
+                for arg in args {
+                    self.node(*arg)?;
+                }
+                self.node(*fn_ptr)?;
+                self.op_fe(0x14);
+                self.calli_op(*sig);
+            }
+            node => panic!("ERROR: can't tail call {node:?}, which is not a call."),
+        }
+        self.op(0x2A);
+        Ok(())
+    }
+    fn node(&mut self, node: NodeIdx) -> Result<(), BodyError> {
+        let node = self.builder.asm.get_node(node).clone();
+        match node {
+            CILNode::Const(cst) => self.constant(&cst),
+            CILNode::BinOp(lhs, rhs, op) => {
+                self.node(lhs)?;
+                self.node(rhs)?;
+                match op {
+                    BinOp::Add => self.op(0x58),
+                    BinOp::Eq => self.op_fe(0x01),
+                    BinOp::Sub => self.op(0x59),
+                    BinOp::Mul => self.op(0x5A),
+                    BinOp::LtUn => self.op_fe(0x05),
+                    BinOp::Lt => self.op_fe(0x04),
+                    BinOp::GtUn => self.op_fe(0x03),
+                    BinOp::Gt => self.op_fe(0x02),
+                    BinOp::Or => self.op(0x60),
+                    BinOp::XOr => self.op(0x61),
+                    BinOp::And => self.op(0x5F),
+                    BinOp::Rem => self.op(0x5D),
+                    BinOp::RemUn => self.op(0x5E),
+                    BinOp::Shl => self.op(0x62),
+                    BinOp::Shr => self.op(0x63),
+                    BinOp::ShrUn => self.op(0x64),
+                    BinOp::DivUn => self.op(0x5C),
+                    BinOp::Div => self.op(0x5B),
+                }
+            }
+            CILNode::UnOp(arg, un) => {
+                self.node(arg)?;
+                match un {
+                    UnOp::Not => self.op(0x66),
+                    UnOp::Neg => self.op(0x65),
+                }
+            }
+            CILNode::LdLoc(loc) => self.var_op(loc, Some(0x06), 0x11, 0x0C),
+            CILNode::LdLocA(loc) => self.var_op(loc, None, 0x12, 0x0D),
+            CILNode::LdArg(arg) => self.var_op(arg, Some(0x02), 0x0E, 0x09),
+            CILNode::LdArgA(arg) => self.var_op(arg, None, 0x0F, 0x0A),
+            CILNode::Call(call) => self.call(call.0, &call.1, false)?,
+            CILNode::IntCast {
+                input,
+                target,
+                extend,
+            } => {
+                self.node(input)?;
+                match (target, extend) {
+                    (Int::U8 | Int::I8, ExtendKind::ZeroExtend) => self.op(0xD2),
+                    (Int::U8 | Int::I8, ExtendKind::SignExtend) => self.op(0x67),
+                    (Int::U16 | Int::I16, ExtendKind::ZeroExtend) => self.op(0xD1),
+                    (Int::U16 | Int::I16, ExtendKind::SignExtend) => self.op(0x68),
+                    (Int::U32 | Int::I32, ExtendKind::ZeroExtend) => self.op(0x6D),
+                    (Int::U32 | Int::I32, ExtendKind::SignExtend) => self.op(0x69),
+                    (Int::U64 | Int::I64, ExtendKind::ZeroExtend) => self.op(0x6E),
+                    (Int::U64 | Int::I64, ExtendKind::SignExtend) => self.op(0x6A),
+                    (Int::USize | Int::ISize, ExtendKind::SignExtend) => self.op(0xD3),
+                    (Int::USize | Int::ISize, ExtendKind::ZeroExtend) => self.op(0xE0),
+                    (Int::U128 | Int::I128, _) => return Err(BodyError::IntCastTo128),
+                }
+            }
+            CILNode::FloatCast {
+                input,
+                target,
+                is_signed,
+            } => {
+                self.node(input)?;
+                if !is_signed {
+                    // conv.r.un
+                    self.op(0x76);
+                }
+                match target {
+                    Float::F32 => self.op(0x6B),
+                    Float::F64 => self.op(0x6C),
+                    Float::F16 | Float::F128 => return Err(BodyError::FloatCastTo(target)),
+                }
+            }
+            CILNode::RefToPtr(inner) => {
+                self.node(inner)?;
+                self.op(0xE0);
+            }
+            CILNode::PtrCast(val, _) => self.node(val)?,
+            CILNode::LdFieldAdress { addr, field } => {
+                self.node(addr)?;
+                let field = self.builder.field_token(field);
+                self.op_token(0x7C, field);
+            }
+            CILNode::LdField { addr, field } => {
+                self.node(addr)?;
+                let field = self.builder.field_token(field);
+                self.op_token(0x7B, field);
+            }
+            CILNode::LdInd {
+                addr,
+                tpe,
+                volatile,
+            } => {
+                self.node(addr)?;
+                let tpe = self.builder.asm[tpe];
+                self.volatile(volatile);
+                self.ldind(tpe)?;
+            }
+            CILNode::SizeOf(tpe) => {
+                let tpe = self.builder.asm[tpe];
+                if tpe == Type::Void {
+                    return Err(BodyError::SizeOfVoid);
+                }
+                let tpe = self.builder.type_token(&tpe);
+                self.op_fe_token(0x1C, tpe);
+            }
+            CILNode::GetException => (),
+            CILNode::IsInst(val, tpe) => {
+                self.node(val)?;
+                let tpe = self.builder.asm[tpe];
+                let tpe = self.builder.type_token(&tpe);
+                self.op_token(0x75, tpe);
+            }
+            CILNode::CheckedCast(val, tpe) => {
+                self.node(val)?;
+                let tpe = self.builder.asm[tpe];
+                let tpe = self.builder.type_token(&tpe);
+                self.op_token(0x74, tpe);
+            }
+            CILNode::CallI(calli) => {
+                let (fn_ptr, sig, args) = calli.as_ref();
+                self.calli(*fn_ptr, *sig, args)?;
+            }
+            CILNode::LocAlloc { size } => {
+                self.node(size)?;
+                self.op_fe(0x0F);
+            }
+            CILNode::LdStaticField(sfld) => {
+                let sfld = self.builder.static_field_token(sfld);
+                self.op_token(0x7E, sfld);
+            }
+            CILNode::LdStaticFieldAdress(sfld) => {
+                let sfld = self.builder.static_field_token(sfld);
+                self.op_token(0x7F, sfld);
+            }
+            CILNode::LdFtn(ftn) => {
+                let ftn = self.builder.method_token(ftn);
+                self.op_fe_token(0x06, ftn);
+            }
+            CILNode::LdTypeToken(tok) => {
+                let tpe = self.builder.asm[tok];
+                let tpe = self.builder.type_token(&tpe);
+                self.op_token(0xD0, tpe);
+            }
+            CILNode::LdLen(array) => {
+                self.node(array)?;
+                self.op(0x8E);
+            }
+            CILNode::LocAllocAlgined { tpe, align } => {
+                let tpe = self.builder.asm[tpe];
+                let tpe = self.builder.type_token(&tpe);
+                let align = i64::try_from(align).expect("Alignment too big");
+                self.op_fe_token(0x1C, tpe);
+                self.ldc_i8(align);
+                self.op(0xD3);
+                self.op(0x58);
+                self.op_fe(0x0F);
+                self.op(0x25);
+                self.ldc_i8(align);
+                self.op(0x58);
+                self.ldc_i8(align);
+                self.op(0x5D);
+                self.op(0x59);
+                self.ldc_i8(align);
+                self.op(0x58);
+                self.op(0xE0);
+            }
+            CILNode::LdElelemRef { array, index } => {
+                self.node(array)?;
+                self.node(index)?;
+                self.op(0x9A);
+            }
+            CILNode::UnboxAny { object, tpe } => {
+                self.node(object)?;
+                let tpe = self.builder.asm[tpe];
+                let tpe = self.builder.type_token(&tpe);
+                self.op_token(0xA5, tpe);
+            }
+        }
+        Ok(())
+    }
+    /// Emits an instruction operating on a local or an argument, picking its shortest form.
+    fn var_op(&mut self, idx: u32, short: Option<u8>, byte: u8, long: u8) {
+        match (idx, short) {
+            (0..=3, Some(short)) => self.op(short + idx as u8),
+            (0..=255, _) => {
+                self.op(byte);
+                self.code.push(idx as u8);
+            }
+            _ => {
+                self.op_fe(long);
+                self.code.extend_from_slice(
+                    &u16::try_from(idx)
+                        .expect("Too many locals or arguments")
+                        .to_le_bytes(),
+                );
+            }
+        }
+    }
+    fn ldind(&mut self, tpe: Type) -> Result<(), BodyError> {
+        match tpe {
+            Type::Ptr(_) | Type::FnPtr(_) => self.op(0x4D),
+            Type::Ref(_) => return Err(BodyError::IndirectRef),
+            Type::Int(int) => match int {
+                Int::U8 => self.op(0x47),
+                Int::U16 => self.op(0x49),
+                Int::U32 => self.op(0x4B),
+                Int::U64 | Int::I64 => self.op(0x4C),
+                Int::USize | Int::ISize => self.op(0x4D),
+                Int::I8 => self.op(0x46),
+                Int::I16 => self.op(0x48),
+                Int::I32 => self.op(0x4A),
+                Int::U128 | Int::I128 => self.ldobj(tpe),
+            },
+            Type::Float(float) => match float {
+                Float::F32 => self.op(0x4E),
+                Float::F64 => self.op(0x4F),
+                Float::F16 | Float::F128 => self.ldobj(tpe),
+            },
+            Type::PlatformString | Type::PlatformObject | Type::PlatformArray { .. } => {
+                self.op(0x50);
+            }
+            Type::PlatformChar => self.op(0x48),
+            Type::Bool => self.op(0x46),
+            Type::Void => panic!("Void can't be dereferenced!"),
+            Type::ClassRef(_) | Type::SIMDVector(_) | Type::PlatformGeneric(_, _) => {
+                self.ldobj(tpe);
+            }
+        }
+        Ok(())
+    }
+    fn ldobj(&mut self, tpe: Type) {
+        let tpe = self.builder.type_token(&tpe);
+        self.op_token(0x71, tpe);
+    }
+    fn stind(&mut self, tpe: Type) -> Result<(), BodyError> {
+        match tpe {
+            Type::Ptr(_) | Type::FnPtr(_) => self.op(0xDF),
+            Type::Ref(_) => return Err(BodyError::IndirectRef),
+            Type::Int(int) => match int {
+                Int::U8 | Int::I8 => self.op(0x52),
+                Int::U16 | Int::I16 => self.op(0x53),
+                Int::U32 | Int::I32 => self.op(0x54),
+                Int::U64 | Int::I64 => self.op(0x55),
+                Int::USize | Int::ISize => self.op(0xDF),
+                Int::U128 | Int::I128 => self.stobj(tpe),
+            },
+            Type::ClassRef(cref) => {
+                if self.builder.asm[cref].is_valuetype() {
+                    self.stobj(tpe);
+                } else {
+                    self.op(0x51);
+                }
+            }
+            Type::Float(float) => match float {
+                Float::F32 => self.op(0x56),
+                Float::F64 => self.op(0x57),
+                Float::F16 | Float::F128 => self.stobj(tpe),
+            },
+            Type::PlatformString | Type::PlatformObject | Type::PlatformArray { .. } => {
+                self.op(0x51);
+            }
+            Type::PlatformChar => self.op(0x53),
+            Type::Bool => self.op(0x52),
+            Type::Void => {
+                self.op(0x26);
+                self.op(0x26);
+                self.throw_msg("Attempted to wrtie to a zero-sized type(void).");
+            }
+            Type::SIMDVector(_) | Type::PlatformGeneric(_, _) => self.stobj(tpe),
+        }
+        Ok(())
+    }
+    fn stobj(&mut self, tpe: Type) {
+        let tpe = self.builder.type_token(&tpe);
+        self.op_token(0x81, tpe);
+    }
+    fn root(
+        &mut self,
+        root: RootIdx,
+        is_handler: bool,
+        has_handler: bool,
+    ) -> Result<(), BodyError> {
+        let root = self.builder.asm.get_root(root).clone();
+        match root {
+            CILRoot::StLoc(loc, val) => {
+                self.node(val)?;
+                self.var_op(loc, Some(0x0A), 0x13, 0x0E);
+            }
+            CILRoot::StArg(arg, val) => {
+                self.node(val)?;
+                self.var_op(arg, None, 0x10, 0x0B);
+            }
+            CILRoot::Ret(val) => {
+                self.node(val)?;
+                self.op(0x2A);
+            }
+            CILRoot::Pop(val) => {
+                self.node(val)?;
+                self.op(0x26);
+            }
+            CILRoot::Throw(val) => {
+                self.node(val)?;
+                self.op(0x7A);
+            }
+            CILRoot::VoidRet => self.op(0x2A),
+            CILRoot::TailCall(call) => self.tail_call(call)?,
+            CILRoot::Switch(switch) => {
+                let (discr, table) = switch.as_ref();
+                // The switched-on value is evaluated once, and kept on the stack until the target is known.
+                self.node(*discr)?;
+                let mut trampolines = vec![];
+                self.switch_plan(table, &table.plan(), &mut trampolines);
+                let otherwise = self.trampoline(&mut trampolines, table.otherwise());
+                self.branch(0x38, otherwise);
+                for ((target, sub_target), label) in trampolines {
+                    self.mark(label);
+                    self.op(0x26);
+                    let target = Self::branch_label(target, sub_target, is_handler, has_handler);
+                    self.branch(0x38, target);
+                }
+            }
+            CILRoot::Break => self.op(0x01),
+            CILRoot::Nop => self.op(0x00),
+            CILRoot::Branch(branch) => {
+                let (target, sub_target, cond) = branch.as_ref();
+                let label = Self::branch_label(*target, *sub_target, is_handler, has_handler);
+                let op = match cond {
+                    Some(BranchCond::True(cond)) => {
+                        self.node(*cond)?;
+                        0x3A
+                    }
+                    Some(BranchCond::False(cond)) => {
+                        self.node(*cond)?;
+                        0x39
+                    }
+                    Some(BranchCond::Eq(a, b)) => {
+                        self.node(*a)?;
+                        self.node(*b)?;
+                        0x3B
+                    }
+                    Some(BranchCond::Ne(a, b)) => {
+                        self.node(*a)?;
+                        self.node(*b)?;
+                        0x40
+                    }
+                    Some(
+                        BranchCond::Lt(a, b, kind)
+                        | BranchCond::Gt(a, b, kind)
+                        | BranchCond::Le(a, b, kind)
+                        | BranchCond::Ge(a, b, kind),
+                    ) => {
+                        self.node(*a)?;
+                        self.node(*b)?;
+                        let unsigned = matches!(kind, CmpKind::Unordered | CmpKind::Unsigned);
+                        let signed_op = match cond {
+                            Some(BranchCond::Lt(..)) => 0x3F,
+                            Some(BranchCond::Gt(..)) => 0x3D,
+                            Some(BranchCond::Le(..)) => 0x3E,
+                            _ => 0x3C,
+                        };
+                        // The unsigned variants come 5 opcodes after the signed ones.
+                        if unsigned {
+                            signed_op + 5
+                        } else {
+                            signed_op
+                        }
+                    }
+                    None => {
+                        // If this is not a handler, then this is the only block in this try, so all jumps are extern, and we can just use leave.
+                        if *sub_target != 0 && !is_handler && has_handler {
+                            self.branch(0xDD, Label::Block(*sub_target));
+                            return Ok(());
+                        }
+                        0x38
+                    }
+                };
+                self.branch(op, label);
+            }
+            CILRoot::SourceFileInfo {
+                line_start,
+                line_len,
+                col_start,
+                col_len,
+                file,
+            } => self.debug.push(SequencePoint {
+                il_offset: self.offset(),
+                line_start,
+                line_len,
+                col_start,
+                col_len,
+                file,
+            }),
+            CILRoot::SetField(flds) => {
+                self.node(flds.1)?;
+                self.node(flds.2)?;
+                let field = self.builder.field_token(flds.0);
+                self.op_token(0x7D, field);
+            }
+            CILRoot::Call(call) => self.call(call.0, &call.1, true)?,
+            CILRoot::CpObj { src, dst, tpe } => {
+                self.node(src)?;
+                self.node(dst)?;
+                let tpe = self.builder.asm[tpe];
+                let tpe = self.builder.type_token(&tpe);
+                self.op_token(0x70, tpe);
+            }
+            CILRoot::StInd(stind) => {
+                self.node(stind.0)?;
+                self.node(stind.1)?;
+                self.volatile(stind.3);
+                self.stind(stind.2)?;
+            }
+            CILRoot::InitBlk(blk) => {
+                self.node(blk.0)?;
+                self.node(blk.1)?;
+                self.node(blk.2)?;
+                self.op_fe(0x18);
+            }
+            CILRoot::CpBlk(blk) => {
+                self.node(blk.0)?;
+                self.node(blk.1)?;
+                self.node(blk.2)?;
+                self.op_fe(0x17);
+            }
+            CILRoot::CallI(calli) => {
+                let (fn_ptr, sig, args) = calli.as_ref();
+                self.calli(*fn_ptr, *sig, args)?;
+            }
+            CILRoot::ExitSpecialRegion { target, source } => {
+                if is_handler {
+                    self.mark(Label::Handler(source, target));
+                } else if has_handler {
+                    self.mark(Label::Jump(source, target));
+                } else {
+                    return Ok(());
+                }
+                self.branch(0xDD, Label::Block(target));
+            }
+            CILRoot::ReThrow => self.op_fe(0x1A),
+            CILRoot::SetStaticField { field, val } => {
+                self.node(val)?;
+                let sfld = self.builder.static_field_token(field);
+                self.op_token(0x80, sfld);
+            }
+            CILRoot::Unreachable(msg) => {
+                let msg = self.builder.asm[msg].to_owned();
+                self.throw_msg(&msg);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use fxhash::FxHashMap;
+
+/// Writes `val` as an ECMA-335 compressed unsigned integer (II.23.2).
+/// ```
+/// # use cilly::v2::pe_exporter::heaps::write_compressed_u32;
+/// let mut out = vec![];
+/// write_compressed_u32(0x03, &mut out);
+/// write_compressed_u32(0x80, &mut out);
+/// write_compressed_u32(0x4000, &mut out);
+/// assert_eq!(out, vec![0x03, 0x80, 0x80, 0xC0, 0x00, 0x40, 0x00]);
+/// ```
+pub fn write_compressed_u32(val: u32, out: &mut Vec<u8>) {
+    match val {
+        0..0x80 => out.push(val as u8),
+        0x80..0x4000 => out.extend_from_slice(&(0x8000 | val as u16).to_be_bytes()),
+        0x4000..0x2000_0000 => out.extend_from_slice(&(0xC000_0000 | val).to_be_bytes()),
+        _ => panic!("Value {val:#x} is too big to be compressed"),
+    }
+}
+/// Writes `val` as an ECMA-335 compressed signed integer (II.23.2): the value is rotated left by one bit, moving
+/// its sign bit to the lowest bit, and then stored using the smallest encoding able to hold it.
+/// ```
+/// # use cilly::v2::pe_exporter::heaps::write_compressed_i32;
+/// let mut out = vec![];
+/// write_compressed_i32(3, &mut out);
+/// write_compressed_i32(-3, &mut out);
+/// write_compressed_i32(64, &mut out);
+/// write_compressed_i32(-8192, &mut out);
+/// assert_eq!(out, vec![0x06, 0x7B, 0x80, 0x80, 0x80, 0x01]);
+/// ```
+pub fn write_compressed_i32(val: i32, out: &mut Vec<u8>) {
+    let sign = u32::from(val < 0);
+    let bits = val as u32;
+    match val {
+        -0x40..0x40 => out.push((((bits & 0x3F) << 1) | sign) as u8),
+        -0x2000..0x2000 => {
+            out.extend_from_slice(&(0x8000 | (((bits & 0x1FFF) << 1) | sign) as u16).to_be_bytes());
+        }
+        -0x1000_0000..0x1000_0000 => {
+            out.extend_from_slice(
+                &(0xC000_0000 | ((bits & 0x0FFF_FFFF) << 1) | sign).to_be_bytes(),
+            );
+        }
+        _ => panic!("Value {val} is too big to be compressed"),
+    }
+}
+/// The `#Strings` heap: null-terminated UTF-8 strings, with offset 0 reserved for the empty string.
+pub struct StringHeap {
+    data: Vec<u8>,
+    map: FxHashMap<Box<str>, u32>,
+}
+impl Default for StringHeap {
+    fn default() -> Self {
+        Self {
+            data: vec![0],
+            map: FxHashMap::default(),
+        }
+    }
+}
+impl StringHeap {
+    /// Adds `string` to this heap, returning its offset. Identical strings are only stored once.
+    /// ```
+    /// # use cilly::v2::pe_exporter::heaps::StringHeap;
+    /// let mut heap = StringHeap::default();
+    /// assert_eq!(heap.alloc(""), 0);
+    /// assert_eq!(heap.alloc("Object"), 1);
+    /// assert_eq!(heap.alloc("Object"), 1);
+    /// assert_eq!(heap.alloc("System"), 8);
+    /// ```
+    pub fn alloc(&mut self, string: &str) -> u32 {
+        if string.is_empty() {
+            return 0;
+        }
+        if let Some(offset) = self.map.get(string) {
+            return *offset;
+        }
+        let offset = u32::try_from(self.data.len()).expect("#Strings heap overflow");
+        assert!(
+            !string.contains('\0'),
+            "Metadata string {string:?} contains a null byte"
+        );
+        self.data.extend_from_slice(string.as_bytes());
+        self.data.push(0);
+        self.map.insert(string.into(), offset);
+        offset
+    }
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+/// The `#US` heap: length-prefixed UTF-16 strings, used by `ldstr`.
+pub struct UserStringHeap {
+    data: Vec<u8>,
+    map: FxHashMap<Box<str>, u32>,
+}
+impl Default for UserStringHeap {
+    fn default() -> Self {
+        Self {
+            data: vec![0],
+            map: FxHashMap::default(),
+        }
+    }
+}
+impl UserStringHeap {
+    /// Adds `string` to this heap, returning its offset.
+    /// ```
+    /// # use cilly::v2::pe_exporter::heaps::UserStringHeap;
+    /// let mut heap = UserStringHeap::default();
+    /// assert_eq!(heap.alloc("Hi"), 1);
+    /// // 2 chars * 2 bytes + the terminal byte.
+    /// assert_eq!(heap.data(), &[0, 5, b'H', 0, b'i', 0, 0]);
+    /// ```
+    pub fn alloc(&mut self, string: &str) -> u32 {
+        if let Some(offset) = self.map.get(string) {
+            return *offset;
+        }
+        let offset = u32::try_from(self.data.len()).expect("#US heap overflow");
+        let utf16: Vec<u16> = string.encode_utf16().collect();
+        let byte_len = u32::try_from(utf16.len() * 2 + 1).expect("#US string too long");
+        write_compressed_u32(byte_len, &mut self.data);
+        for char in &utf16 {
+            self.data.extend_from_slice(&char.to_le_bytes());
+        }
+        // II.24.2.4: the final byte is set if any char needs special handling.
+        let special = utf16.iter().any(|char| {
+            *char > 0xFF || matches!(*char, 0x01..=0x08 | 0x0E..=0x1F | 0x27 | 0x2D | 0x7F)
+        });
+        self.data.push(u8::from(special));
+        self.map.insert(string.into(), offset);
+        offset
+    }
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+/// The `#Blob` heap: length-prefixed binary blobs, holding signatures and custom attribute values.
+pub struct BlobHeap {
+    data: Vec<u8>,
+    map: FxHashMap<Box<[u8]>, u32>,
+}
+impl Default for BlobHeap {
+    fn default() -> Self {
+        Self {
+            data: vec![0],
+            map: FxHashMap::default(),
+        }
+    }
+}
+impl BlobHeap {
+    /// Adds `blob` to this heap, returning its offset. The empty blob always lives at offset 0.
+    /// ```
+    /// # use cilly::v2::pe_exporter::heaps::BlobHeap;
+    /// let mut heap = BlobHeap::default();
+    /// assert_eq!(heap.alloc(&[]), 0);
+    /// assert_eq!(heap.alloc(&[0x06, 0x08]), 1);
+    /// assert_eq!(heap.alloc(&[0x06, 0x08]), 1);
+    /// assert_eq!(heap.data(), &[0, 2, 0x06, 0x08]);
+    /// ```
+    pub fn alloc(&mut self, blob: &[u8]) -> u32 {
+        if blob.is_empty() {
+            return 0;
+        }
+        if let Some(offset) = self.map.get(blob) {
+            return *offset;
+        }
+        let offset = u32::try_from(self.data.len()).expect("#Blob heap overflow");
+        write_compressed_u32(
+            u32::try_from(blob.len()).expect("Blob too long"),
+            &mut self.data,
+        );
+        self.data.extend_from_slice(blob);
+        self.map.insert(blob.into(), offset);
+        offset
+    }
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+/// The `#GUID` heap. Indices into it are 1-based.
+#[derive(Default)]
+pub struct GuidHeap {
+    data: Vec<u8>,
+}
+impl GuidHeap {
+    pub fn alloc(&mut self, guid: [u8; 16]) -> u32 {
+        self.data.extend_from_slice(&guid);
+        u32::try_from(self.data.len() / 16).expect("#GUID heap overflow")
+    }
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+#[test]
+fn compressed_u32() {
+    let mut out = vec![];
+    write_compressed_u32(0x3FFF, &mut out);
+    assert_eq!(out, vec![0xBF, 0xFF]);
+    out.clear();
+    write_compressed_u32(0x1FFF_FFFF, &mut out);
+    assert_eq!(out, vec![0xDF, 0xFF, 0xFF, 0xFF]);
+}
+#[test]
+fn compressed_i32() {
+    let mut out = vec![];
+    write_compressed_i32(-64, &mut out);
+    write_compressed_i32(8192, &mut out);
+    assert_eq!(out, vec![0x01, 0xC0, 0x00, 0x40, 0x00]);
+    out.clear();
+    write_compressed_i32(0x0FFF_FFFF, &mut out);
+    write_compressed_i32(-0x1000_0000, &mut out);
+    assert_eq!(out, vec![0xDF, 0xFF, 0xFF, 0xFE, 0xC0, 0x00, 0x00, 0x01]);
+}
+#[test]
+fn user_string_special() {
+    let mut heap = UserStringHeap::default();
+    heap.alloc("don't");
+    assert_eq!(*heap.data().last().unwrap(), 1);
+    let mut heap = UserStringHeap::default();
+    heap.alloc("ok");
+    assert_eq!(*heap.data().last().unwrap(), 0);
+}
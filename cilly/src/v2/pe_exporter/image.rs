@@ -0,0 +1,306 @@
+use super::{pdb::PdbId, tables::MetadataToken};
+
+/// RVA of the `.text` section.
+pub(super) const TEXT_RVA: u32 = 0x2000;
+/// Offset of the method bodies inside the `.text` section: they come after the IAT(8 bytes) and the CLI header(72 bytes).
+pub(super) const IL_OFFSET: u32 = 0x50;
+const FILE_ALIGNMENT: u32 = 0x200;
+const SECTION_ALIGNMENT: u32 = 0x2000;
+const CLI_HEADER_SIZE: u32 = 72;
+/// The size of all the headers, rounded up to the file alignment.
+const HEADERS_SIZE: u32 = 0x200;
+/// The size of a debug directory entry(PE/COFF, "Debug Directory").
+const DEBUG_DIRECTORY_SIZE: u32 = 28;
+/// The metadata version string used by assemblies.
+pub(super) const ASSEMBLY_VERSION: &[u8] = b"v4.0.30319\0\0";
+/// The MS-DOS header and stub(II.25.2.1), with `e_lfanew` pointing right after it.
+const DOS_HEADER: [u8; 128] = [
+    0x4d, 0x5a, 0x90, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00,
+    0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00,
+    0x0e, 0x1f, 0xba, 0x0e, 0x00, 0xb4, 0x09, 0xcd, 0x21, 0xb8, 0x01, 0x4c, 0xcd, 0x21, 0x54, 0x68,
+    0x69, 0x73, 0x20, 0x70, 0x72, 0x6f, 0x67, 0x72, 0x61, 0x6d, 0x20, 0x63, 0x61, 0x6e, 0x6e, 0x6f,
+    0x74, 0x20, 0x62, 0x65, 0x20, 0x72, 0x75, 0x6e, 0x20, 0x69, 0x6e, 0x20, 0x44, 0x4f, 0x53, 0x20,
+    0x6d, 0x6f, 0x64, 0x65, 0x2e, 0x0d, 0x0d, 0x0a, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+fn align(val: u32, alignment: u32) -> u32 {
+    val.div_ceil(alignment) * alignment
+}
+fn pad_to(out: &mut Vec<u8>, alignment: usize) {
+    while !out.len().is_multiple_of(alignment) {
+        out.push(0);
+    }
+}
+fn push_u16(out: &mut Vec<u8>, val: u16) {
+    out.extend_from_slice(&val.to_le_bytes());
+}
+fn push_u32(out: &mut Vec<u8>, val: u32) {
+    out.extend_from_slice(&val.to_le_bytes());
+}
+fn len_u32(data: &[u8]) -> u32 {
+    u32::try_from(data.len()).expect("PE image too big")
+}
+/// Builds the metadata root(II.24.2.1), containing the provided streams. `version` must be null-padded to 4 bytes.
+pub(super) fn metadata_root(version: &[u8], streams: &[(&str, &[u8])]) -> Vec<u8> {
+    debug_assert_eq!(version.len() % 4, 0);
+    let mut header = vec![];
+    // "BSJB"
+    push_u32(&mut header, 0x424A_5342);
+    // Major and minor version
+    push_u16(&mut header, 1);
+    push_u16(&mut header, 1);
+    // Reserved
+    push_u32(&mut header, 0);
+    push_u32(&mut header, len_u32(version));
+    header.extend_from_slice(version);
+    // Flags
+    push_u16(&mut header, 0);
+    push_u16(&mut header, u16::try_from(streams.len()).unwrap());
+    // The size of the stream headers is needed to calculate the offsets of the streams.
+    let headers_size: usize = streams
+        .iter()
+        .map(|(name, _)| 8 + (name.len() + 1).next_multiple_of(4))
+        .sum();
+    let mut offset = len_u32(&header) + u32::try_from(headers_size).unwrap();
+    for (name, data) in streams {
+        debug_assert_eq!(data.len() % 4, 0, "stream {name} is not 4 byte aligned");
+        push_u32(&mut header, offset);
+        push_u32(&mut header, len_u32(data));
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        pad_to(&mut header, 4);
+        offset += len_u32(data);
+    }
+    for (_, data) in streams {
+        header.extend_from_slice(data);
+    }
+    header
+}
+/// Writes a PE32 image(II.25), containing the method bodies `il` and the metadata `metadata`. If `pdb` is provided,
+/// the image gets a debug directory pointing to the Portable PDB with that id and path.
+pub(super) fn write_image(
+    il: &[u8],
+    metadata: &[u8],
+    entrypoint: Option<MetadataToken>,
+    is_lib: bool,
+    pdb: Option<(&PdbId, &str)>,
+) -> Vec<u8> {
+    let image_base: u32 = if is_lib { 0x1000_0000 } else { 0x0040_0000 };
+    // Lay out the `.text` section.
+    let mut text = vec![0; IL_OFFSET as usize];
+    text.extend_from_slice(il);
+    pad_to(&mut text, 4);
+    let metadata_rva = TEXT_RVA + len_u32(&text);
+    text.extend_from_slice(metadata);
+    pad_to(&mut text, 4);
+    let debug_dir_rva = TEXT_RVA + len_u32(&text);
+    if let Some((pdb_id, pdb_path)) = pdb {
+        // A CodeView entry, in the "RSDS" format: GUID, age, and the path to the PDB.
+        let mut codeview = b"RSDS".to_vec();
+        codeview.extend_from_slice(&pdb_id[..16]);
+        push_u32(&mut codeview, 1);
+        codeview.extend_from_slice(pdb_path.as_bytes());
+        codeview.push(0);
+        let codeview_rva = debug_dir_rva + DEBUG_DIRECTORY_SIZE;
+        // Characteristics
+        push_u32(&mut text, 0);
+        // The time stamp of a Portable PDB is the end of its id.
+        text.extend_from_slice(&pdb_id[16..20]);
+        // Portable PDBs use the major version 0x0100, and the minor version 0x504D.
+        push_u16(&mut text, 0x0100);
+        push_u16(&mut text, 0x504D);
+        // IMAGE_DEBUG_TYPE_CODEVIEW
+        push_u32(&mut text, 2);
+        push_u32(&mut text, len_u32(&codeview));
+        push_u32(&mut text, codeview_rva);
+        push_u32(&mut text, HEADERS_SIZE + codeview_rva - TEXT_RVA);
+        text.extend_from_slice(&codeview);
+        pad_to(&mut text, 4);
+    }
+    let import_dir_rva = TEXT_RVA + len_u32(&text);
+    // Import directory(40 bytes) + import lookup table(8 bytes)
+    let ilt_rva = import_dir_rva + 40;
+    let hint_name_rva = ilt_rva + 8;
+    let entry_name: &[u8] = if is_lib {
+        b"_CorDllMain\0"
+    } else {
+        b"_CorExeMain\0"
+    };
+    let dll_name_rva = hint_name_rva + 2 + len_u32(entry_name);
+    // Import directory entry for mscoree.dll
+    push_u32(&mut text, ilt_rva);
+    push_u32(&mut text, 0);
+    push_u32(&mut text, 0);
+    push_u32(&mut text, dll_name_rva);
+    push_u32(&mut text, TEXT_RVA);
+    // Null terminator entry
+    text.extend_from_slice(&[0; 20]);
+    // Import lookup table
+    push_u32(&mut text, hint_name_rva);
+    push_u32(&mut text, 0);
+    // Hint/Name table
+    push_u16(&mut text, 0);
+    text.extend_from_slice(entry_name);
+    text.extend_from_slice(b"mscoree.dll\0");
+    // The entry point stub is `jmp [IAT]`. Its operand should be 4 byte aligned.
+    pad_to(&mut text, 4);
+    text.extend_from_slice(&[0, 0]);
+    let stub_rva = TEXT_RVA + len_u32(&text);
+    text.extend_from_slice(&[0xFF, 0x25]);
+    let stub_operand_rva = TEXT_RVA + len_u32(&text);
+    push_u32(&mut text, image_base + TEXT_RVA);
+    // Fill in the IAT
+    text[0..4].copy_from_slice(&hint_name_rva.to_le_bytes());
+    // Fill in the CLI header(II.25.3.3)
+    let mut cli = vec![];
+    push_u32(&mut cli, CLI_HEADER_SIZE);
+    push_u16(&mut cli, 2);
+    push_u16(&mut cli, 5);
+    push_u32(&mut cli, metadata_rva);
+    push_u32(&mut cli, len_u32(metadata));
+    // COMIMAGE_FLAGS_ILONLY
+    push_u32(&mut cli, 1);
+    push_u32(&mut cli, entrypoint.map_or(0, |token| token.raw()));
+    // Resources, StrongNameSignature, CodeManagerTable, VTableFixups, ExportAddressTableJumps, ManagedNativeHeader
+    cli.extend_from_slice(&[0; 48]);
+    debug_assert_eq!(len_u32(&cli), CLI_HEADER_SIZE);
+    text[8..8 + CLI_HEADER_SIZE as usize].copy_from_slice(&cli);
+    let text_size = len_u32(&text);
+    let text_raw_size = align(text_size, FILE_ALIGNMENT);
+    // The `.reloc` section, fixing up the stub operand.
+    let reloc_rva = align(TEXT_RVA + text_size, SECTION_ALIGNMENT);
+    let mut reloc = vec![];
+    push_u32(&mut reloc, stub_operand_rva & !0xFFF);
+    push_u32(&mut reloc, 12);
+    // IMAGE_REL_BASED_HIGHLOW
+    push_u16(&mut reloc, (3 << 12) | (stub_operand_rva & 0xFFF) as u16);
+    push_u16(&mut reloc, 0);
+    let reloc_size = len_u32(&reloc);
+    let reloc_raw_size = align(reloc_size, FILE_ALIGNMENT);
+    let image_size = reloc_rva + align(reloc_size, SECTION_ALIGNMENT);
+    let mut out = Vec::with_capacity((HEADERS_SIZE + text_raw_size + reloc_raw_size) as usize);
+    out.extend_from_slice(&DOS_HEADER);
+    out.extend_from_slice(b"PE\0\0");
+    // PE file header(II.25.2.2)
+    push_u16(&mut out, 0x014C);
+    // Number of sections
+    push_u16(&mut out, 2);
+    // Time stamp, symbol table pointer, symbol count
+    push_u32(&mut out, 0);
+    push_u32(&mut out, 0);
+    push_u32(&mut out, 0);
+    // Optional header size
+    push_u16(&mut out, 0xE0);
+    // IMAGE_FILE_EXECUTABLE_IMAGE | IMAGE_FILE_32BIT_MACHINE, optionally IMAGE_FILE_DLL
+    push_u16(&mut out, if is_lib { 0x2102 } else { 0x0102 });
+    // PE optional header standard fields(II.25.2.3.1)
+    push_u16(&mut out, 0x010B);
+    // Linker version
+    out.push(8);
+    out.push(0);
+    push_u32(&mut out, text_raw_size);
+    push_u32(&mut out, reloc_raw_size);
+    push_u32(&mut out, 0);
+    push_u32(&mut out, stub_rva);
+    push_u32(&mut out, TEXT_RVA);
+    push_u32(&mut out, reloc_rva);
+    // NT specific fields(II.25.2.3.2)
+    push_u32(&mut out, image_base);
+    push_u32(&mut out, SECTION_ALIGNMENT);
+    push_u32(&mut out, FILE_ALIGNMENT);
+    // OS version
+    push_u16(&mut out, 4);
+    push_u16(&mut out, 0);
+    // Image version
+    push_u16(&mut out, 0);
+    push_u16(&mut out, 0);
+    // Subsystem version
+    push_u16(&mut out, 4);
+    push_u16(&mut out, 0);
+    // Reserved
+    push_u32(&mut out, 0);
+    push_u32(&mut out, image_size);
+    push_u32(&mut out, HEADERS_SIZE);
+    // Checksum
+    push_u32(&mut out, 0);
+    // IMAGE_SUBSYSTEM_WINDOWS_CUI
+    push_u16(&mut out, 3);
+    // NX compatible, no SEH, dynamic base, terminal server aware
+    push_u16(&mut out, 0x8540);
+    // Stack reserve & commit, heap reserve & commit
+    push_u32(&mut out, 0x0010_0000);
+    push_u32(&mut out, 0x1000);
+    push_u32(&mut out, 0x0010_0000);
+    push_u32(&mut out, 0x1000);
+    // Loader flags
+    push_u32(&mut out, 0);
+    // Number of data directories
+    push_u32(&mut out, 16);
+    // Data directories(II.25.2.3.3)
+    let mut directories = [(0_u32, 0_u32); 16];
+    directories[1] = (import_dir_rva, 40);
+    directories[5] = (reloc_rva, reloc_size);
+    if pdb.is_some() {
+        directories[6] = (debug_dir_rva, DEBUG_DIRECTORY_SIZE);
+    }
+    directories[12] = (TEXT_RVA, 8);
+    directories[14] = (TEXT_RVA + 8, CLI_HEADER_SIZE);
+    for (rva, size) in directories {
+        push_u32(&mut out, rva);
+        push_u32(&mut out, size);
+    }
+    // Section headers(II.25.3)
+    let sections = [
+        (
+            b".text\0\0\0",
+            text_size,
+            TEXT_RVA,
+            text_raw_size,
+            HEADERS_SIZE,
+            // CODE | EXECUTE | READ
+            0x6000_0020_u32,
+        ),
+        (
+            b".reloc\0\0",
+            reloc_size,
+            reloc_rva,
+            reloc_raw_size,
+            HEADERS_SIZE + text_raw_size,
+            // INITIALIZED_DATA | DISCARDABLE | READ
+            0x4200_0040,
+        ),
+    ];
+    for (name, virtual_size, rva, raw_size, raw_ptr, characteristics) in sections {
+        out.extend_from_slice(name);
+        push_u32(&mut out, virtual_size);
+        push_u32(&mut out, rva);
+        push_u32(&mut out, raw_size);
+        push_u32(&mut out, raw_ptr);
+        // Relocations & line numbers
+        push_u32(&mut out, 0);
+        push_u32(&mut out, 0);
+        push_u16(&mut out, 0);
+        push_u16(&mut out, 0);
+        push_u32(&mut out, characteristics);
+    }
+    debug_assert!(len_u32(&out) <= HEADERS_SIZE);
+    out.resize(HEADERS_SIZE as usize, 0);
+    out.extend_from_slice(&text);
+    out.resize((HEADERS_SIZE + text_raw_size) as usize, 0);
+    out.extend_from_slice(&reloc);
+    out.resize((HEADERS_SIZE + text_raw_size + reloc_raw_size) as usize, 0);
+    out
+}
+#[test]
+fn metadata_root_layout() {
+    let root = metadata_root(
+        ASSEMBLY_VERSION,
+        &[("#~", &[1, 2, 3, 4]), ("#Strings", &[0, 0, 0, 0])],
+    );
+    assert_eq!(&root[0..4], b"BSJB");
+    // Header(32 bytes) + "#~" header(12 bytes) + "#Strings" header(20 bytes)
+    let first_offset = u32::from_le_bytes(root[32..36].try_into().unwrap());
+    assert_eq!(first_offset, 64);
+    assert_eq!(&root[64..68], &[1, 2, 3, 4]);
+}
@@ -0,0 +1,986 @@
+use fxhash::FxHashMap;
+
+use super::{
+    heaps::{write_compressed_u32, BlobHeap, GuidHeap, StringHeap, UserStringHeap},
+    tables::{CodedIndex, Column, HeapSizes, MetadataToken, TableId, Tables},
+};
+use crate::v2::{
+    cilnode::MethodKind, tpe::simd::SIMDElem, tpe::GenericKind, Access, Assembly, CallConv,
+    ClassDefIdx, ClassRefIdx, FieldIdx, Float, FnSig, Int, MethodDefIdx, MethodRefIdx,
+    StaticFieldIdx, StringIdx, Type,
+};
+
+// Element types(II.23.1.16)
+pub(super) const ELEMENT_TYPE_VOID: u8 = 0x01;
+pub(super) const ELEMENT_TYPE_BOOLEAN: u8 = 0x02;
+pub(super) const ELEMENT_TYPE_CHAR: u8 = 0x03;
+pub(super) const ELEMENT_TYPE_I1: u8 = 0x04;
+pub(super) const ELEMENT_TYPE_U1: u8 = 0x05;
+pub(super) const ELEMENT_TYPE_I2: u8 = 0x06;
+pub(super) const ELEMENT_TYPE_U2: u8 = 0x07;
+pub(super) const ELEMENT_TYPE_I4: u8 = 0x08;
+pub(super) const ELEMENT_TYPE_U4: u8 = 0x09;
+pub(super) const ELEMENT_TYPE_I8: u8 = 0x0A;
+pub(super) const ELEMENT_TYPE_U8: u8 = 0x0B;
+pub(super) const ELEMENT_TYPE_R4: u8 = 0x0C;
+pub(super) const ELEMENT_TYPE_R8: u8 = 0x0D;
+pub(super) const ELEMENT_TYPE_STRING: u8 = 0x0E;
+pub(super) const ELEMENT_TYPE_PTR: u8 = 0x0F;
+pub(super) const ELEMENT_TYPE_BYREF: u8 = 0x10;
+pub(super) const ELEMENT_TYPE_VALUETYPE: u8 = 0x11;
+pub(super) const ELEMENT_TYPE_CLASS: u8 = 0x12;
+pub(super) const ELEMENT_TYPE_VAR: u8 = 0x13;
+pub(super) const ELEMENT_TYPE_ARRAY: u8 = 0x14;
+pub(super) const ELEMENT_TYPE_GENERICINST: u8 = 0x15;
+pub(super) const ELEMENT_TYPE_I: u8 = 0x18;
+pub(super) const ELEMENT_TYPE_U: u8 = 0x19;
+pub(super) const ELEMENT_TYPE_FNPTR: u8 = 0x1B;
+pub(super) const ELEMENT_TYPE_OBJECT: u8 = 0x1C;
+pub(super) const ELEMENT_TYPE_SZARRAY: u8 = 0x1D;
+pub(super) const ELEMENT_TYPE_MVAR: u8 = 0x1E;
+pub(super) const ELEMENT_TYPE_CMOD_REQD: u8 = 0x1F;
+pub(super) const ELEMENT_TYPE_CMOD_OPT: u8 = 0x20;
+pub(super) const ELEMENT_TYPE_SENTINEL: u8 = 0x41;
+pub(super) const ELEMENT_TYPE_PINNED: u8 = 0x45;
+// Signature flags(II.23.2.1)
+pub(super) const SIG_HASTHIS: u8 = 0x20;
+pub(super) const SIG_GENERIC: u8 = 0x10;
+pub(super) const SIG_VARARG: u8 = 0x05;
+pub(super) const SIG_FIELD: u8 = 0x06;
+pub(super) const SIG_LOCAL: u8 = 0x07;
+pub(super) const SIG_GENERICINST: u8 = 0x0A;
+/// The assembly, all the core .NET types live in.
+pub(super) const CORE_ASSEMBLY: &str = "System.Runtime";
+/// Translates a cilly [`Assembly`] into ECMA-335 metadata tables and heaps.
+pub(super) struct MetadataBuilder {
+    pub(super) asm: Assembly,
+    pub(super) tables: Tables,
+    pub(super) strings: StringHeap,
+    pub(super) user_strings: UserStringHeap,
+    pub(super) blobs: BlobHeap,
+    pub(super) guids: GuidHeap,
+    assembly_refs: FxHashMap<Box<str>, MetadataToken>,
+    module_refs: FxHashMap<Box<str>, MetadataToken>,
+    type_refs: FxHashMap<(Option<Box<str>>, Box<str>), MetadataToken>,
+    type_specs: FxHashMap<Vec<u8>, MetadataToken>,
+    member_refs: FxHashMap<(MetadataToken, u32, u32), MetadataToken>,
+    method_specs: FxHashMap<(MetadataToken, u32), MetadataToken>,
+    stand_alone_sigs: FxHashMap<Vec<u8>, MetadataToken>,
+    type_defs: FxHashMap<ClassDefIdx, MetadataToken>,
+    type_defs_by_name: FxHashMap<StringIdx, MetadataToken>,
+    method_defs: FxHashMap<MethodDefIdx, MetadataToken>,
+    fields: FxHashMap<(ClassRefIdx, StringIdx, Type), MetadataToken>,
+    static_fields: FxHashMap<(ClassRefIdx, StringIdx, Type), MetadataToken>,
+}
+impl MetadataBuilder {
+    pub(super) fn new(asm: Assembly) -> Self {
+        Self {
+            asm,
+            tables: Tables::default(),
+            strings: StringHeap::default(),
+            user_strings: UserStringHeap::default(),
+            blobs: BlobHeap::default(),
+            guids: GuidHeap::default(),
+            assembly_refs: FxHashMap::default(),
+            module_refs: FxHashMap::default(),
+            type_refs: FxHashMap::default(),
+            type_specs: FxHashMap::default(),
+            member_refs: FxHashMap::default(),
+            method_specs: FxHashMap::default(),
+            stand_alone_sigs: FxHashMap::default(),
+            type_defs: FxHashMap::default(),
+            type_defs_by_name: FxHashMap::default(),
+            method_defs: FxHashMap::default(),
+            fields: FxHashMap::default(),
+            static_fields: FxHashMap::default(),
+        }
+    }
+    pub(super) fn heap_sizes(&self) -> HeapSizes {
+        HeapSizes {
+            strings: self.strings.data().len(),
+            guid: self.guids.data().len() / 16,
+            blob: self.blobs.data().len(),
+        }
+    }
+    /// Emits the `Module` and `Assembly` rows, as well as the `<Module>` pseudo-class.
+    pub(super) fn define_module(&mut self, module_name: &str) {
+        let name = self.strings.alloc(module_name);
+        let mvid = module_mvid(&self.asm, module_name);
+        let mvid = self.guids.alloc(mvid);
+        self.tables.push(
+            TableId::Module,
+            [
+                Column::U16(0),
+                Column::String(name),
+                Column::Guid(mvid),
+                Column::Guid(0),
+                Column::Guid(0),
+            ],
+        );
+        let module_class = self.strings.alloc("<Module>");
+        self.tables.push(
+            TableId::TypeDef,
+            [
+                Column::U32(0),
+                Column::String(module_class),
+                Column::String(0),
+                Column::Coded(CodedIndex::TypeDefOrRef, None),
+                Column::Index(TableId::Field, 1),
+                Column::Index(TableId::MethodDef, 1),
+            ],
+        );
+        // The assembly name matches the one used by the IL exporter.
+        let asm_name = self.strings.alloc("_");
+        self.tables.push(
+            TableId::Assembly,
+            [
+                // SHA1
+                Column::U32(0x8004),
+                Column::U16(0),
+                Column::U16(0),
+                Column::U16(0),
+                Column::U16(0),
+                Column::U32(0),
+                Column::Blob(0),
+                Column::String(asm_name),
+                Column::String(0),
+            ],
+        );
+    }
+    /// Emits all the classes defined in the assembly, along with their fields and method signatures.
+    /// Returns the list of methods, whose bodies still need to be emitted.
+    pub(super) fn define_types(&mut self) -> Vec<(MethodDefIdx, MetadataToken)> {
+        let mut class_ids: Vec<ClassDefIdx> = self.asm.iter_class_def_ids().copied().collect();
+        // Sort the classes by name, to make the output deterministic.
+        class_ids.sort_by(|a, b| self.asm[self.asm[*a].name()].cmp(&self.asm[self.asm[*b].name()]));
+        // Pre-assign the TypeDef tokens, since classes can reference each other.
+        for (idx, class_id) in class_ids.iter().enumerate() {
+            // Row 1 is `<Module>`.
+            let token = MetadataToken::new(TableId::TypeDef, idx as u32 + 2);
+            self.type_defs.insert(*class_id, token);
+            self.type_defs_by_name
+                .insert(self.asm[*class_id].name(), token);
+        }
+        let mut bodies = vec![];
+        for class_id in class_ids {
+            let class_def = self.asm[class_id].clone();
+            let vis = match class_def.access() {
+                Access::Extern | Access::Public => 0x0000_0001,
+                Access::Private => 0x0000_0000,
+            };
+            let sealed = if class_def.is_valuetype() {
+                0x0000_0100
+            } else {
+                0
+            };
+            let layout = if class_def.has_explicit_layout() {
+                0x0000_0010
+            } else {
+                0
+            };
+            let extends = match class_def.extends() {
+                Some(parrent) => self.type_def_or_ref(parrent),
+                None if class_def.is_valuetype() => {
+                    self.type_ref(Some(CORE_ASSEMBLY), "System.ValueType")
+                }
+                None => self.type_ref(Some(CORE_ASSEMBLY), "System.Object"),
+            };
+            let (namespace, name) = split_name(&self.asm[class_def.name()]);
+            let namespace = self.strings.alloc(namespace);
+            let name = self.strings.alloc(name);
+            let field_list = self.tables.next_row(TableId::Field).row();
+            let method_list = self.tables.next_row(TableId::MethodDef).row();
+            let type_def = self.tables.push(
+                TableId::TypeDef,
+                [
+                    Column::U32(vis | sealed | layout),
+                    Column::String(name),
+                    Column::String(namespace),
+                    Column::Coded(CodedIndex::TypeDefOrRef, Some(extends)),
+                    Column::Index(TableId::Field, field_list),
+                    Column::Index(TableId::MethodDef, method_list),
+                ],
+            );
+            debug_assert_eq!(type_def, self.type_defs[&class_id]);
+            if let Some(size) = class_def.explict_size() {
+                self.tables.push(
+                    TableId::ClassLayout,
+                    [
+                        Column::U16(0),
+                        Column::U32(size.get()),
+                        Column::Index(TableId::TypeDef, type_def.row()),
+                    ],
+                );
+            }
+            for (tpe, fld_name, offset) in class_def.fields() {
+                let field = self.define_field(*tpe, *fld_name, false);
+                self.fields.insert((*class_id, *fld_name, *tpe), field);
+                if let Some(offset) = offset {
+                    self.tables.push(
+                        TableId::FieldLayout,
+                        [
+                            Column::U32(*offset),
+                            Column::Index(TableId::Field, field.row()),
+                        ],
+                    );
+                }
+            }
+            for (tpe, fld_name, thread_local) in class_def.static_fields() {
+                let field = self.define_field(*tpe, *fld_name, true);
+                self.static_fields
+                    .insert((*class_id, *fld_name, *tpe), field);
+                if *thread_local {
+                    let attr = self.type_ref(Some(CORE_ASSEMBLY), "System.ThreadStaticAttribute");
+                    let ctor = self.attribute_ctor(attr);
+                    self.custom_attribute(field, ctor, &[0x01, 0x00, 0x00, 0x00]);
+                }
+            }
+            for method_id in class_def.methods() {
+                let token = self.define_method(*method_id);
+                if !matches!(
+                    self.asm[*method_id].resolved_implementation(&self.asm),
+                    crate::v2::MethodImpl::Extern { .. }
+                ) {
+                    bodies.push((*method_id, token));
+                }
+            }
+            for iface in class_def.implements() {
+                let iface = self.type_def_or_ref(*iface);
+                self.tables.push(
+                    TableId::InterfaceImpl,
+                    [
+                        Column::Index(TableId::TypeDef, type_def.row()),
+                        Column::Coded(CodedIndex::TypeDefOrRef, Some(iface)),
+                    ],
+                );
+            }
+            for (decl, body) in class_def.overrides() {
+                let body = self.method_defs[body];
+                let decl = self.method_token(*decl);
+                self.tables.push(
+                    TableId::MethodImpl,
+                    [
+                        Column::Index(TableId::TypeDef, type_def.row()),
+                        Column::Coded(CodedIndex::MethodDefOrRef, Some(body)),
+                        Column::Coded(CodedIndex::MethodDefOrRef, Some(decl)),
+                    ],
+                );
+            }
+        }
+        bodies
+    }
+    fn define_field(&mut self, tpe: Type, name: StringIdx, is_static: bool) -> MetadataToken {
+        // Public
+        let flags = if is_static { 0x0016 } else { 0x0006 };
+        let name = self.strings.alloc(&self.asm[name]);
+        let mut sig = vec![SIG_FIELD];
+        self.encode_non_void_type(&tpe, &mut sig);
+        let sig = self.blobs.alloc(&sig);
+        self.tables.push(
+            TableId::Field,
+            [Column::U16(flags), Column::String(name), Column::Blob(sig)],
+        )
+    }
+    fn define_method(&mut self, method_id: MethodDefIdx) -> MetadataToken {
+        let method = self.asm[method_id].clone();
+        let name = self.asm[method.name()].to_owned();
+        let mut flags: u16 = match method.access() {
+            Access::Extern | Access::Public => 0x0006,
+            Access::Private => 0x0001,
+        };
+        // hidebysig
+        flags |= 0x0080;
+        match method.kind() {
+            MethodKind::Static => flags |= 0x0010,
+            MethodKind::Instance => (),
+            MethodKind::Virtual => flags |= 0x0040,
+            MethodKind::Constructor => flags |= 0x0800 | 0x1000,
+        }
+        if name == ".cctor" {
+            // specialname rtspecialname
+            flags |= 0x0800 | 0x1000;
+        }
+        let is_extern = method.implementation().is_extern();
+        if is_extern {
+            // pinvokeimpl
+            flags |= 0x2000;
+        }
+        // preservesig, for pinvoke methods.
+        let impl_flags: u16 = if is_extern { 0x0080 } else { 0x0000 };
+        let sig = self.asm[method.sig()].clone();
+        let sig_blob = self.method_sig(&sig, method.kind(), 0);
+        let sig_blob = self.blobs.alloc(&sig_blob);
+        let name_idx = self.strings.alloc(&name);
+        let param_list = self.tables.next_row(TableId::Param).row();
+        let token = self.tables.push(
+            TableId::MethodDef,
+            [
+                Column::U32(0),
+                Column::U16(impl_flags),
+                Column::U16(flags),
+                Column::String(name_idx),
+                Column::Blob(sig_blob),
+                Column::Index(TableId::Param, param_list),
+            ],
+        );
+        self.method_defs.insert(method_id, token);
+        let explicit_inputs = match method.kind() {
+            MethodKind::Static => sig.inputs().len(),
+            _ => sig.inputs().len().saturating_sub(1),
+        };
+        for (idx, arg_name) in method.arg_names().iter().take(explicit_inputs).enumerate() {
+            let Some(arg_name) = arg_name else {
+                continue;
+            };
+            let arg_name = self.strings.alloc(&self.asm[*arg_name]);
+            self.tables.push(
+                TableId::Param,
+                [
+                    Column::U16(0),
+                    Column::U16(u16::try_from(idx + 1).expect("Too many arguments")),
+                    Column::String(arg_name),
+                ],
+            );
+        }
+        if let crate::v2::MethodImpl::Extern {
+            lib,
+            preserve_errno,
+        } = method.implementation()
+        {
+            let lib = self.asm[*lib].to_owned();
+            let scope = self.module_ref(&lib);
+            // The calling convention, optionally with SupportsLastError
+            let mapping_flags: u16 = match sig.conv() {
+                // .NET only supports the native calling convention of the platform on 64 bit targets, and cdecl maps to it.
+                CallConv::Rust | CallConv::C | CallConv::Win64 | CallConv::SysV64 => 0x0200,
+                CallConv::System => 0x0100,
+                CallConv::StdCall => 0x0300,
+                CallConv::ThisCall => 0x0400,
+                CallConv::FastCall => 0x0500,
+            };
+            let mapping_flags = if *preserve_errno {
+                mapping_flags | 0x0040
+            } else {
+                mapping_flags
+            };
+            self.tables.push(
+                TableId::ImplMap,
+                [
+                    Column::U16(mapping_flags),
+                    Column::Coded(CodedIndex::MemberForwarded, Some(token)),
+                    Column::String(name_idx),
+                    Column::Index(TableId::ModuleRef, scope.row()),
+                ],
+            );
+        }
+        token
+    }
+    /// Sets the RVA of a method body.
+    pub(super) fn set_method_rva(&mut self, method: MetadataToken, rva: u32) {
+        self.tables.set(method, 0, Column::U32(rva));
+    }
+    pub(super) fn assembly_ref(&mut self, name: &str) -> MetadataToken {
+        if let Some(token) = self.assembly_refs.get(name) {
+            return *token;
+        }
+        let name_idx = self.strings.alloc(name);
+        let token = self.tables.push(
+            TableId::AssemblyRef,
+            [
+                Column::U16(0),
+                Column::U16(0),
+                Column::U16(0),
+                Column::U16(0),
+                Column::U32(0),
+                Column::Blob(0),
+                Column::String(name_idx),
+                Column::String(0),
+                Column::Blob(0),
+            ],
+        );
+        self.assembly_refs.insert(name.into(), token);
+        token
+    }
+    pub(super) fn module_ref(&mut self, name: &str) -> MetadataToken {
+        if let Some(token) = self.module_refs.get(name) {
+            return *token;
+        }
+        let name_idx = self.strings.alloc(name);
+        let token = self
+            .tables
+            .push(TableId::ModuleRef, [Column::String(name_idx)]);
+        self.module_refs.insert(name.into(), token);
+        token
+    }
+    /// Returns a `TypeRef` to a type called `name` in `assembly`. Nested types are separated using `/`.
+    pub(super) fn type_ref(&mut self, assembly: Option<&str>, name: &str) -> MetadataToken {
+        let key = (assembly.map(Into::into), name.into());
+        if let Some(token) = self.type_refs.get(&key) {
+            return *token;
+        }
+        let scope = if let Some((outer, _)) = name.rsplit_once('/') {
+            self.type_ref(assembly, outer)
+        } else {
+            match assembly {
+                Some(assembly) => self.assembly_ref(assembly),
+                None => MetadataToken::new(TableId::Module, 1),
+            }
+        };
+        let simple_name = name.rsplit_once('/').map_or(name, |(_, inner)| inner);
+        let (namespace, simple_name) = if name.contains('/') {
+            ("", simple_name)
+        } else {
+            split_name(simple_name)
+        };
+        let namespace = self.strings.alloc(namespace);
+        let simple_name = self.strings.alloc(simple_name);
+        let token = self.tables.push(
+            TableId::TypeRef,
+            [
+                Column::Coded(CodedIndex::ResolutionScope, Some(scope)),
+                Column::String(simple_name),
+                Column::String(namespace),
+            ],
+        );
+        self.type_refs.insert(key, token);
+        token
+    }
+    /// Returns a `TypeDef`, `TypeRef` or `TypeSpec` token for a class.
+    pub(super) fn type_def_or_ref(&mut self, cref_idx: ClassRefIdx) -> MetadataToken {
+        let cref = self.asm[cref_idx].clone();
+        if !cref.generics().is_empty() {
+            let mut sig = vec![];
+            self.encode_type(&Type::ClassRef(cref_idx), &mut sig);
+            return self.type_spec(sig);
+        }
+        self.open_type_def_or_ref(cref_idx)
+    }
+    /// Like [`Self::type_def_or_ref`], but ignores generic arguments.
+    fn open_type_def_or_ref(&mut self, cref_idx: ClassRefIdx) -> MetadataToken {
+        let cref = self.asm[cref_idx].clone();
+        match cref.asm() {
+            Some(assembly) => {
+                let assembly = self.asm[assembly].to_owned();
+                let name = if cref.generics().is_empty() {
+                    self.asm[cref.name()].to_owned()
+                } else {
+                    format!("{}`{}", &self.asm[cref.name()], cref.generics().len())
+                };
+                self.type_ref(Some(&assembly), &name)
+            }
+            None => {
+                if let Some(token) = self
+                    .asm
+                    .class_ref_to_def(cref_idx)
+                    .and_then(|def| self.type_defs.get(&def))
+                {
+                    return *token;
+                }
+                if let Some(token) = self.type_defs_by_name.get(&cref.name()) {
+                    return *token;
+                }
+                let name = self.asm[cref.name()].to_owned();
+                self.type_ref(None, &name)
+            }
+        }
+    }
+    /// Returns a token usable as an operand of instructions like `ldobj` or `sizeof`.
+    pub(super) fn type_token(&mut self, tpe: &Type) -> MetadataToken {
+        match tpe {
+            Type::ClassRef(cref) => self.type_def_or_ref(*cref),
+            Type::PlatformString => self.type_ref(Some(CORE_ASSEMBLY), "System.String"),
+            Type::PlatformObject => self.type_ref(Some(CORE_ASSEMBLY), "System.Object"),
+            _ => {
+                let mut sig = vec![];
+                self.encode_non_void_type(tpe, &mut sig);
+                self.type_spec(sig)
+            }
+        }
+    }
+    fn type_spec(&mut self, sig: Vec<u8>) -> MetadataToken {
+        if let Some(token) = self.type_specs.get(&sig) {
+            return *token;
+        }
+        let blob = self.blobs.alloc(&sig);
+        let token = self.tables.push(TableId::TypeSpec, [Column::Blob(blob)]);
+        self.type_specs.insert(sig, token);
+        token
+    }
+    fn encode_type_def_or_ref(&mut self, token: MetadataToken, out: &mut Vec<u8>) {
+        write_compressed_u32(CodedIndex::TypeDefOrRef.encode(token), out);
+    }
+    /// Encodes a type, replacing `void` with `RustVoid`. `void` can only be used as a return type.
+    pub(super) fn encode_non_void_type(&mut self, tpe: &Type, out: &mut Vec<u8>) {
+        if *tpe == Type::Void {
+            let rust_void = self.local_class_by_name("RustVoid");
+            out.push(ELEMENT_TYPE_VALUETYPE);
+            self.encode_type_def_or_ref(rust_void, out);
+        } else {
+            self.encode_type(tpe, out);
+        }
+    }
+    fn local_class_by_name(&mut self, name: &str) -> MetadataToken {
+        let token = self
+            .asm
+            .get_prealllocated_string(name)
+            .and_then(|name| self.type_defs_by_name.get(&name).copied());
+        token.unwrap_or_else(|| self.type_ref(None, name))
+    }
+    /// Encodes a type, as described in II.23.2.12
+    pub(super) fn encode_type(&mut self, tpe: &Type, out: &mut Vec<u8>) {
+        match tpe {
+            Type::Ptr(inner) => {
+                out.push(ELEMENT_TYPE_PTR);
+                let inner = self.asm[*inner];
+                self.encode_type(&inner, out);
+            }
+            Type::Ref(inner) => {
+                out.push(ELEMENT_TYPE_BYREF);
+                let inner = self.asm[*inner];
+                self.encode_non_void_type(&inner, out);
+            }
+            Type::Int(int) => match int {
+                Int::U8 => out.push(ELEMENT_TYPE_U1),
+                Int::U16 => out.push(ELEMENT_TYPE_U2),
+                Int::U32 => out.push(ELEMENT_TYPE_U4),
+                Int::U64 => out.push(ELEMENT_TYPE_U8),
+                Int::USize => out.push(ELEMENT_TYPE_U),
+                Int::I8 => out.push(ELEMENT_TYPE_I1),
+                Int::I16 => out.push(ELEMENT_TYPE_I2),
+                Int::I32 => out.push(ELEMENT_TYPE_I4),
+                Int::I64 => out.push(ELEMENT_TYPE_I8),
+                Int::ISize => out.push(ELEMENT_TYPE_I),
+                Int::U128 => {
+                    let tref = self.type_ref(Some(CORE_ASSEMBLY), "System.UInt128");
+                    out.push(ELEMENT_TYPE_VALUETYPE);
+                    self.encode_type_def_or_ref(tref, out);
+                }
+                Int::I128 => {
+                    let tref = self.type_ref(Some(CORE_ASSEMBLY), "System.Int128");
+                    out.push(ELEMENT_TYPE_VALUETYPE);
+                    self.encode_type_def_or_ref(tref, out);
+                }
+            },
+            Type::ClassRef(cref_idx) => {
+                let cref = self.asm[*cref_idx].clone();
+                let kind = if cref.is_valuetype() {
+                    ELEMENT_TYPE_VALUETYPE
+                } else {
+                    ELEMENT_TYPE_CLASS
+                };
+                let open = self.open_type_def_or_ref(*cref_idx);
+                if cref.generics().is_empty() {
+                    out.push(kind);
+                    self.encode_type_def_or_ref(open, out);
+                } else {
+                    out.push(ELEMENT_TYPE_GENERICINST);
+                    out.push(kind);
+                    self.encode_type_def_or_ref(open, out);
+                    write_compressed_u32(cref.generics().len() as u32, out);
+                    for generic in cref.generics() {
+                        self.encode_type(generic, out);
+                    }
+                }
+            }
+            Type::Float(float) => match float {
+                Float::F16 => {
+                    let tref = self.type_ref(Some(CORE_ASSEMBLY), "System.Half");
+                    out.push(ELEMENT_TYPE_VALUETYPE);
+                    self.encode_type_def_or_ref(tref, out);
+                }
+                Float::F32 => out.push(ELEMENT_TYPE_R4),
+                Float::F64 => out.push(ELEMENT_TYPE_R8),
+                Float::F128 => {
+                    let f128 = self.local_class_by_name("f128");
+                    out.push(ELEMENT_TYPE_VALUETYPE);
+                    self.encode_type_def_or_ref(f128, out);
+                }
+            },
+            Type::PlatformString => out.push(ELEMENT_TYPE_STRING),
+            Type::PlatformChar => out.push(ELEMENT_TYPE_CHAR),
+            Type::PlatformGeneric(idx, kind) => {
+                match kind {
+                    GenericKind::MethodGeneric | GenericKind::TypeGeneric => {
+                        out.push(ELEMENT_TYPE_VAR);
+                    }
+                    GenericKind::CallGeneric => out.push(ELEMENT_TYPE_MVAR),
+                }
+                write_compressed_u32(*idx, out);
+            }
+            Type::PlatformObject => out.push(ELEMENT_TYPE_OBJECT),
+            Type::Bool => out.push(ELEMENT_TYPE_BOOLEAN),
+            Type::Void => out.push(ELEMENT_TYPE_VOID),
+            Type::PlatformArray { elem, dims } => {
+                let elem = self.asm[*elem];
+                if dims.get() == 1 {
+                    out.push(ELEMENT_TYPE_SZARRAY);
+                    self.encode_non_void_type(&elem, out);
+                } else {
+                    out.push(ELEMENT_TYPE_ARRAY);
+                    self.encode_non_void_type(&elem, out);
+                    write_compressed_u32(u32::from(dims.get()), out);
+                    // No sizes, no lower bounds
+                    out.push(0);
+                    out.push(0);
+                }
+            }
+            Type::FnPtr(sig) => {
+                out.push(ELEMENT_TYPE_FNPTR);
+                let sig = self.asm[*sig].clone();
+                let sig = self.method_sig(&sig, MethodKind::Static, 0);
+                out.extend(sig);
+            }
+            Type::SIMDVector(simdvec) => {
+                let bits = simdvec.bits();
+                assert!(
+                    bits == 64 || bits == 128 || bits == 256 || bits == 512,
+                    "Unusported SIMD vector size"
+                );
+                let vec = self.type_ref(
+                    Some("System.Runtime.Intrinsics"),
+                    &format!("System.Runtime.Intrinsics.Vector{bits}`1"),
+                );
+                out.push(ELEMENT_TYPE_GENERICINST);
+                out.push(ELEMENT_TYPE_VALUETYPE);
+                self.encode_type_def_or_ref(vec, out);
+                out.push(1);
+                let elem = match simdvec.elem() {
+                    SIMDElem::Int(int) => Type::Int(int),
+                    SIMDElem::Float(float) => Type::Float(float),
+                };
+                self.encode_type(&elem, out);
+            }
+        }
+    }
+    /// Encodes a method signature. For non-static methods, the first input is treated as `this`.
+    /// The variable arguments of a C-variadic call site are preceded by a sentinel.
+    pub(super) fn method_sig(&mut self, sig: &FnSig, kind: MethodKind, generics: usize) -> Vec<u8> {
+        let mut out = vec![];
+        let mut flags = match kind {
+            MethodKind::Static => 0,
+            MethodKind::Instance | MethodKind::Virtual | MethodKind::Constructor => SIG_HASTHIS,
+        };
+        if generics > 0 {
+            flags |= SIG_GENERIC;
+        }
+        if sig.is_variadic() {
+            flags |= SIG_VARARG;
+        }
+        out.push(flags);
+        if generics > 0 {
+            write_compressed_u32(generics as u32, &mut out);
+        }
+        let inputs = match kind {
+            MethodKind::Static => sig.inputs(),
+            MethodKind::Instance | MethodKind::Virtual | MethodKind::Constructor => {
+                &sig.inputs()[1..]
+            }
+        };
+        write_compressed_u32(inputs.len() as u32, &mut out);
+        let output = match kind {
+            MethodKind::Constructor => Type::Void,
+            _ => *sig.output(),
+        };
+        self.encode_type(&output, &mut out);
+        let sentinel = inputs.len() - sig.variable_inputs().len();
+        for (idx, input) in inputs.iter().enumerate() {
+            if idx == sentinel {
+                out.push(ELEMENT_TYPE_SENTINEL);
+            }
+            self.encode_non_void_type(input, &mut out);
+        }
+        out
+    }
+    fn member_ref(&mut self, parent: MetadataToken, name: &str, sig: &[u8]) -> MetadataToken {
+        let name = self.strings.alloc(name);
+        let sig = self.blobs.alloc(sig);
+        if let Some(token) = self.member_refs.get(&(parent, name, sig)) {
+            return *token;
+        }
+        let token = self.tables.push(
+            TableId::MemberRef,
+            [
+                Column::Coded(CodedIndex::MemberRefParent, Some(parent)),
+                Column::String(name),
+                Column::Blob(sig),
+            ],
+        );
+        self.member_refs.insert((parent, name, sig), token);
+        token
+    }
+    /// Returns a token referencing the parameterless constructor of an attribute.
+    pub(super) fn attribute_ctor(&mut self, attr: MetadataToken) -> MetadataToken {
+        self.member_ref(attr, ".ctor", &[SIG_HASTHIS, 0, ELEMENT_TYPE_VOID])
+    }
+    pub(super) fn custom_attribute(
+        &mut self,
+        parent: MetadataToken,
+        ctor: MetadataToken,
+        value: &[u8],
+    ) {
+        let value = self.blobs.alloc(value);
+        self.tables.push(
+            TableId::CustomAttribute,
+            [
+                Column::Coded(CodedIndex::HasCustomAttribute, Some(parent)),
+                Column::Coded(CodedIndex::CustomAttributeType, Some(ctor)),
+                Column::Blob(value),
+            ],
+        );
+    }
+    /// Returns a `MethodDef`, `MemberRef` or `MethodSpec` token referencing a method.
+    pub(super) fn method_token(&mut self, mref_idx: MethodRefIdx) -> MetadataToken {
+        let mref = self.asm[mref_idx].clone();
+        // A call site passing variable arguments is a `MemberRef`, whose parent is the definition of the C-variadic function(II.22.25).
+        if let Some(def) = self
+            .asm
+            .variadic_def_ref(mref_idx)
+            .and_then(|def_ref| self.asm.method_ref_to_def(def_ref))
+            .and_then(|def| self.method_defs.get(&def).copied())
+        {
+            let sig = self.asm[mref.sig()].clone();
+            let sig = self.method_sig(&sig, mref.kind(), 0);
+            let name = self.asm[mref.name()].to_owned();
+            return self.member_ref(def, &name, &sig);
+        }
+        if mref.generics().is_empty() {
+            if let Some(token) = self
+                .asm
+                .method_ref_to_def(mref_idx)
+                .and_then(|def| self.method_defs.get(&def))
+            {
+                return *token;
+            }
+        }
+        let parent = self.type_def_or_ref(mref.class());
+        let sig = self.asm[mref.sig()].clone();
+        let sig = self.method_sig(&sig, mref.kind(), mref.generics().len());
+        let name = self.asm[mref.name()].to_owned();
+        let method = self.member_ref(parent, &name, &sig);
+        if mref.generics().is_empty() {
+            return method;
+        }
+        let mut inst = vec![SIG_GENERICINST];
+        write_compressed_u32(mref.generics().len() as u32, &mut inst);
+        for generic in mref.generics() {
+            self.encode_type(generic, &mut inst);
+        }
+        let inst = self.blobs.alloc(&inst);
+        if let Some(token) = self.method_specs.get(&(method, inst)) {
+            return *token;
+        }
+        let token = self.tables.push(
+            TableId::MethodSpec,
+            [
+                Column::Coded(CodedIndex::MethodDefOrRef, Some(method)),
+                Column::Blob(inst),
+            ],
+        );
+        self.method_specs.insert((method, inst), token);
+        token
+    }
+    /// Returns a `Field` or `MemberRef` token referencing an instance field.
+    pub(super) fn field_token(&mut self, field: FieldIdx) -> MetadataToken {
+        let desc = *self.asm.get_field(field);
+        if let Some(token) = self.fields.get(&(desc.owner(), desc.name(), desc.tpe())) {
+            return *token;
+        }
+        self.field_member_ref(desc.owner(), desc.name(), desc.tpe())
+    }
+    /// Returns a `Field` or `MemberRef` token referencing a static field.
+    pub(super) fn static_field_token(&mut self, field: StaticFieldIdx) -> MetadataToken {
+        let desc = *self.asm.get_static_field(field);
+        if let Some(token) = self
+            .static_fields
+            .get(&(desc.owner(), desc.name(), desc.tpe()))
+        {
+            return *token;
+        }
+        self.field_member_ref(desc.owner(), desc.name(), desc.tpe())
+    }
+    fn field_member_ref(
+        &mut self,
+        owner: ClassRefIdx,
+        name: StringIdx,
+        tpe: Type,
+    ) -> MetadataToken {
+        let parent = self.type_def_or_ref(owner);
+        let mut sig = vec![SIG_FIELD];
+        self.encode_non_void_type(&tpe, &mut sig);
+        let name = self.asm[name].to_owned();
+        self.member_ref(parent, &name, &sig)
+    }
+    /// Returns a `StandAloneSig` token describing a list of locals.
+    pub(super) fn locals_sig(&mut self, locals: &[Type]) -> MetadataToken {
+        let mut sig = vec![SIG_LOCAL];
+        write_compressed_u32(locals.len() as u32, &mut sig);
+        for local in locals {
+            self.encode_non_void_type(local, &mut sig);
+        }
+        self.stand_alone_sig(sig)
+    }
+    /// Returns a `StandAloneSig` token describing the signature used by `calli`.
+    pub(super) fn calli_sig(&mut self, sig: &FnSig) -> MetadataToken {
+        let sig = self.method_sig(sig, MethodKind::Static, 0);
+        self.stand_alone_sig(sig)
+    }
+    fn stand_alone_sig(&mut self, sig: Vec<u8>) -> MetadataToken {
+        if let Some(token) = self.stand_alone_sigs.get(&sig) {
+            return *token;
+        }
+        let blob = self.blobs.alloc(&sig);
+        let token = self
+            .tables
+            .push(TableId::StandAloneSig, [Column::Blob(blob)]);
+        self.stand_alone_sigs.insert(sig, token);
+        token
+    }
+    /// Returns the token `ldstr` uses to reference `string`.
+    pub(super) fn user_string(&mut self, string: &str) -> u32 {
+        0x7000_0000 | self.user_strings.alloc(string)
+    }
+    /// Returns the token of the `MethodDef`, which should be used as the entrypoint.
+    pub(super) fn entrypoint(&self) -> Option<MetadataToken> {
+        self.method_defs
+            .iter()
+            .find(|(def, _)| &self.asm[self.asm[**def].name()] == crate::v2::asm::ENTRYPOINT)
+            .map(|(_, token)| *token)
+    }
+}
+/// Splits a type name into its namespace and simple name.
+/// ```
+/// # use cilly::v2::pe_exporter::metadata::split_name;
+/// assert_eq!(split_name("System.Runtime.Intrinsics.Vector128`1"), ("System.Runtime.Intrinsics", "Vector128`1"));
+/// assert_eq!(split_name("RustVoid"), ("", "RustVoid"));
+/// ```
+#[must_use]
+pub fn split_name(name: &str) -> (&str, &str) {
+    name.rsplit_once('.').unwrap_or(("", name))
+}
+/// Derives a module version id from the contents of the assembly, so that builds are reproducible.
+fn module_mvid(asm: &Assembly, module_name: &str) -> [u8; 16] {
+    let low = fxhash::hash64(&(module_name, asm.method_refs().len()));
+    let high = fxhash::hash64(&(asm.strings().len(), module_name));
+    let mut mvid = [0; 16];
+    mvid[..8].copy_from_slice(&low.to_le_bytes());
+    mvid[8..].copy_from_slice(&high.to_le_bytes());
+    // Mark this as a version 4 (random) GUID.
+    mvid[7] = (mvid[7] & 0x0F) | 0x40;
+    mvid[8] = (mvid[8] & 0x3F) | 0x80;
+    mvid
+}
+#[test]
+fn encode_types() {
+    let mut builder = MetadataBuilder::new(Assembly::default());
+    let mut out = vec![];
+    builder.encode_type(&Type::Int(Int::I32), &mut out);
+    assert_eq!(out, vec![ELEMENT_TYPE_I4]);
+    out.clear();
+    let u8_idx = builder.asm.alloc_type(Type::Int(Int::U8));
+    builder.encode_type(&Type::Ptr(u8_idx), &mut out);
+    assert_eq!(out, vec![ELEMENT_TYPE_PTR, ELEMENT_TYPE_U1]);
+    out.clear();
+    builder.encode_type(&Type::Int(Int::I128), &mut out);
+    assert_eq!(out[0], ELEMENT_TYPE_VALUETYPE);
+    assert_eq!(builder.tables.len(TableId::TypeRef), 1);
+    assert_eq!(builder.tables.len(TableId::AssemblyRef), 1);
+}
+#[test]
+fn method_sigs() {
+    let mut builder = MetadataBuilder::new(Assembly::default());
+    let sig = FnSig::new(
+        [Type::PlatformObject, Type::Int(Int::I32)].into(),
+        Type::Bool,
+    );
+    assert_eq!(
+        builder.method_sig(&sig, MethodKind::Static, 0),
+        vec![
+            0x00,
+            2,
+            ELEMENT_TYPE_BOOLEAN,
+            ELEMENT_TYPE_OBJECT,
+            ELEMENT_TYPE_I4
+        ]
+    );
+    assert_eq!(
+        builder.method_sig(&sig, MethodKind::Instance, 0),
+        vec![SIG_HASTHIS, 1, ELEMENT_TYPE_BOOLEAN, ELEMENT_TYPE_I4]
+    );
+    assert_eq!(
+        builder.method_sig(&sig, MethodKind::Constructor, 0),
+        vec![SIG_HASTHIS, 1, ELEMENT_TYPE_VOID, ELEMENT_TYPE_I4]
+    );
+}
+#[test]
+fn vararg_sigs() {
+    let mut builder = MetadataBuilder::new(Assembly::default());
+    let call_site = FnSig::new(
+        [
+            Type::Int(Int::USize),
+            Type::Int(Int::I32),
+            Type::Float(Float::F64),
+        ]
+        .into(),
+        Type::Int(Int::I32),
+    )
+    .with_variadic(1);
+    assert_eq!(
+        builder.method_sig(&call_site, MethodKind::Static, 0),
+        vec![
+            SIG_VARARG,
+            3,
+            ELEMENT_TYPE_I4,
+            ELEMENT_TYPE_U,
+            ELEMENT_TYPE_SENTINEL,
+            ELEMENT_TYPE_I4,
+            ELEMENT_TYPE_R8
+        ]
+    );
+    // The definition has no sentinel.
+    assert_eq!(
+        builder.method_sig(&call_site.fixed_sig(), MethodKind::Static, 0),
+        vec![SIG_VARARG, 1, ELEMENT_TYPE_I4, ELEMENT_TYPE_U]
+    );
+}
+#[test]
+fn interface_impls() {
+    use crate::v2::{ClassDef, ClassRef, MethodDef, MethodImpl};
+    let mut asm = Assembly::default();
+    let name = asm.alloc_string("Handle");
+    let disposable = ClassRef::disposable(&mut asm);
+    let mut def = ClassDef::new(
+        name,
+        false,
+        0,
+        None,
+        vec![],
+        vec![],
+        Access::Public,
+        None,
+        None,
+    );
+    def.add_interface(disposable);
+    let class = asm.class_def(def);
+    let dispose = asm.alloc_string("Dispose");
+    let sig = asm.sig([Type::ClassRef(*class)], Type::Void);
+    let body = asm.new_method(MethodDef::new(
+        Access::Public,
+        class,
+        dispose,
+        sig,
+        MethodKind::Virtual,
+        MethodImpl::Missing,
+        vec![None],
+    ));
+    let decl = asm[disposable]
+        .clone()
+        .virtual_mref(&[], Type::Void, dispose, &mut asm);
+    asm.class_mut(class).add_override(decl, body);
+    let mut builder = MetadataBuilder::new(asm);
+    builder.define_module("handle.dll");
+    builder.define_types();
+    assert_eq!(builder.tables.len(TableId::InterfaceImpl), 1);
+    assert_eq!(builder.tables.len(TableId::MethodImpl), 1);
+    // The interface method is referenced trough a `MemberRef`.
+    assert_eq!(builder.tables.len(TableId::MemberRef), 1);
+}
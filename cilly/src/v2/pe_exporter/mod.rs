@@ -0,0 +1,275 @@
+//! Writes assemblies directly as ECMA-335 PE files, without going trough `ilasm`.
+use std::path::Path;
+
+use fxhash::FxHashMap;
+
+use super::{Assembly, Exporter};
+
+mod body;
+pub use body::BodyError;
+pub mod heaps;
+mod image;
+pub mod metadata;
+pub mod pdb;
+pub mod reader;
+pub mod tables;
+
+/// An error encountered while exporting an assembly as a PE file.
+#[derive(Debug)]
+pub enum ExportError {
+    /// The body of the method `method` contains a node, which can't be encoded.
+    Body { method: String, error: BodyError },
+    /// The PE file or its PDB could not be written.
+    Io(std::io::Error),
+}
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Body { method, error } => write!(f, "in method {method}: {error}"),
+            Self::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+impl std::error::Error for ExportError {}
+impl From<std::io::Error> for ExportError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+pub struct PEExporter {
+    is_lib: bool,
+}
+impl PEExporter {
+    #[must_use]
+    pub fn new(is_lib: bool) -> Self {
+        Self { is_lib }
+    }
+    /// Encodes `asm` as a PE file, returning its bytes.
+    pub fn export_to_bytes(
+        &self,
+        asm: &Assembly,
+        module_name: &str,
+    ) -> Result<Vec<u8>, ExportError> {
+        Ok(self.encode(asm, module_name, None)?.0)
+    }
+    /// Encodes `asm` as a PE file, together with its Portable PDB, which should be saved at `pdb_path`.
+    /// Returns the bytes of the PE file and of the PDB.
+    pub fn export_with_pdb(
+        &self,
+        asm: &Assembly,
+        module_name: &str,
+        pdb_path: &str,
+    ) -> Result<(Vec<u8>, Vec<u8>), ExportError> {
+        let (pe, pdb) = self.encode(asm, module_name, Some(pdb_path))?;
+        Ok((pe, pdb.expect("No PDB emitted")))
+    }
+    fn encode(
+        &self,
+        asm: &Assembly,
+        module_name: &str,
+        pdb_path: Option<&str>,
+    ) -> Result<(Vec<u8>, Option<Vec<u8>>), ExportError> {
+        let mut builder = metadata::MetadataBuilder::new(asm.clone());
+        builder.define_module(module_name);
+        let methods = builder.define_types();
+        let mut il = vec![];
+        let mut debug_info = FxHashMap::default();
+        for (def, token) in methods {
+            let method = builder.asm[def].clone();
+            let mimpl = method.resolved_implementation(&builder.asm).clone();
+            let name = builder.asm[method.name()].to_owned();
+            let (body, debug) = body::BodyEncoder::new(&mut builder)
+                .encode(&mimpl, &name)
+                .map_err(|error| ExportError::Body {
+                    method: name.clone(),
+                    error,
+                })?;
+            debug_info.insert(token.row(), debug);
+            // Fat method headers must be 4 byte aligned.
+            while !il.len().is_multiple_of(4) {
+                il.push(0);
+            }
+            let rva = image::TEXT_RVA
+                + image::IL_OFFSET
+                + u32::try_from(il.len()).expect("Method bodies too big");
+            builder.set_method_rva(token, rva);
+            il.extend(body);
+        }
+        let entrypoint = builder.entrypoint();
+        let pdb = pdb_path.map(|pdb_path| {
+            let (pdb, id) = pdb::write_pdb(&builder.asm, &builder.tables, &debug_info, entrypoint);
+            (pdb, id, pdb_path)
+        });
+        let tables = builder.tables.serialize(&builder.heap_sizes());
+        let mut strings = builder.strings.data().to_vec();
+        let mut user_strings = builder.user_strings.data().to_vec();
+        let mut blobs = builder.blobs.data().to_vec();
+        for stream in [&mut strings, &mut user_strings, &mut blobs] {
+            while !stream.len().is_multiple_of(4) {
+                stream.push(0);
+            }
+        }
+        let metadata = image::metadata_root(
+            image::ASSEMBLY_VERSION,
+            &[
+                ("#~", &tables),
+                ("#Strings", &strings),
+                ("#US", &user_strings),
+                ("#GUID", builder.guids.data()),
+                ("#Blob", &blobs),
+            ],
+        );
+        let image = image::write_image(
+            &il,
+            &metadata,
+            entrypoint,
+            self.is_lib,
+            pdb.as_ref().map(|(_, id, path)| (id, *path)),
+        );
+        Ok((image, pdb.map(|(pdb, _, _)| pdb)))
+    }
+}
+impl Exporter for PEExporter {
+    type Error = ExportError;
+
+    fn export(&self, asm: &Assembly, target: &Path) -> Result<(), Self::Error> {
+        let exe_out = target.with_extension("exe");
+        let pdb_out = target.with_extension("pdb");
+        let module_name = exe_out
+            .file_name()
+            .map_or("_.exe".into(), |name| name.to_string_lossy());
+        let pdb_name = pdb_out
+            .file_name()
+            .map_or("_.pdb".into(), |name| name.to_string_lossy());
+        let (exe, pdb) = self.export_with_pdb(asm, &module_name, &pdb_name)?;
+        std::fs::write(exe_out, exe)?;
+        std::fs::write(pdb_out, pdb)?;
+        Ok(())
+    }
+}
+#[test]
+fn export_empty() {
+    let asm = Assembly::default();
+    let bytes = PEExporter::new(false)
+        .export_to_bytes(&asm, "empty.exe")
+        .unwrap();
+    assert_eq!(&bytes[0..2], b"MZ");
+    assert_eq!(&bytes[0x80..0x84], b"PE\0\0");
+    assert_eq!(bytes.len() % 0x200, 0);
+    assert!(bytes.windows(4).any(|window| window == b"BSJB"));
+}
+#[test]
+fn export_main_module() {
+    use super::{BasicBlock, CILRoot, Const, MethodImpl};
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let ret = asm.alloc_node(Const::I32(42));
+    let ret = asm.alloc_root(CILRoot::Ret(ret));
+    let msg = asm.ldstr("Hello");
+    let msg = asm.alloc_node(msg);
+    let pop = asm.alloc_root(CILRoot::Pop(msg));
+    let sig = asm.sig([], super::Type::Int(super::Int::I32));
+    let name = asm.alloc_string(super::asm::ENTRYPOINT);
+    asm.new_method(super::MethodDef::new(
+        super::Access::Public,
+        main_module,
+        name,
+        sig,
+        super::cilnode::MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![pop, ret], 0, None)],
+            locals: vec![],
+        },
+        vec![],
+    ));
+    let bytes = PEExporter::new(false)
+        .export_to_bytes(&asm, "main.exe")
+        .unwrap();
+    // `ldstr`, `pop`, `ldc.i4.s 42`, `ret`
+    let body = [0x72, 0x01, 0x00, 0x00, 0x70, 0x26, 0x1F, 42, 0x2A];
+    assert!(bytes.windows(body.len()).any(|window| window == body));
+    assert!(bytes
+        .windows(b"_CorExeMain".len())
+        .any(|window| window == b"_CorExeMain"));
+}
+#[test]
+fn export_pdb() {
+    use super::{BasicBlock, CILRoot, MethodImpl};
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let file = asm.alloc_string("src/main.rs");
+    let line = asm.alloc_root(CILRoot::SourceFileInfo {
+        line_start: 3,
+        line_len: 0,
+        col_start: 5,
+        col_len: 10,
+        file,
+    });
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    let sig = asm.sig([], super::Type::Void);
+    let name = asm.alloc_string(super::asm::ENTRYPOINT);
+    asm.new_method(super::MethodDef::new(
+        super::Access::Public,
+        main_module,
+        name,
+        sig,
+        super::cilnode::MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![line, ret], 0, None)],
+            locals: vec![],
+        },
+        vec![],
+    ));
+    let (pe, pdb) = PEExporter::new(false)
+        .export_with_pdb(&asm, "main.exe", "main.pdb")
+        .unwrap();
+    assert_eq!(&pdb[0..4], b"BSJB");
+    assert!(pdb.windows(8).any(|window| window == b"PDB v1.0"));
+    assert!(pdb.windows(4).any(|window| window == b"#Pdb"));
+    // The PE file must point to the PDB, using its id. The first stream header is right after the 12 byte version.
+    let pdb_stream = u32::from_le_bytes(pdb[32..36].try_into().unwrap()) as usize;
+    let rsds = pe
+        .windows(4)
+        .position(|window| window == b"RSDS")
+        .expect("No CodeView entry");
+    assert_eq!(&pe[rsds + 4..rsds + 20], &pdb[pdb_stream..pdb_stream + 16]);
+    assert!(pe[rsds + 24..].starts_with(b"main.pdb\0"));
+}
+#[test]
+fn export_unsupported() {
+    use super::{cilnode::ExtendKind, BasicBlock, CILNode, CILRoot, Const, Int, MethodImpl};
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let val = asm.alloc_node(Const::I32(1));
+    let cast = asm.alloc_node(CILNode::IntCast {
+        input: val,
+        target: Int::I128,
+        extend: ExtendKind::SignExtend,
+    });
+    let pop = asm.alloc_root(CILRoot::Pop(cast));
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    let sig = asm.sig([], super::Type::Void);
+    let name = asm.alloc_string("cast");
+    asm.new_method(super::MethodDef::new(
+        super::Access::Public,
+        main_module,
+        name,
+        sig,
+        super::cilnode::MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![pop, ret], 0, None)],
+            locals: vec![],
+        },
+        vec![],
+    ));
+    let err = PEExporter::new(true)
+        .export_to_bytes(&asm, "cast.dll")
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ExportError::Body {
+            ref method,
+            error: BodyError::IntCastTo128
+        } if method == "cast"
+    ));
+}
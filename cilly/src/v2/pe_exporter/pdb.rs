@@ -0,0 +1,297 @@
+//! Writes Portable PDB files, mapping the IL of the methods emitted by the PE exporter back to the source code, using
+//! the [`crate::v2::CILRoot::SourceFileInfo`] roots. The format is described in the "Portable PDB v1.0" specification.
+use std::hash::Hasher;
+
+use fxhash::{FxHashMap, FxHasher64};
+
+use super::{
+    heaps::{write_compressed_i32, write_compressed_u32, BlobHeap, GuidHeap, StringHeap},
+    image,
+    tables::{Column, HeapSizes, MetadataToken, TableId, Tables},
+};
+use crate::v2::{Assembly, StringIdx};
+
+/// Identifies the source language of a document. No GUID is registered for Rust, so cilly uses its own.
+const RUST_LANGUAGE: [u8; 16] = [
+    0x1e, 0x3c, 0x8f, 0x47, 0x9a, 0x52, 0x4b, 0x1d, 0x8e, 0x6f, 0x2a, 0x0b, 0x7c, 0x45, 0xd1, 0x93,
+];
+/// Marks the PDB stream(PDB v1.0, "#Pdb stream") metadata root.
+const PDB_VERSION: &[u8] = b"PDB v1.0\0\0\0\0";
+/// The id of a PDB: a GUID, followed by a 4 byte stamp. The assembly stores it, to find the matching PDB.
+pub type PdbId = [u8; 20];
+/// The IL instructions starting at `il_offset` correspond to this source code position.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) struct SequencePoint {
+    pub(super) il_offset: u32,
+    pub(super) line_start: u32,
+    pub(super) line_len: u16,
+    pub(super) col_start: u16,
+    pub(super) col_len: u16,
+    pub(super) file: StringIdx,
+}
+/// The debug information of a single method body.
+#[derive(Default)]
+pub(super) struct MethodDebugInfo {
+    pub(super) sequence_points: Vec<SequencePoint>,
+    /// The names of the locals, together with their index.
+    pub(super) locals: Vec<(u16, Box<str>)>,
+    pub(super) locals_sig: Option<MetadataToken>,
+    pub(super) code_size: u32,
+}
+impl MethodDebugInfo {
+    /// Records a sequence point. A later sequence point at the same offset replaces the earlier one.
+    pub(super) fn push(&mut self, point: SequencePoint) {
+        // Line 0 means the position is unknown.
+        if point.line_start == 0 {
+            return;
+        }
+        match self.sequence_points.last_mut() {
+            Some(last) if last.il_offset == point.il_offset => *last = point,
+            _ => self.sequence_points.push(point),
+        }
+    }
+}
+/// Encodes the sequence points of a method(PDB v1.0, "Sequence Points Blob"). If the sequence points span more than one
+/// document, `single_document` is `None`.
+fn sequence_points_blob(
+    info: &MethodDebugInfo,
+    documents: &FxHashMap<StringIdx, u32>,
+    single_document: Option<u32>,
+) -> Vec<u8> {
+    let mut out = vec![];
+    write_compressed_u32(info.locals_sig.map_or(0, |sig| sig.row()), &mut out);
+    let mut current_document = documents[&info.sequence_points[0].file];
+    if single_document.is_none() {
+        write_compressed_u32(current_document, &mut out);
+    }
+    let mut prev_offset = None;
+    let mut prev_start: Option<(u32, u16)> = None;
+    for point in &info.sequence_points {
+        let document = documents[&point.file];
+        if document != current_document {
+            // A document record: a 0 IL offset delta, followed by the new document.
+            write_compressed_u32(0, &mut out);
+            write_compressed_u32(document, &mut out);
+            current_document = document;
+        }
+        write_compressed_u32(point.il_offset - prev_offset.unwrap_or(0), &mut out);
+        write_compressed_u32(u32::from(point.line_len), &mut out);
+        // A sequence point with no lines and no columns is hidden, so it must span at least one column.
+        let col_len = if point.line_len == 0 {
+            point.col_len.max(1)
+        } else {
+            point.col_len
+        };
+        if point.line_len == 0 {
+            write_compressed_u32(u32::from(col_len), &mut out);
+        } else {
+            write_compressed_i32(i32::from(col_len), &mut out);
+        }
+        match prev_start {
+            None => {
+                write_compressed_u32(point.line_start, &mut out);
+                write_compressed_u32(u32::from(point.col_start), &mut out);
+            }
+            Some((line, col)) => {
+                let delta_line = i64::from(point.line_start) - i64::from(line);
+                write_compressed_i32(
+                    i32::try_from(delta_line).expect("Line delta too big"),
+                    &mut out,
+                );
+                write_compressed_i32(i32::from(point.col_start) - i32::from(col), &mut out);
+            }
+        }
+        prev_offset = Some(point.il_offset);
+        prev_start = Some((point.line_start, point.col_start));
+    }
+    out
+}
+/// Encodes a document name(PDB v1.0, "Document Name Blob"), as a separator followed by the blobs of its parts.
+fn document_name_blob(name: &str, blobs: &mut BlobHeap) -> Vec<u8> {
+    let separator = if name.contains('\\') && !name.contains('/') {
+        '\\'
+    } else {
+        '/'
+    };
+    let mut out = vec![separator as u8];
+    for part in name.split(separator) {
+        write_compressed_u32(blobs.alloc(part.as_bytes()), &mut out);
+    }
+    out
+}
+/// Hashes the contents of a PDB, to calculate its id. This makes the id deterministic.
+fn pdb_id(streams: &[&[u8]]) -> PdbId {
+    let mut id = [0; 20];
+    for (chunk, seed) in id.chunks_mut(8).zip(0_u64..) {
+        let mut hasher = FxHasher64::default();
+        hasher.write_u64(seed);
+        for stream in streams {
+            hasher.write(stream);
+        }
+        let hash = hasher.finish().to_le_bytes();
+        chunk.copy_from_slice(&hash[..chunk.len()]);
+    }
+    id
+}
+/// Builds a Portable PDB for an assembly with the type system tables `type_system`. `methods` maps `MethodDef` rows
+/// to their debug information. Returns the PDB, and its id.
+pub(super) fn write_pdb(
+    asm: &Assembly,
+    type_system: &Tables,
+    methods: &FxHashMap<u32, MethodDebugInfo>,
+    entrypoint: Option<MetadataToken>,
+) -> (Vec<u8>, PdbId) {
+    let type_system_rows: Vec<(TableId, u32)> = TableId::ALL
+        .iter()
+        .filter(|table| table.is_type_system())
+        .map(|table| (*table, type_system.len(*table)))
+        .filter(|(_, rows)| *rows > 0)
+        .collect();
+    let mut tables = Tables::with_external_rows(type_system_rows.iter().copied().collect());
+    let mut strings = StringHeap::default();
+    let mut blobs = BlobHeap::default();
+    let mut guids = GuidHeap::default();
+    let language = guids.alloc(RUST_LANGUAGE);
+    // All methods share a single, empty import scope.
+    let import_scope = tables.push(
+        TableId::ImportScope,
+        [Column::Index(TableId::ImportScope, 0), Column::Blob(0)],
+    );
+    let mut documents: FxHashMap<StringIdx, u32> = FxHashMap::default();
+    for row in 1..=type_system.len(TableId::MethodDef) {
+        let Some(info) = methods.get(&row) else {
+            tables.push(
+                TableId::MethodDebugInformation,
+                [Column::Index(TableId::Document, 0), Column::Blob(0)],
+            );
+            continue;
+        };
+        for point in &info.sequence_points {
+            documents.entry(point.file).or_insert_with(|| {
+                let name = document_name_blob(&asm[point.file], &mut blobs);
+                let name = blobs.alloc(&name);
+                tables
+                    .push(
+                        TableId::Document,
+                        [
+                            Column::Blob(name),
+                            Column::Guid(0),
+                            Column::Blob(0),
+                            Column::Guid(language),
+                        ],
+                    )
+                    .row()
+            });
+        }
+        let (document, points) = match info.sequence_points.first() {
+            Some(first) => {
+                let single_document = info
+                    .sequence_points
+                    .iter()
+                    .all(|point| point.file == first.file)
+                    .then(|| documents[&first.file]);
+                let blob = sequence_points_blob(info, &documents, single_document);
+                (single_document.unwrap_or(0), blobs.alloc(&blob))
+            }
+            None => (0, 0),
+        };
+        tables.push(
+            TableId::MethodDebugInformation,
+            [
+                Column::Index(TableId::Document, document),
+                Column::Blob(points),
+            ],
+        );
+        tables.push(
+            TableId::LocalScope,
+            [
+                Column::Index(TableId::MethodDef, row),
+                Column::Index(TableId::ImportScope, import_scope.row()),
+                Column::Index(
+                    TableId::LocalVariable,
+                    tables.next_row(TableId::LocalVariable).row(),
+                ),
+                Column::Index(TableId::LocalConstant, 1),
+                Column::U32(0),
+                Column::U32(info.code_size),
+            ],
+        );
+        for (idx, name) in &info.locals {
+            let name = strings.alloc(name);
+            tables.push(
+                TableId::LocalVariable,
+                [Column::U16(0), Column::U16(*idx), Column::String(name)],
+            );
+        }
+    }
+    let table_stream = tables.serialize(&HeapSizes {
+        strings: strings.data().len(),
+        guid: guids.data().len() / 16,
+        blob: blobs.data().len(),
+    });
+    // The #Pdb stream: id, entrypoint, and the row counts of the referenced type system tables.
+    let mut pdb_stream = vec![0; 20];
+    pdb_stream.extend_from_slice(&entrypoint.map_or(0, |token| token.raw()).to_le_bytes());
+    let referenced = type_system_rows
+        .iter()
+        .fold(0_u64, |mask, (table, _)| mask | (1 << (*table as u8)));
+    pdb_stream.extend_from_slice(&referenced.to_le_bytes());
+    for (_, rows) in &type_system_rows {
+        pdb_stream.extend_from_slice(&rows.to_le_bytes());
+    }
+    let mut strings = strings.data().to_vec();
+    let mut blobs = blobs.data().to_vec();
+    for stream in [&mut strings, &mut blobs] {
+        while !stream.len().is_multiple_of(4) {
+            stream.push(0);
+        }
+    }
+    let id = pdb_id(&[&pdb_stream, &table_stream, &strings, guids.data(), &blobs]);
+    pdb_stream[..20].copy_from_slice(&id);
+    let pdb = image::metadata_root(
+        PDB_VERSION,
+        &[
+            ("#Pdb", &pdb_stream),
+            ("#~", &table_stream),
+            ("#Strings", &strings),
+            ("#GUID", guids.data()),
+            ("#Blob", &blobs),
+        ],
+    );
+    (pdb, id)
+}
+#[cfg(test)]
+fn point(il_offset: u32, line_start: u32, col_start: u16, file: StringIdx) -> SequencePoint {
+    SequencePoint {
+        il_offset,
+        line_start,
+        line_len: 0,
+        col_start,
+        col_len: 4,
+        file,
+    }
+}
+#[test]
+fn sequence_points() {
+    let mut asm = Assembly::default();
+    let file = asm.alloc_string("main.rs");
+    let mut info = MethodDebugInfo::default();
+    info.push(point(0, 10, 5, file));
+    // Replaces the previous point, since it has the same offset.
+    info.push(point(0, 11, 5, file));
+    info.push(point(6, 12, 9, file));
+    // Unknown positions are skipped.
+    info.push(point(8, 0, 0, file));
+    assert_eq!(info.sequence_points.len(), 2);
+    let documents = [(file, 1)].into_iter().collect();
+    let blob = sequence_points_blob(&info, &documents, Some(1));
+    // No locals, then (offset 0, 0 lines, 4 columns, line 11, col 5) and (offset +6, 0 lines, 4 columns, line +1, col +4)
+    assert_eq!(blob, vec![0, 0, 0, 4, 11, 5, 6, 0, 4, 2, 8]);
+}
+#[test]
+fn document_name() {
+    let mut blobs = BlobHeap::default();
+    let name = document_name_blob("/src/main.rs", &mut blobs);
+    // '/', then the empty part, "src" and "main.rs"
+    assert_eq!(name, vec![b'/', 0, 1, 5]);
+}
@@ -0,0 +1,771 @@
+//! Reads the metadata(II.24) of ECMA-335 PE files, written either by the PE exporter, or by any other .NET compiler.
+//! Used to generate Rust bindings for existing .NET assemblies.
+use std::{num::NonZeroU8, ops::Range};
+
+use fxhash::FxHashMap;
+
+use super::{
+    metadata::{
+        ELEMENT_TYPE_ARRAY, ELEMENT_TYPE_BOOLEAN, ELEMENT_TYPE_BYREF, ELEMENT_TYPE_CHAR,
+        ELEMENT_TYPE_CLASS, ELEMENT_TYPE_CMOD_OPT, ELEMENT_TYPE_CMOD_REQD, ELEMENT_TYPE_FNPTR,
+        ELEMENT_TYPE_GENERICINST, ELEMENT_TYPE_I, ELEMENT_TYPE_I1, ELEMENT_TYPE_I2,
+        ELEMENT_TYPE_I4, ELEMENT_TYPE_I8, ELEMENT_TYPE_MVAR, ELEMENT_TYPE_OBJECT,
+        ELEMENT_TYPE_PINNED, ELEMENT_TYPE_PTR, ELEMENT_TYPE_R4, ELEMENT_TYPE_R8,
+        ELEMENT_TYPE_SENTINEL, ELEMENT_TYPE_STRING, ELEMENT_TYPE_SZARRAY, ELEMENT_TYPE_U,
+        ELEMENT_TYPE_U1, ELEMENT_TYPE_U2, ELEMENT_TYPE_U4, ELEMENT_TYPE_U8, ELEMENT_TYPE_VALUETYPE,
+        ELEMENT_TYPE_VAR, ELEMENT_TYPE_VOID, SIG_FIELD, SIG_GENERIC, SIG_HASTHIS, SIG_VARARG,
+    },
+    tables::{CodedIndex, MetadataToken, TableId},
+};
+use crate::v2::{tpe::GenericKind, Assembly, ClassRef, ClassRefIdx, Float, FnSig, Int, Type};
+
+/// An error encountered while reading the metadata of a PE file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadError {
+    /// The file is not a valid PE file, or is truncated.
+    Malformed(&'static str),
+    /// The file is a PE file, but has no CLI header, so it is not a .NET assembly.
+    NotAnAssembly,
+    /// A signature uses an element type, which can't be represented by cilly.
+    UnsupportedElementType(u8),
+}
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed(what) => write!(f, "malformed PE file: {what}"),
+            Self::NotAnAssembly => write!(f, "the PE file is not a .NET assembly"),
+            Self::UnsupportedElementType(elem) => {
+                write!(f, "unsupported signature element type {elem:#x}")
+            }
+        }
+    }
+}
+impl std::error::Error for ReadError {}
+/// Reads an ECMA-335 compressed unsigned integer (II.23.2), returning it together with its encoded size.
+/// ```
+/// # use cilly::v2::pe_exporter::reader::read_compressed_u32;
+/// assert_eq!(read_compressed_u32(&[0x03]), Some((0x03, 1)));
+/// assert_eq!(read_compressed_u32(&[0x80, 0x80]), Some((0x80, 2)));
+/// assert_eq!(read_compressed_u32(&[0xC0, 0x00, 0x40, 0x00]), Some((0x4000, 4)));
+/// ```
+#[must_use]
+pub fn read_compressed_u32(data: &[u8]) -> Option<(u32, usize)> {
+    let first = *data.first()?;
+    match first {
+        0..0x80 => Some((u32::from(first), 1)),
+        0x80..0xC0 => {
+            let bytes = data.get(..2)?;
+            Some((
+                u32::from(u16::from_be_bytes([bytes[0], bytes[1]]) & 0x3FFF),
+                2,
+            ))
+        }
+        _ => {
+            let bytes: [u8; 4] = data.get(..4)?.try_into().unwrap();
+            Some((u32::from_be_bytes(bytes) & 0x1FFF_FFFF, 4))
+        }
+    }
+}
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ReadError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or(ReadError::Malformed("unexpected end of data"))
+}
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ReadError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ReadError::Malformed("unexpected end of data"))
+}
+/// The kind of a table column, used to calculate the layout of the rows.
+#[derive(Clone, Copy)]
+enum ColumnKind {
+    U16,
+    U32,
+    String,
+    Guid,
+    Blob,
+    Index(TableId),
+    Coded(CodedIndex),
+}
+/// Returns the columns of a metadata table(II.22). Portable PDB tables are not supported.
+fn schema(table: TableId) -> Option<&'static [ColumnKind]> {
+    use ColumnKind::{Blob, Coded, Guid, Index, String, U16, U32};
+    Some(match table {
+        TableId::Module => &[U16, String, Guid, Guid, Guid],
+        TableId::TypeRef => &[Coded(CodedIndex::ResolutionScope), String, String],
+        TableId::TypeDef => &[
+            U32,
+            String,
+            String,
+            Coded(CodedIndex::TypeDefOrRef),
+            Index(TableId::Field),
+            Index(TableId::MethodDef),
+        ],
+        TableId::FieldPtr => &[Index(TableId::Field)],
+        TableId::Field => &[U16, String, Blob],
+        TableId::MethodPtr => &[Index(TableId::MethodDef)],
+        TableId::MethodDef => &[U32, U16, U16, String, Blob, Index(TableId::Param)],
+        TableId::ParamPtr => &[Index(TableId::Param)],
+        TableId::Param => &[U16, U16, String],
+        TableId::InterfaceImpl => &[Index(TableId::TypeDef), Coded(CodedIndex::TypeDefOrRef)],
+        TableId::MemberRef => &[Coded(CodedIndex::MemberRefParent), String, Blob],
+        // The type of a constant is a single byte, followed by a padding byte.
+        TableId::Constant => &[U16, Coded(CodedIndex::HasConstant), Blob],
+        TableId::CustomAttribute => &[
+            Coded(CodedIndex::HasCustomAttribute),
+            Coded(CodedIndex::CustomAttributeType),
+            Blob,
+        ],
+        TableId::FieldMarshal => &[Coded(CodedIndex::HasFieldMarshal), Blob],
+        TableId::DeclSecurity => &[U16, Coded(CodedIndex::HasDeclSecurity), Blob],
+        TableId::ClassLayout => &[U16, U32, Index(TableId::TypeDef)],
+        TableId::FieldLayout => &[U32, Index(TableId::Field)],
+        TableId::StandAloneSig => &[Blob],
+        TableId::EventMap => &[Index(TableId::TypeDef), Index(TableId::Event)],
+        TableId::EventPtr => &[Index(TableId::Event)],
+        TableId::Event => &[U16, String, Coded(CodedIndex::TypeDefOrRef)],
+        TableId::PropertyMap => &[Index(TableId::TypeDef), Index(TableId::Property)],
+        TableId::PropertyPtr => &[Index(TableId::Property)],
+        TableId::Property => &[U16, String, Blob],
+        TableId::MethodSemantics => &[
+            U16,
+            Index(TableId::MethodDef),
+            Coded(CodedIndex::HasSemantics),
+        ],
+        TableId::MethodImpl => &[
+            Index(TableId::TypeDef),
+            Coded(CodedIndex::MethodDefOrRef),
+            Coded(CodedIndex::MethodDefOrRef),
+        ],
+        TableId::ModuleRef => &[String],
+        TableId::TypeSpec => &[Blob],
+        TableId::ImplMap => &[
+            U16,
+            Coded(CodedIndex::MemberForwarded),
+            String,
+            Index(TableId::ModuleRef),
+        ],
+        TableId::FieldRVA => &[U32, Index(TableId::Field)],
+        TableId::EncLog => &[U32, U32],
+        TableId::EncMap => &[U32],
+        TableId::Assembly => &[U32, U16, U16, U16, U16, U32, Blob, String, String],
+        TableId::AssemblyProcessor => &[U32],
+        TableId::AssemblyOS => &[U32, U32, U32],
+        TableId::AssemblyRef => &[U16, U16, U16, U16, U32, Blob, String, String, Blob],
+        TableId::AssemblyRefProcessor => &[U32, Index(TableId::AssemblyRef)],
+        TableId::AssemblyRefOS => &[U32, U32, U32, Index(TableId::AssemblyRef)],
+        TableId::File => &[U32, String, Blob],
+        TableId::ExportedType => &[U32, U32, String, String, Coded(CodedIndex::Implementation)],
+        TableId::ManifestResource => &[U32, U32, String, Coded(CodedIndex::Implementation)],
+        TableId::NestedClass => &[Index(TableId::TypeDef), Index(TableId::TypeDef)],
+        TableId::GenericParam => &[U16, U16, Coded(CodedIndex::TypeOrMethodDef), String],
+        TableId::MethodSpec => &[Coded(CodedIndex::MethodDefOrRef), Blob],
+        TableId::GenericParamConstraint => &[
+            Index(TableId::GenericParam),
+            Coded(CodedIndex::TypeDefOrRef),
+        ],
+        TableId::Document
+        | TableId::MethodDebugInformation
+        | TableId::LocalScope
+        | TableId::LocalVariable
+        | TableId::LocalConstant
+        | TableId::ImportScope => return None,
+    })
+}
+/// The rows of a single table, and the layout of its columns.
+struct TableData<'a> {
+    data: &'a [u8],
+    rows: u32,
+    row_size: usize,
+    /// The kind, offset and width of each column.
+    columns: Vec<(ColumnKind, usize, usize)>,
+}
+/// A decoded method or property signature.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodSig {
+    /// Is the method an instance method? The `this` argument is not included in `sig`.
+    pub has_this: bool,
+    /// The number of generic parameters of the method.
+    pub generic_params: u32,
+    pub sig: FnSig,
+}
+/// A cursor over a signature blob.
+struct SigReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl SigReader<'_> {
+    fn peek(&self) -> Result<u8, ReadError> {
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or(ReadError::Malformed("unexpected end of signature"))
+    }
+    fn u8(&mut self) -> Result<u8, ReadError> {
+        let val = self.peek()?;
+        self.pos += 1;
+        Ok(val)
+    }
+    fn compressed(&mut self) -> Result<u32, ReadError> {
+        let (val, len) = read_compressed_u32(&self.data[self.pos..])
+            .ok_or(ReadError::Malformed("unexpected end of signature"))?;
+        self.pos += len;
+        Ok(val)
+    }
+    fn type_def_or_ref(&mut self) -> Result<MetadataToken, ReadError> {
+        let raw = self.compressed()?;
+        CodedIndex::TypeDefOrRef
+            .decode(raw)
+            .ok_or(ReadError::Malformed("invalid TypeDefOrRef in signature"))
+    }
+}
+/// Provides access to the metadata tables and heaps of a .NET assembly.
+pub struct MetadataReader<'a> {
+    strings: &'a [u8],
+    blobs: &'a [u8],
+    tables: FxHashMap<TableId, TableData<'a>>,
+    /// Maps nested `TypeDef`s to the `TypeDef` enclosing them.
+    enclosing: FxHashMap<u32, u32>,
+}
+impl<'a> MetadataReader<'a> {
+    /// Reads the metadata of the PE file `pe`.
+    pub fn new(pe: &'a [u8]) -> Result<Self, ReadError> {
+        let metadata = metadata_root(pe)?;
+        let mut streams = FxHashMap::default();
+        if read_u32(metadata, 0)? != 0x424A_5342 {
+            return Err(ReadError::Malformed("missing metadata signature"));
+        }
+        let version_len = read_u32(metadata, 12)? as usize;
+        // Skip the version, and the flags.
+        let mut pos = 16 + version_len + 2;
+        let stream_count = read_u16(metadata, pos)?;
+        pos += 2;
+        for _ in 0..stream_count {
+            let offset = read_u32(metadata, pos)? as usize;
+            let size = read_u32(metadata, pos + 4)? as usize;
+            pos += 8;
+            let name_len = metadata
+                .get(pos..)
+                .and_then(|name| name.iter().position(|byte| *byte == 0))
+                .ok_or(ReadError::Malformed("unterminated stream name"))?;
+            let name = &metadata[pos..pos + name_len];
+            pos += (name_len + 1).next_multiple_of(4);
+            let data = metadata
+                .get(offset..offset + size)
+                .ok_or(ReadError::Malformed("stream out of bounds"))?;
+            streams.insert(name, data);
+        }
+        // Uncompressed("#-") table streams are laid out the same way as the compressed ones.
+        let table_stream = streams
+            .get(b"#~".as_slice())
+            .or_else(|| streams.get(b"#-".as_slice()))
+            .ok_or(ReadError::Malformed("missing table stream"))?;
+        let strings = streams.get(b"#Strings".as_slice()).copied().unwrap_or(&[]);
+        let blobs = streams.get(b"#Blob".as_slice()).copied().unwrap_or(&[]);
+        let mut reader = Self {
+            strings,
+            blobs,
+            tables: read_tables(table_stream)?,
+            enclosing: FxHashMap::default(),
+        };
+        reader.enclosing = reader
+            .tokens(TableId::NestedClass)
+            .map(|row| (reader.raw(row, 0), reader.raw(row, 1)))
+            .collect();
+        Ok(reader)
+    }
+    /// Returns the number of rows in `table`.
+    #[must_use]
+    pub fn rows(&self, table: TableId) -> u32 {
+        self.tables.get(&table).map_or(0, |table| table.rows)
+    }
+    /// Returns tokens pointing to all the rows of `table`.
+    pub fn tokens(&self, table: TableId) -> impl Iterator<Item = MetadataToken> {
+        (1..=self.rows(table)).map(move |row| MetadataToken::new(table, row))
+    }
+    /// Returns the raw value of a column.
+    #[must_use]
+    pub fn raw(&self, token: MetadataToken, column: usize) -> u32 {
+        let table = &self.tables[&token.table()];
+        assert!(
+            token.row() >= 1 && token.row() <= table.rows,
+            "ERROR: row {} out of bounds for table {:?}",
+            token.row(),
+            token.table()
+        );
+        let (_, offset, width) = table.columns[column];
+        let start = (token.row() as usize - 1) * table.row_size + offset;
+        let bytes = &table.data[start..start + width];
+        if width == 4 {
+            u32::from_le_bytes(bytes.try_into().unwrap())
+        } else {
+            u32::from(u16::from_le_bytes([bytes[0], bytes[1]]))
+        }
+    }
+    /// Returns the string a `#Strings` heap column points to.
+    pub fn string(&self, token: MetadataToken, column: usize) -> Result<&'a str, ReadError> {
+        let start = self.raw(token, column) as usize;
+        let data = self
+            .strings
+            .get(start..)
+            .ok_or(ReadError::Malformed("string out of bounds"))?;
+        let len = data
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(ReadError::Malformed("unterminated string"))?;
+        std::str::from_utf8(&data[..len]).map_err(|_| ReadError::Malformed("invalid UTF-8 string"))
+    }
+    /// Returns the blob a `#Blob` heap column points to.
+    pub fn blob(&self, token: MetadataToken, column: usize) -> Result<&'a [u8], ReadError> {
+        let start = self.raw(token, column) as usize;
+        let data = self
+            .blobs
+            .get(start..)
+            .ok_or(ReadError::Malformed("blob out of bounds"))?;
+        let (len, header) =
+            read_compressed_u32(data).ok_or(ReadError::Malformed("blob out of bounds"))?;
+        data.get(header..header + len as usize)
+            .ok_or(ReadError::Malformed("blob out of bounds"))
+    }
+    /// Returns the row a table index or coded index column points to, or `None` if it is null.
+    #[must_use]
+    pub fn index(&self, token: MetadataToken, column: usize) -> Option<MetadataToken> {
+        let raw = self.raw(token, column);
+        match self.tables[&token.table()].columns[column].0 {
+            ColumnKind::Index(table) => (raw != 0).then(|| MetadataToken::new(table, raw)),
+            ColumnKind::Coded(kind) => kind.decode(raw),
+            _ => panic!(
+                "ERROR: column {column} of {:?} is not an index",
+                token.table()
+            ),
+        }
+    }
+    /// Returns the rows of the list a column(like the field list of a `TypeDef`) points to. The list ends where the list
+    /// of the next row starts.
+    #[must_use]
+    pub fn list(&self, token: MetadataToken, column: usize) -> Range<u32> {
+        let ColumnKind::Index(target) = self.tables[&token.table()].columns[column].0 else {
+            panic!(
+                "ERROR: column {column} of {:?} is not a list",
+                token.table()
+            )
+        };
+        let end_of_table = self.rows(target) + 1;
+        let start = self.raw(token, column).min(end_of_table);
+        let end = if token.row() < self.rows(token.table()) {
+            let next = MetadataToken::new(token.table(), token.row() + 1);
+            self.raw(next, column).min(end_of_table)
+        } else {
+            end_of_table
+        };
+        start..end.max(start)
+    }
+    /// Returns the name of this assembly.
+    pub fn assembly_name(&self) -> Result<&'a str, ReadError> {
+        if self.rows(TableId::Assembly) > 0 {
+            self.string(MetadataToken::new(TableId::Assembly, 1), 7)
+        } else {
+            // Modules without an assembly manifest are referenced by their name, without the extension.
+            let name = self.string(MetadataToken::new(TableId::Module, 1), 1)?;
+            Ok(name.rsplit_once('.').map_or(name, |(name, _)| name))
+        }
+    }
+    /// Returns the full name of a `TypeDef` or `TypeRef`, in the format used by [`ClassRef`]: nested types are
+    /// separated from the type enclosing them using `/`.
+    pub fn type_name(&self, token: MetadataToken) -> Result<String, ReadError> {
+        let (name, namespace, outer) = match token.table() {
+            TableId::TypeDef => (
+                self.string(token, 1)?,
+                self.string(token, 2)?,
+                self.enclosing
+                    .get(&token.row())
+                    .map(|outer| MetadataToken::new(TableId::TypeDef, *outer)),
+            ),
+            TableId::TypeRef => (
+                self.string(token, 1)?,
+                self.string(token, 2)?,
+                self.index(token, 0)
+                    .filter(|scope| scope.table() == TableId::TypeRef),
+            ),
+            _ => panic!("ERROR: {:?} is not a TypeDef or TypeRef", token.table()),
+        };
+        Ok(match (outer, namespace) {
+            (Some(outer), _) => format!("{}/{name}", self.type_name(outer)?),
+            (None, "") => name.to_owned(),
+            (None, namespace) => format!("{namespace}.{name}"),
+        })
+    }
+    /// Returns the name of the assembly defining a `TypeDef` or `TypeRef`.
+    pub fn type_assembly(&self, token: MetadataToken) -> Result<&'a str, ReadError> {
+        if token.table() == TableId::TypeRef {
+            match self.index(token, 0) {
+                Some(scope) if scope.table() == TableId::AssemblyRef => {
+                    return self.string(scope, 6);
+                }
+                Some(scope) if scope.table() == TableId::TypeRef => {
+                    return self.type_assembly(scope);
+                }
+                _ => (),
+            }
+        }
+        self.assembly_name()
+    }
+    /// Returns a class reference to the type a `TypeDef`, `TypeRef` or `TypeSpec` token points to.
+    pub fn class_ref(
+        &self,
+        token: MetadataToken,
+        is_valuetype: bool,
+        asm: &mut Assembly,
+    ) -> Result<ClassRefIdx, ReadError> {
+        if token.table() == TableId::TypeSpec {
+            return match self.decode_type(self.blob(token, 0)?, asm)? {
+                Type::ClassRef(cref) => Ok(cref),
+                _ => Err(ReadError::Malformed("TypeSpec is not a class")),
+            };
+        }
+        let name = asm.alloc_string(self.type_name(token)?);
+        let assembly = asm.alloc_string(self.type_assembly(token)?);
+        Ok(asm.alloc_class_ref(ClassRef::new(name, Some(assembly), is_valuetype, [].into())))
+    }
+    /// Decodes a type signature(II.23.2.12).
+    pub fn decode_type(&self, blob: &[u8], asm: &mut Assembly) -> Result<Type, ReadError> {
+        self.read_type(&mut SigReader { data: blob, pos: 0 }, asm)
+    }
+    /// Decodes a field signature(II.23.2.4), returning the type of the field.
+    pub fn decode_field_sig(&self, blob: &[u8], asm: &mut Assembly) -> Result<Type, ReadError> {
+        let mut sig = SigReader { data: blob, pos: 0 };
+        if sig.u8()? != SIG_FIELD {
+            return Err(ReadError::Malformed("not a field signature"));
+        }
+        self.read_type(&mut sig, asm)
+    }
+    /// Decodes a method(II.23.2.1) or property(II.23.2.5) signature. The variable arguments of vararg methods are
+    /// included in the parameters.
+    pub fn decode_method_sig(
+        &self,
+        blob: &[u8],
+        asm: &mut Assembly,
+    ) -> Result<MethodSig, ReadError> {
+        self.read_method_sig(&mut SigReader { data: blob, pos: 0 }, asm)
+    }
+    fn read_method_sig(
+        &self,
+        sig: &mut SigReader,
+        asm: &mut Assembly,
+    ) -> Result<MethodSig, ReadError> {
+        let flags = sig.u8()?;
+        let generic_params = if flags & SIG_GENERIC != 0 {
+            sig.compressed()?
+        } else {
+            0
+        };
+        let param_count = sig.compressed()?;
+        let ret = self.read_type(sig, asm)?;
+        let mut params = Vec::with_capacity(param_count as usize);
+        let mut fixed = None;
+        for _ in 0..param_count {
+            if sig.peek()? == ELEMENT_TYPE_SENTINEL {
+                sig.u8()?;
+                fixed = Some(params.len());
+            }
+            params.push(self.read_type(sig, asm)?);
+        }
+        let fn_sig = FnSig::new(params.into(), ret);
+        let fn_sig = if flags & 0x0F == SIG_VARARG {
+            let fixed = fixed.unwrap_or(fn_sig.inputs().len());
+            fn_sig.with_variadic(fixed)
+        } else {
+            fn_sig
+        };
+        Ok(MethodSig {
+            has_this: flags & SIG_HASTHIS != 0,
+            generic_params,
+            sig: fn_sig,
+        })
+    }
+    fn read_type(&self, sig: &mut SigReader, asm: &mut Assembly) -> Result<Type, ReadError> {
+        let elem = sig.u8()?;
+        Ok(match elem {
+            ELEMENT_TYPE_VOID => Type::Void,
+            ELEMENT_TYPE_BOOLEAN => Type::Bool,
+            ELEMENT_TYPE_CHAR => Type::PlatformChar,
+            ELEMENT_TYPE_I1 => Type::Int(Int::I8),
+            ELEMENT_TYPE_U1 => Type::Int(Int::U8),
+            ELEMENT_TYPE_I2 => Type::Int(Int::I16),
+            ELEMENT_TYPE_U2 => Type::Int(Int::U16),
+            ELEMENT_TYPE_I4 => Type::Int(Int::I32),
+            ELEMENT_TYPE_U4 => Type::Int(Int::U32),
+            ELEMENT_TYPE_I8 => Type::Int(Int::I64),
+            ELEMENT_TYPE_U8 => Type::Int(Int::U64),
+            ELEMENT_TYPE_I => Type::Int(Int::ISize),
+            ELEMENT_TYPE_U => Type::Int(Int::USize),
+            ELEMENT_TYPE_R4 => Type::Float(Float::F32),
+            ELEMENT_TYPE_R8 => Type::Float(Float::F64),
+            ELEMENT_TYPE_STRING => Type::PlatformString,
+            ELEMENT_TYPE_OBJECT => Type::PlatformObject,
+            ELEMENT_TYPE_PTR => {
+                let inner = self.read_type(sig, asm)?;
+                Type::Ptr(asm.alloc_type(inner))
+            }
+            ELEMENT_TYPE_BYREF => {
+                let inner = self.read_type(sig, asm)?;
+                Type::Ref(asm.alloc_type(inner))
+            }
+            ELEMENT_TYPE_VALUETYPE | ELEMENT_TYPE_CLASS => {
+                let token = sig.type_def_or_ref()?;
+                Type::ClassRef(self.class_ref(token, elem == ELEMENT_TYPE_VALUETYPE, asm)?)
+            }
+            ELEMENT_TYPE_GENERICINST => {
+                let is_valuetype = sig.u8()? == ELEMENT_TYPE_VALUETYPE;
+                let token = sig.type_def_or_ref()?;
+                let arg_count = sig.compressed()?;
+                let generics = (0..arg_count)
+                    .map(|_| self.read_type(sig, asm))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let open = self.class_ref(token, is_valuetype, asm)?;
+                let open = asm[open].clone();
+                // The names of generic cilly classes don't include their arity: the exporters append it.
+                let name = &asm[open.name()];
+                let name = match name.rsplit_once('`') {
+                    Some((name, arity)) if arity.parse::<u32>().is_ok() => name,
+                    _ => name,
+                }
+                .to_owned();
+                let name = asm.alloc_string(name);
+                Type::ClassRef(asm.alloc_class_ref(ClassRef::new(
+                    name,
+                    open.asm(),
+                    is_valuetype,
+                    generics.into(),
+                )))
+            }
+            ELEMENT_TYPE_VAR => Type::PlatformGeneric(sig.compressed()?, GenericKind::TypeGeneric),
+            ELEMENT_TYPE_MVAR => Type::PlatformGeneric(sig.compressed()?, GenericKind::CallGeneric),
+            ELEMENT_TYPE_SZARRAY => {
+                let elem = self.read_type(sig, asm)?;
+                Type::PlatformArray {
+                    elem: asm.alloc_type(elem),
+                    dims: NonZeroU8::new(1).unwrap(),
+                }
+            }
+            ELEMENT_TYPE_ARRAY => {
+                let elem = self.read_type(sig, asm)?;
+                let rank = sig.compressed()?;
+                // The sizes and lower bounds of the dimensions are not a part of the cilly type.
+                for _ in 0..sig.compressed()? {
+                    sig.compressed()?;
+                }
+                for _ in 0..sig.compressed()? {
+                    sig.compressed()?;
+                }
+                Type::PlatformArray {
+                    elem: asm.alloc_type(elem),
+                    dims: u8::try_from(rank)
+                        .ok()
+                        .and_then(NonZeroU8::new)
+                        .ok_or(ReadError::Malformed("invalid array rank"))?,
+                }
+            }
+            ELEMENT_TYPE_FNPTR => {
+                let fn_sig = self.read_method_sig(sig, asm)?;
+                Type::FnPtr(asm.alloc_sig(fn_sig.sig))
+            }
+            // Custom modifiers and pinning don't change the type.
+            ELEMENT_TYPE_CMOD_REQD | ELEMENT_TYPE_CMOD_OPT => {
+                sig.type_def_or_ref()?;
+                self.read_type(sig, asm)?
+            }
+            ELEMENT_TYPE_PINNED => self.read_type(sig, asm)?,
+            _ => return Err(ReadError::UnsupportedElementType(elem)),
+        })
+    }
+}
+/// Finds the metadata root of a PE file, using the CLI header(II.25.3.3).
+fn metadata_root(pe: &[u8]) -> Result<&[u8], ReadError> {
+    let pe_offset = read_u32(pe, 0x3C)? as usize;
+    if pe.get(pe_offset..pe_offset + 4) != Some(b"PE\0\0".as_slice()) {
+        return Err(ReadError::Malformed("missing PE signature"));
+    }
+    let coff = pe_offset + 4;
+    let section_count = read_u16(pe, coff + 2)?;
+    let optional_header = coff + 20;
+    let optional_header_size = read_u16(pe, coff + 16)? as usize;
+    let directories = match read_u16(pe, optional_header)? {
+        // PE32
+        0x10B => optional_header + 96,
+        // PE32+
+        0x20B => optional_header + 112,
+        _ => return Err(ReadError::Malformed("unknown optional header magic")),
+    };
+    // The CLI header is described by the 15th data directory.
+    if read_u32(pe, directories - 4)? < 15 {
+        return Err(ReadError::NotAnAssembly);
+    }
+    let cli_rva = read_u32(pe, directories + 14 * 8)?;
+    if cli_rva == 0 {
+        return Err(ReadError::NotAnAssembly);
+    }
+    let sections = optional_header + optional_header_size;
+    let rva_to_offset = |rva: u32| -> Result<usize, ReadError> {
+        for section in 0..usize::from(section_count) {
+            let header = sections + section * 40;
+            let virtual_size = read_u32(pe, header + 8)?;
+            let virtual_address = read_u32(pe, header + 12)?;
+            let raw_size = read_u32(pe, header + 16)?;
+            let raw_pointer = read_u32(pe, header + 20)?;
+            if rva >= virtual_address && rva - virtual_address < virtual_size.max(raw_size) {
+                return Ok((rva - virtual_address + raw_pointer) as usize);
+            }
+        }
+        Err(ReadError::Malformed("RVA outside of all sections"))
+    };
+    let cli_header = rva_to_offset(cli_rva)?;
+    let metadata = rva_to_offset(read_u32(pe, cli_header + 8)?)?;
+    let metadata_size = read_u32(pe, cli_header + 12)? as usize;
+    pe.get(metadata..metadata + metadata_size)
+        .ok_or(ReadError::Malformed("metadata out of bounds"))
+}
+/// Reads the layout of the tables in the `#~` stream(II.24.2.6).
+fn read_tables(stream: &[u8]) -> Result<FxHashMap<TableId, TableData<'_>>, ReadError> {
+    let heap_sizes = *stream
+        .get(6)
+        .ok_or(ReadError::Malformed("table stream too short"))?;
+    let valid = u64::from(read_u32(stream, 8)?) | (u64::from(read_u32(stream, 12)?) << 32);
+    let mut pos = 24;
+    let mut row_counts = FxHashMap::default();
+    for id in 0..64_u8 {
+        if valid & (1 << id) == 0 {
+            continue;
+        }
+        let table = TableId::ALL
+            .iter()
+            .copied()
+            .find(|table| *table as u8 == id)
+            .filter(|table| table.is_type_system())
+            .ok_or(ReadError::Malformed("unknown metadata table"))?;
+        row_counts.insert(table, read_u32(stream, pos)?);
+        pos += 4;
+    }
+    // Some compilers store 4 additional bytes after the row counts.
+    if heap_sizes & 0x40 != 0 {
+        pos += 4;
+    }
+    let heap_width = |flag: u8| if heap_sizes & flag != 0 { 4 } else { 2 };
+    let rows = |table: &TableId| row_counts.get(table).copied().unwrap_or(0);
+    let mut tables = FxHashMap::default();
+    for table in TableId::ALL {
+        let Some(row_count) = row_counts.get(&table).copied() else {
+            continue;
+        };
+        let mut columns = vec![];
+        let mut row_size = 0;
+        for kind in schema(table).ok_or(ReadError::Malformed("unknown metadata table"))? {
+            let width = match kind {
+                ColumnKind::U16 => 2,
+                ColumnKind::U32 => 4,
+                ColumnKind::String => heap_width(0x01),
+                ColumnKind::Guid => heap_width(0x02),
+                ColumnKind::Blob => heap_width(0x04),
+                ColumnKind::Index(target) => {
+                    if rows(target) >= (1 << 16) {
+                        4
+                    } else {
+                        2
+                    }
+                }
+                ColumnKind::Coded(coded) => {
+                    let max_rows = coded.tables().iter().flatten().map(rows).max();
+                    if max_rows.unwrap_or(0) >= (1 << (16 - coded.tag_bits())) {
+                        4
+                    } else {
+                        2
+                    }
+                }
+            };
+            columns.push((*kind, row_size, width));
+            row_size += width;
+        }
+        let size = row_size * row_count as usize;
+        let data = stream
+            .get(pos..pos + size)
+            .ok_or(ReadError::Malformed("table out of bounds"))?;
+        pos += size;
+        tables.insert(
+            table,
+            TableData {
+                data,
+                rows: row_count,
+                row_size,
+                columns,
+            },
+        );
+    }
+    Ok(tables)
+}
+#[test]
+fn read_exported() {
+    use super::PEExporter;
+    use crate::v2::{cilnode::MethodKind, Access, ClassDef, MethodDef, MethodImpl};
+    let mut asm = Assembly::default();
+    let name = asm.alloc_string("Demo.Point");
+    let x = asm.alloc_string("x");
+    let class = asm.class_def(ClassDef::new(
+        name,
+        false,
+        0,
+        None,
+        vec![(Type::Int(Int::I32), x, None)],
+        vec![],
+        Access::Public,
+        None,
+        None,
+    ));
+    let add = asm.alloc_string("Add");
+    let sig = asm.sig([Type::Int(Int::I32), Type::PlatformString], Type::Bool);
+    asm.new_method(MethodDef::new(
+        Access::Public,
+        class,
+        add,
+        sig,
+        MethodKind::Static,
+        MethodImpl::Missing,
+        vec![None, None],
+    ));
+    let pe = PEExporter::new(true)
+        .export_to_bytes(&asm, "demo.dll")
+        .unwrap();
+    let reader = MetadataReader::new(&pe).unwrap();
+    // The PE exporter always names the assembly `_`, like the IL exporter.
+    assert_eq!(reader.assembly_name().unwrap(), "_");
+    let point = reader
+        .tokens(TableId::TypeDef)
+        .find(|tdef| reader.type_name(*tdef).unwrap() == "Demo.Point")
+        .unwrap();
+    let mut out = Assembly::default();
+    let fields = reader.list(point, 4);
+    assert_eq!(fields.len(), 1);
+    let field = MetadataToken::new(TableId::Field, fields.start);
+    assert_eq!(reader.string(field, 1).unwrap(), "x");
+    let field_tpe = reader.decode_field_sig(reader.blob(field, 2).unwrap(), &mut out);
+    assert_eq!(field_tpe, Ok(Type::Int(Int::I32)));
+    let method = reader
+        .list(point, 5)
+        .map(|row| MetadataToken::new(TableId::MethodDef, row))
+        .find(|method| reader.string(*method, 3).unwrap() == "Add")
+        .unwrap();
+    let sig = reader
+        .decode_method_sig(reader.blob(method, 4).unwrap(), &mut out)
+        .unwrap();
+    assert!(!sig.has_this);
+    assert_eq!(
+        sig.sig,
+        FnSig::new(
+            [Type::Int(Int::I32), Type::PlatformString].into(),
+            Type::Bool
+        )
+    );
+}
+#[test]
+fn not_a_pe() {
+    assert!(MetadataReader::new(&[0; 128]).is_err());
+}
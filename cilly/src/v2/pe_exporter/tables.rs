@@ -0,0 +1,497 @@
+use fxhash::FxHashMap;
+
+/// ECMA-335 metadata tables (II.22), and the Portable PDB tables emitted alongside them. The PE exporter only emits some
+/// of them, but the [`super::reader`] can read all of them.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum TableId {
+    Module = 0x00,
+    TypeRef = 0x01,
+    TypeDef = 0x02,
+    FieldPtr = 0x03,
+    Field = 0x04,
+    MethodPtr = 0x05,
+    MethodDef = 0x06,
+    ParamPtr = 0x07,
+    Param = 0x08,
+    InterfaceImpl = 0x09,
+    MemberRef = 0x0A,
+    Constant = 0x0B,
+    CustomAttribute = 0x0C,
+    FieldMarshal = 0x0D,
+    DeclSecurity = 0x0E,
+    ClassLayout = 0x0F,
+    FieldLayout = 0x10,
+    StandAloneSig = 0x11,
+    EventMap = 0x12,
+    EventPtr = 0x13,
+    Event = 0x14,
+    PropertyMap = 0x15,
+    PropertyPtr = 0x16,
+    Property = 0x17,
+    MethodSemantics = 0x18,
+    MethodImpl = 0x19,
+    ModuleRef = 0x1A,
+    TypeSpec = 0x1B,
+    ImplMap = 0x1C,
+    FieldRVA = 0x1D,
+    EncLog = 0x1E,
+    EncMap = 0x1F,
+    Assembly = 0x20,
+    AssemblyProcessor = 0x21,
+    AssemblyOS = 0x22,
+    AssemblyRef = 0x23,
+    AssemblyRefProcessor = 0x24,
+    AssemblyRefOS = 0x25,
+    File = 0x26,
+    ExportedType = 0x27,
+    ManifestResource = 0x28,
+    NestedClass = 0x29,
+    GenericParam = 0x2A,
+    MethodSpec = 0x2B,
+    GenericParamConstraint = 0x2C,
+    Document = 0x30,
+    MethodDebugInformation = 0x31,
+    LocalScope = 0x32,
+    LocalVariable = 0x33,
+    LocalConstant = 0x34,
+    ImportScope = 0x35,
+}
+impl TableId {
+    /// All tables, in the order they must be serialized.
+    pub const ALL: [Self; 51] = [
+        Self::Module,
+        Self::TypeRef,
+        Self::TypeDef,
+        Self::FieldPtr,
+        Self::Field,
+        Self::MethodPtr,
+        Self::MethodDef,
+        Self::ParamPtr,
+        Self::Param,
+        Self::InterfaceImpl,
+        Self::MemberRef,
+        Self::Constant,
+        Self::CustomAttribute,
+        Self::FieldMarshal,
+        Self::DeclSecurity,
+        Self::ClassLayout,
+        Self::FieldLayout,
+        Self::StandAloneSig,
+        Self::EventMap,
+        Self::EventPtr,
+        Self::Event,
+        Self::PropertyMap,
+        Self::PropertyPtr,
+        Self::Property,
+        Self::MethodSemantics,
+        Self::MethodImpl,
+        Self::ModuleRef,
+        Self::TypeSpec,
+        Self::ImplMap,
+        Self::FieldRVA,
+        Self::EncLog,
+        Self::EncMap,
+        Self::Assembly,
+        Self::AssemblyProcessor,
+        Self::AssemblyOS,
+        Self::AssemblyRef,
+        Self::AssemblyRefProcessor,
+        Self::AssemblyRefOS,
+        Self::File,
+        Self::ExportedType,
+        Self::ManifestResource,
+        Self::NestedClass,
+        Self::GenericParam,
+        Self::MethodSpec,
+        Self::GenericParamConstraint,
+        Self::Document,
+        Self::MethodDebugInformation,
+        Self::LocalScope,
+        Self::LocalVariable,
+        Self::LocalConstant,
+        Self::ImportScope,
+    ];
+    /// Returns true if this table describes the type system, and not the debug information.
+    #[must_use]
+    pub fn is_type_system(self) -> bool {
+        (self as u8) < (Self::Document as u8)
+    }
+    /// Returns the column, by which this table must be sorted, if it is a sorted table.
+    fn sort_key(self) -> Option<usize> {
+        match self {
+            Self::InterfaceImpl
+            | Self::CustomAttribute
+            | Self::MethodImpl
+            | Self::ImplMap
+            | Self::NestedClass
+            | Self::LocalScope => Some(0),
+            Self::ClassLayout => Some(2),
+            Self::FieldLayout => Some(1),
+            Self::GenericParam => Some(2),
+            _ => None,
+        }
+    }
+}
+/// A reference to a row in a metadata table. The `row` is 1-based, as in ECMA-335.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MetadataToken {
+    table: TableId,
+    row: u32,
+}
+impl MetadataToken {
+    #[must_use]
+    pub fn new(table: TableId, row: u32) -> Self {
+        assert!(row < (1 << 24), "Metadata table {table:?} overflow");
+        Self { table, row }
+    }
+    /// Returns the encoded form of this token, as used by IL instructions.
+    /// ```
+    /// # use cilly::v2::pe_exporter::tables::*;
+    /// assert_eq!(MetadataToken::new(TableId::MethodDef, 3).raw(), 0x0600_0003);
+    /// ```
+    #[must_use]
+    pub fn raw(&self) -> u32 {
+        (u32::from(self.table as u8) << 24) | self.row
+    }
+    #[must_use]
+    pub fn table(&self) -> TableId {
+        self.table
+    }
+    #[must_use]
+    pub fn row(&self) -> u32 {
+        self.row
+    }
+}
+/// Kinds of coded indices (II.24.2.6).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CodedIndex {
+    TypeDefOrRef,
+    HasConstant,
+    HasCustomAttribute,
+    HasFieldMarshal,
+    HasDeclSecurity,
+    MemberRefParent,
+    HasSemantics,
+    MethodDefOrRef,
+    MemberForwarded,
+    Implementation,
+    CustomAttributeType,
+    ResolutionScope,
+    TypeOrMethodDef,
+}
+impl CodedIndex {
+    /// The tables this coded index can point into, in tag order.
+    pub(super) fn tables(self) -> &'static [Option<TableId>] {
+        match self {
+            Self::TypeDefOrRef => &[
+                Some(TableId::TypeDef),
+                Some(TableId::TypeRef),
+                Some(TableId::TypeSpec),
+            ],
+            Self::HasConstant => &[
+                Some(TableId::Field),
+                Some(TableId::Param),
+                Some(TableId::Property),
+            ],
+            Self::HasCustomAttribute => &[
+                Some(TableId::MethodDef),
+                Some(TableId::Field),
+                Some(TableId::TypeRef),
+                Some(TableId::TypeDef),
+                Some(TableId::Param),
+                Some(TableId::InterfaceImpl),
+                Some(TableId::MemberRef),
+                Some(TableId::Module),
+                Some(TableId::DeclSecurity),
+                Some(TableId::Property),
+                Some(TableId::Event),
+                Some(TableId::StandAloneSig),
+                Some(TableId::ModuleRef),
+                Some(TableId::TypeSpec),
+                Some(TableId::Assembly),
+                Some(TableId::AssemblyRef),
+                Some(TableId::File),
+                Some(TableId::ExportedType),
+                Some(TableId::ManifestResource),
+                Some(TableId::GenericParam),
+                Some(TableId::GenericParamConstraint),
+                Some(TableId::MethodSpec),
+            ],
+            Self::HasFieldMarshal => &[Some(TableId::Field), Some(TableId::Param)],
+            Self::HasDeclSecurity => &[
+                Some(TableId::TypeDef),
+                Some(TableId::MethodDef),
+                Some(TableId::Assembly),
+            ],
+            Self::MemberRefParent => &[
+                Some(TableId::TypeDef),
+                Some(TableId::TypeRef),
+                Some(TableId::ModuleRef),
+                Some(TableId::MethodDef),
+                Some(TableId::TypeSpec),
+            ],
+            Self::HasSemantics => &[Some(TableId::Event), Some(TableId::Property)],
+            Self::MethodDefOrRef => &[Some(TableId::MethodDef), Some(TableId::MemberRef)],
+            Self::MemberForwarded => &[Some(TableId::Field), Some(TableId::MethodDef)],
+            Self::Implementation => &[
+                Some(TableId::File),
+                Some(TableId::AssemblyRef),
+                Some(TableId::ExportedType),
+            ],
+            Self::CustomAttributeType => &[
+                None,
+                None,
+                Some(TableId::MethodDef),
+                Some(TableId::MemberRef),
+                None,
+            ],
+            Self::ResolutionScope => &[
+                Some(TableId::Module),
+                Some(TableId::ModuleRef),
+                Some(TableId::AssemblyRef),
+                Some(TableId::TypeRef),
+            ],
+            Self::TypeOrMethodDef => &[Some(TableId::TypeDef), Some(TableId::MethodDef)],
+        }
+    }
+    pub(super) fn tag_bits(self) -> u32 {
+        (self.tables().len() as u32)
+            .next_power_of_two()
+            .trailing_zeros()
+    }
+    /// Encodes `token` as this coded index.
+    /// ```
+    /// # use cilly::v2::pe_exporter::tables::*;
+    /// let tref = MetadataToken::new(TableId::TypeRef, 5);
+    /// assert_eq!(CodedIndex::TypeDefOrRef.encode(tref), (5 << 2) | 1);
+    /// ```
+    #[must_use]
+    pub fn encode(self, token: MetadataToken) -> u32 {
+        let tag = self
+            .tables()
+            .iter()
+            .position(|table| *table == Some(token.table()))
+            .unwrap_or_else(|| panic!("{:?} can't be encoded as {self:?}", token.table()));
+        (token.row() << self.tag_bits()) | tag as u32
+    }
+    /// Decodes a coded index. Returns `None` for null indices, and for tags not used by this kind.
+    /// ```
+    /// # use cilly::v2::pe_exporter::tables::*;
+    /// let tref = MetadataToken::new(TableId::TypeRef, 5);
+    /// assert_eq!(CodedIndex::TypeDefOrRef.decode((5 << 2) | 1), Some(tref));
+    /// ```
+    #[must_use]
+    pub fn decode(self, raw: u32) -> Option<MetadataToken> {
+        let bits = self.tag_bits();
+        let row = raw >> bits;
+        if row == 0 {
+            return None;
+        }
+        let table = (*self.tables().get((raw & ((1 << bits) - 1)) as usize)?)?;
+        Some(MetadataToken::new(table, row))
+    }
+}
+/// A single column of a metadata table row.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Column {
+    U16(u16),
+    U32(u32),
+    String(u32),
+    Guid(u32),
+    Blob(u32),
+    /// An index into a table. A row of 0 means "null".
+    Index(TableId, u32),
+    Coded(CodedIndex, Option<MetadataToken>),
+}
+impl Column {
+    fn sort_value(&self) -> u64 {
+        match self {
+            Column::U16(val) => u64::from(*val),
+            Column::U32(val) | Column::String(val) | Column::Guid(val) | Column::Blob(val) => {
+                u64::from(*val)
+            }
+            Column::Index(_, row) => u64::from(*row),
+            Column::Coded(kind, token) => token.map_or(0, |token| u64::from(kind.encode(token))),
+        }
+    }
+}
+/// Sizes of the heaps, used to calculate index widths.
+pub struct HeapSizes {
+    pub strings: usize,
+    pub guid: usize,
+    pub blob: usize,
+}
+/// The contents of the `#~` stream.
+#[derive(Default)]
+pub struct Tables {
+    rows: FxHashMap<TableId, Vec<Box<[Column]>>>,
+    /// Row counts of tables stored in another metadata stream, but referenced by these tables.
+    /// Portable PDBs use them to index into the type system tables of their assembly.
+    external_rows: FxHashMap<TableId, u32>,
+}
+impl Tables {
+    /// Creates tables referencing rows of `external_rows`, which live in another metadata stream.
+    #[must_use]
+    pub fn with_external_rows(external_rows: FxHashMap<TableId, u32>) -> Self {
+        Self {
+            rows: FxHashMap::default(),
+            external_rows,
+        }
+    }
+    /// Adds a new row to `table`, returning a token pointing to it.
+    pub fn push(&mut self, table: TableId, row: impl Into<Box<[Column]>>) -> MetadataToken {
+        let rows = self.rows.entry(table).or_default();
+        rows.push(row.into());
+        MetadataToken::new(table, u32::try_from(rows.len()).unwrap())
+    }
+    /// Returns the number of rows in `table`.
+    #[must_use]
+    pub fn len(&self, table: TableId) -> u32 {
+        self.rows
+            .get(&table)
+            .map_or(0, |rows| u32::try_from(rows.len()).unwrap())
+    }
+    /// Returns the token, the next row pushed to `table` will have.
+    #[must_use]
+    pub fn next_row(&self, table: TableId) -> MetadataToken {
+        MetadataToken::new(table, self.len(table) + 1)
+    }
+    /// Overwrites a column of an already pushed row.
+    pub fn set(&mut self, token: MetadataToken, column: usize, value: Column) {
+        self.rows.get_mut(&token.table()).unwrap()[token.row() as usize - 1][column] = value;
+    }
+    /// Returns the number of rows of `table`, including rows stored in other metadata streams.
+    fn rows_for_width(&self, table: TableId) -> u32 {
+        self.len(table) + self.external_rows.get(&table).copied().unwrap_or(0)
+    }
+    fn index_wide(&self, table: TableId) -> bool {
+        self.rows_for_width(table) >= (1 << 16)
+    }
+    fn coded_wide(&self, kind: CodedIndex) -> bool {
+        let max_rows = kind
+            .tables()
+            .iter()
+            .flatten()
+            .map(|table| self.rows_for_width(*table))
+            .max()
+            .unwrap_or(0);
+        max_rows >= (1 << (16 - kind.tag_bits()))
+    }
+    /// Serializes the tables into the `#~` stream format.
+    #[must_use]
+    pub fn serialize(&self, heaps: &HeapSizes) -> Vec<u8> {
+        let strings_wide = heaps.strings >= (1 << 16);
+        let guid_wide = heaps.guid >= (1 << 16);
+        let blob_wide = heaps.blob >= (1 << 16);
+        let mut out = vec![];
+        // Reserved
+        out.extend_from_slice(&0_u32.to_le_bytes());
+        // Version 2.0
+        out.push(2);
+        out.push(0);
+        let heap_sizes =
+            u8::from(strings_wide) | (u8::from(guid_wide) << 1) | (u8::from(blob_wide) << 2);
+        out.push(heap_sizes);
+        // Reserved
+        out.push(1);
+        let present: Vec<TableId> = TableId::ALL
+            .iter()
+            .copied()
+            .filter(|table| self.len(*table) > 0)
+            .collect();
+        let valid = present
+            .iter()
+            .fold(0_u64, |mask, table| mask | (1 << (*table as u8)));
+        let sorted = TableId::ALL
+            .iter()
+            .filter(|table| table.sort_key().is_some())
+            .fold(0_u64, |mask, table| mask | (1 << (*table as u8)));
+        out.extend_from_slice(&valid.to_le_bytes());
+        out.extend_from_slice(&sorted.to_le_bytes());
+        for table in &present {
+            out.extend_from_slice(&self.len(*table).to_le_bytes());
+        }
+        let write_idx = |out: &mut Vec<u8>, val: u32, wide: bool| {
+            if wide {
+                out.extend_from_slice(&val.to_le_bytes());
+            } else {
+                out.extend_from_slice(&u16::try_from(val).unwrap().to_le_bytes());
+            }
+        };
+        for table in present {
+            let mut rows: Vec<&Box<[Column]>> = self.rows[&table].iter().collect();
+            if let Some(key) = table.sort_key() {
+                rows.sort_by_key(|row| row[key].sort_value());
+            }
+            for row in rows {
+                for column in row.iter() {
+                    match column {
+                        Column::U16(val) => out.extend_from_slice(&val.to_le_bytes()),
+                        Column::U32(val) => out.extend_from_slice(&val.to_le_bytes()),
+                        Column::String(val) => write_idx(&mut out, *val, strings_wide),
+                        Column::Guid(val) => write_idx(&mut out, *val, guid_wide),
+                        Column::Blob(val) => write_idx(&mut out, *val, blob_wide),
+                        Column::Index(table, row) => {
+                            write_idx(&mut out, *row, self.index_wide(*table));
+                        }
+                        Column::Coded(kind, token) => write_idx(
+                            &mut out,
+                            token.map_or(0, |token| kind.encode(token)),
+                            self.coded_wide(*kind),
+                        ),
+                    }
+                }
+            }
+        }
+        // The stream must be 4 byte aligned.
+        while !out.len().is_multiple_of(4) {
+            out.push(0);
+        }
+        out
+    }
+}
+#[test]
+fn coded_index_width() {
+    let mut tables = Tables::default();
+    assert!(!tables.coded_wide(CodedIndex::HasCustomAttribute));
+    for _ in 0..(1 << 11) {
+        tables.push(TableId::MemberRef, [Column::U16(0)]);
+    }
+    // HasCustomAttribute uses 5 tag bits, so 2^11 rows no longer fit in 16 bits.
+    assert!(tables.coded_wide(CodedIndex::HasCustomAttribute));
+    assert!(!tables.coded_wide(CodedIndex::MethodDefOrRef));
+}
+#[test]
+fn sorted_tables() {
+    let mut tables = Tables::default();
+    let field_b = MetadataToken::new(TableId::Field, 2);
+    let field_a = MetadataToken::new(TableId::Field, 1);
+    tables.push(
+        TableId::CustomAttribute,
+        [
+            Column::Coded(CodedIndex::HasCustomAttribute, Some(field_b)),
+            Column::Coded(CodedIndex::CustomAttributeType, None),
+            Column::Blob(0),
+        ],
+    );
+    tables.push(
+        TableId::CustomAttribute,
+        [
+            Column::Coded(CodedIndex::HasCustomAttribute, Some(field_a)),
+            Column::Coded(CodedIndex::CustomAttributeType, None),
+            Column::Blob(0),
+        ],
+    );
+    let data = tables.serialize(&HeapSizes {
+        strings: 0,
+        guid: 0,
+        blob: 0,
+    });
+    // Header(24 bytes) + 1 row count, then the first row must point to field 1.
+    let first_parent = u16::from_le_bytes([data[28], data[29]]);
+    assert_eq!(
+        u32::from(first_parent),
+        CodedIndex::HasCustomAttribute.encode(field_a)
+    );
+}
@@ -0,0 +1,280 @@
+use serde::{Deserialize, Serialize};
+
+use super::Int;
+/// The cases of a [`super::CILRoot::Switch`]: a jump target for some values of an int, and a default target for all other values.
+#[derive(Hash, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct SwitchTable {
+    /// The type of the value being switched on.
+    tpe: Int,
+    /// The values of the cases, truncated to the size of `tpe`.
+    values: Box<[u64]>,
+    /// The target and sub target of each case, followed by the default target.
+    targets: Box<[(u32, u32)]>,
+}
+/// The largest amount of cases compared one by one, instead of using a jump table or a binary search.
+const MAX_LINEAR_CASES: usize = 3;
+impl SwitchTable {
+    /// Creates a switch over a value of type `tpe`, jumping to the target of the case with a matching value, or to `otherwise`.
+    /// ```
+    /// # use cilly::v2::{Int, switch::SwitchTable};
+    /// let table = SwitchTable::new(Int::I8, [(u64::MAX, 1), (2, 2)], 3);
+    /// // Values are truncated to the size of the int.
+    /// assert_eq!(table.target_of(0xFF), (1, 0));
+    /// assert_eq!(table.target_of(2), (2, 0));
+    /// assert_eq!(table.target_of(7), (3, 0));
+    /// ```
+    #[must_use]
+    pub fn new(tpe: Int, cases: impl IntoIterator<Item = (u64, u32)>, otherwise: u32) -> Self {
+        assert!(
+            !matches!(tpe, Int::I128 | Int::U128),
+            "ERROR: switches over 128 bit ints are not supported"
+        );
+        let (values, mut targets): (Vec<_>, Vec<_>) = cases
+            .into_iter()
+            .map(|(value, target)| (truncate(tpe, value), (target, 0)))
+            .unzip();
+        targets.push((otherwise, 0));
+        Self {
+            tpe,
+            values: values.into(),
+            targets: targets.into(),
+        }
+    }
+    /// The type of the value being switched on.
+    #[must_use]
+    pub fn tpe(&self) -> Int {
+        self.tpe
+    }
+    /// Iterates trough the values of all cases, and their target and sub target.
+    pub fn cases(&self) -> impl Iterator<Item = (u64, (u32, u32))> + '_ {
+        self.values
+            .iter()
+            .copied()
+            .zip(self.targets.iter().copied())
+    }
+    /// The target and sub target used when no case matches.
+    #[must_use]
+    pub fn otherwise(&self) -> (u32, u32) {
+        *self
+            .targets
+            .last()
+            .expect("ERROR: a switch must have a default target")
+    }
+    /// The target and sub target of each case, followed by the default target.
+    #[must_use]
+    pub fn targets(&self) -> &[(u32, u32)] {
+        &self.targets
+    }
+    /// The target and sub target of each case, followed by the default target.
+    pub fn targets_mut(&mut self) -> &mut [(u32, u32)] {
+        &mut self.targets
+    }
+    /// Returns the target and sub target jumped to when the switched-on value is `value`.
+    #[must_use]
+    pub fn target_of(&self, value: u64) -> (u32, u32) {
+        let value = truncate(self.tpe, value);
+        self.cases()
+            .find(|(case, _)| *case == value)
+            .map_or_else(|| self.otherwise(), |(_, target)| target)
+    }
+    /// Returns `value` (the value of some case) sign extended if this switch is over a signed int, and zero extended otherwise.
+    /// Cases are ordered by this value.
+    #[must_use]
+    pub fn extend(&self, value: u64) -> i128 {
+        if self.tpe.is_signed() {
+            let bits = self.tpe.bits().unwrap_or(64);
+            i128::from(((value << (64 - bits)) as i64) >> (64 - bits))
+        } else {
+            i128::from(value)
+        }
+    }
+    /// Decides how this switch should be lowered by exporters without a native sparse switch.
+    /// Dense cases use jump tables, and sparse cases are split using a binary search.
+    /// ```
+    /// # use cilly::v2::{Int, switch::{SwitchTable, SwitchPlan}};
+    /// let dense = SwitchTable::new(Int::U8, (0..8).map(|val| (val, val as u32)), 8);
+    /// assert!(matches!(dense.plan(), SwitchPlan::Table { min: 0, .. }));
+    /// let sparse = SwitchTable::new(Int::U32, (0..8).map(|val| (val * 1000, val as u32)), 8);
+    /// assert!(matches!(sparse.plan(), SwitchPlan::Split { pivot: 4000, .. }));
+    /// ```
+    #[must_use]
+    pub fn plan(&self) -> SwitchPlan {
+        let mut cases: Vec<_> = self.cases().collect();
+        cases.sort_by_key(|(value, _)| self.extend(*value));
+        self.plan_sorted(&cases)
+    }
+    fn plan_sorted(&self, cases: &[(u64, (u32, u32))]) -> SwitchPlan {
+        let (Some((min, _)), Some((max, _))) = (cases.first(), cases.last()) else {
+            return SwitchPlan::Cases(vec![]);
+        };
+        let span = self.extend(*max) - self.extend(*min) + 1;
+        // A jump table is used if at least half of its entries are not the default target.
+        if cases.len() > MAX_LINEAR_CASES && span <= 2 * cases.len() as i128 {
+            let mut targets = vec![self.otherwise(); span as usize];
+            for (value, target) in cases {
+                targets[(self.extend(*value) - self.extend(*min)) as usize] = *target;
+            }
+            return SwitchPlan::Table { min: *min, targets };
+        }
+        if cases.len() <= MAX_LINEAR_CASES {
+            return SwitchPlan::Cases(cases.to_vec());
+        }
+        let (lt, ge) = cases.split_at(cases.len() / 2);
+        SwitchPlan::Split {
+            pivot: ge[0].0,
+            lt: Box::new(self.plan_sorted(lt)),
+            ge: Box::new(self.plan_sorted(ge)),
+        }
+    }
+}
+/// Truncates `value` to the size of `tpe`.
+fn truncate(tpe: Int, value: u64) -> u64 {
+    match tpe.bits() {
+        Some(bits) if bits < 64 => value & ((1 << bits) - 1),
+        _ => value,
+    }
+}
+/// A lowering of a [`SwitchTable`] into comparisons and jump tables. All values are truncated to the size of the switched-on int.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SwitchPlan {
+    /// Compares the value with each case in turn, and jumps to the default target if none of them match.
+    Cases(Vec<(u64, (u32, u32))>),
+    /// Jumps to the `value - min`th target, or to the default target if that is out of range.
+    Table { min: u64, targets: Vec<(u32, u32)> },
+    /// Uses `lt` if the value is less than `pivot`(compared as signed, if the int is signed), and `ge` otherwise.
+    Split {
+        pivot: u64,
+        lt: Box<Self>,
+        ge: Box<Self>,
+    },
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::{
+        il_exporter::export_il,
+        interpreter::{add_method, Interpreter, Value},
+        Assembly, BasicBlock, CILNode, CILRoot, Const, Type,
+    };
+    #[test]
+    fn signed_order() {
+        // -3, -2, -1, 3: dense once sign extended, but not as unsigned values.
+        let table = SwitchTable::new(
+            Int::I16,
+            [
+                (-1_i64 as u64, 1),
+                (3, 2),
+                (-3_i64 as u64, 3),
+                (-2_i64 as u64, 4),
+            ],
+            0,
+        );
+        assert_eq!(table.extend(0xFFFF), -1);
+        let SwitchPlan::Table { min, targets } = table.plan() else {
+            panic!("Expected a jump table");
+        };
+        assert_eq!(min, 0xFFFD);
+        assert_eq!(
+            targets,
+            vec![(3, 0), (4, 0), (1, 0), (0, 0), (0, 0), (0, 0), (2, 0)]
+        );
+    }
+    #[test]
+    fn binary_search() {
+        let table = SwitchTable::new(Int::U64, (0..64).map(|val| (val * val, val as u32)), 64);
+        // Every case must be reachable trough the plan.
+        fn find(plan: &SwitchPlan, value: u64) -> Option<(u32, u32)> {
+            match plan {
+                SwitchPlan::Cases(cases) => cases
+                    .iter()
+                    .find(|(case, _)| *case == value)
+                    .map(|(_, target)| *target),
+                SwitchPlan::Table { min, targets } => {
+                    targets.get(value.checked_sub(*min)? as usize).copied()
+                }
+                SwitchPlan::Split { pivot, lt, ge } => {
+                    find(if value < *pivot { lt } else { ge }, value)
+                }
+            }
+        }
+        let plan = table.plan();
+        for val in 0..64 {
+            assert_eq!(find(&plan, val * val), Some((val as u32, 0)));
+            assert_eq!(table.target_of(val * val), (val as u32, 0));
+        }
+        assert_eq!(table.target_of(3), (64, 0));
+    }
+    #[test]
+    fn switch() {
+        let run = |value: i8| {
+            let mut asm = Assembly::default();
+            let value = asm.alloc_node(Const::I8(value));
+            let loc = asm.alloc_node(CILNode::LdLoc(0));
+            let table = SwitchTable::new(Int::I8, [(-2_i64 as u64, 1), (7, 2)], 3);
+            let mut blocks = vec![BasicBlock::new(
+                vec![
+                    asm.alloc_root(CILRoot::StLoc(0, value)),
+                    asm.alloc_root(CILRoot::Switch(Box::new((loc, table)))),
+                ],
+                0,
+                None,
+            )];
+            for target in 1..=3 {
+                let ret = asm.alloc_node(Const::I32(target * 10));
+                blocks.push(BasicBlock::new(
+                    vec![asm.alloc_root(CILRoot::Ret(ret))],
+                    target as u32,
+                    None,
+                ));
+            }
+            let int = Type::Int(Int::I32);
+            let mref = add_method(
+                &mut asm,
+                "switch",
+                &[],
+                int,
+                blocks,
+                vec![Type::Int(Int::I8)],
+            );
+            Interpreter::new(asm, vec![]).call(mref, vec![]).unwrap()
+        };
+        assert_eq!(run(-2), Some(Value::I32(10)));
+        assert_eq!(run(7), Some(Value::I32(20)));
+        assert_eq!(run(2), Some(Value::I32(30)));
+        assert_eq!(run(-1), Some(Value::I32(30)));
+    }
+    #[test]
+    fn switch_il() {
+        let export = |cases: &[(u64, u32)]| {
+            let mut asm = Assembly::default();
+            let value = asm.alloc_node(Const::I8(7));
+            let table = SwitchTable::new(Int::I8, cases.iter().copied(), 4);
+            let mut blocks = vec![BasicBlock::new(
+                vec![asm.alloc_root(CILRoot::Switch(Box::new((value, table))))],
+                0,
+                None,
+            )];
+            for target in 1..=4 {
+                let ret = asm.alloc_node(Const::I32(target * 10));
+                blocks.push(BasicBlock::new(
+                    vec![asm.alloc_root(CILRoot::Ret(ret))],
+                    target as u32,
+                    None,
+                ));
+            }
+            add_method(&mut asm, "switch", &[], Type::Int(Int::I32), blocks, vec![]);
+            export_il(&asm)
+        };
+        // Sparse values are compared one by one. Each target gets a trampoline, popping the switched-on value.
+        let il = export(&[(-2_i64 as u64, 1), (7, 2)]);
+        assert!(il.contains("dup\nldc.i4 -2\nbeq bb0_sw1\n"));
+        assert!(il.contains("dup\nldc.i4 7\nbeq bb0_sw2\n"));
+        assert!(il.contains("bb0_sw0: pop br bb4"));
+        assert!(il.contains("bb0_sw1: pop br bb1"));
+        assert!(il.contains("bb0_sw2: pop br bb2"));
+        // Dense values use a jump table.
+        let il = export(&[(0, 1), (1, 2), (2, 3), (3, 1)]);
+        assert!(il.contains("switch(bb0_sw1,bb0_sw2,bb0_sw3,bb0_sw1)\nbr bb0_sw0\n"));
+        assert!(il.contains("bb0_sw3: pop br bb3"));
+    }
+}
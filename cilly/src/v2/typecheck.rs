@@ -943,6 +943,23 @@ impl CILRoot {
                     }
                 }
             }
+            Self::Switch(boxed) => {
+                let (discr, table) = boxed.as_ref();
+                let discr = asm[*discr].clone().typecheck(sig, locals, asm)?;
+                let expected = Type::Int(table.tpe());
+                // The cases are just bit patterns, so the signedness of the value does not matter.
+                if discr
+                    .as_int()
+                    .is_some_and(|int| int.as_unsigned() == table.tpe().as_unsigned())
+                {
+                    Ok(())
+                } else {
+                    Err(TypeCheckError::CantCompareTypes {
+                        lhs: discr,
+                        rhs: expected,
+                    })
+                }
+            }
             Self::StInd(boxed) => {
                 let (addr, value, tpe, _) = boxed.as_ref();
                 let addr = asm[*addr].clone().typecheck(sig, locals, asm)?;
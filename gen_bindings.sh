@@ -0,0 +1,9 @@
+#!/bin/sh
+# Regenerates `mycorrhiza/src/bindings.rs` from the `System.Private.CoreLib` of the installed .NET 8 runtime.
+# Set CORELIB to the path of `System.Private.CoreLib.dll` to use a different one.
+set -e
+if [ -z "$CORELIB" ]; then
+    RUNTIME=$(dotnet --list-runtimes | grep '^Microsoft.NETCore.App 8\.' | tail -n 1)
+    CORELIB="$(echo "$RUNTIME" | sed 's/.*\[\(.*\)\]/\1/')/$(echo "$RUNTIME" | cut -d' ' -f2)/System.Private.CoreLib.dll"
+fi
+cd cilly && cargo run --release --bin bindgen -- "$CORELIB" --crate crate -o ../mycorrhiza/src/bindings.rs
@@ -2,7 +2,7 @@ use crate::{
     intrinsics::{RustcCLRInteropManagedClass, RustcCLRInteropManagedStruct},
     FromManagedSafe, IntoManagedSafe, ManagedSafe,
 };
-type GCHandle = RustcCLRInteropManagedStruct<
+pub(crate) type GCHandle = RustcCLRInteropManagedStruct<
     "System.Runtime",
     "System.Runtime.InteropServices.GCHandle",
     { size_of::<usize>() },
@@ -0,0 +1,101 @@
+use crate::intrinsics::{
+    rustc_clr_interop_managed_delegate_, RustcCLRInteropManagedClass,
+    RustcCLRInteropManagedGenericClass, RustcCLRInteropManagedGenericParam,
+};
+type T0 = RustcCLRInteropManagedGenericParam<0>;
+type T1 = RustcCLRInteropManagedGenericParam<1>;
+type T2 = RustcCLRInteropManagedGenericParam<2>;
+/// `System.Action`
+pub type Action = RustcCLRInteropManagedClass<"System.Runtime", "System.Action">;
+/// `System.Action<A>`
+pub type Action1<A> =
+    RustcCLRInteropManagedGenericClass<"System.Runtime", "System.Action", false, (A,)>;
+/// `System.Action<A, B>`
+pub type Action2<A, B> =
+    RustcCLRInteropManagedGenericClass<"System.Runtime", "System.Action", false, (A, B)>;
+/// `System.Func<R>`
+pub type Func0<R> =
+    RustcCLRInteropManagedGenericClass<"System.Runtime", "System.Func", false, (R,)>;
+/// `System.Func<A, R>`
+pub type Func1<A, R> =
+    RustcCLRInteropManagedGenericClass<"System.Runtime", "System.Func", false, (A, R)>;
+/// `System.Func<A, B, R>`
+pub type Func2<A, B, R> =
+    RustcCLRInteropManagedGenericClass<"System.Runtime", "System.Func", false, (A, B, R)>;
+impl Action {
+    #[inline(always)]
+    pub fn invoke(self) {
+        Self::virt0::<"Invoke", ()>(self)
+    }
+}
+impl<A> Action1<A> {
+    #[inline(always)]
+    pub fn invoke(self, a: A) {
+        Self::virt_call::<"Invoke", fn(T0), (Self, A), ()>((self, a))
+    }
+}
+impl<A, B> Action2<A, B> {
+    #[inline(always)]
+    pub fn invoke(self, a: A, b: B) {
+        Self::virt_call::<"Invoke", fn(T0, T1), (Self, A, B), ()>((self, a, b))
+    }
+}
+impl<R> Func0<R> {
+    #[inline(always)]
+    pub fn invoke(self) -> R {
+        Self::virt_call::<"Invoke", fn() -> T0, (Self,), R>((self,))
+    }
+}
+impl<A, R> Func1<A, R> {
+    #[inline(always)]
+    pub fn invoke(self, a: A) -> R {
+        Self::virt_call::<"Invoke", fn(T0) -> T1, (Self, A), R>((self, a))
+    }
+}
+impl<A, B, R> Func2<A, B, R> {
+    #[inline(always)]
+    pub fn invoke(self, a: A, b: B) -> R {
+        Self::virt_call::<"Invoke", fn(T0, T1) -> T2, (Self, A, B), R>((self, a, b))
+    }
+}
+/// A Rust closure, which can be turned into a managed delegate whose `Invoke` method has the signature `Sig`, like
+/// `fn(i32) -> bool` for `Func<int, bool>`.
+///
+/// The delegate owns the closure, and drops it once it gets collected. .NET may invoke the delegate, and run its
+/// finalizer, on any thread, so the closure must be `Send` and `Sync`.
+pub trait IntoDelegate<Sig> {
+    /// Creates a delegate of type `Delegate`, like [`Func1<i32, bool>`].
+    fn into_delegate<Delegate>(self) -> Delegate;
+}
+fn drop_closure<F>(env: *mut ()) {
+    // SAFETY: `env` is the closure boxed by `into_delegate`, and the finalizer of the delegate runs only once.
+    drop(unsafe { Box::from_raw(env.cast::<F>()) });
+}
+macro_rules! into_delegate {
+    ($($arg:ident: $tpe:ident),*) => {
+        impl<F, R, $($tpe),*> IntoDelegate<fn($($tpe),*) -> R> for F
+        where
+            F: Fn($($tpe),*) -> R + Send + Sync + 'static,
+        {
+            fn into_delegate<Delegate>(self) -> Delegate {
+                fn invoke<F: Fn($($tpe),*) -> R, R, $($tpe),*>(env: *mut (), $($arg: $tpe),*) -> R {
+                    // SAFETY: `env` is the closure boxed by `into_delegate`, and the delegate keeps it alive
+                    // during the call.
+                    let closure = unsafe { &*env.cast::<F>() };
+                    closure($($arg),*)
+                }
+                let env = Box::into_raw(Box::new(self)).cast::<()>();
+                rustc_clr_interop_managed_delegate_::<Delegate, fn($($tpe),*) -> R>(
+                    env,
+                    invoke::<F, R, $($tpe),*> as *const (),
+                    drop_closure::<F> as *const (),
+                )
+            }
+        }
+    };
+}
+into_delegate!();
+into_delegate!(a: A);
+into_delegate!(a: A, b: B);
+into_delegate!(a: A, b: B, c: C);
+into_delegate!(a: A, b: B, c: C, d: D);
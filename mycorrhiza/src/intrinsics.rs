@@ -1,4 +1,4 @@
-use std::ptr::null;
+use std::{marker::PhantomData, ptr::null};
 
 use crate::ManagedSafe;
 
@@ -17,6 +17,44 @@ pub struct RustcCLRInteropManagedStruct<
 > {
     size_hint: [u8; SIZE],
 }
+/// A reference to an instance of a closed generic managed class, like `List<int>`, or an instance of a generic value
+/// type, if `IS_VALUETYPE` is true. `Generics` is a tuple of the generic arguments of the class. `CLASS_PATH` is the name
+/// of the class without its arity, like `System.Collections.Generic.List`.
+#[repr(C)]
+pub struct RustcCLRInteropManagedGenericClass<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    Generics,
+> {
+    size_hint: usize,
+    generics: PhantomData<Generics>,
+}
+impl<
+        const ASSEMBLY: &'static str,
+        const CLASS_PATH: &'static str,
+        const IS_VALUETYPE: bool,
+        Generics,
+    > Clone for RustcCLRInteropManagedGenericClass<ASSEMBLY, CLASS_PATH, IS_VALUETYPE, Generics>
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<
+        const ASSEMBLY: &'static str,
+        const CLASS_PATH: &'static str,
+        const IS_VALUETYPE: bool,
+        Generics,
+    > Copy for RustcCLRInteropManagedGenericClass<ASSEMBLY, CLASS_PATH, IS_VALUETYPE, Generics>
+{
+}
+/// The generic parameter number `INDEX` of a class(`!INDEX`). Used in the declared signatures of managed methods, which
+/// refer to the generic parameters of their class, and not to the generic arguments.
+pub struct RustcCLRInteropManagedGenericParam<const INDEX: u32>;
+/// The generic parameter number `INDEX` of a method(`!!INDEX`). Used in the declared signatures of generic managed
+/// methods.
+pub struct RustcCLRInteropManagedMethodGenericParam<const INDEX: u32>;
 
 impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str>
     RustcCLRInteropManagedClass<ASSEMBLY, CLASS_PATH>
@@ -39,6 +77,86 @@ impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str>
             arg1, arg2, arg3,
         )
     }
+    /// Creates a new object, calling the constructor with the arguments in the tuple `args`.
+    /// Rust references are passed as managed references, so they can be used for `out` and `ref` parameters.
+    #[inline(always)]
+    pub fn ctor<Args>(args: Args) -> Self {
+        rustc_clr_interop_managed_ctor_tuple_::<ASSEMBLY, CLASS_PATH, false, Args>(args)
+    }
+    /// Calls the static method `METHOD`, with the arguments in the tuple `args`.
+    /// Rust references are passed as managed references, so they can be used for `out` and `ref` parameters.
+    #[inline(always)]
+    pub fn static_call<const METHOD: &'static str, Args, Ret>(args: Args) -> Ret {
+        rustc_clr_interop_managed_call_tuple_::<ASSEMBLY, CLASS_PATH, false, METHOD, true, Ret, Args>(
+            args,
+        )
+    }
+    /// Calls the instance method `METHOD`, with the arguments in the tuple `args`. The first element of `args` is
+    /// `this`.
+    #[inline(always)]
+    pub fn instance_call<const METHOD: &'static str, Args, Ret>(args: Args) -> Ret {
+        rustc_clr_interop_managed_call_tuple_::<ASSEMBLY, CLASS_PATH, false, METHOD, false, Ret, Args>(
+            args,
+        )
+    }
+    /// Calls the virtual method `METHOD`, with the arguments in the tuple `args`. The first element of `args` is
+    /// `this`.
+    #[inline(always)]
+    pub fn virt_call<const METHOD: &'static str, Args, Ret>(args: Args) -> Ret {
+        rustc_clr_interop_managed_call_virt_tuple_::<
+            ASSEMBLY,
+            CLASS_PATH,
+            false,
+            METHOD,
+            false,
+            Ret,
+            Args,
+        >(args)
+    }
+    /// Calls the generic method `METHOD`, with the generic arguments `MethodGenerics`. `Sig` is the declared signature of
+    /// the method, as described in [`rustc_clr_interop_managed_call_generic_`].
+    #[inline(always)]
+    pub fn generic_call<
+        const METHOD: &'static str,
+        const IS_STATIC: bool,
+        Sig,
+        MethodGenerics,
+        Args,
+        Ret,
+    >(
+        args: Args,
+    ) -> Ret {
+        rustc_clr_interop_managed_call_generic_::<
+            Self,
+            METHOD,
+            IS_STATIC,
+            false,
+            Sig,
+            MethodGenerics,
+            Ret,
+            Args,
+        >(args)
+    }
+    /// Loads the instance field `FIELD`.
+    #[inline(always)]
+    pub fn field<const FIELD: &'static str, T>(self) -> T {
+        rustc_clr_interop_managed_ld_fld::<ASSEMBLY, CLASS_PATH, false, FIELD, T, Self>(self)
+    }
+    /// Sets the instance field `FIELD` to `value`.
+    #[inline(always)]
+    pub fn set_field<const FIELD: &'static str, T>(self, value: T) {
+        rustc_clr_interop_managed_st_fld::<ASSEMBLY, CLASS_PATH, false, FIELD, T, Self>(self, value)
+    }
+    /// Loads the static field `FIELD`.
+    #[inline(always)]
+    pub fn static_field<const FIELD: &'static str, T>() -> T {
+        rustc_clr_interop_managed_ld_sfld::<ASSEMBLY, CLASS_PATH, false, FIELD, T>()
+    }
+    /// Sets the static field `FIELD` to `value`.
+    #[inline(always)]
+    pub fn set_static_field<const FIELD: &'static str, T>(value: T) {
+        rustc_clr_interop_managed_st_sfld::<ASSEMBLY, CLASS_PATH, false, FIELD, T>(value)
+    }
     #[inline(always)]
     pub fn static0<const METHOD: &'static str, Ret>() -> Ret {
         rustc_clr_interop_managed_call0_::<ASSEMBLY, CLASS_PATH, false, METHOD, Ret>()
@@ -205,7 +323,41 @@ pub fn rustc_clr_interop_managed_call3_<
 ) -> Ret {
     core::intrinsics::abort();
 }
+/// Calls a method with any number of arguments, passed as the tuple `args`. If the method is not static, the first
+/// element of `args` is `this`. Rust references are passed as managed references(`T&`), so they can be used for
+/// `out`, `ref` and `in` parameters. Raw pointers are passed as unmanaged pointers.
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_managed_call_tuple_<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    const METHOD: &'static str,
+    const IS_STATIC: bool,
+    Ret,
+    Args,
+>(
+    args: Args,
+) -> Ret {
+    core::intrinsics::abort();
+}
 //VCalls
+/// Like [`rustc_clr_interop_managed_call_tuple_`], but calls the method virtually.
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_managed_call_virt_tuple_<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    const METHOD: &'static str,
+    const IS_STATIC: bool,
+    Ret,
+    Args,
+>(
+    args: Args,
+) -> Ret {
+    core::intrinsics::abort();
+}
 #[allow(unused_variables)]
 #[inline(never)]
 pub fn rustc_clr_interop_managed_call_virt0_<
@@ -316,6 +468,116 @@ pub fn rustc_clr_interop_managed_ctor3_<
 ) -> RustcCLRInteropManagedClass<ASSEMBLY, CLASS_PATH> {
     core::intrinsics::abort();
 }
+/// Calls a constructor with any number of arguments, passed as the tuple `args`.
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_managed_ctor_tuple_<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    Args,
+>(
+    args: Args,
+) -> RustcCLRInteropManagedClass<ASSEMBLY, CLASS_PATH> {
+    core::intrinsics::abort();
+}
+/// Loads the instance field `FIELD` of `obj`. Objects of value types are passed by reference.
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_managed_ld_fld<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    const FIELD: &'static str,
+    T,
+    Obj,
+>(
+    obj: Obj,
+) -> T {
+    core::intrinsics::abort();
+}
+/// Sets the instance field `FIELD` of `obj` to `value`. Objects of value types are passed by reference.
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_managed_st_fld<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    const FIELD: &'static str,
+    T,
+    Obj,
+>(
+    obj: Obj,
+    value: T,
+) {
+    core::intrinsics::abort();
+}
+/// Loads the static field `FIELD`.
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_managed_ld_sfld<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    const FIELD: &'static str,
+    T,
+>() -> T {
+    core::intrinsics::abort();
+}
+/// Sets the static field `FIELD` to `value`.
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_managed_st_sfld<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    const FIELD: &'static str,
+    T,
+>(
+    value: T,
+) {
+    core::intrinsics::abort();
+}
+/// Calls the method `METHOD` of `Class`, which may be a generic class. `Sig` is the declared signature of the method,
+/// as a function pointer type without `this`: the parameters using the generic parameters of the class or the method
+/// must use [`RustcCLRInteropManagedGenericParam`] and [`RustcCLRInteropManagedMethodGenericParam`]. `MethodGenerics` is
+/// a tuple of the generic arguments of the method. The arguments are passed like in
+/// [`rustc_clr_interop_managed_call_tuple_`].
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_managed_call_generic_<
+    Class,
+    const METHOD: &'static str,
+    const IS_STATIC: bool,
+    const IS_VIRTUAL: bool,
+    Sig,
+    MethodGenerics,
+    Ret,
+    Args,
+>(
+    args: Args,
+) -> Ret {
+    core::intrinsics::abort();
+}
+/// Creates a new object of `Class`, which may be a generic class. `Sig` is the declared signature of the constructor,
+/// as described in [`rustc_clr_interop_managed_call_generic_`].
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_managed_ctor_generic_<Class, Sig, Args>(args: Args) -> Class {
+    core::intrinsics::abort();
+}
+/// Creates a delegate of type `Delegate`, whose `Invoke` method has the signature `Sig`. Invoking the delegate calls
+/// `invoke(env, args...)`. Once the delegate gets collected, `drop(env)` is called. `invoke` and `drop` must be Rust
+/// functions, cast to pointers.
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_managed_delegate_<Delegate, Sig>(
+    env: *mut (),
+    invoke: *const (),
+    drop: *const (),
+) -> Delegate {
+    core::intrinsics::abort();
+}
 impl From<u16> for RustcCLRInteropManagedChar {
     fn from(utf16_char: u16) -> RustcCLRInteropManagedChar {
         unsafe {
@@ -373,6 +635,75 @@ unsafe impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str> Manage
     for RustcCLRInteropManagedClass<ASSEMBLY, CLASS_PATH>
 {
 }
+unsafe impl<
+        const ASSEMBLY: &'static str,
+        const CLASS_PATH: &'static str,
+        const IS_VALUETYPE: bool,
+        Generics,
+    > ManagedSafe
+    for RustcCLRInteropManagedGenericClass<ASSEMBLY, CLASS_PATH, IS_VALUETYPE, Generics>
+{
+}
+impl<
+        const ASSEMBLY: &'static str,
+        const CLASS_PATH: &'static str,
+        const IS_VALUETYPE: bool,
+        Generics,
+    > RustcCLRInteropManagedGenericClass<ASSEMBLY, CLASS_PATH, IS_VALUETYPE, Generics>
+{
+    /// Creates a new object, calling the constructor with the declared signature `Sig`, and the arguments in the tuple
+    /// `args`.
+    #[inline(always)]
+    pub fn ctor<Sig, Args>(args: Args) -> Self {
+        rustc_clr_interop_managed_ctor_generic_::<Self, Sig, Args>(args)
+    }
+    /// Calls the static method `METHOD`, with the declared signature `Sig`, and the arguments in the tuple `args`.
+    #[inline(always)]
+    pub fn static_call<const METHOD: &'static str, Sig, Args, Ret>(args: Args) -> Ret {
+        rustc_clr_interop_managed_call_generic_::<Self, METHOD, true, false, Sig, (), Ret, Args>(
+            args,
+        )
+    }
+    /// Calls the instance method `METHOD`, with the declared signature `Sig`, and the arguments in the tuple `args`.
+    /// The first element of `args` is `this`.
+    #[inline(always)]
+    pub fn instance_call<const METHOD: &'static str, Sig, Args, Ret>(args: Args) -> Ret {
+        rustc_clr_interop_managed_call_generic_::<Self, METHOD, false, false, Sig, (), Ret, Args>(
+            args,
+        )
+    }
+    /// Calls the virtual method `METHOD`, with the declared signature `Sig`, and the arguments in the tuple `args`.
+    /// The first element of `args` is `this`.
+    #[inline(always)]
+    pub fn virt_call<const METHOD: &'static str, Sig, Args, Ret>(args: Args) -> Ret {
+        rustc_clr_interop_managed_call_generic_::<Self, METHOD, false, true, Sig, (), Ret, Args>(
+            args,
+        )
+    }
+    /// Calls the generic method `METHOD`, with the generic arguments `MethodGenerics`.
+    #[inline(always)]
+    pub fn generic_call<
+        const METHOD: &'static str,
+        const IS_STATIC: bool,
+        Sig,
+        MethodGenerics,
+        Args,
+        Ret,
+    >(
+        args: Args,
+    ) -> Ret {
+        rustc_clr_interop_managed_call_generic_::<
+            Self,
+            METHOD,
+            IS_STATIC,
+            false,
+            Sig,
+            MethodGenerics,
+            Ret,
+            Args,
+        >(args)
+    }
+}
 unsafe impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str, const SIZE: usize>
     ManagedSafe for RustcCLRInteropManagedStruct<ASSEMBLY, CLASS_PATH, SIZE>
 {
@@ -392,4 +723,29 @@ impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str, const SIZE: u
             arg1,
         )
     }
+    /// Calls the instance method `METHOD`, with the arguments in the tuple `args`. The first element of `args` should be
+    /// `&self`, which is passed as a managed reference, like `this` of valuetypes.
+    #[inline(always)]
+    pub fn instance_call<const METHOD: &'static str, Args, Ret>(args: Args) -> Ret {
+        rustc_clr_interop_managed_call_tuple_::<ASSEMBLY, CLASS_PATH, true, METHOD, false, Ret, Args>(
+            args,
+        )
+    }
+    /// Loads the instance field `FIELD`.
+    #[inline(always)]
+    pub fn field<const FIELD: &'static str, T>(&self) -> T {
+        rustc_clr_interop_managed_ld_fld::<ASSEMBLY, CLASS_PATH, true, FIELD, T, &Self>(self)
+    }
+    /// Sets the instance field `FIELD` to `value`.
+    #[inline(always)]
+    pub fn set_field<const FIELD: &'static str, T>(&mut self, value: T) {
+        rustc_clr_interop_managed_st_fld::<ASSEMBLY, CLASS_PATH, true, FIELD, T, &mut Self>(
+            self, value,
+        )
+    }
+    /// Loads the static field `FIELD`.
+    #[inline(always)]
+    pub fn static_field<const FIELD: &'static str, T>() -> T {
+        rustc_clr_interop_managed_ld_sfld::<ASSEMBLY, CLASS_PATH, true, FIELD, T>()
+    }
 }
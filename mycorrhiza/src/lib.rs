@@ -9,11 +9,17 @@
 pub mod bindings;
 pub use bindings::*;
 pub mod class;
+/// Managed delegates calling Rust closures.
+pub mod delegate;
 /// Very low-level interop stuff. Don't use unless you need to.
 pub mod intrinsics;
+/// Definitions of .NET types from Rust, evaluated at compile time.
+pub mod typedef;
 use class::*;
 /// Wrappers around types from the `System` namespace
 pub mod system;
+/// Running Rust futures as .NET tasks.
+pub mod task;
 /// C# `char` type
 pub type DotNetChar = crate::intrinsics::RustcCLRInteropManagedChar;
 
@@ -43,6 +49,25 @@ macro_rules! start {
         }
     };
 }
+/// Exports a Rust function as a public, static method of a .NET class, callable from other .NET code.
+/// Strings and arrays of primitives are converted to and from `&str` and slices.
+/// ```ignore
+/// dotnet_export! {
+///     [MyLib::Api]
+///     pub fn add(a: i32, b: i32) -> i32 {
+///         a + b
+///     }
+/// }
+/// ```
+/// This is equivalent to marking the function with `#[export_name = "rustc_codegen_clr_export::MyLib::Api::add"]`.
+#[macro_export]
+macro_rules! dotnet_export {
+    ([$($class:ident)::+] $(#[$attr:meta])* pub fn $name:ident $($rest:tt)*) => {
+        #[export_name = concat!("rustc_codegen_clr_export::", stringify!($($class)::+), "::", stringify!($name))]
+        $(#[$attr])*
+        pub fn $name $($rest)*
+    };
+}
 /// Marker trait, which signals that a type can be safely passed to and from managed code.
 /// # Safety
 /// Passing this type to .NET code can't cause any UB.
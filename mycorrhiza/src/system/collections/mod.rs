@@ -0,0 +1,67 @@
+use crate::intrinsics::{RustcCLRInteropManagedGenericClass, RustcCLRInteropManagedGenericParam};
+type T0 = RustcCLRInteropManagedGenericParam<0>;
+type T1 = RustcCLRInteropManagedGenericParam<1>;
+pub type List<T> = RustcCLRInteropManagedGenericClass<
+    "System.Collections",
+    "System.Collections.Generic.List",
+    false,
+    (T,),
+>;
+impl<T> List<T> {
+    #[inline(always)]
+    pub fn empty() -> Self {
+        Self::ctor::<fn(), ()>(())
+    }
+    #[inline(always)]
+    pub fn add(self, item: T) {
+        Self::instance_call::<"Add", fn(T0), (Self, T), ()>((self, item))
+    }
+    #[inline(always)]
+    pub fn count(self) -> i32 {
+        Self::instance_call::<"get_Count", fn() -> i32, (Self,), i32>((self,))
+    }
+    #[inline(always)]
+    pub fn get(self, index: i32) -> T {
+        Self::instance_call::<"get_Item", fn(i32) -> T0, (Self, i32), T>((self, index))
+    }
+    #[inline(always)]
+    pub fn set(self, index: i32, item: T) {
+        Self::instance_call::<"set_Item", fn(i32, T0), (Self, i32, T), ()>((self, index, item))
+    }
+    #[inline(always)]
+    pub fn clear(self) {
+        Self::instance_call::<"Clear", fn(), (Self,), ()>((self,))
+    }
+}
+pub type Dictionary<K, V> = RustcCLRInteropManagedGenericClass<
+    "System.Collections",
+    "System.Collections.Generic.Dictionary",
+    false,
+    (K, V),
+>;
+impl<K, V> Dictionary<K, V> {
+    #[inline(always)]
+    pub fn empty() -> Self {
+        Self::ctor::<fn(), ()>(())
+    }
+    #[inline(always)]
+    pub fn insert(self, key: K, value: V) {
+        Self::instance_call::<"set_Item", fn(T0, T1), (Self, K, V), ()>((self, key, value))
+    }
+    #[inline(always)]
+    pub fn get(self, key: K) -> V {
+        Self::instance_call::<"get_Item", fn(T0) -> T1, (Self, K), V>((self, key))
+    }
+    #[inline(always)]
+    pub fn contains_key(self, key: K) -> bool {
+        Self::instance_call::<"ContainsKey", fn(T0) -> bool, (Self, K), bool>((self, key))
+    }
+    #[inline(always)]
+    pub fn remove(self, key: K) -> bool {
+        Self::instance_call::<"Remove", fn(T0) -> bool, (Self, K), bool>((self, key))
+    }
+    #[inline(always)]
+    pub fn count(self) -> i32 {
+        Self::instance_call::<"get_Count", fn() -> i32, (Self,), i32>((self,))
+    }
+}
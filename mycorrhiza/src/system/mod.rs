@@ -1,5 +1,6 @@
 use runtime::interop_services::Marshal;
 
+pub mod collections;
 pub mod console;
 pub mod diagnostics;
 pub mod runtime;
@@ -0,0 +1,159 @@
+use crate::class::GCHandle;
+use crate::delegate::{Action, IntoDelegate};
+use crate::intrinsics::{
+    rustc_clr_interop_managed_checked_cast, RustcCLRInteropManagedClass,
+    RustcCLRInteropManagedGenericClass, RustcCLRInteropManagedGenericParam,
+};
+use crate::System;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+type T0 = RustcCLRInteropManagedGenericParam<0>;
+/// `System.Threading.Tasks.Task`
+pub type Task = RustcCLRInteropManagedClass<"System.Runtime", "System.Threading.Tasks.Task">;
+/// `System.Threading.Tasks.Task<R>`
+pub type Task1<R> = RustcCLRInteropManagedGenericClass<
+    "System.Runtime",
+    "System.Threading.Tasks.Task",
+    false,
+    (R,),
+>;
+impl<R> Task1<R> {
+    /// Waits for the task to complete, and returns its result.
+    #[inline(always)]
+    pub fn result(self) -> R {
+        Self::instance_call::<"get_Result", fn() -> T0, (Self,), R>((self,))
+    }
+}
+/// `System.Threading.Tasks.ValueTask<R>`
+pub type ValueTask1<R> = RustcCLRInteropManagedGenericClass<
+    "System.Runtime",
+    "System.Threading.Tasks.ValueTask",
+    true,
+    (R,),
+>;
+impl<R> ValueTask1<R> {
+    /// Wraps `task` in a `ValueTask<R>`.
+    #[inline(always)]
+    pub fn from_task(task: Task1<R>) -> Self {
+        Self::ctor::<fn(Task1<T0>), (Task1<R>,)>((task,))
+    }
+    /// Waits for the task to complete, and returns its result.
+    #[inline(always)]
+    pub fn result(&self) -> R {
+        Self::instance_call::<"get_Result", fn() -> T0, (&Self,), R>((self,))
+    }
+}
+/// Wakes up the thread polling a future.
+struct ThreadWaker(std::thread::Thread);
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+/// Runs `future` to completion on the current thread, parking it while the future is pending.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(res) => return res,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+/// `System.Threading.Tasks.TaskCompletionSource<R>`
+pub type TaskCompletionSource1<R> = RustcCLRInteropManagedGenericClass<
+    "System.Runtime",
+    "System.Threading.Tasks.TaskCompletionSource",
+    false,
+    (R,),
+>;
+impl<R> TaskCompletionSource1<R> {
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::ctor::<fn(), ()>(())
+    }
+    /// The task completed by this source.
+    #[inline(always)]
+    pub fn task(self) -> Task1<R> {
+        Self::instance_call::<"get_Task", fn() -> Task1<T0>, (Self,), Task1<R>>((self,))
+    }
+    /// Completes the task with `result`.
+    #[inline(always)]
+    pub fn set_result(self, result: R) {
+        Self::instance_call::<"SetResult", fn(T0), (Self, R), ()>((self, result))
+    }
+}
+/// A future exposed as a .NET task. It is polled on the thread pool each time it is woken up, and completes its
+/// `TaskCompletionSource` once it is ready.
+struct FutureTask<F: Future> {
+    /// The future, or `None` once it completed.
+    future: Mutex<Option<Pin<Box<F>>>>,
+    /// A `GCHandle` to the `TaskCompletionSource<F::Output>`, converted to an `isize`, since managed references can't
+    /// be stored in Rust types.
+    source: isize,
+}
+impl<F: Future> FutureTask<F> {
+    fn source(&self) -> TaskCompletionSource1<F::Output> {
+        let handle = GCHandle::static1::<"FromIntPtr", isize, GCHandle>(self.source);
+        rustc_clr_interop_managed_checked_cast(handle.instance0::<"get_Target", System::Object>())
+    }
+}
+impl<F: Future + Send + 'static> FutureTask<F> {
+    /// Polls the future once, on the current thread.
+    fn poll(self: &Arc<Self>) {
+        let waker = Waker::from(self.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut future = self.future.lock().unwrap();
+        // Wake ups after the future completed are ignored.
+        let Some(pending) = future.as_mut() else {
+            return;
+        };
+        if let Poll::Ready(res) = pending.as_mut().poll(&mut cx) {
+            *future = None;
+            drop(future);
+            self.source().set_result(res);
+        }
+    }
+    /// Polls the future on the thread pool, so that the thread waking it up does not run it.
+    fn schedule(self: Arc<Self>) {
+        let poll = IntoDelegate::<fn()>::into_delegate::<Action>(move || self.poll());
+        Task::static1::<"Run", Action, Task>(poll);
+    }
+}
+impl<F: Future + Send + 'static> Wake for FutureTask<F> {
+    fn wake(self: Arc<Self>) {
+        self.schedule();
+    }
+}
+impl<F: Future> Drop for FutureTask<F> {
+    fn drop(&mut self) {
+        GCHandle::static1::<"FromIntPtr", isize, GCHandle>(self.source).instance0::<"Free", ()>();
+    }
+}
+/// Exposes a Rust future as a .NET `Task<R>`. The future is first polled on the thread pool, and then each time it is
+/// woken up, so no thread is blocked while it is pending. .NET code can then `await` the returned task like any other.
+pub fn into_task<F, R>(future: F) -> Task1<R>
+where
+    F: Future<Output = R> + Send + 'static,
+{
+    let source = TaskCompletionSource1::<R>::new();
+    let object: System::Object = rustc_clr_interop_managed_checked_cast(source);
+    let handle = GCHandle::static1::<"Alloc", System::Object, GCHandle>(object);
+    let task = Arc::new(FutureTask {
+        future: Mutex::new(Some(Box::pin(future))),
+        source: GCHandle::static1::<"ToIntPtr", GCHandle, isize>(handle),
+    });
+    task.schedule();
+    source.task()
+}
+/// Exposes a Rust future as a .NET `ValueTask<R>`, for APIs which expect one. Works like [`into_task`].
+pub fn into_value_task<F, R>(future: F) -> ValueTask1<R>
+where
+    F: Future<Output = R> + Send + 'static,
+{
+    ValueTask1::from_task(into_task(future))
+}
@@ -0,0 +1,189 @@
+//! Definitions of .NET types from Rust. The functions in this module are never called: instead, `rustc_codegen_clr`
+//! interprets the functions named `rustc_codegen_clr_comptime_entrypoint` which use them at compile time, and adds the
+//! types they describe to the final assembly. Use the [`crate::dotnet_typedef`] macro instead of calling them directly.
+#![allow(unused_variables)]
+/// A .NET type definition under construction.
+pub struct ClassDef {
+    prevent_construction: usize,
+}
+/// Creates a new type named `NAME`, inheriting from `[INHERITS_ASM]INHERITS`.
+#[inline(never)]
+pub fn rustc_codegen_clr_new_typedef<
+    const NAME: &'static str,
+    const IS_VALUETYPE: bool,
+    const INHERITS_ASM: &'static str,
+    const INHERITS: &'static str,
+>() -> ClassDef {
+    core::intrinsics::abort();
+}
+/// Adds an instance field of type `T`, named `FNAME`.
+#[inline(never)]
+pub fn rustc_codegen_clr_add_field_def<T, const FNAME: &'static str>(class: ClassDef) -> ClassDef {
+    core::intrinsics::abort();
+}
+/// Adds a method named `FNAME`, implemented by `fn_type`. `VIS` is either `"pub"` or `"priv"`, and `MODIFIERS` is one of
+/// `"virtual"`, `"static"` or `"instance"`. For non-static methods, the first argument of `fn_type` is `this`.
+#[inline(never)]
+pub fn rustc_codegen_clr_add_method_def<
+    const VIS: &'static str,
+    const MODIFIERS: &'static str,
+    const FNAME: &'static str,
+    FnType,
+>(
+    class: ClassDef,
+    fn_type: FnType,
+) -> ClassDef {
+    core::intrinsics::abort();
+}
+/// Makes the type implement the interface `[ASM]NAME`, with the generic arguments `Generics`, which must be a tuple of
+/// types (`()` for non-generic interfaces).
+#[inline(never)]
+pub fn rustc_codegen_clr_add_interface<
+    const ASM: &'static str,
+    const NAME: &'static str,
+    Generics,
+>(
+    class: ClassDef,
+) -> ClassDef {
+    core::intrinsics::abort();
+}
+/// Adds a public, virtual method named `FNAME`, implemented by `fn_type`, which implements the method of the same name
+/// of the last added interface. The first argument of `fn_type` is `this`.
+#[inline(never)]
+pub fn rustc_codegen_clr_add_interface_method_def<const FNAME: &'static str, FnType>(
+    class: ClassDef,
+    fn_type: FnType,
+) -> ClassDef {
+    core::intrinsics::abort();
+}
+/// Adds the finished type to the assembly.
+#[inline(never)]
+pub fn rustc_codegen_clr_finish_type(class: ClassDef) {
+    core::intrinsics::abort();
+}
+/// Adds fields and methods to a type definition. Used by [`crate::dotnet_typedef`].
+#[doc(hidden)]
+#[macro_export]
+macro_rules! dotnet_typedef_members {
+    ($typedef:ident $(,)?) => {};
+    ($typedef:ident, $field_name:ident : $field_type:ty, $($tail:tt)*) => {
+        {
+            const FIELD_NAME: &str = stringify!($field_name);
+            $typedef = $crate::typedef::rustc_codegen_clr_add_field_def::<$field_type, FIELD_NAME>($typedef);
+        }
+        $crate::dotnet_typedef_members!($typedef, $($tail)*);
+    };
+    ($typedef:ident, implements [$iasm:path] $($iface:ident)::+ $(<$($generic:ty),*>)? { $($methods:tt)* }, $($tail:tt)*) => {
+        {
+            const IFACE_ASM: &str = stringify!($iasm);
+            const IFACE_NAME: &str = stringify!($($iface)::+);
+            $typedef = $crate::typedef::rustc_codegen_clr_add_interface::<IFACE_ASM, IFACE_NAME, ($($($generic,)*)?)>($typedef);
+        }
+        $crate::dotnet_typedef_members!(@interface $typedef, $($methods)*);
+        $crate::dotnet_typedef_members!($typedef, $($tail)*);
+    };
+    ($typedef:ident, virtual fn $fname:ident($($args:tt)*) -> $ret:ty { $($inner:tt)* }, $($tail:tt)*) => {
+        $crate::dotnet_typedef_members!(@method $typedef, "virtual", $fname($($args)*) -> $ret { $($inner)* });
+        $crate::dotnet_typedef_members!($typedef, $($tail)*);
+    };
+    ($typedef:ident, static fn $fname:ident($($args:tt)*) -> $ret:ty { $($inner:tt)* }, $($tail:tt)*) => {
+        $crate::dotnet_typedef_members!(@method $typedef, "static", $fname($($args)*) -> $ret { $($inner)* });
+        $crate::dotnet_typedef_members!($typedef, $($tail)*);
+    };
+    ($typedef:ident, fn $fname:ident($($args:tt)*) -> $ret:ty { $($inner:tt)* }, $($tail:tt)*) => {
+        $crate::dotnet_typedef_members!(@method $typedef, "instance", $fname($($args)*) -> $ret { $($inner)* });
+        $crate::dotnet_typedef_members!($typedef, $($tail)*);
+    };
+    (@interface $typedef:ident $(,)?) => {};
+    (@interface $typedef:ident, fn $fname:ident($($args:tt)*) -> $ret:ty { $($inner:tt)* }, $($tail:tt)*) => {
+        {
+            $crate::dotnet_typedef_members!(@impl_fn $fname($($args)*) -> $ret { $($inner)* });
+            const FNAME: &str = stringify!($fname);
+            $typedef = $crate::typedef::rustc_codegen_clr_add_interface_method_def::<FNAME, _>(
+                $typedef,
+                $fname::rustc_codegen_clr_not_magic,
+            );
+        }
+        $crate::dotnet_typedef_members!(@interface $typedef, $($tail)*);
+    };
+    (@impl_fn $fname:ident($($args:tt)*) -> $ret:ty { $($inner:tt)* }) => {
+        mod $fname {
+            #[allow(unused_imports)]
+            use super::super::*;
+            #[inline(never)]
+            pub extern "C" fn rustc_codegen_clr_not_magic($($args)*) -> $ret {
+                $($inner)*
+            }
+        }
+        #[used]
+        static KEEP_FN: extern "C" fn($($args)*) -> $ret = $fname::rustc_codegen_clr_not_magic;
+    };
+    (@method $typedef:ident, $modifiers:literal, $fname:ident($($args:tt)*) -> $ret:ty { $($inner:tt)* }) => {
+        {
+            $crate::dotnet_typedef_members!(@impl_fn $fname($($args)*) -> $ret { $($inner)* });
+            const FNAME: &str = stringify!($fname);
+            $typedef = $crate::typedef::rustc_codegen_clr_add_method_def::<"pub", $modifiers, FNAME, _>(
+                $typedef,
+                $fname::rustc_codegen_clr_not_magic,
+            );
+        }
+    };
+}
+/// Defines a .NET class or valuetype, which can be used by other .NET code.
+/// ```ignore
+/// dotnet_typedef! {
+///   class MyClass inherits [System::Runtime]System::Object {
+///     counter: i32,
+///     virtual fn ToString(_this: MyClass::Ref) -> MString {
+///       "I am a class defined in Rust!".into_managed()
+///     },
+///     implements [System::Runtime]System::IComparable<MyClass::Ref> {
+///       fn CompareTo(this: MyClass::Ref, other: MyClass::Ref) -> i32 {
+///         0
+///       },
+///     },
+///   }
+/// }
+/// let obj = MyClass::Ref::ctor0();
+/// ```
+/// The methods are implemented by Rust functions, so their first argument is `this` (for non-static methods).
+/// Methods inside of an `implements` block are public and virtual, and implement the interface method with the same name.
+/// Classes get a public, parameterless constructor calling the one of their base class, and `MyClass::Ref` names a
+/// reference to an instance of `MyClass`.
+#[macro_export]
+macro_rules! dotnet_typedef {
+    (class $name:ident inherits [$superasm:path] $superclass:path { $($inner:tt)* }) => {
+        $crate::dotnet_typedef!(@def $name, false, [$superasm] $superclass { $($inner)* } {
+            /// A reference to an instance of this class.
+            pub type Ref = $crate::intrinsics::RustcCLRInteropManagedClass<"", { stringify!($name) }>;
+        });
+    };
+    (struct $name:ident inherits [$superasm:path] $superclass:path { $($inner:tt)* }) => {
+        $crate::dotnet_typedef!(@def $name, true, [$superasm] $superclass { $($inner)* } {});
+    };
+    (@def $name:ident, $is_valuetype:literal, [$superasm:path] $superclass:path { $($inner:tt)* } { $($items:item)* }) => {
+        #[allow(non_snake_case)]
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+            $($items)*
+            #[used]
+            static PREVENT_DEAD_CODE_REMOVAL: fn() = rustc_codegen_clr_comptime_entrypoint;
+            #[inline(never)]
+            pub fn rustc_codegen_clr_comptime_entrypoint() {
+                const NAME: &str = stringify!($name);
+                const SUPER_ASM: &str = stringify!($superasm);
+                const SUPER_CLASS: &str = stringify!($superclass);
+                #[allow(unused_mut)]
+                let mut class = $crate::typedef::rustc_codegen_clr_new_typedef::<
+                    NAME,
+                    $is_valuetype,
+                    SUPER_ASM,
+                    SUPER_CLASS,
+                >();
+                $crate::dotnet_typedef_members!(class, $($inner)*);
+                $crate::typedef::rustc_codegen_clr_finish_type(class);
+            }
+        }
+    };
+}
@@ -31,7 +31,7 @@ use rustc_middle::{
         mono::MonoItem,
         Local, LocalDecl, Statement, Terminator,
     },
-    ty::{Instance, ParamEnv, TyCtxt, TyKind},
+    ty::{Instance, InstanceKind, ParamEnv, TyCtxt, TyKind},
 };
 type LocalDefList = Vec<LocalDef>;
 type ArgsDebugInfo = Vec<Option<StringIdx>>;
@@ -286,29 +286,13 @@ pub fn terminator_to_ops<'tcx>(
     term: &Terminator<'tcx>,
     ctx: &mut MethodCompileCtx<'tcx, '_>,
 ) -> Result<Vec<CILTree>, CodegenError> {
-    let terminator = if *crate::config::ABORT_ON_ERROR {
-        crate::terminator::handle_terminator(term, ctx)
+    if *crate::config::ABORT_ON_ERROR {
+        Ok(crate::terminator::handle_terminator(term, ctx))
     } else {
-        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        crate::codegen_error::catch_codegen_error(|| {
             crate::terminator::handle_terminator(term, ctx)
-        })) {
-            Ok(ok) => ok,
-            Err(payload) => {
-                let msg = if let Some(msg) = payload.downcast_ref::<&str>() {
-                    rustc_middle::ty::print::with_no_trimmed_paths! {
-                    format!("Tried to execute terminator {term:?} whose compialtion message {msg:?}!")}
-                } else {
-                    eprintln!("handle_terminator panicked with a non-string message when trying to compile {term:?} !");
-                    rustc_middle::ty::print::with_no_trimmed_paths! {
-                    format!("Tried to execute terminator {term:?} whose compialtion failed with a no-string message!")
-                    }
-                };
-                vec![CILRoot::throw(&msg, ctx).into()]
-            }
-        }
-    };
-
-    Ok(terminator)
+        })
+    }
 }
 /// Turns a statement into ops, if `ABORT_ON_ERROR` set to false, will handle and recover from errors.
 pub fn statement_to_ops<'tcx>(
@@ -318,20 +302,9 @@ pub fn statement_to_ops<'tcx>(
     if *crate::config::ABORT_ON_ERROR {
         Ok(crate::statement::handle_statement(statement, ctx))
     } else {
-        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        crate::codegen_error::catch_codegen_error(|| {
             crate::statement::handle_statement(statement, ctx)
-        })) {
-            Ok(success) => Ok(success),
-            Err(payload) => {
-                if let Some(msg) = payload.downcast_ref::<&str>() {
-                    Err(crate::codegen_error::CodegenError::from_panic_message(msg))
-                } else {
-                    Err(crate::codegen_error::CodegenError::from_panic_message(
-                        "statement_to_ops panicked with a non-string message!",
-                    ))
-                }
-            }
-        }
+        })
     }
 }
 /// Adds a rust MIR function to the assembly.
@@ -340,7 +313,9 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
     ctx: &'a mut MethodCompileCtx<'tcx, 'asm>,
 ) -> Result<(), MethodCodegenError> {
     let kind = ctx.instance().ty(ctx.tcx(), ParamEnv::reveal_all()).kind();
-    if let TyKind::FnDef(_, _) = kind {
+    if let InstanceKind::ThreadLocalShim(_) = ctx.instance().def {
+        // Thread local shims have the type of their static, but are still functions.
+    } else if let TyKind::FnDef(_, _) = kind {
         //ALL OK.
     } else if let TyKind::Closure(_, _) = kind {
     } else if let TyKind::Coroutine(_, _) = kind {
@@ -424,6 +399,8 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
     } else {
         vec![]
     };
+    // The first unsupported construct in this function. If there is one, the whole function gets replaced with a throwing stub.
+    let mut codegen_error = None;
     // Used for type-checking the CIL to ensure its validity.
     for (last_bb_id, block_data) in blocks.into_iter().enumerate() {
         let mut trees = Vec::new();
@@ -436,10 +413,13 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
             let statement_tree = match statement_to_ops(statement, ctx) {
                 Ok(ops) => ops,
                 Err(err) => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! {eprintln!(
-                        "Method \"{name}\" failed to compile statement {statement:?} with message {err:?}\n"
-                    )};
-                    rustc_middle::ty::print::with_no_trimmed_paths! {vec![(CILRoot::throw(&format!("Tired to run a statement {statement:?} which failed to compile with error message {err:?}."),ctx).into())]}
+                    crate::codegen_error::record_unsupported(
+                        name,
+                        statement.source_info.span,
+                        &err,
+                    );
+                    codegen_error.get_or_insert(err);
+                    vec![]
                 }
             };
             // Only save debuginfo for statements which result in ops.
@@ -457,7 +437,9 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
                 rustc_middle::ty::print::with_no_trimmed_paths! {trees.push(CILRoot::debug(&format!("{term:?}"),ctx).into())};
             }
             let term_trees = terminator_to_ops(term, ctx).unwrap_or_else(|err| {
-                panic!("Could not compile terminator {term:?} because {err:?}")
+                crate::codegen_error::record_unsupported(name, term.source_info.span, &err);
+                codegen_error.get_or_insert(err);
+                vec![]
             });
             if !term_trees.is_empty() {
                 trees.push(span_source_info(ctx.tcx(), term.source_info.span).into());
@@ -491,6 +473,12 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
         }
         //ops.extend(trees.iter().flat_map(|tree| tree.flatten()))
     }
+    if let Some(err) = codegen_error {
+        // Replace the whole function with a stub, so that only calls to it fail at runtime.
+        normal_bbs = vec![crate::codegen_error::throwing_stub(name, &err, ctx)];
+        cleanup_bbs.clear();
+        repack_cil.clear();
+    }
 
     normal_bbs
         .iter_mut()
@@ -503,6 +491,7 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
     repack_cil.append(first_bb.trees_mut());
     *first_bb.trees_mut() = repack_cil;
 
+    let export = crate::export::export_target(name).map(|target| (target, arg_names.clone()));
     let mut method = Method::new(
         access_modifier,
         MethodType::Static,
@@ -523,6 +512,12 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
     let main_module = ctx.main_module();
     let method = MethodDef::from_v1(&method, ctx, main_module);
     ctx.new_method(method);
+    // Functions marked for export also get a wrapper with a managed signature. C and JS have no managed types, so
+    // exported functions are just regular functions there.
+    let export = export.filter(|_| !*crate::config::C_MODE && !*crate::config::JS_MODE);
+    if let Some(((class, method_name), arg_names)) = export {
+        crate::export::export_fn(&class, &method_name, name, &sig, &arg_names, ctx);
+    }
     drop(timer);
     Ok(())
     //todo!("Can't add function")
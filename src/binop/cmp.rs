@@ -64,7 +64,7 @@ pub fn eq_unchecked(
         TyKind::Float(FloatTy::F128) => {
             let mref = MethodRef::new(
                 *asm.main_module(),
-                asm.alloc_string("__eqtf2"),
+                asm.alloc_string("eq_f128"),
                 asm.sig(
                     [Type::Float(Float::F128), Type::Float(Float::F128)],
                     Type::Bool,
@@ -132,7 +132,7 @@ pub fn lt_unchecked(
         TyKind::Float(FloatTy::F128) => {
             let mref = MethodRef::new(
                 *asm.main_module(),
-                asm.alloc_string("__lttf2"),
+                asm.alloc_string("lt_f128"),
                 asm.sig(
                     [Type::Float(Float::F128), Type::Float(Float::F128)],
                     Type::Bool,
@@ -198,7 +198,7 @@ pub fn gt_unchecked(
         TyKind::Float(FloatTy::F128) => {
             let mref = MethodRef::new(
                 *asm.main_module(),
-                asm.alloc_string("__gttf2"),
+                asm.alloc_string("gt_f128"),
                 asm.sig(
                     [Type::Float(Float::F128), Type::Float(Float::F128)],
                     Type::Bool,
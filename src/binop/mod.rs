@@ -74,7 +74,7 @@ pub(crate) fn binop<'tcx>(
             TyKind::Float(FloatTy::F128) => {
                 let mref = MethodRef::new(
                     *ctx.main_module(),
-                    ctx.alloc_string("__getf2"),
+                    ctx.alloc_string("ge_f128"),
                     ctx.sig(
                         [Type::Float(Float::F128), Type::Float(Float::F128)],
                         Type::Bool,
@@ -97,7 +97,7 @@ pub(crate) fn binop<'tcx>(
             TyKind::Float(FloatTy::F128) => {
                 let mref = MethodRef::new(
                     *ctx.main_module(),
-                    ctx.alloc_string("__letf2"),
+                    ctx.alloc_string("le_f128"),
                     ctx.sig(
                         [Type::Float(Float::F128), Type::Float(Float::F128)],
                         Type::Bool,
@@ -208,7 +208,7 @@ pub fn add_unchecked<'tcx>(
         TyKind::Float(FloatTy::F128) => {
             let mref = MethodRef::new(
                 *ctx.main_module(),
-                ctx.alloc_string("__addtf3"),
+                ctx.alloc_string("add_f128"),
                 ctx.sig(
                     [Type::Float(Float::F128), Type::Float(Float::F128)],
                     Type::Float(Float::F128),
@@ -283,7 +283,7 @@ pub fn sub_unchecked<'tcx>(
         TyKind::Float(FloatTy::F128) => {
             let mref = MethodRef::new(
                 *ctx.main_module(),
-                ctx.alloc_string("__subtf3"),
+                ctx.alloc_string("sub_f128"),
                 ctx.sig(
                     [Type::Float(Float::F128), Type::Float(Float::F128)],
                     Type::Float(Float::F128),
@@ -350,7 +350,7 @@ fn rem_unchecked<'tcx>(
         TyKind::Float(FloatTy::F128) => {
             let mref = MethodRef::new(
                 *ctx.main_module(),
-                ctx.alloc_string("fmodl"),
+                ctx.alloc_string("mod_f128"),
                 ctx.sig(
                     [Type::Float(Float::F128), Type::Float(Float::F128)],
                     Type::Float(Float::F128),
@@ -416,7 +416,7 @@ fn mul_unchecked<'tcx>(
         TyKind::Float(FloatTy::F128) => {
             let mref = MethodRef::new(
                 *ctx.main_module(),
-                ctx.alloc_string("__multf3"),
+                ctx.alloc_string("mul_f128"),
                 ctx.sig(
                     [Type::Float(Float::F128), Type::Float(Float::F128)],
                     Type::Float(Float::F128),
@@ -483,7 +483,7 @@ fn div_unchecked<'tcx>(
         TyKind::Float(FloatTy::F128) => {
             let mref = MethodRef::new(
                 *ctx.main_module(),
-                ctx.alloc_string("__divtf3"),
+                ctx.alloc_string("div_f128"),
                 ctx.sig(
                     [Type::Float(Float::F128), Type::Float(Float::F128)],
                     Type::Float(Float::F128),
@@ -158,6 +158,16 @@ pub fn int_to_int(src: Type, target: Type, operand: CILNode, asm: &mut Assembly)
 }
 /// Returns CIL ops required to convert type src to target
 pub fn float_to_int(src: Type, target: Type, operand: CILNode, asm: &mut Assembly) -> CILNode {
+    if let (Type::Float(Float::F128), Type::Int(int)) = (src, target) {
+        let mref = MethodRef::new(
+            *asm.main_module(),
+            asm.alloc_string(format!("cast_f128_{}", int.name())),
+            asm.sig([src], target),
+            MethodKind::Static,
+            vec![].into(),
+        );
+        return call!(asm.alloc_methodref(mref), [operand]);
+    }
     match target {
         Type::Int(Int::I128) => {
             let mref = MethodRef::new(
@@ -437,7 +447,16 @@ fn to_int(target: Type, operand: CILNode) -> CILNode {
 }
 /// Returns CIL ops required to casts from intiger type `src` to `target` MOVE TO CILLY
 pub fn int_to_float(src: Type, target: Type, parrent: CILNode, asm: &mut Assembly) -> CILNode {
-    if matches!(src, Type::Int(Int::I128)) {
+    if let (Type::Int(int), Type::Float(Float::F128)) = (src, target) {
+        let mref = MethodRef::new(
+            *asm.main_module(),
+            asm.alloc_string(format!("cast_{}_f128", int.name())),
+            asm.sig([src], target),
+            MethodKind::Static,
+            vec![].into(),
+        );
+        call!(asm.alloc_methodref(mref), [parrent])
+    } else if matches!(src, Type::Int(Int::I128)) {
         let mref = MethodRef::new(
             ClassRef::int_128(asm),
             asm.alloc_string("op_Explicit"),
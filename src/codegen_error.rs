@@ -1,4 +1,12 @@
-use std::fmt::Debug;
+use std::{
+    any::Any,
+    fmt::{Debug, Display},
+    sync::Mutex,
+};
+
+use cilly::{basic_block::BasicBlock, cil_root::CILRoot, v2::Assembly};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
 
 #[derive(Debug)]
 /// Repersentation of an error which occured while converting MIR to CIL assembly.
@@ -7,6 +15,40 @@ pub enum CodegenError {
     Error(crate::IString),
     Method(MethodCodegenError),
     FunctionABIUnsuported(&'static str),
+    /// A place of this type was indexed into, but the type does not support indexing.
+    CantIndexInto(crate::IString),
+    /// Constant indices counting from the end of an array are not supported yet.
+    IndexFromEnd,
+    /// A place projection which is not supported when computing the place `context`(get, set, adress or body).
+    UnsupportedProjection {
+        projection: crate::IString,
+        context: &'static str,
+    },
+    /// A value of this type was dereferenced, but dereferencing it is not supported yet.
+    CantDeref(crate::IString),
+    /// Enum variants can't be dereferenced yet.
+    DerefEnumVariant,
+    /// A value of this type was tail called, but only functions and function pointers can be tail called.
+    CantTailCall(crate::IString),
+}
+impl Display for CodegenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnersolvedGeneric => write!(f, "Unresolved generic"),
+            Self::Error(msg) => write!(f, "{msg}"),
+            Self::Method(err) => write!(f, "{err:?}"),
+            Self::FunctionABIUnsuported(abi) => write!(f, "Unsuported function ABI {abi}"),
+            Self::CantIndexInto(tpe) => write!(f, "Can't index into {tpe}"),
+            Self::IndexFromEnd => write!(f, "Can't index array from end"),
+            Self::UnsupportedProjection {
+                projection,
+                context,
+            } => write!(f, "Can't handle projection {projection} in {context}"),
+            Self::CantDeref(tpe) => write!(f, "Can't dereference type {tpe}"),
+            Self::DerefEnumVariant => write!(f, "Can't dereference enum variants"),
+            Self::CantTailCall(tpe) => write!(f, "Can't tail call type {tpe}"),
+        }
+    }
 }
 
 impl From<MethodCodegenError> for CodegenError {
@@ -18,6 +60,86 @@ impl CodegenError {
     pub fn from_panic_message(msg: &str) -> Self {
         Self::Error(msg.into())
     }
+    /// Converts the payload of a caught panic into an error. Errors raised using [`CodegenError::raise`] are preserved as-is.
+    pub fn from_panic_payload(payload: Box<dyn Any + Send>) -> Self {
+        match payload.downcast::<Self>() {
+            Ok(err) => *err,
+            Err(payload) => {
+                if let Some(msg) = payload.downcast_ref::<&str>() {
+                    Self::from_panic_message(msg)
+                } else if let Some(msg) = payload.downcast_ref::<String>() {
+                    Self::from_panic_message(msg)
+                } else {
+                    Self::from_panic_message("Codegen panicked with a non-string message!")
+                }
+            }
+        }
+    }
+    /// Aborts the compilation of the current method with this error. If `ABORT_ON_ERROR` is not set, the error is caught and the method
+    /// gets replaced with a stub throwing an exception.
+    pub fn raise(self) -> ! {
+        if *crate::config::ABORT_ON_ERROR {
+            panic!("{self}")
+        } else {
+            std::panic::panic_any(self)
+        }
+    }
+}
+/// Runs `f`, turning any panic or raised [`CodegenError`] into an error.
+pub fn catch_codegen_error<T>(f: impl FnOnce() -> T) -> Result<T, CodegenError> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+        .map_err(CodegenError::from_panic_payload)
+}
+/// Returns the body of the stub replacing the function `name`, which failed to compile with `err`. The stub throws an
+/// exception, so that only calls to the function fail.
+pub fn throwing_stub(name: &str, err: &CodegenError, asm: &mut Assembly) -> BasicBlock {
+    let msg = format!("Tried to call function {name}, which failed to compile: {err}");
+    BasicBlock::new(vec![CILRoot::throw(&msg, asm).into()], 0, None)
+}
+/// A construct which could not be compiled, and was replaced with a throwing stub.
+struct UnsupportedConstruct {
+    method: String,
+    span: Span,
+    error: String,
+}
+/// All the unsupported constructs encountered while compiling this crate.
+static UNSUPPORTED: Mutex<Vec<UnsupportedConstruct>> = Mutex::new(Vec::new());
+/// Records an unsupported construct at `span`, which will be reported by [`report_unsupported`].
+pub fn record_unsupported(method: &str, span: Span, error: &CodegenError) {
+    UNSUPPORTED
+        .lock()
+        .expect("ERROR: poisoned unsupported construct list")
+        .push(UnsupportedConstruct {
+            method: method.into(),
+            span,
+            error: error.to_string(),
+        });
+}
+/// Emits a warning for each unsupported construct encountered so far, pointing at the code it was found in.
+pub fn report_unsupported(tcx: TyCtxt<'_>) {
+    let unsupported = std::mem::take(
+        &mut *UNSUPPORTED
+            .lock()
+            .expect("ERROR: poisoned unsupported construct list"),
+    );
+    if unsupported.is_empty() {
+        return;
+    }
+    let dcx = tcx.dcx();
+    for construct in &unsupported {
+        dcx.span_warn(
+            construct.span,
+            format!(
+                "unsupported construct in {method}, which will throw an exception when called: {error}",
+                method = construct.method,
+                error = construct.error
+            ),
+        );
+    }
+    dcx.warn(format!(
+        "{} construct(s) could not be compiled, and were replaced with exceptions",
+        unsupported.len()
+    ));
 }
 
 pub struct MethodCodegenError {
@@ -57,3 +179,70 @@ impl Debug for MethodCodegenError {
         )
     }
 }
+#[test]
+fn raised_errors_are_caught() {
+    let err = catch_codegen_error::<()>(|| {
+        CodegenError::UnsupportedProjection {
+            projection: "Subtype".into(),
+            context: "place_adress",
+        }
+        .raise()
+    })
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        CodegenError::UnsupportedProjection {
+            context: "place_adress",
+            ..
+        }
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Can't handle projection Subtype in place_adress"
+    );
+    // Ordinary panics become errors too.
+    let err = catch_codegen_error(|| panic!("bad {}", 1)).unwrap_err();
+    assert_eq!(err.to_string(), "bad 1");
+    assert_eq!(catch_codegen_error(|| 7).unwrap(), 7);
+}
+#[test]
+fn error_messages() {
+    assert_eq!(
+        CodegenError::CantIndexInto("u8".into()).to_string(),
+        "Can't index into u8"
+    );
+    assert_eq!(
+        CodegenError::CantDeref("fn()".into()).to_string(),
+        "Can't dereference type fn()"
+    );
+    assert_eq!(
+        CodegenError::CantTailCall("u32".into()).to_string(),
+        "Can't tail call type u32"
+    );
+    assert_eq!(
+        CodegenError::IndexFromEnd.to_string(),
+        "Can't index array from end"
+    );
+}
+#[test]
+fn stub_throws() {
+    use cilly::cil_node::CILNode;
+    let mut asm = Assembly::default();
+    let stub = throwing_stub("broken", &CodegenError::DerefEnumVariant, &mut asm);
+    let [tree] = stub.trees() else {
+        panic!("The stub should consist of a single throw");
+    };
+    let CILRoot::Throw(CILNode::NewObj(exception)) = tree.root() else {
+        panic!(
+            "The stub should throw a new exception, not {:?}",
+            tree.root()
+        );
+    };
+    let [CILNode::LdStr(msg)] = &exception.args[..] else {
+        panic!("The exception should get a message");
+    };
+    assert_eq!(
+        &**msg,
+        "Tried to call function broken, which failed to compile: Can't dereference enum variants"
+    );
+}
@@ -374,6 +374,16 @@ fn compiler(test_name: &str, test_dir: &str, release: bool) -> std::process::Com
     cmd.current_dir(test_dir)
         .args(rustc_args().iter())
         .args([format!("./{test_name}.rs"), "-o".to_owned()]);
+    // Tests using `mycorrhiza` are linked against a copy of it, built with this backend.
+    let uses_mycorrhiza = std::fs::read_to_string(format!("{test_dir}/{test_name}.rs"))
+        .is_ok_and(|src| src.contains("mycorrhiza::"));
+    if uses_mycorrhiza {
+        let rlib = MYCORRHIZA_RLIB
+            .as_ref()
+            .expect("Could not build mycorrhiza!");
+        cmd.arg("--extern")
+            .arg(format!("mycorrhiza={}", rlib.display()));
+    }
     if release {
         cmd.arg(format!("./{test_name}.exe"));
     } else {
@@ -744,8 +754,11 @@ run_test! {cast,i16_to_u64,stable}
 run_test! {cast,i32_to_u64,stable}
 run_test! {cast,i32_to_usize,stable}
 run_test! {cast,coerce_unsized,unstable}
+run_test! {control_flow,async_join,unstable}
 run_test! {control_flow,cf_for,stable}
 run_test! {control_flow,drop,stable}
+run_test! {control_flow,switch,stable}
+run_test! {control_flow,tail_call,unstable}
 run_test! {fuzz,test0,stable}
 run_test! {fuzz,test1,stable}
 run_test! {intrinsics,addr_of,stable}
@@ -796,12 +809,21 @@ run_test! {types,aligned,stable}
 run_test! {types,any,stable}
 run_test! {types,arr,stable}
 run_test! {types,async_types,unstable}
+run_test! {types,calling_conv,stable}
 run_test! {types,dst,stable}
 run_test! {types,dyns,stable}
 run_test! {types,enums,stable}
+run_test! {types,export,stable}
 run_test! {types,int128,stable}
 run_test! {types,interop,stable}
+run_test! {types,interop_interface,unstable}
+run_test! {types,interop_tuple,stable}
+run_test! {types,interop_field,stable}
+run_test! {types,interop_generic,stable}
+run_test! {types,interop_delegate,stable}
+run_test! {types,interop_task,stable}
 run_test! {types,interop_typedef,unstable}
+run_test! {types,interop_ctor,stable}
 run_test! {types,maybeuninit,stable}
 run_test! {types,nbody,stable}
 run_test! {types,ref_deref,stable}
@@ -961,6 +983,7 @@ cargo_test! {benchmarks,bench}
 // TODO: This trips up some post-link sanity checks, investigate.
 cargo_test! {glam_test,unstable}
 cargo_test! {fastrand_test,stable}
+cargo_test! {thread_local_test,stable}
 
 #[cfg(target_os = "windows")]
 const IS_DOTNET_PRESENT: &bool = &true;
@@ -974,6 +997,30 @@ static IS_PEVERIFY_PRESENT: std::sync::LazyLock<bool> =
     std::sync::LazyLock::new(|| std::process::Command::new("peverify").output().is_ok());
 static RUSTC_BUILD_STATUS: std::sync::LazyLock<Result<(), String>> =
     std::sync::LazyLock::new(build_backend);
+/// The path to `mycorrhiza`, built as an rlib with this backend.
+static MYCORRHIZA_RLIB: std::sync::LazyLock<Result<PathBuf, String>> =
+    std::sync::LazyLock::new(|| {
+        RUSTC_BUILD_STATUS.as_ref().expect("Could not build rustc!");
+        std::fs::create_dir_all("./test/out").map_err(|err| err.to_string())?;
+        let out = std::process::Command::new("rustc")
+            .args(rustc_args().iter())
+            .args([
+                "-O",
+                "--crate-type",
+                "rlib",
+                "--crate-name",
+                "mycorrhiza",
+                "./mycorrhiza/src/lib.rs",
+                "-o",
+                "./test/out/libmycorrhiza.rlib",
+            ])
+            .output()
+            .map_err(|err| err.to_string())?;
+        if !out.status.success() {
+            return Err(String::from_utf8_lossy(&out.stderr).into_owned());
+        }
+        std::fs::canonicalize("./test/out/libmycorrhiza.rlib").map_err(|err| err.to_string())
+    });
 static RUSTC_CODEGEN_CLR_LINKER: std::sync::LazyLock<PathBuf> = std::sync::LazyLock::new(|| {
     let _ = *RUSTC_BUILD_STATUS;
     if cfg!(debug_assertions) {
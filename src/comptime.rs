@@ -1,15 +1,62 @@
-#![allow(dead_code)]
-use crate::fn_ctx::MethodCompileCtx;
-use cilly::v2::ClassDef;
+//! The comptime assembly editor. Functions named `rustc_codegen_clr_comptime_entrypoint` are not compiled, but
+//! interpreted at compile time instead. They can call a small set of magic functions, which build .NET type definitions.
+//! Those types are then added to the assembly, and can be used by other .NET code like any other managed class.
+use crate::{
+    call_info::CallInfo,
+    fn_ctx::MethodCompileCtx,
+    utilis::{garag_to_bool, garg_to_string},
+};
+use cilly::v2::{
+    cilnode::MethodKind, tpe::GenericKind, Access, CILNode, CILRoot, ClassDef, ClassDefIdx,
+    ClassRef, ClassRefIdx, MethodDef, MethodImpl, MethodRef, MethodRefIdx, SigIdx, StringIdx, Type,
+};
+use rustc_middle::{
+    mir::{BasicBlock, Local, Operand, Rvalue, StatementKind, TerminatorKind},
+    ty::{GenericArgsRef, Instance, ParamEnv, Ty, TyKind},
+};
+/// Creates a new type definition. Generic args: name, is valuetype, assembly of the base type, name of the base type.
+pub const NEW_TYPEDEF: &str = "rustc_codegen_clr_new_typedef";
+/// Adds a field to a type definition. Generic args: type of the field, name of the field.
+pub const ADD_FIELD_DEF: &str = "rustc_codegen_clr_add_field_def";
+/// Adds a method to a type definition. Generic args: visibility, modifiers, method name, type of the implementing fn.
+pub const ADD_METHOD_DEF: &str = "rustc_codegen_clr_add_method_def";
+/// Makes a type definition implement an interface. Generic args: assembly of the interface, name of the interface, a
+/// tuple of the generic arguments of the interface.
+pub const ADD_INTERFACE: &str = "rustc_codegen_clr_add_interface";
+/// Adds a method implementing a method of the last added interface. Generic args: method name, type of the
+/// implementing fn.
+pub const ADD_INTERFACE_METHOD_DEF: &str = "rustc_codegen_clr_add_interface_method_def";
+/// Adds the type definition to the assembly.
+pub const FINISH_TYPE: &str = "rustc_codegen_clr_finish_type";
+/// A method of a type under construction, implemented by a Rust function.
+#[derive(Clone)]
+struct ComptimeMethod {
+    access: Access,
+    kind: MethodKind,
+    name: StringIdx,
+    sig: SigIdx,
+    implementation: MethodRefIdx,
+    /// The interface method this method implements, if any.
+    overrides: Option<MethodRefIdx>,
+}
+/// A type definition under construction. Methods are only added once the type is finished, since they need the
+/// [`ClassDefIdx`] of their class.
+#[derive(Clone)]
+struct ComptimeTypeDef {
+    def: ClassDef,
+    methods: Vec<ComptimeMethod>,
+    /// The interface the following interface methods belong to.
+    interface: Option<ClassRefIdx>,
+}
 #[derive(Clone)]
 enum ComptimeLocalVar {
     NotSet,
     Void,
-    ClassDef(ClassDef),
+    ClassDef(Box<ComptimeTypeDef>),
 }
 
 impl ComptimeLocalVar {
-    fn as_type_def(&self) -> Option<&ClassDef> {
+    fn as_type_def(&self) -> Option<&ComptimeTypeDef> {
         if let Self::ClassDef(v) = self {
             Some(v)
         } else {
@@ -17,19 +64,49 @@ impl ComptimeLocalVar {
         }
     }
 }
-#[allow(unused_variables)]
+/// Returns the local an operand reads from. Only locals are supported in comptime code.
+fn operand_local(operand: &Operand) -> Local {
+    operand
+        .place()
+        .and_then(|place| place.as_local())
+        .expect("ERROR: unsupported operand in interop type definition. Only locals may be used.")
+}
+/// Returns the type definition stored in `operand`.
+fn type_def_arg(operand: &Operand, locals: &[ComptimeLocalVar]) -> ComptimeTypeDef {
+    locals[usize::from(operand_local(operand))]
+        .as_type_def()
+        .expect("ERROR: interop type definition used before it was created.")
+        .clone()
+}
+/// Resolves a function definition type to an instance.
+fn resolve_fn<'tcx>(
+    fn_ty: Ty<'tcx>,
+    ctx: &MethodCompileCtx<'tcx, '_>,
+) -> (Instance<'tcx>, GenericArgsRef<'tcx>) {
+    let fn_ty = ctx.monomorphize(fn_ty);
+    let TyKind::FnDef(def_id, subst_ref) = fn_ty.kind() else {
+        panic!(
+            "ERROR: {fn_ty:?} is not a function definition, and can't be called in comptime code."
+        );
+    };
+    let subst_ref = ctx.monomorphize(*subst_ref);
+    let Some(instance) =
+        Instance::try_resolve(ctx.tcx(), ParamEnv::reveal_all(), *def_id, subst_ref)
+            .expect("Invalid function def")
+    else {
+        panic!("ERROR: Could not get function instance. fn type:{fn_ty:?}")
+    };
+    (instance, subst_ref)
+}
+/// Interprets the comptime entrypoint `body`, adding all the types it defines to the assembly.
 pub fn interpret<'tcx>(
     ctx: &mut MethodCompileCtx<'tcx, '_>,
     body: &'tcx rustc_middle::mir::Body<'tcx>,
 ) {
-    todo!("Can't yet start the comptime assembly editor.");
-    /*
-    let block_id = rustc_middle::mir::BasicBlock::from_usize(0);
-    let locals = vec![ComptimeLocalVar::NotSet; body.local_decls.len()];
-
+    let mut block_id = BasicBlock::from_usize(0);
+    let mut locals = vec![ComptimeLocalVar::NotSet; body.local_decls.len()];
     loop {
         let block_data = &body.basic_blocks[block_id];
-        // Skip cleanup in the interpreter
         assert!(
             !block_data.is_cleanup,
             "Can't interpret a cleanup block in rustc_codegen_clr comptime"
@@ -39,194 +116,350 @@ pub fn interpret<'tcx>(
                 StatementKind::Assign(bx) => {
                     let (target, rvalue) = bx.as_ref();
                     let src = match rvalue {
-                        Rvalue::Use(src) => src,
-                        Rvalue::Cast(
+                        Rvalue::Use(Operand::Copy(src) | Operand::Move(src)) => src,
+                        // Constants and function pointers only matter as generic arguments of magic functions.
+                        Rvalue::Use(Operand::Constant(_))
+                        | Rvalue::Cast(
                             rustc_middle::mir::CastKind::PointerCoercion(
-                                rustc_middle::ty::adjustment::PointerCoercion::ReifyFnPointer,
+                                rustc_middle::ty::adjustment::PointerCoercion::ReifyFnPointer(_),
                             ),
                             _,
                             _,
                         ) => continue,
-                        _ => panic!(),
+                        _ => panic!(
+                            "can't interpret the rvalue {rvalue:?} in rustc_codegen_clr comptime"
+                        ),
                     };
-
-                    let src = src.place().unwrap().as_local().unwrap();
-                    let target = target.as_local().unwrap();
+                    let src = src
+                        .as_local()
+                        .expect("ERROR: unsupported place in interop type definition.");
+                    let target = target
+                        .as_local()
+                        .expect("ERROR: unsupported place in interop type definition.");
                     locals[usize::from(target)] = locals[usize::from(src)].clone();
                 }
-                StatementKind::StorageLive(_) | StatementKind::StorageDead(_) => (),
-                _ => todo!(
-                    "can't interpret the statement {statement:?} yet in rustc_codegen_clr comptime"
+                StatementKind::StorageLive(_)
+                | StatementKind::StorageDead(_)
+                | StatementKind::FakeRead(_)
+                | StatementKind::PlaceMention(_)
+                | StatementKind::AscribeUserType(..)
+                | StatementKind::Coverage(_)
+                | StatementKind::ConstEvalCounter
+                | StatementKind::Nop => (),
+                _ => panic!(
+                    "can't interpret the statement {statement:?} in rustc_codegen_clr comptime"
                 ),
             }
         }
-        match &block_data.terminator {
-            Some(term) => match &term.kind {
-                TerminatorKind::Call {
-                    func,
-                    args,
-                    destination,
-                    target,
-                    unwind: _,
-                    call_source: _,
-                    fn_span: _,
-                } => {
-                    let func_ty = func.ty(body, ctx.tcx());
-                    let func_ty = ctx.monomorphize(func_ty);
-                    let (call_instance, subst_ref) = if let TyKind::FnDef(def_id, subst_ref) =
-                        func_ty.kind()
-                    {
-                        let subst_ref = ctx.monomorphize(*subst_ref);
-                        let env = ParamEnv::reveal_all();
-                        let Some(call_instance) =
-                            Instance::try_resolve(ctx.tcx(), env, *def_id, subst_ref)
-                                .expect("Invalid function def")
-                        else {
-                            panic!("ERROR: Could not get function instance. fn type:{func_ty:?}")
-                        };
-
-                        (call_instance, subst_ref)
-                    } else {
-                        todo!("Trying to call a type which is not a function definition!");
-                    };
-                    let function_name =
-                        crate::utilis::function_name(ctx.tcx().symbol_name(call_instance));
-                    let local = destination
-                        .as_local()
-                        .expect("ERROR: unuported operation in interop type definiton.");
-                    locals[usize::from(local)] = if function_name
-                        .contains("rustc_codegen_clr_new_typedef")
-                    {
-                        let name = crate::utilis::garg_to_string(subst_ref[0], ctx.tcx())
-                            .replace("::", ".");
-                        let is_value_type = crate::utilis::garag_to_bool(subst_ref[1], ctx.tcx());
-                        let superclass_asm = crate::utilis::garg_to_string(subst_ref[2], ctx.tcx())
-                            .replace("::", ".");
-                        let superclass_name =
-                            crate::utilis::garg_to_string(subst_ref[3], ctx.tcx())
-                                .replace("::", ".");
-                        let inherits = if superclass_name.is_empty() {
-                            None
-                        } else {
-                            Some(ClassRef::new(
-                                Some(superclass_asm).and_then(|superclass_asm| {
-                                    if superclass_asm.is_empty() {
-                                        None
-                                    } else {
-                                        Some(superclass_asm)
-                                    }
-                                }),
-                                superclass_name,
-                                is_value_type,
-                                [].into(),
-                            ))
-                        };
-                        let tdef = ClassDef::new(
-                            cilly::access_modifier::AccessModifer::Public,
-                            name.into(),
-                            vec![],
-                            vec![],
-                            vec![],
-                            None,
-                            0,
-                            inherits,
-                            None,
-                        );
-
-                        ComptimeLocalVar::ClassDef(tdef)
-                    } else if function_name.contains("rustc_codegen_clr_finish_type") {
-                        let local = args[0]
-                            .node
-                            .place()
-                            .expect("ERROR: unuported operation in interop type definiton.")
-                            .as_local()
-                            .expect("ERROR: unuported operation in interop type definiton.");
-                        ctx
-                            .class_def(locals[usize::from(local)].as_type_def().unwrap().clone());
-                        ComptimeLocalVar::Void
-                    } else if function_name.as_ref() == "black_box" {
-                        ComptimeLocalVar::NotSet
-                    } else if function_name.contains("rustc_codegen_clr_add_field_def") {
-                        let src = args[0]
-                            .node
-                            .place()
-                            .expect("ERROR: unuported operation in interop type definiton.")
-                            .as_local()
-                            .expect("ERROR: unuported operation in interop type definiton.");
-                        let mut type_def = locals[usize::from(src)].as_type_def().unwrap().clone();
-                        let tpe = crate::utilis::monomorphize(
-                            &instance,
-                            subst_ref[0].as_type().unwrap(),
-                            tcx,
-                        );
-                        let tpe = get_type(tpe, ctx);
-                        let name = crate::utilis::garg_to_string(subst_ref[1], ctx.tcx());
-                        type_def.add_field(name.into(), tpe);
-                        ComptimeLocalVar::ClassDef(type_def)
-                    } else if function_name.contains("rustc_codegen_clr_add_method_def") {
-                        let src = args[0]
-                            .node
-                            .place()
-                            .expect("ERROR: unuported operation in interop type definiton.")
-                            .as_local()
-                            .expect("ERROR: unuported operation in interop type definiton.");
-
-                        let mut type_def = locals[usize::from(src)].as_type_def().unwrap().clone();
-                        let fn_type = crate::utilis::monomorphize(
-                            &instance,
-                            subst_ref[3].as_type().unwrap(),
-                            ctx.tcx(),
-                        );
-                        let this_fname = crate::utilis::garg_to_string(subst_ref[2], ctx.tcx())
-                            .replace("::", ".");
-                        let def_instance = if let TyKind::FnDef(def_id, subst_ref) = fn_type.kind()
-                        {
-                            let subst_ref =
-                                crate::utilis::monomorphize(&instance, *subst_ref, ctx.tcx());
-                            let env = ParamEnv::reveal_all();
-                            let Some(instance) =
-                                Instance::try_resolve(ctx.tcx(), env, *def_id, subst_ref)
-                                    .expect("Invalid function def")
-                            else {
-                                panic!(
-                                    "ERROR: Could not get function instance. fn type:{fn_type:?}"
-                                )
-                            };
-
-                            instance
-                        } else {
-                            todo!("Trying to call a type which is not a function definition!");
-                        };
-                        let call_info = CallInfo::sig_from_instance_(def_instance, ctx);
-                        let target_function_name =
-                            crate::utilis::function_name(ctx.tcx().symbol_name(def_instance));
-                        let call_site = MethodRefIdx::new(
-                            None,
-                            target_function_name,
-                            call_info.sig().clone(),
-                            true,
-                        );
-
-                        let method = Method::alias_for(
-                            cilly::access_modifier::AccessModifer::Public,
-                            // Only virtuals for now
-                            cilly::method::MethodType::Virtual,
-                            this_fname.into(),
-                            call_site,
-                        );
-                        type_def.add_method(method);
-
-                        //type_def.add_method(name.into(), tpe);
-                        ComptimeLocalVar::ClassDef(type_def)
-                    } else {
-                        todo!("Can't yet call the rustc_codegen_clr comptime interop fn named {function_name:?}")
-                    };
-                    block_id = target.unwrap();
-                }
-                TerminatorKind::Return => return,
-                _ => todo!("can't interpret the term {term:?} yet in rustc_codegen_clr comptime"),
-            },
-            None => panic!("Terminatorless block"),
+        let term = block_data.terminator();
+        block_id = match &term.kind {
+            TerminatorKind::Call {
+                func,
+                args,
+                destination,
+                target,
+                ..
+            } => {
+                let (call_instance, subst_ref) = resolve_fn(func.ty(body, ctx.tcx()), ctx);
+                let function_name =
+                    crate::utilis::function_name(ctx.tcx().symbol_name(call_instance));
+                let result = if function_name.contains(NEW_TYPEDEF) {
+                    new_typedef(subst_ref, ctx)
+                } else if function_name.contains(ADD_FIELD_DEF) {
+                    let mut type_def = type_def_arg(&args[0].node, &locals);
+                    let tpe = subst_ref[0]
+                        .as_type()
+                        .expect("ERROR: the type of a field must be a type.");
+                    let tpe = ctx.type_from_cache(ctx.monomorphize(tpe));
+                    let name = garg_to_string(subst_ref[1], ctx.tcx());
+                    let name = ctx.alloc_string(name);
+                    type_def.def.fields_mut().push((tpe, name, None));
+                    ComptimeLocalVar::ClassDef(Box::new(type_def))
+                } else if function_name.contains(ADD_INTERFACE_METHOD_DEF) {
+                    let mut type_def = type_def_arg(&args[0].node, &locals);
+                    let interface = type_def.interface.expect(
+                        "ERROR: an interface method must be added after the interface it implements.",
+                    );
+                    type_def
+                        .methods
+                        .push(interface_method_def(interface, subst_ref, ctx));
+                    ComptimeLocalVar::ClassDef(Box::new(type_def))
+                } else if function_name.contains(ADD_INTERFACE) {
+                    let mut type_def = type_def_arg(&args[0].node, &locals);
+                    let interface = interface(subst_ref, ctx);
+                    type_def.def.add_interface(interface);
+                    type_def.interface = Some(interface);
+                    ComptimeLocalVar::ClassDef(Box::new(type_def))
+                } else if function_name.contains(ADD_METHOD_DEF) {
+                    let mut type_def = type_def_arg(&args[0].node, &locals);
+                    type_def.methods.push(method_def(subst_ref, ctx));
+                    ComptimeLocalVar::ClassDef(Box::new(type_def))
+                } else if function_name.contains(FINISH_TYPE) {
+                    finish_type(type_def_arg(&args[0].node, &locals), ctx);
+                    ComptimeLocalVar::Void
+                } else if function_name.as_ref() == "black_box" {
+                    ComptimeLocalVar::NotSet
+                } else {
+                    panic!("Can't call {function_name:?} in rustc_codegen_clr comptime code. Only interop type definition functions may be called.")
+                };
+                let local = destination
+                    .as_local()
+                    .expect("ERROR: unsupported place in interop type definition.");
+                locals[usize::from(local)] = result;
+                target.expect("ERROR: diverging call in interop type definition.")
+            }
+            // Type definitions don't need dropping.
+            TerminatorKind::Goto { target } | TerminatorKind::Drop { target, .. } => *target,
+            TerminatorKind::Return => return,
+            _ => panic!("can't interpret the terminator {term:?} in rustc_codegen_clr comptime"),
+        };
+    }
+}
+/// Creates a new, empty type definition, described by the generic arguments of [`NEW_TYPEDEF`].
+fn new_typedef<'tcx>(
+    subst_ref: GenericArgsRef<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> ComptimeLocalVar {
+    let name = garg_to_string(subst_ref[0], ctx.tcx()).replace("::", ".");
+    let is_valuetype = garag_to_bool(subst_ref[1], ctx.tcx());
+    let superclass_asm = garg_to_string(subst_ref[2], ctx.tcx()).replace("::", ".");
+    let superclass_name = garg_to_string(subst_ref[3], ctx.tcx()).replace("::", ".");
+    let extends = if superclass_name.is_empty() {
+        None
+    } else {
+        let superclass_asm = (!superclass_asm.is_empty()).then(|| ctx.alloc_string(superclass_asm));
+        let superclass_name = ctx.alloc_string(superclass_name);
+        // The base type of a valuetype is always `System.ValueType`, which is not a valuetype itself.
+        Some(ctx.alloc_class_ref(ClassRef::new(
+            superclass_name,
+            superclass_asm,
+            false,
+            [].into(),
+        )))
+    };
+    let name = ctx.alloc_string(name);
+    // The type must be visible from other assemblies, and must never be removed as dead code.
+    let def = ClassDef::new(
+        name,
+        is_valuetype,
+        0,
+        extends,
+        vec![],
+        vec![],
+        Access::Extern,
+        None,
+        None,
+    );
+    ComptimeLocalVar::ClassDef(Box::new(ComptimeTypeDef {
+        def,
+        methods: vec![],
+        interface: None,
+    }))
+}
+/// Describes a method implemented by a Rust function, based on the generic arguments of [`ADD_METHOD_DEF`].
+fn method_def<'tcx>(
+    subst_ref: GenericArgsRef<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> ComptimeMethod {
+    let access = match garg_to_string(subst_ref[0], ctx.tcx()).as_ref() {
+        "pub" => Access::Extern,
+        "" | "priv" => Access::Private,
+        other => panic!(
+            "ERROR: {other:?} is not a valid method visibility. Expected \"pub\" or \"priv\"."
+        ),
+    };
+    let kind = match garg_to_string(subst_ref[1], ctx.tcx()).as_ref() {
+        "virtual" => MethodKind::Virtual,
+        "static" => MethodKind::Static,
+        "" | "instance" => MethodKind::Instance,
+        other => panic!("ERROR: {other:?} is not a valid method modifier. Expected \"virtual\", \"static\" or \"instance\"."),
+    };
+    let name = garg_to_string(subst_ref[2], ctx.tcx()).replace("::", ".");
+    let fn_type = subst_ref[3]
+        .as_type()
+        .expect("ERROR: the implementation of a method must be a function.");
+    rust_method(access, kind, &name, fn_type, ctx)
+}
+/// Describes a method implemented by the Rust function `fn_type`.
+fn rust_method<'tcx>(
+    access: Access,
+    kind: MethodKind,
+    name: &str,
+    fn_type: Ty<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> ComptimeMethod {
+    let (def_instance, _) = resolve_fn(fn_type, ctx);
+    // The Rust function is static, but its first argument becomes `this` for instance methods. So, the signatures match.
+    let sig = CallInfo::sig_from_instance_(def_instance, ctx)
+        .sig()
+        .clone();
+    let sig = ctx.alloc_sig(sig);
+    let target_name = crate::utilis::function_name(ctx.tcx().symbol_name(def_instance));
+    let implementation = MethodRef::new(
+        *ctx.main_module(),
+        ctx.alloc_string(target_name),
+        sig,
+        MethodKind::Static,
+        vec![].into(),
+    );
+    ComptimeMethod {
+        access,
+        kind,
+        name: ctx.alloc_string(name),
+        sig,
+        implementation: ctx.alloc_methodref(implementation),
+        overrides: None,
+    }
+}
+/// Gets the interface described by the generic arguments of [`ADD_INTERFACE`].
+fn interface<'tcx>(
+    subst_ref: GenericArgsRef<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> ClassRefIdx {
+    // The name may come from a list of identifiers, so it can contain whitespace (`System :: IDisposable`).
+    let strip = |name: &str| -> String {
+        name.chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .replace("::", ".")
+    };
+    let asm = strip(&garg_to_string(subst_ref[0], ctx.tcx()));
+    let name = strip(&garg_to_string(subst_ref[1], ctx.tcx()));
+    let generics = subst_ref[2]
+        .as_type()
+        .expect("ERROR: the generic arguments of an interface must be a tuple of types.");
+    let TyKind::Tuple(generics) = ctx.monomorphize(generics).kind() else {
+        panic!("ERROR: the generic arguments of an interface must be a tuple of types.")
+    };
+    let generics: Box<[Type]> = generics
+        .iter()
+        .map(|tpe| ctx.type_from_cache(tpe))
+        .collect();
+    let asm = (!asm.is_empty()).then(|| ctx.alloc_string(asm));
+    let name = ctx.alloc_string(name);
+    ctx.alloc_class_ref(ClassRef::new(name, asm, false, generics))
+}
+/// Replaces all uses of the generic arguments of `interface` in `tpe` with the generic parameters of the interface.
+/// Interface methods are referenced using the signature from the generic interface definition, so an
+/// `IComparable<Foo>.CompareTo(Foo)` method must be referenced as `IComparable<Foo>.CompareTo(!0)`.
+fn generic_interface_type(tpe: Type, interface: ClassRefIdx, ctx: &mut MethodCompileCtx) -> Type {
+    if let Some(idx) = ctx[interface].generics().iter().position(|arg| *arg == tpe) {
+        return Type::PlatformGeneric(
+            u32::try_from(idx).expect("Too many generics"),
+            GenericKind::TypeGeneric,
+        );
+    }
+    let Type::ClassRef(cref) = tpe else {
+        return tpe;
+    };
+    if ctx[cref].generics().is_empty() {
+        return tpe;
+    }
+    let mut class = ctx[cref].clone();
+    let generics: Vec<_> = class
+        .generics()
+        .iter()
+        .map(|arg| generic_interface_type(*arg, interface, ctx))
+        .collect();
+    class.set_generics(generics);
+    Type::ClassRef(ctx.alloc_class_ref(class))
+}
+/// Describes a public, virtual method implementing the same-named method of `interface`, based on the generic arguments
+/// of [`ADD_INTERFACE_METHOD_DEF`].
+fn interface_method_def<'tcx>(
+    interface: ClassRefIdx,
+    subst_ref: GenericArgsRef<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> ComptimeMethod {
+    let name = garg_to_string(subst_ref[0], ctx.tcx()).replace("::", ".");
+    let fn_type = subst_ref[1]
+        .as_type()
+        .expect("ERROR: the implementation of a method must be a function.");
+    let mut method = rust_method(Access::Extern, MethodKind::Virtual, &name, fn_type, ctx);
+    let sig = ctx[method.sig].clone();
+    assert!(
+        !sig.inputs().is_empty(),
+        "ERROR: the interface method {name} must take `this` as its first argument."
+    );
+    let inputs: Vec<_> = sig.inputs()[1..]
+        .iter()
+        .map(|tpe| generic_interface_type(*tpe, interface, ctx))
+        .collect();
+    let output = generic_interface_type(*sig.output(), interface, ctx);
+    method.overrides = Some(
+        ctx[interface]
+            .clone()
+            .virtual_mref(&inputs, output, method.name, ctx),
+    );
+    method
+}
+/// Adds a finished type definition, and all of its methods, to the assembly.
+fn finish_type(type_def: ComptimeTypeDef, ctx: &mut MethodCompileCtx<'_, '_>) {
+    let cref = ctx.alloc_class_ref(ClassRef::new(
+        type_def.def.name(),
+        None,
+        type_def.def.is_valuetype(),
+        [].into(),
+    ));
+    assert!(
+        ctx.class_ref_to_def(cref).is_none(),
+        "ERROR: the interop type {} is defined more than once.",
+        &ctx[type_def.def.name()]
+    );
+    let is_valuetype = type_def.def.is_valuetype();
+    let extends = type_def.def.extends();
+    let class: ClassDefIdx = ctx.class_def(type_def.def);
+    // Valuetypes are created with `initobj`, but classes need a constructor to be instantiated with `newobj`.
+    if !is_valuetype {
+        default_ctor(class, extends, ctx);
+    }
+    for method in type_def.methods {
+        let arg_names = vec![None; ctx[method.sig].inputs().len()];
+        let body = ctx.new_method(MethodDef::new(
+            method.access,
+            class,
+            method.name,
+            method.sig,
+            method.kind,
+            MethodImpl::AliasFor(method.implementation),
+            arg_names,
+        ));
+        if let Some(decl) = method.overrides {
+            ctx.class_mut(class).add_override(decl, body);
         }
-    } */
+    }
+}
+/// Adds a public, parameterless `.ctor` to `class`, which calls the parameterless constructor of its base class.
+fn default_ctor(
+    class: ClassDefIdx,
+    extends: Option<ClassRefIdx>,
+    ctx: &mut MethodCompileCtx<'_, '_>,
+) {
+    let base = extends.unwrap_or_else(|| ClassRef::object(ctx));
+    let name = ctx.alloc_string(".ctor");
+    let base_sig = ctx.sig([Type::ClassRef(base)], Type::Void);
+    // The base constructor is called on `this`, like any other instance method, and not with `newobj`.
+    let base_ctor = ctx.alloc_methodref(MethodRef::new(
+        base,
+        name,
+        base_sig,
+        MethodKind::Instance,
+        vec![].into(),
+    ));
+    let this = ctx.alloc_node(CILNode::LdArg(0));
+    let call_base = ctx.alloc_root(CILRoot::Call(Box::new((base_ctor, [this].into()))));
+    let ret = ctx.alloc_root(CILRoot::VoidRet);
+    let sig = ctx.sig([Type::ClassRef(*class)], Type::Void);
+    ctx.new_method(MethodDef::new(
+        Access::Extern,
+        class,
+        name,
+        sig,
+        MethodKind::Constructor,
+        MethodImpl::MethodBody {
+            blocks: vec![cilly::v2::BasicBlock::new(vec![call_base, ret], 0, None)],
+            locals: vec![],
+        },
+        vec![None],
+    ));
 }
@@ -368,7 +368,7 @@ fn get_fn_from_static_name(name: &str, ctx: &mut MethodCompileCtx<'_, '_>) -> Me
             MethodRef::new(
                 *ctx.main_module(),
                 ctx.alloc_string("__cxa_thread_atexit_impl"),
-                ctx.sig([fn_ptr_sig, void_ptr, void_ptr], Type::Void),
+                ctx.sig([fn_ptr_sig, void_ptr, void_ptr], Type::Int(Int::I32)),
                 MethodKind::Static,
                 vec![].into(),
             )
@@ -0,0 +1,331 @@
+//! Creates managed delegates calling Rust closures. A delegate is bound to an instance of a thunk class, which owns the
+//! boxed closure. The `Invoke` method of the thunk calls a Rust function, passing it the closure together with the
+//! arguments of the delegate. Once the delegate gets collected, the finalizer of the thunk drops the closure.
+//!
+//! There is one thunk class per signature, and it stores the Rust functions as function pointers, so closures of
+//! different types can share it.
+use crate::assembly::MethodCompileCtx;
+use cilly::{
+    cil_node::{CILNode, CallOpArgs},
+    cil_root::CILRoot,
+    v2::{
+        cilnode::MethodKind, Access, BasicBlock, ClassDef, ClassDefIdx, ClassRef, FieldDesc,
+        FieldIdx, Int, MethodDef, MethodImpl, MethodRef, SigIdx,
+    },
+    Type,
+};
+use rustc_middle::{
+    mir::{Operand, Place},
+    ty::{GenericArg, ParamEnv, TyKind},
+};
+use rustc_span::Spanned;
+/// The prefix of the names of thunk classes.
+const THUNK_PREFIX: &str = "RustDelegateThunk_";
+/// The fields of a thunk class: the closure, the function invoking it, and the function dropping it.
+struct ThunkFields {
+    env: FieldIdx,
+    invoke: FieldIdx,
+    drop: FieldIdx,
+}
+/// Creates a delegate, described by the generic arguments of [`crate::utilis::MANAGED_DELEGATE`]: [delegate,
+/// signature]. The arguments are a pointer to the boxed closure, the function invoking it, and the function dropping
+/// it.
+pub(crate) fn managed_delegate<'tcx>(
+    subst_ref: &[GenericArg<'tcx>],
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    assert_eq!(
+        args.len(),
+        3,
+        "ERROR: a delegate must be created from a closure, and the functions invoking and dropping it."
+    );
+    let delegate = subst_ref[0]
+        .as_type()
+        .expect("ERROR: the delegate type must be a type.");
+    let delegate = ctx
+        .type_from_cache(ctx.monomorphize(delegate))
+        .as_class_ref()
+        .expect("ERROR: delegates must be managed classes.");
+    let sig = ctx.monomorphize(
+        subst_ref[1]
+            .as_type()
+            .expect("ERROR: the signature of a delegate must be a type."),
+    );
+    let TyKind::FnPtr(sig, _) = sig.kind() else {
+        panic!("ERROR: the signature of a delegate must be a function pointer type, not {sig:?}.")
+    };
+    let sig = ctx
+        .tcx()
+        .normalize_erasing_late_bound_regions(ParamEnv::reveal_all(), *sig);
+    let inputs: Vec<Type> = sig
+        .inputs()
+        .iter()
+        .map(|input| ctx.type_from_cache(*input))
+        .collect();
+    assert!(
+        !inputs.contains(&Type::Void),
+        "ERROR: delegates can't have zero-sized parameters."
+    );
+    let output = ctx.type_from_cache(sig.output());
+    let (thunk, invoke) = thunk_class(&inputs, output, ctx);
+    let thunk = *thunk;
+    // The Rust functions are passed as data pointers, and need to be cast back into function pointers.
+    let void_ptr = ctx.nptr(Type::Void);
+    let mut invoke_inputs = vec![void_ptr];
+    invoke_inputs.extend(inputs.iter().copied());
+    let invoke_sig = ctx.sig(invoke_inputs, output);
+    let drop_sig = ctx.sig([void_ptr], Type::Void);
+    let env = crate::operand::handle_operand(&args[0].node, ctx).cast_ptr(void_ptr);
+    let invoke_fn =
+        crate::operand::handle_operand(&args[1].node, ctx).cast_ptr(Type::FnPtr(invoke_sig));
+    let drop_fn =
+        crate::operand::handle_operand(&args[2].node, ctx).cast_ptr(Type::FnPtr(drop_sig));
+    let thunk_ctor = ctx[thunk].clone().ctor(
+        &[void_ptr, Type::FnPtr(invoke_sig), Type::FnPtr(drop_sig)],
+        ctx,
+    );
+    let thunk_obj = CILNode::NewObj(Box::new(CallOpArgs {
+        site: thunk_ctor,
+        args: [env, invoke_fn, drop_fn].into(),
+    }));
+    // `new Delegate(thunk, &thunk.Invoke)`
+    let delegate_ctor = ctx[delegate]
+        .clone()
+        .ctor(&[Type::PlatformObject, Type::Int(Int::ISize)], ctx);
+    let invoke_ptr = CILNode::LDFtn(invoke).cast_ptr(Type::Int(Int::ISize));
+    crate::place::place_set(
+        destination,
+        CILNode::NewObj(Box::new(CallOpArgs {
+            site: delegate_ctor,
+            args: [thunk_obj, invoke_ptr].into(),
+        })),
+        ctx,
+    )
+}
+/// Returns the thunk class for delegates with the signature `inputs -> output`, and a reference to its `Invoke`
+/// method. Defines the class if it does not exist yet.
+fn thunk_class(
+    inputs: &[Type],
+    output: Type,
+    ctx: &mut MethodCompileCtx<'_, '_>,
+) -> (ClassDefIdx, cilly::v2::MethodRefIdx) {
+    let mangled: Vec<String> = inputs
+        .iter()
+        .chain(std::iter::once(&output))
+        .map(|tpe| tpe.mangle(ctx))
+        .collect();
+    let name = ctx.alloc_string(format!("{THUNK_PREFIX}{}", mangled.join("_")));
+    let class_ref = ctx.alloc_class_ref(ClassRef::new(name, None, false, [].into()));
+    let mut invoke_sig = vec![Type::ClassRef(class_ref)];
+    invoke_sig.extend(inputs.iter().copied());
+    let invoke_sig = ctx.sig(invoke_sig, output);
+    let invoke_name = ctx.alloc_string("Invoke");
+    let invoke = ctx.alloc_methodref(MethodRef::new(
+        class_ref,
+        invoke_name,
+        invoke_sig,
+        MethodKind::Instance,
+        vec![].into(),
+    ));
+    if let Some(thunk) = ctx.class_ref_to_def(class_ref) {
+        return (thunk, invoke);
+    }
+    let void_ptr = ctx.nptr(Type::Void);
+    let mut rust_invoke_inputs = vec![void_ptr];
+    rust_invoke_inputs.extend(inputs.iter().copied());
+    let rust_invoke_sig = ctx.sig(rust_invoke_inputs, output);
+    let rust_drop_sig = ctx.sig([void_ptr], Type::Void);
+    let env_name = ctx.alloc_string("env");
+    let invoke_fn_name = ctx.alloc_string("invoke");
+    let drop_fn_name = ctx.alloc_string("drop");
+    let object = ClassRef::object(ctx);
+    let thunk = ctx.class_def(ClassDef::new(
+        name,
+        false,
+        0,
+        Some(object),
+        vec![
+            (void_ptr, env_name, None),
+            (Type::FnPtr(rust_invoke_sig), invoke_fn_name, None),
+            (Type::FnPtr(rust_drop_sig), drop_fn_name, None),
+        ],
+        vec![],
+        // Like `UnmanagedThreadStart`, the thunk must not be removed by dead code elimination.
+        Access::Extern,
+        None,
+        None,
+    ));
+    let fields = ThunkFields {
+        env: ctx.alloc_field(FieldDesc::new(*thunk, env_name, void_ptr)),
+        invoke: ctx.alloc_field(FieldDesc::new(
+            *thunk,
+            invoke_fn_name,
+            Type::FnPtr(rust_invoke_sig),
+        )),
+        drop: ctx.alloc_field(FieldDesc::new(
+            *thunk,
+            drop_fn_name,
+            Type::FnPtr(rust_drop_sig),
+        )),
+    };
+    add_ctor(thunk, &fields, rust_invoke_sig, rust_drop_sig, ctx);
+    add_invoke(
+        thunk,
+        &fields,
+        invoke_name,
+        invoke_sig,
+        rust_invoke_sig,
+        inputs.len(),
+        ctx,
+    );
+    add_finalizer(thunk, &fields, rust_drop_sig, ctx);
+    (thunk, invoke)
+}
+/// Adds a constructor, setting all the fields of the thunk.
+fn add_ctor(
+    thunk: ClassDefIdx,
+    fields: &ThunkFields,
+    rust_invoke_sig: SigIdx,
+    rust_drop_sig: SigIdx,
+    ctx: &mut MethodCompileCtx<'_, '_>,
+) {
+    use cilly::v2::{CILNode, CILRoot};
+    let void_ptr = ctx.nptr(Type::Void);
+    let this = ctx.alloc_node(CILNode::LdArg(0));
+    let mut roots = vec![];
+    let mut arg_names = vec![None];
+    for (arg, field) in [fields.env, fields.invoke, fields.drop]
+        .into_iter()
+        .enumerate()
+    {
+        let arg = u32::try_from(arg + 1).unwrap();
+        let val = ctx.alloc_node(CILNode::LdArg(arg));
+        roots.push(ctx.alloc_root(CILRoot::SetField(Box::new((field, this, val)))));
+        arg_names.push(Some(ctx.get_field(field).name()));
+    }
+    roots.push(ctx.alloc_root(CILRoot::VoidRet));
+    let ctor_sig = ctx.sig(
+        [
+            Type::ClassRef(*thunk),
+            void_ptr,
+            Type::FnPtr(rust_invoke_sig),
+            Type::FnPtr(rust_drop_sig),
+        ],
+        Type::Void,
+    );
+    let ctor = ctx.alloc_string(".ctor");
+    ctx.new_method(MethodDef::new(
+        Access::Public,
+        thunk,
+        ctor,
+        ctor_sig,
+        MethodKind::Constructor,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(roots, 0, None)],
+            locals: vec![],
+        },
+        arg_names,
+    ));
+}
+/// Adds the `Invoke` method, calling the Rust function with the closure and the arguments of the delegate.
+fn add_invoke(
+    thunk: ClassDefIdx,
+    fields: &ThunkFields,
+    name: cilly::v2::StringIdx,
+    sig: SigIdx,
+    rust_invoke_sig: SigIdx,
+    arg_count: usize,
+    ctx: &mut MethodCompileCtx<'_, '_>,
+) {
+    use cilly::v2::{CILNode, CILRoot};
+    let this = ctx.alloc_node(CILNode::LdArg(0));
+    let env = ctx.alloc_node(CILNode::LdField {
+        addr: this,
+        field: fields.env,
+    });
+    let invoke = ctx.alloc_node(CILNode::LdField {
+        addr: this,
+        field: fields.invoke,
+    });
+    let mut args = vec![env];
+    for arg in 1..=arg_count {
+        args.push(ctx.alloc_node(CILNode::LdArg(u32::try_from(arg).unwrap())));
+    }
+    let call = Box::new((invoke, rust_invoke_sig, args.into()));
+    let output = *ctx[sig].output();
+    // The thunk must stay alive until the Rust function returns. Otherwise, the GC could finalize it, and drop the
+    // closure, while it is still running.
+    let gc = ClassRef::gc(ctx);
+    let keep_alive = ctx.alloc_string("KeepAlive");
+    let keep_alive =
+        ctx[gc]
+            .clone()
+            .static_mref(&[Type::PlatformObject], Type::Void, keep_alive, ctx);
+    let keep_alive = ctx.alloc_root(CILRoot::Call(Box::new((keep_alive, [this].into()))));
+    let (roots, locals) = if output == Type::Void {
+        let call = ctx.alloc_root(CILRoot::CallI(call));
+        let ret = ctx.alloc_root(CILRoot::VoidRet);
+        (vec![call, keep_alive, ret], vec![])
+    } else {
+        let call = ctx.alloc_node(CILNode::CallI(call));
+        let call = ctx.alloc_root(CILRoot::StLoc(0, call));
+        let ret = ctx.alloc_node(CILNode::LdLoc(0));
+        let ret = ctx.alloc_root(CILRoot::Ret(ret));
+        (
+            vec![call, keep_alive, ret],
+            vec![(None, ctx.alloc_type(output))],
+        )
+    };
+    ctx.new_method(MethodDef::new(
+        Access::Public,
+        thunk,
+        name,
+        sig,
+        MethodKind::Instance,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(roots, 0, None)],
+            locals,
+        },
+        vec![None; arg_count + 1],
+    ));
+}
+/// Overrides `Finalize`, dropping the closure.
+fn add_finalizer(
+    thunk: ClassDefIdx,
+    fields: &ThunkFields,
+    rust_drop_sig: SigIdx,
+    ctx: &mut MethodCompileCtx<'_, '_>,
+) {
+    use cilly::v2::{CILNode, CILRoot};
+    let this = ctx.alloc_node(CILNode::LdArg(0));
+    let env = ctx.alloc_node(CILNode::LdField {
+        addr: this,
+        field: fields.env,
+    });
+    let drop = ctx.alloc_node(CILNode::LdField {
+        addr: this,
+        field: fields.drop,
+    });
+    let call = ctx.alloc_root(CILRoot::CallI(Box::new((
+        drop,
+        rust_drop_sig,
+        [env].into(),
+    ))));
+    let ret = ctx.alloc_root(CILRoot::VoidRet);
+    let sig = ctx.sig([Type::ClassRef(*thunk)], Type::Void);
+    let name = ctx.alloc_string("Finalize");
+    ctx.new_method(MethodDef::new(
+        Access::Public,
+        thunk,
+        name,
+        sig,
+        MethodKind::Virtual,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![call, ret], 0, None)],
+            locals: vec![],
+        },
+        vec![None],
+    ));
+}
@@ -0,0 +1,471 @@
+//! Exports Rust functions as public .NET APIs. A function whose symbol starts with [`EXPORT_PREFIX`] (set using
+//! `#[export_name = "rustc_codegen_clr_export::Namespace::Class::Method"]`) gets a static wrapper method with a managed
+//! signature, placed in the class named by its symbol. The wrapper converts .NET strings and arrays into Rust slices,
+//! calls the Rust function, and converts its result back, so other .NET code never sees fat pointers or raw memory.
+use crate::fn_ctx::MethodCompileCtx;
+use cilly::v2::{
+    cilnode::{ExtendKind, MethodKind, PtrCastRes},
+    method::LocalDef,
+    Access, BasicBlock, BinOp, CILNode, CILRoot, ClassDef, ClassRef, ClassRefIdx, FieldDesc,
+    FieldIdx, FnSig, Int, MethodDef, MethodImpl, MethodRef, MethodRefIdx, NodeIdx, RootIdx,
+    StringIdx, Type,
+};
+use rustc_middle::ty::{FloatTy, Mutability, ParamEnv, Ty, TyKind};
+use std::num::NonZeroU8;
+/// Symbols starting with this prefix are exported as .NET methods.
+pub const EXPORT_PREFIX: &str = "rustc_codegen_clr_export::";
+/// Returns the class and the method name a function with symbol `name` should be exported as, if it is exported.
+pub fn export_target(name: &str) -> Option<(String, String)> {
+    let path = name.strip_prefix(EXPORT_PREFIX)?;
+    // `stringify!` may insert spaces between path segments.
+    let path: String = path.chars().filter(|c| !c.is_whitespace()).collect();
+    let Some((class, method)) = path.rsplit_once("::") else {
+        panic!("ERROR: exported function {name} has no class. Use `{EXPORT_PREFIX}Namespace::Class::Method` as its symbol.")
+    };
+    Some((class.replace("::", "."), method.to_owned()))
+}
+/// How a value is passed between .NET and Rust.
+#[derive(Clone, Copy)]
+enum Marshal {
+    /// Passed as-is: primitives, and `()` as a return type.
+    Direct(Type),
+    /// A `&str`, visible to .NET as `System.String`.
+    Str,
+    /// A `&[T]` or `&mut [T]`, visible to .NET as `T[]`. Changes to a `&mut [T]` get copied back into the array.
+    Slice { elem: Type, mutable: bool },
+}
+impl Marshal {
+    fn of<'tcx>(ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Option<Self> {
+        match ty.kind() {
+            TyKind::Bool
+            | TyKind::Int(_)
+            | TyKind::Uint(_)
+            | TyKind::Float(FloatTy::F32 | FloatTy::F64) => {
+                Some(Self::Direct(ctx.type_from_cache(ty)))
+            }
+            TyKind::Tuple(elems) if elems.is_empty() => Some(Self::Direct(Type::Void)),
+            TyKind::Ref(_, inner, mutability) => match inner.kind() {
+                TyKind::Str if *mutability == Mutability::Not => Some(Self::Str),
+                TyKind::Slice(elem) => match Self::of(*elem, ctx)? {
+                    Self::Direct(Type::Void) => None,
+                    Self::Direct(elem) => Some(Self::Slice {
+                        elem,
+                        mutable: *mutability == Mutability::Mut,
+                    }),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    fn of_exported<'tcx>(ty: Ty<'tcx>, symbol: &str, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Self {
+        Self::of(ty, ctx).unwrap_or_else(|| {
+            panic!("ERROR: exported function {symbol} uses the type {ty:?}, which can't be passed to .NET. Only primitives, `&str`, and slices of primitives are supported.")
+        })
+    }
+    /// The type of this value on the .NET side.
+    fn managed_type(self, ctx: &mut MethodCompileCtx<'_, '_>) -> Type {
+        match self {
+            Self::Direct(tpe) => tpe,
+            Self::Str => Type::PlatformString,
+            Self::Slice { elem, .. } => Type::PlatformArray {
+                elem: ctx.alloc_type(elem),
+                dims: NonZeroU8::new(1).unwrap(),
+            },
+        }
+    }
+}
+/// The body of an export wrapper under construction.
+struct WrapperBody {
+    locals: Vec<LocalDef>,
+    roots: Vec<RootIdx>,
+    /// Roots freeing the unmanaged copies of the arguments, run after the Rust function returns.
+    cleanup: Vec<RootIdx>,
+}
+impl WrapperBody {
+    fn new_local(&mut self, tpe: Type, ctx: &mut MethodCompileCtx<'_, '_>) -> u32 {
+        let local = u32::try_from(self.locals.len()).expect("More than 2^32 locals");
+        self.locals.push((None, ctx.alloc_type(tpe)));
+        local
+    }
+    /// Stores `val` in a new local of type `tpe`, and returns that local.
+    fn store(&mut self, val: CILNode, tpe: Type, ctx: &mut MethodCompileCtx<'_, '_>) -> u32 {
+        let local = self.new_local(tpe, ctx);
+        let val = ctx.alloc_node(val);
+        self.roots.push(ctx.alloc_root(CILRoot::StLoc(local, val)));
+        local
+    }
+    /// Creates a Rust fat pointer of type `fat_tpe`, with the data pointer `data` and the length `len`.
+    fn fat_ptr(
+        &mut self,
+        fat_tpe: Type,
+        data: CILNode,
+        len: CILNode,
+        ctx: &mut MethodCompileCtx<'_, '_>,
+    ) -> CILNode {
+        let local = self.new_local(fat_tpe, ctx);
+        let (data_field, len_field) = fat_ptr_fields(fat_tpe, ctx);
+        for (field, val) in [(data_field, data), (len_field, len)] {
+            let addr = ctx.alloc_node(CILNode::LdLocA(local));
+            let val = ctx.alloc_node(val);
+            self.roots
+                .push(ctx.alloc_root(CILRoot::SetField(Box::new((field, addr, val)))));
+        }
+        CILNode::LdLoc(local)
+    }
+}
+/// Returns the data pointer and the metadata field of a fat pointer type.
+fn fat_ptr_fields(fat_tpe: Type, ctx: &mut MethodCompileCtx<'_, '_>) -> (FieldIdx, FieldIdx) {
+    let Type::ClassRef(fat_ptr) = fat_tpe else {
+        panic!("ERROR: {fat_tpe:?} is not a fat pointer")
+    };
+    let data_name = ctx.alloc_string(crate::DATA_PTR);
+    let void_ptr = ctx.nptr(Type::Void);
+    let data_field = ctx.alloc_field(FieldDesc::new(fat_ptr, data_name, void_ptr));
+    let len_name = ctx.alloc_string(crate::METADATA);
+    let len_field = ctx.alloc_field(FieldDesc::new(fat_ptr, len_name, Type::Int(Int::USize)));
+    (data_field, len_field)
+}
+/// Calls a static method of `class`.
+fn call_static(
+    class: ClassRefIdx,
+    name: &str,
+    inputs: &[Type],
+    output: Type,
+    args: &[CILNode],
+    ctx: &mut MethodCompileCtx<'_, '_>,
+) -> CILNode {
+    let name = ctx.alloc_string(name);
+    let mref = ctx[class].clone().static_mref(inputs, output, name, ctx);
+    call(mref, args, ctx)
+}
+fn call(mref: MethodRefIdx, args: &[CILNode], ctx: &mut MethodCompileCtx<'_, '_>) -> CILNode {
+    let args: Box<[NodeIdx]> = args.iter().map(|arg| ctx.alloc_node(arg.clone())).collect();
+    CILNode::Call(Box::new((mref, args)))
+}
+/// Returns a `ref byte` pointing to the first element of `arr`.
+fn array_data(arr: CILNode, ctx: &mut MethodCompileCtx<'_, '_>) -> CILNode {
+    let array = ClassRef::array(ctx);
+    let array_tpe = ctx.alloc_type(Type::ClassRef(array));
+    let arr = ctx.alloc_node(arr);
+    let byte_ref = ctx.nref(Type::Int(Int::U8));
+    call_static(
+        ClassRef::memory_marshal(ctx),
+        "GetArrayDataReference",
+        &[Type::ClassRef(array)],
+        byte_ref,
+        &[CILNode::CheckedCast(arr, array_tpe)],
+        ctx,
+    )
+}
+/// Returns the length of an array, as an `usize`.
+fn array_len(arr: CILNode, ctx: &mut MethodCompileCtx<'_, '_>) -> CILNode {
+    let arr = ctx.alloc_node(arr);
+    let len = ctx.alloc_node(CILNode::LdLen(arr));
+    ctx.int_cast(len, Int::USize, ExtendKind::ZeroExtend)
+}
+/// Calculates the size of `len` elements of type `elem`.
+fn byte_len(len: CILNode, elem: Type, ctx: &mut MethodCompileCtx<'_, '_>) -> CILNode {
+    let elem_size = ctx.size_of(elem);
+    let elem_size = ctx.int_cast(elem_size, Int::USize, ExtendKind::ZeroExtend);
+    ctx.biop(len, elem_size, BinOp::Mul)
+}
+/// Converts the managed argument `arg` into its Rust representation of type `rust_tpe`.
+fn marshal_arg(
+    arg: u32,
+    marshal: Marshal,
+    rust_tpe: Type,
+    body: &mut WrapperBody,
+    ctx: &mut MethodCompileCtx<'_, '_>,
+) -> CILNode {
+    match marshal {
+        Marshal::Direct(_) => CILNode::LdArg(arg),
+        Marshal::Str => {
+            // The string gets converted to an unmanaged, UTF-8 copy, freed after the call.
+            let marshal_class = ClassRef::marshal(ctx);
+            let utf8 = call_static(
+                marshal_class,
+                "StringToCoTaskMemUTF8",
+                &[Type::PlatformString],
+                Type::Int(Int::ISize),
+                &[CILNode::LdArg(arg)],
+                ctx,
+            );
+            let ptr = body.store(utf8, Type::Int(Int::ISize), ctx);
+            let encoding = ClassRef::encoding(ctx);
+            let get_utf8 = call_static(
+                encoding,
+                "get_UTF8",
+                &[],
+                Type::ClassRef(encoding),
+                &[],
+                ctx,
+            );
+            let get_byte_count = ctx.alloc_string("GetByteCount");
+            let get_byte_count = ctx[encoding].clone().virtual_mref(
+                &[Type::PlatformString],
+                Type::Int(Int::I32),
+                get_byte_count,
+                ctx,
+            );
+            let len = call(get_byte_count, &[get_utf8, CILNode::LdArg(arg)], ctx);
+            let len = ctx.int_cast(len, Int::USize, ExtendKind::ZeroExtend);
+            let void = ctx.alloc_type(Type::Void);
+            let data = ctx.ptr_cast(CILNode::LdLoc(ptr), PtrCastRes::Ptr(void));
+            let free = call_static(
+                marshal_class,
+                "FreeCoTaskMem",
+                &[Type::Int(Int::ISize)],
+                Type::Void,
+                &[CILNode::LdLoc(ptr)],
+                ctx,
+            );
+            let CILNode::Call(free) = free else {
+                unreachable!()
+            };
+            body.cleanup.push(ctx.alloc_root(CILRoot::Call(free)));
+            body.fat_ptr(rust_tpe, data, len, ctx)
+        }
+        Marshal::Slice { elem, mutable } => {
+            // The array gets copied into unmanaged memory, since the GC may move it during the call.
+            let len = array_len(CILNode::LdArg(arg), ctx);
+            let bytes = byte_len(len.clone(), elem, ctx);
+            let bytes = body.store(bytes, Type::Int(Int::USize), ctx);
+            let native_mem = ClassRef::native_mem(ctx);
+            let void_ptr = ctx.nptr(Type::Void);
+            let alloc = call_static(
+                native_mem,
+                "Alloc",
+                &[Type::Int(Int::USize)],
+                void_ptr,
+                &[CILNode::LdLoc(bytes)],
+                ctx,
+            );
+            let ptr = body.store(alloc, void_ptr, ctx);
+            let src = array_data(CILNode::LdArg(arg), ctx);
+            let copy = CILRoot::CpBlk(Box::new((
+                ctx.alloc_node(CILNode::LdLoc(ptr)),
+                ctx.alloc_node(src),
+                ctx.alloc_node(CILNode::LdLoc(bytes)),
+            )));
+            body.roots.push(ctx.alloc_root(copy));
+            if mutable {
+                let dst = array_data(CILNode::LdArg(arg), ctx);
+                let copy_back = CILRoot::CpBlk(Box::new((
+                    ctx.alloc_node(dst),
+                    ctx.alloc_node(CILNode::LdLoc(ptr)),
+                    ctx.alloc_node(CILNode::LdLoc(bytes)),
+                )));
+                body.cleanup.push(ctx.alloc_root(copy_back));
+            }
+            let free = call_static(
+                native_mem,
+                "Free",
+                &[void_ptr],
+                Type::Void,
+                &[CILNode::LdLoc(ptr)],
+                ctx,
+            );
+            let CILNode::Call(free) = free else {
+                unreachable!()
+            };
+            body.cleanup.push(ctx.alloc_root(CILRoot::Call(free)));
+            body.fat_ptr(rust_tpe, CILNode::LdLoc(ptr), len, ctx)
+        }
+    }
+}
+/// Converts the Rust return value stored in local `ret` into its managed representation.
+fn marshal_ret(
+    ret: u32,
+    marshal: Marshal,
+    rust_tpe: Type,
+    body: &mut WrapperBody,
+    ctx: &mut MethodCompileCtx<'_, '_>,
+) -> CILNode {
+    match marshal {
+        Marshal::Direct(_) => CILNode::LdLoc(ret),
+        Marshal::Str => {
+            let (data_field, len_field) = fat_ptr_fields(rust_tpe, ctx);
+            let addr = ctx.alloc_node(CILNode::LdLocA(ret));
+            let data = ctx.alloc_node(CILNode::LdField {
+                addr,
+                field: data_field,
+            });
+            let data = ctx.ptr_cast(data, PtrCastRes::ISize);
+            let len = ctx.alloc_node(CILNode::LdField {
+                addr,
+                field: len_field,
+            });
+            let len = ctx.int_cast(len, Int::I32, ExtendKind::ZeroExtend);
+            call_static(
+                ClassRef::marshal(ctx),
+                "PtrToStringUTF8",
+                &[Type::Int(Int::ISize), Type::Int(Int::I32)],
+                Type::PlatformString,
+                &[data, len],
+                ctx,
+            )
+        }
+        Marshal::Slice { elem, .. } => {
+            let (data_field, len_field) = fat_ptr_fields(rust_tpe, ctx);
+            let addr = ctx.alloc_node(CILNode::LdLocA(ret));
+            let len = CILNode::LdField {
+                addr,
+                field: len_field,
+            };
+            let len_i32 = ctx.int_cast(len.clone(), Int::I32, ExtendKind::ZeroExtend);
+            // Array.CreateInstance(Type.GetTypeFromHandle(elem), len)
+            let elem_idx = ctx.alloc_type(elem);
+            let type_type = ClassRef::type_type(ctx);
+            let handle = ClassRef::runtime_type_hadle(ctx);
+            let elem_type = call_static(
+                type_type,
+                "GetTypeFromHandle",
+                &[Type::ClassRef(handle)],
+                Type::ClassRef(type_type),
+                &[CILNode::LdTypeToken(elem_idx)],
+                ctx,
+            );
+            let array = ClassRef::array(ctx);
+            let new_arr = call_static(
+                array,
+                "CreateInstance",
+                &[Type::ClassRef(type_type), Type::Int(Int::I32)],
+                Type::ClassRef(array),
+                &[elem_type, len_i32],
+                ctx,
+            );
+            let arr_tpe = marshal.managed_type(ctx);
+            let new_arr = ctx.alloc_node(new_arr);
+            let arr_tpe_idx = ctx.alloc_type(arr_tpe);
+            let arr = body.store(CILNode::CheckedCast(new_arr, arr_tpe_idx), arr_tpe, ctx);
+            let dst = array_data(CILNode::LdLoc(arr), ctx);
+            let src = CILNode::LdField {
+                addr,
+                field: data_field,
+            };
+            let bytes = byte_len(len, elem, ctx);
+            let copy = CILRoot::CpBlk(Box::new((
+                ctx.alloc_node(dst),
+                ctx.alloc_node(src),
+                ctx.alloc_node(bytes),
+            )));
+            body.roots.push(ctx.alloc_root(copy));
+            CILNode::LdLoc(arr)
+        }
+    }
+}
+/// Adds a public, static .NET method `method` to `class`, which calls the Rust function `symbol` with the signature
+/// `sig`, converting its arguments and return value to and from their managed representation.
+pub fn export_fn<'tcx>(
+    class: &str,
+    method: &str,
+    symbol: &str,
+    sig: &FnSig,
+    arg_names: &[Option<StringIdx>],
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) {
+    let fn_ty = ctx.instance().ty(ctx.tcx(), ParamEnv::reveal_all());
+    let rust_sig = ctx
+        .tcx()
+        .normalize_erasing_late_bound_regions(ParamEnv::reveal_all(), fn_ty.fn_sig(ctx.tcx()));
+    assert_eq!(
+        rust_sig.inputs().len(),
+        sig.inputs().len(),
+        "ERROR: exported function {symbol} can't have hidden arguments (e.g. `#[track_caller]`)"
+    );
+    let inputs: Vec<Marshal> = rust_sig
+        .inputs()
+        .iter()
+        .map(|ty| Marshal::of_exported(*ty, symbol, ctx))
+        .collect();
+    let output = Marshal::of_exported(rust_sig.output(), symbol, ctx);
+    let mut body = WrapperBody {
+        locals: vec![],
+        roots: vec![],
+        cleanup: vec![],
+    };
+    // Convert the arguments, and call the Rust function.
+    let args: Vec<CILNode> = inputs
+        .iter()
+        .zip(sig.inputs())
+        .enumerate()
+        .map(|(arg, (marshal, rust_tpe))| {
+            marshal_arg(
+                u32::try_from(arg).unwrap(),
+                *marshal,
+                *rust_tpe,
+                &mut body,
+                ctx,
+            )
+        })
+        .collect();
+    let main_module = ctx.main_module();
+    let rust_fn_name = ctx.alloc_string(symbol);
+    let rust_sig_idx = ctx.alloc_sig(sig.clone());
+    let rust_fn = ctx.alloc_methodref(MethodRef::new(
+        *main_module,
+        rust_fn_name,
+        rust_sig_idx,
+        MethodKind::Static,
+        vec![].into(),
+    ));
+    let rust_call = call(rust_fn, &args, ctx);
+    let managed_output = output.managed_type(ctx);
+    let ret = if managed_output == Type::Void {
+        let CILNode::Call(rust_call) = rust_call else {
+            unreachable!()
+        };
+        body.roots.push(ctx.alloc_root(CILRoot::Call(rust_call)));
+        None
+    } else {
+        let rust_ret = body.store(rust_call, *sig.output(), ctx);
+        // The return value must be converted before the arguments are freed, since it may borrow from them.
+        let ret = marshal_ret(rust_ret, output, *sig.output(), &mut body, ctx);
+        Some(body.store(ret, managed_output, ctx))
+    };
+    body.roots.append(&mut body.cleanup);
+    let ret = match ret {
+        Some(ret) => CILRoot::Ret(ctx.alloc_node(CILNode::LdLoc(ret))),
+        None => CILRoot::VoidRet,
+    };
+    body.roots.push(ctx.alloc_root(ret));
+    // Add the wrapper to the export class.
+    let class_name = ctx.alloc_string(class);
+    let class_ref = ctx.alloc_class_ref(ClassRef::new(class_name, None, false, [].into()));
+    let class = match ctx.class_ref_to_def(class_ref) {
+        Some(class) => class,
+        None => ctx.class_def(ClassDef::new(
+            class_name,
+            false,
+            0,
+            None,
+            vec![],
+            vec![],
+            Access::Extern,
+            None,
+            None,
+        )),
+    };
+    let managed_inputs: Vec<Type> = inputs
+        .into_iter()
+        .map(|marshal| marshal.managed_type(ctx))
+        .collect();
+    let managed_sig = ctx.sig(managed_inputs, managed_output);
+    let method_name = ctx.alloc_string(method);
+    let mut arg_names = arg_names.to_vec();
+    arg_names.resize(sig.inputs().len(), None);
+    ctx.new_method(MethodDef::new(
+        Access::Extern,
+        class,
+        method_name,
+        managed_sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(body.roots, 0, None)],
+            locals: body.locals,
+        },
+        arg_names,
+    ));
+}
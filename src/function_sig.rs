@@ -1,9 +1,16 @@
+use crate::call_info::CallInfo;
 use crate::codegen_error::CodegenError;
 use crate::fn_ctx::MethodCompileCtx;
 use crate::r#type::get_type;
-use cilly::{v2::FnSig, Type};
-use rustc_middle::ty::{Instance, List, ParamEnv, ParamEnvAnd, Ty, TyCtxt, TyKind};
-use rustc_target::abi::call::Conv;
+use cilly::{
+    v2::{CallConv, FnSig},
+    Type,
+};
+use rustc_middle::{
+    mir::Operand,
+    ty::{FnHeader, Instance, TyCtxt},
+};
+use rustc_span::Spanned;
 use rustc_target::spec::abi::Abi as TargetAbi;
 
 /// Creates a `FnSig` from ` `. May not match the result of `sig_from_instance_`!
@@ -11,6 +18,7 @@ use rustc_target::spec::abi::Abi as TargetAbi;
 pub fn from_poly_sig<'tcx>(
     ctx: &mut MethodCompileCtx<'tcx, '_>,
     sig: rustc_middle::ty::FnSigTys<TyCtxt<'tcx>>,
+    hdr: FnHeader<TyCtxt<'tcx>>,
 ) -> FnSig {
     let output = get_type(ctx.monomorphize(sig.output()), ctx);
     let inputs: Box<[Type]> = sig
@@ -18,59 +26,53 @@ pub fn from_poly_sig<'tcx>(
         .iter()
         .map(|input| get_type(ctx.monomorphize(*input), ctx))
         .collect();
-    FnSig::new(inputs, output)
+    let fixed = inputs.len();
+    let sig = FnSig::new(inputs, output).with_conv(call_conv(hdr.abi));
+    if hdr.c_variadic {
+        sig.with_variadic(fixed)
+    } else {
+        sig
+    }
 }
 /// Returns the signature of function behind `function`.
 pub fn sig_from_instance_<'tcx>(
     function: Instance<'tcx>,
     ctx: &mut MethodCompileCtx<'tcx, '_>,
 ) -> Result<FnSig, CodegenError> {
-    let fn_abi = ctx.tcx().fn_abi_of_instance(ParamEnvAnd {
-        param_env: ParamEnv::reveal_all(),
-        value: (function, List::empty()),
-    });
-    let fn_abi = match fn_abi {
-        Ok(abi) => abi,
-        Err(_error) => todo!(),
-    };
-    let conv = fn_abi.conv;
-    match conv {
-        Conv::Rust | Conv::C => (),
-        _ => panic!("ERROR:calling using convention {conv:?} is not supported!"),
-    }
-    //assert!(!fn_abi.c_variadic);
-    let ret = ctx.monomorphize(fn_abi.ret.layout.ty);
-    let ret = get_type(ret, ctx);
-    let mut args = Vec::with_capacity(fn_abi.args.len());
-    for arg in &fn_abi.args {
-        let arg = ctx.monomorphize(arg.layout.ty);
-        args.push(get_type(arg, ctx));
-    }
-    // There are 2 ABI enums for some reasons(they differ in what memebers they have)
-    let fn_ty = function.ty(ctx.tcx(), ParamEnv::reveal_all());
-    let internal_abi = match fn_ty.kind() {
-        TyKind::FnDef(_, _) => fn_ty.fn_sig(ctx.tcx()),
-        TyKind::Closure(_, args) => args.as_closure().sig(),
-        _ => todo!("Can't get signature of {fn_ty}"),
-    }
-    .abi();
-    // Only those ABIs are supported
-    match internal_abi {
-        TargetAbi::C { unwind: _ }
-        | TargetAbi::Cdecl { unwind: _ }
-        | TargetAbi::RustIntrinsic
-        | TargetAbi::Rust
+    Ok(CallInfo::sig_from_instance_(function, ctx).sig().clone())
+}
+/// Returns the calling convention used by functions with the ABI `abi`.
+#[must_use]
+pub fn call_conv(abi: TargetAbi) -> CallConv {
+    match abi {
+        TargetAbi::Rust
+        | TargetAbi::RustCall
         | TargetAbi::RustCold
-        | TargetAbi::RustCall => (), /*Err(CodegenError::FunctionABIUnsuported(
-        "\"rust_call\" ABI, used for things like clsoures, is not supported yet!",
-        ))?,*/
-        _ => todo!("Unsuported ABI:{internal_abi:?}"),
+        | TargetAbi::RustIntrinsic
+        | TargetAbi::Unadjusted => CallConv::Rust,
+        TargetAbi::C { unwind: _ } | TargetAbi::Cdecl { unwind: _ } => CallConv::C,
+        TargetAbi::System { unwind: _ } => CallConv::System,
+        TargetAbi::Stdcall { unwind: _ } => CallConv::StdCall,
+        TargetAbi::Fastcall { unwind: _ } => CallConv::FastCall,
+        TargetAbi::Thiscall { unwind: _ } => CallConv::ThisCall,
+        TargetAbi::Win64 { unwind: _ } => CallConv::Win64,
+        TargetAbi::SysV64 { unwind: _ } => CallConv::SysV64,
+        _ => panic!("ERROR: calling using the ABI {abi:?} is not supported!"),
     }
-    Ok(FnSig::new(args.into(), ret))
 }
-
-/// Checks if this function is variadic.
-#[must_use]
-pub fn is_fn_variadic<'tcx>(ty: Ty<'tcx>, tcx: TyCtxt<'tcx>) -> bool {
-    ty.fn_sig(tcx).skip_binder().c_variadic
+/// Sets the inputs of a call site of a C-variadic function with signature `sig` to the types of `args`,
+/// since the variable arguments are passed as-is.
+pub fn variadic_call_site<'tcx>(
+    sig: &mut FnSig,
+    args: &[Spanned<Operand<'tcx>>],
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) {
+    debug_assert!(sig.is_variadic());
+    sig.set_inputs(
+        args.iter()
+            .map(|operand| {
+                ctx.type_from_cache(ctx.monomorphize(operand.node.ty(ctx.body(), ctx.tcx())))
+            })
+            .collect(),
+    );
 }
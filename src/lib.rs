@@ -128,6 +128,10 @@ mod constant;
 
 /// Implementation of compiletime features neccessary for interop.
 mod comptime;
+/// Creates managed delegates calling Rust closures.
+mod delegate;
+/// Exports Rust functions as public .NET methods with managed signatures.
+mod export;
 /// Signature of a function (inputs)->output
 pub mod function_sig;
 /// Interop type handling.
@@ -214,6 +218,7 @@ impl CodegenBackend for MyBackend {
                     assembly::add_item(&mut asm, *item, tcx).expect("Could not add function");
                 }
             }
+            codegen_error::report_unsupported(tcx);
 
             if let Some((entrypoint, _kind)) = tcx.entry_fn(()) {
                 let penv = rustc_middle::ty::ParamEnv::reveal_all();
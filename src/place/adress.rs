@@ -2,6 +2,7 @@ use super::PlaceTy;
 use crate::{
     assembly::MethodCompileCtx,
     assert_morphic,
+    codegen_error::CodegenError,
     r#type::{fat_ptr_to, pointer_to_is_fat},
 };
 use cilly::{
@@ -236,7 +237,7 @@ pub fn place_elem_adress<'tcx>(
                     call!(ctx.alloc_methodref(mref), [addr_calc, CILNode::V2(index)])
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! {todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! {CodegenError::CantIndexInto(curr_ty.to_string().into()).raise()}
                 }
             }
         }
@@ -325,7 +326,7 @@ pub fn place_elem_adress<'tcx>(
                         vec![].into(),
                     );
                     if *from_end {
-                        todo!("Can't index array from end!");
+                        CodegenError::IndexFromEnd.raise();
                     } else {
                         call!(
                             ctx.alloc_methodref(mref),
@@ -337,12 +338,12 @@ pub fn place_elem_adress<'tcx>(
                     }
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! { todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! { CodegenError::CantIndexInto(curr_ty.to_string().into()).raise()}
                 }
             }
         }
         _ => {
-            rustc_middle::ty::print::with_no_trimmed_paths! {todo!("Can't handle porojection {place_elem:?} in adress")}
+            rustc_middle::ty::print::with_no_trimmed_paths! {CodegenError::UnsupportedProjection {projection: format!("{place_elem:?}").into(), context: "adress"}.raise()}
         }
     }
 }
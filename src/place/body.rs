@@ -3,6 +3,7 @@ use super::{pointed_type, PlaceTy};
 use crate::{
     assembly::MethodCompileCtx,
     assert_morphic,
+    codegen_error::CodegenError,
     place::{body_ty_is_by_adress, deref_op},
     r#type::fat_ptr_to,
 };
@@ -200,7 +201,7 @@ pub fn place_elem_body_index<'tcx>(
             }
         }
         _ => {
-            rustc_middle::ty::print::with_no_trimmed_paths! {todo!("Can't index into {curr_ty}!")}
+            rustc_middle::ty::print::with_no_trimmed_paths! {CodegenError::CantIndexInto(curr_ty.to_string().into()).raise()}
         }
     }
 }
@@ -237,10 +238,7 @@ pub fn place_elem_body<'tcx>(
                 .as_ty()
                 .expect("Can't get enum variant of an enum varaint!");
             let curr_type = ctx.monomorphize(curr_type);
-            if matches!(curr_ty.as_ty().unwrap().kind(), TyKind::Coroutine(_, _)) {
-                eprintln!("UNTESTED:  downcaststing coroutines is not fully supported, and the behaviour of corrutines is not yet fully tested! variant:{variant:?} curr_type:{curr_type:?}");
-                return (curr_type.into(), parrent_node);
-            }
+            // Coroutine states are laid out like enum variants, so they are handled the same way.
             let variant_type = PlaceTy::EnumVariant(curr_type, variant.as_u32());
 
             (variant_type, parrent_node)
@@ -322,10 +320,14 @@ pub fn place_elem_body<'tcx>(
                     }
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! { todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! { CodegenError::CantIndexInto(curr_ty.to_string().into()).raise()}
                 }
             }
         }
-        _ => todo!("Can't handle porojection {place_elem:?} in body"),
+        _ => CodegenError::UnsupportedProjection {
+            projection: format!("{place_elem:?}").into(),
+            context: "body",
+        }
+        .raise(),
     }
 }
@@ -1,4 +1,4 @@
-use crate::{assembly::MethodCompileCtx, r#type::fat_ptr_to};
+use crate::{assembly::MethodCompileCtx, codegen_error::CodegenError, r#type::fat_ptr_to};
 use cilly::{
     asm::Assembly,
     call,
@@ -178,7 +178,7 @@ fn place_elem_get<'a>(
                     call!(ctx.alloc_methodref(mref), [addr_calc, CILNode::V2(index)])
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! {todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! {CodegenError::CantIndexInto(curr_ty.to_string().into()).raise()}
                 }
             }
         }
@@ -233,7 +233,7 @@ fn place_elem_get<'a>(
                     //eprintln!("WARNING: ConstantIndex has required min_length of {min_length}, but bounds checking on const access not supported yet!");
                     let arr_ref = ctx.nref(array_type);
                     if *from_end {
-                        todo!("Can't index array from end!");
+                        CodegenError::IndexFromEnd.raise();
                     } else {
                         let index = CILNode::V2(ctx.alloc_node(cilly::Const::USize(*offset)));
                         let mref = MethodRef::new(
@@ -250,7 +250,7 @@ fn place_elem_get<'a>(
                     }
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! { todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! { CodegenError::CantIndexInto(curr_ty.to_string().into()).raise()}
                 }
             }
         }
@@ -261,6 +261,10 @@ fn place_elem_get<'a>(
                 addr_calc
             }
         }
-        _ => todo!("Can't handle porojection {place_elem:?} in get"),
+        _ => CodegenError::UnsupportedProjection {
+            projection: format!("{place_elem:?}").into(),
+            context: "get",
+        }
+        .raise(),
     }
 }
@@ -1,6 +1,7 @@
 // FIXME: This file may contain unnecesary morphize calls.
 
 use crate::assembly::MethodCompileCtx;
+use crate::codegen_error::CodegenError;
 use crate::r#type::pointer_to_is_fat;
 use cilly::cil_node::CILNode;
 use cilly::cil_root::CILRoot;
@@ -33,7 +34,7 @@ fn pointed_type(ty: PlaceTy) -> Ty {
             panic!("{ty:?} is not a pointer type!");
         }
     } else {
-        panic!("Can't dereference enum variant!");
+        CodegenError::DerefEnumVariant.raise();
     }
 }
 fn body_ty_is_by_adress<'tcx>(last_ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> bool {
@@ -54,10 +55,7 @@ fn body_ty_is_by_adress<'tcx>(last_ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx
         TyKind::Ref(_, ty, _) | TyKind::RawPtr(ty, _) => {
             pointer_to_is_fat(ty, ctx.tcx(), ctx.instance())
         }
-        _ => todo!(
-            "TODO: body_ty_is_by_adress does not support type {last_ty:?} kind:{kind:?}",
-            kind = last_ty.kind()
-        ),
+        _ => CodegenError::CantDeref(format!("{last_ty:?}").into()).raise(),
     }
 }
 
@@ -150,10 +148,10 @@ pub fn deref_op<'tcx>(
                 }
             }
 
-            _ => todo!("TODO: can't deref type {derefed_type:?} yet"),
+            _ => CodegenError::CantDeref(format!("{derefed_type:?}").into()).raise(),
         }
     } else {
-        todo!("Can't dereference enum variants yet!")
+        CodegenError::DerefEnumVariant.raise()
     };
     res
 }
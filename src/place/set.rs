@@ -1,5 +1,6 @@
 use crate::{
     assembly::MethodCompileCtx,
+    codegen_error::CodegenError,
     place::{pointed_type, PlaceTy},
     r#type::{fat_ptr_to, pointer_to_is_fat},
 };
@@ -121,7 +122,7 @@ pub fn place_elem_set<'a>(
                     }
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! { todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! { CodegenError::CantIndexInto(curr_ty.to_string().into()).raise()}
                 }
             }
         }
@@ -194,7 +195,7 @@ pub fn place_elem_set<'a>(
                     }
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! { todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! { CodegenError::CantIndexInto(curr_ty.to_string().into()).raise()}
                 }
             }
         }
@@ -219,7 +220,11 @@ pub fn place_elem_set<'a>(
             ));
             ops
         }*/
-        _ => todo!("Can't handle porojection {place_elem:?} in set"),
+        _ => CodegenError::UnsupportedProjection {
+            projection: format!("{place_elem:?}").into(),
+            context: "set",
+        }
+        .raise(),
     }
 }
 /// Returns a set of instructons to set a pointer to a `pointed_type` to a value from the stack.
@@ -308,9 +313,9 @@ pub fn ptr_set_op<'tcx>(
                     CILRoot::STIndPtr(addr_calc, value_calc, Box::new(inner))
                 }
             }
-            _ => todo!(" can't deref type {pointed_type:?} yet"),
+            _ => CodegenError::CantDeref(format!("{pointed_type:?}").into()).raise(),
         }
     } else {
-        todo!("Can't set the value behind a poitner to an enum variant!");
+        CodegenError::DerefEnumVariant.raise();
     }
 }
@@ -6,6 +6,7 @@ use crate::{
     r#type::{fat_ptr_to, get_type, pointer_to_is_fat},
 };
 use cilly::{
+    call,
     cil_node::CILNode,
     cil_root::CILRoot,
     conv_usize, ld_field, size_of,
@@ -244,10 +245,25 @@ pub fn handle_rvalue<'tcx>(
         Rvalue::Cast(CastKind::FloatToFloat, operand, target) => {
             let target = ctx.monomorphize(*target);
             let target = ctx.type_from_cache(target);
+            let src = operand.ty(&ctx.body().local_decls, ctx.tcx());
+            let src = ctx.monomorphize(src);
+            let src = ctx.type_from_cache(src);
             let mut ops = handle_operand(operand, ctx);
-            match target {
-                Type::Float(Float::F32) => ops = CILNode::ConvF32(ops.into()),
-                Type::Float(Float::F64) => ops = CILNode::ConvF64(ops.into()),
+            match (src, target) {
+                (Type::Float(src), Type::Float(target))
+                    if src != target && (src == Float::F128 || target == Float::F128) =>
+                {
+                    let mref = MethodRef::new(
+                        *ctx.main_module(),
+                        ctx.alloc_string(format!("cast_{}_{}", src.name(), target.name())),
+                        ctx.sig([Type::Float(src)], Type::Float(target)),
+                        MethodKind::Static,
+                        vec![].into(),
+                    );
+                    ops = call!(ctx.alloc_methodref(mref), [ops]);
+                }
+                (_, Type::Float(Float::F32)) => ops = CILNode::ConvF32(ops.into()),
+                (_, Type::Float(Float::F64)) => ops = CILNode::ConvF64(ops.into()),
                 _ => panic!("Can't preform a FloatToFloat cast to type {target:?}"),
             }
             (vec![], ops)
@@ -365,12 +381,26 @@ pub fn handle_rvalue<'tcx>(
         Rvalue::Repeat(operand, times) => repeat(rvalue, ctx, operand, *times, target_location),
         Rvalue::ThreadLocalRef(def_id) => {
             if !def_id.is_local() && ctx.tcx().needs_thread_local_shim(*def_id) {
-                let _instance = Instance {
+                // The thread local is defined in another crate, so its address must be obtained by calling its shim.
+                let instance = Instance {
                     def: InstanceKind::ThreadLocalShim(*def_id),
                     args: GenericArgs::empty(),
                 };
-                // Call instance
-                todo!("Thread locals with shims unsupported!")
+                let call_info = CallInfo::sig_from_instance_(instance, ctx);
+                let function_name = crate::utilis::function_name(ctx.tcx().symbol_name(instance));
+                let mref = MethodRef::new(
+                    *ctx.main_module(),
+                    ctx.alloc_string(function_name),
+                    ctx.alloc_sig(call_info.sig().clone()),
+                    MethodKind::Static,
+                    vec![].into(),
+                );
+                let rvalue_ty = rvalue.ty(ctx.body(), ctx.tcx());
+                let rvalue_type = ctx.type_from_cache(rvalue_ty);
+                (
+                    vec![],
+                    call!(ctx.alloc_methodref(mref), []).cast_ptr(rvalue_type),
+                )
             } else {
                 let alloc_id = ctx.tcx().reserve_and_set_static_alloc(*def_id);
                 let rvalue_ty = rvalue.ty(ctx.body(), ctx.tcx());
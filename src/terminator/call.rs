@@ -4,9 +4,11 @@ use crate::{
     interop::AssemblyRef,
     operand::operand_address,
     utilis::{
-        garg_to_string, CTOR_FN_NAME, MANAGED_CALL_FN_NAME, MANAGED_CALL_VIRT_FN_NAME,
-        MANAGED_CHECKED_CAST, MANAGED_IS_INST, MANAGED_LD_ELEM_REF, MANAGED_LD_LEN,
-        MANAGED_LD_NULL,
+        garg_to_string, CTOR_FN_NAME, CTOR_GENERIC_FN_NAME, CTOR_TUPLE_FN_NAME,
+        MANAGED_CALL_FN_NAME, MANAGED_CALL_GENERIC_FN_NAME, MANAGED_CALL_TUPLE_FN_NAME,
+        MANAGED_CALL_VIRT_FN_NAME, MANAGED_CALL_VIRT_TUPLE_FN_NAME, MANAGED_CHECKED_CAST,
+        MANAGED_DELEGATE, MANAGED_IS_INST, MANAGED_LD_ELEM_REF, MANAGED_LD_FLD, MANAGED_LD_LEN,
+        MANAGED_LD_NULL, MANAGED_LD_SFLD, MANAGED_ST_FLD, MANAGED_ST_SFLD,
     },
 };
 use cilly::{
@@ -14,7 +16,7 @@ use cilly::{
     cil_node::{CILNode, CallOpArgs},
     cil_root::CILRoot,
     conv_usize, ld_field,
-    v2::{cilnode::MethodKind, ClassRef, FieldDesc, FnSig, Int},
+    v2::{cilnode::MethodKind, ClassRef, FieldDesc, FnSig, Int, StaticFieldDesc},
     IntoAsmIndex,
 };
 use cilly::{v2::MethodRef, Type};
@@ -56,7 +58,7 @@ fn call_managed<'tcx>(
         .expect("Can't get the function signature");
 
     if argument_count == 0 {
-        let ret = cilly::Type::Void;
+        let ret = *signature.output();
         let call_site = MethodRef::new(
             ctx.alloc_class_ref(tpe),
             ctx.alloc_string(managed_fn_name),
@@ -130,7 +132,7 @@ fn callvirt_managed<'tcx>(
     let signature = crate::function_sig::sig_from_instance_(fn_instance, ctx)
         .expect("Can't get the function signature");
     if argument_count == 0 {
-        let ret = cilly::Type::Void;
+        let ret = *signature.output();
         let call = MethodRef::new(
             ctx.alloc_class_ref(tpe),
             ctx.alloc_string(managed_fn_name),
@@ -253,6 +255,355 @@ fn call_ctor<'tcx>(
         )
     }
 }
+/// Unpacks the argument tuple of a tuple-based managed call, returning the types of the managed parameters, and the
+/// arguments. Rust references are passed as managed references(`T&`), so they can be used for `out`, `ref` and `in`
+/// parameters. Raw pointers are passed as unmanaged pointers.
+fn managed_tuple_args<'tcx>(
+    tuple: &Operand<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> (Vec<Type>, Vec<CILNode>) {
+    let tuple_ty = ctx.monomorphize(tuple.ty(ctx.body(), ctx.tcx()));
+    let TyKind::Tuple(elements) = tuple_ty.kind() else {
+        panic!("ERROR: the arguments of a managed call must be passed as a tuple, not as {tuple_ty:?}.")
+    };
+    let mut inputs = Vec::new();
+    let mut call_args = Vec::new();
+    if elements.is_empty() {
+        return (inputs, call_args);
+    }
+    let tuple_type = ctx.type_from_cache(tuple_ty);
+    let tuple_type = tuple_type.as_class_ref().expect("Invalid tuple type");
+    for (index, element) in elements.iter().enumerate() {
+        let element_type = ctx.type_from_cache(element);
+        // Zero-sized arguments have no managed equivalent.
+        if element_type == Type::Void {
+            continue;
+        }
+        let field_descriptor = FieldDesc::new(
+            tuple_type,
+            ctx.alloc_string(format!("Item{}", index + 1)),
+            element_type,
+        );
+        let arg = ld_field!(
+            crate::operand::handle_operand(tuple, ctx),
+            ctx.alloc_field(field_descriptor)
+        );
+        match element.kind() {
+            TyKind::Ref(_, pointed, _)
+                if !crate::r#type::pointer_to_is_fat(*pointed, ctx.tcx(), ctx.instance()) =>
+            {
+                let pointed = ctx.type_from_cache(*pointed);
+                let managed_ref = ctx.nref(pointed);
+                inputs.push(managed_ref);
+                call_args.push(arg.cast_ptr(managed_ref));
+            }
+            _ => {
+                inputs.push(element_type);
+                call_args.push(arg);
+            }
+        }
+    }
+    (inputs, call_args)
+}
+/// Calls a managed method, with arguments passed as a tuple. This supports methods with any number of arguments.
+fn call_managed_tuple<'tcx>(
+    subst_ref: &[GenericArg<'tcx>],
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    is_virtual: bool,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    assert_eq!(
+        args.len(),
+        1,
+        "ERROR: a tuple-based managed call must have exactly one argument: the argument tuple."
+    );
+    let asm = AssemblyRef::decode_assembly_ref(subst_ref[0], ctx.tcx());
+    let asm = asm.name().map(|name| ctx.alloc_string(name));
+    let class_name = garg_to_string(subst_ref[1], ctx.tcx());
+    let class_name = ctx.alloc_string(class_name);
+    let is_valuetype = crate::utilis::garag_to_bool(subst_ref[2], ctx.tcx());
+    let managed_fn_name = garg_to_string(ctx.monomorphize(subst_ref[3]), ctx.tcx());
+    let is_static = crate::utilis::garag_to_bool(subst_ref[4], ctx.tcx());
+    assert!(
+        !(is_static && is_virtual),
+        "ERROR: the static method {managed_fn_name} can't be called virtually."
+    );
+    let ret = subst_ref[5]
+        .as_type()
+        .expect("ERROR: the return type of a managed call must be a type.");
+    let ret = ctx.type_from_cache(ctx.monomorphize(ret));
+    let (inputs, call_args) = managed_tuple_args(&args[0].node, ctx);
+    let tpe = ClassRef::new(class_name, asm, is_valuetype, [].into());
+    let call = MethodRef::new(
+        ctx.alloc_class_ref(tpe),
+        ctx.alloc_string(managed_fn_name),
+        ctx.sig(inputs, ret),
+        if is_static {
+            MethodKind::Static
+        } else {
+            MethodKind::Instance
+        },
+        vec![].into(),
+    );
+    let call = ctx.alloc_methodref(call);
+    match (ret == Type::Void, is_virtual) {
+        (true, false) => CILRoot::Call {
+            site: call,
+            args: call_args.into(),
+        },
+        (true, true) => CILRoot::CallVirt {
+            site: call,
+            args: call_args.into(),
+        },
+        (false, false) => crate::place::place_set(destination, call!(call, call_args), ctx),
+        (false, true) => crate::place::place_set(destination, call_virt!(call, call_args), ctx),
+    }
+}
+/// Creates a new managed object, with constructor arguments passed as a tuple, and places a reference to it in
+/// destination.
+fn call_ctor_tuple<'tcx>(
+    subst_ref: &[GenericArg<'tcx>],
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    assert_eq!(
+        args.len(),
+        1,
+        "ERROR: a tuple-based constructor call must have exactly one argument: the argument tuple."
+    );
+    let asm = AssemblyRef::decode_assembly_ref(subst_ref[0], ctx.tcx());
+    let asm = asm.name().map(|name| ctx.alloc_string(name));
+    let class_name = garg_to_string(subst_ref[1], ctx.tcx());
+    let class_name = ctx.alloc_string(class_name);
+    let is_valuetype = crate::utilis::garag_to_bool(subst_ref[2], ctx.tcx());
+    let tpe = ctx.alloc_class_ref(ClassRef::new(class_name, asm, is_valuetype, [].into()));
+    let (mut inputs, call_args) = managed_tuple_args(&args[0].node, ctx);
+    inputs.insert(0, Type::ClassRef(tpe));
+    let ctor = MethodRef::new(
+        tpe,
+        ctx.alloc_string(".ctor"),
+        ctx.sig(inputs, Type::Void),
+        MethodKind::Constructor,
+        vec![].into(),
+    );
+    crate::place::place_set(
+        destination,
+        CILNode::NewObj(Box::new(CallOpArgs {
+            site: ctx.alloc_methodref(ctor),
+            args: call_args.into(),
+        })),
+        ctx,
+    )
+}
+/// Returns the .NET type of a parameter of a managed signature. Like in tuple-based calls, Rust references are
+/// managed references.
+fn managed_param_type<'tcx>(ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Type {
+    match ty.kind() {
+        TyKind::Ref(_, pointed, _)
+            if !crate::r#type::pointer_to_is_fat(*pointed, ctx.tcx(), ctx.instance()) =>
+        {
+            let pointed = ctx.type_from_cache(*pointed);
+            ctx.nref(pointed)
+        }
+        _ => ctx.type_from_cache(ty),
+    }
+}
+/// Decodes the declared signature of a managed method, passed as a Rust function pointer type. The parameters of generic
+/// methods and classes are referred to using the generic parameter marker types.
+fn managed_declared_sig<'tcx>(
+    sig: GenericArg<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> (Vec<Type>, Type) {
+    let sig = ctx.monomorphize(
+        sig.as_type()
+            .expect("ERROR: the signature of a managed method must be a type."),
+    );
+    let TyKind::FnPtr(sig, _) = sig.kind() else {
+        panic!("ERROR: the signature of a managed method must be a function pointer type, not {sig:?}.")
+    };
+    let sig = ctx
+        .tcx()
+        .normalize_erasing_late_bound_regions(ParamEnv::reveal_all(), *sig);
+    let inputs = sig
+        .inputs()
+        .iter()
+        .map(|input| managed_param_type(*input, ctx))
+        .collect();
+    (inputs, ctx.type_from_cache(sig.output()))
+}
+/// Returns the type of `this` in the instance methods of `class`. Value types are passed by reference, so their methods
+/// must be called with a Rust reference to the object.
+fn this_type(class: Type, ctx: &mut MethodCompileCtx<'_, '_>) -> Type {
+    match class {
+        Type::ClassRef(cref) if ctx[cref].is_valuetype() => ctx.nref(class),
+        _ => class,
+    }
+}
+/// Calls a method of a managed class, which may be generic, or be declared in a generic class. The generic arguments are
+/// [class, method, is_static, is_virtual, declared signature, method generics, Ret, Args]. The arguments are passed as a
+/// tuple, like in tuple-based calls. `this` is passed by reference for value types.
+fn call_managed_generic<'tcx>(
+    subst_ref: &[GenericArg<'tcx>],
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    assert_eq!(
+        args.len(),
+        1,
+        "ERROR: a generic managed call must have exactly one argument: the argument tuple."
+    );
+    let class = subst_ref[0]
+        .as_type()
+        .expect("ERROR: the class of a managed call must be a type.");
+    let class_tpe = ctx.type_from_cache(ctx.monomorphize(class));
+    let class = class_tpe
+        .as_class_ref()
+        .expect("ERROR: generic managed calls can only call methods of managed classes.");
+    let managed_fn_name = garg_to_string(ctx.monomorphize(subst_ref[1]), ctx.tcx());
+    let is_static = crate::utilis::garag_to_bool(subst_ref[2], ctx.tcx());
+    let is_virtual = crate::utilis::garag_to_bool(subst_ref[3], ctx.tcx());
+    assert!(
+        !(is_static && is_virtual),
+        "ERROR: the static method {managed_fn_name} can't be called virtually."
+    );
+    let (mut inputs, ret) = managed_declared_sig(subst_ref[4], ctx);
+    if !is_static {
+        inputs.insert(0, this_type(class_tpe, ctx));
+    }
+    let method_generics = crate::r#type::generic_args_from_tuple(
+        subst_ref[5]
+            .as_type()
+            .expect("ERROR: the generic arguments of a managed method must be a type."),
+        ctx,
+    );
+    let (_, call_args) = managed_tuple_args(&args[0].node, ctx);
+    assert_eq!(
+        inputs.len(),
+        call_args.len(),
+        "ERROR: the managed method {managed_fn_name} was called with a wrong number of arguments."
+    );
+    let call = MethodRef::new(
+        class,
+        ctx.alloc_string(managed_fn_name),
+        ctx.sig(inputs, ret),
+        if is_static {
+            MethodKind::Static
+        } else {
+            MethodKind::Instance
+        },
+        method_generics,
+    );
+    let call = ctx.alloc_methodref(call);
+    match (ret == Type::Void, is_virtual) {
+        (true, false) => CILRoot::Call {
+            site: call,
+            args: call_args.into(),
+        },
+        (true, true) => CILRoot::CallVirt {
+            site: call,
+            args: call_args.into(),
+        },
+        (false, false) => crate::place::place_set(destination, call!(call, call_args), ctx),
+        (false, true) => crate::place::place_set(destination, call_virt!(call, call_args), ctx),
+    }
+}
+/// Creates a new object of a managed class, which may be generic. The generic arguments are [class, declared signature,
+/// Args].
+fn call_ctor_generic<'tcx>(
+    subst_ref: &[GenericArg<'tcx>],
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    assert_eq!(
+        args.len(),
+        1,
+        "ERROR: a generic constructor call must have exactly one argument: the argument tuple."
+    );
+    let class = subst_ref[0]
+        .as_type()
+        .expect("ERROR: the class of a constructor must be a type.");
+    let class_tpe = ctx.type_from_cache(ctx.monomorphize(class));
+    let class = class_tpe
+        .as_class_ref()
+        .expect("ERROR: only managed classes have constructors.");
+    let (mut inputs, _) = managed_declared_sig(subst_ref[1], ctx);
+    inputs.insert(0, this_type(class_tpe, ctx));
+    let (_, call_args) = managed_tuple_args(&args[0].node, ctx);
+    let ctor = MethodRef::new(
+        class,
+        ctx.alloc_string(".ctor"),
+        ctx.sig(inputs, Type::Void),
+        MethodKind::Constructor,
+        vec![].into(),
+    );
+    crate::place::place_set(
+        destination,
+        CILNode::NewObj(Box::new(CallOpArgs {
+            site: ctx.alloc_methodref(ctor),
+            args: call_args.into(),
+        })),
+        ctx,
+    )
+}
+/// Loads or stores a field of a managed type. The generic arguments are [asm, class, is_valuetype, field, T], and
+/// the arguments are the object(for instance fields) followed by the new value(for stores). Objects of value types are
+/// passed by reference.
+fn managed_field_access<'tcx>(
+    subst_ref: &[GenericArg<'tcx>],
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    is_static: bool,
+    is_store: bool,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    let asm = AssemblyRef::decode_assembly_ref(subst_ref[0], ctx.tcx());
+    let asm = asm.name().map(|name| ctx.alloc_string(name));
+    let class_name = garg_to_string(subst_ref[1], ctx.tcx());
+    let class_name = ctx.alloc_string(class_name);
+    let is_valuetype = crate::utilis::garag_to_bool(subst_ref[2], ctx.tcx());
+    let field_name = garg_to_string(ctx.monomorphize(subst_ref[3]), ctx.tcx());
+    let field_name = ctx.alloc_string(field_name);
+    let field_tpe = subst_ref[4]
+        .as_type()
+        .expect("ERROR: the type of a managed field must be a type.");
+    let field_tpe = ctx.type_from_cache(ctx.monomorphize(field_tpe));
+    let owner = ctx.alloc_class_ref(ClassRef::new(class_name, asm, is_valuetype, [].into()));
+    assert_eq!(
+        args.len(),
+        usize::from(!is_static) + usize::from(is_store),
+        "ERROR: wrong number of arguments passed to a managed field access."
+    );
+    if is_static {
+        let desc = Box::new(StaticFieldDesc::new(owner, field_name, field_tpe));
+        return if is_store {
+            CILRoot::SetStaticField {
+                descr: desc,
+                value: crate::operand::handle_operand(&args[0].node, ctx),
+            }
+        } else {
+            crate::place::place_set(destination, CILNode::LDStaticField(desc), ctx)
+        };
+    }
+    let mut obj = crate::operand::handle_operand(&args[0].node, ctx);
+    let obj_ty = ctx.monomorphize(args[0].node.ty(ctx.body(), ctx.tcx()));
+    if matches!(obj_ty.kind(), TyKind::Ref(..) | TyKind::RawPtr(..)) {
+        obj = obj.cast_ptr(ctx.nref(Type::ClassRef(owner)));
+    }
+    let field = ctx.alloc_field(FieldDesc::new(owner, field_name, field_tpe));
+    if is_store {
+        CILRoot::SetField {
+            addr: Box::new(obj),
+            value: Box::new(crate::operand::handle_operand(&args[1].node, ctx)),
+            desc: field,
+        }
+    } else {
+        crate::place::place_set(destination, ld_field!(obj, field), ctx)
+    }
+}
 pub fn call_closure<'tcx>(
     args: &[Spanned<Operand<'tcx>>],
     destination: &Place<'tcx>,
@@ -324,13 +675,15 @@ pub fn call_closure<'tcx>(
         crate::place::place_set(destination, call!(call, call_args), ctx)
     }
 }
-/// Calls `fn_type` with `args`, placing the return value in destination.
+/// Calls `fn_type` with `args`, placing the return value in destination. If `tail` is set, the call is emitted as a
+/// [`CILRoot::TailCall`] where possible, and `destination` is unused then.
 pub fn call<'tcx>(
     fn_type: Ty<'tcx>,
     ctx: &mut MethodCompileCtx<'tcx, '_>,
     args: &[Spanned<Operand<'tcx>>],
     destination: &Place<'tcx>,
     span: rustc_span::Span,
+    tail: bool,
 ) -> CILRoot {
     let fn_type = ctx.monomorphize(fn_type);
     let (instance, subst_ref) = if let TyKind::FnDef(def_id, subst_ref) = fn_type.kind() {
@@ -435,7 +788,11 @@ pub fn call<'tcx>(
             "sig:{signature:?} call_args:{call_args:?}"
         );
         let is_ret_void = matches!(signature.output(), cilly::Type::Void);
-        return if is_ret_void {
+        return if tail {
+            CILRoot::TailCall {
+                call: CILNode::CallI(Box::new((signature, fn_ptr, call_args.into()))),
+            }
+        } else if is_ret_void {
             CILRoot::CallI {
                 sig: Box::new(signature),
                 fn_ptr: Box::new(fn_ptr),
@@ -463,8 +820,19 @@ pub fn call<'tcx>(
         );
     }
     let mut signature = call_info.sig().clone();
-    // Checks if function is "magic"
-    if function_name.contains(CTOR_FN_NAME) {
+    // Checks if function is "magic". The tuple-based and generic calls must be checked first, since their names start
+    // with the names of the fixed-arity calls.
+    if function_name.contains(CTOR_GENERIC_FN_NAME) {
+        return call_ctor_generic(subst_ref, args, destination, ctx);
+    } else if function_name.contains(MANAGED_CALL_GENERIC_FN_NAME) {
+        return call_managed_generic(subst_ref, args, destination, ctx);
+    } else if function_name.contains(CTOR_TUPLE_FN_NAME) {
+        return call_ctor_tuple(subst_ref, args, destination, ctx);
+    } else if function_name.contains(MANAGED_CALL_VIRT_TUPLE_FN_NAME) {
+        return call_managed_tuple(subst_ref, args, destination, true, ctx);
+    } else if function_name.contains(MANAGED_CALL_TUPLE_FN_NAME) {
+        return call_managed_tuple(subst_ref, args, destination, false, ctx);
+    } else if function_name.contains(CTOR_FN_NAME) {
         assert!(
             !call_info.split_last_tuple(),
             "Constructors may not use the `rust_call` calling convention!"
@@ -531,6 +899,16 @@ pub fn call<'tcx>(
         let input = crate::operand::handle_operand(&args[0].node, ctx);
         // Not-Virtual (for interop)
         return crate::place::place_set(destination, CILNode::IsInst(Box::new((input, tpe))), ctx);
+    } else if function_name.contains(MANAGED_DELEGATE) {
+        return crate::delegate::managed_delegate(subst_ref, args, destination, ctx);
+    } else if function_name.contains(MANAGED_LD_FLD) {
+        return managed_field_access(subst_ref, args, destination, false, false, ctx);
+    } else if function_name.contains(MANAGED_ST_FLD) {
+        return managed_field_access(subst_ref, args, destination, false, true, ctx);
+    } else if function_name.contains(MANAGED_LD_SFLD) {
+        return managed_field_access(subst_ref, args, destination, true, false, ctx);
+    } else if function_name.contains(MANAGED_ST_SFLD) {
+        return managed_field_access(subst_ref, args, destination, true, true, ctx);
     } else if function_name.contains(MANAGED_LD_ELEM_REF) {
         assert!(
             !call_info.split_last_tuple(),
@@ -555,14 +933,8 @@ pub fn call<'tcx>(
         let res_calc = crate::operand::handle_operand(&arg.node, ctx);
         call_args.push(res_calc);
     }
-    if crate::function_sig::is_fn_variadic(fn_type, ctx.tcx()) {
-        signature.set_inputs(
-            args.iter()
-                .map(|operand| {
-                    ctx.type_from_cache(ctx.monomorphize(operand.node.ty(ctx.body(), ctx.tcx())))
-                })
-                .collect(),
-        );
+    if signature.is_variadic() {
+        crate::function_sig::variadic_call_site(&mut signature, args, ctx);
     }
     if args.len() < signature.inputs().len() {
         let tpe: cilly::Type = signature.inputs()[signature.inputs().len() - 1];
@@ -588,7 +960,11 @@ pub fn call<'tcx>(
     );
     // Hande
     let site = ctx.alloc_methodref(call_site);
-    if is_void {
+    if tail {
+        CILRoot::TailCall {
+            call: call!(site, call_args),
+        }
+    } else if is_void {
         CILRoot::Call {
             site,
             args: call_args.into(),
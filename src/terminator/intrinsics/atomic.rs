@@ -32,21 +32,22 @@ pub fn xchg<'tcx>(
     );
     let src_type = ctx.monomorphize(args[1].node.ty(ctx.body(), ctx.tcx()));
     let src_type = ctx.type_from_cache(src_type);
-    let uint8_ref = ctx.nref(Type::Int(Int::U8));
-    let xchng = MethodRef::new(
-        *ctx.main_module(),
-        ctx.alloc_string("atomic_xchng_u8"),
-        ctx.sig([uint8_ref, Type::Int(Int::U8)], Type::Int(Int::U8)),
-        MethodKind::Static,
-        vec![].into(),
-    );
     match src_type {
-        Type::Int(Int::U8) => {
+        // Implemented by a builtin, since older runtimes have no 8 and 16 bit `Interlocked.Exchange`.
+        Type::Int(int @ (Int::U8 | Int::I8 | Int::U16 | Int::I16)) => {
+            let int_ref = ctx.nref(Type::Int(int));
+            let xchng = MethodRef::new(
+                *ctx.main_module(),
+                ctx.alloc_string(format!("atomic_xchng_{}", int.name())),
+                ctx.sig([int_ref, Type::Int(int)], Type::Int(int)),
+                MethodKind::Static,
+                vec![].into(),
+            );
             return place_set(
                 destination,
                 call!(ctx.alloc_methodref(xchng), [dst, new]),
                 ctx,
-            )
+            );
         }
         Type::Ptr(_) => {
             let usize_ref = ctx.nref(Type::Int(Int::USize));
@@ -70,7 +71,7 @@ pub fn xchg<'tcx>(
                 ctx,
             );
         }
-        Type::Int(Int::I8 | Int::U16 | Int::I16) | Type::Bool | Type::PlatformChar => {
+        Type::Bool | Type::PlatformChar => {
             todo!("can't atomic_xchg {src_type:?}")
         }
         _ => (),
@@ -137,8 +138,22 @@ pub fn cxchg<'tcx>(
             )
             .cast_ptr(src_type)
         }
-        // TODO: this is a bug, on purpose. The 1 byte compare exchange is not supported untill .NET 9. Remove after November, when .NET 9 Releases.
-        Type::Int(Int::U8) => comparand.clone(),
+        // Implemented by a builtin, since older runtimes have no 8 and 16 bit `Interlocked.CompareExchange`.
+        Type::Int(int @ (Int::U8 | Int::I8 | Int::U16 | Int::I16)) => {
+            let tpe = Type::Int(*int);
+            let int_ref = ctx.nref(tpe);
+            let call_site = MethodRef::new(
+                *ctx.main_module(),
+                ctx.alloc_string(format!("atomic_cmpxchng_{}", int.name())),
+                ctx.sig([int_ref, tpe, tpe], tpe),
+                MethodKind::Static,
+                vec![].into(),
+            );
+            call!(
+                ctx.alloc_methodref(call_site),
+                [dst, value, comparand.clone()]
+            )
+        }
         _ => {
             let src_ref = ctx.nref(src_type);
             let call_site = MethodRef::new(
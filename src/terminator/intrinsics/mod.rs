@@ -26,6 +26,7 @@ mod floats;
 mod interop;
 mod ints;
 mod saturating;
+mod simd;
 mod type_info;
 mod utilis;
 use floats::{fmaf32, fmaf64, powf32, powf64, powif32, powif64, roundf32, roundf64};
@@ -1361,6 +1362,44 @@ pub fn handle_intrinsic<'tcx>(
             let allset = call!(allset, []);
             place_set(destination, call!(eq, [x, allset]), ctx)
         }
+        "simd_div"
+        | "simd_rem"
+        | "simd_xor"
+        | "simd_shl"
+        | "simd_shr"
+        | "simd_lt"
+        | "simd_le"
+        | "simd_gt"
+        | "simd_ge"
+        | "simd_fmin"
+        | "simd_fmax"
+        | "simd_saturating_add"
+        | "simd_saturating_sub"
+        | "simd_select"
+        | "simd_select_bitmask"
+        | "simd_cast"
+        | "simd_gather"
+        | "simd_scatter"
+        | "simd_masked_load"
+        | "simd_masked_store"
+        | "simd_extract"
+        | "simd_insert"
+        | "simd_reduce_and"
+        | "simd_reduce_or"
+        | "simd_reduce_xor"
+        | "simd_reduce_min"
+        | "simd_reduce_max" => simd::simd_builtin(fn_name, args, destination, ctx),
+        // Float to int casts saturate, so `simd_cast` already has the semantics of `as`.
+        "simd_as" => simd::simd_builtin("simd_cast", args, destination, ctx),
+        "simd_extract_dyn" => simd::simd_builtin("simd_extract", args, destination, ctx),
+        "simd_insert_dyn" => simd::simd_builtin("simd_insert", args, destination, ctx),
+        // The ordered reductions take an accumulator, and the unordered ones don't.
+        "simd_reduce_add_ordered" | "simd_reduce_add_unordered" => {
+            simd::simd_builtin("simd_reduce_add", args, destination, ctx)
+        }
+        "simd_reduce_mul_ordered" | "simd_reduce_mul_unordered" => {
+            simd::simd_builtin("simd_reduce_mul", args, destination, ctx)
+        }
         _ => intrinsic_slow(fn_name, args, destination, ctx, call_instance, span),
     }
 }
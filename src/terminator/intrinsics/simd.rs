@@ -0,0 +1,37 @@
+use crate::{assembly::MethodCompileCtx, operand::handle_operand, place::place_set};
+use cilly::{call, cil_node::CILNode, cil_root::CILRoot, Type};
+use rustc_middle::mir::{Operand, Place};
+use rustc_span::Spanned;
+/// Lowers a portable SIMD intrinsic to a call to the `cilly` builtin `builtin`, which shares its signature with the intrinsic.
+/// The builtin is implemented using `System.Runtime.Intrinsics` where possible, and lane by lane otherwise.
+pub fn simd_builtin<'tcx>(
+    builtin: &str,
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    let inputs: Vec<Type> = args
+        .iter()
+        .map(|arg| {
+            let ty = ctx.monomorphize(arg.node.ty(ctx.body(), ctx.tcx()));
+            ctx.type_from_cache(ty)
+        })
+        .collect();
+    let output = ctx.monomorphize(destination.ty(ctx.body(), ctx.tcx()).ty);
+    let output = ctx.type_from_cache(output);
+    let vals: Vec<CILNode> = args
+        .iter()
+        .map(|arg| handle_operand(&arg.node, ctx))
+        .collect();
+    let name = ctx.alloc_string(builtin);
+    let main_module = ctx.main_module();
+    let main_module = ctx[*main_module].clone();
+    let mref = main_module.static_mref(&inputs, output, name, ctx);
+    if output == Type::Void {
+        return CILRoot::Call {
+            site: mref,
+            args: vals.into(),
+        };
+    }
+    place_set(destination, call!(mref, vals), ctx)
+}
@@ -66,7 +66,7 @@ pub fn atomic_or(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly)
             );
             call!(asm.alloc_methodref(mref), [addr, addend])
         }
-        Type::Int(int @ (Int::ISize | Int::USize)) => {
+        Type::Int(int @ (Int::ISize | Int::USize | Int::U8 | Int::I8 | Int::U16 | Int::I16)) => {
             let int_ref = asm.nref(Type::Int(int));
             let mref = MethodRef::new(
                 *asm.main_module(),
@@ -103,7 +103,18 @@ pub fn atomic_or(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly)
 }
 pub fn atomic_xor(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly) -> CILNode {
     match tpe {
-        Type::Int(int @ (Int::U32 | Int::I32 | Int::U64 | Int::I64 | Int::USize | Int::ISize)) => {
+        Type::Int(
+            int @ (Int::U8
+            | Int::I8
+            | Int::U16
+            | Int::I16
+            | Int::U32
+            | Int::I32
+            | Int::U64
+            | Int::I64
+            | Int::USize
+            | Int::ISize),
+        ) => {
             let iref = asm.nref(Type::Int(int));
             let mref = MethodRef::new(
                 *asm.main_module(),
@@ -163,6 +174,17 @@ pub fn atomic_and(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly)
             let cilnode = call!(asm.alloc_methodref(mref), [addr, addend]);
             cilnode
         }
+        Type::Int(int @ (Int::U8 | Int::I8 | Int::U16 | Int::I16)) => {
+            let int_ref = asm.nref(Type::Int(int));
+            let mref = MethodRef::new(
+                *asm.main_module(),
+                asm.alloc_string(format!("atomic_and_{}", int.name())),
+                asm.sig([int_ref, Type::Int(int)], Type::Int(int)),
+                MethodKind::Static,
+                vec![].into(),
+            );
+            call!(asm.alloc_methodref(mref), [addr, addend])
+        }
         Type::Int(Int::USize | Int::ISize) => {
             let usize_ref = asm.nref(Type::Int(Int::USize));
             let mref = MethodRef::new(
@@ -209,7 +231,18 @@ pub fn compare_bytes(a: CILNode, b: CILNode, len: CILNode, asm: &mut Assembly) -
 }
 pub fn atomic_nand(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly) -> CILNode {
     match tpe {
-        Type::Int(int @ (Int::U32 | Int::I32 | Int::U64 | Int::I64 | Int::USize | Int::ISize)) => {
+        Type::Int(
+            int @ (Int::U8
+            | Int::I8
+            | Int::U16
+            | Int::I16
+            | Int::U32
+            | Int::I32
+            | Int::U64
+            | Int::I64
+            | Int::USize
+            | Int::ISize),
+        ) => {
             let iref = asm.nref(Type::Int(int));
             let mref = MethodRef::new(
                 *asm.main_module(),
@@ -245,7 +278,18 @@ pub fn atomic_nand(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly
 }
 pub fn atomic_min(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly) -> CILNode {
     match tpe {
-        Type::Int(int @ (Int::U32 | Int::I32 | Int::U64 | Int::I64 | Int::USize | Int::ISize)) => {
+        Type::Int(
+            int @ (Int::U8
+            | Int::I8
+            | Int::U16
+            | Int::I16
+            | Int::U32
+            | Int::I32
+            | Int::U64
+            | Int::I64
+            | Int::USize
+            | Int::ISize),
+        ) => {
             let iref = asm.nref(Type::Int(int));
             let mref = MethodRef::new(
                 *asm.main_module(),
@@ -281,7 +325,18 @@ pub fn atomic_min(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly)
 }
 pub fn atomic_max(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly) -> CILNode {
     match tpe {
-        Type::Int(int @ (Int::U32 | Int::I32 | Int::U64 | Int::I64 | Int::USize | Int::ISize)) => {
+        Type::Int(
+            int @ (Int::U8
+            | Int::I8
+            | Int::U16
+            | Int::I16
+            | Int::U32
+            | Int::I32
+            | Int::U64
+            | Int::I64
+            | Int::USize
+            | Int::ISize),
+        ) => {
             let iref = asm.nref(Type::Int(int));
             let mref = MethodRef::new(
                 *asm.main_module(),
@@ -1,10 +1,10 @@
-use crate::{assembly::MethodCompileCtx, place::place_set};
+use crate::{assembly::MethodCompileCtx, codegen_error::CodegenError, place::place_set};
 use cilly::{
     cil_node::CILNode,
     cil_root::CILRoot,
     cil_tree::CILTree,
     ld_field,
-    v2::{cilnode::MethodKind, Assembly, FieldDesc, FnSig, Int, MethodRef},
+    v2::{cilnode::MethodKind, Assembly, FieldDesc, FnSig, Int, MethodRef, SwitchTable},
     Const, Type,
 };
 use rustc_middle::{
@@ -39,15 +39,25 @@ pub fn handle_call_terminator<'tycxt>(
                 "fn_ty{fn_ty:?} in call is not a function type!"
             );
             let fn_ty = ctx.monomorphize(fn_ty);
-            let call_ops = call::call(fn_ty, ctx, args, destination, terminator.source_info.span);
+            let call_ops = call::call(
+                fn_ty,
+                ctx,
+                args,
+                destination,
+                terminator.source_info.span,
+                false,
+            );
             //eprintln!("\nCalling FnDef:{fn_ty:?}. call_ops:{call_ops:?}");
             trees.push(call_ops.into());
         }
-        TyKind::FnPtr(sig, _) => {
+        TyKind::FnPtr(sig, hdr) => {
             //eprintln!("Calling FnPtr:{func_ty:?}");
 
             let sig = ctx.tcx().instantiate_bound_regions_with_erased(*sig);
-            let sig = crate::function_sig::from_poly_sig(ctx, sig);
+            let mut sig = crate::function_sig::from_poly_sig(ctx, sig, *hdr);
+            if sig.is_variadic() {
+                crate::function_sig::variadic_call_site(&mut sig, args, ctx);
+            }
             let mut arg_operands = Vec::new();
             for arg in args {
                 arg_operands.push(crate::operand::handle_operand(&arg.node, ctx));
@@ -93,6 +103,56 @@ pub fn handle_call_terminator<'tycxt>(
     }
     trees
 }
+/// Lowers `become func(args)`. Calls which can't be turned into a CIL tail call(e.g. intrinsics) are emitted as a regular call, followed by a return.
+fn handle_tail_call<'tcx>(
+    terminator: &Terminator<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+    args: &[Spanned<Operand<'tcx>>],
+    func: &Operand<'tcx>,
+) -> Vec<CILTree> {
+    let func_ty = ctx.monomorphize(func.ty(ctx.body(), ctx.tcx()));
+    let root = match func_ty.kind() {
+        TyKind::FnDef(_, _) => call::call(
+            func_ty,
+            ctx,
+            args,
+            &Place::return_place(),
+            terminator.source_info.span,
+            true,
+        ),
+        TyKind::FnPtr(sig, hdr) => {
+            let sig = ctx.tcx().instantiate_bound_regions_with_erased(*sig);
+            let sig = crate::function_sig::from_poly_sig(ctx, sig, *hdr);
+            let args = args
+                .iter()
+                .map(|arg| crate::operand::handle_operand(&arg.node, ctx))
+                .collect();
+            let called_operand = crate::operand::handle_operand(func, ctx);
+            CILRoot::TailCall {
+                call: CILNode::CallI(Box::new((sig, called_operand, args))),
+            }
+        }
+        _ => CodegenError::CantTailCall(format!("{func_ty:?}").into()).raise(),
+    };
+    if matches!(root, CILRoot::TailCall { .. }) {
+        vec![root.into()]
+    } else {
+        let mut trees = vec![root.into()];
+        trees.extend(handle_return(ctx));
+        trees
+    }
+}
+fn handle_return(ctx: &mut MethodCompileCtx<'_, '_>) -> Vec<CILTree> {
+    let ret = ctx.monomorphize(ctx.body().return_ty());
+    if ctx.type_from_cache(ret) == cilly::Type::Void {
+        vec![CILRoot::VoidRet.into()]
+    } else {
+        vec![CILRoot::Ret {
+            tree: CILNode::LDLoc(0),
+        }
+        .into()]
+    }
+}
 pub fn handle_terminator<'tcx>(
     terminator: &Terminator<'tcx>,
     ctx: &mut MethodCompileCtx<'tcx, '_>,
@@ -107,18 +167,12 @@ pub fn handle_terminator<'tcx>(
             call_source: _,
             fn_span: _,
         } => handle_call_terminator(terminator, ctx, args, destination, func, *target),
-        TerminatorKind::TailCall { .. } => todo!(),
-        TerminatorKind::Return => {
-            let ret = ctx.monomorphize(ctx.body().return_ty());
-            if ctx.type_from_cache(ret) == cilly::Type::Void {
-                vec![CILRoot::VoidRet.into()]
-            } else {
-                vec![CILRoot::Ret {
-                    tree: CILNode::LDLoc(0),
-                }
-                .into()]
-            }
-        }
+        TerminatorKind::TailCall {
+            func,
+            args,
+            fn_span: _,
+        } => handle_tail_call(terminator, ctx, args, func),
+        TerminatorKind::Return => handle_return(ctx),
         TerminatorKind::SwitchInt { discr, targets } => {
             let ty = ctx.monomorphize(discr.ty(ctx.body(), ctx.tcx()));
             let discr = crate::operand::handle_operand(discr, ctx);
@@ -295,13 +349,20 @@ pub fn handle_terminator<'tcx>(
             }
             .into()]
         }
-        TerminatorKind::CoroutineDrop {} => todo!("Can't drop corutines yet!"),
+        // The coroutine transform replaces `CoroutineDrop` with a return from the drop shim, so it should never reach codegen.
+        TerminatorKind::CoroutineDrop {} => {
+            panic!("ERROR: CoroutineDrop terminator present after the coroutine state transform.")
+        }
+        // Suspension points are turned into state switches by the coroutine transform, so they should never reach codegen.
         TerminatorKind::Yield {
             value: _,
             resume: _,
             resume_arg: _,
             drop: _,
-        } => todo!("Can't yeld yet!"), //_ => todo!("Unhandled terminator kind {kind:?}", kind = terminator.kind),
+        } => CodegenError::from_panic_message(
+            "Yield terminator present after the coroutine state transform.",
+        )
+        .raise(), //_ => todo!("Unhandled terminator kind {kind:?}", kind = terminator.kind),
     };
     let last = res.last().unwrap().root();
     assert!(
@@ -309,6 +370,8 @@ pub fn handle_terminator<'tcx>(
             last,
             CILRoot::GoTo { .. }
                 | CILRoot::Ret { .. }
+                | CILRoot::TailCall { .. }
+                | CILRoot::Switch { .. }
                 | CILRoot::VoidRet
                 | CILRoot::ReThrow
                 | CILRoot::Throw(_)
@@ -324,6 +387,29 @@ fn handle_switch(
     switch: &SwitchTargets,
     asm: &mut Assembly,
 ) -> Vec<CILTree> {
+    // Switches with more than one case become a single root, which gets lowered to a jump table or a binary search.
+    // 128 bit ints are not supported by `SwitchTable`, and bools are better off with a simple conditional branch.
+    let int = match ty.kind() {
+        TyKind::Int(int) => crate::r#type::from_int(int).as_int(),
+        TyKind::Uint(uint) => crate::r#type::from_uint(uint).as_int(),
+        TyKind::Char => Some(Int::U32),
+        _ => None,
+    }
+    .filter(|int| !matches!(int, Int::I128 | Int::U128));
+    if let Some(int) = int.filter(|_| switch.iter().nth(1).is_some()) {
+        let table = SwitchTable::new(
+            int,
+            switch
+                .iter()
+                .map(|(value, target)| (value as u64, target.as_u32())),
+            switch.otherwise().as_u32(),
+        );
+        return vec![CILRoot::Switch {
+            discr: discr.clone(),
+            table,
+        }
+        .into()];
+    }
     let mut trees = Vec::new();
     for (value, target) in switch.iter() {
         //ops.extend(CILOp::debug_msg("Switchin"));
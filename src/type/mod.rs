@@ -11,15 +11,15 @@ use cilly::{
     add, ld_arg, ptr_cast,
     tpe::simd::SIMDVector,
     v2::{
-        cilnode::MethodKind, Access, BasicBlock, CILNode, CILRoot, ClassDef, ClassDefIdx, ClassRef,
-        ClassRefIdx, Float, Int, MethodDef, MethodImpl, StringIdx, Type,
+        cilnode::MethodKind, tpe::GenericKind, Access, BasicBlock, CILNode, CILRoot, ClassDef,
+        ClassDefIdx, ClassRef, ClassRefIdx, Float, Int, MethodDef, MethodImpl, StringIdx, Type,
     },
     Assembly, IntoAsmIndex,
 };
 pub use r#type::*;
 use rustc_middle::ty::{AdtDef, AdtKind, FloatTy, IntTy, List, ParamEnv, Ty, TyKind, UintTy};
 use rustc_span::def_id::DefId;
-use rustc_target::abi::Layout;
+use rustc_target::abi::{Layout, VariantIdx};
 //pub use tycache::*;
 //pub use type_def::*;
 
@@ -145,18 +145,12 @@ pub fn get_type<'tcx>(ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Typ
         TyKind::Float(float) => from_float(float),
         TyKind::Foreign(_foregin) => Type::Void,
         TyKind::FnDef(_did, _subst) => Type::Void,
-        TyKind::FnPtr(sig, _) => {
+        TyKind::FnPtr(sig, hdr) => {
             let sig = ctx
                 .tcx()
                 .normalize_erasing_late_bound_regions(ParamEnv::reveal_all(), *sig);
-            //let sig = crate::function_sig::from_poly_sig(method, tcx, self, sig);
-            let output = get_type(ctx.monomorphize(sig.output()), ctx);
-            let inputs: Box<[Type]> = sig
-                .inputs()
-                .iter()
-                .map(|input| get_type(ctx.monomorphize(*input), ctx))
-                .collect();
-            let sig = ctx.sig(inputs, output);
+            let sig = crate::function_sig::from_poly_sig(ctx, sig, *hdr);
+            let sig = ctx.alloc_sig(sig);
             Type::FnPtr(sig)
         }
         TyKind::Int(int) => from_int(int),
@@ -207,6 +201,11 @@ pub fn get_type<'tcx>(ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Typ
                 if count == 1 {
                     return elem;
                 }
+                // Vectors of pointers(used by gathers and scatters) are stored as vectors of addresses.
+                let elem = match elem {
+                    Type::Ptr(_) | Type::FnPtr(_) => Type::Int(Int::USize),
+                    elem => elem,
+                };
                 return Type::SIMDVector(SIMDVector::new(
                     elem.try_into().unwrap(),
                     count.try_into().unwrap(),
@@ -258,6 +257,36 @@ pub fn get_type<'tcx>(ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Typ
                     }
                 } else if name.contains(INTEROP_CHR_TPE_NAME) {
                     Type::PlatformChar
+                } else if name.contains(INTEROP_GENERIC_CLASS_TPE_NAME) {
+                    assert!(
+                        subst.len() == 4,
+                        "Managed generic object reference must have exactly 4 generic arguments!"
+                    );
+                    let assembly = garg_to_string(subst[0], ctx.tcx());
+                    let assembly = Some(assembly)
+                        .filter(|assembly| !assembly.is_empty())
+                        .map(|asm| ctx.alloc_string(asm));
+                    let name = garg_to_string(subst[1], ctx.tcx());
+                    let name = ctx.alloc_string(name);
+                    let is_valuetype = crate::utilis::garag_to_bool(subst[2], ctx.tcx());
+                    let generics = generic_args_from_tuple(
+                        subst[3]
+                            .as_type()
+                            .expect("The generic arguments of a managed class must be a type!"),
+                        ctx,
+                    );
+                    Type::ClassRef(ctx.alloc_class_ref(ClassRef::new(
+                        name,
+                        assembly,
+                        is_valuetype,
+                        generics,
+                    )))
+                } else if name.contains(INTEROP_METHOD_GENERIC_PARAM_TPE_NAME) {
+                    let idx = garag_to_usize(subst[0], ctx.tcx());
+                    Type::PlatformGeneric(idx.try_into().unwrap(), GenericKind::CallGeneric)
+                } else if name.contains(INTEROP_GENERIC_PARAM_TPE_NAME) {
+                    let idx = garag_to_usize(subst[0], ctx.tcx());
+                    Type::PlatformGeneric(idx.try_into().unwrap(), GenericKind::TypeGeneric)
                 } else {
                     todo!("Interop type {name:?} is not yet supported!")
                 }
@@ -290,8 +319,25 @@ pub fn get_type<'tcx>(ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Typ
                 .iter()
                 .map(|ty| get_type(ty, ctx))
                 .collect();
-            // Get a coroutine name.
-            let name = coroutine_name(*defid, &fields, ctx);
+            // Extract the locals saved across suspension points, for each state of the coroutine.
+            let state_tys: Vec<Vec<Type>> = coroutine_args
+                .state_tys(*defid, ctx.tcx())
+                .map(|variant| {
+                    variant
+                        .map(|ty| {
+                            let ty = ctx.monomorphize(ty);
+                            get_type(ty, ctx)
+                        })
+                        .collect()
+                })
+                .collect();
+            // Get a coroutine name. Saved locals are a part of the name, since they may depend on generics not present in upvars.
+            let mangled: Vec<_> = fields
+                .iter()
+                .chain(state_tys.iter().flatten())
+                .copied()
+                .collect();
+            let name = coroutine_name(*defid, &mangled, ctx);
             let name = ctx.alloc_string(name);
             // Get the layout of the coroutine
             let layout = ctx.layout_of(ty);
@@ -301,6 +347,21 @@ pub fn get_type<'tcx>(ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Typ
             if ctx.class_ref_to_def(cref).is_none() {
                 let mut type_def = closure_typedef(&fields, layout.layout, ctx, name);
                 handle_tag(&layout.layout, ctx, ty, type_def.fields_mut());
+                // Each state of a coroutine is laid out like an enum variant.
+                for (vidx, variant) in state_tys.iter().enumerate() {
+                    let vidx = VariantIdx::from_usize(vidx);
+                    let offsets = crate::utilis::adt::variant_offsets(layout.layout, vidx);
+                    for ((fidx, field), offset) in variant.iter().enumerate().zip(offsets) {
+                        if *field == Type::Void {
+                            continue;
+                        }
+                        let name = ctx.alloc_string(coroutine_field_name(
+                            vidx.as_u32(),
+                            fidx.try_into().unwrap(),
+                        ));
+                        type_def.fields_mut().push((*field, name, Some(offset)));
+                    }
+                }
                 ctx.class_def(type_def);
             }
 
@@ -487,6 +548,11 @@ pub fn coroutine_name(
         field_count = fields.len()
     )
 }
+/// Returns the name of the field `field_idx` of the coroutine state `variant_idx`.
+#[must_use]
+pub fn coroutine_field_name(variant_idx: u32, field_idx: u32) -> String {
+    format!("s{variant_idx}_{field_idx}")
+}
 /// Creates a [`ClassDef`] representing a closure with certain layout and fields.
 #[must_use]
 pub fn closure_typedef(
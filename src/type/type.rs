@@ -51,6 +51,9 @@ pub const INTEROP_CLASS_TPE_NAME: &str = "RustcCLRInteropManagedClass";
 pub const INTEROP_STRUCT_TPE_NAME: &str = "RustcCLRInteropManagedStruct";
 pub const INTEROP_CHR_TPE_NAME: &str = "RustcCLRInteropManagedChar";
 pub const INTEROP_ARR_TPE_NAME: &str = "RustcCLRInteropManagedArray";
+pub const INTEROP_GENERIC_CLASS_TPE_NAME: &str = "RustcCLRInteropManagedGenericClass";
+pub const INTEROP_GENERIC_PARAM_TPE_NAME: &str = "RustcCLRInteropManagedGenericParam";
+pub const INTEROP_METHOD_GENERIC_PARAM_TPE_NAME: &str = "RustcCLRInteropManagedMethodGenericParam";
 #[must_use]
 /// Checks if a type is a magic interop type.
 pub fn is_name_magic(name: &str) -> bool {
@@ -109,6 +112,23 @@ pub fn magic_type<'tcx>(
         todo!("Interop type {name:?} is not yet supported!")
     }
 }
+/// Converts a tuple of Rust types into a list of .NET generic arguments.
+/// # Panics
+/// Will panic if `tuple` is not a tuple.
+#[must_use]
+pub fn generic_args_from_tuple<'tcx>(
+    tuple: Ty<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> Box<[Type]> {
+    let tuple = ctx.monomorphize(tuple);
+    let TyKind::Tuple(elements) = tuple.kind() else {
+        panic!("ERROR: managed generic arguments must be passed as a tuple, not as {tuple:?}.")
+    };
+    elements
+        .iter()
+        .map(|element| get_type(element, ctx))
+        .collect()
+}
 #[must_use]
 pub fn garag_to_usize<'tcx>(garg: GenericArg<'tcx>, _ctx: TyCtxt<'tcx>) -> u64 {
     let usize_const = garg
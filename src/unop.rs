@@ -4,11 +4,11 @@ use crate::r#type::get_type;
 use cilly::cil_node::CILNode;
 
 use cilly::v2::cilnode::MethodKind;
-use cilly::v2::{ClassRef, FieldDesc, Int, MethodRef};
+use cilly::v2::{ClassRef, FieldDesc, Float, Int, MethodRef};
 use cilly::{call, ld_field, Type};
 
 use rustc_middle::mir::{Operand, UnOp};
-use rustc_middle::ty::{IntTy, TyKind, UintTy};
+use rustc_middle::ty::{FloatTy, IntTy, TyKind, UintTy};
 
 /// Implements an unary operation, such as negation.
 pub fn unop<'tcx>(
@@ -42,6 +42,16 @@ pub fn unop<'tcx>(
                 );
                 call!(ctx.alloc_methodref(mref), [parrent_node])
             }
+            TyKind::Float(FloatTy::F128) => {
+                let mref = MethodRef::new(
+                    *ctx.main_module(),
+                    ctx.alloc_string("neg_f128"),
+                    ctx.sig([Type::Float(Float::F128)], Type::Float(Float::F128)),
+                    MethodKind::Static,
+                    vec![].into(),
+                );
+                call!(ctx.alloc_methodref(mref), [parrent_node])
+            }
             _ => CILNode::Neg(parrent_node.into()),
         },
         UnOp::Not => match ty.kind() {
@@ -13,6 +13,10 @@ use rustc_target::abi::{
 
 use crate::fn_ctx::MethodCompileCtx;
 pub fn enum_variant_offsets(_: AdtDef, layout: Layout, vidix: VariantIdx) -> FieldOffsetIterator {
+    variant_offsets(layout, vidix)
+}
+/// Returns the offsets of fields of variant `vidix` of a type with multiple variants(an enum or a coroutine).
+pub fn variant_offsets(layout: Layout, vidix: VariantIdx) -> FieldOffsetIterator {
     FieldOffsetIterator::fields(get_variant_at_index(vidix, (*layout.0).clone()))
 }
 
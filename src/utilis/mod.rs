@@ -12,6 +12,11 @@ use rustc_middle::{
 };
 pub mod adt;
 pub const CTOR_FN_NAME: &str = "rustc_clr_interop_managed_ctor";
+pub const CTOR_TUPLE_FN_NAME: &str = "rustc_clr_interop_managed_ctor_tuple";
+pub const CTOR_GENERIC_FN_NAME: &str = "rustc_clr_interop_managed_ctor_generic";
+pub const MANAGED_CALL_GENERIC_FN_NAME: &str = "rustc_clr_interop_managed_call_generic";
+pub const MANAGED_CALL_TUPLE_FN_NAME: &str = "rustc_clr_interop_managed_call_tuple";
+pub const MANAGED_CALL_VIRT_TUPLE_FN_NAME: &str = "rustc_clr_interop_managed_call_virt_tuple";
 pub const MANAGED_CALL_FN_NAME: &str = "rustc_clr_interop_managed_call";
 pub const MANAGED_CALL_VIRT_FN_NAME: &str = "rustc_clr_interop_managed_call_virt";
 pub const MANAGED_LD_LEN: &str = "rustc_clr_interop_managed_ld_len";
@@ -19,6 +24,11 @@ pub const MANAGED_LD_NULL: &str = "rustc_clr_interop_managed_ld_null";
 pub const MANAGED_CHECKED_CAST: &str = "rustc_clr_interop_managed_checked_cast";
 pub const MANAGED_IS_INST: &str = "rustc_clr_interop_managed_is_inst";
 pub const MANAGED_LD_ELEM_REF: &str = "rustc_clr_interop_managed_ld_elem_ref";
+pub const MANAGED_LD_FLD: &str = "rustc_clr_interop_managed_ld_fld";
+pub const MANAGED_ST_FLD: &str = "rustc_clr_interop_managed_st_fld";
+pub const MANAGED_LD_SFLD: &str = "rustc_clr_interop_managed_ld_sfld";
+pub const MANAGED_ST_SFLD: &str = "rustc_clr_interop_managed_st_sfld";
+pub const MANAGED_DELEGATE: &str = "rustc_clr_interop_managed_delegate";
 pub fn is_function_magic(name: &str) -> bool {
     name.contains(CTOR_FN_NAME) || name.contains(MANAGED_CALL_FN_NAME)
 }
@@ -122,6 +132,25 @@ pub fn enum_field_descriptor<'tcx>(
     variant_idx: u32,
     ctx: &mut MethodCompileCtx<'tcx, '_>,
 ) -> FieldIdx {
+    if let TyKind::Coroutine(def_id, args) = owner_ty.kind() {
+        // Fields of a coroutine state are the locals saved across a suspension point.
+        let field_ty = args
+            .as_coroutine()
+            .state_tys(*def_id, ctx.tcx())
+            .nth(variant_idx as usize)
+            .expect("No coroutine state with such index!")
+            .nth(field_idx as usize)
+            .expect("No coroutine state field with provided index!");
+        let field_ty = ctx.monomorphize(field_ty);
+        let field_ty = ctx.type_from_cache(field_ty);
+        let field_name =
+            ctx.alloc_string(crate::r#type::coroutine_field_name(variant_idx, field_idx));
+        let owner_ty = ctx
+            .type_from_cache(owner_ty)
+            .as_class_ref()
+            .expect("Coroutine type invalid!");
+        return ctx.alloc_field(FieldDesc::new(owner_ty, field_name, field_ty));
+    }
     let (adt, subst) = as_adt(owner_ty).expect("Tried to get a field of a non ADT type!");
     let variant = adt
         .variants()
@@ -0,0 +1,122 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+use core::future::Future;
+use core::pin::{pin, Pin};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+static mut DROPPED: u32 = 0;
+struct DropCounter(u32);
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        unsafe { DROPPED += self.0 };
+    }
+}
+// Returns `Pending` once, forcing the awaiting coroutine to suspend.
+struct YieldNow(bool);
+impl Future for YieldNow {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            Poll::Pending
+        }
+    }
+}
+fn yield_now() -> YieldNow {
+    YieldNow(false)
+}
+// Polls two futures until both complete.
+struct Join<A: Future, B: Future> {
+    a: A,
+    b: B,
+    a_res: Option<A::Output>,
+    b_res: Option<B::Output>,
+}
+impl<A: Future, B: Future> Future for Join<A, B> {
+    type Output = (A::Output, B::Output);
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.a_res.is_none() {
+            if let Poll::Ready(res) = unsafe { Pin::new_unchecked(&mut this.a) }.poll(cx) {
+                this.a_res = Some(res);
+            }
+        }
+        if this.b_res.is_none() {
+            if let Poll::Ready(res) = unsafe { Pin::new_unchecked(&mut this.b) }.poll(cx) {
+                this.b_res = Some(res);
+            }
+        }
+        if this.a_res.is_some() && this.b_res.is_some() {
+            Poll::Ready((this.a_res.take().unwrap(), this.b_res.take().unwrap()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+fn join<A: Future, B: Future>(a: A, b: B) -> Join<A, B> {
+    Join {
+        a,
+        b,
+        a_res: None,
+        b_res: None,
+    }
+}
+const VTABLE: RawWakerVTable = RawWakerVTable::new(
+    |_| RawWaker::new(core::ptr::null(), &VTABLE),
+    |_| {},
+    |_| {},
+    |_| {},
+);
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) };
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(res) = future.as_mut().poll(&mut cx) {
+            return res;
+        }
+    }
+}
+async fn sum(count: u64) -> u64 {
+    let mut acc = 0;
+    for i in 0..count {
+        // `acc` and `i` are saved across the suspension point.
+        yield_now().await;
+        acc += i;
+    }
+    acc
+}
+async fn add_late(a: u32, b: f32) -> f32 {
+    let guard = DropCounter(1);
+    yield_now().await;
+    a as f32 + b
+}
+fn main() {
+    test_eq!(block_on(sum(black_box(10))), 45);
+    test_eq!(block_on(add_late(black_box(8), black_box(1.5))), 9.5);
+    test_eq!(unsafe { DROPPED }, 1);
+    let (a, b) = block_on(join(sum(black_box(5)), add_late(2, 0.5)));
+    test_eq!(a, 10);
+    test_eq!(b, 2.5);
+    test_eq!(unsafe { DROPPED }, 2);
+    // Dropping a coroutine suspended at an await point must drop its saved locals.
+    {
+        let mut suspended = pin!(add_late(black_box(1), 1.0));
+        let waker = unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) };
+        let mut cx = Context::from_waker(&waker);
+        test!(suspended.as_mut().poll(&mut cx).is_pending());
+        test_eq!(unsafe { DROPPED }, 2);
+    }
+    test_eq!(unsafe { DROPPED }, 3);
+}
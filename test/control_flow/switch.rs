@@ -0,0 +1,138 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+// Dense: lowered to a jump table.
+#[inline(never)]
+fn dense(val: u8) -> u32 {
+    match val {
+        0 => 10,
+        1 => 11,
+        2 => 12,
+        3 => 13,
+        5 => 15,
+        6 => 16,
+        7 => 17,
+        _ => 99,
+    }
+}
+// Dense, but only once sign extended.
+#[inline(never)]
+fn signed(val: i16) -> i32 {
+    match val {
+        -3 => 1,
+        -2 => 2,
+        -1 => 3,
+        0 => 4,
+        1 => 5,
+        _ => 0,
+    }
+}
+// Sparse: lowered to a binary search.
+#[inline(never)]
+fn sparse(val: u64) -> u32 {
+    match val {
+        1 => 1,
+        100 => 2,
+        1_000 => 3,
+        10_000 => 4,
+        100_000 => 5,
+        1_000_000 => 6,
+        u64::MAX => 7,
+        _ => 0,
+    }
+}
+// Sparse, with cases on both sides of zero.
+#[inline(never)]
+fn sparse_signed(val: i64) -> u32 {
+    match val {
+        i64::MIN => 1,
+        -1_000 => 2,
+        -7 => 3,
+        0 => 4,
+        7 => 5,
+        1_000 => 6,
+        i64::MAX => 7,
+        _ => 0,
+    }
+}
+#[inline(never)]
+fn chars(val: char) -> u32 {
+    match val {
+        'a' => 1,
+        'b' => 2,
+        'z' => 3,
+        'ż' => 4,
+        _ => 0,
+    }
+}
+#[derive(Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Neg(i32),
+}
+#[inline(never)]
+fn apply(op: Op, a: i32, b: i32) -> i32 {
+    match op {
+        Op::Add => a + b,
+        Op::Sub => a - b,
+        Op::Mul => a * b,
+        Op::Div => a / b,
+        Op::Rem => a % b,
+        Op::Neg(val) => -val,
+    }
+}
+fn main() {
+    test_eq!(dense(black_box(0)), 10);
+    test_eq!(dense(black_box(3)), 13);
+    test_eq!(dense(black_box(4)), 99);
+    test_eq!(dense(black_box(7)), 17);
+    test_eq!(dense(black_box(8)), 99);
+    test_eq!(dense(black_box(255)), 99);
+
+    test_eq!(signed(black_box(-4)), 0);
+    test_eq!(signed(black_box(-3)), 1);
+    test_eq!(signed(black_box(-1)), 3);
+    test_eq!(signed(black_box(1)), 5);
+    test_eq!(signed(black_box(2)), 0);
+    test_eq!(signed(black_box(i16::MIN)), 0);
+
+    test_eq!(sparse(black_box(0)), 0);
+    test_eq!(sparse(black_box(1)), 1);
+    test_eq!(sparse(black_box(1_000)), 3);
+    test_eq!(sparse(black_box(1_001)), 0);
+    test_eq!(sparse(black_box(1_000_000)), 6);
+    test_eq!(sparse(black_box(u64::MAX)), 7);
+    test_eq!(sparse(black_box(u64::MAX - 1)), 0);
+
+    test_eq!(sparse_signed(black_box(i64::MIN)), 1);
+    test_eq!(sparse_signed(black_box(-1_000)), 2);
+    test_eq!(sparse_signed(black_box(-7)), 3);
+    test_eq!(sparse_signed(black_box(-6)), 0);
+    test_eq!(sparse_signed(black_box(0)), 4);
+    test_eq!(sparse_signed(black_box(1_000)), 6);
+    test_eq!(sparse_signed(black_box(i64::MAX)), 7);
+
+    test_eq!(chars(black_box('a')), 1);
+    test_eq!(chars(black_box('z')), 3);
+    test_eq!(chars(black_box('ż')), 4);
+    test_eq!(chars(black_box('c')), 0);
+
+    test_eq!(apply(black_box(Op::Add), 6, 3), 9);
+    test_eq!(apply(black_box(Op::Sub), 6, 3), 3);
+    test_eq!(apply(black_box(Op::Mul), 6, 3), 18);
+    test_eq!(apply(black_box(Op::Div), 6, 3), 2);
+    test_eq!(apply(black_box(Op::Rem), 7, 3), 1);
+    test_eq!(apply(black_box(Op::Neg(5)), 6, 3), -5);
+}
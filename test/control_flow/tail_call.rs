@@ -0,0 +1,57 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    explicit_tail_calls,
+    start,
+    unsized_const_params
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+// Deep enough to overflow the stack if the calls were not tail calls.
+const DEPTH: u32 = 10_000_000;
+fn count_down(n: u32, acc: u64) -> u64 {
+    if n == 0 {
+        return acc;
+    }
+    become count_down(n - 1, acc + n as u64)
+}
+fn is_even(n: u32) -> bool {
+    if n == 0 {
+        return true;
+    }
+    become is_odd(n - 1)
+}
+fn is_odd(n: u32) -> bool {
+    if n == 0 {
+        return false;
+    }
+    become is_even(n - 1)
+}
+// A tiny dispatch loop, in the style of a threaded interpreter.
+type Op = fn(&[u8], usize, i64) -> i64;
+const OPS: [Op; 3] = [op_inc, op_dec, op_halt];
+fn dispatch(code: &[u8], pc: usize, acc: i64) -> i64 {
+    let op = OPS[code[pc] as usize];
+    become op(code, pc + 1, acc)
+}
+fn op_inc(code: &[u8], pc: usize, acc: i64) -> i64 {
+    become dispatch(code, pc, acc + 1)
+}
+fn op_dec(code: &[u8], pc: usize, acc: i64) -> i64 {
+    become dispatch(code, pc, acc - 1)
+}
+fn op_halt(_code: &[u8], _pc: usize, acc: i64) -> i64 {
+    acc
+}
+fn main() {
+    test_eq!(
+        count_down(black_box(DEPTH), 0),
+        (DEPTH as u64) * (DEPTH as u64 + 1) / 2
+    );
+    test!(is_even(black_box(DEPTH)));
+    test!(!is_odd(black_box(DEPTH)));
+    test_eq!(dispatch(black_box(&[0, 0, 1, 0, 2]), 0, 0), 2);
+}
@@ -53,6 +53,57 @@ fn main() {
     let atomic_old = atomic.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
     unsafe { printf(c"atomic_old:%lx\n".as_ptr(), atomic_old as u64) };
     test_eq!(atomic_old, 0);
+    subword_atomics();
+}
+fn subword_atomics() {
+    use core::sync::atomic::{AtomicBool, AtomicI16, AtomicI8, AtomicU16, AtomicU8};
+    // Neighbouring values must not be affected by atomic operations.
+    let bytes = [
+        AtomicU8::new(0x11),
+        AtomicU8::new(0x22),
+        AtomicU8::new(0x33),
+    ];
+    test_eq!(bytes[1].swap(0xAA, SeqCst), 0x22);
+    test_eq!(
+        bytes[1].compare_exchange(0xAA, 0xBB, SeqCst, SeqCst),
+        Ok(0xAA)
+    );
+    test_eq!(
+        bytes[1].compare_exchange(0xAA, 0xCC, SeqCst, SeqCst),
+        Err(0xBB)
+    );
+    test_eq!(bytes[1].fetch_add(0x10, SeqCst), 0xBB);
+    test_eq!(bytes[1].fetch_xor(0xFF, SeqCst), 0xCB);
+    test_eq!(bytes[1].fetch_nand(0x0F, SeqCst), 0x34);
+    test_eq!(bytes[1].fetch_max(0xF0, SeqCst), 0xFB);
+    test_eq!(bytes[1].fetch_min(0x01, SeqCst), 0xFB);
+    test_eq!(bytes[1].load(SeqCst), 0x01);
+    test_eq!(bytes[0].load(SeqCst), 0x11);
+    test_eq!(bytes[2].load(SeqCst), 0x33);
+
+    let flag = AtomicBool::new(false);
+    test_eq!(flag.swap(true, SeqCst), false);
+    test_eq!(flag.fetch_and(false, SeqCst), true);
+    test_eq!(flag.fetch_or(true, SeqCst), false);
+    test_eq!(flag.fetch_xor(true, SeqCst), true);
+    test_eq!(flag.fetch_nand(true, SeqCst), false);
+    test!(flag.load(SeqCst));
+
+    let signed = AtomicI8::new(-5);
+    test_eq!(signed.fetch_max(3, SeqCst), -5);
+    test_eq!(signed.fetch_min(-100, SeqCst), 3);
+    test_eq!(signed.fetch_sub(28, SeqCst), -100);
+    test_eq!(signed.load(SeqCst), -128);
+
+    let shorts = [AtomicU16::new(0x1111), AtomicU16::new(0x2222)];
+    test_eq!(shorts[1].fetch_or(0x0F00, SeqCst), 0x2222);
+    test_eq!(shorts[1].fetch_and(0xFF0F, SeqCst), 0x2F22);
+    test_eq!(shorts[1].fetch_max(0x1000, SeqCst), 0x2F02);
+    test_eq!(shorts[0].load(SeqCst), 0x1111);
+    let short = AtomicI16::new(-300);
+    test_eq!(short.fetch_min(-400, SeqCst), -300);
+    test_eq!(short.compare_exchange(-400, 400, SeqCst, SeqCst), Ok(-400));
+    test_eq!(short.load(SeqCst), 400);
 }
 fn ptr_bitops_tagging() {
     #[repr(align(16))]
@@ -23,4 +23,87 @@ fn main() {
     let a = Simd::from_array([4, 5, 6, 7]);
     let b = Simd::from_array([0, 1, 2, 3]);
     test_eq!(a - b, Simd::from_array([4, 4, 4, 4]));
+    portable();
+}
+fn portable() {
+    use core::simd::cmp::{SimdOrd, SimdPartialOrd};
+    use core::simd::num::{SimdFloat, SimdInt, SimdUint};
+    use core::simd::{simd_swizzle, Mask};
+    let a = black_box(Simd::from_array([1_i32, -2, 3, -4]));
+    let b = black_box(Simd::from_array([4_i32, -3, 2, -1]));
+    // Comparisons and select
+    let lt = a.simd_lt(b);
+    test_eq!(lt.to_array(), [true, false, false, true]);
+    test_eq!(lt.to_bitmask(), 0b1001);
+    test_eq!(lt.select(a, b), Simd::from_array([1, -3, 2, -4]));
+    test_eq!(a.simd_min(b), Simd::from_array([1, -3, 2, -4]));
+    test_eq!(a.simd_max(b), Simd::from_array([4, -2, 3, -1]));
+    // Shifts and remainders
+    let shift = Simd::from_array([0_i32, 1, 2, 3]);
+    test_eq!(a << shift, Simd::from_array([1, -4, 12, -32]));
+    test_eq!(b >> Simd::splat(1), Simd::from_array([2, -2, 1, -1]));
+    test_eq!(a % Simd::splat(2), Simd::from_array([1, 0, 1, 0]));
+    test_eq!(a ^ b, Simd::from_array([1 ^ 4, -2 ^ -3, 3 ^ 2, -4 ^ -1]));
+    // Saturating ops
+    let bytes = black_box(Simd::from_array([250_u8, 5, 128, 0]));
+    test_eq!(
+        bytes.saturating_add(Simd::splat(10)),
+        Simd::from_array([255, 15, 138, 10])
+    );
+    test_eq!(
+        bytes.saturating_sub(Simd::splat(10)),
+        Simd::from_array([240, 0, 118, 0])
+    );
+    let signed = black_box(Simd::from_array([120_i8, -120, 0, 1]));
+    test_eq!(
+        signed.saturating_add(Simd::splat(10)),
+        Simd::from_array([127, -110, 10, 11])
+    );
+    test_eq!(
+        signed.saturating_sub(Simd::splat(10)),
+        Simd::from_array([110, -128, -10, -9])
+    );
+    // Reductions
+    test_eq!(a.reduce_sum(), -2);
+    test_eq!(a.reduce_product(), 24);
+    test_eq!(a.reduce_min(), -4);
+    test_eq!(a.reduce_max(), 3);
+    test_eq!(bytes.reduce_or(), 255);
+    test_eq!(bytes.reduce_and(), 0);
+    let floats = black_box(Simd::from_array([1.5_f32, -2.0, 4.0, 0.5]));
+    test_eq!(floats.reduce_sum(), 4.0);
+    test_eq!(floats.reduce_max(), 4.0);
+    test_eq!(
+        floats.simd_min(Simd::from_array([f32::NAN, -3.0, 5.0, 0.0])),
+        Simd::from_array([1.5, -3.0, 4.0, 0.0])
+    );
+    // Casts
+    test_eq!(floats.cast::<i32>(), Simd::from_array([1, -2, 4, 0]));
+    test_eq!(a.cast::<f32>(), Simd::from_array([1.0, -2.0, 3.0, -4.0]));
+    test_eq!(bytes.cast::<i8>(), Simd::from_array([-6, 5, -128, 0]));
+    test_eq!(signed.cast::<i64>(), Simd::from_array([120, -120, 0, 1]));
+    // Shuffles
+    test_eq!(
+        simd_swizzle!(a, b, [0, 5, 2, 7]),
+        Simd::from_array([1, -3, 3, -1])
+    );
+    test_eq!(a.reverse(), Simd::from_array([-4, 3, -2, 1]));
+    test_eq!(
+        simd_swizzle!(a, [3, 3, 0, 0, 1, 1, 2, 2]),
+        Simd::from_array([-4, -4, 1, 1, -2, -2, 3, 3])
+    );
+    // Gathers and scatters
+    let mut data = [10_i32, 20, 30, 40, 50];
+    let idxs = black_box(Simd::from_array([4_usize, 0, 9, 2]));
+    test_eq!(
+        Simd::gather_or_default(&data, idxs),
+        Simd::from_array([50, 10, 0, 30])
+    );
+    Simd::from_array([1, 2, 3, 4]).scatter(&mut data, idxs);
+    test_eq!(data, [2, 20, 4, 40, 1]);
+    let enable = Mask::from_array([true, false, true, true]);
+    test_eq!(
+        Simd::load_select(&data, enable, Simd::splat(-1)),
+        Simd::from_array([2, -1, 4, 40])
+    );
 }
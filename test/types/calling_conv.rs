@@ -0,0 +1,76 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params
+)]
+#![allow(
+    internal_features,
+    incomplete_features,
+    unused_variables,
+    dead_code,
+    improper_ctypes_definitions
+)]
+#![no_std]
+include!("../common.rs");
+extern "C" {
+    fn sprintf(buf: *mut core::ffi::c_char, fmt: *const core::ffi::c_char, ...)
+        -> core::ffi::c_int;
+    fn strcmp(lhs: *const core::ffi::c_char, rhs: *const core::ffi::c_char) -> core::ffi::c_int;
+}
+#[inline(never)]
+extern "system" fn system(a: i32, b: i64) -> i64 {
+    a as i64 * b
+}
+#[inline(never)]
+extern "sysv64" fn sysv64(a: i32, b: f64) -> f64 {
+    a as f64 + b
+}
+#[inline(never)]
+extern "win64" fn win64(a: u8, b: u16, c: u32, d: u64, e: u64) -> u64 {
+    a as u64 + b as u64 + c as u64 + d + e
+}
+fn main() {
+    // Direct calls
+    test_eq!(system(black_box(3), 7), 21);
+    test_eq!(sysv64(black_box(2), 0.5), 2.5);
+    test_eq!(win64(black_box(1), 2, 3, 4, 5), 15);
+    // Calls trough function pointers
+    let system_ptr: extern "system" fn(i32, i64) -> i64 = system;
+    let system_ptr = black_box(system_ptr);
+    test_eq!(system_ptr(4, 5), 20);
+    let sysv64_ptr: extern "sysv64" fn(i32, f64) -> f64 = sysv64;
+    let sysv64_ptr = black_box(sysv64_ptr);
+    test_eq!(sysv64_ptr(1, 0.25), 1.25);
+    let win64_ptr: extern "win64" fn(u8, u16, u32, u64, u64) -> u64 = win64;
+    let win64_ptr = black_box(win64_ptr);
+    test_eq!(win64_ptr(5, 4, 3, 2, 1), 15);
+    // C-variadic calls, with different variable arguments.
+    let mut buf = [0_i8; 64];
+    unsafe {
+        let len = sprintf(buf.as_mut_ptr(), c"%d".as_ptr(), black_box(42_i32));
+        test_eq!(len, 2);
+        test_eq!(strcmp(buf.as_ptr(), c"42".as_ptr()), 0);
+        let len = sprintf(
+            buf.as_mut_ptr(),
+            c"%s %ld %.2f".as_ptr(),
+            c"abc".as_ptr(),
+            black_box(-7_i64),
+            black_box(1.5_f64),
+        );
+        test_eq!(len, 11);
+        test_eq!(strcmp(buf.as_ptr(), c"abc -7 1.50".as_ptr()), 0);
+        // A C-variadic call trough a function pointer
+        let sprintf_ptr: unsafe extern "C" fn(
+            *mut core::ffi::c_char,
+            *const core::ffi::c_char,
+            ...
+        ) -> core::ffi::c_int = sprintf;
+        let sprintf_ptr = black_box(sprintf_ptr);
+        let len = sprintf_ptr(buf.as_mut_ptr(), c"%x-%u".as_ptr(), 255_u32, 3_u32);
+        test_eq!(len, 4);
+        test_eq!(strcmp(buf.as_ptr(), c"ff-3".as_ptr()), 0);
+    }
+}
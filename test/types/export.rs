@@ -0,0 +1,50 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+#[derive(Clone, Copy)]
+struct RustcCLRInteropManagedClass<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str> {
+    prevent_construction: usize,
+}
+type MString = RustcCLRInteropManagedClass<"System.Runtime", "System.String">;
+#[export_name = "rustc_codegen_clr_export::Exported::Api::add"]
+pub fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+#[export_name = "rustc_codegen_clr_export::Exported::Api::greeting"]
+pub fn greeting() -> &'static str {
+    "Hello from Rust!"
+}
+#[export_name = "rustc_codegen_clr_export::Exported::Api::str_len"]
+pub fn str_len(s: &str) -> usize {
+    s.len()
+}
+fn main() {
+    test_eq!(add(black_box(2), 3), 5);
+    // Call the exported methods through their managed wrappers.
+    let sum =
+        rustc_clr_interop_managed_call2_::<"", "Exported.Api", false, "add", true, i32, i32, i32>(
+            black_box(2),
+            3,
+        );
+    test_eq!(sum, 5);
+    let greeting =
+        rustc_clr_interop_managed_call0_::<"", "Exported.Api", false, "greeting", MString>();
+    let len = rustc_clr_interop_managed_call1_::<
+        "",
+        "Exported.Api",
+        false,
+        "str_len",
+        true,
+        usize,
+        MString,
+    >(greeting);
+    test_eq!(len, "Hello from Rust!".len());
+}
@@ -15,14 +15,32 @@ include!("../common.rs");
 fn main() {
     // 1st. Check that a const f128 can be created.
     let zero = black_box(0_f128);
-    let one = black_box(0_f128);
-    let two = black_box(0_f128);
+    let one = black_box(1_f128);
+    let two = black_box(2_f128);
     // Check that addtion works
     test_eq!(black_box(one + one), two);
     // Check that subtraction works
     test_eq!(black_box(one - one), zero);
     // Check that multiplaction works
     test_eq!(black_box(one * one), one);
+    test_eq!(black_box(two * two), black_box(4_f128));
     // Check that division works
     test_eq!(black_box(two / one), two);
+    test_eq!(black_box(one / two), black_box(0.5_f128));
+    // Check that reminders work
+    test_eq!(black_box(black_box(5_f128) % two), one);
+    // Check that negation works
+    test_eq!(black_box(-one) + one, zero);
+    // Check that comparisons work, including ones with NaNs.
+    test!(one < two);
+    test!(two >= one);
+    test!(!(black_box(f128::NAN) == black_box(f128::NAN)));
+    test!(!(black_box(f128::NAN) <= one));
+    // Check that casts work
+    test_eq!(black_box(0.1_f64) as f128 as f64, 0.1_f64);
+    test_eq!(black_box(-7_i64) as f128 as i64, -7_i64);
+    test_eq!(black_box(u128::MAX) as f128 as u128, u128::MAX);
+    // Float to int casts saturate.
+    test_eq!(black_box(-1_f128) as u8, 0);
+    test_eq!(black_box(300_f128) as u8, u8::MAX);
 }
@@ -0,0 +1,19 @@
+#![feature(adt_const_params, unsized_const_params)]
+#![allow(incomplete_features, improper_ctypes_definitions)]
+use mycorrhiza::dotnet_typedef;
+dotnet_typedef! {
+    class Counter inherits [System::Runtime]System::Object {
+        count: i32,
+        virtual fn GetHashCode(this: Counter::Ref) -> i32 {
+            this.field::<"count", i32>() + 40
+        },
+    }
+}
+fn main() {
+    // Created by the runtime, trough the default constructor of the class.
+    let counter = Counter::Ref::ctor0();
+    assert_eq!(counter.field::<"count", i32>(), 0);
+    counter.set_field::<"count", i32>(2);
+    // `GetHashCode` is virtual, so this calls the Rust implementation.
+    assert_eq!(counter.virt0::<"GetHashCode", i32>(), 42);
+}
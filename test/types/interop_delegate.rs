@@ -0,0 +1,33 @@
+#![feature(adt_const_params, unsized_const_params)]
+#![allow(incomplete_features)]
+use mycorrhiza::delegate::{Func1, IntoDelegate};
+use mycorrhiza::System;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+static CALLS: AtomicI32 = AtomicI32::new(0);
+static DROPPED: AtomicBool = AtomicBool::new(false);
+/// Captured by the closure, to check that the delegate drops it.
+struct Offset(i32);
+impl Drop for Offset {
+    fn drop(&mut self) {
+        DROPPED.store(true, Ordering::SeqCst);
+    }
+}
+// Not inlined, so that the delegate is no longer reachable once this returns.
+#[inline(never)]
+fn invoke_delegate() -> i32 {
+    let offset = Offset(40);
+    let add: Func1<i32, i32> = (move |x: i32| {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        offset.0 + x
+    })
+    .into_delegate();
+    add.invoke(2)
+}
+fn main() {
+    assert_eq!(invoke_delegate(), 42);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    // The closure is dropped by the finalizer of the delegate.
+    System::GC::static0::<"Collect", ()>();
+    System::GC::static0::<"WaitForPendingFinalizers", ()>();
+    assert!(DROPPED.load(Ordering::SeqCst));
+}
@@ -0,0 +1,68 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+#[derive(Clone, Copy)]
+struct RustcCLRInteropManagedClass<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str> {
+    prevent_construction: usize,
+}
+type MString = RustcCLRInteropManagedClass<"System.Runtime", "System.String">;
+#[inline(never)]
+fn rustc_clr_interop_managed_ld_sfld<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    const FIELD: &'static str,
+    T,
+>() -> T {
+    core::intrinsics::abort();
+}
+#[inline(never)]
+fn rustc_clr_interop_managed_call_tuple_<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    const METHOD: &'static str,
+    const IS_STATIC: bool,
+    Ret,
+    Args,
+>(
+    args: Args,
+) -> Ret {
+    core::intrinsics::abort();
+}
+fn main() {
+    // `BitConverter.IsLittleEndian` is a static, readonly field.
+    let little_endian = rustc_clr_interop_managed_ld_sfld::<
+        "System.Runtime",
+        "System.BitConverter",
+        false,
+        "IsLittleEndian",
+        bool,
+    >();
+    test_eq!(little_endian, cfg!(target_endian = "little"));
+    let empty = rustc_clr_interop_managed_ld_sfld::<
+        "System.Runtime",
+        "System.String",
+        false,
+        "Empty",
+        MString,
+    >();
+    let len = rustc_clr_interop_managed_call_tuple_::<
+        "System.Runtime",
+        "System.String",
+        false,
+        "get_Length",
+        false,
+        i32,
+        (MString,),
+    >((empty,));
+    test_eq!(len, 0);
+}
@@ -0,0 +1,217 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+use core::marker::PhantomData;
+#[repr(C)]
+struct RustcCLRInteropManagedGenericClass<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    Generics,
+> {
+    prevent_construction: usize,
+    generics: PhantomData<Generics>,
+}
+impl<
+        const ASSEMBLY: &'static str,
+        const CLASS_PATH: &'static str,
+        const IS_VALUETYPE: bool,
+        Generics,
+    > Clone for RustcCLRInteropManagedGenericClass<ASSEMBLY, CLASS_PATH, IS_VALUETYPE, Generics>
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<
+        const ASSEMBLY: &'static str,
+        const CLASS_PATH: &'static str,
+        const IS_VALUETYPE: bool,
+        Generics,
+    > Copy for RustcCLRInteropManagedGenericClass<ASSEMBLY, CLASS_PATH, IS_VALUETYPE, Generics>
+{
+}
+struct RustcCLRInteropManagedGenericParam<const INDEX: u32>;
+struct RustcCLRInteropManagedMethodGenericParam<const INDEX: u32>;
+#[derive(Clone, Copy)]
+struct RustcCLRInteropManagedClass<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str> {
+    prevent_construction: usize,
+}
+type List<T> = RustcCLRInteropManagedGenericClass<
+    "System.Collections",
+    "System.Collections.Generic.List",
+    false,
+    (T,),
+>;
+type Dictionary<K, V> = RustcCLRInteropManagedGenericClass<
+    "System.Collections",
+    "System.Collections.Generic.Dictionary",
+    false,
+    (K, V),
+>;
+type Comparer<T> = RustcCLRInteropManagedGenericClass<
+    "System.Collections",
+    "System.Collections.Generic.Comparer",
+    false,
+    (T,),
+>;
+type KeyValuePair<K, V> = RustcCLRInteropManagedGenericClass<
+    "System.Runtime",
+    "System.Collections.Generic.KeyValuePair",
+    true,
+    (K, V),
+>;
+type Unsafe =
+    RustcCLRInteropManagedClass<"System.Runtime", "System.Runtime.CompilerServices.Unsafe">;
+type T0 = RustcCLRInteropManagedGenericParam<0>;
+type T1 = RustcCLRInteropManagedGenericParam<1>;
+#[inline(never)]
+fn rustc_clr_interop_managed_call_generic_<
+    Class,
+    const METHOD: &'static str,
+    const IS_STATIC: bool,
+    const IS_VIRTUAL: bool,
+    Sig,
+    MethodGenerics,
+    Ret,
+    Args,
+>(
+    args: Args,
+) -> Ret {
+    core::intrinsics::abort();
+}
+#[inline(never)]
+fn rustc_clr_interop_managed_ctor_generic_<Class, Sig, Args>(args: Args) -> Class {
+    core::intrinsics::abort();
+}
+fn main() {
+    let list = rustc_clr_interop_managed_ctor_generic_::<List<i32>, fn(), ()>(());
+    for value in [3, 5, 8] {
+        rustc_clr_interop_managed_call_generic_::<List<i32>, "Add", false, false, fn(T0), (), (), _>(
+            (list, value),
+        );
+    }
+    let count = rustc_clr_interop_managed_call_generic_::<
+        List<i32>,
+        "get_Count",
+        false,
+        false,
+        fn() -> i32,
+        (),
+        i32,
+        _,
+    >((list,));
+    test_eq!(count, 3);
+    let second = rustc_clr_interop_managed_call_generic_::<
+        List<i32>,
+        "get_Item",
+        false,
+        false,
+        fn(i32) -> T0,
+        (),
+        i32,
+        _,
+    >((list, 1));
+    test_eq!(second, 5);
+    let dict = rustc_clr_interop_managed_ctor_generic_::<Dictionary<u64, f32>, fn(), ()>(());
+    rustc_clr_interop_managed_call_generic_::<
+        Dictionary<u64, f32>,
+        "set_Item",
+        false,
+        false,
+        fn(T0, T1),
+        (),
+        (),
+        _,
+    >((dict, 7_u64, 0.5_f32));
+    let contains = rustc_clr_interop_managed_call_generic_::<
+        Dictionary<u64, f32>,
+        "ContainsKey",
+        false,
+        false,
+        fn(T0) -> bool,
+        (),
+        bool,
+        _,
+    >((dict, 7_u64));
+    test!(contains);
+    let value = rustc_clr_interop_managed_call_generic_::<
+        Dictionary<u64, f32>,
+        "get_Item",
+        false,
+        false,
+        fn(T0) -> T1,
+        (),
+        f32,
+        _,
+    >((dict, 7_u64));
+    test_eq!(value, 0.5);
+    // A static property of a generic class.
+    let comparer = rustc_clr_interop_managed_call_generic_::<
+        Comparer<i32>,
+        "get_Default",
+        true,
+        false,
+        fn() -> Comparer<T0>,
+        (),
+        Comparer<i32>,
+        _,
+    >(());
+    let order = rustc_clr_interop_managed_call_generic_::<
+        Comparer<i32>,
+        "Compare",
+        false,
+        true,
+        fn(T0, T0) -> i32,
+        (),
+        i32,
+        _,
+    >((comparer, 1, 2));
+    test!(order < 0);
+    // A generic value type, whose instance methods take `this` by reference.
+    let mut pair = rustc_clr_interop_managed_ctor_generic_::<KeyValuePair<i32, u64>, fn(T0, T1), _>(
+        (4, 9_u64),
+    );
+    let key = rustc_clr_interop_managed_call_generic_::<
+        KeyValuePair<i32, u64>,
+        "get_Key",
+        false,
+        false,
+        fn() -> T0,
+        (),
+        i32,
+        _,
+    >((&mut pair,));
+    test_eq!(key, 4);
+    let value = rustc_clr_interop_managed_call_generic_::<
+        KeyValuePair<i32, u64>,
+        "get_Value",
+        false,
+        false,
+        fn() -> T1,
+        (),
+        u64,
+        _,
+    >((&mut pair,));
+    test_eq!(value, 9);
+    // A generic method of a non-generic class.
+    let size = rustc_clr_interop_managed_call_generic_::<
+        Unsafe,
+        "SizeOf",
+        true,
+        false,
+        fn() -> i32,
+        (u64,),
+        i32,
+        _,
+    >(());
+    test_eq!(size, 8);
+}
@@ -0,0 +1,48 @@
+#![feature(adt_const_params, unsized_const_params)]
+#![allow(incomplete_features, improper_ctypes_definitions)]
+use mycorrhiza::dotnet_typedef;
+use mycorrhiza::intrinsics::{
+    rustc_clr_interop_managed_checked_cast, RustcCLRInteropManagedGenericClass,
+    RustcCLRInteropManagedGenericParam,
+};
+use mycorrhiza::System;
+dotnet_typedef! {
+    class Handle inherits [System::Runtime]System::Object {
+        value: i32,
+        disposed: bool,
+        implements [System::Runtime]System::IDisposable {
+            fn Dispose(this: Handle::Ref) -> () {
+                this.set_field::<"disposed", bool>(true);
+            },
+        },
+        implements [System::Runtime]System::IComparable<Handle::Ref> {
+            fn CompareTo(this: Handle::Ref, other: Handle::Ref) -> i32 {
+                this.field::<"value", i32>() - other.field::<"value", i32>()
+            },
+        },
+    }
+}
+type IComparable<T> =
+    RustcCLRInteropManagedGenericClass<"System.Runtime", "System.IComparable", false, (T,)>;
+fn handle(value: i32) -> Handle::Ref {
+    let handle = Handle::Ref::ctor0();
+    handle.set_field::<"value", i32>(value);
+    handle
+}
+fn main() {
+    let small = handle(1);
+    let big = handle(5);
+    // Both calls go trough the interface, so they only work if the class implements it.
+    let comparable: IComparable<Handle::Ref> = rustc_clr_interop_managed_checked_cast(small);
+    let ord = IComparable::<Handle::Ref>::virt_call::<
+        "CompareTo",
+        fn(RustcCLRInteropManagedGenericParam<0>) -> i32,
+        _,
+        i32,
+    >((comparable, big));
+    assert_eq!(ord, -4);
+    let disposable: System::IDisposable = rustc_clr_interop_managed_checked_cast(small);
+    assert!(!small.field::<"disposed", bool>());
+    disposable.virt0::<"Dispose", ()>();
+    assert!(small.field::<"disposed", bool>());
+}
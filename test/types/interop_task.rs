@@ -0,0 +1,39 @@
+#![feature(adt_const_params, unsized_const_params)]
+#![allow(incomplete_features)]
+use mycorrhiza::task::{into_task, into_value_task};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::task::{Context, Poll};
+static POLLS: AtomicI32 = AtomicI32::new(0);
+/// Returns `Pending` `count` times, waking itself up each time.
+struct Countdown {
+    count: u32,
+}
+impl Future for Countdown {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        POLLS.fetch_add(1, Ordering::SeqCst);
+        if self.count == 0 {
+            Poll::Ready(())
+        } else {
+            self.count -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+async fn add_late(a: i32, b: i32) -> i32 {
+    Countdown { count: 3 }.await;
+    a + b
+}
+fn main() {
+    // The task completes once the future gets woken up and polled to completion, without any thread blocking on it.
+    let task = into_task(add_late(40, 2));
+    assert_eq!(task.result(), 42);
+    assert_eq!(POLLS.load(Ordering::SeqCst), 4);
+    // A `ValueTask` wraps the same kind of task.
+    let task = into_value_task(add_late(2, 3));
+    assert_eq!(task.result(), 5);
+    assert_eq!(POLLS.load(Ordering::SeqCst), 8);
+}
@@ -0,0 +1,115 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+#[derive(Clone, Copy)]
+struct RustcCLRInteropManagedClass<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str> {
+    prevent_construction: usize,
+}
+type Version = RustcCLRInteropManagedClass<"System.Runtime", "System.Version">;
+#[inline(never)]
+fn rustc_clr_interop_managed_call_tuple_<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    const METHOD: &'static str,
+    const IS_STATIC: bool,
+    Ret,
+    Args,
+>(
+    args: Args,
+) -> Ret {
+    core::intrinsics::abort();
+}
+#[inline(never)]
+fn rustc_clr_interop_managed_call_virt_tuple_<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    const METHOD: &'static str,
+    const IS_STATIC: bool,
+    Ret,
+    Args,
+>(
+    args: Args,
+) -> Ret {
+    core::intrinsics::abort();
+}
+#[inline(never)]
+fn rustc_clr_interop_managed_ctor_tuple_<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    const IS_VALUETYPE: bool,
+    Args,
+>(
+    args: Args,
+) -> RustcCLRInteropManagedClass<ASSEMBLY, CLASS_PATH> {
+    core::intrinsics::abort();
+}
+fn main() {
+    // A constructor with 4 arguments
+    let version = rustc_clr_interop_managed_ctor_tuple_::<
+        "System.Runtime",
+        "System.Version",
+        false,
+        (i32, i32, i32, i32),
+    >((1, black_box(2), 3, 4));
+    let build = rustc_clr_interop_managed_call_tuple_::<
+        "System.Runtime",
+        "System.Version",
+        false,
+        "get_Build",
+        false,
+        i32,
+        (Version,),
+    >((version,));
+    test_eq!(build, 3);
+    let hash = rustc_clr_interop_managed_call_virt_tuple_::<
+        "System.Runtime",
+        "System.Object",
+        false,
+        "GetHashCode",
+        false,
+        i32,
+        (Version,),
+    >((version,));
+    black_box(hash);
+    // An `out` parameter
+    let mut rem = 0_i32;
+    let quot = rustc_clr_interop_managed_call_tuple_::<
+        "System.Runtime",
+        "System.Math",
+        false,
+        "DivRem",
+        true,
+        i32,
+        (i32, i32, &mut i32),
+    >((black_box(17), 5, &mut rem));
+    test_eq!(quot, 3);
+    test_eq!(rem, 2);
+    // Raw pointers stay unmanaged pointers.
+    let src = [1_u8, 2, 3, 4];
+    let mut dst = [0_u8; 4];
+    rustc_clr_interop_managed_call_tuple_::<
+        "System.Runtime",
+        "System.Buffer",
+        false,
+        "MemoryCopy",
+        true,
+        (),
+        (*const (), *mut (), i64, i64),
+    >((
+        src.as_ptr().cast(),
+        dst.as_mut_ptr().cast(),
+        4,
+        black_box(4),
+    ));
+    test_eq!(dst[3], 4);
+}